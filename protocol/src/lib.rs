@@ -0,0 +1,235 @@
+// archy-protocol - Wire protocol shared by archy-executor and rust-brain
+// One request per Unix socket connection: the client writes a JSON object
+//   {"action": "...", "data": {...}, "id": "..."}
+// and reads back one JSON object, after which the server closes the connection. The request is
+// complete as soon as the bytes read so far parse, so clients don't have to half-close their side.
+//
+// Every reply carries `success`; a failed one also carries `error` (a message, or the executor's
+// structured {kind, message} object) and `error_code`, a stable snake_case ErrorKind clients can
+// branch on. A request `id` (any JSON value) is echoed back unchanged. Both servers answer `health`
+// and `describe` the same way, so one client can tell which process it reached and what it offers.
+//
+// The executor's `monitor` action is the one exception: it answers with one JSON object per line (each
+// stamped with the request id) until it sends {"done": true} or the client hangs up.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::Read;
+use std::time::Instant;
+
+/// Bumped when the envelope changes incompatibly
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Deserialize)]
+pub struct Request {
+    #[serde(alias = "task")] // rust-brain's original spelling
+    pub action: String,
+    #[serde(default, alias = "payload")]
+    pub data: Value,
+    #[serde(default)]
+    pub id: Option<Value>,
+}
+
+/// Stable classification of failures (serialized as snake_case strings)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    TmuxUnavailable,   // tmux binary missing or server not reachable
+    SessionMissing,    // Target session/pane does not exist or could not be created
+    Validation,        // Bad request parameters or blocked command
+    Io,                // Socket/filesystem/process I/O failure
+    Timeout,           // Command or wait exceeded its deadline
+    Parse,             // Output or request could not be parsed
+    UnknownAction,     // No handler for the requested action
+    Denied,            // Refused by ACL, kill switch or configuration
+    Failed,            // The action ran and failed without a finer classification
+}
+
+impl ErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::TmuxUnavailable => "tmux_unavailable",
+            ErrorKind::SessionMissing => "session_missing",
+            ErrorKind::Validation => "validation",
+            ErrorKind::Io => "io",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Parse => "parse",
+            ErrorKind::UnknownAction => "unknown_action",
+            ErrorKind::Denied => "denied",
+            ErrorKind::Failed => "failed",
+        }
+    }
+
+    /// Build the structured `error` object: {"kind", "message", "detail"?}
+    pub fn to_json(self, message: &str, detail: Option<&str>) -> Value {
+        let mut error = json!({
+            "kind": self.as_str(),
+            "message": message,
+        });
+        if let Some(detail) = detail {
+            error["detail"] = json!(detail);
+        }
+        error
+    }
+}
+
+/// Why no request could be read off a connection
+#[derive(Debug)]
+pub struct FrameError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl FrameError {
+    fn new(kind: ErrorKind, message: String) -> Self {
+        FrameError { kind, message }
+    }
+}
+
+/// Read one request, `chunk_size` bytes at a time and at most `max_bytes` in all. A read timeout
+/// ends the request with whatever arrived
+pub fn read_request(stream: &mut impl Read, chunk_size: usize, max_bytes: usize) -> Result<Request, FrameError> {
+    let mut buffer = Vec::new();
+    let mut chunk = vec![0; chunk_size.max(1)];
+
+    loop {
+        match stream.read(&mut chunk) {
+            Ok(0) => break, // EOF
+            Ok(n) => {
+                buffer.extend_from_slice(&chunk[..n]);
+                // Only parse once the data could be complete, so large requests aren't re-parsed per chunk
+                let may_be_complete = buffer.iter().rev().find(|b| !b.is_ascii_whitespace()) == Some(&b'}');
+                if may_be_complete && serde_json::from_slice::<Request>(&buffer).is_ok() {
+                    break;
+                }
+                if buffer.len() > max_bytes {
+                    return Err(FrameError::new(ErrorKind::Validation, format!("Request too large (limit {} bytes)", max_bytes)));
+                }
+            }
+            // Blocking sockets with a read timeout report TimedOut (WouldBlock on some platforms)
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock) => {
+                if buffer.is_empty() {
+                    return Err(FrameError::new(ErrorKind::Timeout, "Connection timeout".to_string()));
+                }
+                break;
+            }
+            Err(e) => return Err(FrameError::new(ErrorKind::Io, format!("Read error: {}", e))),
+        }
+    }
+
+    if buffer.is_empty() {
+        return Err(FrameError::new(ErrorKind::Parse, "Empty request received".to_string()));
+    }
+    serde_json::from_slice(&buffer).map_err(|e| FrameError::new(ErrorKind::Parse, format!("Invalid JSON: {}", e)))
+}
+
+/// A failure reply
+pub fn error_reply(kind: ErrorKind, message: &str) -> Value {
+    json!({"success": false, "error": message, "error_code": kind.as_str()})
+}
+
+/// Finish a reply for the wire: echo the request id, and give a failure without an `error_code` one -
+/// the kind of a structured error, else `failed`
+pub fn stamp(mut reply: Value, id: Option<&Value>) -> Value {
+    let map = match reply.as_object_mut() {
+        Some(map) => map,
+        None => return reply,
+    };
+    if let Some(id) = id {
+        map.insert("id".to_string(), id.clone());
+    }
+    if map.get("success") == Some(&Value::Bool(false)) && !map.contains_key("error_code") {
+        let code = map.get("error")
+            .and_then(|error| error.get("kind"))
+            .and_then(|kind| kind.as_str())
+            .unwrap_or(ErrorKind::Failed.as_str())
+            .to_string();
+        map.insert("error_code".to_string(), json!(code));
+    }
+    reply
+}
+
+/// Who answered: {"success", "service", "version", "protocol"}
+pub fn identity(service: &str, version: &str) -> Value {
+    json!({"success": true, "service": service, "version": version, "protocol": PROTOCOL_VERSION})
+}
+
+/// Reply to `health` - the identity plus process id and uptime
+pub fn health(service: &str, version: &str, started: Instant) -> Value {
+    let mut reply = identity(service, version);
+    reply["pid"] = json!(std::process::id());
+    reply["uptime_secs"] = json!(started.elapsed().as_secs());
+    reply
+}
+
+/// Reply to `describe` - the identity plus the actions the server handles
+pub fn describe(service: &str, version: &str, actions: Value) -> Value {
+    let mut reply = identity(service, version);
+    reply["actions"] = actions;
+    reply
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self, Cursor};
+
+    /// Hands out its data a few bytes at a time, then times out like an idle socket
+    struct Trickle {
+        data: Vec<u8>,
+        pos: usize,
+    }
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.pos == self.data.len() {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "idle"));
+            }
+            let n = buf.len().min(3).min(self.data.len() - self.pos);
+            buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_read_request_stops_once_complete() {
+        // The client never closes its side - the request ends when it parses
+        let mut stream = Trickle { data: br#"{"action": "health", "id": 7}"#.to_vec(), pos: 0 };
+        let request = read_request(&mut stream, 8, 1024).unwrap();
+        assert_eq!(request.action, "health");
+        assert_eq!(request.id, Some(json!(7)));
+        assert_eq!(request.data, Value::Null);
+
+        // rust-brain's {task, payload} spelling still reads
+        let mut stream = Cursor::new(br#"{"task": "embed_texts", "payload": {"texts": []}}"#.to_vec());
+        let request = read_request(&mut stream, 8, 1024).unwrap();
+        assert_eq!(request.action, "embed_texts");
+        assert_eq!(request.data["texts"], json!([]));
+    }
+
+    #[test]
+    fn test_read_request_errors() {
+        let kind = |data: &[u8], max| read_request(&mut Cursor::new(data.to_vec()), 4, max).unwrap_err().kind;
+        assert_eq!(kind(b"", 1024), ErrorKind::Parse);
+        assert_eq!(kind(b"{not json}", 1024), ErrorKind::Parse);
+        assert_eq!(kind(br#"{"action": "execute", "data": {"command": "ls -la"}}"#, 16), ErrorKind::Validation);
+
+        let mut idle = Trickle { data: Vec::new(), pos: 0 };
+        assert_eq!(read_request(&mut idle, 4, 1024).unwrap_err().kind, ErrorKind::Timeout);
+    }
+
+    #[test]
+    fn test_stamp() {
+        let id = json!("req-1");
+        let reply = stamp(json!({"success": true, "output": "ok"}), Some(&id));
+        assert_eq!(reply["id"], "req-1");
+        assert!(reply.get("error_code").is_none());
+
+        // Plain failures are `failed`, structured ones keep their kind, explicit codes stay
+        assert_eq!(stamp(json!({"success": false, "error": "boom"}), None)["error_code"], "failed");
+        let structured = json!({"success": false, "error": ErrorKind::Timeout.to_json("slow", None)});
+        assert_eq!(stamp(structured, None)["error_code"], "timeout");
+        assert_eq!(stamp(error_reply(ErrorKind::Denied, "no"), None)["error_code"], "denied");
+    }
+}
@@ -0,0 +1,154 @@
+// Outlier scoring against past outputs (`anomaly_score`)
+// A new output is measured by its mean cosine distance to its k nearest neighbors in the baseline (past
+// outputs of the same command). Every baseline output gets the same measure against the rest of the
+// baseline, leaving itself out, and the score is the share of them the new output lies further out than:
+// around 0.5 is ordinary, 0.99 is further from anything seen before than 99% of the past outputs are.
+// Distances are cosine whatever the collection's metric, like diverse.rs. A z-score of the distance
+// against the baseline's is reported beside it, except when the baseline's distances don't vary.
+use rayon::prelude::*;
+use serde::Serialize;
+use crate::metric::{dot, normalize};
+
+pub const DEFAULT_K: usize = 5;
+/// Fewer past outputs than this make no baseline
+pub const MIN_BASELINE: usize = 5;
+/// Most recent past outputs compared against (the baseline costs n^2 comparisons)
+pub const MAX_BASELINE: usize = 2000;
+pub const DEFAULT_THRESHOLD: f32 = 0.95;
+
+#[derive(Debug, Serialize)]
+pub struct Anomaly {
+    pub score: f32,              // share of the baseline that is less of an outlier, ties counting half
+    pub z_score: Option<f32>,
+    pub distance: f32,           // mean cosine distance to the k nearest baseline outputs
+    pub baseline_mean: f32,      // the same, averaged over the baseline
+    pub baseline_std: f32,
+    pub nearest: usize,          // position of the closest baseline output
+    pub nearest_similarity: f32,
+    pub k: usize,
+}
+
+impl Anomaly {
+    /// Flagged at or above `threshold` - a threshold of 1.0 flags only outputs beyond the whole baseline
+    pub fn exceeds(&self, threshold: f32) -> bool {
+        self.score >= threshold
+    }
+}
+
+/// Mean of the `k` smallest `distances`
+fn knn_mean(mut distances: Vec<f32>, k: usize) -> f32 {
+    distances.sort_by(f32::total_cmp);
+    distances.iter().take(k).sum::<f32>() / k.max(1) as f32
+}
+
+/// Score `vector` against `baseline` (at least two vectors)
+pub fn score(baseline: &[&[f32]], vector: &[f32], k: usize) -> Anomaly {
+    let units: Vec<Vec<f32>> = baseline.par_iter().map(|v| normalize(v)).collect();
+    let unit = normalize(vector);
+    // Each baseline output has one fewer neighbor to choose from
+    let k = k.clamp(1, units.len().saturating_sub(1).max(1));
+
+    let similarities: Vec<f32> = units.iter().map(|b| dot(&unit, b)).collect();
+    let (nearest, nearest_similarity) = similarities.iter()
+        .copied()
+        .enumerate()
+        .fold((0, f32::NEG_INFINITY), |best, (i, s)| if s > best.1 { (i, s) } else { best });
+    let distance = knn_mean(similarities.iter().map(|s| 1.0 - s).collect(), k);
+
+    let spread: Vec<f32> = (0..units.len())
+        .into_par_iter()
+        .map(|i| {
+            let others = units.iter().enumerate().filter(|&(j, _)| j != i).map(|(_, b)| 1.0 - dot(&units[i], b)).collect();
+            knn_mean(others, k)
+        })
+        .collect();
+    let n = spread.len() as f32;
+    let mean = spread.iter().sum::<f32>() / n;
+    let std = (spread.iter().map(|d| (d - mean).powi(2)).sum::<f32>() / n).sqrt();
+
+    // Rounding noise on identical outputs is no difference
+    const EPSILON: f32 = 1e-6;
+    let below = spread.iter().filter(|&&d| d < distance - EPSILON).count() as f32;
+    let ties = spread.iter().filter(|&&d| (d - distance).abs() <= EPSILON).count() as f32;
+    Anomaly {
+        score: (below + ties / 2.0) / n,
+        z_score: (std > EPSILON).then(|| (distance - mean) / std),
+        distance,
+        baseline_mean: mean,
+        baseline_std: std,
+        nearest,
+        nearest_similarity,
+        k,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit vectors `step` radians apart in the xy plane
+    fn arc(count: usize, step: f32) -> Vec<Vec<f32>> {
+        (0..count).map(|i| vec![(i as f32 * step).cos(), (i as f32 * step).sin(), 0.0]).collect()
+    }
+
+    fn refs(vectors: &[Vec<f32>]) -> Vec<&[f32]> {
+        vectors.iter().map(Vec::as_slice).collect()
+    }
+
+    #[test]
+    fn test_outlier_scores_above_ordinary_output() {
+        let baseline = arc(10, 0.05);
+        let baseline = refs(&baseline);
+
+        let outlier = score(&baseline, &[0.0, 0.0, 1.0], DEFAULT_K);
+        assert_eq!(outlier.score, 1.0);
+        assert!((outlier.distance - 1.0).abs() < 1e-5);
+        assert!(outlier.z_score.is_some_and(|z| z > 3.0));
+        assert!(outlier.exceeds(DEFAULT_THRESHOLD));
+
+        // Inside the arc: closer to its neighbors than the arc's ends are to theirs
+        let ordinary = score(&baseline, &[0.225f32.cos(), 0.225f32.sin(), 0.0], DEFAULT_K);
+        assert!(ordinary.score < 0.5, "{}", ordinary.score);
+        assert!(!ordinary.exceeds(DEFAULT_THRESHOLD));
+        assert!(ordinary.nearest == 4 || ordinary.nearest == 5);
+        assert!(ordinary.distance < outlier.distance);
+
+        // Only length differs - cosine sees the same output
+        let scaled = score(&baseline, &[0.225f32.cos() * 7.0, 0.225f32.sin() * 7.0, 0.0], DEFAULT_K);
+        assert!((scaled.score - ordinary.score).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_identical_baseline_and_threshold_edges() {
+        let baseline = vec![vec![1.0, 0.0, 0.0]; 5];
+        let baseline = refs(&baseline);
+
+        // Every baseline output ties with an identical new one: ordinary, and no z-score without spread
+        let same = score(&baseline, &[2.0, 0.0, 0.0], DEFAULT_K);
+        assert_eq!(same.score, 0.5);
+        assert!(same.z_score.is_none());
+        assert_eq!(same.baseline_std, 0.0);
+        assert!(same.exceeds(0.5));
+        assert!(!same.exceeds(0.5 + f32::EPSILON));
+
+        let different = score(&baseline, &[0.0, 1.0, 0.0], DEFAULT_K);
+        assert_eq!(different.score, 1.0);
+        assert!(different.z_score.is_none());
+        assert!(different.exceeds(1.0));
+        assert!(different.exceeds(0.0));
+    }
+
+    #[test]
+    fn test_k_is_clamped_to_the_baseline() {
+        let baseline = arc(3, 0.1);
+        let baseline = refs(&baseline);
+        assert_eq!(score(&baseline, &[1.0, 0.0, 0.0], 10).k, 2);
+        assert_eq!(score(&baseline, &[1.0, 0.0, 0.0], 0).k, 1);
+
+        // k = 1 is the distance to the nearest output alone
+        let nearest = score(&baseline, &[1.0, 0.0, 0.0], 1);
+        assert_eq!(nearest.nearest, 0);
+        assert!(nearest.distance.abs() < 1e-6);
+        assert!((nearest.nearest_similarity - 1.0).abs() < 1e-6);
+    }
+}
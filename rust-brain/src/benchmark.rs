@@ -0,0 +1,219 @@
+// Synthetic workload benchmark (`benchmark`)
+// Generates `n` texts and `queries` query texts from a fixed pseudo-word vocabulary (a given `seed`
+// always yields the same workload), then times each stage the brain depends on:
+//   embed   the corpus, in batches, with the requested backend
+//   insert  every vector into a scratch in-memory store
+//   search  each query on its own - latency percentiles, throughput, and recall@k against brute force
+// Nothing touches disk, so runs are comparable between CI and the field.
+use rayon::prelude::*;
+use serde::Serialize;
+use std::time::Instant;
+use crate::cluster::Lcg;
+use crate::embedder;
+use crate::filter::Filter;
+use crate::quantize::Quantization;
+use crate::vector_store::VectorStore;
+
+/// Largest corpus a benchmark builds (it all sits in memory)
+pub const MAX_ITEMS: usize = 200_000;
+const VOCABULARY: usize = 4096;
+const SYLLABLES: &[&str] = &[
+    "ar", "ch", "ex", "ke", "lo", "mi", "nu", "or", "pa", "qu", "ra", "si", "ta", "ul", "ve", "xo", "yz", "zen",
+];
+
+pub struct Workload {
+    pub n: usize,
+    pub dim: usize,
+    pub queries: usize,
+    pub k: usize,
+    pub ef: usize,
+    pub words: usize, // per text
+    pub seed: u64,
+    pub backend: Option<String>,
+    pub quantization: Quantization,
+}
+
+#[derive(Serialize)]
+pub struct Stage {
+    pub total_ms: f64,
+    pub items_per_sec: f64,
+}
+
+#[derive(Serialize)]
+pub struct SearchStats {
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+    pub qps: f64,
+    pub recall_at_k: f64,
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    pub backend: &'static str,
+    pub dim: usize,
+    pub embed: Stage,
+    pub insert: Stage,
+    pub search: SearchStats,
+}
+
+fn ms_since(start: Instant) -> f64 {
+    start.elapsed().as_secs_f64() * 1000.0
+}
+
+fn stage(total_ms: f64, items: usize) -> Stage {
+    Stage { total_ms, items_per_sec: if total_ms > 0.0 { items as f64 * 1000.0 / total_ms } else { 0.0 } }
+}
+
+/// Value at quantile `q` of sorted `values`
+fn percentile(sorted: &[f64], q: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    sorted[((sorted.len() - 1) as f64 * q).round() as usize]
+}
+
+/// `count` texts of `words` pseudo-words each - a few common words and a long tail, like real text
+fn texts(rng: &mut Lcg, vocabulary: &[String], count: usize, words: usize) -> Vec<String> {
+    (0..count)
+        .map(|_| {
+            (0..words)
+                .map(|_| {
+                    let u = rng.next();
+                    vocabulary[(u * u * vocabulary.len() as f64) as usize].as_str()
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect()
+}
+
+pub fn run(workload: &Workload) -> Result<Report, String> {
+    if workload.n == 0 || workload.n > MAX_ITEMS {
+        return Err(format!("'n' must be between 1 and {}, got {}", MAX_ITEMS, workload.n));
+    }
+    let mut rng = Lcg(workload.seed);
+    let vocabulary: Vec<String> = (0..VOCABULARY)
+        .map(|_| (0..3).map(|_| SYLLABLES[(rng.next() * SYLLABLES.len() as f64) as usize]).collect())
+        .collect();
+    let corpus = texts(&mut rng, &vocabulary, workload.n, workload.words.max(1));
+    let queries = texts(&mut rng, &vocabulary, workload.queries, workload.words.max(1));
+    let backend = workload.backend.as_deref();
+
+    let start = Instant::now();
+    let refs: Vec<&str> = corpus.iter().map(String::as_str).collect();
+    let embedded = embedder::embed(&refs, backend, workload.dim, embedder::DEFAULT_BATCH_SIZE)?;
+    let embed = stage(ms_since(start), workload.n);
+    let refs: Vec<&str> = queries.iter().map(String::as_str).collect();
+    let query_vectors = embedder::embed(&refs, backend, workload.dim, embedder::DEFAULT_BATCH_SIZE)?.vectors;
+
+    let mut store = VectorStore::in_memory().with_quantization(workload.quantization)?;
+    let start = Instant::now();
+    for (i, vector) in embedded.vectors.iter().enumerate() {
+        store.upsert(&i.to_string(), vector, serde_json::Value::Null)?;
+    }
+    let insert = stage(ms_since(start), workload.n);
+
+    let filter = Filter::default();
+    let mut latencies = Vec::with_capacity(query_vectors.len());
+    let mut found = Vec::with_capacity(query_vectors.len());
+    let start = Instant::now();
+    for query in &query_vectors {
+        let started = Instant::now();
+        let matches = store.search(query, workload.k, workload.ef.max(workload.k), &filter)?;
+        latencies.push(ms_since(started));
+        found.push(matches.into_iter().map(|m| m.id).collect::<Vec<_>>());
+    }
+    let search_ms = ms_since(start);
+
+    // Exact top-k by scanning every stored vector
+    let metric = store.metric();
+    let ids = store.ids();
+    let hits: usize = query_vectors.par_iter()
+        .zip(&found)
+        .map(|(query, found)| {
+            let query = metric.prepare(query);
+            let mut exact: Vec<(f32, &String)> = ids.iter()
+                .filter_map(|id| store.get(id).map(|v| (metric.distance(&query, v), id)))
+                .collect();
+            exact.sort_by(|a, b| a.0.total_cmp(&b.0));
+            exact.iter().take(workload.k).filter(|(_, id)| found.contains(id)).count()
+        })
+        .sum();
+    let expected = query_vectors.len() * workload.k.min(workload.n);
+
+    latencies.sort_by(f64::total_cmp);
+    let search = SearchStats {
+        mean_ms: latencies.iter().sum::<f64>() / latencies.len().max(1) as f64,
+        p50_ms: percentile(&latencies, 0.5),
+        p95_ms: percentile(&latencies, 0.95),
+        p99_ms: percentile(&latencies, 0.99),
+        max_ms: latencies.last().copied().unwrap_or(0.0),
+        qps: if search_ms > 0.0 { latencies.len() as f64 * 1000.0 / search_ms } else { 0.0 },
+        recall_at_k: if expected > 0 { hits as f64 / expected as f64 } else { 1.0 },
+    };
+    Ok(Report { backend: embedded.backend, dim: embedded.dim, embed, insert, search })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workload(n: usize) -> Workload {
+        Workload {
+            n,
+            dim: 32,
+            queries: 20,
+            k: 5,
+            ef: 64,
+            words: 6,
+            seed: 7,
+            backend: None,
+            quantization: Quantization::None,
+        }
+    }
+
+    #[test]
+    fn test_percentiles_and_throughput() {
+        let sorted: Vec<f64> = (1..=100).map(f64::from).collect();
+        assert_eq!(percentile(&sorted, 0.5), 51.0); // index 49.5 rounds up
+        assert_eq!(percentile(&sorted, 0.95), 95.0);
+        assert_eq!(percentile(&sorted, 1.0), 100.0);
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+        assert_eq!(percentile(&[], 0.99), 0.0);
+
+        assert_eq!(stage(500.0, 1000).items_per_sec, 2000.0);
+        assert_eq!(stage(0.0, 1000).items_per_sec, 0.0);
+    }
+
+    #[test]
+    fn test_workload_depends_only_on_the_seed() {
+        let vocabulary: Vec<String> = ["a", "b", "c", "d"].iter().map(|w| w.to_string()).collect();
+        let first = texts(&mut Lcg(3), &vocabulary, 5, 4);
+        assert_eq!(first, texts(&mut Lcg(3), &vocabulary, 5, 4));
+        assert_ne!(first, texts(&mut Lcg(4), &vocabulary, 5, 4));
+        assert!(first.iter().all(|text| text.split(' ').count() == 4));
+    }
+
+    #[test]
+    fn test_report_covers_every_stage() {
+        let report = run(&workload(300)).unwrap();
+        assert_eq!((report.backend, report.dim), ("hash", 32));
+        assert!(report.embed.total_ms >= 0.0 && report.insert.total_ms >= 0.0);
+
+        let search = &report.search;
+        assert!(search.p50_ms <= search.p95_ms && search.p95_ms <= search.p99_ms && search.p99_ms <= search.max_ms);
+        assert!(search.mean_ms <= search.max_ms);
+        assert!(search.recall_at_k > 0.9, "recall {}", search.recall_at_k);
+        assert!(search.recall_at_k <= 1.0);
+
+        // k beyond the corpus: recall is measured against what exists
+        let small = run(&Workload { k: 50, ..workload(10) }).unwrap();
+        assert_eq!(small.search.recall_at_k, 1.0);
+
+        assert!(run(&workload(0)).is_err());
+        assert!(run(&workload(MAX_ITEMS + 1)).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+// K-means clustering (`cluster`)
+// Spherical k-means over normalized embeddings: k-means++ seeding, then assign/update rounds until no
+// entry changes cluster. Assignment runs in parallel with rayon. Seeding is driven by `seed`, so the
+// same input gives the same clusters.
+use rayon::prelude::*;
+
+pub const DEFAULT_MAX_ITER: usize = 100;
+
+pub struct KMeans {
+    pub centroids: Vec<Vec<f32>>,
+    pub assignments: Vec<usize>,
+    pub similarities: Vec<f32>, // each entry's cosine similarity to its centroid
+    pub iterations: usize,
+}
+
+/// Deterministic pseudo-random numbers (same LCG as the hash embedder)
+pub struct Lcg(pub u64);
+
+impl Lcg {
+    /// Uniform in [0, 1)
+    pub fn next(&mut self) -> f64 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn normalized(mut v: Vec<f32>) -> Vec<f32> {
+    let norm = dot(&v, &v).sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+    v
+}
+
+/// Closest centroid and its similarity
+fn nearest(vector: &[f32], centroids: &[Vec<f32>]) -> (usize, f32) {
+    centroids.iter()
+        .enumerate()
+        .map(|(c, centroid)| (c, dot(vector, centroid)))
+        .fold((0, f32::MIN), |best, cur| if cur.1 > best.1 { cur } else { best })
+}
+
+/// k-means++: each further centroid is drawn with probability proportional to its squared distance
+fn seed_centroids(vectors: &[&[f32]], k: usize, rng: &mut Lcg) -> Vec<Vec<f32>> {
+    let first = (rng.next() * vectors.len() as f64) as usize;
+    let mut centroids = vec![vectors[first].to_vec()];
+    while centroids.len() < k {
+        let weights: Vec<f64> = vectors.par_iter()
+            .map(|v| (1.0 - nearest(v, &centroids).1).max(0.0) as f64)
+            .map(|d| d * d)
+            .collect();
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            break; // fewer distinct points than k
+        }
+        let mut target = rng.next() * total;
+        let pick = weights.iter().position(|w| {
+            target -= w;
+            target < 0.0
+        });
+        centroids.push(vectors[pick.unwrap_or(vectors.len() - 1)].to_vec());
+    }
+    centroids
+}
+
+/// Cluster normalized `vectors` into (at most) `k` groups
+pub fn kmeans(vectors: &[&[f32]], k: usize, max_iter: usize, seed: u64) -> KMeans {
+    if vectors.is_empty() || k == 0 {
+        return KMeans { centroids: Vec::new(), assignments: Vec::new(), similarities: Vec::new(), iterations: 0 };
+    }
+    let mut rng = Lcg(seed);
+    let mut centroids = seed_centroids(vectors, k.min(vectors.len()), &mut rng);
+    let dim = vectors[0].len();
+    let mut assignments = vec![usize::MAX; vectors.len()];
+    let mut iterations = 0;
+
+    while iterations < max_iter {
+        iterations += 1;
+        let next: Vec<usize> = vectors.par_iter().map(|v| nearest(v, &centroids).0).collect();
+        if next == assignments {
+            break;
+        }
+        assignments = next;
+
+        let mut sums = vec![vec![0.0f32; dim]; centroids.len()];
+        let mut counts = vec![0usize; centroids.len()];
+        for (v, &c) in vectors.iter().zip(&assignments) {
+            sums[c].iter_mut().zip(v.iter()).for_each(|(s, x)| *s += x);
+            counts[c] += 1;
+        }
+        for (c, sum) in sums.into_iter().enumerate() {
+            // An emptied cluster keeps its old centroid and may pick members up again next round
+            if counts[c] > 0 {
+                centroids[c] = normalized(sum);
+            }
+        }
+    }
+
+    let similarities = vectors.par_iter().zip(&assignments).map(|(v, &c)| dot(v, &centroids[c])).collect();
+    KMeans { centroids, assignments, similarities, iterations }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `per` noisy normalized points around each of `centers` axis directions (in 8 dimensions)
+    fn blobs(centers: usize, per: usize, rng: &mut Lcg) -> Vec<Vec<f32>> {
+        (0..centers * per)
+            .map(|i| normalized((0..8).map(|d| if d == i % centers { 1.0 } else { 0.0 } + (rng.next() as f32 - 0.5) * 0.3).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_kmeans_converges_on_separated_groups() {
+        let points = blobs(4, 50, &mut Lcg(1));
+        let refs: Vec<&[f32]> = points.iter().map(Vec::as_slice).collect();
+        let result = kmeans(&refs, 4, DEFAULT_MAX_ITER, 42);
+
+        // Stopped because nothing moved, not because it ran out of rounds
+        assert!(result.iterations < DEFAULT_MAX_ITER, "{} iterations", result.iterations);
+        assert_eq!(result.centroids.len(), 4);
+
+        // Each group lands in one cluster of its own
+        for group in 0..4 {
+            let cluster = result.assignments[group];
+            assert!((group..points.len()).step_by(4).all(|i| result.assignments[i] == cluster));
+            assert!((0..points.len()).filter(|i| i % 4 != group).all(|i| result.assignments[i] != cluster));
+        }
+
+        // At convergence every entry sits with its nearest centroid, and centroids are unit length
+        for (i, point) in points.iter().enumerate() {
+            assert_eq!(nearest(point, &result.centroids).0, result.assignments[i]);
+            assert!((result.similarities[i] - dot(point, &result.centroids[result.assignments[i]])).abs() < 1e-6);
+            assert!(result.similarities[i] > 0.9);
+        }
+        assert!(result.centroids.iter().all(|c| (dot(c, c) - 1.0).abs() < 1e-5));
+    }
+
+    #[test]
+    fn test_kmeans_is_deterministic_per_seed() {
+        let points = blobs(3, 40, &mut Lcg(7));
+        let refs: Vec<&[f32]> = points.iter().map(Vec::as_slice).collect();
+        let a = kmeans(&refs, 5, DEFAULT_MAX_ITER, 3);
+        let b = kmeans(&refs, 5, DEFAULT_MAX_ITER, 3);
+        assert_eq!(a.assignments, b.assignments);
+        assert_eq!(a.centroids, b.centroids);
+
+        // A round limit is honored
+        assert_eq!(kmeans(&refs, 5, 1, 3).iterations, 1);
+    }
+
+    #[test]
+    fn test_kmeans_small_inputs() {
+        assert!(kmeans(&[], 3, DEFAULT_MAX_ITER, 1).centroids.is_empty());
+
+        // More clusters than distinct points
+        let same = [1.0, 0.0];
+        let result = kmeans(&[&same, &same, &same], 3, DEFAULT_MAX_ITER, 1);
+        assert_eq!(result.centroids.len(), 1);
+        assert_eq!(result.assignments, [0, 0, 0]);
+    }
+}
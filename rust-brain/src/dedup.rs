@@ -0,0 +1,132 @@
+// Near-duplicate detection (`dedup`)
+// Every entry asks the HNSW index for its nearest neighbors; pairs at or above the similarity threshold
+// are joined with union-find, so A~B and B~C end up in one cluster even when A and C are further apart.
+// Each cluster names a representative (the member with the most duplicate links) - the one to keep
+// when pruning learned fragments.
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use crate::filter::Filter;
+use crate::vector_store::VectorStore;
+
+pub const DEFAULT_THRESHOLD: f32 = 0.95;
+pub const DEFAULT_NEIGHBORS: usize = 10;
+
+/// One group of near-duplicates, as positions in the `ids` passed to `clusters`
+#[derive(Debug, Serialize)]
+pub struct Cluster {
+    pub representative: usize,
+    pub members: Vec<usize>,
+    pub min_similarity: f32, // weakest link that joined the cluster
+}
+
+fn find(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+/// Duplicate clusters among `ids` (largest first) - only pairs within `ids` count
+pub fn clusters(store: &VectorStore, ids: &[String], threshold: f32, neighbors: usize, ef: usize) -> Result<Vec<Cluster>, String> {
+    let position: HashMap<&str, usize> = ids.iter().enumerate().map(|(i, id)| (id.as_str(), i)).collect();
+    let filter = Filter { min_score: Some(threshold), ..Filter::default() };
+
+    let edges: Vec<(usize, usize, f32)> = ids
+        .par_iter()
+        .enumerate()
+        .map(|(i, id)| {
+            let vector = store.get(id).ok_or_else(|| format!("Unknown id '{}'", id))?;
+            let matches = store.search(vector, neighbors + 1, ef, &filter)?;
+            Ok(matches.into_iter()
+                .filter_map(|m| position.get(m.id.as_str()).map(|&j| (i, j, m.score)))
+                .filter(|&(i, j, _)| i < j)
+                .collect::<Vec<_>>())
+        })
+        .collect::<Result<Vec<_>, String>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+
+    let mut parent: Vec<usize> = (0..ids.len()).collect();
+    let mut degree = vec![0usize; ids.len()];
+    for &(i, j, _) in &edges {
+        degree[i] += 1;
+        degree[j] += 1;
+        let (a, b) = (find(&mut parent, i), find(&mut parent, j));
+        if a != b {
+            parent[a.max(b)] = a.min(b);
+        }
+    }
+
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..ids.len() {
+        let root = find(&mut parent, i);
+        groups.entry(root).or_default().push(i);
+    }
+    let mut weakest: HashMap<usize, f32> = HashMap::new();
+    for &(i, _, score) in &edges {
+        let root = find(&mut parent, i);
+        let entry = weakest.entry(root).or_insert(score);
+        *entry = entry.min(score);
+    }
+
+    let mut clusters: Vec<Cluster> = groups
+        .into_iter()
+        .filter(|(_, members)| members.len() > 1)
+        .map(|(root, members)| Cluster {
+            // Most links wins, ties go to the earliest entry
+            representative: *members.iter().max_by_key(|&&m| (degree[m], std::cmp::Reverse(m))).expect("cluster has members"),
+            min_similarity: weakest.get(&root).copied().unwrap_or(threshold),
+            members,
+        })
+        .collect();
+    clusters.sort_by_key(|c| (std::cmp::Reverse(c.members.len()), c.members[0]));
+    Ok(clusters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector_store::DEFAULT_EF;
+    use serde_json::Value;
+
+    fn store(vectors: &[(&str, [f32; 3])]) -> (VectorStore, Vec<String>) {
+        let mut store = VectorStore::in_memory();
+        for (id, vector) in vectors {
+            store.upsert(id, vector, Value::Null).unwrap();
+        }
+        let ids = store.ids();
+        (store, ids)
+    }
+
+    #[test]
+    fn test_chained_duplicates_share_a_cluster() {
+        // a~b and b~c clear 0.99, a and c alone don't; d is a separate pair with e, f stands alone
+        let (store, ids) = store(&[
+            ("a", [1.0, 0.0, 0.0]),
+            ("b", [1.0, 0.1, 0.0]),
+            ("c", [1.0, 0.2, 0.0]),
+            ("d", [0.0, 0.0, 1.0]),
+            ("e", [0.0, 0.05, 1.0]),
+            ("f", [0.0, 1.0, 0.0]),
+        ]);
+        let clusters = clusters(&store, &ids, 0.99, DEFAULT_NEIGHBORS, DEFAULT_EF).unwrap();
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].members, [0, 1, 2]);
+        assert_eq!(clusters[0].representative, 1); // linked to both others
+        assert!(clusters[0].min_similarity >= 0.99 && clusters[0].min_similarity < 0.996);
+        assert_eq!(clusters[1].members, [3, 4]);
+        assert_eq!(clusters[1].representative, 3); // tie goes to the earlier entry
+    }
+
+    #[test]
+    fn test_only_listed_ids_count() {
+        let (store, _) = store(&[("a", [1.0, 0.0, 0.0]), ("b", [1.0, 0.1, 0.0]), ("c", [1.0, 0.2, 0.0])]);
+        let ids = vec!["a".to_string(), "c".to_string()];
+        assert!(clusters(&store, &ids, 0.99, DEFAULT_NEIGHBORS, DEFAULT_EF).unwrap().is_empty());
+        assert_eq!(clusters(&store, &ids, 0.9, DEFAULT_NEIGHBORS, DEFAULT_EF).unwrap()[0].members, [0, 1]);
+        assert!(clusters(&store, &["x".to_string()], 0.9, DEFAULT_NEIGHBORS, DEFAULT_EF).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+// Diversified selection (`select_diverse`) and pairwise similarity (`similarity_matrix`)
+// Maximal marginal relevance picks results one at a time, each maximizing
+//   lambda * relevance - (1 - lambda) * (highest similarity to anything already picked)
+// so lambda = 1 is a plain relevance ranking and lower values trade relevance for variety - k results
+// that answer the query without five of them saying the same thing. Similarities are cosine whatever the
+// vectors' metric, and only the picks so far are compared against, so no n x n matrix is built.
+use rayon::prelude::*;
+use serde::Serialize;
+use crate::metric::{dot, normalize};
+
+pub const DEFAULT_LAMBDA: f32 = 0.5;
+/// Largest set `similarity_matrix` answers for - the reply grows with the square
+pub const MAX_MATRIX_SIZE: usize = 2048;
+
+/// One pick, as a position in the candidates passed to `mmr`
+#[derive(Debug, Serialize)]
+pub struct Selection {
+    pub index: usize,
+    pub relevance: f32,
+    pub redundancy: f32, // highest similarity to an earlier pick (0 for the first)
+    pub score: f32,
+}
+
+/// Pick up to `k` of `vectors` by maximal marginal relevance, given each one's `relevance`
+pub fn mmr(vectors: &[&[f32]], relevance: &[f32], k: usize, lambda: f32) -> Vec<Selection> {
+    let units: Vec<Vec<f32>> = vectors.par_iter().map(|v| normalize(v)).collect();
+    let mut redundancy = vec![0.0f32; units.len()];
+    let mut picked = vec![false; units.len()];
+    let mut selected: Vec<Selection> = Vec::new();
+
+    while selected.len() < k.min(units.len()) {
+        let marginal = |i: usize| lambda * relevance[i] - (1.0 - lambda) * redundancy[i];
+        // Ties go to the earlier candidate, so input order breaks them the same way every time
+        let best = (0..units.len())
+            .filter(|&i| !picked[i])
+            .reduce(|best, i| if marginal(i) > marginal(best) { i } else { best });
+        let Some(best) = best else { break };
+        picked[best] = true;
+        selected.push(Selection {
+            index: best,
+            relevance: relevance[best],
+            redundancy: redundancy[best],
+            score: marginal(best),
+        });
+        let chosen = &units[best];
+        redundancy.par_iter_mut().zip(&units).for_each(|(r, unit)| *r = r.max(dot(unit, chosen)));
+    }
+    selected
+}
+
+/// Cosine similarity of every pair of `vectors`, row by row
+pub fn similarity_matrix(vectors: &[&[f32]]) -> Vec<Vec<f32>> {
+    let units: Vec<Vec<f32>> = vectors.par_iter().map(|v| normalize(v)).collect();
+    units.par_iter()
+        .map(|a| units.iter().map(|b| dot(a, b)).collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn picks(selection: &[Selection]) -> Vec<usize> {
+        selection.iter().map(|s| s.index).collect()
+    }
+
+    #[test]
+    fn test_mmr_trades_relevance_for_variety() {
+        // Two near-copies of the best match, an unrelated match and one halfway between
+        let vectors: [&[f32]; 4] = [&[1.0, 0.0], &[1.0, 0.01], &[0.0, 1.0], &[0.7, 0.7]];
+        let relevance = [0.9, 0.89, 0.5, 0.7];
+
+        assert_eq!(picks(&mmr(&vectors, &relevance, 3, 1.0)), [0, 1, 3]);
+        let diverse = mmr(&vectors, &relevance, 3, DEFAULT_LAMBDA);
+        assert_eq!(picks(&diverse), [0, 2, 3]);
+        assert_eq!(diverse[0].redundancy, 0.0);
+        assert!((diverse[0].score - DEFAULT_LAMBDA * 0.9).abs() < 1e-6);
+        let expected = DEFAULT_LAMBDA * 0.7 - (1.0 - DEFAULT_LAMBDA) * diverse[2].redundancy;
+        assert!((diverse[2].score - expected).abs() < 1e-6);
+        assert!((diverse[2].redundancy - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_mmr_limits_and_ties() {
+        let vectors: [&[f32]; 2] = [&[1.0, 0.0], &[0.0, 1.0]];
+        assert_eq!(picks(&mmr(&vectors, &[0.5, 0.5], 5, 0.5)), [0, 1]);
+        assert!(mmr(&vectors, &[0.5, 0.5], 0, 0.5).is_empty());
+    }
+
+    #[test]
+    fn test_similarity_matrix_is_cosine() {
+        let matrix = similarity_matrix(&[&[2.0, 0.0], &[0.0, 3.0], &[1.0, 1.0]]);
+        assert_eq!(matrix.len(), 3);
+        for (i, row) in matrix.iter().enumerate() {
+            assert!((row[i] - 1.0).abs() < 1e-6);
+            assert!(row.iter().enumerate().all(|(j, &similarity)| similarity == matrix[j][i]));
+        }
+        assert_eq!(matrix[0][1], 0.0);
+        assert!((matrix[0][2] - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+    }
+}
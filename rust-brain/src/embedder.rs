@@ -0,0 +1,286 @@
+// Text embedding backends
+// The deterministic hash embedder is always available. Built with `--features model`, a real
+// sentence-embedding model (config.json, tokenizer.json, model.safetensors - e.g. all-MiniLM-L6-v2) is
+// used instead: run in batches, mean-pooled. One model is held at a time - the one `load_model` picked,
+// or else the registry's active model, loaded on first use (see models.rs).
+//
+// Config (environment, a request's payload overrides):
+//   RUST_BRAIN_EMBEDDER   auto | hash | model  (auto = model when one is available, else hash)
+//   RUST_BRAIN_MODEL_DIR  directory holding the model files, when no registered model is active
+use rayon::prelude::*;
+#[cfg(feature = "model")]
+use std::path::Path;
+use crate::models;
+
+pub const DEFAULT_BATCH_SIZE: usize = 32;
+
+/// Backends this build can embed with
+#[cfg(feature = "model")]
+pub const BACKENDS: &[&str] = &["hash", "model"];
+#[cfg(not(feature = "model"))]
+pub const BACKENDS: &[&str] = &["hash"];
+
+/// Embeddings plus which backend produced them (model vectors have the model's width, not `dim`)
+pub struct Embedded {
+    pub vectors: Vec<Vec<f32>>,
+    pub backend: &'static str,
+    pub dim: usize,
+}
+
+/// Embed `texts` with the configured backend
+pub fn embed(texts: &[&str], backend: Option<&str>, dim: usize, batch_size: usize) -> Result<Embedded, String> {
+    let backend = backend
+        .map(str::to_string)
+        .or_else(|| std::env::var("RUST_BRAIN_EMBEDDER").ok())
+        .unwrap_or_else(|| "auto".to_string());
+
+    match backend.as_str() {
+        "hash" => Ok(embed_hash(texts, dim)),
+        "model" => embed_model(texts, batch_size),
+        // A missing or broken model falls back to the hash embedder
+        "auto" => match loaded_model().is_some() || models::default_model().is_some() {
+            true => embed_model(texts, batch_size).or_else(|e| {
+                eprintln!("rust-brain: model unavailable ({}), using hash embeddings", e);
+                Ok(embed_hash(texts, dim))
+            }),
+            false => Ok(embed_hash(texts, dim)),
+        },
+        other => Err(format!("Unknown embedding backend '{}' (auto, hash or model)", other)),
+    }
+}
+
+fn embed_hash(texts: &[&str], dim: usize) -> Embedded {
+    Embedded {
+        vectors: texts.par_iter().map(|text| hash_embedding(text, dim)).collect(),
+        backend: "hash",
+        dim,
+    }
+}
+
+/// Deterministic pseudo-embedding - stable per text, but carries no meaning
+pub fn hash_embedding(text: &str, dim: usize) -> Vec<f32> {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    let seed = hasher.finish();
+
+    // Deterministic pseudo-random using simple LCG
+    let mut rng = seed;
+    let mut emb = Vec::with_capacity(dim);
+
+    for _ in 0..dim {
+        rng = rng.wrapping_mul(1103515245).wrapping_add(12345);
+        let val = ((rng / 65536) % 1000) as f32 / 1000.0 - 0.5;
+        emb.push(val);
+    }
+
+    normalize(emb)
+}
+
+fn normalize(mut emb: Vec<f32>) -> Vec<f32> {
+    let norm: f32 = emb.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        for val in &mut emb {
+            *val /= norm;
+        }
+    }
+    emb
+}
+
+#[cfg(not(feature = "model"))]
+fn embed_model(_texts: &[&str], _batch_size: usize) -> Result<Embedded, String> {
+    Err("rust-brain was built without the `model` feature".to_string())
+}
+
+/// Load the model in `dir` as `name`, replacing the one held - its embedding width
+#[cfg(feature = "model")]
+pub fn load_model(name: &str, dir: &Path) -> Result<usize, String> {
+    model::load(name, dir)
+}
+
+#[cfg(not(feature = "model"))]
+pub fn load_model(_name: &str, _dir: &std::path::Path) -> Result<usize, String> {
+    Err("rust-brain was built without the `model` feature".to_string())
+}
+
+/// Drop the held model - the name it was loaded as
+#[cfg(feature = "model")]
+pub fn unload_model() -> Option<String> {
+    model::unload()
+}
+
+#[cfg(not(feature = "model"))]
+pub fn unload_model() -> Option<String> {
+    None
+}
+
+/// Name and embedding width of the held model
+#[cfg(feature = "model")]
+pub fn loaded_model() -> Option<(String, usize)> {
+    model::loaded()
+}
+
+#[cfg(not(feature = "model"))]
+pub fn loaded_model() -> Option<(String, usize)> {
+    None
+}
+
+#[cfg(feature = "model")]
+fn embed_model(texts: &[&str], batch_size: usize) -> Result<Embedded, String> {
+    let model = model::get()?;
+    let model = model.as_ref();
+    let mut vectors = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(batch_size.max(1)) {
+        vectors.extend(model.embed(batch)?.into_iter().map(normalize));
+    }
+    Ok(Embedded { vectors, backend: "model", dim: model.dim })
+}
+
+#[cfg(feature = "model")]
+mod model {
+    use candle_core::{Device, Tensor};
+    use candle_nn::VarBuilder;
+    use candle_transformers::models::bert::{BertModel, Config, DTYPE};
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use tokenizers::{PaddingParams, PaddingStrategy, Tokenizer, TruncationParams};
+
+    /// Longer texts are truncated (MiniLM was trained on 256 tokens)
+    const MAX_TOKENS: usize = 256;
+
+    /// The held model by name - loaded on first use, so requests that never embed don't pay for it. A
+    /// failed load is kept too, so a broken model isn't retried on every batch
+    static MODEL: Mutex<Option<Held>> = Mutex::new(None);
+
+    /// A model's name and how loading it went
+    type Held = (String, Result<Arc<Model>, String>);
+
+    pub struct Model {
+        bert: BertModel,
+        tokenizer: Tokenizer,
+        device: Device,
+        pub dim: usize,
+    }
+
+    fn err(e: impl std::fmt::Display) -> String {
+        e.to_string()
+    }
+
+    pub fn get() -> Result<Arc<Model>, String> {
+        let mut held = MODEL.lock().unwrap_or_else(|e| e.into_inner());
+        let (_, model) = held.get_or_insert_with(|| match crate::models::default_model() {
+            Some((name, dir)) => (name, Model::load(&dir).map(Arc::new)),
+            None => (String::new(), Err("No model is loaded or active, and RUST_BRAIN_MODEL_DIR holds none".to_string())),
+        });
+        model.clone()
+    }
+
+    pub fn load(name: &str, dir: &Path) -> Result<usize, String> {
+        let model = Model::load(dir)?;
+        let dim = model.dim;
+        *MODEL.lock().unwrap_or_else(|e| e.into_inner()) = Some((name.to_string(), Ok(Arc::new(model))));
+        Ok(dim)
+    }
+
+    pub fn unload() -> Option<String> {
+        let held = MODEL.lock().unwrap_or_else(|e| e.into_inner()).take();
+        held.filter(|(_, model)| model.is_ok()).map(|(name, _)| name)
+    }
+
+    pub fn loaded() -> Option<(String, usize)> {
+        let held = MODEL.lock().unwrap_or_else(|e| e.into_inner());
+        held.as_ref().and_then(|(name, model)| model.as_ref().ok().map(|m| (name.clone(), m.dim)))
+    }
+
+    impl Model {
+        fn load(dir: &Path) -> Result<Model, String> {
+            let config = std::fs::read_to_string(dir.join("config.json"))
+                .map_err(|e| format!("config.json: {}", e))?;
+            let config: Config = serde_json::from_str(&config).map_err(|e| format!("config.json: {}", e))?;
+
+            let mut tokenizer = Tokenizer::from_file(dir.join("tokenizer.json"))
+                .map_err(|e| format!("tokenizer.json: {}", e))?;
+            tokenizer.with_padding(Some(PaddingParams {
+                strategy: PaddingStrategy::BatchLongest,
+                ..Default::default()
+            }));
+            tokenizer
+                .with_truncation(Some(TruncationParams { max_length: MAX_TOKENS, ..Default::default() }))
+                .map_err(err)?;
+
+            let device = Device::Cpu;
+            // SAFETY: the weights file is only read, and isn't expected to change while mapped
+            let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[dir.join("model.safetensors")], DTYPE, &device) }
+                .map_err(|e| format!("model.safetensors: {}", e))?;
+            let bert = BertModel::load(vb, &config).map_err(err)?;
+            Ok(Model { bert, tokenizer, device, dim: config.hidden_size })
+        }
+
+        /// Mean-pooled token embeddings (padding excluded) for one batch
+        pub fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, String> {
+            if texts.is_empty() {
+                return Ok(Vec::new());
+            }
+            let encodings = self.tokenizer.encode_batch(texts.to_vec(), true).map_err(err)?;
+            let ids = encodings.iter()
+                .map(|e| Tensor::new(e.get_ids(), &self.device))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(err)?;
+            let masks = encodings.iter()
+                .map(|e| Tensor::new(e.get_attention_mask(), &self.device))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(err)?;
+
+            let input_ids = Tensor::stack(&ids, 0).map_err(err)?;
+            let mask = Tensor::stack(&masks, 0).map_err(err)?;
+            let token_type_ids = input_ids.zeros_like().map_err(err)?;
+            let hidden = self.bert.forward(&input_ids, &token_type_ids, Some(&mask)).map_err(err)?;
+
+            let mask = mask.to_dtype(DTYPE).and_then(|m| m.unsqueeze(2)).map_err(err)?;
+            let summed = hidden.broadcast_mul(&mask).and_then(|t| t.sum(1)).map_err(err)?;
+            let counts = mask.sum(1).map_err(err)?;
+            summed.broadcast_div(&counts).and_then(|t| t.to_vec2()).map_err(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_embedding_dimension_and_determinism() {
+        for dim in [1, 16, 384] {
+            let vector = hash_embedding("journalctl -u nginx", dim);
+            assert_eq!(vector.len(), dim);
+            let norm: f32 = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+            assert!((norm - 1.0).abs() < 1e-5, "dim {} norm {}", dim, norm);
+        }
+        assert_eq!(hash_embedding("ls -la", 64), hash_embedding("ls -la", 64));
+        assert_ne!(hash_embedding("ls -la", 64), hash_embedding("ls -l", 64));
+        assert!(hash_embedding("", 0).is_empty());
+    }
+
+    #[test]
+    fn test_embed_batches_match_single_texts() {
+        let texts = ["uptime", "df -h", "uptime"];
+        let embedded = embed(&texts, Some("hash"), 32, 2).unwrap();
+        assert_eq!((embedded.backend, embedded.dim, embedded.vectors.len()), ("hash", 32, 3));
+        assert_eq!(embedded.vectors[0], embedded.vectors[2]);
+        assert_eq!(embedded.vectors[1], hash_embedding("df -h", 32));
+        assert!(embed(&[], Some("hash"), 32, 2).unwrap().vectors.is_empty());
+    }
+
+    #[test]
+    fn test_unknown_and_unavailable_backends() {
+        assert!(embed(&["x"], Some("word2vec"), 8, 1).map(drop).unwrap_err().contains("Unknown embedding backend"));
+        #[cfg(not(feature = "model"))]
+        {
+            assert!(embed(&["x"], Some("model"), 8, 1).map(drop).unwrap_err().contains("without the `model` feature"));
+            assert_eq!(BACKENDS, ["hash"]);
+            assert!(loaded_model().is_none() && unload_model().is_none());
+        }
+    }
+}
@@ -0,0 +1,161 @@
+// Metadata filters for similarity search
+// Applied while ranking (cosine_rank) and while walking the index (vector store), so "the most similar
+// command outputs from this session in the last hour" returns top_k matching entries rather than
+// top_k entries of which a few happen to match.
+//
+//   {"tags": ["error"], "source": "tmux", "where": {"session": "main"},
+//    "since": 1718000000, "until": 1718003600, "within_seconds": 3600, "min_score": 0.5}
+//
+// Times are unix seconds compared with the entry's `timestamp` metadata; an entry without one never
+// matches a time filter. `tags` must all be present in the entry's `tags` array.
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Filter {
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub source: Option<String>,
+    #[serde(default, rename = "where")]
+    pub fields: Map<String, Value>, // metadata fields that must equal these values
+    pub since: Option<f64>,
+    pub until: Option<f64>,
+    pub within_seconds: Option<f64>, // shorthand for since = now - within_seconds
+    pub min_score: Option<f32>,
+}
+
+impl Filter {
+    /// The payload's `filter` object (absent = match everything)
+    pub fn from_payload(payload: &Value) -> Result<Filter, String> {
+        let mut filter: Filter = match payload.get("filter") {
+            None | Some(Value::Null) => Filter::default(),
+            Some(value) => serde_json::from_value(value.clone()).map_err(|e| format!("Invalid filter: {}", e))?,
+        };
+        if let Some(window) = filter.within_seconds.take() {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0);
+            filter.since = Some(filter.since.map_or(now - window, |since| since.max(now - window)));
+        }
+        Ok(filter)
+    }
+
+    /// Whether an entry with this metadata may be returned (the score is checked separately)
+    pub fn matches(&self, metadata: &Value) -> bool {
+        let has_tags = self.tags.iter().all(|tag| {
+            metadata.get("tags")
+                .and_then(|v| v.as_array())
+                .is_some_and(|tags| tags.iter().any(|t| t.as_str() == Some(tag)))
+        });
+        let source_ok = self.source.as_ref()
+            .is_none_or(|source| metadata.get("source").and_then(|v| v.as_str()) == Some(source));
+        let fields_ok = self.fields.iter().all(|(key, value)| metadata.get(key) == Some(value));
+
+        let timestamp = metadata.get("timestamp").and_then(|v| v.as_f64());
+        let time_ok = match (self.since, self.until) {
+            (None, None) => true,
+            (since, until) => timestamp.is_some_and(|ts| {
+                since.is_none_or(|since| ts >= since) && until.is_none_or(|until| ts <= until)
+            }),
+        };
+
+        has_tags && source_ok && fields_ok && time_ok
+    }
+
+    pub fn score_ok(&self, score: f32) -> bool {
+        self.min_score.is_none_or(|min| score >= min)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn filter(value: Value) -> Filter {
+        Filter::from_payload(&json!({ "filter": value })).unwrap()
+    }
+
+    fn at(timestamp: f64) -> Value {
+        json!({ "timestamp": timestamp })
+    }
+
+    #[test]
+    fn test_time_range_is_inclusive() {
+        let range = filter(json!({ "since": 100.0, "until": 200.0 }));
+        assert!(range.matches(&at(100.0)) && range.matches(&at(150.0)) && range.matches(&at(200.0)));
+        assert!(!range.matches(&at(99.9)) && !range.matches(&at(200.1)));
+        // No timestamp never matches a time filter
+        assert!(!range.matches(&json!({})));
+        assert!(!filter(json!({ "since": 0.0 })).matches(&json!({ "timestamp": "yesterday" })));
+
+        let open_ended = filter(json!({ "until": 200.0 }));
+        assert!(open_ended.matches(&at(-5.0)) && !open_ended.matches(&at(201.0)));
+    }
+
+    #[test]
+    fn test_empty_and_inverted_ranges() {
+        // A single instant
+        let instant = filter(json!({ "since": 150.0, "until": 150.0 }));
+        assert!(instant.matches(&at(150.0)));
+        assert!(!instant.matches(&at(150.5)));
+
+        // Inverted: nothing is both after since and before until
+        let inverted = filter(json!({ "since": 200.0, "until": 100.0 }));
+        assert!([50.0, 100.0, 150.0, 200.0, 250.0].iter().all(|&ts| !inverted.matches(&at(ts))));
+
+        // within_seconds narrows since, and never widens it
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs_f64();
+        let recent = filter(json!({ "within_seconds": 60.0 }));
+        assert!(recent.within_seconds.is_none());
+        assert!(recent.matches(&at(now - 10.0)) && !recent.matches(&at(now - 120.0)));
+        let later = filter(json!({ "within_seconds": 3600.0, "since": now - 30.0 }));
+        assert!(!later.matches(&at(now - 60.0)));
+        let window_past_until = filter(json!({ "within_seconds": 60.0, "until": now - 3600.0 }));
+        assert!(!window_past_until.matches(&at(now - 30.0)) && !window_past_until.matches(&at(now - 3600.0)));
+    }
+
+    #[test]
+    fn test_min_score() {
+        assert!(Filter::default().score_ok(-1.0));
+        let min = filter(json!({ "min_score": 0.5 }));
+        assert!(min.score_ok(0.5) && min.score_ok(0.9));
+        assert!(!min.score_ok(0.4999));
+        // Scores aren't part of metadata matching
+        assert!(min.matches(&json!({})));
+    }
+
+    #[test]
+    fn test_tags_source_and_fields() {
+        let entry = json!({ "tags": ["error", "nginx"], "source": "tmux", "session": "main", "exit_code": 1 });
+        assert!(filter(json!({ "tags": ["error"] })).matches(&entry));
+        assert!(filter(json!({ "tags": ["nginx", "error"] })).matches(&entry));
+        assert!(!filter(json!({ "tags": ["error", "disk"] })).matches(&entry));
+        assert!(filter(json!({ "tags": [] })).matches(&json!({})));
+        assert!(!filter(json!({ "tags": ["error"] })).matches(&json!({ "tags": "error" })));
+
+        assert!(filter(json!({ "source": "tmux", "where": { "session": "main", "exit_code": 1 } })).matches(&entry));
+        assert!(!filter(json!({ "source": "journal" })).matches(&entry));
+        assert!(!filter(json!({ "where": { "exit_code": "1" } })).matches(&entry));
+        assert!(!filter(json!({ "where": { "pane": 0 } })).matches(&entry));
+
+        assert!(Filter::from_payload(&json!({})).unwrap().matches(&json!(null)));
+        assert!(Filter::from_payload(&json!({ "filter": { "tag": ["error"] } })).is_err());
+    }
+
+    #[test]
+    fn test_search_returns_top_k_matching_entries() {
+        let mut store = crate::vector_store::VectorStore::in_memory();
+        for i in 0..40 {
+            let tags = if i % 10 == 0 { json!(["error"]) } else { json!([]) };
+            let vector = [1.0, i as f32 / 40.0, 0.0];
+            store.upsert(&format!("e{}", i), &vector, json!({ "tags": tags, "timestamp": i as f64 })).unwrap();
+        }
+        let errors = filter(json!({ "tags": ["error"] }));
+        let found = store.search(&[1.0, 0.0, 0.0], 3, 16, &errors).unwrap();
+        assert_eq!(found.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(), ["e0", "e10", "e20"]);
+
+        let inverted = filter(json!({ "since": 30.0, "until": 10.0 }));
+        assert!(store.search(&[1.0, 0.0, 0.0], 3, 16, &inverted).unwrap().is_empty());
+    }
+}
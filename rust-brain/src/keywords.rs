@@ -0,0 +1,270 @@
+// Keyword index with BM25 scoring
+// Pseudo-embeddings (and even real ones) miss exact tokens - an error code, a file name, a flag. Each
+// vector store keeps an inverted index of its entries' text in `keywords.json`, so hybrid_search can
+// fuse BM25 with vector similarity.
+//
+// The same index is the corpus `extract_keywords` weighs a text against: a term scores by how often the
+// text uses it (TF) times how rare it is among stored entries (IDF), so words every entry shares sink
+// and the ones that set this text apart rise. Two-word phrases count when the text repeats them.
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+
+#[derive(Default, Serialize, Deserialize)]
+struct Doc {
+    len: u32,
+    terms: Vec<String>, // distinct terms, for removal
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct KeywordIndex {
+    docs: HashMap<String, Doc>,
+    postings: HashMap<String, HashMap<String, u32>>, // term -> entry id -> term frequency
+    total_len: u64,
+}
+
+/// Lowercased words; `_ . - /` stay inside a word so `nginx.conf` and `ERR_TIMEOUT` match whole
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !(c.is_alphanumeric() || "_.-/".contains(c)))
+        .map(|word| word.trim_matches(|c: char| ".-/".contains(c)))
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Words too common to be worth a tag, whatever the corpus says
+const STOPWORDS: &[&str] = &[
+    "a", "about", "after", "all", "also", "an", "and", "any", "are", "as", "at", "be", "been", "before",
+    "but", "by", "can", "could", "did", "do", "does", "for", "from", "had", "has", "have", "he", "her",
+    "his", "how", "i", "if", "in", "into", "is", "it", "its", "just", "me", "more", "my", "no", "not", "now",
+    "of", "on", "one", "only", "or", "other", "our", "out", "she", "so", "some", "than", "that", "the",
+    "their", "them", "then", "there", "these", "they", "this", "to", "up", "use", "used", "using", "was",
+    "we", "were", "what", "when", "where", "which", "while", "who", "will", "with", "would", "you", "your",
+];
+
+fn is_keyword(token: &str) -> bool {
+    token.chars().count() > 1 && !STOPWORDS.contains(&token)
+}
+
+/// A term or phrase `extract` picked, weighted relative to the best one (1.0)
+#[derive(Serialize)]
+pub struct Keyword {
+    pub term: String,
+    pub weight: f32,
+    pub count: u32,
+}
+
+impl KeywordIndex {
+    /// Index in `dir` (empty when there is none yet)
+    pub fn load(dir: &Path) -> Result<KeywordIndex, String> {
+        match fs::read_to_string(dir.join("keywords.json")) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| format!("Corrupt keywords.json: {}", e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(KeywordIndex::default()),
+            Err(e) => Err(format!("Cannot read keywords.json: {}", e)),
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), String> {
+        let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        let tmp = dir.join("keywords.json.tmp");
+        fs::write(&tmp, json)
+            .and_then(|_| fs::rename(&tmp, dir.join("keywords.json")))
+            .map_err(|e| format!("Cannot write keywords.json: {}", e))
+    }
+
+    /// Index (or re-index) the text of entry `id`
+    pub fn insert(&mut self, id: &str, text: &str) {
+        self.remove(id);
+        let tokens = tokenize(text);
+        let mut counts: HashMap<String, u32> = HashMap::new();
+        for token in &tokens {
+            *counts.entry(token.clone()).or_default() += 1;
+        }
+        for (term, count) in &counts {
+            self.postings.entry(term.clone()).or_default().insert(id.to_string(), *count);
+        }
+        self.total_len += tokens.len() as u64;
+        self.docs.insert(id.to_string(), Doc { len: tokens.len() as u32, terms: counts.into_keys().collect() });
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        let doc = match self.docs.remove(id) {
+            Some(doc) => doc,
+            None => return,
+        };
+        self.total_len -= doc.len as u64;
+        for term in doc.terms {
+            if let Some(posting) = self.postings.get_mut(&term) {
+                posting.remove(id);
+                if posting.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+    }
+
+    /// Best `limit` entries for `query` by BM25 that `accept` lets through, best first
+    pub fn search(&self, query: &str, limit: usize, accept: impl Fn(&str) -> bool) -> Vec<(String, f32)> {
+        if self.docs.is_empty() {
+            return Vec::new();
+        }
+        let avg_len = self.total_len as f32 / self.docs.len() as f32;
+        let mut terms = tokenize(query);
+        terms.sort();
+        terms.dedup();
+
+        let mut scores: HashMap<&str, f32> = HashMap::new();
+        for term in &terms {
+            let posting = match self.postings.get(term) {
+                Some(posting) => posting,
+                None => continue,
+            };
+            let idf = self.idf(term);
+            for (id, &tf) in posting {
+                let len = self.docs.get(id).map_or(0, |doc| doc.len) as f32;
+                let tf = tf as f32;
+                *scores.entry(id.as_str()).or_default() +=
+                    idf * tf * (K1 + 1.0) / (tf + K1 * (1.0 - B + B * len / avg_len.max(1.0)));
+            }
+        }
+
+        let mut ranked: Vec<(String, f32)> = scores.into_iter()
+            .filter(|(id, _)| accept(id))
+            .map(|(id, score)| (id.to_string(), score))
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ranked.truncate(limit);
+        ranked
+    }
+
+    /// Entries indexed
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    /// BM25 inverse document frequency - equal for every term while the index is empty
+    fn idf(&self, term: &str) -> f32 {
+        let n = self.docs.len() as f32;
+        let df = self.postings.get(term).map_or(0, |posting| posting.len()) as f32;
+        (1.0 + (n - df + 0.5) / (df + 0.5)).ln()
+    }
+
+    /// Top `limit` terms of `text` by TF-IDF against the indexed entries; two-word phrases that occur
+    /// at least `min_phrase_count` times compete too, weighted by both words' rarity
+    pub fn extract(&self, text: &str, limit: usize, min_phrase_count: u32) -> Vec<Keyword> {
+        let mut terms: HashMap<String, u32> = HashMap::new();
+        let mut phrases: HashMap<(String, String), u32> = HashMap::new();
+        let mut total = 0;
+        // A phrase never spans punctuation or a sentence break
+        for segment in text.split(['\n', ',', ';', ':', '!', '?', '(', ')', '[', ']', '{', '}', '"', '|']).flat_map(|s| s.split(". ")) {
+            let tokens = tokenize(segment);
+            total += tokens.len();
+            for (i, token) in tokens.iter().enumerate() {
+                if !is_keyword(token) {
+                    continue;
+                }
+                *terms.entry(token.clone()).or_default() += 1;
+                if let Some(next) = tokens.get(i + 1).filter(|next| is_keyword(next)) {
+                    *phrases.entry((token.clone(), next.clone())).or_default() += 1;
+                }
+            }
+        }
+        let total = total.max(1) as f32;
+
+        let mut phrases: Vec<((String, String), u32)> = phrases.into_iter()
+            .filter(|&(_, count)| count >= min_phrase_count.max(1))
+            .collect();
+        phrases.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let mut ranked: Vec<(String, f32, u32)> = Vec::new();
+        for ((first, second), count) in &phrases {
+            let score = *count as f32 / total * (self.idf(first) + self.idf(second));
+            ranked.push((format!("{} {}", first, second), score, *count));
+        }
+        // A word that only ever appears inside a kept phrase is already covered by it
+        for (term, count) in terms {
+            let covered = phrases.iter().any(|((first, second), phrase_count)| {
+                (*first == term || *second == term) && *phrase_count >= count
+            });
+            if !covered {
+                let score = count as f32 / total * self.idf(&term);
+                ranked.push((term, score, count));
+            }
+        }
+
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ranked.truncate(limit);
+        let top = ranked.first().map_or(1.0, |best| best.1.max(f32::MIN_POSITIVE));
+        ranked.into_iter()
+            .map(|(term, score, count)| Keyword { term, weight: score / top, count })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(docs: &[(&str, &str)]) -> KeywordIndex {
+        let mut index = KeywordIndex::default();
+        for (id, text) in docs {
+            index.insert(id, text);
+        }
+        index
+    }
+
+    fn ids(ranked: &[(String, f32)]) -> Vec<&str> {
+        ranked.iter().map(|(id, _)| id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_tokenize_keeps_identifiers_whole() {
+        assert_eq!(tokenize("Failed: /etc/nginx.conf, ERR_TIMEOUT (exit-code 2)."),
+                   ["failed", "etc/nginx.conf", "err_timeout", "exit-code", "2"]);
+    }
+
+    #[test]
+    fn test_bm25_ranking_order() {
+        let index = index(&[
+            ("once", "disk full on build host"),
+            ("twice", "disk full again, disk full on build host"),
+            ("long", "disk full while the nightly build was copying artifacts to the shared cache volume"),
+            ("rare", "ERR_TIMEOUT talking to build host"),
+            ("none", "deploy succeeded"),
+        ]);
+
+        // More occurrences rank higher, and a match in a long text counts for less
+        assert_eq!(ids(&index.search("full", 10, |_| true)), ["twice", "once", "long"]);
+        // A rare term outweighs a common one
+        assert_eq!(ids(&index.search("build err_timeout", 1, |_| true)), ["rare"]);
+        // Entries matching every query term come before those matching one
+        let both = index.search("disk host", 10, |_| true);
+        let mut top: Vec<&str> = ids(&both)[..2].to_vec();
+        top.sort();
+        assert_eq!(top, ["once", "twice"]);
+        assert_eq!(both.len(), 4);
+        assert!(both.iter().all(|(_, score)| *score > 0.0));
+        assert!(index.search("kernel", 10, |_| true).is_empty());
+    }
+
+    #[test]
+    fn test_bm25_filter_limit_and_removal() {
+        let mut index = index(&[("a", "timeout timeout"), ("b", "timeout"), ("c", "timeout retry")]);
+        assert_eq!(ids(&index.search("timeout", 10, |id| id != "a")), ["b", "c"]);
+        assert_eq!(index.search("timeout", 1, |_| true).len(), 1);
+
+        index.remove("a");
+        index.insert("b", "retry");
+        assert_eq!(index.len(), 2);
+        assert_eq!(ids(&index.search("timeout", 10, |_| true)), ["c"]);
+        assert_eq!(ids(&index.search("retry", 10, |_| true)), ["b", "c"]);
+
+        index.remove("b");
+        index.remove("c");
+        assert!(index.postings.is_empty());
+        assert_eq!(index.total_len, 0);
+    }
+}
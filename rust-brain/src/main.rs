@@ -1,13 +1,58 @@
 // Rust Brain Worker - Heavy numeric operations for AI learning
-// Handles: embeddings, similarity search, batch validation
-use serde::{Deserialize, Serialize};
-use std::io::{self, Read};
+// Handles: embeddings, similarity search, vector store, batch validation
+// One request on stdin per process, or many over a Unix socket with `rust-brain serve` - either way
+// in the executor's wire format (archy-protocol), so one client speaks to both.
+use archy_protocol::{ErrorKind, Request};
+use serde::Serialize;
+use std::io::{self, BufRead, Read};
+use std::sync::OnceLock;
+use std::time::Instant;
 use rayon::prelude::*;
 
-#[derive(Deserialize)]
-struct Request {
-    task: String,
-    payload: serde_json::Value,
+mod anomaly;
+mod benchmark;
+mod cluster;
+mod dedup;
+mod diverse;
+mod embedder;
+mod filter;
+mod keywords;
+mod maintenance;
+mod metric;
+mod models;
+mod quantize;
+mod recency;
+mod server;
+mod stream;
+mod telemetry;
+mod validate;
+mod vector_store;
+
+/// When this process started, for `health`
+static STARTED: OnceLock<Instant> = OnceLock::new();
+
+/// Every task `handle_request` answers (plus the protocol's own), for `describe`
+const TASKS: &[&str] = &[
+    "embed_texts", "cosine_rank", "validate_fragment", "store_upsert", "store_delete", "store_search",
+    "hybrid_search", "extract_keywords", "dedup", "cluster", "select_diverse", "similarity_matrix",
+    "anomaly_score",
+    "create_collection", "drop_collection", "list_collections", "snapshot", "restore", "compact",
+    "relink", "stats", "list_models", "model_info", "load_model", "unload_model", "benchmark", "telemetry",
+    "embed_stream", "health", "describe",
+];
+
+/// A response as sent: `success` beside `status`, and the protocol's `error_code` and echoed `id`
+#[derive(Serialize)]
+struct Reply<'a> {
+    #[serde(flatten)]
+    response: &'a Response,
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_code: Option<ErrorKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<&'a serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    elapsed_ms: Option<f64>,
 }
 
 #[derive(Serialize)]
@@ -38,36 +83,6 @@ fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     }
 }
 
-/// Generate deterministic pseudo-embeddings (replace with real model later)
-fn generate_embedding(text: &str, dim: usize) -> Vec<f32> {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
-
-    let mut hasher = DefaultHasher::new();
-    text.hash(&mut hasher);
-    let seed = hasher.finish();
-
-    // Deterministic pseudo-random using simple LCG
-    let mut rng = seed;
-    let mut emb = Vec::with_capacity(dim);
-
-    for _ in 0..dim {
-        rng = rng.wrapping_mul(1103515245).wrapping_add(12345);
-        let val = ((rng / 65536) % 1000) as f32 / 1000.0 - 0.5;
-        emb.push(val);
-    }
-
-    // Normalize
-    let norm: f32 = emb.iter().map(|x| x * x).sum::<f32>().sqrt();
-    if norm > 0.0 {
-        for val in &mut emb {
-            *val /= norm;
-        }
-    }
-
-    emb
-}
-
 /// Handle embedding generation task
 fn handle_embed_texts(payload: &serde_json::Value) -> Response {
     let texts = match payload.get("texts").and_then(|v| v.as_array()) {
@@ -83,21 +98,26 @@ fn handle_embed_texts(payload: &serde_json::Value) -> Response {
     };
 
     let dim = payload.get("dim").and_then(|v| v.as_u64()).unwrap_or(128) as usize;
+    let backend = payload.get("backend").and_then(|v| v.as_str());
+    let batch_size = payload.get("batch_size")
+        .and_then(|v| v.as_u64())
+        .map(|n| n as usize)
+        .unwrap_or(embedder::DEFAULT_BATCH_SIZE);
 
-    // Parallel embedding generation
-    let embeddings: Vec<Vec<f32>> = texts
-        .par_iter()
-        .map(|text| {
-            let text_str = text.as_str().unwrap_or("");
-            generate_embedding(text_str, dim)
-        })
-        .collect();
-
-    Response {
-        status: "ok".to_string(),
-        result: None,
-        embeddings: Some(embeddings),
-        error: None,
+    let texts: Vec<&str> = texts.iter().map(|text| text.as_str().unwrap_or("")).collect();
+    match embedder::embed(&texts, backend, dim, batch_size) {
+        Ok(embedded) => Response {
+            status: "ok".to_string(),
+            result: Some(serde_json::json!({"backend": embedded.backend, "dim": embedded.dim})),
+            embeddings: Some(embedded.vectors),
+            error: None,
+        },
+        Err(e) => Response {
+            status: "error".to_string(),
+            result: None,
+            embeddings: None,
+            error: Some(e),
+        },
     }
 }
 
@@ -135,6 +155,20 @@ fn handle_cosine_rank(payload: &serde_json::Value) -> Response {
 
     let top_k = payload.get("top_k").and_then(|v| v.as_u64()).unwrap_or(5) as usize;
 
+    // Optional metadata per candidate (same order) for the filter
+    let filter = match filter::Filter::from_payload(payload) {
+        Ok(filter) => filter,
+        Err(e) => return Response::error(e),
+    };
+    let no_metadata = serde_json::Value::Null;
+    let metadata = payload.get("metadata").and_then(|v| v.as_array());
+    // Timestamps per candidate (same order) for recency weighting, else each one's metadata `timestamp`
+    let recency = match recency::Recency::from_payload(payload) {
+        Ok(recency) => recency,
+        Err(e) => return Response::error(e),
+    };
+    let timestamps = payload.get("timestamps").and_then(|v| v.as_array());
+
     // Convert candidates to Vec<Vec<f32>>
     let cands: Vec<Vec<f32>> = candidates
         .iter()
@@ -154,11 +188,25 @@ fn handle_cosine_rank(payload: &serde_json::Value) -> Response {
         .map(|cand| cosine_similarity(&query, cand))
         .collect();
 
-    // Create indices and sort by similarity (descending)
+    // Create indices and sort by score (descending) - the similarity, plus any recency boost
     let mut indexed: Vec<(usize, f32)> = similarities
         .iter()
         .enumerate()
         .map(|(i, &sim)| (i, sim))
+        .filter(|&(i, sim)| {
+            let meta = metadata.and_then(|m| m.get(i)).unwrap_or(&no_metadata);
+            filter.score_ok(sim) && filter.matches(meta)
+        })
+        .map(|(i, sim)| match &recency {
+            Some(recency) => {
+                let timestamp = timestamps
+                    .and_then(|t| t.get(i))
+                    .or_else(|| metadata.and_then(|m| m.get(i)).and_then(|meta| meta.get("timestamp")))
+                    .and_then(|v| v.as_f64());
+                (i, sim + recency.boost(timestamp))
+            }
+            None => (i, sim),
+        })
         .collect();
 
     indexed.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
@@ -166,10 +214,14 @@ fn handle_cosine_rank(payload: &serde_json::Value) -> Response {
     let top_indices: Vec<usize> = indexed.iter().take(top_k).map(|(i, _)| *i).collect();
     let top_scores: Vec<f32> = indexed.iter().take(top_k).map(|(_, s)| *s).collect();
 
-    let result = serde_json::json!({
+    let mut result = serde_json::json!({
         "indices": top_indices,
         "scores": top_scores
     });
+    if recency.is_some() {
+        let top_similarities: Vec<f32> = top_indices.iter().map(|&i| similarities[i]).collect();
+        result["similarities"] = serde_json::json!(top_similarities);
+    }
 
     Response {
         status: "ok".to_string(),
@@ -183,84 +235,1030 @@ fn handle_cosine_rank(payload: &serde_json::Value) -> Response {
 fn handle_validate_fragment(payload: &serde_json::Value) -> Response {
     let text = match payload.get("text").and_then(|v| v.as_str()) {
         Some(t) => t,
+        None => return Response::error("Missing 'text' field".to_string()),
+    };
+    let weights: std::collections::HashMap<String, f32> = payload.get("weights")
+        .and_then(|v| v.as_object())
+        .map(|w| w.iter().filter_map(|(rule, v)| v.as_f64().map(|w| (rule.clone(), w as f32))).collect())
+        .unwrap_or_default();
+    let threshold = payload.get("duplicate_threshold")
+        .and_then(|v| v.as_f64())
+        .map(|t| t as f32)
+        .unwrap_or(validate::DEFAULT_DUPLICATE_THRESHOLD);
+
+    // Near-duplicate check against the store, when there is one with entries
+    let check_duplicates = payload.get("check_duplicates").and_then(|v| v.as_bool()).unwrap_or(true);
+    let closest = match store_dir(payload) {
+        Ok(dir) if check_duplicates && dir.join("store.json").is_file() => match closest_entry(payload, &dir, text) {
+            Ok(closest) => closest,
+            Err(e) => return Response::error(e),
+        },
+        Ok(_) => None,
+        Err(e) => return Response::error(e),
+    };
+
+    let report = validate::validate(text, closest, threshold, &weights);
+    Response::ok(serde_json::json!({
+        "validation_score": report.score,
+        "length_ok": report.length_ok,
+        "has_content": report.has_content,
+        "suspicious": report.suspicious,
+        "language": report.language,
+        "policy": report.policy,
+        "commands": report.commands,
+        "duplicate_of": report.duplicate_of,
+        "rules": report.rules,
+    }))
+}
+
+/// Most similar stored entry to `text` (None for an empty store)
+fn closest_entry(payload: &serde_json::Value, dir: &std::path::Path, text: &str) -> Result<Option<(String, f32)>, String> {
+    let store = vector_store::VectorStore::open(dir, false)?;
+    if store.len() == 0 {
+        return Ok(None);
+    }
+    let backend = payload.get("backend").and_then(|v| v.as_str());
+    let query = embedder::embed(&[text], backend, embed_dim(payload, &store), 1)?.vectors.into_iter().next().unwrap_or_default();
+    let matches = store.search(&query, 1, vector_store::DEFAULT_EF, &filter::Filter::default())?;
+    Ok(matches.into_iter().next().map(|m| (m.id, m.score)))
+}
+
+impl Response {
+    fn ok(result: serde_json::Value) -> Response {
+        Response { status: "ok".to_string(), result: Some(result), embeddings: None, error: None }
+    }
+
+    fn error(message: String) -> Response {
+        Response { status: "error".to_string(), result: None, embeddings: None, error: Some(message) }
+    }
+
+    /// Serialized for the wire - failures without a finer `code` are `failed`
+    fn to_wire(&self, id: Option<&serde_json::Value>, code: Option<ErrorKind>, elapsed_ms: Option<f64>) -> String {
+        let success = self.status == "ok";
+        let error_code = if success { None } else { Some(code.unwrap_or(ErrorKind::Failed)) };
+        serde_json::to_string(&Reply { response: self, success, error_code, id, elapsed_ms })
+            .unwrap_or_else(|e| format!(r#"{{"status":"error","success":false,"error":"Cannot serialize reply: {}","error_code":"failed"}}"#, e))
+    }
+}
+
+/// Handle one request, timed and counted - the protocol's `health` and `describe` answer in the
+/// executor's shape
+fn answer(req: Request) -> String {
+    let service = env!("CARGO_PKG_NAME");
+    let version = env!("CARGO_PKG_VERSION");
+    let reply = match req.action.as_str() {
+        "health" => archy_protocol::health(service, version, *STARTED.get_or_init(Instant::now)),
+        "describe" => archy_protocol::describe(service, version, serde_json::json!(TASKS)),
+        action => {
+            let code = (!TASKS.contains(&action)).then_some(ErrorKind::UnknownAction);
+            let (task, id, items) = (action.to_string(), req.id.clone(), telemetry::work_items(&req.data));
+            let start = Instant::now();
+            let response = handle_request(req);
+            let elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
+            // Unknown tasks aren't counted, or any client could grow the table without bound
+            if code.is_none() {
+                telemetry::record(&task, elapsed_ms, response.status == "ok", items);
+            }
+            return response.to_wire(id.as_ref(), code, Some(elapsed_ms));
+        }
+    };
+    let mut reply = archy_protocol::stamp(reply, req.id.as_ref());
+    reply["status"] = serde_json::json!("ok");
+    reply.to_string()
+}
+
+/// Store root from the payload, else RUST_BRAIN_STORE_DIR, else brain/vector_store
+fn store_root(payload: &serde_json::Value) -> std::path::PathBuf {
+    payload.get("store")
+        .and_then(|v| v.as_str())
+        .map(std::path::PathBuf::from)
+        .or_else(|| std::env::var_os("RUST_BRAIN_STORE_DIR").map(std::path::PathBuf::from))
+        .unwrap_or_else(|| std::path::PathBuf::from("brain/vector_store"))
+}
+
+/// Where collection `name` lives under the store root
+fn collection_dir(payload: &serde_json::Value, name: &str) -> Result<std::path::PathBuf, String> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        return Err(format!("Invalid collection name '{}' (letters, digits, '_' and '-')", name));
+    }
+    Ok(store_root(payload).join("collections").join(name))
+}
+
+/// Directory of the payload's "collection" - the root store itself when none (or "default") is named
+fn store_dir(payload: &serde_json::Value) -> Result<std::path::PathBuf, String> {
+    match payload.get("collection").and_then(|v| v.as_str()) {
+        None | Some("default") => Ok(store_root(payload)),
+        Some(name) => {
+            let dir = collection_dir(payload, name)?;
+            if !dir.join("store.json").is_file() {
+                return Err(format!("Unknown collection '{}' - create it with create_collection", name));
+            }
+            Ok(dir)
+        }
+    }
+}
+
+/// Open the store for writing - a "quantization" (none, int8, binary) picks the store's search index,
+/// re-encoding what is already stored when it changes
+fn open_for_write(payload: &serde_json::Value) -> Result<vector_store::VectorStore, String> {
+    let store = vector_store::VectorStore::open(&store_dir(payload)?, true)?;
+    match payload.get("quantization").and_then(|v| v.as_str()) {
+        Some(name) => store.with_quantization(quantize::Quantization::parse(name)?),
+        None => Ok(store),
+    }
+}
+
+/// Hash embeddings for a store default to the store's width
+fn embed_dim(payload: &serde_json::Value, store: &vector_store::VectorStore) -> usize {
+    match payload.get("dim").and_then(|v| v.as_u64()) {
+        Some(dim) => dim as usize,
+        None if store.dim() > 0 => store.dim(),
+        None => 128,
+    }
+}
+
+fn f32_array(value: &serde_json::Value) -> Option<Vec<f32>> {
+    value.as_array().map(|arr| arr.iter().filter_map(|v| v.as_f64()).map(|f| f as f32).collect())
+}
+
+/// Handle store insert/update task: items are {id, vector and/or text, metadata} - text is embedded
+/// when there is no vector, and always indexed for keyword search
+fn handle_store_upsert(payload: &serde_json::Value) -> Response {
+    let items = match payload.get("items").and_then(|v| v.as_array()) {
+        Some(arr) => arr,
+        None => return Response::error("Missing 'items' array".to_string()),
+    };
+
+    let mut store = match open_for_write(payload) {
+        Ok(store) => store,
+        Err(e) => return Response::error(e),
+    };
+
+    // Items given only as text are embedded first, all in one go
+    let texts: Vec<&str> = items.iter()
+        .filter(|item| item.get("vector").is_none())
+        .filter_map(|item| item.get("text").and_then(|v| v.as_str()))
+        .collect();
+    let mut embedded = if texts.is_empty() {
+        Vec::new()
+    } else {
+        let dim = embed_dim(payload, &store);
+        let backend = payload.get("backend").and_then(|v| v.as_str());
+        match embedder::embed(&texts, backend, dim, embedder::DEFAULT_BATCH_SIZE) {
+            Ok(embedded) => embedded.vectors,
+            Err(e) => return Response::error(e),
+        }
+    }
+    .into_iter();
+
+    let (mut inserted, mut updated) = (0, 0);
+    for (idx, item) in items.iter().enumerate() {
+        let id = match item.get("id").and_then(|v| v.as_str()) {
+            Some(id) => id,
+            None => return Response::error(format!("Item {} has no 'id'", idx)),
+        };
+        let text = item.get("text").and_then(|v| v.as_str());
+        let vector = match (item.get("vector"), text) {
+            (Some(vector), _) => f32_array(vector).unwrap_or_default(),
+            (None, Some(_)) => embedded.next().unwrap_or_default(),
+            (None, None) => return Response::error(format!("Item '{}' needs a 'vector' or a 'text'", id)),
+        };
+        let metadata = item.get("metadata").cloned().unwrap_or(serde_json::Value::Null);
+        match store.upsert(id, &vector, metadata) {
+            Ok(true) => updated += 1,
+            Ok(false) => inserted += 1,
+            Err(e) => return Response::error(e),
+        }
+        if let Some(text) = text {
+            store.index_text(id, text);
+        }
+    }
+
+    let (count, quantization) = (store.len(), store.quantization());
+    match maintenance::save(store) {
+        Ok(()) => Response::ok(serde_json::json!({
+            "inserted": inserted,
+            "updated": updated,
+            "count": count,
+            "quantization": quantization.name(),
+        })),
+        Err(e) => Response::error(e),
+    }
+}
+
+/// Handle store delete task
+fn handle_store_delete(payload: &serde_json::Value) -> Response {
+    let ids = match payload.get("ids").and_then(|v| v.as_array()) {
+        Some(arr) => arr,
+        None => return Response::error("Missing 'ids' array".to_string()),
+    };
+    let mut store = match store_dir(payload).and_then(|dir| vector_store::VectorStore::open(&dir, true)) {
+        Ok(store) => store,
+        Err(e) => return Response::error(e),
+    };
+    let deleted = ids.iter().filter_map(|id| id.as_str()).filter(|id| store.remove(id)).count();
+    let count = store.len();
+    match maintenance::save(store) {
+        Ok(()) => Response::ok(serde_json::json!({"deleted": deleted, "count": count})),
+        Err(e) => Response::error(e),
+    }
+}
+
+/// Handle store similarity search task: query is a vector, or text to embed - "recency" favours fresher
+/// entries among close matches (see recency.rs)
+fn handle_store_search(payload: &serde_json::Value) -> Response {
+    let store = match store_dir(payload).and_then(|dir| vector_store::VectorStore::open(&dir, false)) {
+        Ok(store) => store,
+        Err(e) => return Response::error(e),
+    };
+    let query = match (payload.get("query").and_then(f32_array), payload.get("text").and_then(|v| v.as_str())) {
+        (Some(query), None) => query,
+        (None, Some(text)) => {
+            let backend = payload.get("backend").and_then(|v| v.as_str());
+            match embedder::embed(&[text], backend, embed_dim(payload, &store), 1) {
+                Ok(embedded) => embedded.vectors.into_iter().next().unwrap_or_default(),
+                Err(e) => return Response::error(e),
+            }
+        }
+        _ => return Response::error("Need exactly one of 'query' (vector) or 'text'".to_string()),
+    };
+    let top_k = payload.get("top_k").and_then(|v| v.as_u64()).unwrap_or(5) as usize;
+    let ef = payload.get("ef").and_then(|v| v.as_u64()).map(|n| n as usize).unwrap_or(vector_store::DEFAULT_EF);
+    let filter = match filter::Filter::from_payload(payload) {
+        Ok(filter) => filter,
+        Err(e) => return Response::error(e),
+    };
+    let recency = match recency::Recency::from_payload(payload) {
+        Ok(recency) => recency,
+        Err(e) => return Response::error(e),
+    };
+    // With recency weighting, rerank a wider pool so a fresher match just past top_k can move up
+    let pool = if recency.is_some() { top_k * recency::POOL_FACTOR } else { top_k };
+    let mut matches = match store.search(&query, pool, ef, &filter) {
+        Ok(matches) => matches,
+        Err(e) => return Response::error(e),
+    };
+    if let Some(recency) = &recency {
+        for m in &mut matches {
+            m.similarity = Some(m.score);
+            m.score = recency.score(m.score, &m.metadata);
+        }
+        matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+        matches.truncate(top_k);
+    }
+    Response::ok(serde_json::json!({"matches": matches, "count": store.len()}))
+}
+
+/// Settings and size of a collection, as the collection tasks report them
+fn collection_info(name: &str, store: &vector_store::VectorStore) -> serde_json::Value {
+    serde_json::json!({
+        "name": name,
+        "count": store.len(),
+        "dim": store.dim(),
+        "metric": store.metric().name(),
+        "quantization": store.quantization().name(),
+    })
+}
+
+/// Handle collection creation: {name, dim (0 = from the first vector), metric, quantization} - fixed
+/// for the collection's lifetime, except quantization
+fn handle_create_collection(payload: &serde_json::Value) -> Response {
+    let name = match payload.get("name").and_then(|v| v.as_str()) {
+        Some("default") => return Response::error("'default' is the root store and always exists".to_string()),
+        Some(name) => name,
+        None => return Response::error("Missing 'name'".to_string()),
+    };
+    let setting = |key: &str| payload.get(key).and_then(|v| v.as_str());
+    let created = collection_dir(payload, name).and_then(|dir| {
+        let metric = setting("metric").map(metric::Metric::parse).transpose()?.unwrap_or_default();
+        let quantization = setting("quantization").map(quantize::Quantization::parse).transpose()?.unwrap_or_default();
+        let dim = payload.get("dim").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+        vector_store::VectorStore::create(&dir, dim, metric, quantization)
+    });
+    match created {
+        Ok(store) => {
+            let info = collection_info(name, &store);
+            match store.save() {
+                Ok(()) => Response::ok(info),
+                Err(e) => Response::error(e),
+            }
+        }
+        Err(e) => Response::error(e),
+    }
+}
+
+/// Handle collection removal - waits for the collection's writers, then deletes it with its files
+fn handle_drop_collection(payload: &serde_json::Value) -> Response {
+    let name = match payload.get("name").and_then(|v| v.as_str()) {
+        Some("default") => return Response::error("The root store can't be dropped".to_string()),
+        Some(name) => name,
+        None => return Response::error("Missing 'name'".to_string()),
+    };
+    let dir = match collection_dir(payload, name) {
+        Ok(dir) => dir,
+        Err(e) => return Response::error(e),
+    };
+    if !dir.join("store.json").is_file() {
+        return Response::ok(serde_json::json!({"dropped": false}));
+    }
+    let store = match vector_store::VectorStore::open(&dir, true) {
+        Ok(store) => store,
+        Err(e) => return Response::error(e),
+    };
+    let count = store.len();
+    match std::fs::remove_dir_all(&dir) {
+        Ok(()) => Response::ok(serde_json::json!({"dropped": true, "count": count})),
+        Err(e) => Response::error(format!("Cannot remove collection '{}': {}", name, e)),
+    }
+}
+
+/// Collection named in the payload and its directory, whether or not it holds a store yet
+fn collection_target(payload: &serde_json::Value) -> Result<(String, std::path::PathBuf), String> {
+    match payload.get("collection").and_then(|v| v.as_str()) {
+        None | Some("default") => Ok(("default".to_string(), store_root(payload))),
+        Some(name) => Ok((name.to_string(), collection_dir(payload, name)?)),
+    }
+}
+
+/// Handle snapshot task: copy a collection to "path" (default <store>/snapshots/<collection>-<unix time>)
+fn handle_snapshot(payload: &serde_json::Value) -> Response {
+    let (name, dir) = match collection_target(payload) {
+        Ok(target) => target,
+        Err(e) => return Response::error(e),
+    };
+    let dest = match payload.get("path").and_then(|v| v.as_str()) {
+        Some(path) => std::path::PathBuf::from(path),
         None => {
-            return Response {
-                status: "error".to_string(),
-                result: None,
-                embeddings: None,
-                error: Some("Missing 'text' field".to_string()),
-            };
+            let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+            store_root(payload).join("snapshots").join(format!("{}-{}", name, now))
         }
     };
+    match maintenance::snapshot(&dir, &name, &dest) {
+        Ok(manifest) => Response::ok(serde_json::json!({"path": dest, "snapshot": manifest})),
+        Err(e) => Response::error(e),
+    }
+}
 
-    // Simple validation metrics
-    let length_ok = text.len() >= 10 && text.len() <= 10000;
-    let has_content = !text.trim().is_empty();
+/// Handle restore task: replace a collection (created when missing) with the snapshot at "path"
+fn handle_restore(payload: &serde_json::Value) -> Response {
+    let source = match payload.get("path").and_then(|v| v.as_str()) {
+        Some(path) => std::path::PathBuf::from(path),
+        None => return Response::error("Missing 'path'".to_string()),
+    };
+    let (name, dir) = match collection_target(payload) {
+        Ok(target) => target,
+        Err(e) => return Response::error(e),
+    };
+    let overwrite = payload.get("overwrite").and_then(|v| v.as_bool()).unwrap_or(false);
+    match maintenance::restore(&source, &dir, overwrite) {
+        Ok(manifest) => Response::ok(serde_json::json!({"collection": name, "snapshot": manifest})),
+        Err(e) => Response::error(e),
+    }
+}
 
-    // Check for suspicious patterns (basic)
-    let suspicious = text.contains("rm -rf") ||
-                     text.contains("exfiltrate") ||
-                     text.contains("bypass");
+/// Handle compact task: drop a collection's tombstones now, instead of waiting for the background pass
+fn handle_compact(payload: &serde_json::Value) -> Response {
+    let store = match store_dir(payload).and_then(|dir| vector_store::VectorStore::open(&dir, true)) {
+        Ok(store) => store,
+        Err(e) => return Response::error(e),
+    };
+    let count = store.len();
+    match store.compact() {
+        Ok(removed) => Response::ok(serde_json::json!({"removed": removed, "count": count})),
+        Err(e) => Response::error(e),
+    }
+}
 
-    let validation_score = if !length_ok || !has_content || suspicious {
-        0.0
+/// Handle relink task: re-select every entry's links now, instead of waiting for enough churn
+fn handle_relink(payload: &serde_json::Value) -> Response {
+    let mut store = match store_dir(payload).and_then(|dir| vector_store::VectorStore::open(&dir, true)) {
+        Ok(store) => store,
+        Err(e) => return Response::error(e),
+    };
+    let relinked = store.relink();
+    match store.save() {
+        Ok(()) => Response::ok(serde_json::json!({"relinked": relinked})),
+        Err(e) => Response::error(e),
+    }
+}
+
+/// Handle stats task: a collection's settings plus index health - graph connectivity from the entry
+/// point and a recall@k estimate over `sample` stored vectors (0 skips it)
+fn handle_stats(payload: &serde_json::Value) -> Response {
+    let store = match store_dir(payload).and_then(|dir| vector_store::VectorStore::open(&dir, false)) {
+        Ok(store) => store,
+        Err(e) => return Response::error(e),
+    };
+    let sample = payload.get("sample").and_then(|v| v.as_u64()).unwrap_or(50) as usize;
+    let k = payload.get("k").and_then(|v| v.as_u64()).unwrap_or(10) as usize;
+    let ef = payload.get("ef").and_then(|v| v.as_u64()).unwrap_or(64) as usize;
+    let name = payload.get("collection").and_then(|v| v.as_str()).unwrap_or("default");
+
+    let mut stats = collection_info(name, &store);
+    stats["tombstones"] = serde_json::json!(store.tombstones());
+    stats["index"] = serde_json::json!(store.health(sample, k, ef.max(k)));
+    stats["wants_relink"] = serde_json::json!(store.wants_relink());
+    stats["wants_compaction"] = serde_json::json!(store.wants_compaction());
+    Response::ok(stats)
+}
+
+/// Handle collection listing: the root store ("default", once it exists) and every named collection
+fn handle_list_collections(payload: &serde_json::Value) -> Response {
+    let root = store_root(payload);
+    let mut found: Vec<(String, std::path::PathBuf)> = Vec::new();
+    if root.join("store.json").is_file() {
+        found.push(("default".to_string(), root.clone()));
+    }
+    if let Ok(entries) = std::fs::read_dir(root.join("collections")) {
+        let mut named: Vec<(String, std::path::PathBuf)> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().join("store.json").is_file())
+            .map(|entry| (entry.file_name().to_string_lossy().into_owned(), entry.path()))
+            .collect();
+        named.sort();
+        found.extend(named);
+    }
+
+    let collections: Vec<serde_json::Value> = found.iter()
+        .map(|(name, dir)| match vector_store::VectorStore::open(dir, false) {
+            Ok(store) => collection_info(name, &store),
+            Err(e) => serde_json::json!({"name": name, "error": e}),
+        })
+        .collect();
+    Response::ok(serde_json::json!({"collections": collections}))
+}
+
+/// One entry's standing in hybrid_search
+#[derive(Default)]
+struct Fused {
+    score: f32, // reciprocal rank fusion of both sides
+    vector_score: Option<f32>,
+    bm25_score: Option<f32>,
+}
+
+/// Handle hybrid retrieval task: BM25 over stored text fused with vector similarity by reciprocal rank
+/// fusion - weights {"vector": w, "keyword": w} scale each side's 1 / (rrf_k + rank)
+fn handle_hybrid_search(payload: &serde_json::Value) -> Response {
+    let store = match store_dir(payload).and_then(|dir| vector_store::VectorStore::open(&dir, false)) {
+        Ok(store) => store,
+        Err(e) => return Response::error(e),
+    };
+    let text = match payload.get("text").and_then(|v| v.as_str()) {
+        Some(text) => text,
+        None => return Response::error("Missing 'text'".to_string()),
+    };
+    let filter = match filter::Filter::from_payload(payload) {
+        Ok(filter) => filter,
+        Err(e) => return Response::error(e),
+    };
+    let top_k = payload.get("top_k").and_then(|v| v.as_u64()).unwrap_or(5) as usize;
+    let ef = payload.get("ef").and_then(|v| v.as_u64()).map(|n| n as usize).unwrap_or(vector_store::DEFAULT_EF);
+    let rrf_k = payload.get("rrf_k").and_then(|v| v.as_f64()).unwrap_or(60.0) as f32;
+    let weight = |side: &str| {
+        payload.get("weights").and_then(|w| w.get(side)).and_then(|v| v.as_f64()).unwrap_or(1.0) as f32
+    };
+    let (vector_weight, keyword_weight) = (weight("vector"), weight("keyword"));
+    // Each side ranks a deeper list than top_k, so entries strong on one side only can still surface
+    let depth = (top_k * 4).max(50);
+
+    let vector_hits = if vector_weight > 0.0 {
+        let query = match payload.get("query").and_then(f32_array) {
+            Some(query) => query,
+            None => {
+                let backend = payload.get("backend").and_then(|v| v.as_str());
+                match embedder::embed(&[text], backend, embed_dim(payload, &store), 1) {
+                    Ok(embedded) => embedded.vectors.into_iter().next().unwrap_or_default(),
+                    Err(e) => return Response::error(e),
+                }
+            }
+        };
+        match store.search(&query, depth, ef.max(depth), &filter) {
+            Ok(matches) => matches.into_iter().map(|m| (m.id, m.score)).collect(),
+            Err(e) => return Response::error(e),
+        }
+    } else {
+        Vec::new()
+    };
+    let keyword_hits = if keyword_weight > 0.0 {
+        store.keyword_search(text, depth, &filter)
+    } else {
+        Vec::new()
+    };
+
+    let mut fused: std::collections::HashMap<String, Fused> = std::collections::HashMap::new();
+    for (rank, (id, score)) in vector_hits.into_iter().enumerate() {
+        let entry = fused.entry(id).or_default();
+        entry.score += vector_weight / (rrf_k + rank as f32 + 1.0);
+        entry.vector_score = Some(score);
+    }
+    for (rank, (id, score)) in keyword_hits.into_iter().enumerate() {
+        let entry = fused.entry(id).or_default();
+        entry.score += keyword_weight / (rrf_k + rank as f32 + 1.0);
+        entry.bm25_score = Some(score);
+    }
+    let mut ranked: Vec<(String, Fused)> = fused.into_iter()
+        .filter(|(_, f)| f.vector_score.is_none_or(|s| filter.score_ok(s)))
+        .collect();
+    ranked.sort_by(|a, b| b.1.score.total_cmp(&a.1.score).then_with(|| a.0.cmp(&b.0)));
+
+    let matches: Vec<serde_json::Value> = ranked.into_iter()
+        .take(top_k)
+        .map(|(id, f)| serde_json::json!({
+            "metadata": store.metadata(&id),
+            "id": id,
+            "score": f.score,
+            "vector_score": f.vector_score,
+            "bm25_score": f.bm25_score,
+        }))
+        .collect();
+    Response::ok(serde_json::json!({"matches": matches, "count": store.len()}))
+}
+
+/// Handle keyword extraction task: top terms and repeated phrases of `text` (or each of `texts`) by
+/// TF-IDF against the store's keyword index, so tags favour what sets a text apart from what is stored
+fn handle_extract_keywords(payload: &serde_json::Value) -> Response {
+    let corpus = match store_dir(payload).and_then(|dir| keywords::KeywordIndex::load(&dir)) {
+        Ok(corpus) => corpus,
+        Err(e) => return Response::error(e),
+    };
+    let top_k = payload.get("top_k").and_then(|v| v.as_u64()).unwrap_or(10) as usize;
+    let min_phrase_count = payload.get("min_phrase_count").and_then(|v| v.as_u64()).unwrap_or(2) as u32;
+
+    let mut result = serde_json::json!({"corpus_size": corpus.len()});
+    if let Some(text) = payload.get("text").and_then(|v| v.as_str()) {
+        result["keywords"] = serde_json::json!(corpus.extract(text, top_k, min_phrase_count));
+    } else if let Some(texts) = payload.get("texts").and_then(|v| v.as_array()) {
+        let keywords: Vec<Vec<keywords::Keyword>> = texts
+            .par_iter()
+            .map(|text| corpus.extract(text.as_str().unwrap_or(""), top_k, min_phrase_count))
+            .collect();
+        result["results"] = serde_json::json!(keywords);
+    } else {
+        return Response::error("Missing 'text' (or 'texts' array)".to_string());
+    }
+    Response::ok(result)
+}
+
+/// Entries to work on: supplied `texts` or `vectors` (in a scratch store, ids are their positions), or
+/// stored entries (`ids`, default all). The flag says whether they came from the store
+fn vector_source(payload: &serde_json::Value) -> Result<(vector_store::VectorStore, Vec<String>, bool), String> {
+    let supplied: Option<Vec<Vec<f32>>> = if let Some(texts) = payload.get("texts").and_then(|v| v.as_array()) {
+        let texts: Vec<&str> = texts.iter().map(|t| t.as_str().unwrap_or("")).collect();
+        let dim = payload.get("dim").and_then(|v| v.as_u64()).unwrap_or(128) as usize;
+        let backend = payload.get("backend").and_then(|v| v.as_str());
+        Some(embedder::embed(&texts, backend, dim, embedder::DEFAULT_BATCH_SIZE)?.vectors)
+    } else {
+        payload.get("vectors")
+            .and_then(|v| v.as_array())
+            .map(|vectors| vectors.iter().map(|v| f32_array(v).unwrap_or_default()).collect())
+    };
+
+    if let Some(vectors) = supplied {
+        let mut store = vector_store::VectorStore::in_memory();
+        let ids: Vec<String> = (0..vectors.len()).map(|i| i.to_string()).collect();
+        for (id, vector) in ids.iter().zip(&vectors) {
+            store.upsert(id, vector, serde_json::Value::Null)?;
+        }
+        return Ok((store, ids, false));
+    }
+
+    let store = vector_store::VectorStore::open(&store_dir(payload)?, false)?;
+    let ids = match payload.get("ids").and_then(|v| v.as_array()) {
+        Some(ids) => ids.iter().filter_map(|id| id.as_str()).map(str::to_string).collect(),
+        None => store.ids(),
+    };
+    Ok((store, ids, true))
+}
+
+/// Handle near-duplicate detection task
+fn handle_dedup(payload: &serde_json::Value) -> Response {
+    let (store, ids, stored) = match vector_source(payload) {
+        Ok(source) => source,
+        Err(e) => return Response::error(e),
+    };
+    let threshold = payload.get("threshold").and_then(|v| v.as_f64()).map(|t| t as f32).unwrap_or(dedup::DEFAULT_THRESHOLD);
+    let neighbors = payload.get("neighbors").and_then(|v| v.as_u64()).map(|n| n as usize).unwrap_or(dedup::DEFAULT_NEIGHBORS);
+    let ef = payload.get("ef").and_then(|v| v.as_u64()).map(|n| n as usize).unwrap_or(vector_store::DEFAULT_EF);
+
+    let clusters = match dedup::clusters(&store, &ids, threshold, neighbors, ef) {
+        Ok(clusters) => clusters,
+        Err(e) => return Response::error(e),
+    };
+    // Stored entries are named by id, supplied ones by position
+    let label = |i: usize| if stored { serde_json::json!(ids[i]) } else { serde_json::json!(i) };
+    let duplicates: usize = clusters.iter().map(|c| c.members.len() - 1).sum();
+    let clusters: Vec<serde_json::Value> = clusters.iter()
+        .map(|c| serde_json::json!({
+            "representative": label(c.representative),
+            "members": c.members.iter().map(|&m| label(m)).collect::<Vec<_>>(),
+            "min_similarity": c.min_similarity,
+        }))
+        .collect();
+    Response::ok(serde_json::json!({"clusters": clusters, "duplicates": duplicates}))
+}
+
+/// Handle k-means clustering task
+fn handle_cluster(payload: &serde_json::Value) -> Response {
+    let (store, ids, stored) = match vector_source(payload) {
+        Ok(source) => source,
+        Err(e) => return Response::error(e),
+    };
+    let vectors = match source_vectors(&store, &ids) {
+        Ok(vectors) => vectors,
+        Err(e) => return Response::error(e),
+    };
+    // Default k: the usual sqrt(n/2) rule of thumb
+    let k = payload.get("k")
+        .and_then(|v| v.as_u64())
+        .map(|k| k as usize)
+        .unwrap_or_else(|| ((vectors.len() as f64 / 2.0).sqrt().round() as usize).max(1));
+    let max_iter = payload.get("max_iter").and_then(|v| v.as_u64()).map(|n| n as usize).unwrap_or(cluster::DEFAULT_MAX_ITER);
+    let seed = payload.get("seed").and_then(|v| v.as_u64()).unwrap_or(42);
+    let representatives = payload.get("representatives").and_then(|v| v.as_u64()).unwrap_or(3) as usize;
+
+    let result = cluster::kmeans(&vectors, k, max_iter, seed);
+
+    // Stored entries are named by id, supplied ones by position
+    let label = |i: usize| if stored { serde_json::json!(ids[i]) } else { serde_json::json!(i) };
+    let clusters: Vec<serde_json::Value> = (0..result.centroids.len())
+        .map(|c| {
+            let mut members: Vec<usize> = (0..ids.len()).filter(|&i| result.assignments[i] == c).collect();
+            // Closest to the centroid first - the first few stand for the cluster
+            members.sort_by(|&a, &b| result.similarities[b].total_cmp(&result.similarities[a]));
+            serde_json::json!({
+                "size": members.len(),
+                "representatives": members.iter().take(representatives).map(|&m| label(m)).collect::<Vec<_>>(),
+                "centroid": result.centroids[c],
+            })
+        })
+        .collect();
+
+    Response::ok(serde_json::json!({
+        "assignments": result.assignments,
+        "ids": (0..ids.len()).map(label).collect::<Vec<_>>(),
+        "clusters": clusters,
+        "iterations": result.iterations,
+        "mean_similarity": if ids.is_empty() { 0.0 } else { result.similarities.iter().sum::<f32>() / ids.len() as f32 },
+    }))
+}
+
+/// Candidate vectors for `ids`, in order
+fn source_vectors<'a>(store: &'a vector_store::VectorStore, ids: &[String]) -> Result<Vec<&'a [f32]>, String> {
+    ids.iter().map(|id| store.get(id).ok_or_else(|| format!("Unknown id '{}'", id))).collect()
+}
+
+/// Handle diversified selection task: k candidates by maximal marginal relevance - relevance is the
+/// cosine to the `query` vector (or the embedded `text`), or given outright as a `relevance` array
+fn handle_select_diverse(payload: &serde_json::Value) -> Response {
+    let (store, ids, stored) = match vector_source(payload) {
+        Ok(source) => source,
+        Err(e) => return Response::error(e),
+    };
+    let vectors = match source_vectors(&store, &ids) {
+        Ok(vectors) => vectors,
+        Err(e) => return Response::error(e),
+    };
+    let k = payload.get("k").and_then(|v| v.as_u64()).unwrap_or(5) as usize;
+    let lambda = payload.get("lambda").and_then(|v| v.as_f64()).map(|l| l as f32).unwrap_or(diverse::DEFAULT_LAMBDA);
+    if !(0.0..=1.0).contains(&lambda) {
+        return Response::error(format!("'lambda' must be between 0 and 1, got {}", lambda));
+    }
+
+    let relevance: Vec<f32> = if let Some(relevance) = payload.get("relevance").and_then(f32_array) {
+        if relevance.len() != vectors.len() {
+            return Response::error(format!("'relevance' has {} scores for {} candidates", relevance.len(), vectors.len()));
+        }
+        relevance
     } else {
-        let length_factor = (text.len() as f32 / 512.0).min(1.0);
-        length_factor * 0.8 + 0.2
+        let query = match payload.get("query").and_then(f32_array) {
+            Some(query) => query,
+            None => match payload.get("text").and_then(|v| v.as_str()) {
+                Some(text) => {
+                    let backend = payload.get("backend").and_then(|v| v.as_str());
+                    let dim = vectors.first().map_or(128, |v| v.len());
+                    match embedder::embed(&[text], backend, dim, 1) {
+                        Ok(embedded) => embedded.vectors.into_iter().next().unwrap_or_default(),
+                        Err(e) => return Response::error(e),
+                    }
+                }
+                None => return Response::error("Missing 'query' array, 'text' or 'relevance' array".to_string()),
+            },
+        };
+        if let Some(v) = vectors.iter().find(|v| v.len() != query.len()) {
+            return Response::error(format!("Query has {} dimensions, candidates have {}", query.len(), v.len()));
+        }
+        vectors.par_iter().map(|v| cosine_similarity(&query, v)).collect()
+    };
+
+    let selected = diverse::mmr(&vectors, &relevance, k, lambda);
+    // Stored entries are named by id, supplied ones by position
+    let label = |i: usize| if stored { serde_json::json!(ids[i]) } else { serde_json::json!(i) };
+    let selected: Vec<serde_json::Value> = selected.iter()
+        .map(|s| serde_json::json!({
+            "id": label(s.index),
+            "relevance": s.relevance,
+            "redundancy": s.redundancy,
+            "score": s.score,
+        }))
+        .collect();
+    Response::ok(serde_json::json!({"selected": selected, "candidates": ids.len(), "lambda": lambda}))
+}
+
+/// Handle similarity matrix task: cosine similarity of every pair of candidates
+fn handle_similarity_matrix(payload: &serde_json::Value) -> Response {
+    let (store, ids, stored) = match vector_source(payload) {
+        Ok(source) => source,
+        Err(e) => return Response::error(e),
+    };
+    if ids.len() > diverse::MAX_MATRIX_SIZE {
+        return Response::error(format!(
+            "{} entries is too many for a matrix (at most {}) - pass 'ids' to pick some",
+            ids.len(), diverse::MAX_MATRIX_SIZE,
+        ));
+    }
+    let vectors = match source_vectors(&store, &ids) {
+        Ok(vectors) => vectors,
+        Err(e) => return Response::error(e),
+    };
+    let label = |i: usize| if stored { serde_json::json!(ids[i]) } else { serde_json::json!(i) };
+    Response::ok(serde_json::json!({
+        "ids": (0..ids.len()).map(label).collect::<Vec<_>>(),
+        "matrix": diverse::similarity_matrix(&vectors),
+    }))
+}
+
+/// Handle anomaly scoring task: how far a new output (a `vector`, `text` to embed, or a stored `id`) lies
+/// from the stored outputs whose metadata `field` (default "command") equals `command`, against how far
+/// those lie from each other - `filter` narrows the baseline further, e.g. to one session
+fn handle_anomaly_score(payload: &serde_json::Value) -> Response {
+    let store = match store_dir(payload).and_then(|dir| vector_store::VectorStore::open(&dir, false)) {
+        Ok(store) => store,
+        Err(e) => return Response::error(e),
+    };
+    let command = match payload.get("command").and_then(|v| v.as_str()) {
+        Some(command) => command,
+        None => return Response::error("Missing 'command'".to_string()),
+    };
+    let field = payload.get("field").and_then(|v| v.as_str()).unwrap_or("command");
+    let filter = match filter::Filter::from_payload(payload) {
+        Ok(filter) => filter,
+        Err(e) => return Response::error(e),
+    };
+    let own_id = payload.get("id").and_then(|v| v.as_str());
+    let vector = match (payload.get("vector").and_then(f32_array), payload.get("text").and_then(|v| v.as_str())) {
+        (Some(vector), None) => vector,
+        (None, Some(text)) => {
+            let backend = payload.get("backend").and_then(|v| v.as_str());
+            match embedder::embed(&[text], backend, embed_dim(payload, &store), 1) {
+                Ok(embedded) => embedded.vectors.into_iter().next().unwrap_or_default(),
+                Err(e) => return Response::error(e),
+            }
+        }
+        (None, None) => match own_id.map(|id| store.get(id).ok_or_else(|| format!("Unknown id '{}'", id))) {
+            Some(Ok(vector)) => vector.to_vec(),
+            Some(Err(e)) => return Response::error(e),
+            None => return Response::error("Need one of 'vector', 'text' or a stored 'id'".to_string()),
+        },
+        (Some(_), Some(_)) => return Response::error("Give either 'vector' or 'text', not both".to_string()),
+    };
+    if store.dim() > 0 && vector.len() != store.dim() {
+        return Response::error(format!("Output has {} dimensions, the store holds {}", vector.len(), store.dim()));
+    }
+
+    // Past outputs of the command, newest first - the output itself, if stored, is not its own baseline
+    let mut past: Vec<(f64, String)> = store.ids()
+        .into_iter()
+        .filter(|id| Some(id.as_str()) != own_id)
+        .filter_map(|id| {
+            let metadata = store.metadata(&id)?;
+            let same = metadata.get(field).and_then(|v| v.as_str()) == Some(command) && filter.matches(metadata);
+            same.then(|| (metadata.get("timestamp").and_then(|v| v.as_f64()).unwrap_or(0.0), id))
+        })
+        .collect();
+    past.sort_by(|a, b| b.0.total_cmp(&a.0));
+    past.truncate(anomaly::MAX_BASELINE);
+
+    let min_baseline = payload.get("min_baseline").and_then(|v| v.as_u64()).map_or(anomaly::MIN_BASELINE, |n| n as usize).max(2);
+    if past.len() < min_baseline {
+        return Response::ok(serde_json::json!({
+            "command": command,
+            "baseline": past.len(),
+            "score": null,
+            "anomalous": false,
+            "reason": format!("{} past outputs of '{}' are too few for a baseline (need {})", past.len(), command, min_baseline),
+        }));
+    }
+    let ids: Vec<String> = past.into_iter().map(|(_, id)| id).collect();
+    let baseline = match source_vectors(&store, &ids) {
+        Ok(vectors) => vectors,
+        Err(e) => return Response::error(e),
     };
+    let k = payload.get("k").and_then(|v| v.as_u64()).map_or(anomaly::DEFAULT_K, |k| k as usize);
+    let threshold = payload.get("threshold").and_then(|v| v.as_f64()).map_or(anomaly::DEFAULT_THRESHOLD, |t| t as f32);
 
-    let result = serde_json::json!({
-        "validation_score": validation_score,
-        "length_ok": length_ok,
-        "has_content": has_content,
-        "suspicious": suspicious
+    let anomaly = anomaly::score(&baseline, &vector, k);
+    Response::ok(serde_json::json!({
+        "command": command,
+        "baseline": ids.len(),
+        "score": anomaly.score,
+        "anomalous": anomaly.exceeds(threshold),
+        "threshold": threshold,
+        "nearest_id": ids[anomaly.nearest],
+        "details": anomaly,
+    }))
+}
+
+/// Handle benchmark task: time embedding, insertion and search over a synthetic workload
+fn handle_benchmark(payload: &serde_json::Value) -> Response {
+    let number = |key: &str, default: u64| payload.get(key).and_then(|v| v.as_u64()).unwrap_or(default) as usize;
+    let quantization = match payload.get("quantization").and_then(|v| v.as_str()).map(quantize::Quantization::parse) {
+        Some(Ok(quantization)) => quantization,
+        Some(Err(e)) => return Response::error(e),
+        None => quantize::Quantization::None,
+    };
+    let workload = benchmark::Workload {
+        n: number("n", 1000),
+        dim: number("dim", 128),
+        queries: number("queries", 100),
+        k: number("k", 10),
+        ef: number("ef", vector_store::DEFAULT_EF as u64),
+        words: number("words", 12),
+        seed: payload.get("seed").and_then(|v| v.as_u64()).unwrap_or(42),
+        backend: payload.get("backend").and_then(|v| v.as_str()).map(str::to_string),
+        quantization,
+    };
+    let report = match benchmark::run(&workload) {
+        Ok(report) => report,
+        Err(e) => return Response::error(e),
+    };
+    let mut result = serde_json::to_value(&report).unwrap_or_default();
+    result["workload"] = serde_json::json!({
+        "n": workload.n,
+        "queries": workload.queries,
+        "k": workload.k,
+        "ef": workload.ef,
+        "words": workload.words,
+        "seed": workload.seed,
+        "quantization": workload.quantization.name(),
     });
+    Response::ok(result)
+}
 
-    Response {
-        status: "ok".to_string(),
-        result: Some(result),
-        embeddings: None,
-        error: None,
+/// Handle telemetry task: per-task counters of this process, or totalled from the telemetry log
+fn handle_telemetry(payload: &serde_json::Value) -> Response {
+    let from_log = payload.get("log").and_then(|v| v.as_bool()).unwrap_or(false);
+    match telemetry::report(from_log) {
+        Ok(report) => Response::ok(report),
+        Err(e) => Response::error(e),
+    }
+}
+
+/// Handle model listing: registered models, model directories and which one is active and loaded
+fn handle_list_models() -> Response {
+    match models::list() {
+        Ok(list) => Response::ok(list),
+        Err(e) => Response::error(e),
+    }
+}
+
+/// Handle model info task: a model's files, checksums and shape ("name" defaults to the loaded model)
+fn handle_model_info(payload: &serde_json::Value) -> Response {
+    match models::info(payload.get("name").and_then(|v| v.as_str())) {
+        Ok(info) => Response::ok(info),
+        Err(e) => Response::error(e),
+    }
+}
+
+/// Handle model loading: {name, path?, url?, sha256?, activate?} - registers the settings given, fetches
+/// missing files, checks checksums, then swaps the model in. It becomes the active model unless
+/// "activate" is false
+fn handle_load_model(payload: &serde_json::Value) -> Response {
+    let name = match payload.get("name").and_then(|v| v.as_str()) {
+        Some(name) => name,
+        None => return Response::error("Missing 'name'".to_string()),
+    };
+    let settings = serde_json::json!({
+        "path": payload.get("path"),
+        "url": payload.get("url"),
+        "sha256": payload.get("sha256").cloned().unwrap_or_else(|| serde_json::json!({})),
+    });
+    let settings: models::Entry = match serde_json::from_value(settings) {
+        Ok(settings) => settings,
+        Err(e) => return Response::error(format!("Invalid model settings: {}", e)),
+    };
+    let activate = payload.get("activate").and_then(|v| v.as_bool()).unwrap_or(true);
+
+    let loaded = models::prepare(name, settings).and_then(|dir| {
+        let dim = embedder::load_model(name, &dir)?;
+        if activate {
+            models::set_active(Some(name))?;
+        }
+        Ok((dir, dim))
+    });
+    match loaded {
+        Ok((dir, dim)) => Response::ok(serde_json::json!({"name": name, "path": dir, "dim": dim, "active": activate})),
+        Err(e) => Response::error(e),
     }
 }
 
+/// Handle model unloading: frees the held model - with "deactivate", workers also stop loading it on
+/// their own (embedding falls back to RUST_BRAIN_MODEL_DIR, else hash under `auto`)
+fn handle_unload_model(payload: &serde_json::Value) -> Response {
+    let unloaded = embedder::unload_model();
+    if payload.get("deactivate").and_then(|v| v.as_bool()).unwrap_or(false) {
+        if let Err(e) = models::set_active(None) {
+            return Response::error(e);
+        }
+    }
+    Response::ok(serde_json::json!({"unloaded": unloaded}))
+}
+
 /// Main dispatcher
 fn handle_request(req: Request) -> Response {
-    match req.task.as_str() {
-        "embed_texts" => handle_embed_texts(&req.payload),
-        "cosine_rank" => handle_cosine_rank(&req.payload),
-        "validate_fragment" => handle_validate_fragment(&req.payload),
-        other => Response {
-            status: "error".to_string(),
-            result: None,
-            embeddings: None,
-            error: Some(format!("Unknown task: {}", other)),
-        },
+    match req.action.as_str() {
+        "embed_texts" => handle_embed_texts(&req.data),
+        "cosine_rank" => handle_cosine_rank(&req.data),
+        "validate_fragment" => handle_validate_fragment(&req.data),
+        "store_upsert" => handle_store_upsert(&req.data),
+        "store_delete" => handle_store_delete(&req.data),
+        "store_search" => handle_store_search(&req.data),
+        "hybrid_search" => handle_hybrid_search(&req.data),
+        "extract_keywords" => handle_extract_keywords(&req.data),
+        "dedup" => handle_dedup(&req.data),
+        "cluster" => handle_cluster(&req.data),
+        "select_diverse" => handle_select_diverse(&req.data),
+        "similarity_matrix" => handle_similarity_matrix(&req.data),
+        "anomaly_score" => handle_anomaly_score(&req.data),
+        "create_collection" => handle_create_collection(&req.data),
+        "drop_collection" => handle_drop_collection(&req.data),
+        "list_collections" => handle_list_collections(&req.data),
+        "snapshot" => handle_snapshot(&req.data),
+        "restore" => handle_restore(&req.data),
+        "compact" => handle_compact(&req.data),
+        "relink" => handle_relink(&req.data),
+        "stats" => handle_stats(&req.data),
+        "benchmark" => handle_benchmark(&req.data),
+        "telemetry" => handle_telemetry(&req.data),
+        "list_models" => handle_list_models(),
+        "model_info" => handle_model_info(&req.data),
+        "load_model" => handle_load_model(&req.data),
+        "unload_model" => handle_unload_model(&req.data),
+        "embed_stream" => Response::error("embed_stream must be the first line of input, followed by one item per line".to_string()),
+        other => Response::error(format!("Unknown task: {}", other)),
     }
 }
 
 fn main() -> io::Result<()> {
-    // Read all stdin as JSON request
-    let mut input = String::new();
-    io::stdin().read_to_string(&mut input)?;
+    // `rust-brain compact <dir>` / `rust-brain relink <dir>` - the background jobs a worker starts
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() == 3 && matches!(args[1].as_str(), "compact" | "relink") {
+        let dir = std::path::Path::new(&args[2]);
+        let done = if args[1] == "compact" {
+            maintenance::compact(dir).map(|removed| format!("compacted {}: {} tombstones removed", args[2], removed))
+        } else {
+            maintenance::relink(dir).map(|relinked| format!("relinked {}: {} entries", args[2], relinked))
+        };
+        match done {
+            Ok(message) => println!("{}", message),
+            Err(e) => {
+                eprintln!("rust-brain: {} of {} failed: {}", args[1], args[2], e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
 
-    let req: Request = match serde_json::from_str(&input) {
-        Ok(r) => r,
-        Err(e) => {
-            let err_resp = Response {
-                status: "error".to_string(),
-                result: None,
-                embeddings: None,
-                error: Some(format!("Invalid JSON: {}", e)),
-            };
-            println!("{}", serde_json::to_string(&err_resp).unwrap());
-            return Ok(());
+    // `rust-brain serve [socket]` - answer requests over a Unix socket until killed
+    if args.len() <= 3 && args.get(1).is_some_and(|arg| arg == "serve") {
+        STARTED.get_or_init(Instant::now);
+        let path = args.get(2).cloned()
+            .or_else(|| std::env::var("RUST_BRAIN_SOCKET").ok())
+            .unwrap_or_else(|| server::DEFAULT_SOCKET.to_string());
+        return server::serve(std::path::Path::new(&path));
+    }
+
+    // The first line may be an embed_stream request, whose items follow line by line
+    let stdin = io::stdin();
+    let mut stdin = stdin.lock();
+    let mut input = String::new();
+    stdin.read_line(&mut input)?;
+    if let Ok(req) = serde_json::from_str::<Request>(&input) {
+        if req.action == "embed_stream" {
+            return stream::run(&req.data, stdin, &mut io::stdout().lock());
         }
-    };
+    }
+
+    // Otherwise read all stdin as JSON request
+    stdin.read_to_string(&mut input)?;
 
-    let resp = handle_request(req);
-    println!("{}", serde_json::to_string(&resp).unwrap());
+    let reply = match serde_json::from_str::<Request>(&input) {
+        Ok(req) => answer(req),
+        Err(e) => Response::error(format!("Invalid JSON: {}", e)).to_wire(None, Some(ErrorKind::Parse), None),
+    };
+    println!("{}", reply);
 
     Ok(())
 }
-
@@ -0,0 +1,280 @@
+// Store upkeep: snapshots, restore, and background compaction and relinking
+// A snapshot is a plain directory - the store's files as they were under the store lock plus a
+// `snapshot.json` manifest - so it can be copied to another machine and restored there. Snapshots are
+// built under a temporary name and renamed into place, so a half-written one is never mistaken for
+// a whole one.
+//
+// Deletes and updates leave tombstones; once they pile up the worker that made them starts
+// `rust-brain compact <dir>` as a detached process, which waits for the store lock and rebuilds the
+// graph without them. Short of that, enough churn starts `rust-brain relink <dir>`, which keeps every
+// entry and only re-selects links. RUST_BRAIN_AUTO_COMPACT=false turns both off (the `compact` and
+// `relink` tasks still work).
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::vector_store::{VectorStore, STORE_FILES};
+
+const MANIFEST: &str = "snapshot.json";
+const SNAPSHOT_FORMAT: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    format: u32,
+    collection: String,
+    created: u64, // unix seconds
+    count: usize,
+    dim: usize,
+    metric: String,
+    quantization: String,
+    files: BTreeMap<String, u64>, // name -> size
+}
+
+fn copy_synced(from: &Path, to: &Path) -> std::io::Result<u64> {
+    let size = fs::copy(from, to)?;
+    File::open(to)?.sync_all()?;
+    Ok(size)
+}
+
+/// Copy the store in `dir` to a new snapshot directory `dest`
+pub fn snapshot(dir: &Path, collection: &str, dest: &Path) -> Result<Manifest, String> {
+    if !dir.join("store.json").is_file() {
+        return Err(format!("Nothing to snapshot - {} holds no store", dir.display()));
+    }
+    if dest.exists() {
+        return Err(format!("Snapshot {} already exists", dest.display()));
+    }
+    // Held until the copy is done - writers wait, so the files match each other
+    let store = VectorStore::open(dir, true)?;
+
+    let mut tmp = dest.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let _ = fs::remove_dir_all(&tmp);
+    fs::create_dir_all(&tmp).map_err(|e| format!("Cannot create {}: {}", tmp.display(), e))?;
+    let mut files = BTreeMap::new();
+    for name in STORE_FILES.iter().filter(|name| dir.join(name).is_file()) {
+        let size = copy_synced(&dir.join(name), &tmp.join(name)).map_err(|e| format!("Cannot copy {}: {}", name, e))?;
+        files.insert(name.to_string(), size);
+    }
+
+    let manifest = Manifest {
+        format: SNAPSHOT_FORMAT,
+        collection: collection.to_string(),
+        created: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        count: store.len(),
+        dim: store.dim(),
+        metric: store.metric().name().to_string(),
+        quantization: store.quantization().name().to_string(),
+        files,
+    };
+    let json = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+    fs::write(tmp.join(MANIFEST), json)
+        .and_then(|_| fs::rename(&tmp, dest))
+        .map_err(|e| format!("Cannot write snapshot {}: {}", dest.display(), e))?;
+    Ok(manifest)
+}
+
+/// Replace the store in `dir` with the snapshot in `source` - a store that holds entries is only
+/// replaced with `overwrite`
+pub fn restore(source: &Path, dir: &Path, overwrite: bool) -> Result<Manifest, String> {
+    let manifest: Manifest = fs::read_to_string(source.join(MANIFEST))
+        .map_err(|e| format!("{} is not a snapshot: {}", source.display(), e))
+        .and_then(|json| serde_json::from_str(&json).map_err(|e| format!("Corrupt {}: {}", MANIFEST, e)))?;
+    if manifest.format != SNAPSHOT_FORMAT {
+        return Err(format!("Unsupported snapshot format {}", manifest.format));
+    }
+    for (name, &size) in &manifest.files {
+        if !STORE_FILES.contains(&name.as_str()) {
+            return Err(format!("Unexpected file '{}' in snapshot", name));
+        }
+        let actual = fs::metadata(source.join(name)).map(|m| m.len()).unwrap_or(0);
+        if actual != size {
+            return Err(format!("Snapshot file {} is {} bytes, the manifest says {}", name, actual, size));
+        }
+    }
+    // Loads only if store.json and the vector files agree
+    VectorStore::open(source, false)?;
+
+    let target = VectorStore::open(dir, true)?;
+    if target.len() > 0 && !overwrite {
+        return Err(format!("{} holds {} entries - set 'overwrite' to replace them", dir.display(), target.len()));
+    }
+    // Each file lands by rename, store.json last; files the snapshot doesn't have go after it
+    for name in STORE_FILES {
+        if manifest.files.contains_key(name) {
+            let tmp = dir.join(format!("{}.restore", name));
+            copy_synced(&source.join(name), &tmp)
+                .and_then(|_| fs::rename(&tmp, dir.join(name)))
+                .map_err(|e| format!("Cannot restore {}: {}", name, e))?;
+        }
+    }
+    for name in STORE_FILES.iter().filter(|name| !manifest.files.contains_key(**name)) {
+        match fs::remove_file(dir.join(name)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(format!("Cannot remove {}: {}", name, e)),
+            _ => {}
+        }
+    }
+    Ok(manifest)
+}
+
+/// Save `store`, starting a background compaction when tombstones have piled up, else a background
+/// relink when enough has changed (a compaction relinks everything anyway)
+pub fn save(store: VectorStore) -> Result<(), String> {
+    let job = if store.wants_compaction() {
+        Some("compact")
+    } else {
+        store.wants_relink().then_some("relink")
+    };
+    let dir = store.dir().to_path_buf();
+    store.save()?;
+    if let Some(job) = job {
+        in_background(job, &dir);
+    }
+    Ok(())
+}
+
+/// Start a detached `rust-brain <job> <dir>` (it waits for this worker's lock to be released)
+fn in_background(job: &str, dir: &Path) {
+    if std::env::var("RUST_BRAIN_AUTO_COMPACT").is_ok_and(|v| v == "false") {
+        return;
+    }
+    let spawned = std::env::current_exe().and_then(|exe| {
+        Command::new(exe)
+            .arg(job)
+            .arg(dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+    });
+    if let Err(e) = spawned {
+        eprintln!("rust-brain: cannot start background {}: {}", job, e);
+    }
+}
+
+/// Body of `rust-brain compact <dir>` - another worker may have compacted first
+pub fn compact(dir: &Path) -> Result<usize, String> {
+    let store = VectorStore::open(dir, true)?;
+    if store.tombstones() == 0 {
+        return Ok(0);
+    }
+    store.compact()
+}
+
+/// Body of `rust-brain relink <dir>` - nothing to do if another worker relinked or compacted first
+pub fn relink(dir: &Path) -> Result<usize, String> {
+    let mut store = VectorStore::open(dir, true)?;
+    if !store.wants_relink() {
+        return Ok(0);
+    }
+    let relinked = store.relink();
+    store.save()?;
+    Ok(relinked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::Filter;
+    use crate::metric::Metric;
+    use crate::quantize::Quantization;
+    use crate::vector_store::DEFAULT_EF;
+    use serde_json::json;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rust-brain-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// Store of `count` entries in `dir`, every tenth with text
+    fn fill(dir: &Path, count: usize, quantization: Quantization) {
+        let mut store = VectorStore::create(dir, 4, Metric::Cosine, quantization).unwrap();
+        for i in 0..count {
+            let x = i as f32;
+            store.upsert(&format!("e{}", i), &[x.sin(), x.cos(), (x * 0.3).sin(), 1.0], json!({ "n": i })).unwrap();
+            if i % 10 == 0 {
+                store.index_text(&format!("e{}", i), &format!("entry number{}", i));
+            }
+        }
+        store.save().unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_restore_round_trip() {
+        let root = temp_dir("snapshot");
+        let (dir, snap, copy) = (root.join("store"), root.join("snap"), root.join("copy"));
+        fill(&dir, 120, Quantization::Int8);
+        {
+            let mut store = VectorStore::open(&dir, true).unwrap();
+            store.remove("e5");
+            store.save().unwrap();
+        }
+
+        let manifest = snapshot(&dir, "logs", &snap).unwrap();
+        assert_eq!((manifest.collection.as_str(), manifest.count, manifest.dim), ("logs", 119, 4));
+        assert_eq!(manifest.quantization, "int8");
+        assert!(manifest.files.contains_key("vectors.i8") && manifest.files.contains_key("keywords.json"));
+        assert!(!root.join("snap.tmp").exists());
+        assert!(snapshot(&dir, "logs", &snap).is_err());
+
+        restore(&snap, &copy, false).unwrap();
+        let (original, restored) = (VectorStore::open(&dir, false).unwrap(), VectorStore::open(&copy, false).unwrap());
+        assert_eq!(restored.ids(), original.ids());
+        assert_eq!(restored.quantization(), Quantization::Int8);
+        assert!(restored.get("e5").is_none());
+        for id in original.ids() {
+            assert_eq!(restored.get(&id), original.get(&id));
+            assert_eq!(restored.metadata(&id), original.metadata(&id));
+        }
+        let query = original.get("e42").unwrap();
+        let ids = |store: &VectorStore| -> Vec<String> {
+            store.search(query, 5, DEFAULT_EF, &Filter::default()).unwrap().into_iter().map(|m| m.id).collect()
+        };
+        assert_eq!(ids(&restored), ids(&original));
+        assert_eq!(restored.keyword_search("number30", 1, &Filter::default())[0].0, "e30");
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_restore_refuses_to_clobber_and_checks_the_snapshot() {
+        let root = temp_dir("restore");
+        let (dir, snap, other) = (root.join("store"), root.join("snap"), root.join("other"));
+        fill(&dir, 30, Quantization::None);
+        snapshot(&dir, "logs", &snap).unwrap();
+        fill(&other, 10, Quantization::Binary);
+
+        // A store with entries is only replaced on request, and loses files the snapshot doesn't have
+        assert!(restore(&snap, &other, false).map(drop).unwrap_err().contains("overwrite"));
+        restore(&snap, &other, true).unwrap();
+        let restored = VectorStore::open(&other, false).unwrap();
+        assert_eq!((restored.len(), restored.quantization()), (30, Quantization::None));
+        assert!(!other.join("vectors.bit").exists());
+
+        // A file that doesn't match the manifest stops the restore before anything is touched
+        fs::write(snap.join("vectors.f32"), b"short").unwrap();
+        assert!(restore(&snap, &other, true).map(drop).unwrap_err().contains("manifest"));
+        assert_eq!(VectorStore::open(&other, false).unwrap().len(), 30);
+        assert!(restore(&root.join("missing"), &other, true).is_err());
+        assert!(snapshot(&root.join("missing"), "logs", &root.join("snap2")).is_err());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_compact_drops_tombstones_once() {
+        let dir = temp_dir("compact");
+        fill(&dir, 40, Quantization::None);
+        let mut store = VectorStore::open(&dir, true).unwrap();
+        (0..10).for_each(|i| assert!(store.remove(&format!("e{}", i))));
+        store.save().unwrap();
+
+        assert_eq!(compact(&dir).unwrap(), 10);
+        assert_eq!(compact(&dir).unwrap(), 0);
+        let store = VectorStore::open(&dir, false).unwrap();
+        assert_eq!((store.len(), store.tombstones()), (30, 0));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,129 @@
+// Similarity metrics a collection can use
+// Internally everything is a distance (smaller = closer); replies carry a score where larger is closer:
+//   cosine     vectors normalized on the way in, score = cosine similarity
+//   dot        raw inner product, score = dot product
+//   euclidean  squared L2 distance, score = 1 / (1 + L2 distance)
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    #[default]
+    Cosine,
+    Dot,
+    Euclidean,
+}
+
+impl Metric {
+    pub fn parse(name: &str) -> Result<Metric, String> {
+        match name {
+            "cosine" => Ok(Metric::Cosine),
+            "dot" => Ok(Metric::Dot),
+            "euclidean" | "l2" => Ok(Metric::Euclidean),
+            other => Err(format!("Unknown metric '{}' (cosine, dot or euclidean)", other)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Metric::Cosine => "cosine",
+            Metric::Dot => "dot",
+            Metric::Euclidean => "euclidean",
+        }
+    }
+
+    /// A vector as the store keeps it (and compares queries)
+    pub fn prepare(self, vector: &[f32]) -> Vec<f32> {
+        match self {
+            Metric::Cosine => normalize(vector),
+            Metric::Dot | Metric::Euclidean => vector.to_vec(),
+        }
+    }
+
+    pub fn distance(self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Metric::Cosine => 1.0 - dot(a, b),
+            Metric::Dot => -dot(a, b),
+            Metric::Euclidean => a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum(),
+        }
+    }
+
+    /// Reply score for a distance
+    pub fn score(self, distance: f32) -> f32 {
+        match self {
+            Metric::Cosine => 1.0 - distance,
+            Metric::Dot => -distance,
+            Metric::Euclidean => 1.0 / (1.0 + distance.max(0.0).sqrt()),
+        }
+    }
+}
+
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+pub fn normalize(vector: &[f32]) -> Vec<f32> {
+    let norm = dot(vector, vector).sqrt();
+    if norm > 0.0 {
+        vector.iter().map(|v| v / norm).collect()
+    } else {
+        vector.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Metric; 3] = [Metric::Cosine, Metric::Dot, Metric::Euclidean];
+
+    #[test]
+    fn test_parse_and_name_round_trip() {
+        for metric in ALL {
+            assert_eq!(Metric::parse(metric.name()), Ok(metric));
+        }
+        assert_eq!(Metric::parse("l2"), Ok(Metric::Euclidean));
+        assert!(Metric::parse("manhattan").is_err());
+        assert_eq!(Metric::default(), Metric::Cosine);
+        assert_eq!(serde_json::to_string(&Metric::Euclidean).unwrap(), "\"euclidean\"");
+    }
+
+    #[test]
+    fn test_distances_and_scores() {
+        let (a, b) = (Metric::Cosine.prepare(&[3.0, 4.0]), Metric::Cosine.prepare(&[4.0, 3.0]));
+        assert!((Metric::Cosine.distance(&a, &a)).abs() < 1e-6);
+        assert!((Metric::Cosine.score(Metric::Cosine.distance(&a, &b)) - 0.96).abs() < 1e-6);
+
+        // Dot keeps length: the longer vector in the same direction scores higher
+        assert_eq!(Metric::Dot.prepare(&[3.0, 4.0]), vec![3.0, 4.0]);
+        assert_eq!(Metric::Dot.score(Metric::Dot.distance(&[1.0, 2.0], &[3.0, 4.0])), 11.0);
+        assert!(Metric::Dot.distance(&[1.0, 0.0], &[2.0, 0.0]) < Metric::Dot.distance(&[1.0, 0.0], &[1.0, 0.0]));
+
+        // Euclidean distance is squared; the score undoes it
+        assert_eq!(Metric::Euclidean.distance(&[0.0, 0.0], &[3.0, 4.0]), 25.0);
+        assert!((Metric::Euclidean.score(25.0) - 1.0 / 6.0).abs() < 1e-6);
+        assert_eq!(Metric::Euclidean.score(0.0), 1.0);
+        assert_eq!(Metric::Euclidean.score(-1e-7), 1.0);
+    }
+
+    #[test]
+    fn test_closer_always_scores_higher() {
+        let query = [1.0, 0.5, -0.25];
+        let (near, far) = ([0.9, 0.6, -0.2], [-1.0, 0.3, 0.8]);
+        for metric in ALL {
+            let q = metric.prepare(&query);
+            let near = metric.distance(&q, &metric.prepare(&near));
+            let far = metric.distance(&q, &metric.prepare(&far));
+            assert!(near < far, "{}", metric.name());
+            assert!(metric.score(near) > metric.score(far), "{}", metric.name());
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(&[0.0, 0.0]), vec![0.0, 0.0]);
+        let unit = normalize(&[2.0, 0.0, 0.0]);
+        assert_eq!(unit, vec![1.0, 0.0, 0.0]);
+        assert!((dot(&normalize(&[1.0, 2.0, 2.0]), &normalize(&[1.0, 2.0, 2.0])) - 1.0).abs() < 1e-6);
+    }
+}
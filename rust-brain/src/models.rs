@@ -0,0 +1,333 @@
+// Embedding model registry (`list_models`, `model_info`, `load_model`, `unload_model`)
+// Models live under RUST_BRAIN_MODELS_DIR (default brain/models), one directory per model holding
+// config.json, tokenizer.json and model.safetensors. `models.json` there names them, and an entry may
+// give a download path other than <root>/<name>, a base URL to fetch missing files from (with curl),
+// and checksums every file must match before the model is loaded:
+//
+//   {"active": "minilm",
+//    "models": {"minilm": {"path": "/data/models/minilm",
+//                          "url": "https://huggingface.co/sentence-transformers/all-MiniLM-L6-v2/resolve/main",
+//                          "sha256": {"model.safetensors": "53aa51..."}}}}
+//
+// `active` is the model a worker loads on first use, so `load_model` in one worker carries over to the
+// next (and `rust-brain serve` switches without a restart). With nothing active, RUST_BRAIN_MODEL_DIR
+// still names a model, listed as "env".
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use crate::embedder;
+
+pub const MODEL_FILES: [&str; 3] = ["config.json", "tokenizer.json", "model.safetensors"];
+const REGISTRY: &str = "models.json";
+/// The model RUST_BRAIN_MODEL_DIR points at
+const ENV_MODEL: &str = "env";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Registry {
+    active: Option<String>,
+    #[serde(default)]
+    models: BTreeMap<String, Entry>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Entry {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sha256: BTreeMap<String, String>, // file name -> hex digest
+}
+
+fn root() -> PathBuf {
+    std::env::var_os("RUST_BRAIN_MODELS_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("brain/models"))
+}
+
+fn env_dir() -> Option<PathBuf> {
+    std::env::var_os("RUST_BRAIN_MODEL_DIR").map(PathBuf::from)
+}
+
+fn load_registry(root: &Path) -> Result<Registry, String> {
+    match fs::read_to_string(root.join(REGISTRY)) {
+        Ok(json) => serde_json::from_str(&json).map_err(|e| format!("Corrupt {}: {}", REGISTRY, e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Registry::default()),
+        Err(e) => Err(format!("Cannot read {}: {}", REGISTRY, e)),
+    }
+}
+
+fn save_registry(root: &Path, registry: &Registry) -> Result<(), String> {
+    fs::create_dir_all(root).map_err(|e| format!("Cannot create {}: {}", root.display(), e))?;
+    let json = serde_json::to_string_pretty(registry).map_err(|e| e.to_string())?;
+    let tmp = root.join(format!("{}.tmp", REGISTRY));
+    fs::write(&tmp, json)
+        .and_then(|_| fs::rename(&tmp, root.join(REGISTRY)))
+        .map_err(|e| format!("Cannot write {}: {}", REGISTRY, e))
+}
+
+fn check_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if !valid || name.starts_with('.') {
+        return Err(format!("Invalid model name '{}' (letters, digits, '_', '-' and '.')", name));
+    }
+    Ok(())
+}
+
+/// Where a model's files are
+fn model_dir(root: &Path, name: &str, entry: Option<&Entry>) -> Option<PathBuf> {
+    match entry.and_then(|e| e.path.as_ref()) {
+        Some(path) => Some(PathBuf::from(path)),
+        None if name == ENV_MODEL => env_dir(),
+        None => Some(root.join(name)),
+    }
+}
+
+fn complete(dir: &Path) -> bool {
+    MODEL_FILES.iter().all(|file| dir.join(file).is_file())
+}
+
+/// The model to load when embedding asks for one and none is loaded: the active one, else RUST_BRAIN_MODEL_DIR
+pub fn default_model() -> Option<(String, PathBuf)> {
+    let root = root();
+    let active = load_registry(&root).ok().and_then(|registry| {
+        let name = registry.active?;
+        let dir = model_dir(&root, &name, registry.models.get(&name))?;
+        Some((name, dir))
+    });
+    active
+        .or_else(|| env_dir().map(|dir| (ENV_MODEL.to_string(), dir)))
+        .filter(|(_, dir)| dir.join("model.safetensors").is_file())
+}
+
+fn sha256_file(path: &Path) -> Result<String, String> {
+    let mut file = File::open(path).map_err(|e| format!("Cannot open {}: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Fetch `<url>/<file>` into `dir` (through a temporary name, so a failed download leaves nothing)
+fn download(url: &str, file: &str, dir: &Path) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    let source = format!("{}/{}", url.trim_end_matches('/'), file);
+    let tmp = dir.join(format!("{}.download", file));
+    let status = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location", "--retry", "2", "--output"])
+        .arg(&tmp)
+        .arg(&source)
+        .status()
+        .map_err(|e| format!("Cannot run curl: {}", e))?;
+    if !status.success() {
+        let _ = fs::remove_file(&tmp);
+        return Err(format!("Downloading {} failed ({})", source, status));
+    }
+    fs::rename(&tmp, dir.join(file)).map_err(|e| format!("Cannot save {}: {}", file, e))
+}
+
+/// Register `name` with the given settings layered over its entry, fetch any missing files and check
+/// every checksum - the directory to load it from
+pub fn prepare(name: &str, overrides: Entry) -> Result<PathBuf, String> {
+    prepare_in(&root(), name, overrides)
+}
+
+fn prepare_in(root: &Path, name: &str, overrides: Entry) -> Result<PathBuf, String> {
+    let mut registry = load_registry(root)?;
+    if name != ENV_MODEL {
+        check_name(name)?;
+    }
+    let mut entry = registry.models.get(name).cloned().unwrap_or_default();
+    entry.path = overrides.path.or(entry.path);
+    entry.url = overrides.url.or(entry.url);
+    entry.sha256.extend(overrides.sha256);
+    if let Some(file) = entry.sha256.keys().find(|file| !MODEL_FILES.contains(&file.as_str())) {
+        return Err(format!("Checksum for unknown file '{}' (expected one of {})", file, MODEL_FILES.join(", ")));
+    }
+    let dir = model_dir(root, name, Some(&entry)).ok_or("RUST_BRAIN_MODEL_DIR is not set")?;
+
+    let mut downloaded = Vec::new();
+    for file in MODEL_FILES.iter().filter(|file| !dir.join(file).is_file()) {
+        match &entry.url {
+            Some(url) => download(url, file, &dir)?,
+            None => return Err(format!("Model '{}' has no {} in {} and no 'url' to fetch it from", name, file, dir.display())),
+        }
+        downloaded.push(*file);
+    }
+    for (file, expected) in &entry.sha256 {
+        let actual = sha256_file(&dir.join(file))?;
+        if !actual.eq_ignore_ascii_case(expected) {
+            // A bad download is fetched again next time; files that were already there are left alone
+            if downloaded.contains(&file.as_str()) {
+                let _ = fs::remove_file(dir.join(file));
+            }
+            return Err(format!("{} of model '{}' has sha256 {}, expected {}", file, name, actual, expected));
+        }
+    }
+
+    if name != ENV_MODEL {
+        registry.models.insert(name.to_string(), entry);
+        save_registry(root, &registry)?;
+    }
+    Ok(dir)
+}
+
+/// Make `name` (or nothing) the model workers load on first use
+pub fn set_active(name: Option<&str>) -> Result<(), String> {
+    let root = root();
+    let mut registry = load_registry(&root)?;
+    registry.active = name.map(str::to_string);
+    save_registry(&root, &registry)
+}
+
+/// Every registered model, model directory under the root, and the RUST_BRAIN_MODEL_DIR one
+pub fn list() -> Result<Value, String> {
+    let root = root();
+    let registry = load_registry(&root)?;
+    let mut names: Vec<String> = registry.models.keys().cloned().collect();
+    if let Ok(entries) = fs::read_dir(&root) {
+        for entry in entries.filter_map(|e| e.ok()).filter(|e| e.path().is_dir()) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !names.contains(&name) && check_name(&name).is_ok() {
+                names.push(name);
+            }
+        }
+    }
+    if env_dir().is_some() && !names.iter().any(|n| n == ENV_MODEL) {
+        names.push(ENV_MODEL.to_string());
+    }
+    names.sort();
+
+    let loaded = embedder::loaded_model().map(|(name, _)| name);
+    let models: Vec<Value> = names.iter()
+        .map(|name| {
+            let entry = registry.models.get(name);
+            let dir = model_dir(&root, name, entry);
+            json!({
+                "name": name,
+                "path": dir,
+                "registered": entry.is_some(),
+                "complete": dir.as_deref().is_some_and(complete),
+                "active": registry.active.as_deref() == Some(name.as_str()),
+                "loaded": loaded.as_deref() == Some(name.as_str()),
+            })
+        })
+        .collect();
+    Ok(json!({
+        "root": root,
+        "models": models,
+        "active": registry.active,
+        "loaded": loaded,
+        "backends": embedder::BACKENDS,
+    }))
+}
+
+/// One model's files, checksums and shape - `name` defaults to the loaded model, else the active one
+pub fn info(name: Option<&str>) -> Result<Value, String> {
+    let root = root();
+    let registry = load_registry(&root)?;
+    let loaded = embedder::loaded_model();
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => loaded.as_ref().map(|(name, _)| name.clone())
+            .or_else(|| default_model().map(|(name, _)| name))
+            .ok_or("No model is loaded or active - name one")?,
+    };
+    let entry = registry.models.get(&name);
+    let dir = model_dir(&root, &name, entry).ok_or_else(|| format!("Unknown model '{}'", name))?;
+    if entry.is_none() && !dir.is_dir() {
+        return Err(format!("Unknown model '{}'", name));
+    }
+
+    let files: Vec<Value> = MODEL_FILES.iter()
+        .map(|file| {
+            let size = fs::metadata(dir.join(file)).ok().map(|m| m.len());
+            json!({"name": file, "present": size.is_some(), "bytes": size, "sha256": entry.and_then(|e| e.sha256.get(*file))})
+        })
+        .collect();
+    // What candle reads from config.json - the embedding width is hidden_size
+    let config: Value = fs::read_to_string(dir.join("config.json"))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or(Value::Null);
+    let shape: serde_json::Map<String, Value> = ["model_type", "hidden_size", "num_hidden_layers", "num_attention_heads", "vocab_size", "max_position_embeddings"]
+        .iter()
+        .filter_map(|key| config.get(*key).map(|v| (key.to_string(), v.clone())))
+        .collect();
+    let loaded_dim = loaded.filter(|(loaded, _)| *loaded == name).map(|(_, dim)| dim);
+
+    Ok(json!({
+        "name": name,
+        "path": dir,
+        "url": entry.and_then(|e| e.url.as_ref()),
+        "files": files,
+        "complete": complete(&dir),
+        "config": shape,
+        "active": registry.active.as_deref() == Some(name.as_str()),
+        "loaded": loaded_dim.is_some(),
+        "dim": loaded_dim,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("rust-brain-models-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("mini")).unwrap();
+        for file in MODEL_FILES {
+            fs::write(root.join("mini").join(file), format!("contents of {}", file)).unwrap();
+        }
+        root
+    }
+
+    fn checksums(pairs: &[(&str, &str)]) -> Entry {
+        Entry { sha256: pairs.iter().map(|(file, sum)| (file.to_string(), sum.to_string())).collect(), ..Entry::default() }
+    }
+
+    #[test]
+    fn test_mismatched_checksum_is_rejected() {
+        let root = model_root("mismatch");
+        let wrong = "0".repeat(64);
+        let e = prepare_in(&root, "mini", checksums(&[("model.safetensors", &wrong)])).unwrap_err();
+        assert!(e.contains("model.safetensors") && e.contains(&wrong), "{}", e);
+
+        // Files that were already there stay, and the model isn't registered
+        assert!(root.join("mini/model.safetensors").is_file());
+        assert!(load_registry(&root).unwrap().models.is_empty());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_matching_checksum_registers_the_model() {
+        let root = model_root("match");
+        let actual = sha256_file(&root.join("mini/config.json")).unwrap();
+        let dir = prepare_in(&root, "mini", checksums(&[("config.json", &actual.to_uppercase())])).unwrap();
+        assert_eq!(dir, root.join("mini"));
+        assert_eq!(load_registry(&root).unwrap().models["mini"].sha256["config.json"], actual.to_uppercase());
+
+        // The registered checksum is checked again on the next load
+        fs::write(root.join("mini/config.json"), "tampered").unwrap();
+        assert!(prepare_in(&root, "mini", Entry::default()).unwrap_err().contains("config.json"));
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_bad_names_files_and_missing_files() {
+        let root = model_root("invalid");
+        assert!(prepare_in(&root, "../mini", Entry::default()).is_err());
+        assert!(prepare_in(&root, ".hidden", Entry::default()).is_err());
+        assert!(prepare_in(&root, "mini", checksums(&[("vocab.txt", "00")])).unwrap_err().contains("unknown file"));
+
+        // Nothing to fetch a missing file from
+        fs::remove_file(root.join("mini/tokenizer.json")).unwrap();
+        assert!(prepare_in(&root, "mini", Entry::default()).unwrap_err().contains("no 'url'"));
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,219 @@
+// Quantized search index
+// A quantized store walks its HNSW graph over int8 or sign-bit copies of the vectors instead of the f32
+// originals, which stay in `vectors.f32` but are only read to rescore the final candidates - the part
+// of the store that has to sit in memory shrinks ~4x (int8) or 32x (binary). Queries stay f32 and are
+// scored against the quantized rows directly (asymmetric scoring), so only the stored side loses
+// precision:
+//   int8    1 byte per dimension + a 4-byte scale per row
+//   binary  1 bit per dimension (coarse - leans on rescoring, best for wide model embeddings; cosine
+//           collections only, a sign carries no magnitude)
+use serde::{Deserialize, Serialize};
+use crate::metric::Metric;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Quantization {
+    #[default]
+    None,
+    Int8,
+    Binary,
+}
+
+impl Quantization {
+    pub fn parse(name: &str) -> Result<Quantization, String> {
+        match name {
+            "none" => Ok(Quantization::None),
+            "int8" => Ok(Quantization::Int8),
+            "binary" => Ok(Quantization::Binary),
+            other => Err(format!("Unknown quantization '{}' (none, int8 or binary)", other)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Quantization::None => "none",
+            Quantization::Int8 => "int8",
+            Quantization::Binary => "binary",
+        }
+    }
+
+    /// Index file next to vectors.f32 (none without quantization)
+    pub fn file_name(self) -> Option<&'static str> {
+        match self {
+            Quantization::None => None,
+            Quantization::Int8 => Some("vectors.i8"),
+            Quantization::Binary => Some("vectors.bit"),
+        }
+    }
+
+    /// Bytes per index row
+    pub fn row_bytes(self, dim: usize) -> usize {
+        match self {
+            Quantization::None => 0,
+            Quantization::Int8 => 4 + dim,
+            Quantization::Binary => dim.div_ceil(8),
+        }
+    }
+
+    /// Candidates walked per requested result - sign bits are too coarse to shortlist tightly
+    pub fn oversample(self) -> usize {
+        match self {
+            Quantization::Binary => 2,
+            _ => 1,
+        }
+    }
+
+    /// Whether this quantization can serve `metric`
+    pub fn check(self, metric: Metric) -> Result<(), String> {
+        if self == Quantization::Binary && metric != Metric::Cosine {
+            return Err(format!("Binary quantization needs the cosine metric, not {}", metric.name()));
+        }
+        Ok(())
+    }
+
+    /// Append the index row for `vector` to `out`
+    pub fn encode(self, vector: &[f32], out: &mut Vec<u8>) {
+        match self {
+            Quantization::None => {}
+            Quantization::Int8 => {
+                let max = vector.iter().fold(0.0f32, |max, v| max.max(v.abs()));
+                let scale = if max > 0.0 { max / 127.0 } else { 1.0 };
+                out.extend(scale.to_ne_bytes());
+                out.extend(vector.iter().map(|v| (v / scale).round().clamp(-127.0, 127.0) as i8 as u8));
+            }
+            Quantization::Binary => out.extend(vector.chunks(8).map(|chunk| {
+                chunk.iter().enumerate().fold(0u8, |byte, (bit, v)| if *v > 0.0 { byte | 1 << bit } else { byte })
+            })),
+        }
+    }
+
+    /// Approximate `metric` distance from an f32 `query` to an index row
+    pub fn distance(self, metric: Metric, query: &[f32], row: &[u8]) -> f32 {
+        match self {
+            Quantization::None => 0.0,
+            Quantization::Int8 => {
+                let scale = f32::from_ne_bytes([row[0], row[1], row[2], row[3]]);
+                let codes = query.iter().zip(&row[4..]);
+                if metric == Metric::Euclidean {
+                    return codes.map(|(q, &c)| (q - c as i8 as f32 * scale).powi(2)).sum();
+                }
+                let dot = scale * codes.map(|(q, &c)| q * c as i8 as f32).sum::<f32>();
+                if metric == Metric::Dot { -dot } else { 1.0 - dot }
+            }
+            // The row stands for the unit vector of ±1/sqrt(dim) along each sign
+            Quantization::Binary => {
+                let sum: f32 = query.iter()
+                    .enumerate()
+                    .map(|(i, q)| if row[i / 8] >> (i % 8) & 1 == 1 { *q } else { -q })
+                    .sum();
+                1.0 - sum / (query.len() as f32).sqrt()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::{dot, normalize};
+
+    const DIM: usize = 24;
+
+    /// Deterministic vectors in [-1, 1) (xorshift, so runs are repeatable)
+    fn vectors(count: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut state = seed.max(1);
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+        };
+        (0..count).map(|_| (0..DIM).map(|_| next()).collect()).collect()
+    }
+
+    fn encoded(quantization: Quantization, vector: &[f32]) -> Vec<u8> {
+        let mut row = Vec::new();
+        quantization.encode(vector, &mut row);
+        assert_eq!(row.len(), quantization.row_bytes(vector.len()));
+        row
+    }
+
+    /// Int8 row back to f32
+    fn dequantize(row: &[u8]) -> Vec<f32> {
+        let scale = f32::from_ne_bytes([row[0], row[1], row[2], row[3]]);
+        row[4..].iter().map(|&c| c as i8 as f32 * scale).collect()
+    }
+
+    #[test]
+    fn test_int8_round_trip_error_is_half_a_step() {
+        for vector in vectors(50, 3) {
+            let max = vector.iter().fold(0.0f32, |max, v| max.max(v.abs()));
+            let restored = dequantize(&encoded(Quantization::Int8, &vector));
+            for (original, restored) in vector.iter().zip(&restored) {
+                assert!((original - restored).abs() <= max / 254.0 + 1e-6, "{} -> {}", original, restored);
+            }
+        }
+        // The largest component is exact, and an all-zero vector stays zero
+        assert_eq!(dequantize(&encoded(Quantization::Int8, &[0.5, -2.0, 1.0]))[1], -2.0);
+        assert_eq!(dequantize(&encoded(Quantization::Int8, &[0.0; 4])), vec![0.0; 4]);
+    }
+
+    #[test]
+    fn test_int8_distance_error_bound() {
+        let rows = vectors(50, 5);
+        for query in vectors(10, 7) {
+            for metric in [Metric::Cosine, Metric::Dot, Metric::Euclidean] {
+                let query = metric.prepare(&query);
+                for row in &rows {
+                    let row = metric.prepare(row);
+                    let max = row.iter().fold(0.0f32, |max, v| max.max(v.abs()));
+                    let step = max / 254.0; // worst error per stored component
+                    let bound = match metric {
+                        Metric::Euclidean => query.iter().zip(&row).map(|(q, r)| 2.0 * (q - r).abs() * step + step * step).sum(),
+                        _ => query.iter().map(|q| q.abs() * step).sum::<f32>(),
+                    };
+                    let exact = metric.distance(&query, &row);
+                    let approx = Quantization::Int8.distance(metric, &query, &encoded(Quantization::Int8, &row));
+                    assert!((approx - exact).abs() <= bound + 1e-5, "{:?}: {} vs {}", metric, approx, exact);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_binary_keeps_signs() {
+        let vector = [0.3, -0.1, 0.0, 2.0, -5.0, 0.1, 0.2, -0.2, 1.0];
+        assert_eq!(encoded(Quantization::Binary, &vector), vec![0b0110_1001, 0b1]);
+
+        // A row holding the query's own signs is the closest one a query can get
+        let rows = vectors(50, 9);
+        for query in vectors(10, 11).iter().map(|q| normalize(q)) {
+            let own = Quantization::Binary.distance(Metric::Cosine, &query, &encoded(Quantization::Binary, &query));
+            let expected = 1.0 - query.iter().map(|q| q.abs()).sum::<f32>() / (DIM as f32).sqrt();
+            assert!((own - expected).abs() < 1e-5);
+            for row in &rows {
+                assert!(Quantization::Binary.distance(Metric::Cosine, &query, &encoded(Quantization::Binary, row)) >= own - 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_binary_distance_is_cosine_to_the_sign_vector() {
+        let query = normalize(&vectors(1, 13)[0]);
+        for row in vectors(20, 15) {
+            let signs: Vec<f32> = row.iter().map(|v| if *v > 0.0 { 1.0 } else { -1.0 }).collect();
+            let exact = 1.0 - dot(&query, &normalize(&signs));
+            let approx = Quantization::Binary.distance(Metric::Cosine, &query, &encoded(Quantization::Binary, &row));
+            assert!((approx - exact).abs() < 1e-5, "{} vs {}", approx, exact);
+        }
+    }
+
+    #[test]
+    fn test_binary_needs_cosine() {
+        assert!(Quantization::Binary.check(Metric::Cosine).is_ok());
+        assert!(Quantization::Binary.check(Metric::Dot).is_err());
+        assert!(Quantization::Int8.check(Metric::Euclidean).is_ok());
+        assert_eq!(Quantization::parse("int8"), Ok(Quantization::Int8));
+        assert!(Quantization::parse("int4").is_err());
+    }
+}
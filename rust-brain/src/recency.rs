@@ -0,0 +1,106 @@
+// Recency weighting for ranking (`recency` in cosine_rank and store_search)
+// Adds a boost that halves every `half_life` seconds of an entry's age to its similarity score:
+//
+//   {"recency": {"half_life": 86400, "weight": 0.1, "now": 1718003600}}
+//   score = similarity + weight * 0.5 ^ (age / half_life)
+//
+// A fresh entry gains up to `weight`, so recency only reorders matches whose similarities are within
+// about that much of each other - a much closer old match still wins. Ages come from the entry's
+// `timestamp` (unix seconds, as in filter.rs); an entry without one gets no boost, and one dated in the
+// future counts as brand new. `now` defaults to the current time.
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Matches a search fetches per requested result, so boosted ones further down can move up
+pub const POOL_FACTOR: usize = 4;
+
+fn default_half_life() -> f64 {
+    7.0 * 86400.0
+}
+
+fn default_weight() -> f32 {
+    0.1
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Recency {
+    #[serde(default = "default_half_life")]
+    pub half_life: f64, // seconds
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+    pub now: Option<f64>,
+}
+
+impl Recency {
+    /// The payload's `recency` object (absent = rank by similarity alone)
+    pub fn from_payload(payload: &Value) -> Result<Option<Recency>, String> {
+        let mut recency: Recency = match payload.get("recency") {
+            None | Some(Value::Null) => return Ok(None),
+            Some(value) => serde_json::from_value(value.clone()).map_err(|e| format!("Invalid recency: {}", e))?,
+        };
+        if !(recency.half_life > 0.0 && recency.half_life.is_finite()) {
+            return Err(format!("'recency.half_life' must be a positive number of seconds, got {}", recency.half_life));
+        }
+        if !(recency.weight >= 0.0 && recency.weight.is_finite()) {
+            return Err(format!("'recency.weight' must not be negative, got {}", recency.weight));
+        }
+        if recency.now.is_none() {
+            recency.now = Some(SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0));
+        }
+        Ok(Some(recency))
+    }
+
+    /// Boost for an entry stamped `timestamp`
+    pub fn boost(&self, timestamp: Option<f64>) -> f32 {
+        let Some(timestamp) = timestamp else { return 0.0 };
+        let age = (self.now.unwrap_or(timestamp) - timestamp).max(0.0);
+        self.weight * 0.5f64.powf(age / self.half_life) as f32
+    }
+
+    /// `similarity` blended with the age of an entry carrying `metadata`
+    pub fn score(&self, similarity: f32, metadata: &Value) -> f32 {
+        similarity + self.boost(metadata.get("timestamp").and_then(|v| v.as_f64()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const DAY: f64 = 86400.0;
+
+    fn recency(value: Value) -> Result<Option<Recency>, String> {
+        Recency::from_payload(&json!({ "recency": value }))
+    }
+
+    #[test]
+    fn test_boost_halves_every_half_life() {
+        let recency = recency(json!({ "half_life": DAY, "weight": 0.2, "now": 10.0 * DAY })).unwrap().unwrap();
+        assert!((recency.boost(Some(10.0 * DAY)) - 0.2).abs() < 1e-6);
+        assert!((recency.boost(Some(9.0 * DAY)) - 0.1).abs() < 1e-6);
+        assert!((recency.boost(Some(8.0 * DAY)) - 0.05).abs() < 1e-6);
+        assert!((recency.boost(Some(11.0 * DAY)) - 0.2).abs() < 1e-6); // future counts as new
+        assert_eq!(recency.boost(None), 0.0);
+
+        // Only close matches are reordered
+        let (fresh, old) = (json!({ "timestamp": 10.0 * DAY }), json!({ "timestamp": 0.0 }));
+        assert!(recency.score(0.80, &fresh) > recency.score(0.85, &old));
+        assert!(recency.score(0.60, &fresh) < recency.score(0.85, &old));
+        assert_eq!(recency.score(0.5, &json!({})), 0.5);
+    }
+
+    #[test]
+    fn test_payload_defaults_and_validation() {
+        assert!(Recency::from_payload(&json!({})).unwrap().is_none());
+        let defaults = recency(json!({})).unwrap().unwrap();
+        assert_eq!((defaults.half_life, defaults.weight), (7.0 * DAY, 0.1));
+        assert!(defaults.now.is_some_and(|now| now > 0.0));
+
+        assert!(recency(json!({ "half_life": 0 })).is_err());
+        assert!(recency(json!({ "weight": -0.1 })).is_err());
+        assert!(recency(json!({ "halflife": DAY })).is_err());
+    }
+}
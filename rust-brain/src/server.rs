@@ -0,0 +1,90 @@
+// Socket mode (`rust-brain serve [socket]`)
+// Listens on a Unix socket and answers each connection exactly like the executor does (archy-protocol):
+// one JSON request in, one reply out, connection closed. Every connection gets its own thread - store
+// writers still take the store lock, so concurrent requests on one store queue up instead of racing.
+// Requests are the same as on stdin, except `embed_stream`, which needs its items on stdin.
+use archy_protocol::ErrorKind;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::time::Duration;
+use crate::Response;
+
+pub const DEFAULT_SOCKET: &str = "/tmp/archy-brain.sock";
+/// Embedding batches and vector upserts run large
+const MAX_REQUEST_BYTES: usize = 64 * 1024 * 1024;
+const READ_CHUNK: usize = 64 * 1024;
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn serve(path: &Path) -> io::Result<()> {
+    // A socket left behind by an earlier run
+    let _ = fs::remove_file(path);
+    let listener = UnixListener::bind(path)?;
+    eprintln!("rust-brain: listening on {}", path.display());
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                std::thread::spawn(move || {
+                    if let Err(e) = serve_client(stream) {
+                        eprintln!("rust-brain: client error: {}", e);
+                    }
+                });
+            }
+            Err(e) => eprintln!("rust-brain: connection failed: {}", e),
+        }
+    }
+    Ok(())
+}
+
+fn serve_client(mut stream: UnixStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    let reply = match archy_protocol::read_request(&mut stream, READ_CHUNK, MAX_REQUEST_BYTES) {
+        Ok(request) => crate::answer(request),
+        // The peer is gone - nobody to reply to
+        Err(e) if e.kind == ErrorKind::Io => return Err(io::Error::other(e.message)),
+        Err(e) => Response::error(e.message).to_wire(None, Some(e.kind), None),
+    };
+    stream.write_all(reply.as_bytes())?;
+    stream.flush()?;
+    let _ = stream.shutdown(std::net::Shutdown::Both);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_one_request_one_reply() {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || serve_client(server));
+
+        // The client keeps its side open - the request ends when it parses
+        client.write_all(br#"{"task": "health", "id": 9}"#).unwrap();
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).unwrap();
+        handle.join().unwrap().unwrap();
+
+        let reply: serde_json::Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!((reply["status"].as_str(), reply["id"].as_u64()), (Some("ok"), Some(9)));
+    }
+
+    #[test]
+    fn test_client_leaving_early_is_an_error_not_a_hang() {
+        // Half a request, then gone before the reply
+        let (mut client, server) = UnixStream::pair().unwrap();
+        client.write_all(br#"{"task": "embed_te"#).unwrap();
+        drop(client);
+        assert!(serve_client(server).is_err());
+
+        // Gone before sending anything
+        let (client, server) = UnixStream::pair().unwrap();
+        drop(client);
+        assert!(serve_client(server).is_err());
+    }
+}
@@ -0,0 +1,236 @@
+// Streaming embeddings (`embed_stream`)
+// The request is the first line of stdin; every following line is one item - a JSON string, or
+// {"id": ..., "text": ..., "metadata": ...}. Items are embedded `chunk_size` at a time and each chunk's
+// reply is written (and flushed) before more input is read, so memory stays bounded and a slow reader
+// holds the worker back through the pipe instead of letting output pile up.
+//
+// Per chunk:  {"status":"ok","offset":0,"embeddings":[[...], ...]}   (or "stored": n with a store)
+// At the end: {"status":"done","count":N,"backend":"hash","dim":128}
+// A bad line ends the stream with {"status":"error","offset":N,"error":"..."}.
+//
+// With "store": true (or a store path) items go straight into the vector store - they need an `id`,
+// and embeddings are left out of the replies unless "include_embeddings" is set. The store is saved
+// once, after the last chunk.
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, Write};
+use crate::embedder;
+
+pub const DEFAULT_CHUNK_SIZE: usize = 256;
+
+/// Serialized directly, so vectors keep their f32 formatting
+#[derive(Serialize)]
+struct ChunkReply<'a> {
+    status: &'static str,
+    offset: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stored: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    embeddings: Option<&'a [Vec<f32>]>,
+}
+
+struct Item {
+    id: Option<String>,
+    text: String,
+    metadata: Value,
+}
+
+fn parse_item(line: &str) -> Result<Item, String> {
+    match serde_json::from_str::<Value>(line).map_err(|e| format!("Invalid JSON: {}", e))? {
+        Value::String(text) => Ok(Item { id: None, text, metadata: Value::Null }),
+        Value::Object(mut map) => {
+            let text = match map.remove("text") {
+                Some(Value::String(text)) => text,
+                _ => return Err("Item has no 'text'".to_string()),
+            };
+            let id = match map.remove("id") {
+                None | Some(Value::Null) => None,
+                Some(Value::String(id)) => Some(id),
+                Some(other) => Some(other.to_string()),
+            };
+            Ok(Item { id, text, metadata: map.remove("metadata").unwrap_or(Value::Null) })
+        }
+        _ => Err("Item must be a string or an object".to_string()),
+    }
+}
+
+fn emit(out: &mut impl Write, value: &Value) -> io::Result<()> {
+    writeln!(out, "{}", value)?;
+    out.flush()
+}
+
+/// Serve an `embed_stream` request whose items follow on `input`
+pub fn run(payload: &Value, input: impl BufRead, out: &mut impl Write) -> io::Result<()> {
+    let chunk_size = payload.get("chunk_size")
+        .and_then(|v| v.as_u64())
+        .map(|n| (n as usize).max(1))
+        .unwrap_or(DEFAULT_CHUNK_SIZE);
+    let backend = payload.get("backend").and_then(|v| v.as_str());
+    let include_embeddings = payload.get("include_embeddings").and_then(|v| v.as_bool());
+
+    let mut store = match payload.get("store") {
+        None | Some(Value::Null) | Some(Value::Bool(false)) => None,
+        Some(_) => match crate::open_for_write(payload) {
+            Ok(store) => Some(store),
+            Err(e) => return emit(out, &json!({"status": "error", "offset": 0, "error": e})),
+        },
+    };
+    let dim = match (payload.get("dim").and_then(|v| v.as_u64()), &store) {
+        (Some(dim), _) => dim as usize,
+        (None, Some(store)) if store.dim() > 0 => store.dim(),
+        _ => 128,
+    };
+    let include_embeddings = include_embeddings.unwrap_or(store.is_none());
+
+    let mut offset = 0;
+    let mut last: Option<(&'static str, usize)> = None;
+    let mut chunk: Vec<Item> = Vec::with_capacity(chunk_size);
+    let mut lines = input.lines();
+
+    loop {
+        let line = lines.next().transpose()?;
+        let done = line.is_none();
+        if let Some(line) = line.filter(|l| !l.trim().is_empty()) {
+            match parse_item(&line) {
+                Ok(item) => chunk.push(item),
+                Err(e) => return emit(out, &json!({"status": "error", "offset": offset + chunk.len(), "error": e})),
+            }
+        }
+        if chunk.len() < chunk_size && !done {
+            continue;
+        }
+
+        if !chunk.is_empty() {
+            let texts: Vec<&str> = chunk.iter().map(|item| item.text.as_str()).collect();
+            let embedded = match embedder::embed(&texts, backend, dim, embedder::DEFAULT_BATCH_SIZE) {
+                Ok(embedded) => embedded,
+                Err(e) => return emit(out, &json!({"status": "error", "offset": offset, "error": e})),
+            };
+            last = Some((embedded.backend, embedded.dim));
+
+            let mut reply = ChunkReply { status: "ok", offset, stored: None, embeddings: None };
+            if let Some(store) = store.as_mut() {
+                for (index, (item, vector)) in chunk.iter().zip(&embedded.vectors).enumerate() {
+                    let stored = match &item.id {
+                        Some(id) => store.upsert(id, vector, item.metadata.clone())
+                            .map(|_| store.index_text(id, &item.text)),
+                        None => Err("Items written to the store need an 'id'".to_string()),
+                    };
+                    if let Err(e) = stored {
+                        return emit(out, &json!({"status": "error", "offset": offset + index, "error": e}));
+                    }
+                }
+                reply.stored = Some(chunk.len());
+            }
+            if include_embeddings {
+                reply.embeddings = Some(&embedded.vectors);
+            }
+            writeln!(out, "{}", serde_json::to_string(&reply).map_err(io::Error::other)?)?;
+            out.flush()?;
+
+            offset += chunk.len();
+            chunk.clear();
+        }
+        if done {
+            break;
+        }
+    }
+
+    let mut summary = json!({"status": "done", "count": offset});
+    if let Some((backend, dim)) = last {
+        summary["backend"] = json!(backend);
+        summary["dim"] = json!(dim);
+    }
+    if let Some(store) = store {
+        summary["stored_total"] = json!(store.len());
+        if let Err(e) = crate::maintenance::save(store) {
+            return emit(out, &json!({"status": "error", "offset": offset, "error": e}));
+        }
+    }
+    emit(out, &summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Every reply line, parsed - each must be a complete JSON object on its own line
+    fn reply_lines(out: &[u8]) -> Vec<Value> {
+        let text = std::str::from_utf8(out).unwrap();
+        assert!(text.is_empty() || text.ends_with('\n'));
+        text.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+
+    /// Accepts `lines` writes, then fails like a pipe whose reader went away
+    struct Closing {
+        lines: usize,
+        written: Vec<u8>,
+    }
+
+    impl Write for Closing {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.lines == 0 {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "reader gone"));
+            }
+            self.written.extend_from_slice(buf);
+            self.lines -= buf.iter().filter(|&&b| b == b'\n').count();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_chunks_are_framed_one_per_line() {
+        let input = "\"one\"\n\"two\"\n\n{\"id\": 3, \"text\": \"three\"}\n\"four\"\n\"five\"\n";
+        let mut out = Vec::new();
+        run(&json!({"chunk_size": 2, "dim": 16}), Cursor::new(input), &mut out).unwrap();
+
+        let replies = reply_lines(&out);
+        assert_eq!(replies.len(), 4);
+        for (reply, (offset, size)) in replies.iter().zip([(0, 2), (2, 2), (4, 1)]) {
+            assert_eq!(reply["status"], "ok");
+            assert_eq!(reply["offset"], offset);
+            let embeddings = reply["embeddings"].as_array().unwrap();
+            assert_eq!(embeddings.len(), size);
+            assert!(embeddings.iter().all(|e| e.as_array().unwrap().len() == 16));
+        }
+        assert_eq!(replies[3], json!({"status": "done", "count": 5, "backend": "hash", "dim": 16}));
+
+        // No items: only the summary
+        let mut out = Vec::new();
+        run(&json!({}), Cursor::new(""), &mut out).unwrap();
+        assert_eq!(reply_lines(&out), vec![json!({"status": "done", "count": 0})]);
+    }
+
+    #[test]
+    fn test_bad_item_ends_the_stream_at_its_offset() {
+        let mut out = Vec::new();
+        run(&json!({"chunk_size": 2}), Cursor::new("\"a\"\n\"b\"\n\"c\"\n{\"id\": 4}\n\"e\"\n"), &mut out).unwrap();
+        let replies = reply_lines(&out);
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0]["offset"], 0);
+        assert_eq!((replies[1]["status"].as_str(), replies[1]["offset"].as_u64()), (Some("error"), Some(3)));
+        assert_eq!(replies[1]["error"], "Item has no 'text'");
+
+        let mut out = Vec::new();
+        run(&json!({}), Cursor::new("not json\n"), &mut out).unwrap();
+        assert!(reply_lines(&out)[0]["error"].as_str().unwrap().starts_with("Invalid JSON"));
+    }
+
+    #[test]
+    fn test_reader_going_away_stops_the_stream() {
+        let items: String = (0..10).map(|i| format!("\"item {}\"\n", i)).collect();
+        let mut input = Cursor::new(items.clone());
+        let mut out = Closing { lines: 1, written: Vec::new() };
+        let e = run(&json!({"chunk_size": 2}), &mut input, &mut out).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::BrokenPipe);
+
+        // The first chunk got out whole, and the rest of the input was never read
+        assert_eq!(reply_lines(&out.written).len(), 1);
+        assert!((input.position() as usize) < items.len());
+    }
+}
@@ -0,0 +1,176 @@
+// Per-task telemetry (`telemetry`)
+// Every request is timed. The process keeps counters per task - requests, failures, work items and time
+// spent - which `telemetry` reports; that suits `rust-brain serve`, which lives across requests. A
+// one-shot stdin worker forgets them on exit, so with RUST_BRAIN_TELEMETRY_LOG set each request is also
+// appended there as a JSON line, and `telemetry` with "log": true totals that file instead.
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static STATS: Mutex<BTreeMap<String, TaskStats>> = Mutex::new(BTreeMap::new());
+
+/// One request, as logged
+#[derive(Serialize, Deserialize)]
+struct Sample {
+    ts_ms: u64,
+    task: String,
+    elapsed_ms: f64,
+    ok: bool,
+    items: usize,
+}
+
+#[derive(Default)]
+struct TaskStats {
+    requests: u64,
+    errors: u64,
+    items: u64,
+    total_ms: f64,
+    max_ms: f64,
+}
+
+impl TaskStats {
+    fn add(&mut self, elapsed_ms: f64, ok: bool, items: usize) {
+        self.requests += 1;
+        self.errors += u64::from(!ok);
+        self.items += items as u64;
+        self.total_ms += elapsed_ms;
+        self.max_ms = self.max_ms.max(elapsed_ms);
+    }
+
+    fn to_json(&self) -> Value {
+        serde_json::json!({
+            "requests": self.requests,
+            "errors": self.errors,
+            "items": self.items,
+            "mean_ms": self.total_ms / self.requests.max(1) as f64,
+            "max_ms": self.max_ms,
+            "items_per_sec": if self.total_ms > 0.0 { self.items as f64 * 1000.0 / self.total_ms } else { 0.0 },
+        })
+    }
+}
+
+/// Units of work in a request: its texts, items, vectors, candidates or ids - else 1
+pub fn work_items(payload: &Value) -> usize {
+    ["texts", "items", "vectors", "candidates", "ids"].iter()
+        .find_map(|key| payload.get(key).and_then(|v| v.as_array()))
+        .map_or(1, |list| list.len())
+}
+
+/// Count a finished request
+pub fn record(task: &str, elapsed_ms: f64, ok: bool, items: usize) {
+    STATS.lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(task.to_string())
+        .or_default()
+        .add(elapsed_ms, ok, items);
+
+    let path = match std::env::var("RUST_BRAIN_TELEMETRY_LOG") {
+        Ok(path) if !path.is_empty() => path,
+        _ => return,
+    };
+    let sample = Sample {
+        ts_ms: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0),
+        task: task.to_string(),
+        elapsed_ms,
+        ok,
+        items,
+    };
+    // Telemetry never fails a request
+    let written = serde_json::to_string(&sample).map_err(std::io::Error::other).and_then(|line| {
+        OpenOptions::new().create(true).append(true).open(&path)?.write_all(format!("{}\n", line).as_bytes())
+    });
+    if let Err(e) = written {
+        eprintln!("rust-brain: cannot write telemetry to {}: {}", path, e);
+    }
+}
+
+/// Counters per task from telemetry log lines
+fn totals(log: &str) -> BTreeMap<String, TaskStats> {
+    let mut stats: BTreeMap<String, TaskStats> = BTreeMap::new();
+    // A torn last line (a worker killed mid-write) is skipped
+    for sample in log.lines().filter_map(|line| serde_json::from_str::<Sample>(line).ok()) {
+        stats.entry(sample.task).or_default().add(sample.elapsed_ms, sample.ok, sample.items);
+    }
+    stats
+}
+
+/// Counters per task - this process's, or totalled from the telemetry log
+pub fn report(from_log: bool) -> Result<Value, String> {
+    let tasks: BTreeMap<String, Value> = if from_log {
+        let path = std::env::var("RUST_BRAIN_TELEMETRY_LOG")
+            .map_err(|_| "RUST_BRAIN_TELEMETRY_LOG is not set".to_string())?;
+        let log = fs::read_to_string(&path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+        totals(&log).iter().map(|(task, s)| (task.clone(), s.to_json())).collect()
+    } else {
+        STATS.lock().unwrap_or_else(|e| e.into_inner()).iter().map(|(task, s)| (task.clone(), s.to_json())).collect()
+    };
+    Ok(serde_json::json!({"source": if from_log { "log" } else { "process" }, "tasks": tasks}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn counter(task: &str, key: &str) -> u64 {
+        report(false).unwrap()["tasks"][task][key].as_u64().unwrap_or(0)
+    }
+
+    fn answer(request: Value) -> Value {
+        serde_json::from_str(&crate::answer(serde_json::from_value(request).unwrap())).unwrap()
+    }
+
+    #[test]
+    fn test_counters_after_a_request() {
+        // Only this test sends embed_texts through answer()
+        let [requests, errors, items] = ["requests", "errors", "items"].map(|key| counter("embed_texts", key));
+
+        let reply = answer(json!({"task": "embed_texts", "payload": {"texts": ["a", "b", "c"], "backend": "hash", "dim": 8}}));
+        assert_eq!(reply["status"], "ok");
+        assert!(reply["elapsed_ms"].as_f64().is_some());
+        assert_eq!(answer(json!({"task": "embed_texts", "payload": {}}))["status"], "error");
+
+        assert_eq!(counter("embed_texts", "requests"), requests + 2);
+        assert_eq!(counter("embed_texts", "errors"), errors + 1);
+        assert_eq!(counter("embed_texts", "items"), items + 3 + 1); // no texts counts as one item
+
+        // Unknown tasks never get a row
+        answer(json!({"task": "no_such_task"}));
+        assert!(report(false).unwrap()["tasks"].get("no_such_task").is_none());
+    }
+
+    #[test]
+    fn test_stats_and_log_totals() {
+        let mut stats = TaskStats::default();
+        stats.add(10.0, true, 4);
+        stats.add(30.0, false, 6);
+        assert_eq!(
+            stats.to_json(),
+            json!({"requests": 2, "errors": 1, "items": 10, "mean_ms": 20.0, "max_ms": 30.0, "items_per_sec": 250.0})
+        );
+        assert_eq!(TaskStats::default().to_json()["items_per_sec"], 0.0);
+
+        let log = [
+            r#"{"ts_ms": 1, "task": "cosine_rank", "elapsed_ms": 2.0, "ok": true, "items": 50}"#,
+            r#"{"ts_ms": 2, "task": "cosine_rank", "elapsed_ms": 4.0, "ok": true, "items": 30}"#,
+            r#"{"ts_ms": 3, "task": "store_search", "elapsed_ms": 1.0, "ok": false, "items": 1}"#,
+            r#"{"ts_ms": 4, "task": "cosine_ra"#,
+        ].join("\n");
+        let totals = totals(&log);
+        assert_eq!(totals.len(), 2);
+        assert_eq!((totals["cosine_rank"].requests, totals["cosine_rank"].items), (2, 80));
+        assert_eq!(totals["store_search"].errors, 1);
+    }
+
+    #[test]
+    fn test_work_items() {
+        assert_eq!(work_items(&json!({"texts": ["a", "b"]})), 2);
+        assert_eq!(work_items(&json!({"ids": []})), 0);
+        assert_eq!(work_items(&json!({"query": "x"})), 1);
+        assert_eq!(work_items(&json!({"texts": "not a list", "candidates": [1, 2, 3]})), 3);
+    }
+}
@@ -0,0 +1,464 @@
+// Fragment validation (`validate_fragment`)
+// A fragment is scored by independent rules, each 0..1, combined as a weighted mean:
+//   length     too short to carry anything, or too long to be one fragment
+//   language   what the text is (shell, python, rust, json, yaml, prose) - unrecognizable text scores low
+//   entropy    garbage detection: character entropy, printable and alphanumeric share, repeated runs,
+//              long random-looking tokens (keys, encoded blobs)
+//   safety     commands in the fragment judged by the executor's own policy (`archy-executor classify`,
+//              found through ARCHY_EXECUTOR_BIN or PATH), plus phrases that read like an attack
+//   duplicate  similarity to the closest entry already in the store (looked up by the caller)
+// An empty fragment, one outside the length limits or one with a blocked command fails outright.
+// Weights come from the payload's "weights" ({"safety": 3.0, ...}; 0 turns a rule off).
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+const MIN_LENGTH: usize = 10;
+const MAX_LENGTH: usize = 10000;
+
+/// Most commands sent to the executor per fragment
+const MAX_COMMANDS: usize = 50;
+
+/// Similarity from which a fragment starts to count as a near-duplicate, and where it fully is
+const SIMILAR_FROM: f32 = 0.8;
+pub const DEFAULT_DUPLICATE_THRESHOLD: f32 = 0.95;
+
+const DEFAULT_WEIGHTS: [(&str, f32); 5] = [
+    ("length", 1.0),
+    ("language", 1.0),
+    ("entropy", 1.5),
+    ("safety", 2.0),
+    ("duplicate", 1.0),
+];
+
+/// Phrases that make a fragment suspect whatever commands it holds
+const SUSPICIOUS_PHRASES: &[&str] = &["exfiltrat", "bypass", "reverse shell", "/dev/tcp/", "disable selinux"];
+
+#[derive(Serialize)]
+pub struct RuleResult {
+    pub rule: &'static str,
+    pub score: f32,
+    pub weight: f32,
+    pub detail: String,
+}
+
+pub struct Report {
+    pub score: f32,
+    pub language: &'static str,
+    pub length_ok: bool,
+    pub has_content: bool,
+    pub suspicious: bool,
+    pub policy: &'static str, // who judged the commands: "executor", "builtin" or "none"
+    pub commands: Vec<Value>,
+    pub duplicate_of: Option<String>, // stored entry at or above the duplicate threshold
+    pub rules: Vec<RuleResult>,
+}
+
+/// Run every rule over `text` - `closest` is the most similar stored entry, when there is a store
+pub fn validate(text: &str, closest: Option<(String, f32)>, duplicate_threshold: f32, weights: &HashMap<String, f32>) -> Report {
+    let weight = |rule: &str| {
+        weights.get(rule).copied().unwrap_or_else(|| {
+            DEFAULT_WEIGHTS.iter().find(|(name, _)| *name == rule).map_or(1.0, |(_, w)| *w)
+        })
+    };
+    let has_content = !text.trim().is_empty();
+    let length_ok = (MIN_LENGTH..=MAX_LENGTH).contains(&text.len());
+    let (language, language_confidence) = detect_language(text);
+
+    let mut rules = vec![
+        RuleResult {
+            rule: "length",
+            score: if length_ok { (text.len() as f32 / 512.0).min(1.0) * 0.8 + 0.2 } else { 0.0 },
+            weight: weight("length"),
+            detail: format!("{} bytes (allowed {}..{})", text.len(), MIN_LENGTH, MAX_LENGTH),
+        },
+        RuleResult {
+            rule: "language",
+            score: if language == "unknown" { 0.3 } else { 0.6 + 0.4 * language_confidence },
+            weight: weight("language"),
+            detail: format!("{} (confidence {:.2})", language, language_confidence),
+        },
+        entropy_rule(text, weight("entropy")),
+    ];
+
+    let commands = extract_commands(text, language);
+    let (policy, verdicts) = judge_commands(&commands);
+    let lower = text.to_lowercase();
+    let phrases: Vec<&str> = SUSPICIOUS_PHRASES.iter().copied().filter(|p| lower.contains(p)).collect();
+    let blocked: Vec<&str> = verdicts.iter()
+        .filter(|v| v["allowed"] == false)
+        .filter_map(|v| v["blocked"].as_str())
+        .collect();
+    let (safety, safety_detail) = safety_score(&verdicts, &blocked, &phrases);
+    rules.push(RuleResult { rule: "safety", score: safety, weight: weight("safety"), detail: safety_detail });
+
+    let mut duplicate_of = None;
+    if let Some((id, similarity)) = closest {
+        let score = if similarity >= duplicate_threshold {
+            duplicate_of = Some(id.clone());
+            0.0
+        } else {
+            1.0 - ((similarity - SIMILAR_FROM) / (duplicate_threshold - SIMILAR_FROM)).clamp(0.0, 1.0)
+        };
+        rules.push(RuleResult {
+            rule: "duplicate",
+            score,
+            weight: weight("duplicate"),
+            detail: format!("closest stored entry '{}' at {:.3} (duplicate from {:.2})", id, similarity, duplicate_threshold),
+        });
+    }
+
+    let fatal = !has_content || !length_ok || !blocked.is_empty();
+    let total_weight: f32 = rules.iter().map(|r| r.weight.max(0.0)).sum();
+    let score = if fatal || total_weight == 0.0 {
+        0.0
+    } else {
+        rules.iter().map(|r| r.score * r.weight.max(0.0)).sum::<f32>() / total_weight
+    };
+
+    Report {
+        score,
+        language,
+        length_ok,
+        has_content,
+        suspicious: !phrases.is_empty() || !blocked.is_empty(),
+        policy,
+        commands: verdicts,
+        duplicate_of,
+        rules,
+    }
+}
+
+/// Best-matching language and how strongly it matched (0..1)
+fn detect_language(text: &str) -> (&'static str, f32) {
+    let trimmed = text.trim();
+    if (trimmed.starts_with('{') || trimmed.starts_with('[')) && serde_json::from_str::<Value>(trimmed).is_ok() {
+        return ("json", 1.0);
+    }
+    let lines: Vec<&str> = trimmed.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with("```")).collect();
+    if lines.is_empty() {
+        return ("unknown", 0.0);
+    }
+
+    let share = |hit: &dyn Fn(&str) -> bool| lines.iter().filter(|l| hit(l)).count() as f32 / lines.len() as f32;
+    let first_word = |line: &str| line.trim_start_matches("$ ").split_whitespace().next().unwrap_or("").to_string();
+    let mut code = [
+        ("shell", share(&|l| {
+            l.starts_with("$ ") || l.starts_with("#!/bin/") || l.contains(" | ") || l.contains(" && ")
+                || SHELL_COMMANDS.contains(&first_word(l).as_str())
+        })),
+        ("python", share(&|l| {
+            ["def ", "import ", "from ", "class ", "elif ", "return ", "print("].iter().any(|k| l.starts_with(k))
+                || l.contains("self.") || (l.ends_with(':') && !l.contains(": "))
+        })),
+        ("rust", share(&|l| {
+            ["fn ", "pub ", "let ", "impl ", "use ", "struct ", "enum ", "match ", "#["].iter().any(|k| l.starts_with(k))
+                || l.contains("::") || l.contains("->") || (l.ends_with(';') && l.contains('('))
+        })),
+        ("yaml", share(&|l| {
+            l.starts_with("- ") || l.starts_with('#')
+                || l.split_once(": ").is_some_and(|(key, _)| !key.is_empty() && !key.contains(' '))
+                || (l.ends_with(':') && !l.contains(' '))
+        })),
+    ];
+    code.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let words: Vec<String> = trimmed.split_whitespace().map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase()).collect();
+    let common = words.iter().filter(|w| STOPWORDS.contains(&w.as_str())).count();
+    // Around a third of English words are stopwords - a quarter counts fully
+    let prose = (common as f32 / words.len().max(1) as f32 * 4.0).min(1.0);
+
+    // Code uses plenty of English words too, so mostly-code lines decide first
+    match code[0] {
+        (language, confidence) if confidence >= 0.5 => (language, confidence),
+        _ if prose >= 0.3 => ("prose", prose),
+        (language, confidence) if confidence >= 0.3 => (language, confidence),
+        (_, confidence) => ("unknown", confidence.max(prose)),
+    }
+}
+
+const SHELL_COMMANDS: &[&str] = &[
+    "sudo", "ls", "cd", "cat", "echo", "grep", "find", "git", "docker", "systemctl", "journalctl", "pacman",
+    "apt", "yay", "curl", "wget", "export", "cp", "mv", "rm", "mkdir", "chmod", "chown", "tar", "ssh", "make",
+    "cargo", "npm", "pip", "kubectl", "ps", "kill", "tail", "head", "sed", "awk", "ip", "ss", "tmux",
+];
+
+const STOPWORDS: &[&str] = &[
+    "the", "a", "an", "and", "or", "of", "to", "in", "is", "it", "for", "on", "with", "as", "be", "this",
+    "that", "are", "was", "by", "not", "if", "when", "from", "you", "can", "use", "your", "then", "will",
+];
+
+/// Shannon entropy of a character sequence, bits per character
+fn entropy(chars: impl Iterator<Item = char>) -> f32 {
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    let mut total = 0;
+    for c in chars {
+        *counts.entry(c).or_default() += 1;
+        total += 1;
+    }
+    counts.values()
+        .map(|&n| {
+            let p = n as f32 / total as f32;
+            p * (1.0 / p).log2()
+        })
+        .sum()
+}
+
+fn entropy_rule(text: &str, weight: f32) -> RuleResult {
+    let total = text.chars().count().max(1) as f32;
+    let bits = entropy(text.chars());
+    let printable = text.chars().filter(|c| !c.is_control() || "\n\t\r".contains(*c)).count() as f32 / total;
+    let wordlike = text.chars().filter(|c| c.is_alphanumeric() || c.is_whitespace()).count() as f32 / total;
+    let longest_run = text.chars()
+        .fold((None, 0, 0), |(last, run, longest), c| {
+            let run = if last == Some(c) { run + 1 } else { 1 };
+            (Some(c), run, longest.max(run))
+        })
+        .2;
+    // Long tokens mixing letters and digits at near-random entropy: keys, hashes, base64
+    let random_tokens = text.split_whitespace()
+        .filter(|t| t.len() >= 24 && entropy(t.chars()) >= 4.2)
+        .filter(|t| t.chars().any(|c| c.is_ascii_digit()) && t.chars().any(|c| c.is_ascii_alphabetic()))
+        .count();
+
+    let mut score = 1.0f32;
+    let mut issues = Vec::new();
+    if printable < 0.95 {
+        score *= printable;
+        issues.push(format!("{:.0}% control characters", (1.0 - printable) * 100.0));
+    }
+    if wordlike < 0.5 {
+        score *= wordlike / 0.5;
+        issues.push(format!("only {:.0}% letters, digits and spaces", wordlike * 100.0));
+    }
+    if total >= 20.0 && bits < 2.5 {
+        score *= bits / 2.5;
+        issues.push(format!("repetitive ({:.2} bits/char)", bits));
+    }
+    if bits > 5.5 {
+        score *= (7.0 - bits).max(0.0) / 1.5;
+        issues.push(format!("near-random ({:.2} bits/char)", bits));
+    }
+    if longest_run > 20 {
+        score *= 0.5;
+        issues.push(format!("a character repeated {} times", longest_run));
+    }
+    if random_tokens > 0 {
+        score *= 0.5;
+        issues.push(format!("{} random-looking token(s) - keys or encoded data?", random_tokens));
+    }
+    let detail = if issues.is_empty() {
+        format!("{:.2} bits/char", bits)
+    } else {
+        issues.join("; ")
+    };
+    RuleResult { rule: "entropy", score: score.clamp(0.0, 1.0), weight, detail }
+}
+
+/// Command lines in a fragment: fenced code blocks, `$ ` prompts, or every line of a shell fragment
+fn extract_commands(text: &str, language: &str) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut in_fence = false;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with("```") {
+            in_fence = !in_fence;
+            continue;
+        }
+        let command = if let Some(command) = line.strip_prefix("$ ") {
+            command
+        } else if (in_fence || language == "shell") && !line.starts_with('#') {
+            line
+        } else {
+            continue;
+        };
+        if !command.is_empty() {
+            commands.push(command.to_string());
+        }
+    }
+    commands.truncate(MAX_COMMANDS);
+    commands
+}
+
+/// Verdicts from the executor's policy, or the built-in fallback when it can't be run
+fn judge_commands(commands: &[String]) -> (&'static str, Vec<Value>) {
+    if commands.is_empty() {
+        return ("none", Vec::new());
+    }
+    match executor_verdicts(commands) {
+        Ok(verdicts) => ("executor", verdicts),
+        Err(e) => {
+            eprintln!("rust-brain: executor policy unavailable ({}), using built-in checks", e);
+            ("builtin", commands.iter().map(|c| builtin_verdict(c)).collect())
+        }
+    }
+}
+
+fn executor_verdicts(commands: &[String]) -> Result<Vec<Value>, String> {
+    let bin = std::env::var("ARCHY_EXECUTOR_BIN").unwrap_or_else(|_| "archy-executor".to_string());
+    let mut child = Command::new(&bin)
+        .arg("classify")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("{}: {}", bin, e))?;
+    // Newlines inside a command would split it - the executor sees one command per line
+    let input: String = commands.iter().map(|c| format!("{}\n", c.replace('\n', " "))).collect();
+    child.stdin.take().ok_or("no stdin")?.write_all(input.as_bytes()).map_err(|e| e.to_string())?;
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(format!("{} classify exited with {}", bin, output.status));
+    }
+    serde_json::from_slice(&output.stdout).map_err(|e| format!("unreadable verdicts: {}", e))
+}
+
+/// Coarse stand-in for the executor's policy - its blocked patterns and the riskiest binaries only
+fn builtin_verdict(command: &str) -> Value {
+    const BLOCKED: &[&str] = &["rm -rf /", "> /dev/sda", "dd if=/dev/zero of=/dev/sda", "mkfs.", ":(){ :|:& };:"];
+    let lower = command.to_lowercase();
+    let blocked = BLOCKED.iter().find(|p| lower.contains(*p)).map(|p| format!("Blocked dangerous command pattern: {}", p));
+    let words: Vec<&str> = lower.split_whitespace().collect();
+    let class = if words.iter().any(|w| matches!(*w, "sudo" | "doas" | "su" | "pkexec")) {
+        "privileged"
+    } else if words.iter().any(|w| matches!(*w, "rm" | "dd" | "shred" | "wipefs" | "kill" | "pkill" | "reboot" | "shutdown")) {
+        "destructive"
+    } else {
+        "read_only"
+    };
+    json!({"command": command, "allowed": blocked.is_none(), "blocked": blocked, "risk": {"class": class}})
+}
+
+fn safety_score(verdicts: &[Value], blocked: &[&str], phrases: &[&str]) -> (f32, String) {
+    if !blocked.is_empty() {
+        return (0.0, format!("blocked: {}", blocked.join("; ")));
+    }
+    let (mut score, mut worst) = (1.0f32, "read_only");
+    for class in verdicts.iter().filter_map(|v| v["risk"]["class"].as_str()) {
+        let class_score = match class {
+            "modifying" => 0.85,
+            "privileged" => 0.5,
+            "destructive" => 0.4,
+            _ => 1.0,
+        };
+        if class_score < score {
+            (score, worst) = (class_score, class);
+        }
+    }
+    let mut detail = format!("{} command(s), riskiest {}", verdicts.len(), worst);
+    if !phrases.is_empty() {
+        score = score.min(0.2);
+        detail.push_str(&format!("; suspicious: {}", phrases.join(", ")));
+    }
+    (score, detail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROSE: &str = "Restart the service when the config changes, then check the logs for errors.";
+
+    fn rule<'a>(report: &'a Report, name: &str) -> &'a RuleResult {
+        report.rules.iter().find(|r| r.rule == name).unwrap_or_else(|| panic!("no {} rule", name))
+    }
+
+    fn plain(text: &str) -> Report {
+        validate(text, None, DEFAULT_DUPLICATE_THRESHOLD, &HashMap::new())
+    }
+
+    #[test]
+    fn test_length_rule_and_fatal_lengths() {
+        let short = plain("too short");
+        assert!(!short.length_ok && short.has_content);
+        assert_eq!((rule(&short, "length").score, short.score), (0.0, 0.0));
+        assert_eq!(plain(&"word ".repeat(2001)).score, 0.0);
+        assert_eq!(plain("   \n\t   \n ").score, 0.0);
+
+        // Scales up to 512 bytes
+        let minimal = rule(&plain("ten bytes!"), "length").score;
+        assert!((minimal - (0.2 + 0.8 * 10.0 / 512.0)).abs() < 1e-6);
+        assert_eq!(rule(&plain(&PROSE.repeat(8)), "length").score, 1.0);
+    }
+
+    #[test]
+    fn test_language_rule() {
+        let json = plain(r#"{"service": "nginx", "ports": [80, 443]}"#);
+        assert_eq!((json.language, rule(&json, "language").score), ("json", 1.0));
+        assert_eq!(plain("fn main() {\n    let x = Vec::new();\n    println!(\"{:?}\", x);\n}").language, "rust");
+        assert_eq!(plain("def main():\n    import os\n    return os.getcwd()").language, "python");
+        assert_eq!(plain(PROSE).language, "prose");
+
+        let unknown = plain("zq8 xv7 wk1 pj0 yy4 qq3");
+        assert_eq!((unknown.language, rule(&unknown, "language").score), ("unknown", 0.3));
+    }
+
+    #[test]
+    fn test_entropy_rule_flags_garbage() {
+        assert_eq!(rule(&plain(PROSE), "entropy").score, 1.0);
+
+        let repeated = plain(&"a".repeat(40));
+        assert!(rule(&repeated, "entropy").score < 0.2);
+        assert!(rule(&repeated, "entropy").detail.contains("repeated 40 times"));
+
+        let key = plain("token sk9Fq2LmZ7xR4vT1bN8cW3yH6jK0pD5g for the staging api");
+        assert_eq!(rule(&key, "entropy").score, 0.5);
+        assert!(rule(&key, "entropy").detail.contains("random-looking"));
+
+        let control = plain("\u{1}\u{2}\u{3}\u{4} binary header \u{5}\u{6}");
+        assert!(rule(&control, "entropy").detail.contains("control characters"));
+    }
+
+    #[test]
+    fn test_safety_rule() {
+        let verdict = |class: &str| json!({"allowed": true, "risk": {"class": class}});
+        assert_eq!(safety_score(&[], &[], &[]).0, 1.0);
+        assert_eq!(safety_score(&[verdict("read_only"), verdict("modifying")], &[], &[]).0, 0.85);
+        let (score, detail) = safety_score(&[verdict("modifying"), verdict("destructive"), verdict("privileged")], &[], &[]);
+        assert_eq!(score, 0.4);
+        assert!(detail.contains("riskiest destructive"));
+        assert_eq!(safety_score(&[verdict("read_only")], &[], &["bypass"]).0, 0.2);
+        assert_eq!(safety_score(&[verdict("read_only")], &["Blocked: rm -rf /"], &[]).0, 0.0);
+
+        // A blocked command fails the fragment, whichever policy judged it
+        let blocked = plain("Clean up the disk:\n$ rm -rf / --no-preserve-root");
+        assert!(blocked.suspicious && blocked.policy != "none");
+        assert_eq!((rule(&blocked, "safety").score, blocked.score), (0.0, 0.0));
+
+        let builtin = builtin_verdict("sudo systemctl restart nginx");
+        assert_eq!((builtin["allowed"].as_bool(), builtin["risk"]["class"].as_str()), (Some(true), Some("privileged")));
+        assert_eq!(plain(PROSE).policy, "none");
+    }
+
+    #[test]
+    fn test_duplicate_rule() {
+        let duplicate = |similarity: f32| validate(PROSE, Some(("e1".to_string(), similarity)), 0.95, &HashMap::new());
+        let exact = duplicate(0.97);
+        assert_eq!(exact.duplicate_of.as_deref(), Some("e1"));
+        assert_eq!(rule(&exact, "duplicate").score, 0.0);
+        assert!(exact.score > 0.0); // a duplicate scores low, it doesn't fail outright
+
+        assert_eq!(rule(&duplicate(0.5), "duplicate").score, 1.0);
+        assert!((rule(&duplicate(0.875), "duplicate").score - 0.5).abs() < 1e-5);
+        assert!(duplicate(0.875).duplicate_of.is_none());
+        assert!(plain(PROSE).rules.iter().all(|r| r.rule != "duplicate"));
+    }
+
+    #[test]
+    fn test_score_is_the_weighted_mean() {
+        let report = plain(PROSE);
+        let (sum, total) = report.rules.iter().fold((0.0, 0.0), |(sum, total), r| (sum + r.score * r.weight, total + r.weight));
+        assert!((report.score - sum / total).abs() < 1e-6);
+        assert_eq!(rule(&report, "safety").weight, 2.0);
+
+        // Weight 0 turns a rule off; only the length rule left
+        let weights: HashMap<String, f32> = ["language", "entropy", "safety"].iter().map(|r| (r.to_string(), 0.0)).collect();
+        let length_only = validate(PROSE, None, DEFAULT_DUPLICATE_THRESHOLD, &weights);
+        assert!((length_only.score - rule(&length_only, "length").score).abs() < 1e-6);
+
+        let none: HashMap<String, f32> = DEFAULT_WEIGHTS.iter().map(|(r, _)| (r.to_string(), 0.0)).collect();
+        assert_eq!(validate(PROSE, None, DEFAULT_DUPLICATE_THRESHOLD, &none).score, 0.0);
+    }
+}
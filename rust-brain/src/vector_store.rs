@@ -0,0 +1,1034 @@
+// Persistent vector store with an HNSW index
+// A store (one collection) is a directory: `vectors.f32` holds the vectors back to back (native-endian
+// f32, memory-mapped on open; normalized under the cosine metric) and `store.json` the settings, ids,
+// metadata and HNSW graph. A quantized store also keeps
+// an int8 or binary copy that searches walk instead (see quantize.rs). Writers take `store.lock`, so
+// concurrent workers don't interleave.
+//
+// The index is updated in place. An update or delete leaves a tombstone row; before the store is saved
+// every node that linked to a tombstone re-picks its neighbors from its other links and the tombstone's
+// own, so searches never route through deleted entries and nothing has to be rebuilt. Tombstones only
+// cost disk space until a compaction drops them. Repairs are local, so after enough churn a background
+// relink re-selects every node's links against the graph as it is now (see maintenance.rs).
+// Entries stored with text are also in the keyword index (`keywords.json`, see keywords.rs).
+use memmap2::Mmap;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use crate::filter::Filter;
+use crate::keywords::KeywordIndex;
+use crate::metric::Metric;
+use crate::quantize::Quantization;
+
+/// Links per node above layer 0 (layer 0 keeps twice as many)
+const M: usize = 16;
+const EF_CONSTRUCTION: usize = 100;
+pub const DEFAULT_EF: usize = 64;
+const FORMAT_VERSION: u32 = 1;
+
+/// Background compaction is worth it from this many tombstones, once they are a quarter of the graph
+const COMPACT_MIN_TOMBSTONES: usize = 64;
+/// Background relinking is worth it from this many deletes and replacements, once they reach a quarter
+/// of the live entries
+const RELINK_MIN_CHURN: usize = 256;
+/// Tombstones walked through when looking for a repaired node's replacement neighbors
+const REPAIR_EXPANSION: usize = 64;
+
+/// Every file a store can consist of - store.json last, it is what makes the others current
+pub const STORE_FILES: [&str; 5] = ["vectors.f32", "vectors.i8", "vectors.bit", "keywords.json", "store.json"];
+
+#[derive(Serialize, Deserialize)]
+struct StoreFile {
+    version: u32,
+    dim: usize,
+    #[serde(default)]
+    metric: Metric,
+    #[serde(default)]
+    quantization: Quantization,
+    entry: Option<u32>,
+    max_level: usize,
+    #[serde(default)]
+    churn: usize, // deletes and replacements since the graph was last relinked
+    nodes: Vec<Node>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Node {
+    id: String,
+    #[serde(default)]
+    metadata: Value,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    deleted: bool,
+    links: Vec<Vec<u32>>, // neighbors per layer, 0..=level
+}
+
+/// One search hit
+#[derive(Debug, Serialize)]
+pub struct Match {
+    pub id: String,
+    pub score: f32, // larger is closer, see metric.rs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub similarity: Option<f32>, // the score before recency weighting, when there was some
+    pub metadata: Value,
+}
+
+/// Index health, from `health`
+#[derive(Debug, Serialize)]
+pub struct IndexHealth {
+    pub live: usize,
+    pub tombstones: usize,
+    pub linked_tombstones: usize,      // tombstones a search can still walk into
+    pub max_level: usize,
+    pub layer_sizes: Vec<usize>,       // live nodes per layer
+    pub mean_degree: f32,              // layer-0 links per live node
+    pub unlinked: usize,               // live nodes without layer-0 links
+    pub reachable: usize,              // live nodes a search can reach from the entry point
+    pub connectivity: f32,             // reachable / live
+    pub recall_estimate: Option<f32>,  // recall@k of stored vectors searched for themselves
+    pub recall_sample: usize,
+    pub churn: usize,
+}
+
+/// Distance-ordered candidate (smaller distance = closer)
+#[derive(Clone, Copy, PartialEq)]
+struct Scored(f32, u32);
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+pub struct VectorStore {
+    dir: PathBuf,
+    dim: usize,
+    metric: Metric,
+    quantization: Quantization,
+    entry: Option<u32>,
+    max_level: usize,
+    churn: usize,
+    repair_pending: bool, // tombstoned since open, links not yet repaired
+    nodes: Vec<Node>,
+    live: HashMap<String, u32>,
+    mapped: Option<Mmap>,
+    rows_on_disk: usize,
+    pending: Vec<f32>, // rows added since open, not yet in vectors.f32
+    index: Option<Mmap>, // quantized rows, for a quantized store
+    pending_index: Vec<u8>,
+    keywords: KeywordIndex,
+    keywords_changed: bool,
+    _lock: Option<File>,
+}
+
+impl VectorStore {
+    /// Open (or create) the store in `dir` - `write` takes the store's lock until dropped
+    pub fn open(dir: &Path, write: bool) -> Result<VectorStore, String> {
+        if write {
+            fs::create_dir_all(dir).map_err(|e| format!("Cannot create store {}: {}", dir.display(), e))?;
+        }
+        let lock = if write {
+            let file = File::create(dir.join("store.lock")).map_err(|e| format!("Cannot lock store: {}", e))?;
+            file.lock().map_err(|e| format!("Cannot lock store: {}", e))?;
+            Some(file)
+        } else {
+            None
+        };
+
+        let file: StoreFile = match fs::read_to_string(dir.join("store.json")) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| format!("Corrupt store.json: {}", e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => StoreFile {
+                version: FORMAT_VERSION,
+                dim: 0,
+                metric: Metric::Cosine,
+                quantization: Quantization::None,
+                entry: None,
+                max_level: 0,
+                churn: 0,
+                nodes: Vec::new(),
+            },
+            Err(e) => return Err(format!("Cannot read store.json: {}", e)),
+        };
+        if file.version != FORMAT_VERSION {
+            return Err(format!("Unsupported store version {}", file.version));
+        }
+
+        // Rows past the last saved node are leftovers of an interrupted save
+        let rows_on_disk = file.nodes.len();
+        let mapped = map_rows(dir, "vectors.f32", rows_on_disk * file.dim * 4)?;
+        let index = match file.quantization.file_name() {
+            Some(name) => map_rows(dir, name, rows_on_disk * file.quantization.row_bytes(file.dim))?,
+            None => None,
+        };
+
+        let live = file.nodes.iter()
+            .enumerate()
+            .filter(|(_, node)| !node.deleted)
+            .map(|(i, node)| (node.id.clone(), i as u32))
+            .collect();
+
+        Ok(VectorStore {
+            dir: dir.to_path_buf(),
+            dim: file.dim,
+            metric: file.metric,
+            quantization: file.quantization,
+            entry: file.entry,
+            max_level: file.max_level,
+            churn: file.churn,
+            repair_pending: false,
+            nodes: file.nodes,
+            live,
+            mapped,
+            rows_on_disk,
+            pending: Vec::new(),
+            index,
+            pending_index: Vec::new(),
+            keywords: KeywordIndex::load(dir)?,
+            keywords_changed: false,
+            _lock: lock,
+        })
+    }
+
+    /// Start a new store in `dir` with fixed settings (`dim` 0 = taken from the first vector) - written
+    /// on save
+    pub fn create(dir: &Path, dim: usize, metric: Metric, quantization: Quantization) -> Result<VectorStore, String> {
+        quantization.check(metric)?;
+        let mut store = VectorStore::open(dir, true)?;
+        if dir.join("store.json").exists() {
+            return Err(format!("{} already holds a store", dir.display()));
+        }
+        store.dim = dim;
+        store.metric = metric;
+        store.quantization = quantization;
+        Ok(store)
+    }
+
+    /// Scratch store that is never saved (for work on supplied vectors)
+    pub fn in_memory() -> VectorStore {
+        VectorStore {
+            dir: PathBuf::new(),
+            dim: 0,
+            metric: Metric::Cosine,
+            quantization: Quantization::None,
+            entry: None,
+            max_level: 0,
+            churn: 0,
+            repair_pending: false,
+            nodes: Vec::new(),
+            live: HashMap::new(),
+            mapped: None,
+            rows_on_disk: 0,
+            pending: Vec::new(),
+            index: None,
+            pending_index: Vec::new(),
+            keywords: KeywordIndex::default(),
+            keywords_changed: false,
+            _lock: None,
+        }
+    }
+
+    /// Live ids in insertion order
+    pub fn ids(&self) -> Vec<String> {
+        self.nodes.iter().filter(|node| !node.deleted).map(|node| node.id.clone()).collect()
+    }
+
+    /// Stored vector of `id` (normalized under cosine)
+    pub fn get(&self, id: &str) -> Option<&[f32]> {
+        self.live.get(id).map(|&index| self.vector(index))
+    }
+
+    pub fn len(&self) -> usize {
+        self.live.len()
+    }
+
+    /// Deleted or replaced entries still in the graph
+    pub fn tombstones(&self) -> usize {
+        self.nodes.len() - self.live.len()
+    }
+
+    /// Enough tombstones that compacting is worth the disk space it frees
+    pub fn wants_compaction(&self) -> bool {
+        let tombstones = self.tombstones();
+        tombstones >= COMPACT_MIN_TOMBSTONES && tombstones * 4 >= self.nodes.len()
+    }
+
+    /// Enough churn since the last relink that the graph's links have drifted
+    pub fn wants_relink(&self) -> bool {
+        self.churn >= RELINK_MIN_CHURN && self.churn * 4 >= self.live.len()
+    }
+
+    /// Vector width, 0 while the store is empty
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    fn vector(&self, node: u32) -> &[f32] {
+        let row = node as usize;
+        if row < self.rows_on_disk {
+            let bytes = &self.mapped.as_ref().expect("rows on disk are mapped")[row * self.dim * 4..(row + 1) * self.dim * 4];
+            // SAFETY: the map is page-aligned and every row starts at a multiple of 4 bytes
+            unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const f32, self.dim) }
+        } else {
+            let row = row - self.rows_on_disk;
+            &self.pending[row * self.dim..(row + 1) * self.dim]
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn metric(&self) -> Metric {
+        self.metric
+    }
+
+    pub fn quantization(&self) -> Quantization {
+        self.quantization
+    }
+
+    /// Switch the search index to `quantization` - rebuilt from the f32 originals when entries exist
+    pub fn with_quantization(mut self, quantization: Quantization) -> Result<VectorStore, String> {
+        quantization.check(self.metric)?;
+        if quantization == self.quantization {
+            Ok(self)
+        } else if self.nodes.is_empty() {
+            self.quantization = quantization;
+            Ok(self)
+        } else {
+            self.rebuilt(quantization)
+        }
+    }
+
+    /// Quantized row of a node
+    fn index_row(&self, node: u32) -> &[u8] {
+        let size = self.quantization.row_bytes(self.dim);
+        let row = node as usize;
+        if row < self.rows_on_disk {
+            &self.index.as_ref().expect("rows on disk are mapped")[row * size..(row + 1) * size]
+        } else {
+            let row = row - self.rows_on_disk;
+            &self.pending_index[row * size..(row + 1) * size]
+        }
+    }
+
+    /// Distance used to walk the graph - against the quantized rows when there are some
+    fn distance(&self, query: &[f32], node: u32) -> f32 {
+        match self.quantization {
+            Quantization::None => self.metric.distance(query, self.vector(node)),
+            quantization => quantization.distance(self.metric, query, self.index_row(node)),
+        }
+    }
+
+    /// Insert or replace `id` - returns true when it replaced an existing entry
+    pub fn upsert(&mut self, id: &str, vector: &[f32], metadata: Value) -> Result<bool, String> {
+        if vector.is_empty() {
+            return Err(format!("'{}': empty vector", id));
+        }
+        if self.dim == 0 {
+            self.dim = vector.len();
+        } else if vector.len() != self.dim {
+            return Err(format!("'{}': vector has {} dimensions, the store holds {}", id, vector.len(), self.dim));
+        }
+        let replaced = self.remove(id);
+
+        let vector = self.metric.prepare(vector);
+        let index = self.nodes.len() as u32;
+        let level = random_level(id, self.nodes.len());
+        self.pending.extend_from_slice(&vector);
+        self.quantization.encode(&vector, &mut self.pending_index);
+        self.nodes.push(Node {
+            id: id.to_string(),
+            metadata,
+            deleted: false,
+            links: vec![Vec::new(); level + 1],
+        });
+        self.live.insert(id.to_string(), index);
+        self.link(index, &vector, level);
+        Ok(replaced)
+    }
+
+    /// Tombstone `id` - returns false when it wasn't stored
+    pub fn remove(&mut self, id: &str) -> bool {
+        match self.live.remove(id) {
+            Some(index) => {
+                self.nodes[index as usize].deleted = true;
+                self.churn += 1;
+                self.repair_pending = true;
+                self.keywords.remove(id);
+                self.keywords_changed = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Make a stored entry findable by keyword (an upsert drops the previous text)
+    pub fn index_text(&mut self, id: &str, text: &str) {
+        if self.live.contains_key(id) {
+            self.keywords.insert(id, text);
+            self.keywords_changed = true;
+        }
+    }
+
+    /// BM25 matches for `query` among live entries that pass `filter`, best first
+    pub fn keyword_search(&self, query: &str, limit: usize, filter: &Filter) -> Vec<(String, f32)> {
+        self.keywords.search(query, limit, |id| {
+            self.live.get(id).is_some_and(|&index| filter.matches(&self.nodes[index as usize].metadata))
+        })
+    }
+
+    /// Metadata of a live entry
+    pub fn metadata(&self, id: &str) -> Option<&Value> {
+        self.live.get(id).map(|&index| &self.nodes[index as usize].metadata)
+    }
+
+    /// Wire a new node into the graph
+    fn link(&mut self, index: u32, vector: &[f32], level: usize) {
+        let mut entry = match self.entry {
+            Some(entry) => entry,
+            None => {
+                self.entry = Some(index);
+                self.max_level = level;
+                return;
+            }
+        };
+
+        for layer in (level + 1..=self.max_level).rev() {
+            entry = self.greedy(vector, entry, layer);
+        }
+        for layer in (0..=level.min(self.max_level)).rev() {
+            let candidates = self.search_layer(vector, &[entry], EF_CONSTRUCTION, layer, &|_| true);
+            let limit = if layer == 0 { 2 * M } else { M };
+            let neighbors = self.select_neighbors(&candidates, limit);
+
+            for &neighbor in &neighbors {
+                let links = &mut self.nodes[neighbor as usize].links[layer];
+                links.push(index);
+                if links.len() > limit {
+                    self.prune(neighbor, layer, limit);
+                }
+            }
+            self.nodes[index as usize].links[layer] = neighbors;
+            entry = candidates[0].1;
+        }
+
+        if level > self.max_level {
+            self.entry = Some(index);
+            self.max_level = level;
+        }
+    }
+
+    /// Cut the links of `node` on `layer` back to `limit`
+    fn prune(&mut self, node: u32, layer: usize, limit: usize) {
+        let base = self.vector(node).to_vec();
+        let mut links: Vec<Scored> = self.nodes[node as usize].links[layer]
+            .iter()
+            .map(|&n| Scored(self.distance(&base, n), n))
+            .collect();
+        links.sort();
+        self.nodes[node as usize].links[layer] = self.select_neighbors(&links, limit);
+    }
+
+    /// Up to `limit` of the (closest first) `candidates`, skipping any that is closer to an already
+    /// chosen neighbor than to the base - keeping only the closest would spend every link inside a
+    /// cluster and cut the clusters off from each other
+    fn select_neighbors(&self, candidates: &[Scored], limit: usize) -> Vec<u32> {
+        let mut selected: Vec<u32> = Vec::with_capacity(limit);
+        for candidate in candidates {
+            if selected.len() >= limit {
+                break;
+            }
+            let vector = self.vector(candidate.1);
+            if selected.iter().all(|&chosen| self.distance(vector, chosen) > candidate.0) {
+                selected.push(candidate.1);
+            }
+        }
+        selected
+    }
+
+    /// Unlink tombstones: every live node linking to one re-picks its neighbors from its live links and
+    /// the live nodes behind the tombstones, so searches never have to walk through deleted entries
+    fn repair(&mut self) {
+        // A deleted entry point hands over to the highest live node
+        if self.entry.is_some_and(|entry| self.nodes[entry as usize].deleted) {
+            self.entry = None;
+            self.max_level = 0;
+            for (index, node) in self.nodes.iter().enumerate().filter(|(_, node)| !node.deleted) {
+                if self.entry.is_none() || node.links.len() - 1 > self.max_level {
+                    self.entry = Some(index as u32);
+                    self.max_level = node.links.len() - 1;
+                }
+            }
+        }
+
+        for index in 0..self.nodes.len() as u32 {
+            if self.nodes[index as usize].deleted {
+                continue;
+            }
+            for layer in 0..self.nodes[index as usize].links.len() {
+                if self.nodes[index as usize].links[layer].iter().all(|&n| !self.nodes[n as usize].deleted) {
+                    continue;
+                }
+                let base = self.vector(index).to_vec();
+                let mut candidates: Vec<Scored> = self.replacements(index, layer)
+                    .into_iter()
+                    .map(|n| Scored(self.distance(&base, n), n))
+                    .collect();
+                candidates.sort();
+                let limit = if layer == 0 { 2 * M } else { M };
+                self.nodes[index as usize].links[layer] = self.select_neighbors(&candidates, limit);
+            }
+        }
+        // Nothing links to them any more
+        for node in self.nodes.iter_mut().filter(|node| node.deleted) {
+            node.links.iter_mut().for_each(Vec::clear);
+        }
+        self.repair_pending = false;
+    }
+
+    /// Live nodes `node` links to on `layer`, plus those reached through the tombstones it links to
+    /// (nearest tombstones first, at most REPAIR_EXPANSION of them)
+    fn replacements(&self, node: u32, layer: usize) -> Vec<u32> {
+        let mut live = Vec::new();
+        let mut seen: HashSet<u32> = HashSet::from([node]);
+        let mut tombstones: VecDeque<u32> = VecDeque::new();
+        let mut visit = |n: u32, live: &mut Vec<u32>, tombstones: &mut VecDeque<u32>| {
+            if seen.insert(n) {
+                if self.nodes[n as usize].deleted {
+                    tombstones.push_back(n);
+                } else {
+                    live.push(n);
+                }
+            }
+        };
+        for &n in &self.nodes[node as usize].links[layer] {
+            visit(n, &mut live, &mut tombstones);
+        }
+        let mut expanded = 0;
+        while let Some(tombstone) = tombstones.pop_front().filter(|_| expanded < REPAIR_EXPANSION) {
+            expanded += 1;
+            for &n in self.nodes[tombstone as usize].links.get(layer).into_iter().flatten() {
+                visit(n, &mut live, &mut tombstones);
+            }
+        }
+        live
+    }
+
+    /// Re-select every live node's links from a fresh search of the graph, the way it would be
+    /// inserted now - returns how many nodes were relinked
+    pub fn relink(&mut self) -> usize {
+        self.repair();
+        let top = match self.entry {
+            Some(entry) => entry,
+            None => return 0,
+        };
+        let order: Vec<u32> = (0..self.nodes.len() as u32).filter(|&n| !self.nodes[n as usize].deleted).collect();
+        for &index in &order {
+            let vector = self.vector(index).to_vec();
+            let level = self.nodes[index as usize].links.len() - 1;
+            let mut entry = top;
+            for layer in (level + 1..=self.max_level).rev() {
+                entry = self.greedy(&vector, entry, layer);
+            }
+            for layer in (0..=level).rev() {
+                let accept = |n: u32| n != index && !self.nodes[n as usize].deleted;
+                let mut candidates = self.search_layer(&vector, &[entry], EF_CONSTRUCTION, layer, &accept);
+                // Current links stay in the running
+                for &n in &self.nodes[index as usize].links[layer] {
+                    if !candidates.iter().any(|c| c.1 == n) {
+                        candidates.push(Scored(self.distance(&vector, n), n));
+                    }
+                }
+                candidates.sort();
+                let Some(closest) = candidates.first() else { continue };
+                entry = closest.1;
+
+                let limit = if layer == 0 { 2 * M } else { M };
+                let neighbors = self.select_neighbors(&candidates, limit);
+                for &neighbor in &neighbors {
+                    let links = &mut self.nodes[neighbor as usize].links[layer];
+                    if !links.contains(&index) {
+                        links.push(index);
+                        if links.len() > limit {
+                            self.prune(neighbor, layer, limit);
+                        }
+                    }
+                }
+                self.nodes[index as usize].links[layer] = neighbors;
+            }
+        }
+        self.churn = 0;
+        order.len()
+    }
+
+    /// Closest node to `query` on `layer`, walking from `entry`
+    fn greedy(&self, query: &[f32], mut entry: u32, layer: usize) -> u32 {
+        let mut best = self.distance(query, entry);
+        loop {
+            let mut moved = false;
+            for &neighbor in &self.nodes[entry as usize].links[layer] {
+                let d = self.distance(query, neighbor);
+                if d < best {
+                    best = d;
+                    entry = neighbor;
+                    moved = true;
+                }
+            }
+            if !moved {
+                return entry;
+            }
+        }
+    }
+
+    /// The `ef` nearest nodes on `layer` that `accept` lets through, closest first - rejected nodes
+    /// are still walked through, so a selective filter widens the search instead of emptying it
+    fn search_layer(&self, query: &[f32], entries: &[u32], ef: usize, layer: usize, accept: &dyn Fn(u32) -> bool) -> Vec<Scored> {
+        let mut visited: HashSet<u32> = entries.iter().copied().collect();
+        let mut candidates: BinaryHeap<std::cmp::Reverse<Scored>> = BinaryHeap::new();
+        let mut found: BinaryHeap<Scored> = BinaryHeap::new();
+        for &entry in entries {
+            let scored = Scored(self.distance(query, entry), entry);
+            candidates.push(std::cmp::Reverse(scored));
+            if accept(entry) {
+                found.push(scored);
+            }
+        }
+
+        while let Some(std::cmp::Reverse(current)) = candidates.pop() {
+            if found.len() >= ef && found.peek().is_some_and(|worst| current.0 > worst.0) {
+                break;
+            }
+            for &neighbor in &self.nodes[current.1 as usize].links[layer] {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let d = self.distance(query, neighbor);
+                if found.len() < ef || found.peek().is_some_and(|worst| d < worst.0) {
+                    candidates.push(std::cmp::Reverse(Scored(d, neighbor)));
+                    if accept(neighbor) {
+                        found.push(Scored(d, neighbor));
+                        if found.len() > ef {
+                            found.pop();
+                        }
+                    }
+                }
+            }
+        }
+        found.into_sorted_vec()
+    }
+
+    /// The `top_k` live entries most similar to `query` that pass `filter`
+    pub fn search(&self, query: &[f32], top_k: usize, ef: usize, filter: &Filter) -> Result<Vec<Match>, String> {
+        let mut entry = match self.entry {
+            Some(entry) if !self.live.is_empty() => entry,
+            _ => return Ok(Vec::new()),
+        };
+        if query.len() != self.dim {
+            return Err(format!("Query has {} dimensions, the store holds {}", query.len(), self.dim));
+        }
+        let query = self.metric.prepare(query);
+
+        for layer in (1..=self.max_level).rev() {
+            entry = self.greedy(&query, entry, layer);
+        }
+        let accept = |n: u32| {
+            let node = &self.nodes[n as usize];
+            !node.deleted && filter.matches(&node.metadata)
+        };
+        let ef = ef.max(top_k) * self.quantization.oversample();
+        let mut found = self.search_layer(&query, &[entry], ef, 0, &accept);
+        // Quantized scores only pick the candidates - the originals decide the order
+        if self.quantization != Quantization::None {
+            for candidate in &mut found {
+                candidate.0 = self.metric.distance(&query, self.vector(candidate.1));
+            }
+            found.sort();
+        }
+        Ok(found
+            .into_iter()
+            .map(|s| (self.metric.score(s.0), &self.nodes[s.1 as usize]))
+            .filter(|(score, _)| filter.score_ok(*score))
+            .take(top_k)
+            .map(|(score, node)| Match { id: node.id.clone(), score, similarity: None, metadata: node.metadata.clone() })
+            .collect())
+    }
+
+    /// Graph statistics, with recall@`k` estimated by searching (at `ef`) for up to `sample` stored
+    /// vectors and comparing against an exact scan
+    pub fn health(&self, sample: usize, k: usize, ef: usize) -> IndexHealth {
+        let live: Vec<u32> = (0..self.nodes.len() as u32).filter(|&n| !self.nodes[n as usize].deleted).collect();
+        let mut linked = vec![false; self.nodes.len()];
+        let mut layer_sizes = vec![0; self.max_level + 1];
+        let (mut degree, mut unlinked) = (0, 0);
+        for &index in &live {
+            let links = &self.nodes[index as usize].links;
+            for (layer, neighbors) in links.iter().enumerate() {
+                if let Some(size) = layer_sizes.get_mut(layer) {
+                    *size += 1;
+                }
+                neighbors.iter().for_each(|&n| linked[n as usize] = true);
+            }
+            degree += links[0].len();
+            unlinked += usize::from(links[0].is_empty());
+        }
+        let linked_tombstones = self.nodes.iter().zip(&linked).filter(|(node, &linked)| node.deleted && linked).count();
+
+        // Searches walk layer 0 from the entry point, through tombstones too
+        let mut seen = vec![false; self.nodes.len()];
+        let mut queue: VecDeque<u32> = self.entry.into_iter().collect();
+        self.entry.inspect(|&entry| seen[entry as usize] = true);
+        let mut reachable = 0;
+        while let Some(node) = queue.pop_front() {
+            reachable += usize::from(!self.nodes[node as usize].deleted);
+            for &n in &self.nodes[node as usize].links[0] {
+                if !std::mem::replace(&mut seen[n as usize], true) {
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        // Spread evenly over the store
+        let count = sample.min(live.len());
+        let probes: Vec<u32> = (0..count).map(|i| live[i * live.len() / count]).collect();
+        let k = k.clamp(1, live.len().max(1));
+        let hits: usize = probes.par_iter()
+            .map(|&probe| {
+                let query = self.metric.prepare(self.vector(probe));
+                let found = self.search(&query, k, ef, &Filter::default()).unwrap_or_default();
+                let mut exact: Vec<Scored> = live.iter().map(|&n| Scored(self.metric.distance(&query, self.vector(n)), n)).collect();
+                exact.sort();
+                exact.iter().take(k).filter(|s| found.iter().any(|m| m.id == self.nodes[s.1 as usize].id)).count()
+            })
+            .sum();
+
+        IndexHealth {
+            live: live.len(),
+            tombstones: self.tombstones(),
+            linked_tombstones,
+            max_level: self.max_level,
+            layer_sizes,
+            mean_degree: if live.is_empty() { 0.0 } else { degree as f32 / live.len() as f32 },
+            unlinked,
+            reachable,
+            connectivity: if live.is_empty() { 1.0 } else { reachable as f32 / live.len() as f32 },
+            recall_estimate: (!probes.is_empty()).then(|| hits as f32 / (probes.len() * k) as f32),
+            recall_sample: probes.len(),
+            churn: self.churn,
+        }
+    }
+
+    /// Write pending vectors and the graph to disk, repairing links around new tombstones first
+    pub fn save(mut self) -> Result<(), String> {
+        if self.repair_pending {
+            self.repair();
+        }
+
+        let bytes: Vec<u8> = self.pending.iter().flat_map(|v| v.to_ne_bytes()).collect();
+        append_rows(&self.dir, "vectors.f32", self.rows_on_disk * self.dim * 4, &bytes)?;
+        if let Some(name) = self.quantization.file_name() {
+            let saved = self.rows_on_disk * self.quantization.row_bytes(self.dim);
+            append_rows(&self.dir, name, saved, &self.pending_index)?;
+        }
+
+        if self.keywords_changed {
+            self.keywords.save(&self.dir)?;
+        }
+
+        let file = StoreFile {
+            version: FORMAT_VERSION,
+            dim: self.dim,
+            metric: self.metric,
+            quantization: self.quantization,
+            entry: self.entry,
+            max_level: self.max_level,
+            churn: self.churn,
+            nodes: self.nodes,
+        };
+        let json = serde_json::to_string(&file).map_err(|e| e.to_string())?;
+        let tmp = self.dir.join("store.json.tmp");
+        fs::write(&tmp, json)
+            .and_then(|_| fs::rename(&tmp, self.dir.join("store.json")))
+            .map_err(|e| format!("Cannot write store.json: {}", e))?;
+
+        // An index left from a quantization the store no longer uses
+        for quantization in [Quantization::Int8, Quantization::Binary] {
+            if let Some(name) = quantization.file_name().filter(|_| quantization != file.quantization) {
+                match fs::remove_file(self.dir.join(name)) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(format!("Cannot remove {}: {}", name, e)),
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuild without tombstones and save - returns how many were dropped
+    pub fn compact(self) -> Result<usize, String> {
+        let removed = self.tombstones();
+        let quantization = self.quantization;
+        self.rebuilt(quantization)?.save()?;
+        Ok(removed)
+    }
+
+    /// Same entries without tombstones, re-indexed from scratch (with a `quantization` index)
+    fn rebuilt(mut self, quantization: Quantization) -> Result<VectorStore, String> {
+        let mut fresh = VectorStore {
+            dir: self.dir.clone(),
+            dim: self.dim,
+            metric: self.metric,
+            quantization,
+            keywords: std::mem::take(&mut self.keywords),
+            keywords_changed: self.keywords_changed,
+            ..VectorStore::in_memory()
+        };
+        for (index, node) in self.nodes.iter().enumerate().filter(|(_, node)| !node.deleted) {
+            fresh.upsert(&node.id, self.vector(index as u32), node.metadata.clone())?;
+        }
+        // Nothing is on disk for the fresh store - save replaces the old vector files as a whole
+        fresh._lock = self._lock;
+        Ok(fresh)
+    }
+}
+
+/// Map the first `len` bytes of a rows file (None while the store is empty)
+fn map_rows(dir: &Path, name: &str, len: usize) -> Result<Option<Mmap>, String> {
+    if len == 0 {
+        return Ok(None);
+    }
+    let file = File::open(dir.join(name)).map_err(|e| format!("Cannot open {}: {}", name, e))?;
+    // SAFETY: under the store lock rows files are only appended to, or recreated by a rebuild - rows a
+    // reader has mapped never change
+    let mapped = unsafe { Mmap::map(&file) }.map_err(|e| format!("Cannot map {}: {}", name, e))?;
+    if mapped.len() < len {
+        return Err(format!("{} is shorter than store.json says", name));
+    }
+    Ok(Some(mapped))
+}
+
+/// Write `bytes` after the first `saved` bytes of a rows file - leftovers of an interrupted save are cut
+/// off before the new rows go in. A file written from scratch replaces the old one in a single rename
+fn append_rows(dir: &Path, name: &str, saved: usize, bytes: &[u8]) -> Result<(), String> {
+    if saved == 0 {
+        let tmp = dir.join(format!("{}.tmp", name));
+        return File::create(&tmp)
+            .and_then(|mut file| file.write_all(bytes).and_then(|_| file.sync_all()))
+            .and_then(|_| fs::rename(&tmp, dir.join(name)))
+            .map_err(|e| format!("Cannot write {}: {}", name, e));
+    }
+    let saved = saved as u64;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(dir.join(name))
+        .map_err(|e| format!("Cannot write {}: {}", name, e))?;
+    file.set_len(saved)
+        .and_then(|_| file.seek(SeekFrom::Start(saved)))
+        .and_then(|_| file.write_all(bytes))
+        .and_then(|_| file.sync_all())
+        .map_err(|e| format!("Cannot write {}: {}", name, e))
+}
+
+/// HNSW layer for a new node: P(level >= l) = M^-l, drawn deterministically from the id
+fn random_level(id: &str, salt: usize) -> usize {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    (id, salt).hash(&mut hasher);
+    let uniform = ((hasher.finish() >> 11) as f64 + 1.0) / (1u64 << 53) as f64; // (0, 1]
+    ((-uniform.ln()) / (M as f64).ln()).floor() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Deterministic vectors in [-1, 1) (xorshift, so runs are repeatable)
+    fn vectors(count: usize, dim: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut state = seed.max(1);
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+        };
+        (0..count).map(|_| (0..dim).map(|_| next()).collect()).collect()
+    }
+
+    fn filled(count: usize) -> VectorStore {
+        let mut store = VectorStore::in_memory();
+        for (i, vector) in vectors(count, 16, 7).iter().enumerate() {
+            store.upsert(&format!("v{}", i), vector, json!({ "n": i })).unwrap();
+        }
+        store
+    }
+
+    /// Ids of the `k` live entries closest to `query` by an exact scan
+    fn brute_force(store: &VectorStore, query: &[f32], k: usize) -> Vec<String> {
+        let query = store.metric.prepare(query);
+        let mut exact: Vec<(f32, String)> = store.ids().into_iter()
+            .map(|id| (store.metric.distance(&query, store.get(&id).unwrap()), id))
+            .collect();
+        exact.sort_by(|a, b| a.0.total_cmp(&b.0));
+        exact.into_iter().take(k).map(|(_, id)| id).collect()
+    }
+
+    fn recall(store: &VectorStore, queries: &[Vec<f32>], k: usize) -> f32 {
+        let hits: usize = queries.iter()
+            .map(|query| {
+                let found = store.search(query, k, DEFAULT_EF, &Filter::default()).unwrap();
+                brute_force(store, query, k).iter().filter(|id| found.iter().any(|m| &&m.id == id)).count()
+            })
+            .sum();
+        hits as f32 / (queries.len() * k) as f32
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rust-brain-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_recall_against_brute_force() {
+        let store = filled(1000);
+        let queries = vectors(50, 16, 99);
+        let recall = recall(&store, &queries, 10);
+        assert!(recall >= 0.95, "recall@10 {}", recall);
+
+        // A stored vector finds itself first
+        let hit = store.search(store.get("v123").unwrap(), 1, DEFAULT_EF, &Filter::default()).unwrap();
+        assert_eq!(hit[0].id, "v123");
+        assert!((hit[0].score - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_connectivity_after_insert_and_delete() {
+        let mut store = filled(600);
+        let health = store.health(0, 10, DEFAULT_EF);
+        assert_eq!((health.live, health.unlinked, health.reachable), (600, 0, 600));
+
+        // Delete a third of the store, the entry point included
+        let entry = store.nodes[store.entry.unwrap() as usize].id.clone();
+        assert!(store.remove(&entry));
+        for i in (0..600).step_by(3) {
+            store.remove(&format!("v{}", i));
+        }
+        assert!(store.health(0, 10, DEFAULT_EF).linked_tombstones > 0);
+        store.repair();
+
+        let health = store.health(100, 10, DEFAULT_EF);
+        assert_eq!(health.live, store.len());
+        assert_eq!(health.linked_tombstones, 0);
+        assert_eq!(health.unlinked, 0);
+        assert_eq!(health.connectivity, 1.0);
+        assert!(health.recall_estimate.unwrap() >= 0.95, "{:?}", health.recall_estimate);
+        assert!(!store.nodes[store.entry.unwrap() as usize].deleted);
+
+        // Deleted entries never come back, and recall holds against the survivors
+        let queries = vectors(30, 16, 5);
+        for query in &queries {
+            for found in store.search(query, 20, DEFAULT_EF, &Filter::default()).unwrap() {
+                assert!(store.get(&found.id).is_some(), "{} was deleted", found.id);
+            }
+        }
+        assert!(recall(&store, &queries, 10) >= 0.95);
+
+        // New entries link into the repaired graph
+        for (i, vector) in vectors(100, 16, 11).iter().enumerate() {
+            store.upsert(&format!("new{}", i), vector, Value::Null).unwrap();
+        }
+        let health = store.health(0, 10, DEFAULT_EF);
+        assert_eq!(health.unlinked, 0);
+        assert_eq!(health.connectivity, 1.0);
+    }
+
+    #[test]
+    fn test_churn_repairs_on_save_and_relinks() {
+        let dir = temp_dir("churn");
+        let data = vectors(400, 16, 21);
+        let mut store = VectorStore::open(&dir, true).unwrap();
+        for (i, vector) in data.iter().enumerate() {
+            store.upsert(&format!("v{}", i), vector, Value::Null).unwrap();
+        }
+        // Every update leaves a tombstone behind
+        for (i, vector) in data.iter().enumerate().take(150) {
+            assert!(store.upsert(&format!("v{}", i), &vector.iter().map(|v| -v).collect::<Vec<f32>>(), Value::Null).unwrap());
+        }
+        assert_eq!((store.len(), store.tombstones(), store.churn), (400, 150, 150));
+        assert!(!store.wants_relink());
+        store.save().unwrap();
+
+        // Saving repaired the graph around the tombstones
+        let mut store = VectorStore::open(&dir, true).unwrap();
+        let health = store.health(0, 10, DEFAULT_EF);
+        assert_eq!((health.linked_tombstones, health.connectivity), (0, 1.0));
+
+        (0..120).for_each(|i| assert!(store.remove(&format!("v{}", i))));
+        assert!(store.wants_relink());
+        assert_eq!(store.relink(), 280);
+        assert_eq!(store.churn, 0);
+        let health = store.health(100, 10, DEFAULT_EF);
+        assert_eq!((health.linked_tombstones, health.unlinked, health.connectivity), (0, 0, 1.0));
+        assert!(recall(&store, &vectors(30, 16, 23), 10) >= 0.95);
+        drop(store);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_persistence_round_trip() {
+        let dir = temp_dir("store");
+        let data = vectors(200, 8, 3);
+        let mut store = VectorStore::create(&dir, 0, Metric::Cosine, Quantization::Int8).unwrap();
+        for (i, vector) in data.iter().enumerate() {
+            store.upsert(&format!("v{}", i), vector, json!({ "n": i })).unwrap();
+        }
+        store.index_text("v7", "kernel panic on boot");
+        assert!(store.remove("v9"));
+        let expected: Vec<Vec<String>> = data.iter().take(20).map(|q| brute_force(&store, q, 5)).collect();
+        store.save().unwrap();
+
+        let store = VectorStore::open(&dir, false).unwrap();
+        assert_eq!((store.dim(), store.metric(), store.quantization()), (8, Metric::Cosine, Quantization::Int8));
+        assert_eq!(store.len(), 199);
+        assert_eq!(store.tombstones(), 1);
+        assert!(store.get("v9").is_none());
+        assert_eq!(store.get("v42").unwrap(), Metric::Cosine.prepare(&data[42]).as_slice());
+        assert_eq!(store.metadata("v42"), Some(&json!({ "n": 42 })));
+        assert_eq!(store.keyword_search("panic", 5, &Filter::default())[0].0, "v7");
+        for (query, expected) in data.iter().zip(&expected) {
+            assert_eq!(&brute_force(&store, query, 5), expected);
+            assert_eq!(store.search(query, 1, DEFAULT_EF, &Filter::default()).unwrap()[0].id, expected[0]);
+        }
+        drop(store);
+
+        // Rows appended by a later writer land after the saved ones, and compaction drops the tombstones
+        let mut store = VectorStore::open(&dir, true).unwrap();
+        store.upsert("extra", &data[0], Value::Null).unwrap();
+        store.save().unwrap();
+        let store = VectorStore::open(&dir, true).unwrap();
+        assert_eq!((store.len(), store.tombstones()), (200, 1));
+        assert_eq!(store.compact().unwrap(), 1);
+
+        let store = VectorStore::open(&dir, false).unwrap();
+        assert_eq!((store.len(), store.tombstones()), (200, 0));
+        assert_eq!(store.get("extra"), store.get("v0"));
+        assert_eq!(store.health(0, 10, DEFAULT_EF).connectivity, 1.0);
+        drop(store);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
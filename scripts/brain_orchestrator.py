@@ -3,12 +3,15 @@ Brain Orchestrator - Neural network learning layer
 Coordinates between Python ML and Rust heavy lifting
 """
 import json
+import os
 import subprocess
 import hashlib
 from pathlib import Path
 from typing import List, Dict, Any, Optional
 import time
 
+from rust_executor import send_request
+
 
 class BrainOrchestrator:
     """
@@ -21,8 +24,11 @@ class BrainOrchestrator:
     
     def __init__(self, 
                  rust_bin: Path = Path("rust-brain/target/release/rust-brain"),
-                 cache_dir: Path = Path("brain/cache")):
+                 cache_dir: Path = Path("brain/cache"),
+                 rust_socket: Optional[str] = None):
         self.rust_bin = Path(rust_bin)
+        # A `rust-brain serve` socket, if one is running - else each call starts the binary
+        self.rust_socket = rust_socket or os.environ.get("ARCHY_BRAIN_SOCKET")
         self.cache_dir = Path(cache_dir)
         self.cache_dir.mkdir(parents=True, exist_ok=True)
         
@@ -55,9 +61,18 @@ class BrainOrchestrator:
     
     def call_rust_worker(self, task: str, payload: Dict[str, Any], timeout: float = 30.0) -> Dict[str, Any]:
         """
-        Call Rust worker binary with JSON payload.
+        Call Rust worker with JSON payload - over the brain socket when one is up,
+        otherwise by running the binary.
         Returns parsed JSON response.
         """
+        if self.rust_socket and Path(self.rust_socket).exists():
+            try:
+                response = send_request(self.rust_socket, task, payload, timeout)
+                if response is not None:
+                    return response
+            except (OSError, ValueError) as e:
+                print(f"⚠️ Rust brain socket failed, running the binary: {e}")
+
         if not self.rust_bin.exists():
             # Fallback: try debug build
             debug_bin = Path("rust-brain/target/debug/rust-brain")
@@ -137,10 +152,14 @@ class BrainOrchestrator:
                      query: str, 
                      candidates: List[str], 
                      top_k: int = 5,
-                     dim: int = 128) -> List[Dict[str, Any]]:
+                     dim: int = 128,
+                     timestamps: Optional[List[Optional[float]]] = None,
+                     half_life: float = 7 * 86400) -> List[Dict[str, Any]]:
         """
         Find most similar candidates to query using cosine similarity.
-        Returns list of {text, score, index} sorted by similarity.
+        With per-candidate timestamps (unix seconds), fresher candidates are
+        preferred among close matches (boost halves every half_life seconds).
+        Returns list of {text, score, index} sorted by score.
         """
         # Get embeddings
         all_texts = [query] + candidates
@@ -158,6 +177,9 @@ class BrainOrchestrator:
             "candidates": cand_embs,
             "top_k": top_k
         }
+        if timestamps is not None:
+            payload["timestamps"] = timestamps
+            payload["recency"] = {"half_life": half_life}
         
         response = self.call_rust_worker("cosine_rank", payload)
         
@@ -5,7 +5,45 @@ Handles all system-level operations via Unix socket IPC
 
 import socket
 import json
-from typing import Dict, Any, Optional
+import uuid
+from typing import Dict, Any, Iterator, List, Optional, Union
+
+
+def send_request(socket_path: str, action: str, data: Dict[str, Any], timeout: float = 10.0,
+                 max_response_size: int = 10 * 1024 * 1024) -> Optional[Dict[str, Any]]:
+    """
+    Send one request over the archy wire protocol, spoken by both the executor daemon and
+    `rust-brain serve`: one JSON request per connection, one JSON reply back.
+
+    Every reply carries `success`, failures an `error_code`, and the request id is echoed.
+    Returns None when nothing came back; socket errors are left to the caller.
+    """
+    request_id = uuid.uuid4().hex
+    client = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+    try:
+        client.settimeout(timeout)
+        client.connect(socket_path)
+        client.sendall(json.dumps({"action": action, "data": data, "id": request_id}).encode())
+
+        # Receive response in chunks to handle large outputs
+        response_data = b''
+        while len(response_data) < max_response_size:
+            try:
+                chunk = client.recv(8192)
+                if not chunk:
+                    break
+                response_data += chunk
+            except socket.timeout:
+                break  # Stop receiving if timeout is reached
+    finally:
+        client.close()
+
+    if not response_data:
+        return None
+    response = json.loads(response_data.decode('utf-8', errors='replace'))
+    if response.get("id", request_id) != request_id:
+        raise ValueError(f"Reply is for request {response.get('id')}, not {request_id}")
+    return response
 
 
 class RustExecutor:
@@ -33,8 +71,6 @@ class RustExecutor:
         
         while retry_count <= max_retries:
             try:
-                client = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
-
                 # Dynamic timeout based on action type
                 # For actions that wait for command completion, use max_wait + buffer
                 if action in ['execute_and_wait', 'execute_analyzed', 'wait_for_prompt']:
@@ -45,31 +81,10 @@ class RustExecutor:
                     # Quick actions get 10 second timeout
                     socket_timeout = 10.0
 
-                client.settimeout(socket_timeout)
-                client.connect(self.socket_path)
-                
-                message = json.dumps({"action": action, "data": data})
-                client.sendall(message.encode())
-                
-                # Receive response in chunks to handle large outputs
-                response_data = b''
-                max_response_size = 10 * 1024 * 1024  # 10MB limit
-                while len(response_data) < max_response_size:
-                    try:
-                        chunk = client.recv(8192)
-                        if not chunk:
-                            break
-                        response_data += chunk
-                    except socket.timeout:
-                        break  # Stop receiving if timeout is reached
-
-                client.close()
-
-                if not response_data:
+                response = send_request(self.socket_path, action, data, socket_timeout)
+                if response is None:
                     return {"success": False, "error": "No response from executor (timeout or empty response)"}
-
-                response = response_data.decode('utf-8', errors='replace')
-                return json.loads(response)
+                return response
             except FileNotFoundError:
                 return {
                     "success": False,
@@ -93,6 +108,43 @@ class RustExecutor:
             except Exception as e:
                 return {"success": False, "error": str(e)}
     
+    def health(self) -> Dict[str, Any]:
+        """Liveness probe: service, version, protocol and uptime."""
+        return self.send_command("health", {})
+
+    def recent_requests(self, limit: int = 20, action: Optional[str] = None,
+                        slow_only: bool = False) -> Dict[str, Any]:
+        """The daemon's last requests with per-phase timings (read, validate, execute, wait, ...), newest first."""
+        data: Dict[str, Any] = {"limit": limit, "slow_only": slow_only}
+        if action:
+            data["action"] = action
+        return self.send_command("get_recent_requests", data)
+
+    def stats(self, action: Optional[str] = None, top: int = 10, reset: bool = False) -> Dict[str, Any]:
+        """p50/p95 latency, failure and over-goal rates per action plus the most-run commands, kept across restarts."""
+        data: Dict[str, Any] = {"top": top, "reset": reset}
+        if action:
+            data["action"] = action
+        return self.send_command("stats", data)
+
+    def events(self, since: int = 0, limit: int = 100, kinds: Optional[List[str]] = None,
+               session: Optional[str] = None) -> Dict[str, Any]:
+        """Daemon events (sessions, batches, blocks, emergency stop, config changes) after `since`; pass back `next`."""
+        data: Dict[str, Any] = {"since": since, "limit": limit}
+        if kinds:
+            data["kinds"] = kinds
+        if session:
+            data["session"] = session
+        return self.send_command("get_events", data)
+
+    def debug_bundle(self, requests: int = 20, log_lines: int = 500) -> Dict[str, Any]:
+        """Write a redacted .tar.gz (logs, recent requests/replies, config, versions, environment) for a bug report; `path` says where."""
+        return self.send_command("collect_debug_bundle", {"requests": requests, "log_lines": log_lines})
+
+    def introspect(self) -> Dict[str, Any]:
+        """What the daemon is doing right now: the request in flight and queued ones, the running batch, pending timers."""
+        return self.send_command("introspect", {})
+
     def execute_in_tmux(self, command: str, session: str = "archy_session") -> Dict[str, Any]:
         """Execute a command in the tmux session."""
         return self.send_command("execute", {"command": command, "session": session})
@@ -137,6 +189,78 @@ class RustExecutor:
         result = self.send_command("get_system_info", {})
         return result.get("output", "System info unavailable")
 
+    def get_system_details(self) -> Dict[str, Any]:
+        """Structured system facts: kernel, distro, cpu, memory, uptime, virtualization."""
+        result = self.send_command("get_system_info", {})
+        return result.get("system", {}) if result.get("success") else {}
+
+    def monitor(self, interval_ms: int = 1000, samples: int = 60) -> Iterator[Dict[str, Any]]:
+        """Stream resource samples (CPU %, per-core, memory, disk and network throughput), one dict each.
+        Stops after `samples`, or when the caller stops iterating."""
+        client = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+        try:
+            client.settimeout(interval_ms / 1000.0 + 10.0)
+            client.connect(self.socket_path)
+            request = {"action": "monitor", "data": {"interval_ms": interval_ms, "samples": samples}, "id": uuid.uuid4().hex}
+            client.sendall(json.dumps(request).encode())
+            for line in client.makefile('r', encoding='utf-8', errors='replace'):
+                reply = json.loads(line)
+                if not reply.get("success"):
+                    raise RuntimeError(reply.get("error", "monitor failed"))
+                if reply.get("done"):
+                    return
+                yield reply["sample"]
+        finally:
+            client.close()
+
+    def network_info(self, probe: bool = False) -> Dict[str, Any]:
+        """Interfaces, default routes, DNS servers and (with probe=True) an active connectivity check."""
+        return self.send_command("network_info", {"probe": probe})
+
+    def pkg_search(self, query: str, limit: int = 50) -> Dict[str, Any]:
+        """Search the distro's package manager; `packages` lists name, version, description, installed."""
+        return self.send_command("pkg_search", {"query": query, "limit": limit})
+
+    def pkg_info(self, package: str) -> Dict[str, Any]:
+        """Details of one package (version, repository, licenses, depends, ...) as `package`."""
+        return self.send_command("pkg_info", {"package": package})
+
+    def pkg_list_upgrades(self, limit: int = 50) -> Dict[str, Any]:
+        """Pending upgrades, with findings for security and kernel updates."""
+        return self.send_command("pkg_list_upgrades", {"limit": limit})
+
+    def unit_status(self, unit: str, scope: str = "system") -> Dict[str, Any]:
+        """A systemd unit's state, result and restart count, with findings when it has failed."""
+        return self.send_command("unit_status", {"unit": unit, "scope": scope})
+
+    def list_failed_units(self, scope: str = "system") -> Dict[str, Any]:
+        """Units in the failed state, with journalctl suggestions."""
+        return self.send_command("list_failed_units", {"scope": scope})
+
+    def unit_journal_tail(self, unit: str, lines: int = 50, scope: str = "system") -> Dict[str, Any]:
+        """A unit's last journal entries as structured records, with findings from their messages."""
+        return self.send_command("unit_journal_tail", {"unit": unit, "lines": lines, "scope": scope})
+
+    def storage_health(self, smart: bool = True) -> Dict[str, Any]:
+        """Filesystem usage, read-only remounts and disk SMART status, with findings."""
+        return self.send_command("storage_health", {"smart": smart})
+
+    def session_info(self) -> Dict[str, Any]:
+        """Graphical session type and compositor, logged-in users, idle state and the GUI launch environment."""
+        return self.send_command("session_info", {})
+
+    def thermal_info(self) -> Dict[str, Any]:
+        """CPU, GPU and NVMe temperatures and fan speeds, with findings past the configured thresholds."""
+        return self.send_command("thermal_info", {})
+
+    def doctor(self) -> Dict[str, Any]:
+        """Environment checks (tmux, shell, terminals, socket, display, log space), each pass/warn/fail with a remedy."""
+        return self.send_command("doctor", {})
+
+    def power_info(self) -> Dict[str, Any]:
+        """Battery percentage, charge state, time remaining and AC status, with low-battery findings."""
+        return self.send_command("power_info", {})
+
     def find_desktop_entry(self, app_name: str) -> Optional[str]:
         """Find the desktop entry for a given application name."""
         result = self.send_command("find_desktop_entry", {"app_name": app_name})
@@ -177,9 +301,66 @@ class RustExecutor:
         """Execute a command smartly (GUI or CLI)."""
         return self.send_command("execute_smart", {"command": command, "session": session})
 
-    def launch_gui_app(self, desktop_entry: str) -> Dict[str, Any]:
-        """Launch a GUI application using its desktop entry."""
-        return self.send_command("launch_gui_app", {"desktop_entry": desktop_entry})
+    def launch_gui_app(self, desktop_entry: str, files: Optional[List[str]] = None,
+                       uris: Optional[List[str]] = None, mode: str = "launch",
+                       workspace: Optional[Union[str, int]] = None,
+                       monitor: Optional[Union[str, int]] = None) -> Dict[str, Any]:
+        """Launch a GUI application using its desktop entry, opening absolute file paths and/or URIs.
+        mode="launch_or_focus" focuses the app's window if it already runs.
+        workspace/monitor place the new window (monitor by name or 1-based position)."""
+        data: Dict[str, Any] = {"desktop_entry": desktop_entry, "mode": mode}
+        if files:
+            data["files"] = files
+        if uris:
+            data["uris"] = uris
+        if workspace is not None:
+            data["workspace"] = workspace
+        if monitor is not None:
+            data["monitor"] = monitor
+        return self.send_command("launch_gui_app", data)
+
+    def list_monitors(self) -> Dict[str, Any]:
+        """Monitors of the session (name, geometry, active workspace) for placement."""
+        return self.send_command("list_monitors", {})
+
+    def get_app_info(self, app_name: str, size: int = 48, scale: int = 1,
+                     theme: Optional[str] = None) -> Dict[str, Any]:
+        """Metadata for an app card: the index entry plus its icon resolved in the icon theme."""
+        params: Dict[str, Any] = {"app_name": app_name, "size": size, "scale": scale}
+        if theme:
+            params["theme"] = theme
+        return self.send_command("get_app_info", params)
+
+    def is_app_running(self, app_name: str) -> bool:
+        """Check whether an application already runs (process table and window list)."""
+        result = self.send_command("is_app_running", {"app_name": app_name})
+        return bool(result.get("success") and result.get("running"))
+
+    def open_with_default(self, files: Optional[List[str]] = None,
+                          uris: Optional[List[str]] = None) -> Dict[str, Any]:
+        """Open files or URIs with the application xdg-mime associates with them."""
+        return self.send_command("open_with_default", {"files": files or [], "uris": uris or []})
+
+    def add_autostart(self, desktop_entry: str, replace: bool = False) -> Dict[str, Any]:
+        """Start an installed application at login (~/.config/autostart)."""
+        return self.send_command("add_autostart", {"desktop_entry": desktop_entry, "replace": replace})
+
+    def list_autostart(self) -> List[Dict[str, Any]]:
+        """Autostart entries, user and system, with whether each is enabled."""
+        result = self.send_command("list_autostart", {})
+        return result.get("entries", []) if result.get("success") else []
+
+    def remove_autostart(self, desktop_entry: str) -> Dict[str, Any]:
+        """Stop starting an application at login."""
+        return self.send_command("remove_autostart", {"desktop_entry": desktop_entry})
+
+    def get_default_app(self, mime_or_url: str) -> Dict[str, Any]:
+        """What opens a MIME type, URL, file or extension by default (and what else could)."""
+        return self.send_command("get_default_app", {"mime_or_url": mime_or_url})
+
+    def set_default_app(self, mime_or_url: str, desktop_entry: str) -> Dict[str, Any]:
+        """Make an app the default for a MIME type (``video/*`` for a whole kind), URL scheme or file type."""
+        return self.send_command("set_default_app", {"mime_or_url": mime_or_url, "desktop_entry": desktop_entry})
 
     def execute_analyzed(self, command: str, session: str = "archy_session",
                         max_wait: int = 600, interval_ms: int = 500) -> Dict[str, Any]:
@@ -260,7 +441,8 @@ class RustExecutor:
             return False
 
     def detect_terminal(self) -> Optional[Dict[str, Any]]:
-        """Detect available terminal emulator. Returns dict with 'terminal' and 'args'."""
+        """Detect available terminal emulator. Returns dict with 'terminal', 'args' and 'argv'
+        (the full command line, with the script going where '{command}' is)."""
         result = self.send_command("detect_terminal", {})
         if result.get("success", False) and result.get("output"):
             import json
@@ -270,12 +452,13 @@ class RustExecutor:
                 pass
         return None
 
-    def launch_fallback_terminal(self, command: str, terminal: str = "foot") -> Dict[str, Any]:
-        """Launch command in a new terminal window (fallback method)."""
-        return self.send_command("launch_fallback_terminal", {
-            "command": command,
-            "terminal": terminal
-        })
+    def launch_fallback_terminal(self, command: str, terminal: Optional[str] = None) -> Dict[str, Any]:
+        """Launch command in a new terminal window (fallback method). Without a terminal the
+        executor picks one for the session."""
+        params: Dict[str, Any] = {"command": command}
+        if terminal:
+            params["terminal"] = terminal
+        return self.send_command("launch_fallback_terminal", params)
 
     def batch_execute(self, commands: list[str], explanations: list[str] = None,
                      session: str = "archy_session") -> Dict[str, Any]:
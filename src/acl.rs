@@ -0,0 +1,140 @@
+// acl.rs - Per-client authorization on the Unix socket
+// Peers are identified by SO_PEERCRED and mapped to an access level in `[acl]`. Each action needs a
+// level; anything the peer isn't allowed is rejected at dispatch. The daemon's own user is admin
+// unless the config says otherwise, and everyone else gets `acl.default` (none out of the box).
+
+use serde::{Deserialize, Serialize};
+use crate::config::Config;
+use crate::peer::{self, PeerCred};
+
+/// Access levels, each including the ones below it (the derived ordering is relied on)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Access {
+    None,
+    Read,    // inspect sessions, output, artifacts and workflows
+    Execute, // run commands, launch apps, manage terminals and workflows
+    Admin,   // daemon controls (log level, audit log, resume after panic_stop) and confirm_execute
+}
+
+impl Access {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Access::None => "none",
+            Access::Read => "read",
+            Access::Execute => "execute",
+            Access::Admin => "admin",
+        }
+    }
+}
+
+/// Level an action needs - actions not listed here are admin-only, so new ones start locked down
+pub fn required(action: &str) -> Access {
+    match action {
+        // Anyone who can connect may ask who is listening
+        "health" | "describe" => Access::None,
+
+        "capture" | "capture_analyzed" | "check_session" | "is_foot_running" | "check_command"
+        | "get_system_info" | "power_info" | "monitor" | "network_info"
+        | "pkg_search" | "pkg_info" | "pkg_list_upgrades" | "unit_status" | "list_failed_units" | "unit_journal_tail" | "storage_health" | "session_info" | "thermal_info" | "doctor"
+        | "find_desktop_entry" | "list_applications" | "get_app_info" | "get_default_app" | "extract_directory"
+        | "wait_for_prompt" | "detect_terminal" | "list_workflows" | "get_artifact" | "validate_config"
+        | "recall_similar_outputs" | "list_windows" | "list_monitors" | "is_app_running" | "list_autostart" => Access::Read,
+
+        "execute" | "execute_analyzed" | "execute_and_wait" | "execute_smart" | "batch_execute"
+        | "execute_batch" | "execute_batch_analyzed" | "resume_batch" | "run_workflow"
+        | "save_workflow" | "open_terminal" | "close_terminal" | "close_session"
+        | "launch_gui_app" | "open_with_default" | "focus_window" | "close_window" | "launch_fallback_terminal"
+        | "claim_session" | "panic_stop" => Access::Execute,
+
+        // Approving a held command is for a human, not the execute-level client that was held
+        "confirm_execute" => Access::Admin,
+
+        _ => Access::Admin,
+    }
+}
+
+/// Level granted to a peer: an entry for its uid or user name, else owner/admin, else `acl.default`
+pub fn access_for(peer: Option<PeerCred>, config: &Config) -> Access {
+    let peer = match peer {
+        Some(peer) => peer,
+        None => return config.acl_default,
+    };
+
+    if let Some(access) = config.acl_users.get(&peer.uid.to_string()) {
+        return *access;
+    }
+    if let Some(access) = peer::user_name(peer.uid).and_then(|name| config.acl_users.get(&name)) {
+        return *access;
+    }
+    if peer.uid == peer::own_uid() {
+        return Access::Admin;
+    }
+    config.acl_default
+}
+
+/// Dispatcher gate - Err when the peer's level is below what the action needs
+pub fn authorize(action: &str, peer: Option<PeerCred>, config: &Config) -> Result<(), String> {
+    let who = peer.map(|p| format!("uid {}", p.uid)).unwrap_or_else(|| "unidentified peer".to_string());
+    check(action, access_for(peer, config), &who)
+}
+
+/// Err when `granted` is below what the action needs (`who` names the client in the message)
+pub fn check(action: &str, granted: Access, who: &str) -> Result<(), String> {
+    let needed = required(action);
+    if granted >= needed {
+        return Ok(());
+    }
+    Err(format!(
+        "Permission denied: {} has {} access, '{}' needs {}",
+        who,
+        granted.as_str(),
+        action,
+        needed.as_str()
+    ))
+}
+
+/// Config errors for the `[acl]` section
+pub fn validate(config: &Config) -> Vec<String> {
+    config.acl_users.keys()
+        .filter(|key| key.parse::<u32>().is_err() && peer::uid_for_name(key).is_none())
+        .map(|key| format!("acl user {:?} is neither a uid nor a known user name", key))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(uid: u32) -> Option<PeerCred> {
+        Some(PeerCred { pid: 1, uid, gid: uid })
+    }
+
+    #[test]
+    fn test_owner_is_admin_others_default() {
+        let config = Config::default();
+        assert!(authorize("set_log_level", peer(peer::own_uid()), &config).is_ok());
+
+        let stranger = peer::own_uid().wrapping_add(4242);
+        let err = authorize("capture", peer(stranger), &config).unwrap_err();
+        assert!(err.contains("has none access"));
+        assert!(authorize("capture", None, &config).is_err());
+        // Probes stay open to everyone who can connect
+        assert!(authorize("health", peer(stranger), &config).is_ok());
+        assert!(authorize("describe", None, &config).is_ok());
+    }
+
+    #[test]
+    fn test_user_entries_and_levels() {
+        let stranger = peer::own_uid().wrapping_add(4242);
+        let mut config = Config::default();
+        config.acl_users.insert(stranger.to_string(), Access::Read);
+
+        assert!(authorize("capture", peer(stranger), &config).is_ok());
+        assert!(authorize("execute", peer(stranger), &config).is_err());
+        assert_eq!(required("some_future_action"), Access::Admin);
+
+        config.acl_users.insert("no-such-user-archy".to_string(), Access::Admin);
+        assert_eq!(validate(&config).len(), 1);
+    }
+}
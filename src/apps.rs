@@ -0,0 +1,744 @@
+// apps.rs - Index of installed desktop applications
+// `find_desktop_entry` and `list_applications` answer from an in-memory index of the .desktop files in
+// the applications directories (name, generic name, exec, icon, categories, as desktop.rs reads them)
+// instead of re-reading every directory per call. An inotify watcher marks the index stale whenever a
+// file in one of them is added, changed or removed, and the next lookup rebuilds it. A directory that
+// doesn't exist yet is covered by watching its nearest existing parent, so installing into a fresh
+// ~/.local/share/applications is seen too.
+//
+// The index is built under the path policy of the request that builds it - a change of
+// `[filesystem]` policy rebuilds it. Without inotify (or if the watcher dies) every lookup rebuilds,
+// which is what lookups did before the index.
+//
+// Flatpak and Snap apps are indexed from the directories they export desktop files to, and flatpak
+// apps `flatpak list` knows but that export nothing there are added by app id - each entry says which
+// it is (`source`), so the launcher can start it through `flatpak run` / `snap run`.
+//
+// Names are matched by score, not by first hit: an entry scores by its best field (file name, Name,
+// GenericName, Keywords, program) - equal, prefix, word start, substring, then letters in order
+// (skim-style, rewarding word starts and runs). `search` ranks them; `find` takes the best if it at
+// least contains the name, so "code" finds Visual Studio Code and offers VSCodium next to it.
+//
+// `running` tells whether an app runs: the daemon user's processes whose executable name is the app's
+// program (flatpak apps by `flatpak ps`, snaps by their snap.<name> cgroup), and the windows whose
+// class / app_id is the app's (its StartupWMClass, id, app id or program) or that belong to those
+// processes.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use crate::config::Config;
+use crate::desktop::{self, DesktopEntry};
+use crate::helpers::security::PathPolicy;
+use crate::windows;
+
+/// Shorter names only match exactly (no fuzzy `ls` -> "Files")
+const MIN_FUZZY_CHARS: usize = 4;
+
+/// Least score `find` takes a match at - a name containing the query. Scattered-letter matches are
+/// only offered as search results, never picked
+const MIN_FIND_SCORE: f64 = 0.6;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct App {
+    pub id: String, // file name without .desktop - what launch_gui_app takes
+    pub name: String, // in the daemon's locale
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generic_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>, // the tooltip-style description, in the daemon's locale
+    pub exec: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub program: Option<String>, // what Exec starts, without its directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    pub categories: Vec<String>,
+    pub keywords: Vec<String>, // in the daemon's locale
+    #[serde(skip)]
+    pub mime_types: Vec<String>, // what it opens (MimeType)
+    pub terminal: bool, // a terminal program - runs in a terminal window, not as a GUI app
+    pub hidden: bool, // NoDisplay or Hidden - installed, but not meant for menus
+    pub source: &'static str, // desktop, flatpak or snap
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_id: Option<String>, // flatpak app id or snap command - what `flatpak run` / `snap run` take
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>, // None for flatpak apps only `flatpak list` knows
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wm_class: Option<String>, // StartupWMClass - the class its windows carry
+    #[serde(skip)]
+    names: Vec<String>, // Name and GenericName, unlocalized and in the daemon's locale, then Keywords
+}
+
+impl App {
+    fn parse(id: &str, path: &Path, content: &str) -> App {
+        let entry = DesktopEntry::parse(content);
+        let packaging = entry.packaging();
+        let mut names: Vec<String> = [entry.get("Name"), entry.localized("Name"), entry.get("GenericName"), entry.localized("GenericName")]
+            .into_iter()
+            .flatten()
+            .filter(|name| !name.is_empty())
+            .collect();
+        names.dedup();
+        names.extend(entry.list("Keywords"));
+        App {
+            id: id.to_string(),
+            name: entry.localized("Name").unwrap_or_default(),
+            generic_name: entry.localized("GenericName").filter(|n| !n.is_empty()),
+            comment: entry.localized("Comment").filter(|c| !c.is_empty()),
+            exec: entry.get("Exec").unwrap_or_default(),
+            program: entry.program(),
+            icon: entry.get("Icon").filter(|i| !i.is_empty()),
+            categories: entry.list("Categories"),
+            keywords: entry.localized_list("Keywords"),
+            mime_types: entry.list("MimeType"),
+            terminal: entry.terminal(),
+            hidden: entry.is_hidden(),
+            source: packaging.name(),
+            app_id: packaging.app_id().map(str::to_string),
+            path: Some(path.to_path_buf()),
+            wm_class: entry.get("StartupWMClass").filter(|c| !c.is_empty()),
+            names,
+        }
+    }
+
+    /// A flatpak app known from `flatpak list` alone
+    fn flatpak(id: &str, name: &str) -> App {
+        App {
+            id: id.to_string(),
+            name: name.to_string(),
+            generic_name: None,
+            comment: None,
+            exec: String::new(),
+            program: None,
+            icon: Some(id.to_string()),
+            categories: Vec::new(),
+            keywords: Vec::new(),
+            mime_types: Vec::new(),
+            terminal: false,
+            hidden: false,
+            source: "flatpak",
+            app_id: Some(id.to_string()),
+            path: None,
+            wm_class: None,
+            names: vec![name.to_string()].into_iter().filter(|n| !n.is_empty()).collect(),
+        }
+    }
+
+    /// Whether a process is this app's, by its executable name, the first argument's base name, and
+    /// (snaps) its cgroup
+    fn owns_process(&self, comm: &str, argv0: &str, cgroup: &str) -> bool {
+        if self.source == "snap" {
+            let snap = self.app_id.as_deref().and_then(|command| command.split('.').next()).unwrap_or_default();
+            return !snap.is_empty() && cgroup.contains(&format!("snap.{}.", snap));
+        }
+        let Some(program) = self.program.as_deref().filter(|p| !p.is_empty()) else { return false };
+        let base = argv0.rsplit('/').next().unwrap_or(argv0);
+        // comm is cut to 15 bytes
+        base == program || comm == program || (program.len() > 15 && program.starts_with(comm) && comm.len() == 15)
+    }
+
+    /// Whether a window is this app's, by its class / app_id or its process
+    fn owns_window(&self, window: &windows::Window, pids: &[u32]) -> bool {
+        let class = window.app.to_lowercase();
+        let named = [self.wm_class.as_deref(), Some(self.id.as_str()), self.app_id.as_deref(), self.program.as_deref()]
+            .into_iter()
+            .flatten()
+            .any(|name| name.to_lowercase() == class);
+        named || window.pid.is_some_and(|pid| pids.iter().any(|&own| windows::descends_from(pid, own)))
+    }
+}
+
+/// Parsed entries in directory order, with lookup tables into them
+#[derive(Default)]
+pub struct Index {
+    apps: Vec<App>,
+    by_id: HashMap<String, usize>,       // first directory wins, as with XDG_DATA_DIRS
+    policy: String,                      // the policy the index was built under
+}
+
+impl Index {
+    /// Read every .desktop file in `dirs` the path policy allows (a symlink out of the allowed roots is skipped)
+    fn build(dirs: &[PathBuf], policy: &PathPolicy, policy_key: String) -> Index {
+        let mut index = Index { policy: policy_key, ..Index::default() };
+        for dir in dirs {
+            let Ok(entries) = fs::read_dir(dir) else { continue };
+            for path in entries.flatten().map(|e| e.path()) {
+                if path.extension().is_none_or(|ext| ext != "desktop") {
+                    continue;
+                }
+                let Some(id) = path.file_stem().map(|s| s.to_string_lossy().into_owned()) else { continue };
+                let content = match policy.check(&path) {
+                    Ok(checked) => fs::read_to_string(checked).unwrap_or_default(),
+                    Err(e) => {
+                        tracing::warn!("Skipping desktop file: {}", e);
+                        continue;
+                    }
+                };
+                index.insert(App::parse(&id, &path, &content));
+            }
+        }
+        index
+    }
+
+    /// Add the installed flatpak apps that have no desktop file here (`flatpak list` lines: id, name)
+    fn add_flatpaks(&mut self, listed: &str) {
+        for line in listed.lines() {
+            let mut columns = line.split('\t').map(str::trim);
+            let (Some(id), name) = (columns.next(), columns.next().unwrap_or("")) else { continue };
+            if desktop::is_flatpak_id(id) && !self.by_id.contains_key(id) {
+                self.insert(App::flatpak(id, name));
+            }
+        }
+    }
+
+    fn insert(&mut self, app: App) {
+        let position = self.apps.len();
+        self.by_id.entry(app.id.clone()).or_insert(position);
+        self.apps.push(app);
+    }
+
+    /// The entry a desktop file id names, exactly
+    pub fn get(&self, id: &str) -> Option<&App> {
+        self.by_id.get(id).map(|&i| &self.apps[i])
+    }
+
+    /// Ids of the entries whose MimeType lists `mime`, in directory order
+    pub fn handlers(&self, mime: &str) -> Vec<String> {
+        let mut seen = std::collections::HashSet::new();
+        self.apps.iter()
+            .filter(|app| seen.insert(app.id.as_str()) && app.mime_types.iter().any(|m| m.eq_ignore_ascii_case(mime)))
+            .map(|app| app.id.clone())
+            .collect()
+    }
+
+    /// The entry `app_name` means: the best match scoring at least MIN_FIND_SCORE
+    pub fn find(&self, app_name: &str) -> Option<&App> {
+        self.search(app_name, 1)
+            .into_iter()
+            .find(|m| m.score >= MIN_FIND_SCORE)
+            .map(|m| m.app)
+    }
+
+    /// Up to `limit` entries matching `query`, best first. Each is scored by its best field (file name,
+    /// Name, GenericName, Keywords, program); ties go to visible entries, then to directory order
+    pub fn search(&self, query: &str, limit: usize) -> Vec<Match<'_>> {
+        let wanted = query.trim().to_lowercase();
+        if wanted.is_empty() {
+            return Vec::new();
+        }
+        let exact_only = wanted.chars().count() < MIN_FUZZY_CHARS;
+        let mut matches: Vec<(usize, Match)> = self.apps
+            .iter()
+            .enumerate()
+            .filter_map(|(i, app)| {
+                let id = (app.id == query).then_some(1.0).or_else(|| match_score(&wanted, &app.id.to_lowercase()));
+                let score = app.names.iter().chain(&app.program)
+                    .filter_map(|field| match_score(&wanted, &field.to_lowercase()))
+                    .chain(id)
+                    .fold(0.0, f64::max);
+                (score > 0.0 && (!exact_only || score >= EXACT_SCORE)).then_some((i, Match { app, score }))
+            })
+            .collect();
+        matches.sort_by(|(i, a), (j, b)| {
+            b.score.total_cmp(&a.score).then(a.app.hidden.cmp(&b.app.hidden)).then(i.cmp(j))
+        });
+        // An id once, at its best - whichever file matched, the id launches the one that shadows the rest
+        let mut seen = std::collections::HashSet::new();
+        matches.into_iter()
+            .map(|(_, m)| m)
+            .filter(|m| seen.insert(m.app.id.as_str()))
+            .take(limit)
+            .collect()
+    }
+
+    pub fn apps(&self) -> &[App] {
+        &self.apps
+    }
+}
+
+/// An entry `search` found, scored 0-1
+#[derive(Debug, Serialize)]
+pub struct Match<'a> {
+    #[serde(flatten)]
+    pub app: &'a App,
+    pub score: f64,
+}
+
+/// A field equal to the query (a file name equal to it, case and all, scores 1)
+const EXACT_SCORE: f64 = 0.95;
+
+/// How well `field` matches `query` (both lowercase), skim-style: equal beats a prefix, which beats a
+/// word starting with the query, which beats containing it anywhere, which beats its letters in order.
+/// Within a tier the closer the lengths, the higher. None if the letters aren't all there in order
+fn match_score(query: &str, field: &str) -> Option<f64> {
+    if field.is_empty() {
+        return None;
+    }
+    let coverage = query.chars().count() as f64 / field.chars().count().max(1) as f64;
+    if field == query {
+        return Some(EXACT_SCORE);
+    }
+    if field.starts_with(query) {
+        return Some(0.8 + 0.1 * coverage);
+    }
+    if field.match_indices(query).any(|(at, _)| is_boundary(&field[..at])) {
+        return Some(0.7 + 0.1 * coverage);
+    }
+    if field.contains(query) {
+        return Some(0.6 + 0.1 * coverage);
+    }
+    subsequence_score(query, field).map(|quality| 0.5 * quality)
+}
+
+/// Whether a word starts after `before` (the text up to a position)
+fn is_boundary(before: &str) -> bool {
+    before.chars().next_back().is_some_and(|c| !c.is_alphanumeric())
+}
+
+/// Best placement of `query`'s letters, in order, inside `field`, as 0-1: each letter earns a point,
+/// more for starting a word or following the previous letter, less the further it is from it
+fn subsequence_score(query: &str, field: &str) -> Option<f64> {
+    const LETTER: f64 = 1.0;
+    const WORD_START: f64 = 1.0;
+    const CONSECUTIVE: f64 = 1.5;
+    const GAP: f64 = 0.1;
+
+    let query: Vec<char> = query.chars().collect();
+    let field: Vec<char> = field.chars().collect();
+    let word_start = |j: usize| j == 0 || !field[j - 1].is_alphanumeric();
+    // best[j]: best score with the current query letter placed at field[j]
+    let mut best: Vec<Option<f64>> = field.iter().enumerate()
+        .map(|(j, &c)| (c == query[0]).then(|| LETTER + if word_start(j) { WORD_START } else { 0.0 }))
+        .collect();
+    for &letter in &query[1..] {
+        let mut next = vec![None; field.len()];
+        for j in 0..field.len() {
+            if field[j] != letter {
+                continue;
+            }
+            let placed = LETTER + if word_start(j) { WORD_START } else { 0.0 };
+            next[j] = (0..j)
+                .filter_map(|k| best[k].map(|score| {
+                    let joined = if k + 1 == j { CONSECUTIVE } else { -GAP * (j - k - 1) as f64 };
+                    score + placed + joined
+                }))
+                .reduce(f64::max);
+        }
+        best = next;
+    }
+    let top = best.into_iter().flatten().reduce(f64::max)?;
+    let most = query.len() as f64 * (LETTER + WORD_START + CONSECUTIVE) - CONSECUTIVE;
+    Some((top / most).clamp(0.0, 1.0))
+}
+
+/// Where desktop files are looked up, highest precedence first
+fn directories() -> Vec<PathBuf> {
+    let home = std::env::var("HOME").unwrap_or_default();
+    let mut dirs = vec![
+        PathBuf::from(format!("{}/.local/share/applications", home)),
+        PathBuf::from("/usr/local/share/applications"),
+        PathBuf::from("/usr/share/applications"),
+        PathBuf::from("/usr/share/applications/kde4"),
+        PathBuf::from("/usr/share/applications/kde5"),
+        PathBuf::from(format!("{}/.config/applications", home)),
+        PathBuf::from("/opt/applications"),
+    ];
+    dirs.extend(package_directories());
+    dirs
+}
+
+/// Where flatpak (user and system installs) and snapd export their apps' desktop files
+pub fn package_directories() -> Vec<PathBuf> {
+    let home = std::env::var("HOME").unwrap_or_default();
+    vec![
+        PathBuf::from(format!("{}/.local/share/flatpak/exports/share/applications", home)),
+        PathBuf::from("/var/lib/flatpak/exports/share/applications"),
+        PathBuf::from("/var/lib/snapd/desktop/applications"),
+    ]
+}
+
+/// `flatpak list` output (id<TAB>name per line), empty without flatpak
+fn flatpak_list() -> String {
+    Command::new("flatpak")
+        .args(["list", "--app", "--columns=application,name"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+        .unwrap_or_default()
+}
+
+/// Whether an app runs, as far as the process table and the window list tell
+#[derive(Debug, Default, Serialize)]
+pub struct Running {
+    pub pids: Vec<u32>,
+    pub windows: Vec<windows::Window>,
+    #[serde(skip)]
+    pub backend: Option<windows::Backend>, // what listed the windows, to focus one
+}
+
+impl Running {
+    pub fn is_running(&self) -> bool {
+        !self.pids.is_empty() || !self.windows.is_empty()
+    }
+}
+
+/// The daemon user's processes and the windows that are `app`'s
+pub fn running(app: &App) -> Running {
+    let mut pids = if app.source == "flatpak" {
+        app.app_id.as_deref().map(flatpak_pids).unwrap_or_default()
+    } else {
+        process_pids(app)
+    };
+    pids.sort_unstable();
+    let backend = windows::Backend::detect().ok();
+    let windows = backend
+        .and_then(|backend| backend.list().ok())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|window| app.owns_window(window, &pids))
+        .collect();
+    Running { pids, windows, backend }
+}
+
+/// Processes of ours `app` owns, from /proc
+fn process_pids(app: &App) -> Vec<u32> {
+    use std::os::unix::fs::MetadataExt;
+    let own_uid = crate::peer::own_uid();
+    let Ok(entries) = fs::read_dir("/proc") else { return Vec::new() };
+    entries.flatten()
+        .filter_map(|entry| {
+            let pid: u32 = entry.file_name().to_str()?.parse().ok()?;
+            if entry.metadata().ok()?.uid() != own_uid || pid == std::process::id() {
+                return None;
+            }
+            let dir = entry.path();
+            let comm = fs::read_to_string(dir.join("comm")).unwrap_or_default();
+            let cmdline = fs::read(dir.join("cmdline")).unwrap_or_default();
+            let argv0 = cmdline.split(|&b| b == 0).next().map(String::from_utf8_lossy).unwrap_or_default();
+            let cgroup = if app.source == "snap" { fs::read_to_string(dir.join("cgroup")).unwrap_or_default() } else { String::new() };
+            app.owns_process(comm.trim(), &argv0, &cgroup).then_some(pid)
+        })
+        .collect()
+}
+
+/// Pids of the running instances of a flatpak app (`flatpak ps`)
+fn flatpak_pids(app_id: &str) -> Vec<u32> {
+    Command::new("flatpak")
+        .args(["ps", "--columns=child-pid,application"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .filter_map(|line| {
+                    let mut columns = line.split_whitespace();
+                    let pid = columns.next()?.parse().ok()?;
+                    (columns.next()? == app_id).then_some(pid)
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// inotify instance whose reader thread raises `stale` on every event
+struct Watcher {
+    fd: libc::c_int,
+    alive: &'static AtomicBool,
+}
+
+impl Watcher {
+    fn start(stale: &'static AtomicBool, alive: &'static AtomicBool) -> Option<Watcher> {
+        // SAFETY: plain syscall, the descriptor is owned by the reader thread for the life of the process
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            tracing::warn!("inotify unavailable ({}) - the application index is rebuilt on every lookup", std::io::Error::last_os_error());
+            return None;
+        }
+        alive.store(true, Ordering::SeqCst);
+        std::thread::spawn(move || {
+            let mut buffer = [0u8; 4096];
+            loop {
+                // SAFETY: reads into a buffer we own, at most its length
+                let read = unsafe { libc::read(fd, buffer.as_mut_ptr().cast(), buffer.len()) };
+                if read > 0 {
+                    stale.store(true, Ordering::SeqCst);
+                } else if read < 0 && std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                } else {
+                    tracing::warn!("Application index watcher stopped: {}", std::io::Error::last_os_error());
+                    alive.store(false, Ordering::SeqCst);
+                    stale.store(true, Ordering::SeqCst);
+                    return;
+                }
+            }
+        });
+        Some(Watcher { fd, alive })
+    }
+
+    /// Watch `dir`, or the nearest parent that exists while it doesn't (watching twice is harmless)
+    fn watch(&self, dir: &Path) {
+        let Some(existing) = dir.ancestors().find(|d| d.is_dir()) else { return };
+        let Ok(path) = CString::new(existing.as_os_str().as_bytes()) else { return };
+        let mask = libc::IN_CREATE | libc::IN_DELETE | libc::IN_CLOSE_WRITE | libc::IN_MOVED_FROM
+            | libc::IN_MOVED_TO | libc::IN_ATTRIB | libc::IN_DELETE_SELF | libc::IN_MOVE_SELF;
+        // SAFETY: `path` is a valid NUL-terminated string for the duration of the call
+        if unsafe { libc::inotify_add_watch(self.fd, path.as_ptr(), mask) } < 0 {
+            tracing::debug!("Cannot watch {}: {}", existing.display(), std::io::Error::last_os_error());
+        }
+    }
+
+    fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::SeqCst)
+    }
+}
+
+/// Set until the first build, then by the watcher
+static STALE: AtomicBool = AtomicBool::new(true);
+static WATCHER_ALIVE: AtomicBool = AtomicBool::new(false);
+static WATCHER: OnceLock<Option<Watcher>> = OnceLock::new();
+static INDEX: Mutex<Option<Index>> = Mutex::new(None);
+
+/// Run `f` on an up-to-date index
+pub fn with_index<T>(config: &Config, f: impl FnOnce(&Index) -> T) -> Result<T, String> {
+    let policy = config.path_policy()?;
+    let policy_key = format!("{:?}|{:?}|{}", config.fs_allowed_roots, config.fs_denied_paths, config.fs_max_depth);
+    let watcher = WATCHER.get_or_init(|| Watcher::start(&STALE, &WATCHER_ALIVE)).as_ref().filter(|w| w.is_alive());
+
+    let mut held = INDEX.lock().unwrap_or_else(|e| e.into_inner());
+    // Cleared before rescanning, so a change made during the scan marks it stale again
+    let stale = STALE.swap(false, Ordering::SeqCst) || watcher.is_none();
+    let current = held.as_ref().filter(|index| !stale && index.policy == policy_key);
+    if current.is_none() {
+        let dirs = directories();
+        if let Some(watcher) = watcher {
+            dirs.iter().for_each(|dir| watcher.watch(dir));
+        }
+        let mut index = Index::build(&dirs, &policy, policy_key);
+        index.add_flatpaks(&flatpak_list());
+        *held = Some(index);
+    }
+    Ok(f(held.as_ref().expect("index was just built")))
+}
+
+/// `list_applications`: {query?, category?, include_hidden?} - installed applications by name
+pub fn list(data: &serde_json::Value, config: &Config) -> Result<Vec<App>, String> {
+    let query = data.get("query").and_then(|v| v.as_str()).map(str::to_lowercase);
+    let category = data.get("category").and_then(|v| v.as_str());
+    let include_hidden = data.get("include_hidden").and_then(|v| v.as_bool()).unwrap_or(false);
+
+    with_index(config, |index| {
+        let mut seen = std::collections::HashSet::new();
+        let mut apps: Vec<App> = index.apps()
+            .iter()
+            // A file shadowed by one of the same name in an earlier directory isn't what launches
+            .filter(|app| seen.insert(app.id.as_str()))
+            .filter(|app| include_hidden || !app.hidden)
+            .filter(|app| category.is_none_or(|c| app.categories.iter().any(|own| own.eq_ignore_ascii_case(c))))
+            .filter(|app| query.as_deref().is_none_or(|q| {
+                app.names.iter().chain([&app.id]).chain(&app.program).any(|field| field.to_lowercase().contains(q))
+            }))
+            .cloned()
+            .collect();
+        apps.sort_by_key(|app| app.name.to_lowercase());
+        apps
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    const FIREFOX: &str = "[Desktop Entry]\nName=Firefox\nGenericName=Web Browser\nExec=/usr/lib/firefox/firefox %u\n\
+        Icon=firefox\nCategories=Network;WebBrowser;\n\n[Desktop Action new-window]\nName=New Window\nExec=firefox --new-window\n";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("archy-apps-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn open_policy(dir: &Path) -> PathPolicy {
+        PathPolicy::new(&[dir.to_string_lossy().into_owned()], &[], 32).unwrap()
+    }
+
+    #[test]
+    fn test_parse_desktop_entry() {
+        let app = App::parse("firefox", Path::new("/x/firefox.desktop"), FIREFOX);
+        assert_eq!(app.name, "Firefox");
+        assert_eq!(app.generic_name.as_deref(), Some("Web Browser"));
+        assert_eq!(app.icon.as_deref(), Some("firefox"));
+        assert_eq!(app.categories, vec!["Network", "WebBrowser"]);
+        assert_eq!(app.program.as_deref(), Some("firefox"));
+        assert!(!app.hidden);
+        // The action's Name and Exec belong to the action
+        assert_eq!(app.exec, "/usr/lib/firefox/firefox %u");
+    }
+
+    #[test]
+    fn test_index_lookups() {
+        let dir = temp_dir("lookup");
+        let (user, system) = (dir.join("user"), dir.join("system"));
+        fs::create_dir_all(&user).unwrap();
+        fs::create_dir_all(&system).unwrap();
+        fs::write(system.join("firefox.desktop"), FIREFOX).unwrap();
+        fs::write(system.join("org.gnome.Nautilus.desktop"), "[Desktop Entry]\nName=Files\nExec=nautilus --new-window\n").unwrap();
+        fs::write(system.join("gimp.desktop"), "[Desktop Entry]\nName=GNU Image Manipulation Program\nExec=gimp\n").unwrap();
+        fs::write(user.join("gimp.desktop"), "[Desktop Entry]\nName=GIMP (user)\nExec=gimp-2.10\n").unwrap();
+
+        let index = Index::build(&[user, system], &open_policy(&dir), String::new());
+        assert_eq!(index.find("firefox").unwrap().name, "Firefox");
+        assert_eq!(index.find("web browser").unwrap().id, "firefox");
+        assert_eq!(index.find("nautilus").unwrap().id, "org.gnome.Nautilus");
+        assert_eq!(index.find("FILES").unwrap().id, "org.gnome.Nautilus");
+        // The user's copy shadows the system one
+        assert_eq!(index.find("gimp").unwrap().name, "GIMP (user)");
+        assert_eq!(index.find("manipulation").unwrap().name, "GNU Image Manipulation Program");
+        // Short names never match partially
+        assert!(index.find("fil").is_none());
+        assert!(index.find("thunderbird").is_none());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_search_ranks_matches() {
+        let dir = temp_dir("search");
+        fs::write(dir.join("code.desktop"), "[Desktop Entry]\nName=Visual Studio Code\nExec=/usr/bin/code %F\n").unwrap();
+        fs::write(dir.join("codium.desktop"), "[Desktop Entry]\nName=VSCodium\nExec=/usr/bin/codium %F\nKeywords=vscode;\n").unwrap();
+        fs::write(dir.join("org.gnome.TextEditor.desktop"), "[Desktop Entry]\nName=Text Editor\nExec=gnome-text-editor\n").unwrap();
+        fs::write(dir.join("colordialog.desktop"), "[Desktop Entry]\nName=Color Dialog Editor\nExec=colordialog\nNoDisplay=true\n").unwrap();
+
+        let index = Index::build(std::slice::from_ref(&dir), &open_policy(&dir), String::new());
+        let ids = |query: &str| index.search(query, 10).iter().map(|m| m.app.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids("code")[..2], ["code", "codium"]);
+        assert_eq!(index.find("code").unwrap().id, "code");
+        let matches = index.search("code", 10);
+        assert!(matches.windows(2).all(|pair| pair[0].score >= pair[1].score));
+        assert_eq!(matches[0].score, 1.0);
+
+        // Letters in order are offered, but never picked
+        assert_eq!(ids("txtedit"), ["org.gnome.TextEditor"]);
+        assert!(index.find("txtedit").is_none());
+        assert_eq!(index.search("code", 1).len(), 1);
+        assert!(index.search("", 10).is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_match_score_tiers() {
+        let exact = match_score("firefox", "firefox").unwrap();
+        let prefix = match_score("fire", "firefox").unwrap();
+        let word = match_score("browser", "web browser").unwrap();
+        let inside = match_score("fox", "firefox").unwrap();
+        let scattered = match_score("ffx", "firefox").unwrap();
+        assert!(exact > prefix && prefix > word && word > inside && inside > scattered);
+        assert!(match_score("xyz", "firefox").is_none());
+        // A run of letters beats the same letters spread out
+        assert!(subsequence_score("edit", "xeditx").unwrap() > subsequence_score("edit", "exdxixt").unwrap());
+    }
+
+    #[test]
+    fn test_running_app_detection() {
+        let app = App::parse("org.mozilla.firefox", Path::new("/x/firefox.desktop"),
+            "[Desktop Entry]\nName=Firefox\nExec=/usr/lib/firefox/firefox %u\nStartupWMClass=Navigator\n");
+        assert!(app.owns_process("firefox", "/usr/lib/firefox/firefox", ""));
+        assert!(app.owns_process("firefox", "", ""));
+        assert!(!app.owns_process("firefox-bin-helper", "/usr/bin/python3", ""));
+
+        let window = |app: &str, pid: Option<u32>| windows::Window {
+            id: "1".to_string(), app: app.to_string(), title: String::new(), pid, workspace: None, focused: None,
+        };
+        assert!(app.owns_window(&window("navigator", None), &[]));
+        assert!(app.owns_window(&window("org.mozilla.firefox", None), &[]));
+        assert!(!app.owns_window(&window("foot", Some(1)), &[]));
+
+        let snap = App::parse("code_code", Path::new("/x/code_code.desktop"),
+            "[Desktop Entry]\nName=Code\nExec=env BAMF_DESKTOP_FILE_HINT=x /snap/bin/code %F\n");
+        assert!(snap.owns_process("code", "/snap/code/100/usr/share/code/code", "0::/user.slice/snap.code.code-1234.scope"));
+        assert!(!snap.owns_process("code", "/usr/bin/code", "0::/user.slice/session.scope"));
+
+        // A process of ours, found in /proc, and a window of it found by pid
+        let mut child = Command::new("sleep").arg("5").spawn().unwrap();
+        let sleeper = App::parse("sleep", Path::new("/x/sleep.desktop"), "[Desktop Entry]\nName=Sleep\nExec=sleep 5\n");
+        assert!(process_pids(&sleeper).contains(&child.id()));
+        assert!(sleeper.owns_window(&window("whatever", Some(child.id())), &[child.id()]));
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+
+    #[test]
+    fn test_index_respects_path_policy() {
+        let dir = temp_dir("policy");
+        let (allowed, outside) = (dir.join("allowed"), dir.join("outside"));
+        fs::create_dir_all(&allowed).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("evil.desktop"), "[Desktop Entry]\nName=Evil\nExec=evil\n").unwrap();
+        std::os::unix::fs::symlink(outside.join("evil.desktop"), allowed.join("evil.desktop")).unwrap();
+
+        let index = Index::build(std::slice::from_ref(&allowed), &open_policy(&allowed), String::new());
+        assert!(index.find("evil").is_none());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_sandboxed_apps() {
+        let dir = temp_dir("sandboxed");
+        fs::write(dir.join("org.gnome.Calculator.desktop"),
+            "[Desktop Entry]\nName=Calculator\nExec=/usr/bin/flatpak run --command=gnome-calculator org.gnome.Calculator\nX-Flatpak=org.gnome.Calculator\n").unwrap();
+        fs::write(dir.join("firefox_firefox.desktop"),
+            "[Desktop Entry]\nName=Firefox\nExec=env BAMF_DESKTOP_FILE_HINT=/var/lib/snapd/desktop/applications/firefox_firefox.desktop /snap/bin/firefox %u\n").unwrap();
+
+        let mut index = Index::build(std::slice::from_ref(&dir), &open_policy(&dir), String::new());
+        index.add_flatpaks("org.gnome.Calculator\tCalculator\ncom.spotify.Client\tSpotify\nnot-an-id\tNope\n");
+
+        let calculator = index.find("gnome-calculator").unwrap();
+        assert_eq!((calculator.source, calculator.app_id.as_deref()), ("flatpak", Some("org.gnome.Calculator")));
+        assert!(calculator.path.is_some());
+        let firefox = index.find("firefox").unwrap();
+        assert_eq!((firefox.id.as_str(), firefox.source, firefox.app_id.as_deref()), ("firefox_firefox", "snap", Some("firefox")));
+        // Known from `flatpak list` alone
+        let spotify = index.find("spotify").unwrap();
+        assert_eq!((spotify.id.as_str(), spotify.source, spotify.path.as_ref()), ("com.spotify.Client", "flatpak", None));
+        assert_eq!(index.apps().len(), 3);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_watcher_marks_stale() {
+        static STALE: AtomicBool = AtomicBool::new(false);
+        static ALIVE: AtomicBool = AtomicBool::new(false);
+        let dir = temp_dir("watch");
+        let Some(watcher) = Watcher::start(&STALE, &ALIVE) else { return };
+
+        // Not there yet - the parent is watched
+        let apps = dir.join("applications");
+        watcher.watch(&apps);
+        fs::create_dir_all(&apps).unwrap();
+        let wait = |flag: &AtomicBool| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while !flag.swap(false, Ordering::SeqCst) && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            Instant::now() < deadline
+        };
+        assert!(wait(&STALE));
+
+        watcher.watch(&apps);
+        fs::write(apps.join("new.desktop"), "[Desktop Entry]\nName=New\n").unwrap();
+        assert!(wait(&STALE));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,115 @@
+// artifacts.rs - Overflow artifact storage
+// Oversized outputs are spilled to files here; clients fetch them back with `get_artifact`
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::secrets;
+
+static ARTIFACT_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Artifact IDs are generated by us - only allow our own format back in (prevents path traversal)
+pub fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 64
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+fn artifact_path(dir: &str, id: &str) -> PathBuf {
+    PathBuf::from(dir).join(format!("{}.json", id))
+}
+
+/// Unique, path-safe ID (millis-pid-seq) - also used for batch checkpoints
+pub fn new_id() -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let seq = ARTIFACT_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{}-{}-{}", millis, std::process::id(), seq)
+}
+
+/// Store content as a new artifact and return its ID
+pub fn store(dir: &str, content: &str) -> Result<String, String> {
+    fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create artifact dir {}: {}", dir, e))?;
+
+    let id = new_id();
+
+    fs::write(artifact_path(dir, &id), secrets::redact(content))
+        .map_err(|e| format!("Failed to write artifact {}: {}", id, e))?;
+
+    Ok(id)
+}
+
+/// Load an artifact, optionally a byte window of it (offset/length are clamped to char boundaries)
+pub fn load(dir: &str, id: &str, offset: usize, length: Option<usize>) -> Result<String, String> {
+    if !is_valid_id(id) {
+        return Err("Invalid artifact_ref".to_string());
+    }
+
+    let content = fs::read_to_string(artifact_path(dir, id))
+        .map_err(|e| format!("Artifact {} not available: {}", id, e))?;
+
+    let mut start = offset.min(content.len());
+    while !content.is_char_boundary(start) {
+        start += 1;
+    }
+
+    let mut end = match length {
+        Some(len) => start.saturating_add(len).min(content.len()),
+        None => content.len(),
+    };
+    while !content.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    Ok(content[start..end.max(start)].to_string())
+}
+
+/// Cut a string to at most `max_bytes` on a char boundary
+pub fn truncate_to_bytes(s: &str, max_bytes: usize) -> &str {
+    if s.len() <= max_bytes {
+        return s;
+    }
+    let mut end = max_bytes;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir() -> String {
+        std::env::temp_dir()
+            .join(format!("archy-artifacts-test-{}", std::process::id()))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[test]
+    fn test_store_and_load_roundtrip() {
+        let dir = test_dir();
+        let id = store(&dir, "hello artifact").unwrap();
+        assert!(is_valid_id(&id));
+        assert_eq!(load(&dir, &id, 0, None).unwrap(), "hello artifact");
+        assert_eq!(load(&dir, &id, 6, Some(3)).unwrap(), "art");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_rejects_traversal() {
+        assert!(!is_valid_id("../../etc/passwd"));
+        assert!(load("/tmp", "../secret", 0, None).is_err());
+    }
+
+    #[test]
+    fn test_truncate_respects_char_boundary() {
+        assert_eq!(truncate_to_bytes("héllo", 2), "h");
+        assert_eq!(truncate_to_bytes("abc", 10), "abc");
+    }
+}
@@ -0,0 +1,272 @@
+// audit.rs - Append-only audit log of every request
+// One JSON line per request: what was asked, by whom and how it ended. With hash_chain each entry also
+// carries the previous entry's hash, so edited, inserted or deleted lines break the chain.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::canonical;
+use crate::config::Config;
+use crate::peer::PeerCred;
+use crate::secrets;
+
+/// Entry for the request being handled (the daemon serves one connection at a time)
+static CURRENT: Mutex<Option<AuditEntry>> = Mutex::new(None);
+
+/// Hash of the last chained entry, per log path - read from the file once, then kept up to date
+static TIP: Mutex<Option<(String, Option<String>)>> = Mutex::new(None);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub ts_ms: u64,
+    pub action: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub commands: Vec<String>,
+    pub session: Option<String>,
+    pub requester: Option<PeerCred>, // None when the kernel wouldn't say
+    #[serde(default)]
+    pub confirmed: bool, // ran through confirm_execute
+    pub status: String, // reply status ("success", "error", "confirmation_required", ...)
+    pub exit_code: Option<i64>,
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prev_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl AuditEntry {
+    fn chain_hash(&self) -> String {
+        canonical::content_hash(&AuditEntry { hash: None, ..self.clone() })
+    }
+}
+
+/// Start the entry for a request (called again after confirm_execute swaps in the held request)
+pub fn begin(action: &str, commands: Vec<String>, session: Option<String>, requester: Option<PeerCred>, confirmed: bool) {
+    let ts_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let mut current = CURRENT.lock().unwrap_or_else(|e| e.into_inner());
+    *current = Some(AuditEntry {
+        ts_ms,
+        action: action.to_string(),
+        commands: commands.iter().map(|c| secrets::redact(c)).collect(),
+        session,
+        requester,
+        confirmed,
+        status: String::new(),
+        exit_code: None,
+        error: None,
+        prev_hash: None,
+        hash: None,
+    });
+}
+
+/// Note the outcome from the reply about to be sent (the first reply of a request wins)
+pub fn record_reply(reply: &Value) {
+    let mut current = CURRENT.lock().unwrap_or_else(|e| e.into_inner());
+    let entry = match current.as_mut() {
+        Some(entry) if entry.status.is_empty() => entry,
+        _ => return,
+    };
+
+    let succeeded = reply.get("success").and_then(|v| v.as_bool()).unwrap_or(false);
+    entry.status = reply.get("status")
+        .and_then(|v| v.as_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| if succeeded { "success" } else { "error" }.to_string());
+    entry.exit_code = reply.get("exit_code").and_then(|v| v.as_i64());
+    entry.error = reply.get("error").and_then(|v| v.as_str()).map(secrets::redact);
+}
+
+/// Write the current request's entry - failures are logged, never surfaced to the client
+pub fn finish(config: &Config) {
+    let entry = match CURRENT.lock().unwrap_or_else(|e| e.into_inner()).take() {
+        Some(entry) => entry,
+        None => return,
+    };
+    if !config.audit_enabled {
+        return;
+    }
+    if let Err(e) = append(&config.audit_log, entry, config.audit_hash_chain) {
+        tracing::error!("Audit log write failed: {}", e);
+    }
+}
+
+/// Append one entry (0600 file, append-only writes), chaining it to the previous one if asked
+pub fn append(path: &str, mut entry: AuditEntry, hash_chain: bool) -> Result<(), String> {
+    if entry.status.is_empty() {
+        entry.status = "no_reply".to_string();
+    }
+
+    let mut tip = TIP.lock().unwrap_or_else(|e| e.into_inner());
+    if hash_chain {
+        let prev = match tip.as_ref() {
+            Some((tip_path, hash)) if tip_path == path => hash.clone(),
+            _ => read_entries(path)?.last().and_then(|e| e.hash.clone()),
+        };
+        entry.prev_hash = prev;
+        entry.hash = Some(entry.chain_hash());
+    }
+
+    if let Some(dir) = Path::new(path).parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    }
+    let line = serde_json::to_string(&entry).map_err(|e| e.to_string())?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|e| format!("Cannot open {}: {}", path, e))?;
+    writeln!(file, "{}", line).map_err(|e| format!("Cannot write {}: {}", path, e))?;
+
+    *tip = Some((path.to_string(), entry.hash));
+    Ok(())
+}
+
+fn read_entries(path: &str) -> Result<Vec<AuditEntry>, String> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Cannot read {}: {}", path, e)),
+    };
+    content.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| format!("{} line {}: {}", path, i + 1, e))
+        })
+        .collect()
+}
+
+/// Check every chained entry's hash and its link to the entry before it
+pub fn verify_chain(entries: &[AuditEntry]) -> Result<(), String> {
+    for (i, entry) in entries.iter().enumerate() {
+        let hash = match &entry.hash {
+            Some(hash) => hash,
+            None => continue,
+        };
+        if *hash != entry.chain_hash() {
+            return Err(format!("entry {} was modified (hash mismatch)", i + 1));
+        }
+        if i > 0 && entry.prev_hash != entries[i - 1].hash {
+            return Err(format!("chain broken before entry {} (entry missing or inserted)", i + 1));
+        }
+    }
+    Ok(())
+}
+
+/// `query_audit` filters - all optional
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    pub action: Option<String>,
+    pub session: Option<String>,
+    pub status: Option<String>,
+    pub since_ms: Option<u64>,
+    pub limit: Option<usize>, // newest N matches (default 100)
+    #[serde(default)]
+    pub verify: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditQueryResult {
+    pub success: bool,
+    pub total_matched: usize,
+    pub entries: Vec<AuditEntry>, // oldest first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain_valid: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain_error: Option<String>,
+}
+
+pub fn query(path: &str, query: &AuditQuery) -> Result<AuditQueryResult, String> {
+    let entries = read_entries(path)?;
+
+    let (chain_valid, chain_error) = if query.verify {
+        match verify_chain(&entries) {
+            Ok(()) => (Some(true), None),
+            Err(e) => (Some(false), Some(e)),
+        }
+    } else {
+        (None, None)
+    };
+
+    let matched: Vec<AuditEntry> = entries.into_iter()
+        .filter(|e| query.action.as_ref().is_none_or(|a| &e.action == a))
+        .filter(|e| query.session.as_ref().is_none_or(|s| e.session.as_ref() == Some(s)))
+        .filter(|e| query.status.as_ref().is_none_or(|s| &e.status == s))
+        .filter(|e| query.since_ms.is_none_or(|since| e.ts_ms >= since))
+        .collect();
+
+    let total_matched = matched.len();
+    let limit = query.limit.unwrap_or(100);
+    Ok(AuditQueryResult {
+        success: true,
+        total_matched,
+        entries: matched.into_iter().skip(total_matched.saturating_sub(limit)).collect(),
+        chain_valid,
+        chain_error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(action: &str) -> AuditEntry {
+        AuditEntry {
+            ts_ms: 1,
+            action: action.to_string(),
+            commands: vec!["ls".to_string()],
+            session: Some("s".to_string()),
+            requester: Some(PeerCred { pid: 1, uid: 1000, gid: 1000 }),
+            confirmed: false,
+            status: "success".to_string(),
+            exit_code: Some(0),
+            error: None,
+            prev_hash: None,
+            hash: None,
+        }
+    }
+
+    #[test]
+    fn test_chain_detects_tampering() {
+        let path = std::env::temp_dir()
+            .join(format!("archy-audit-test-{}/audit.jsonl", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        for action in ["execute", "capture", "execute_batch"] {
+            append(&path, entry(action), true).unwrap();
+        }
+
+        let all = query(&path, &AuditQuery { verify: true, ..AuditQuery::default() }).unwrap();
+        assert_eq!(all.chain_valid, Some(true));
+        let only = query(&path, &AuditQuery { action: Some("capture".to_string()), ..AuditQuery::default() }).unwrap();
+        assert_eq!(only.total_matched, 1);
+
+        let mut entries = read_entries(&path).unwrap();
+        entries[1].commands = vec!["rm -rf /".to_string()];
+        assert!(verify_chain(&entries).unwrap_err().contains("entry 2"));
+        entries.remove(1);
+        assert!(verify_chain(&entries).unwrap_err().contains("chain broken"));
+
+        let _ = fs::remove_dir_all(Path::new(&path).parent().unwrap());
+    }
+
+    #[test]
+    fn test_reply_outcome() {
+        begin("execute", vec!["false".to_string()], None, None, false);
+        record_reply(&serde_json::json!({"success": false, "status": "error", "exit_code": 1}));
+        record_reply(&serde_json::json!({"success": true}));
+        let entry = CURRENT.lock().unwrap().take().unwrap();
+        assert_eq!(entry.status, "error");
+        assert_eq!(entry.exit_code, Some(1));
+    }
+}
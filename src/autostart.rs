@@ -0,0 +1,247 @@
+// autostart.rs - XDG autostart entries ("launch at login")
+// The session starts every .desktop file in the autostart directories at login: $XDG_CONFIG_HOME/autostart
+// (~/.config/autostart) for the user, then each of $XDG_CONFIG_DIRS (/etc/xdg) /autostart. A user file
+// shadows a system file of the same name, and one with Hidden=true turns it off.
+//
+//   - `add_autostart` copies an installed application's desktop file into the user directory. The file
+//     is vetted the way launch_gui_app vets it (path policy, owner, permissions, age) and so is what it
+//     would run (TryExec installed, Exec well-formed, its program inside `[filesystem] exec_dirs`) -
+//     nothing from the request is ever written as Exec
+//   - `list_autostart` lists the effective entries, user over system
+//   - `remove_autostart` deletes the user file, and if a system entry remains it's disabled by a
+//     Hidden=true override in the user directory
+
+use serde::Serialize;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use crate::config::Config;
+use crate::desktop::{self, DesktopEntry};
+use crate::helpers::security::{canonical_dirs, validate_desktop_entry};
+
+/// An autostart entry as the session will see it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AutostartEntry {
+    pub id: String,
+    pub name: String,
+    pub exec: Option<String>,
+    pub path: String,
+    pub scope: &'static str, // "user" or "system"
+    pub enabled: bool, // not Hidden, not X-GNOME-Autostart-enabled=false
+    pub overrides_system: bool, // a user file shadowing a system one
+}
+
+/// What `remove` did
+#[derive(Debug, PartialEq)]
+pub enum Removed {
+    Deleted,  // the user entry is gone
+    Disabled, // a system entry is turned off by a Hidden=true user entry
+}
+
+/// The user autostart directory and the system ones, highest precedence first
+fn directories() -> (PathBuf, Vec<PathBuf>) {
+    let config_home = std::env::var("XDG_CONFIG_HOME").ok().filter(|dir| dir.starts_with('/'))
+        .unwrap_or_else(|| format!("{}/.config", std::env::var("HOME").unwrap_or_default()));
+    let config_dirs = std::env::var("XDG_CONFIG_DIRS").ok().filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/etc/xdg".to_string());
+    let system = config_dirs.split(':')
+        .filter(|dir| dir.starts_with('/'))
+        .map(|dir| Path::new(dir).join("autostart"))
+        .collect();
+    (Path::new(&config_home).join("autostart"), system)
+}
+
+/// `desktop_entry` from the request, checked like launch_gui_app's
+fn entry_id(data: &serde_json::Value) -> Result<&str, String> {
+    let id = data.get("desktop_entry").and_then(|v| v.as_str()).ok_or("Missing desktop_entry parameter")?;
+    validate_desktop_entry(id)?;
+    if id.is_empty() {
+        return Err("Invalid desktop_entry: empty".to_string());
+    }
+    Ok(id)
+}
+
+/// `add_autostart`: {desktop_entry, replace?} - start an installed application at login. The path of
+/// the written file
+pub fn add(data: &serde_json::Value, config: &Config) -> Result<PathBuf, String> {
+    let id = entry_id(data)?;
+    let replace = data.get("replace").and_then(|v| v.as_bool()).unwrap_or(false);
+
+    let candidates = crate::desktop_candidates(id, config)?;
+    let source = candidates.iter()
+        .find(|c| c.age.as_secs() >= config.desktop_trust_seconds)
+        .ok_or_else(|| match candidates.first() {
+            Some(recent) => format!(
+                "Desktop file {} was modified {}s ago - too recently to start it at every login",
+                recent.path.display(),
+                recent.age.as_secs()
+            ),
+            None => format!("No usable desktop file for '{}'", id),
+        })?;
+    let content = fs::read_to_string(&source.path)
+        .map_err(|e| format!("Cannot read {}: {}", source.path.display(), e))?;
+    vet(&content, &source.path, &canonical_dirs(&config.fs_exec_dirs))
+        .map_err(|e| format!("Refused to autostart '{}': {}", id, e))?;
+
+    let (user_dir, _) = directories();
+    let target = user_dir.join(format!("{}.desktop", id));
+    config.path_policy()?.check(&target)?;
+    write_entry(&target, &content, replace)?;
+    Ok(target)
+}
+
+/// What the session would run at login must be something launch_gui_app would start
+fn vet(content: &str, file: &Path, exec_dirs: &[String]) -> Result<(), String> {
+    let entry = DesktopEntry::parse(content);
+    if entry.get("Type").is_some_and(|t| t != "Application") {
+        return Err("it is not an application".to_string());
+    }
+    if entry.flag("Hidden") {
+        return Err("its desktop file is hidden (uninstalled)".to_string());
+    }
+    if let Some(try_exec) = entry.get("TryExec").filter(|t| !t.is_empty()) {
+        if desktop::resolve_program(&try_exec).is_none() {
+            return Err(format!("TryExec {} is not installed", try_exec));
+        }
+    }
+    let exec = entry.exec_argv(&[], Some(file))?;
+    let program = entry.packaging().runner().unwrap_or(exec).remove(0);
+    let resolved = desktop::resolve_program(&program).ok_or_else(|| format!("{} is not an installed program", program))?;
+    if !desktop::in_exec_dirs(&resolved, exec_dirs) {
+        return Err(format!("{} is outside the allowed program directories", resolved.display()));
+    }
+    Ok(())
+}
+
+/// Write `content` to `target` (0644, through a temp file so the session never reads half of it)
+fn write_entry(target: &Path, content: &str, replace: bool) -> Result<(), String> {
+    if !replace && target.exists() {
+        return Err(format!("{} already exists - pass replace: true to overwrite it", target.display()));
+    }
+    let dir = target.parent().ok_or("autostart entry has no directory")?;
+    fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    let temp = target.with_extension("desktop.tmp");
+    fs::write(&temp, content)
+        .and_then(|()| fs::set_permissions(&temp, fs::Permissions::from_mode(0o644)))
+        .and_then(|()| fs::rename(&temp, target))
+        .map_err(|e| {
+            let _ = fs::remove_file(&temp);
+            format!("Cannot write {}: {}", target.display(), e)
+        })
+}
+
+/// `list_autostart`: the entries the session starts (or has turned off), by id
+pub fn list() -> Vec<AutostartEntry> {
+    let (user_dir, system_dirs) = directories();
+    list_in(&user_dir, &system_dirs)
+}
+
+fn list_in(user_dir: &Path, system_dirs: &[PathBuf]) -> Vec<AutostartEntry> {
+    let mut entries: Vec<AutostartEntry> = Vec::new();
+    let dirs = std::iter::once((user_dir, "user")).chain(system_dirs.iter().map(|dir| (dir.as_path(), "system")));
+    for (dir, scope) in dirs {
+        for (id, path) in desktop_files(dir) {
+            if let Some(shadowing) = entries.iter_mut().find(|e| e.id == id) {
+                shadowing.overrides_system |= scope == "system" && shadowing.scope == "user";
+                continue;
+            }
+            let Ok(content) = fs::read_to_string(&path) else { continue };
+            let entry = DesktopEntry::parse(&content);
+            entries.push(AutostartEntry {
+                name: entry.localized("Name").unwrap_or_else(|| id.clone()),
+                exec: entry.get("Exec"),
+                enabled: !entry.flag("Hidden") && entry.get("X-GNOME-Autostart-enabled").is_none_or(|v| v != "false"),
+                path: path.to_string_lossy().to_string(),
+                overrides_system: false,
+                scope,
+                id,
+            });
+        }
+    }
+    entries.sort_by(|a, b| a.id.cmp(&b.id));
+    entries
+}
+
+/// (id, path) of the .desktop files directly in `dir`
+fn desktop_files(dir: &Path) -> Vec<(String, PathBuf)> {
+    let Ok(read) = fs::read_dir(dir) else { return Vec::new() };
+    read.filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| path.is_file())
+        .filter_map(|path| {
+            let id = path.file_name()?.to_str()?.strip_suffix(".desktop")?.to_string();
+            Some((id, path))
+        })
+        .collect()
+}
+
+/// `remove_autostart`: {desktop_entry} - stop starting it at login
+pub fn remove(data: &serde_json::Value, config: &Config) -> Result<Removed, String> {
+    let id = entry_id(data)?;
+    let (user_dir, system_dirs) = directories();
+    config.path_policy()?.check(&user_dir.join(format!("{}.desktop", id)))?;
+    remove_in(id, &user_dir, &system_dirs)
+}
+
+fn remove_in(id: &str, user_dir: &Path, system_dirs: &[PathBuf]) -> Result<Removed, String> {
+    let file = format!("{}.desktop", id);
+    let user = user_dir.join(&file);
+    let system = system_dirs.iter().map(|dir| dir.join(&file)).find(|path| path.is_file());
+    match system {
+        Some(system) => {
+            let name = fs::read_to_string(&system).ok()
+                .and_then(|content| DesktopEntry::parse(&content).get("Name"))
+                .unwrap_or_else(|| id.to_string());
+            let content = format!("[Desktop Entry]\nType=Application\nName={}\nHidden=true\n", name.replace('\n', " "));
+            write_entry(&user, &content, true)?;
+            Ok(Removed::Disabled)
+        }
+        None if user.is_file() => fs::remove_file(&user)
+            .map(|()| Removed::Deleted)
+            .map_err(|e| format!("Cannot remove {}: {}", user.display(), e)),
+        None => Err(format!("'{}' is not started at login", id)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_and_remove() {
+        let root = std::env::temp_dir().join(format!("archy-autostart-{}", std::process::id()));
+        let (user, system) = (root.join("user"), root.join("system"));
+        fs::create_dir_all(&user).unwrap();
+        fs::create_dir_all(&system).unwrap();
+        fs::write(user.join("mine.desktop"), "[Desktop Entry]\nName=Mine\nExec=/usr/bin/true\n").unwrap();
+        fs::write(system.join("agent.desktop"), "[Desktop Entry]\nName=Agent\nExec=/usr/bin/agent\n").unwrap();
+        fs::write(system.join("off.desktop"), "[Desktop Entry]\nName=Off\nExec=x\nX-GNOME-Autostart-enabled=false\n").unwrap();
+        let systems = [system.clone()];
+
+        let entries = list_in(&user, &systems);
+        assert_eq!(entries.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), ["agent", "mine", "off"]);
+        assert!(entries[0].enabled && entries[0].scope == "system");
+        assert!(!entries[2].enabled);
+
+        // A system entry can't be deleted - it's overridden with Hidden=true
+        assert_eq!(remove_in("agent", &user, &systems), Ok(Removed::Disabled));
+        let agent = list_in(&user, &systems).into_iter().find(|e| e.id == "agent").unwrap();
+        assert!(!agent.enabled && agent.overrides_system && agent.scope == "user");
+
+        assert_eq!(remove_in("mine", &user, &systems), Ok(Removed::Deleted));
+        assert!(!user.join("mine.desktop").exists());
+        assert!(remove_in("mine", &user, &systems).is_err());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_vet() {
+        let file = Path::new("/usr/share/applications/shell.desktop");
+        let sh = desktop::resolve_program("sh").unwrap();
+        let dirs = vec![fs::canonicalize(sh.parent().unwrap()).unwrap().to_string_lossy().to_string()];
+        assert!(vet("[Desktop Entry]\nName=Shell\nExec=sh -c true\n", file, &dirs).is_ok());
+        assert!(vet("[Desktop Entry]\nName=Shell\nExec=sh -c true\n", file, &["/nowhere".to_string()]).is_err());
+        assert!(vet("[Desktop Entry]\nName=Link\nType=Link\nURL=https://example.org\n", file, &dirs).is_err());
+        assert!(vet("[Desktop Entry]\nName=Gone\nExec=sh\nHidden=true\n", file, &dirs).is_err());
+    }
+}
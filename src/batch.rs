@@ -1,11 +1,87 @@
 // batch.rs - Batch command execution module
 // Executes multiple commands in sequence with structured result aggregation
 
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
 use crate::tmux;
-use crate::parser::parse_intelligently;
+use crate::parser::{parse_intelligently, Finding, Importance, RiskLevel};
+use crate::helpers::security::{check_blocked_patterns, quote_argv, validate_command};
 use crate::config::Config;
+use crate::output::DisplayOutput;
+use crate::artifacts;
+use crate::secrets;
+use crate::killswitch;
+use crate::peer;
+use crate::events;
+use crate::eventlog::{self, Kind};
+use crate::risk::{self, RiskAssessment, RiskClass};
+use crate::timings::{self, Phase};
+
+/// Default per-step wait when no `timeout_ms`, `max_waits[i]` or `max_wait` is given
+const DEFAULT_STEP_TIMEOUT_MS: u64 = 300_000;
+
+/// Upper bound for any step wait (matches wait_for_prompt's one hour cap)
+const MAX_STEP_TIMEOUT_MS: u64 = 3_600_000;
+
+/// Retry limits - a flaky step shouldn't be able to pin the batch forever
+const MAX_RETRIES: u32 = 10;
+const MAX_RETRY_BACKOFF_MS: u64 = 60_000;
+
+/// Stop auto-answering after this many prompts in one step (a wrong answer can loop forever)
+const MAX_AUTO_ANSWERS: usize = 10;
+
+/// The batch being run, for `introspect`
+static ACTIVE: Mutex<Option<ActiveBatch>> = Mutex::new(None);
+
+/// Where a running batch is
+#[derive(Debug, Clone, Serialize)]
+pub struct ActiveBatch {
+    pub batch_id: String,
+    pub session: String,
+    pub phase: &'static str, // "starting", "step", "parallel" or "rollback"
+    pub steps: Vec<usize>, // running now
+    pub completed: usize,
+    pub total: usize,
+    pub elapsed_ms: u64,
+    pub phase_elapsed_ms: u64,
+    #[serde(skip)]
+    started: Instant,
+    #[serde(skip)]
+    phase_started: Instant,
+}
+
+/// The batch in progress, if any
+pub fn active() -> Option<ActiveBatch> {
+    ACTIVE.lock().unwrap_or_else(|e| e.into_inner()).clone().map(|mut batch| {
+        batch.elapsed_ms = batch.started.elapsed().as_millis() as u64;
+        batch.phase_elapsed_ms = batch.phase_started.elapsed().as_millis() as u64;
+        batch
+    })
+}
+
+fn set_phase(phase: &'static str, steps: Vec<usize>, completed: usize) {
+    if let Some(batch) = ACTIVE.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        batch.phase = phase;
+        batch.steps = steps;
+        batch.completed = completed;
+        batch.phase_started = Instant::now();
+    }
+}
+
+/// Clears ACTIVE however run_batch ends
+struct ActiveGuard;
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        *ACTIVE.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
 
 /// Single command result in a batch
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,52 +90,713 @@ pub struct BatchCommandResult {
     pub command: String,
     pub explanation: String,
     pub success: bool,
-    pub status: String, // "success", "error", "timeout"
+    pub status: String, // "success", "warning", "error", "timeout", "skipped", "awaiting_input"
     pub output_preview: Option<String>,
     pub error: Option<String>,
+    pub duration_ms: u64,
+    /// Full DisplayOutput for the step (only with `include_outputs`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<Value>,
+    /// Set instead of `output` when the step's DisplayOutput is too large to inline
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifact_ref: Option<String>,
+    #[serde(default = "default_attempts")]
+    pub attempts: u32,
+    /// The step failed but declared `ignore_failure`, so the failure policy didn't react
+    #[serde(default)]
+    pub failure_ignored: bool,
+    /// Interactive prompt the step is blocked on (status "awaiting_input")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    /// Prompts answered automatically from `responses`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub answered_prompts: Vec<String>,
+    /// Parser findings for this step's output
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub findings: Vec<Finding>,
+    /// Risk class of the step's command (absent in checkpoints written before classification)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub risk: Option<RiskAssessment>,
+}
+
+fn default_attempts() -> u32 {
+    1
 }
 
 /// Overall batch execution result
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BatchExecutionResult {
+    pub batch_id: String,               // Pass to resume_batch to continue after a failure
+    pub resumed_from: Option<usize>,    // First step re-run when this result came from resume_batch
     pub total_commands: usize,
     pub successful: usize,
     pub failed: usize,
+    pub timed_out: usize,
+    pub skipped: usize,
+    pub ignored_failures: usize,
+    pub duration_ms: u64,
+    pub wall_time_saved_ms: u64, // Sum of parallel step durations minus their groups' wall time
     pub commands: Vec<BatchCommandResult>,
+    pub rollback: Option<RollbackReport>, // Set when failure_policy: rollback kicked in
+    pub awaiting_input: Option<usize>,    // Step paused at a prompt - answer via resume_batch {input}
+    pub aggregate: BatchAggregate,
     pub summary: String,
 }
 
 impl BatchExecutionResult {
     pub fn new() -> Self {
         Self {
+            batch_id: String::new(),
+            resumed_from: None,
             total_commands: 0,
             successful: 0,
             failed: 0,
+            timed_out: 0,
+            skipped: 0,
+            ignored_failures: 0,
+            duration_ms: 0,
+            wall_time_saved_ms: 0,
             commands: Vec::new(),
+            rollback: None,
+            awaiting_input: None,
+            aggregate: BatchAggregate::default(),
             summary: String::new(),
         }
     }
 }
 
+/// Cross-step summary pass: one finding list and one sentence for the whole batch
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchAggregate {
+    pub total_errors: usize,            // Error-level findings plus failed/timed-out steps
+    pub most_severe: Option<Finding>,
+    pub needs_attention: Vec<usize>,    // Steps that failed, timed out, wait for input or raised Critical/High findings
+    pub findings: Vec<Finding>,         // Every step's findings (tagged with the step), most severe first
+    pub summary: String,
+}
+
+/// Outcome of running compensations after a failure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RollbackReport {
+    pub triggered_by: usize,               // Step whose failure started the rollback
+    pub complete: bool,                    // Every compensation succeeded
+    pub steps: Vec<BatchCommandResult>,    // In execution (reverse) order; index = compensated step
+    pub not_compensated: Vec<usize>,       // Completed steps that declared no compensation or weren't undone after panic_stop
+}
+
+/// What to do with the remaining steps once a step fails or times out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailurePolicy {
+    Abort,          // Skip everything after the failed step (default)
+    Continue,       // Run every step regardless
+    SkipDependents, // Skip only steps whose `depends_on` includes a failed/skipped step
+    Rollback,       // Abort, then run `compensations` of completed steps in reverse order
+}
+
+impl FailurePolicy {
+    pub fn from_request(data: &Value) -> Result<Self, String> {
+        match data.get("failure_policy").and_then(|v| v.as_str()) {
+            None | Some("abort") => Ok(FailurePolicy::Abort),
+            Some("continue") => Ok(FailurePolicy::Continue),
+            Some("skip_dependents") | Some("skip-dependents") => Ok(FailurePolicy::SkipDependents),
+            Some("rollback") => Ok(FailurePolicy::Rollback),
+            Some(other) => Err(format!(
+                "Unknown failure_policy '{}' (supported: abort, continue, skip_dependents, rollback)", other
+            )),
+        }
+    }
+}
+
+/// What to do when a step stops at a prompt no `responses` entry answers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptPolicy {
+    Pause, // Stop the batch with status "awaiting_input" (default)
+    Abort, // Interrupt the command (Ctrl-C) and fail the step
+}
+
+impl PromptPolicy {
+    pub fn from_request(data: &Value) -> Result<Self, String> {
+        match data.get("prompt_policy").and_then(|v| v.as_str()) {
+            None | Some("pause") => Ok(PromptPolicy::Pause),
+            Some("abort") => Ok(PromptPolicy::Abort),
+            Some(other) => Err(format!("Unknown prompt_policy '{}' (supported: pause, abort)", other)),
+        }
+    }
+}
+
+/// Server-side condition on an earlier step, e.g. {"step": 2, "status": "success"}
+/// or {"step": 1, "output_matches": "inactive"} - all given predicates must hold
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunCondition {
+    pub step: usize,
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default)]
+    pub output_matches: Option<String>,
+    #[serde(default)]
+    pub output_not_matches: Option<String>,
+}
+
+impl RunCondition {
+    /// Evaluate against the earlier step's result and full output; Err carries the reason it failed
+    pub fn evaluate(&self, result: Option<&BatchCommandResult>, output: &str) -> Result<(), String> {
+        let result = result.ok_or_else(|| format!("step {} has no result", self.step))?;
+
+        if let Some(status) = &self.status {
+            if &result.status != status {
+                return Err(format!("step {} status '{}' (expected '{}')", self.step, result.status, status));
+            }
+        }
+
+        if let Some(pattern) = &self.output_matches {
+            let re = Regex::new(pattern).map_err(|e| format!("invalid regex: {}", e))?;
+            if !re.is_match(output) {
+                return Err(format!("step {} output does not match '{}'", self.step, pattern));
+            }
+        }
+
+        if let Some(pattern) = &self.output_not_matches {
+            let re = Regex::new(pattern).map_err(|e| format!("invalid regex: {}", e))?;
+            if re.is_match(output) {
+                return Err(format!("step {} output matches '{}'", self.step, pattern));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// One step of a batch, resolved from the parallel request arrays
+#[derive(Debug, Clone)]
+pub struct BatchStep {
+    pub index: usize,           // 1-based position in `commands`
+    pub command: String,
+    pub explanation: String,
+    pub timeout_ms: u64,
+    pub retries: u32,             // Extra attempts after an error (timeouts are not retried)
+    pub retry_backoff_ms: u64,    // Doubles after every attempt
+    pub ignore_failure: bool,     // Failure doesn't trigger the failure policy
+    pub depends_on: Vec<usize>, // 1-based step indices
+    pub run_if: Option<RunCondition>,
+    pub compensation: Option<String>, // Undo command run on rollback
+    pub responses: Vec<(String, String)>, // Prompt pattern (case-insensitive regex) -> answer
+}
+
+impl BatchStep {
+    /// Answer for an interactive prompt, if any pattern matches it
+    fn answer_for(&self, prompt: &str) -> Option<&str> {
+        self.responses.iter()
+            .find(|(pattern, _)| match regex::RegexBuilder::new(pattern).case_insensitive(true).build() {
+                Ok(re) => re.is_match(prompt),
+                Err(_) => prompt.to_lowercase().contains(&pattern.to_lowercase()),
+            })
+            .map(|(_, answer)| answer.as_str())
+    }
+}
+
+/// Object form of a batch command - every field except `command` is optional
+#[derive(Debug, Clone, Default, Deserialize)]
+struct StepSpec {
+    command: String,
+    explanation: Option<String>,
+    timeout_ms: Option<u64>,
+    retries: Option<u32>,
+    retry_backoff_ms: Option<u64>,
+    #[serde(default)]
+    ignore_failure: bool,
+    depends_on: Option<Vec<usize>>,
+    run_if: Option<RunCondition>,
+    compensation: Option<String>,
+    responses: Option<BTreeMap<String, String>>, // Sorted, so matching order is deterministic
+}
+
+/// Turn the `argv` form (the request's, or a step object's) into the equivalent quoted `command`,
+/// so every later check and the audit log see exactly what will run
+pub fn resolve_argv(data: &mut Value) -> Result<(), String> {
+    fn resolve(object: &mut serde_json::Map<String, Value>, what: &str) -> Result<(), String> {
+        let argv = match object.remove("argv") {
+            Some(argv) => argv,
+            None => return Ok(()),
+        };
+        if object.contains_key("command") {
+            return Err(format!("{} has both 'command' and 'argv' - send one", what));
+        }
+        let argv: Vec<String> = serde_json::from_value(argv)
+            .map_err(|_| format!("{}: 'argv' must be an array of strings", what))?;
+        let command = quote_argv(&argv).map_err(|e| format!("{}: {}", what, e))?;
+        object.insert("command".to_string(), Value::String(command));
+        Ok(())
+    }
+
+    let object = match data.as_object_mut() {
+        Some(object) => object,
+        None => return Ok(()),
+    };
+    resolve(object, "Request")?;
+    if let Some(Value::Array(steps)) = object.get_mut("commands") {
+        for (idx, step) in steps.iter_mut().enumerate() {
+            if let Value::Object(step) = step {
+                resolve(step, &format!("Step {}", idx + 1))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Resolve `commands` (plain strings or step objects) plus the optional parallel arrays
+/// `explanations`, `max_waits`, `depends_on`, `run_if` and `compensations` - object fields win.
+/// Step objects may also carry `responses` for interactive prompts
+pub fn parse_steps(data: &Value) -> Result<Vec<BatchStep>, String> {
+    let commands_arr = data
+        .get("commands")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| "Missing or invalid 'commands' array".to_string())?;
+
+    let nth = |key: &str, idx: usize| -> Option<&Value> {
+        data.get(key).and_then(|v| v.as_array()).and_then(|arr| arr.get(idx))
+    };
+
+    let mut steps = Vec::new();
+    for (idx, cmd_val) in commands_arr.iter().enumerate() {
+        let spec = match cmd_val {
+            Value::String(cmd) => StepSpec { command: cmd.clone(), ..StepSpec::default() },
+            Value::Object(_) => serde_json::from_value(cmd_val.clone())
+                .map_err(|e| format!("Invalid step {}: {}", idx + 1, e))?,
+            _ => continue,
+        };
+
+        let command = spec.command.trim().to_string();
+        if command.is_empty() {
+            continue;
+        }
+
+        let explanation = spec.explanation.unwrap_or_else(|| {
+            nth("explanations", idx)
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string()
+        });
+
+        // Per-step timeout overrides the batch-wide max_wait (seconds, kept for compatibility)
+        let timeout_ms = spec.timeout_ms
+            .or_else(|| nth("max_waits", idx).and_then(|v| v.as_u64()).map(|s| s * 1000))
+            .or_else(|| data.get("max_wait").and_then(|v| v.as_u64()).map(|s| s * 1000))
+            .unwrap_or(DEFAULT_STEP_TIMEOUT_MS)
+            .min(MAX_STEP_TIMEOUT_MS);
+
+        let depends_on: Vec<usize> = spec.depends_on.unwrap_or_else(|| {
+            nth("depends_on", idx)
+                .and_then(|v| v.as_array())
+                .map(|deps| deps.iter().filter_map(|d| d.as_u64()).map(|d| d as usize).collect())
+                .unwrap_or_default()
+        });
+
+        if let Some(bad) = depends_on.iter().find(|&&d| d == 0 || d > idx) {
+            return Err(format!(
+                "Step {} depends_on {} - steps can only depend on earlier steps", idx + 1, bad
+            ));
+        }
+
+        let run_if: Option<RunCondition> = match (spec.run_if, nth("run_if", idx)) {
+            (Some(condition), _) => Some(condition),
+            (None, None) | (None, Some(Value::Null)) => None,
+            (None, Some(value)) => Some(
+                serde_json::from_value(value.clone())
+                    .map_err(|e| format!("Invalid run_if for step {}: {}", idx + 1, e))?,
+            ),
+        };
+
+        if let Some(condition) = &run_if {
+            if condition.step == 0 || condition.step > idx {
+                return Err(format!(
+                    "Step {} run_if references step {} - conditions can only use earlier steps",
+                    idx + 1, condition.step
+                ));
+            }
+        }
+
+        let compensation = spec.compensation
+            .or_else(|| nth("compensations", idx).and_then(|v| v.as_str()).map(|c| c.to_string()))
+            .map(|c| c.trim().to_string())
+            .filter(|c| !c.is_empty());
+
+        // Batch-wide `responses` apply to every step; step entries win on conflicts
+        let mut responses: Vec<(String, String)> = spec.responses
+            .map(|r| r.into_iter().collect())
+            .unwrap_or_default();
+        if let Some(shared) = data.get("responses").and_then(|v| v.as_object()) {
+            for (pattern, answer) in shared {
+                if let Some(answer) = answer.as_str() {
+                    if !responses.iter().any(|(p, _)| p == pattern) {
+                        responses.push((pattern.clone(), answer.to_string()));
+                    }
+                }
+            }
+        }
+
+        steps.push(BatchStep {
+            index: idx + 1,
+            command,
+            explanation,
+            timeout_ms,
+            retries: spec.retries.unwrap_or(0).min(MAX_RETRIES),
+            retry_backoff_ms: spec.retry_backoff_ms.unwrap_or(1000).min(MAX_RETRY_BACKOFF_MS),
+            ignore_failure: spec.ignore_failure,
+            depends_on,
+            run_if,
+            compensation,
+            responses,
+        });
+    }
+
+    Ok(steps)
+}
+
+/// Resolve `parallel_groups` (arrays of 1-based step indices run concurrently in their own panes)
+pub fn parse_parallel_groups(data: &Value, steps: &[BatchStep]) -> Result<Vec<Vec<usize>>, String> {
+    let groups = match data.get("parallel_groups").and_then(|v| v.as_array()) {
+        Some(groups) => groups,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut seen: Vec<usize> = Vec::new();
+    let mut parsed = Vec::new();
+
+    for group in groups {
+        let members: Vec<usize> = group.as_array()
+            .ok_or_else(|| "parallel_groups entries must be arrays of step indices".to_string())?
+            .iter()
+            .map(|v| v.as_u64().map(|i| i as usize).ok_or_else(|| "Invalid step index in parallel_groups".to_string()))
+            .collect::<Result<_, _>>()?;
+
+        for &member in &members {
+            let step = steps.iter().find(|s| s.index == member)
+                .ok_or_else(|| format!("parallel_groups references unknown step {}", member))?;
+
+            if seen.contains(&member) {
+                return Err(format!("Step {} appears in more than one parallel group", member));
+            }
+            seen.push(member);
+
+            // Members run at the same time, so they can't observe each other
+            let references_member = step.depends_on.iter().any(|d| members.contains(d))
+                || step.run_if.as_ref().is_some_and(|c| members.contains(&c.step));
+            if references_member {
+                return Err(format!(
+                    "Step {} depends on another step in its own parallel group", member
+                ));
+            }
+        }
+
+        if members.len() > 1 {
+            parsed.push(members);
+        }
+    }
+
+    Ok(parsed)
+}
+
+/// Per-step execution settings shared by sequential and parallel steps
+struct StepOptions<'a> {
+    interval_ms: u64,
+    include_output: bool, // Attach the full DisplayOutput to every step
+    prompt_policy: PromptPolicy,
+    config: &'a Config,
+}
+
+impl<'a> StepOptions<'a> {
+    fn from_request(data: &Value, config: &'a Config) -> Result<Self, String> {
+        Ok(StepOptions {
+            interval_ms: data
+                .get("interval_ms")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(config.poll_interval_ms)
+                .max(100),
+            include_output: data.get("include_outputs").and_then(|v| v.as_bool()).unwrap_or(false),
+            prompt_policy: PromptPolicy::from_request(data)?,
+            config,
+        })
+    }
+}
+
+/// Mutable state while a batch runs
+struct BatchRun {
+    batch_id: String,
+    request: Value,                    // Original payload, replayed by resume_batch
+    state_dir: String,
+    policy: FailurePolicy,
+    result: BatchExecutionResult,
+    unsuccessful: Vec<usize>,          // Step indices that failed, timed out or were skipped
+    aborted_by: Option<usize>,
+    paused_at: Option<usize>,          // Step waiting for input - nothing after it may run
+    outputs: HashMap<usize, String>,   // Full output per executed step, for run_if output predicates
+}
+
+impl BatchRun {
+    /// Why a step must not run (failure policy first, then run_if)
+    fn skip_reason(&self, step: &BatchStep) -> Option<String> {
+        if killswitch::is_stopped() {
+            return Some(STOP_REASON.to_string());
+        }
+
+        // The pane is blocked on a prompt - typing more commands into it would answer the prompt
+        if let Some(waiting) = self.paused_at {
+            return Some(format!("step {} is waiting for input", waiting));
+        }
+
+        let reason = match (self.policy, self.aborted_by) {
+            (FailurePolicy::Abort, Some(failed)) => {
+                Some(format!("step {} failed (failure_policy: abort)", failed))
+            }
+            (FailurePolicy::Rollback, Some(failed)) => {
+                Some(format!("step {} failed (failure_policy: rollback)", failed))
+            }
+            (FailurePolicy::SkipDependents, _) => step.depends_on.iter()
+                .find(|d| self.unsuccessful.contains(d))
+                .map(|d| format!("depends on step {} which did not succeed", d)),
+            _ => None,
+        };
+
+        // run_if is only checked for steps the failure policy would still run
+        reason.or_else(|| {
+            step.run_if.as_ref().and_then(|condition| {
+                let earlier = self.result.commands.iter().find(|r| r.index == condition.step);
+                let output = self.outputs.get(&condition.step).map(String::as_str).unwrap_or("");
+                condition.evaluate(earlier, output)
+                    .err()
+                    .map(|reason| format!("run_if not met: {}", reason))
+            })
+        })
+    }
+
+    fn record(&mut self, step_result: BatchCommandResult, output: Option<String>) {
+        if step_result.status == "awaiting_input" {
+            self.paused_at = Some(step_result.index);
+            self.result.awaiting_input = Some(step_result.index);
+            if let Some(output) = output {
+                self.outputs.insert(step_result.index, output);
+            }
+            self.result.commands.push(step_result);
+            self.save_checkpoint(false);
+            return;
+        }
+
+        match step_result.status.as_str() {
+            _ if step_result.failure_ignored => self.result.ignored_failures += 1,
+            "skipped" => self.result.skipped += 1,
+            "timeout" => self.result.timed_out += 1,
+            _ if step_result.success => self.result.successful += 1,
+            _ => self.result.failed += 1,
+        }
+
+        if !step_result.success && !step_result.failure_ignored {
+            self.unsuccessful.push(step_result.index);
+            if step_result.status != "skipped" && self.aborted_by.is_none() {
+                self.aborted_by = Some(step_result.index);
+            }
+        }
+
+        if let Some(output) = output {
+            self.outputs.insert(step_result.index, output);
+        }
+
+        self.result.commands.push(step_result);
+        self.save_checkpoint(false);
+    }
+
+    /// Persist progress after every step so a restart or failure can resume from here
+    fn save_checkpoint(&self, complete: bool) {
+        let checkpoint = BatchCheckpoint {
+            batch_id: self.batch_id.clone(),
+            request: self.request.clone(),
+            results: self.result.commands.clone(),
+            outputs: self.outputs.clone(),
+            complete,
+        };
+        if let Err(e) = save_checkpoint(&self.state_dir, &checkpoint) {
+            tracing::warn!("Failed to checkpoint batch {}: {}", self.batch_id, e);
+        }
+    }
+}
+
+/// Persisted batch state (one JSON file per batch in `batch_state_dir`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchCheckpoint {
+    pub batch_id: String,
+    pub request: Value,
+    pub results: Vec<BatchCommandResult>,
+    pub outputs: HashMap<usize, String>,
+    pub complete: bool, // Every step has a result (successful or not)
+}
+
+fn checkpoint_path(dir: &str, batch_id: &str) -> std::path::PathBuf {
+    std::path::PathBuf::from(dir).join(format!("{}.json", batch_id))
+}
+
+/// Checkpoints are replayed as commands, so only this user's own, unshared files are trusted
+fn check_private(path: &Path) -> Result<(), String> {
+    let meta = std::fs::metadata(path).map_err(|e| format!("Cannot inspect {}: {}", path.display(), e))?;
+    if meta.uid() != peer::own_uid() {
+        return Err(format!("{} is owned by uid {} - refusing it", path.display(), meta.uid()));
+    }
+    if meta.mode() & 0o022 != 0 {
+        return Err(format!("{} is writable by other users - refusing it", path.display()));
+    }
+    Ok(())
+}
+
+pub fn save_checkpoint(dir: &str, checkpoint: &BatchCheckpoint) -> Result<(), String> {
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .map_err(|e| format!("Failed to create batch state dir {}: {}", dir, e))?;
+    check_private(Path::new(dir))?;
+    let json = serde_json::to_string(checkpoint)
+        .map_err(|e| format!("Serialization error: {}", e))?;
+    let json = secrets::redact(&json);
+
+    // Write-then-rename so a crash mid-write never leaves a truncated checkpoint
+    let path = checkpoint_path(dir, &checkpoint.batch_id);
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, json)
+        .and_then(|_| std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600)))
+        .and_then(|_| std::fs::rename(&tmp, &path))
+        .map_err(|e| format!("Failed to write checkpoint: {}", e))
+}
+
+pub fn load_checkpoint(dir: &str, batch_id: &str) -> Result<BatchCheckpoint, String> {
+    // Batch IDs are generated by us - same format as artifact IDs
+    if !artifacts::is_valid_id(batch_id) {
+        return Err("Invalid batch_id".to_string());
+    }
+
+    let path = checkpoint_path(dir, batch_id);
+    if !path.exists() {
+        return Err(format!("Batch {} not found", batch_id));
+    }
+    check_private(Path::new(dir)).and_then(|()| check_private(&path))?;
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Batch {} not found: {}", batch_id, e))?;
+    serde_json::from_str(&content)
+        .map_err(|e| format!("Corrupt checkpoint for batch {}: {}", batch_id, e))
+}
+
+/// Skip reason for steps that came up while panic_stop was engaged
+const STOP_REASON: &str = "emergency stop engaged";
+
+/// Results worth keeping on resume: everything before the first error/timeout, or the first step
+/// the emergency stop skipped (it never got its turn - policy and run_if skips did)
+fn resumable_prefix(results: &[BatchCommandResult]) -> Vec<BatchCommandResult> {
+    let mut sorted = results.to_vec();
+    sorted.sort_by_key(|r| r.index);
+    sorted.into_iter()
+        .take_while(|r| {
+            r.failure_ignored
+                || (r.status != "error" && r.status != "timeout" && r.status != "awaiting_input" && !skipped_by_stop(r))
+        })
+        .collect()
+}
+
+fn skipped_by_stop(result: &BatchCommandResult) -> bool {
+    result.status == "skipped"
+        && result.error.as_deref().and_then(|e| e.strip_prefix("Skipped: ")) == Some(STOP_REASON)
+}
+
 /// Execute a batch of commands and return structured result
 pub fn execute_batch(
     data: &Value,
     config: &Config,
 ) -> Result<BatchExecutionResult, String> {
-    // Extract commands array
-    let commands_arr = data
-        .get("commands")
-        .and_then(|v| v.as_array())
-        .ok_or_else(|| "Missing or invalid 'commands' array".to_string())?;
+    run_batch(data, config, artifacts::new_id(), Vec::new(), HashMap::new())
+}
+
+/// Resume a checkpointed batch from its first failed (or never-run) step
+/// With `input`, a step paused at a prompt gets the answer typed in and is finished first.
+/// The caller gates `checkpoint.request` like a new batch before calling this.
+pub fn resume_batch(checkpoint: BatchCheckpoint, input: Option<&str>, config: &Config) -> Result<BatchExecutionResult, String> {
+    let batch_id = checkpoint.batch_id.as_str();
+
+    let mut kept = resumable_prefix(&checkpoint.results);
+    let steps = parse_steps(&checkpoint.request)?;
+    if checkpoint.complete && kept.len() == steps.len() {
+        return Err(format!("Batch {} already completed - nothing to resume", batch_id));
+    }
+
+    let mut outputs: HashMap<usize, String> = checkpoint.outputs.into_iter()
+        .filter(|(index, _)| kept.iter().any(|r| r.index == *index))
+        .collect();
+
+    let waiting = checkpoint.results.iter().find(|r| r.status == "awaiting_input");
+    match (waiting, input) {
+        (Some(waiting), Some(input)) => {
+            let step = steps.iter().find(|s| s.index == waiting.index)
+                .ok_or_else(|| format!("Step {} no longer exists", waiting.index))?;
+            let options = StepOptions::from_request(&checkpoint.request, config)?;
+
+            // Parallel steps wait in their own session
+            let session = config.get_session(&checkpoint.request);
+            let pane = format!("{}-par-{}", session, step.index);
+            let session = if tmux::has_session(&pane) { pane } else { session.to_string() };
+
+            tmux::send_input(&session, input)?;
+            let (mut step_result, output) = finish_step(&session, step, &options, std::time::Instant::now());
+            step_result.answered_prompts = waiting.answered_prompts.clone();
+            if let Some(prompt) = &waiting.prompt {
+                step_result.answered_prompts.push(prompt.clone());
+            }
+            outputs.insert(step.index, output);
+            kept.push(step_result);
+        }
+        (Some(waiting), None) => {
+            return Err(format!(
+                "Step {} is waiting for input ({}) - pass `input` to answer it",
+                waiting.index,
+                waiting.prompt.as_deref().unwrap_or("prompt")
+            ));
+        }
+        (None, Some(_)) => return Err(format!("Batch {} is not waiting for input", batch_id)),
+        (None, None) => {}
+    }
+
+    run_batch(&checkpoint.request, config, checkpoint.batch_id, kept, outputs)
+}
+
+/// Run (or continue) a batch; `prior` results are kept as-is and their steps are not re-run
+fn run_batch(
+    data: &Value,
+    config: &Config,
+    batch_id: String,
+    prior: Vec<BatchCommandResult>,
+    prior_outputs: HashMap<usize, String>,
+) -> Result<BatchExecutionResult, String> {
+    let steps = parse_steps(data)?;
+    let groups = parse_parallel_groups(data, &steps)?;
+    let policy = FailurePolicy::from_request(data)?;
 
     // Extract session name
-    let session = data
-        .get("session")
-        .and_then(|v| v.as_str())
-        .unwrap_or(&config.default_session);
+    let session = config.get_session(data);
 
-    let mut result = BatchExecutionResult::new();
-    result.total_commands = commands_arr.len();
+    let options = StepOptions::from_request(data, config)?;
+
+    let mut run = BatchRun {
+        batch_id: batch_id.clone(),
+        request: data.clone(),
+        state_dir: config.batch_state_dir.clone(),
+        policy,
+        result: BatchExecutionResult::new(),
+        unsuccessful: Vec::new(),
+        aborted_by: None,
+        paused_at: None,
+        outputs: prior_outputs,
+    };
+    run.result.batch_id = batch_id;
+    run.result.resumed_from = prior.iter().map(|r| r.index).max().map(|last| last + 1);
+    run.result.total_commands = data
+        .get("commands")
+        .and_then(|v| v.as_array())
+        .map(|arr| arr.len())
+        .unwrap_or(0);
 
     // Ensure session exists
     if !tmux::has_session(session) {
@@ -68,82 +805,1010 @@ pub fn execute_batch(
         std::thread::sleep(std::time::Duration::from_millis(100));
     }
 
-    // Execute each command
-    for (idx, cmd_val) in commands_arr.iter().enumerate() {
-        let command = match cmd_val.as_str() {
-            Some(cmd) => cmd.trim().to_string(),
-            None => continue,
-        };
+    eventlog::emit(
+        Kind::BatchStarted,
+        Some(session),
+        format!("Batch {} started ({} steps)", run.batch_id, run.result.total_commands),
+        serde_json::json!({"batch_id": run.batch_id, "steps": run.result.total_commands, "resumed_from": run.result.resumed_from}),
+    );
+    let started = Instant::now();
+    *ACTIVE.lock().unwrap_or_else(|e| e.into_inner()) = Some(ActiveBatch {
+        batch_id: run.batch_id.clone(),
+        session: session.to_string(),
+        phase: "starting",
+        steps: Vec::new(),
+        completed: prior.len(),
+        total: run.result.total_commands,
+        elapsed_ms: 0,
+        phase_elapsed_ms: 0,
+        started,
+        phase_started: started,
+    });
+    let _active = ActiveGuard;
+    let mut done: Vec<usize> = prior.iter().map(|r| r.index).collect();
+    for step_result in prior {
+        run.record(step_result, None);
+    }
 
-        if command.is_empty() {
+    for step in &steps {
+        if done.contains(&step.index) {
             continue;
         }
 
-        let explanation = data
-            .get("explanations")
-            .and_then(|v| v.as_array())
-            .and_then(|arr| arr.get(idx))
-            .and_then(|v| v.as_str())
-            .unwrap_or("")
-            .to_string();
+        // A parallel group runs as a unit when its first member comes up
+        if let Some(group) = groups.iter().find(|g| g.contains(&step.index)) {
+            let members: Vec<&BatchStep> = steps.iter()
+                .filter(|s| group.contains(&s.index) && !done.contains(&s.index))
+                .collect();
+            set_phase("parallel", members.iter().map(|s| s.index).collect(), run.result.commands.len());
+            run_parallel_group(&mut run, session, &members, &options);
+            done.extend(group.iter().copied());
+            continue;
+        }
 
-        // Execute command
-        match tmux::send_keys(session, &command) {
-            Ok(_) => {
-                // Wait briefly for output
-                std::thread::sleep(std::time::Duration::from_millis(500));
-
-                // Capture output
-                let output = tmux::capture_pane(session, 100).unwrap_or_default();
-
-                // Parse intelligently to get summary
-                let _parsed = parse_intelligently(&output, &command);
-
-                // Keep preview (first 6 lines)
-                let preview = output
-                    .lines()
-                    .take(6)
-                    .collect::<Vec<_>>()
-                    .join("\n");
-
-                result.commands.push(BatchCommandResult {
-                    index: idx + 1,
-                    command: command.clone(),
-                    explanation,
-                    success: true,
-                    status: "success".to_string(),
-                    output_preview: if preview.is_empty() {
-                        None
-                    } else {
-                        Some(preview)
-                    },
-                    error: None,
-                });
-
-                result.successful += 1;
-            }
-            Err(e) => {
-                result.commands.push(BatchCommandResult {
-                    index: idx + 1,
-                    command: command.clone(),
-                    explanation,
-                    success: false,
-                    status: "error".to_string(),
-                    output_preview: None,
-                    error: Some(e.clone()),
-                });
-
-                result.failed += 1;
+        match run.skip_reason(step) {
+            Some(reason) => run.record(skipped_step(step, reason), None),
+            None => {
+                set_phase("step", vec![step.index], run.result.commands.len());
+                let (step_result, output) = run_step(session, step, &options);
+                run.record(step_result, Some(output));
             }
         }
     }
 
+    if let (FailurePolicy::Rollback, Some(failed)) = (run.policy, run.aborted_by) {
+        set_phase("rollback", Vec::new(), run.result.commands.len());
+        run.result.rollback = Some(rollback(&run.result.commands, &steps, failed, |undo| run_step(session, undo, &options).0));
+    }
+
+    run.save_checkpoint(true);
+    let mut result = run.result;
+
+    // Merge parallel results back into original order
+    result.commands.sort_by_key(|r| r.index);
+    result.duration_ms = started.elapsed().as_millis() as u64;
+
     // Build summary
     result.summary = format!(
-        "Batch executed {} commands: {} succeeded, {} failed",
-        result.total_commands, result.successful, result.failed
+        "Batch executed {} commands: {} succeeded, {} failed, {} timed out, {} skipped",
+        result.total_commands, result.successful, result.failed, result.timed_out, result.skipped
     );
+    if result.ignored_failures > 0 {
+        result.summary.push_str(&format!(", {} failures ignored", result.ignored_failures));
+    }
+    result.aggregate = aggregate(&result.commands);
+    if let Some(rollback) = &result.rollback {
+        result.summary.push_str(&format!(
+            "; rolled back {} step(s){}",
+            rollback.steps.len(),
+            if rollback.complete { "" } else { " - rollback INCOMPLETE" }
+        ));
+    }
+    if result.wall_time_saved_ms > 0 {
+        result.summary.push_str(&format!(
+            " ({:.1}s saved by parallel groups)",
+            result.wall_time_saved_ms as f64 / 1000.0
+        ));
+    }
+
+    let (kind, message) = match result.awaiting_input {
+        Some(step) => (Kind::BatchPaused, format!("Batch {} waits for input at step {}", result.batch_id, step)),
+        None => (Kind::BatchFinished, format!("Batch {} finished: {}", result.batch_id, result.summary)),
+    };
+    eventlog::emit(kind, Some(session), message, serde_json::json!({
+        "batch_id": result.batch_id,
+        "successful": result.successful,
+        "failed": result.failed,
+        "timed_out": result.timed_out,
+        "skipped": result.skipped,
+        "awaiting_input": result.awaiting_input,
+        "duration_ms": result.duration_ms,
+    }));
 
     Ok(result)
 }
 
+/// Run compensations for every completed step, newest first (best effort - keeps going on failure,
+/// but runs nothing once panic_stop is engaged)
+fn rollback(
+    completed: &[BatchCommandResult],
+    steps: &[BatchStep],
+    triggered_by: usize,
+    mut run_undo: impl FnMut(&BatchStep) -> BatchCommandResult,
+) -> RollbackReport {
+    let mut done: Vec<&BatchCommandResult> = completed.iter().filter(|r| r.success).collect();
+    done.sort_by_key(|r| std::cmp::Reverse(r.index));
+
+    let mut report = RollbackReport {
+        triggered_by,
+        complete: true,
+        steps: Vec::new(),
+        not_compensated: Vec::new(),
+    };
+
+    for result in done {
+        let step = match steps.iter().find(|s| s.index == result.index) {
+            Some(step) => step,
+            None => continue,
+        };
+
+        let compensation = match &step.compensation {
+            Some(_) if killswitch::is_stopped() => {
+                report.complete = false;
+                report.not_compensated.push(step.index);
+                continue;
+            }
+            Some(command) => command,
+            None => {
+                report.not_compensated.push(step.index);
+                continue;
+            }
+        };
+
+        let undo = BatchStep {
+            command: compensation.clone(),
+            explanation: format!("Undo step {}", step.index),
+            ignore_failure: false,
+            depends_on: Vec::new(),
+            run_if: None,
+            compensation: None,
+            ..step.clone()
+        };
+
+        let undo_result = run_undo(&undo);
+        if !undo_result.success {
+            report.complete = false;
+        }
+        report.steps.push(undo_result);
+    }
+
+    report
+}
+
+/// Run a parallel group: each member gets its own session (started in the main pane's cwd)
+fn run_parallel_group(run: &mut BatchRun, session: &str, members: &[&BatchStep], options: &StepOptions) {
+    let cwd = tmux::get_pane_cwd(session).ok();
+
+    let mut to_run: Vec<(&BatchStep, String)> = Vec::new();
+    for step in members {
+        if let Some(reason) = run.skip_reason(step) {
+            run.record(skipped_step(step, reason), None);
+            continue;
+        }
+
+        let pane = format!("{}-par-{}", session, step.index);
+        if !tmux::has_session(&pane) {
+            if let Err(e) = tmux::new_session_in(&pane, cwd.as_deref()) {
+                run.record(failed_step(step, format!("Failed to create parallel session: {}", e)), None);
+                continue;
+            }
+        }
+        to_run.push((step, pane));
+    }
+
+    if to_run.is_empty() {
+        return;
+    }
+
+    let group_started = std::time::Instant::now();
+    let outcomes: Vec<(BatchCommandResult, String)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = to_run.iter()
+            .map(|(step, pane)| scope.spawn(move || run_step(pane, step, options)))
+            .collect();
+        handles.into_iter()
+            .zip(to_run.iter())
+            .map(|(handle, (step, _))| handle.join().unwrap_or_else(|_| {
+                (failed_step(step, "Parallel step panicked".to_string()), String::new())
+            }))
+            .collect()
+    });
+    let group_wall_ms = group_started.elapsed().as_millis() as u64;
+
+    let sequential_ms: u64 = outcomes.iter().map(|(r, _)| r.duration_ms).sum();
+    run.result.wall_time_saved_ms += sequential_ms.saturating_sub(group_wall_ms);
+
+    for (step_result, output) in outcomes {
+        run.record(step_result, Some(output));
+    }
+
+    // Panes blocked on a prompt stay alive so resume_batch can answer them
+    for (step, pane) in &to_run {
+        if run.paused_at != Some(step.index) {
+            let _ = tmux::kill_session(pane);
+        }
+    }
+}
+
+/// Run a step, retrying errors with exponential backoff (returns the step's full output too)
+fn run_step(session: &str, step: &BatchStep, options: &StepOptions) -> (BatchCommandResult, String) {
+    let started = std::time::Instant::now();
+    let mut attempt = 1;
+
+    loop {
+        let (mut step_result, output) = run_attempt(session, step, options);
+        step_result.attempts = if step_result.status == "skipped" { attempt - 1 } else { attempt };
+        step_result.duration_ms = started.elapsed().as_millis() as u64;
+
+        // Timeouts aren't retried - the command may still be running in the pane - and nothing is sent
+        // again once panic_stop is engaged (checked after the backoff too)
+        let delay = std::time::Duration::from_millis(retry_delay_ms(step.retry_backoff_ms, attempt));
+        if step_result.success || step_result.status == "timeout" || attempt > step.retries || stopped_during(delay) {
+            step_result.failure_ignored = !step_result.success && step.ignore_failure;
+            return (step_result, output);
+        }
+        attempt += 1;
+    }
+}
+
+/// Wait out a retry backoff - true when the emergency stop is engaged before or after it
+fn stopped_during(delay: std::time::Duration) -> bool {
+    if killswitch::is_stopped() {
+        return true;
+    }
+    std::thread::sleep(delay);
+    killswitch::is_stopped()
+}
+
+/// Backoff before retry number `attempt` (1-based): base, 2x base, 4x base, ... capped
+fn retry_delay_ms(base_ms: u64, attempt: u32) -> u64 {
+    base_ms
+        .saturating_mul(1u64 << (attempt - 1).min(16))
+        .min(MAX_RETRY_BACKOFF_MS)
+}
+
+/// Send one step to the session and wait for it to actually finish
+fn run_attempt(session: &str, step: &BatchStep, options: &StepOptions) -> (BatchCommandResult, String) {
+    let started = std::time::Instant::now();
+
+    // The stop may have been engaged since the batch checked its steps
+    if killswitch::is_stopped() {
+        return (skipped_step(step, STOP_REASON.to_string()), String::new());
+    }
+
+    // Same checks as a single execute
+    if let Err(e) = validate_command(&step.command)
+        .and_then(|_| check_blocked_patterns(&step.command, &options.config.blocked_patterns))
+    {
+        events::blocked(&step.command, &e);
+        let failed = BatchCommandResult {
+            duration_ms: started.elapsed().as_millis() as u64,
+            ..failed_step(step, e)
+        };
+        return (failed, String::new());
+    }
+    if let Err(e) = tmux::send_keys(session, &step.command) {
+        let failed = BatchCommandResult {
+            duration_ms: started.elapsed().as_millis() as u64,
+            ..failed_step(step, e)
+        };
+        return (failed, String::new());
+    }
+
+    finish_step(session, step, options, started)
+}
+
+/// Wait for a started step to finish (answering prompts from `responses`) and build its result
+fn finish_step(
+    session: &str,
+    step: &BatchStep,
+    options: &StepOptions,
+    started: std::time::Instant,
+) -> (BatchCommandResult, String) {
+    let mut answered: Vec<String> = Vec::new();
+
+    let (outcome, blocked_on) = loop {
+        let remaining = step.timeout_ms.saturating_sub(started.elapsed().as_millis() as u64);
+        let outcome = tmux::wait_for_completion_or_prompt(session, &step.command, remaining, options.interval_ms);
+
+        let prompt = match &outcome.prompt {
+            Some(prompt) => prompt.clone(),
+            None => break (outcome, None),
+        };
+
+        match step.answer_for(&prompt) {
+            Some(answer) if answered.len() < MAX_AUTO_ANSWERS => {
+                if let Err(e) = tmux::send_input(session, answer) {
+                    tracing::warn!("Failed to answer prompt for step {}: {}", step.index, e);
+                    break (outcome, Some(prompt));
+                }
+                answered.push(prompt);
+            }
+            _ => break (outcome, Some(prompt)),
+        }
+    };
+
+    let output = step_output(&outcome.output, &step.command);
+    let preview = output.lines().take(6).collect::<Vec<_>>().join("\n");
+
+    let display = if !options.include_output {
+        None
+    } else if outcome.completed {
+        Some(DisplayOutput::from_command_output(&step.command, &output, 0))
+    } else {
+        Some(DisplayOutput::from_timeout(&step.command, &output))
+    };
+
+    let mut findings: Vec<Finding> = Vec::new();
+    let (status, error) = if let Some(prompt) = &blocked_on {
+        match options.prompt_policy {
+            PromptPolicy::Pause => ("awaiting_input".to_string(), Some(format!("Waiting for input: {}", prompt))),
+            PromptPolicy::Abort => {
+                let _ = tmux::send_interrupt(session);
+                ("error".to_string(), Some(format!("Aborted at interactive prompt: {}", prompt)))
+            }
+        }
+    } else if outcome.completed {
+        // Reuse the DisplayOutput's analysis when we built one
+        let (status, summary, parsed_findings) = match &display {
+            Some(display) => (display.status.clone(), display.summary.clone(), display.findings.clone()),
+            None => {
+                let _parse = timings::enter(Phase::Parse);
+                let parsed = parse_intelligently(&output, &step.command);
+                (parsed.status, parsed.summary, parsed.findings)
+            }
+        };
+        findings = parsed_findings;
+        let error = if status == "error" { Some(summary) } else { None };
+        (status, error)
+    } else {
+        (
+            "timeout".to_string(),
+            Some(format!("Timed out after {:.1}s - may still be running", step.timeout_ms as f64 / 1000.0)),
+        )
+    };
+
+    let mut step_result = BatchCommandResult {
+        index: step.index,
+        command: step.command.clone(),
+        explanation: step.explanation.clone(),
+        success: status == "success" || status == "warning",
+        status,
+        output_preview: if preview.is_empty() { None } else { Some(preview) },
+        error,
+        duration_ms: started.elapsed().as_millis() as u64,
+        output: None,
+        artifact_ref: None,
+        attempts: 1,
+        failure_ignored: false,
+        prompt: blocked_on,
+        answered_prompts: answered,
+        findings,
+        risk: Some(risk::classify(&step.command)),
+    };
+
+    if let Some(display) = display {
+        attach_output(&mut step_result, &display, options.config);
+    }
+
+    (step_result, output)
+}
+
+/// Inline the step's DisplayOutput, or spill it to an artifact when it exceeds the display budget
+fn attach_output(step_result: &mut BatchCommandResult, display: &DisplayOutput, config: &Config) {
+    let json = match serde_json::to_string(display) {
+        Ok(json) => json,
+        Err(e) => {
+            tracing::warn!("Failed to serialize step {} output: {}", step_result.index, e);
+            return;
+        }
+    };
+
+    if json.len() <= config.max_display_bytes {
+        step_result.output = serde_json::from_str(&json).ok();
+        return;
+    }
+
+    match artifacts::store(&config.artifact_dir, &json) {
+        Ok(id) => step_result.artifact_ref = Some(id),
+        Err(e) => tracing::warn!("Failed to spill step {} output: {}", step_result.index, e),
+    }
+}
+
+fn failed_step(step: &BatchStep, error: String) -> BatchCommandResult {
+    BatchCommandResult {
+        index: step.index,
+        command: step.command.clone(),
+        explanation: step.explanation.clone(),
+        success: false,
+        status: "error".to_string(),
+        output_preview: None,
+        error: Some(error),
+        duration_ms: 0,
+        output: None,
+        artifact_ref: None,
+        attempts: 1,
+        failure_ignored: false,
+        prompt: None,
+        answered_prompts: Vec::new(),
+        findings: Vec::new(),
+        risk: Some(risk::classify(&step.command)),
+    }
+}
+
+fn skipped_step(step: &BatchStep, reason: String) -> BatchCommandResult {
+    BatchCommandResult {
+        index: step.index,
+        command: step.command.clone(),
+        explanation: step.explanation.clone(),
+        success: false,
+        status: "skipped".to_string(),
+        output_preview: None,
+        error: Some(format!("Skipped: {}", reason)),
+        duration_ms: 0,
+        output: None,
+        artifact_ref: None,
+        attempts: 0,
+        failure_ignored: false,
+        prompt: None,
+        answered_prompts: Vec::new(),
+        findings: Vec::new(),
+        risk: Some(risk::classify(&step.command)),
+    }
+}
+
+/// Merge per-step findings and step outcomes into one severity-ordered list and sentence
+pub fn aggregate(commands: &[BatchCommandResult]) -> BatchAggregate {
+    let mut findings: Vec<Finding> = Vec::new();
+    let mut needs_attention: Vec<usize> = Vec::new();
+    let mut total_errors = 0;
+
+    for cmd in commands {
+        // Step outcomes the parsers can't see (no output to analyze)
+        let outcome = match cmd.status.as_str() {
+            "timeout" => Some((Importance::High, "Step Timed Out")),
+            "awaiting_input" => Some((Importance::High, "Step Awaiting Input")),
+            "error" if cmd.failure_ignored => Some((Importance::Low, "Step Failed (ignored)")),
+            "error" => Some((Importance::High, "Step Failed")),
+            _ => None,
+        };
+
+        if let Some((importance, category)) = outcome {
+            if matches!(importance, Importance::High) {
+                total_errors += 1;
+                needs_attention.push(cmd.index);
+            }
+            findings.push(
+                Finding::new(
+                    category,
+                    format!("[step {}] {}: {}", cmd.index, cmd.command, cmd.error.as_deref().unwrap_or(&cmd.status)),
+                    importance,
+                )
+                .with_provenance("batch_aggregator", 1.0),
+            );
+        }
+
+        for finding in &cmd.findings {
+            if finding.category.starts_with("Error:") && finding.importance.rank() <= Importance::High.rank() {
+                total_errors += 1;
+            }
+            if finding.importance.rank() <= Importance::High.rank() && !needs_attention.contains(&cmd.index) {
+                needs_attention.push(cmd.index);
+            }
+            let mut tagged = finding.clone();
+            tagged.message = format!("[step {}] {}", cmd.index, finding.message);
+            findings.push(tagged);
+        }
+    }
+
+    // Stable sort keeps step order within the same severity
+    findings.sort_by_key(|f| f.importance.rank());
+    needs_attention.sort_unstable();
+
+    let most_severe = findings.first().cloned();
+    let summary = match (&most_severe, needs_attention.is_empty()) {
+        (_, true) => format!("All {} steps look healthy", commands.len()),
+        (Some(worst), false) => format!(
+            "{} step(s) need attention ({}); {} error(s) total, most severe: {} - {}",
+            needs_attention.len(),
+            needs_attention.iter().map(|i| format!("#{}", i)).collect::<Vec<_>>().join(", "),
+            total_errors,
+            worst.category,
+            worst.message
+        ),
+        (None, false) => format!("{} step(s) need attention", needs_attention.len()),
+    };
+
+    BatchAggregate { total_errors, most_severe, needs_attention, findings, summary }
+}
+
+/// A `$VAR` / `${VAR}` reference found in a command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VariableRef {
+    pub name: String,
+    pub value: Option<String>, // None = not set in the executor's environment
+}
+
+/// Pre-flight result for one step
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DryRunStep {
+    pub index: usize,
+    pub command: String,
+    pub explanation: String,
+    pub valid: bool,
+    pub validation_error: Option<String>,
+    pub binary: String,
+    pub binary_available: bool,
+    pub risk: RiskLevel,
+    pub risk_class: RiskClass,
+    pub risk_reason: String,
+    pub variables: Vec<VariableRef>,
+    pub resolved_command: String, // Command with resolvable variables substituted
+}
+
+/// Pre-flight report for a whole batch - nothing is executed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DryRunReport {
+    pub total_commands: usize,
+    pub ok_to_run: bool,
+    pub invalid: usize,
+    pub missing_binaries: usize,
+    pub high_risk: usize,
+    pub steps: Vec<DryRunStep>,
+    pub summary: String,
+}
+
+/// Shell builtins never show up in `which` but are always available
+const SHELL_BUILTINS: &[&str] = &[
+    "cd", "export", "source", ".", "echo", "printf", "set", "unset", "alias",
+    "test", "[", "true", "false", "exit", "read", "eval", "exec", "pushd", "popd",
+];
+
+/// Validate every step without executing anything
+pub fn dry_run(data: &Value) -> Result<DryRunReport, String> {
+    let steps = parse_steps(data)?;
+    parse_parallel_groups(data, &steps)?;
+    FailurePolicy::from_request(data)?;
+
+    let total_commands = data
+        .get("commands")
+        .and_then(|v| v.as_array())
+        .map(|arr| arr.len())
+        .unwrap_or(0);
+
+    let steps: Vec<DryRunStep> = steps.iter().map(dry_run_step).collect();
+
+    let invalid = steps.iter().filter(|s| !s.valid).count();
+    let missing_binaries = steps.iter().filter(|s| !s.binary_available).count();
+    let high_risk = steps.iter().filter(|s| s.risk == RiskLevel::High).count();
+
+    let summary = format!(
+        "Dry run of {} commands: {} invalid, {} missing binaries, {} high risk",
+        steps.len(), invalid, missing_binaries, high_risk
+    );
+
+    Ok(DryRunReport {
+        total_commands,
+        ok_to_run: invalid == 0 && missing_binaries == 0,
+        invalid,
+        missing_binaries,
+        high_risk,
+        steps,
+        summary,
+    })
+}
+
+fn dry_run_step(step: &BatchStep) -> DryRunStep {
+    let validation_error = validate_command(&step.command).err();
+    let binary = command_binary(&step.command);
+
+    let binary_available = binary.is_empty()
+        || SHELL_BUILTINS.contains(&binary.as_str())
+        || std::process::Command::new("which")
+            .arg(&binary)
+            .output()
+            .map(|out| out.status.success())
+            .unwrap_or(false);
+
+    let assessment = risk::classify(&step.command);
+    let variables = find_variables(&step.command);
+
+    let mut resolved_command = step.command.clone();
+    for var in &variables {
+        if let Some(value) = &var.value {
+            resolved_command = resolved_command
+                .replace(&format!("${{{}}}", var.name), value)
+                .replace(&format!("${}", var.name), value);
+        }
+    }
+
+    DryRunStep {
+        index: step.index,
+        command: step.command.clone(),
+        explanation: step.explanation.clone(),
+        valid: validation_error.is_none(),
+        validation_error,
+        binary,
+        binary_available,
+        risk: assessment.class.level(),
+        risk_class: assessment.class,
+        risk_reason: assessment.reason,
+        variables,
+        resolved_command,
+    }
+}
+
+/// First real program in a command line (past sudo/env, VAR=value assignments and wrappers like timeout)
+pub fn command_binary(command: &str) -> String {
+    risk::unwrapped(command).split_whitespace().next().unwrap_or("").to_string()
+}
+
+/// Collect `$VAR` / `${VAR}` references and resolve them from the executor's environment
+fn find_variables(command: &str) -> Vec<VariableRef> {
+    let re = Regex::new(r"\$\{?([A-Za-z_][A-Za-z0-9_]*)\}?").expect("valid variable regex");
+    let mut names: Vec<String> = Vec::new();
+    for cap in re.captures_iter(command) {
+        let name = cap[1].to_string();
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    names.into_iter()
+        .map(|name| VariableRef { value: std::env::var(&name).ok(), name })
+        .collect()
+}
+
+/// Output belonging to one step: everything after the last echo of the command, minus the
+/// trailing prompt line (the pane also holds earlier steps)
+fn step_output(captured: &str, command: &str) -> String {
+    let lines: Vec<&str> = captured.trim_end().lines().collect();
+
+    let start = lines.iter()
+        .rposition(|line| line.contains(command))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+
+    let end = if lines.len() > start { lines.len() - 1 } else { lines.len() };
+
+    lines[start..end.max(start)].join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_step_output_isolates_last_command() {
+        let captured = "$ echo one\none\n$ ls /nope\nls: cannot access '/nope': No such file or directory\n$ \n";
+        assert_eq!(
+            step_output(captured, "ls /nope"),
+            "ls: cannot access '/nope': No such file or directory"
+        );
+        assert_eq!(step_output("$ true\n$ ", "true"), "");
+    }
+
+    #[test]
+    fn test_parse_steps_and_policy() {
+        let data = serde_json::json!({
+            "commands": ["cd /nonexistent", "", "ls"],
+            "explanations": ["enter dir", "", "list"],
+            "max_waits": [5],
+            "depends_on": [[], [], [1]],
+            "compensations": ["", "", "cd -"],
+            "failure_policy": "skip_dependents"
+        });
+        let steps = parse_steps(&data).unwrap();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].timeout_ms, 5000);
+        assert_eq!(steps[1].index, 3);
+        assert_eq!(steps[1].depends_on, vec![1]);
+        assert_eq!(steps[0].compensation, None);
+        assert_eq!(steps[1].compensation.as_deref(), Some("cd -"));
+        assert_eq!(FailurePolicy::from_request(&data).unwrap(), FailurePolicy::SkipDependents);
+        assert_eq!(FailurePolicy::from_request(&serde_json::json!({})).unwrap(), FailurePolicy::Abort);
+        assert_eq!(
+            FailurePolicy::from_request(&serde_json::json!({"failure_policy": "rollback"})).unwrap(),
+            FailurePolicy::Rollback
+        );
+        assert!(FailurePolicy::from_request(&serde_json::json!({"failure_policy": "yolo"})).is_err());
+    }
+
+    #[test]
+    fn test_run_if_evaluation() {
+        let data = serde_json::json!({
+            "commands": ["systemctl is-active sshd", "systemctl start sshd"],
+            "run_if": [null, {"step": 1, "status": "success", "output_matches": "^inactive"}]
+        });
+        let steps = parse_steps(&data).unwrap();
+        let condition = steps[1].run_if.as_ref().unwrap();
+
+        let mut earlier = BatchCommandResult {
+            status: "success".to_string(),
+            success: true,
+            ..skipped_step(&steps[0], String::new())
+        };
+        assert!(condition.evaluate(Some(&earlier), "inactive").is_ok());
+        assert!(condition.evaluate(Some(&earlier), "active").is_err());
+
+        earlier.status = "error".to_string();
+        assert!(condition.evaluate(Some(&earlier), "inactive").unwrap_err().contains("status"));
+        assert!(condition.evaluate(None, "").is_err());
+
+        let bad = serde_json::json!({"commands": ["a"], "run_if": [{"step": 1, "status": "success"}]});
+        assert!(parse_steps(&bad).is_err());
+    }
+
+    #[test]
+    fn test_parallel_groups_validation() {
+        let data = serde_json::json!({
+            "commands": ["uptime", "df -h", "free -m", "ls"],
+            "depends_on": [[], [], [], [3]],
+            "parallel_groups": [[1, 2, 3]]
+        });
+        let steps = parse_steps(&data).unwrap();
+        assert_eq!(parse_parallel_groups(&data, &steps).unwrap(), vec![vec![1, 2, 3]]);
+
+        let overlapping = serde_json::json!({"commands": ["a", "b", "c"], "parallel_groups": [[1, 2], [2, 3]]});
+        let steps = parse_steps(&overlapping).unwrap();
+        assert!(parse_parallel_groups(&overlapping, &steps).is_err());
+
+        let intra = serde_json::json!({"commands": ["a", "b"], "depends_on": [[], [1]], "parallel_groups": [[1, 2]]});
+        let steps = parse_steps(&intra).unwrap();
+        assert!(parse_parallel_groups(&intra, &steps).is_err());
+    }
+
+    #[test]
+    fn test_large_step_output_spills_to_artifact() {
+        let mut config = Config {
+            artifact_dir: std::env::temp_dir()
+                .join(format!("archy-batch-test-{}", std::process::id()))
+                .to_string_lossy()
+                .to_string(),
+            ..Config::default()
+        };
+
+        let step = BatchStep {
+            index: 1,
+            command: "echo hi".to_string(),
+            explanation: String::new(),
+            timeout_ms: 5000,
+            retries: 0,
+            retry_backoff_ms: 1000,
+            ignore_failure: false,
+            depends_on: Vec::new(),
+            run_if: None,
+            compensation: None,
+            responses: Vec::new(),
+        };
+
+        let mut small = failed_step(&step, String::new());
+        attach_output(&mut small, &DisplayOutput::from_command_output("echo hi", "hi\n", 0), &config);
+        assert_eq!(small.output.as_ref().unwrap()["command"], "echo hi");
+        assert!(small.artifact_ref.is_none());
+
+        config.max_display_bytes = 16;
+        let mut large = failed_step(&step, String::new());
+        attach_output(&mut large, &DisplayOutput::from_command_output("echo hi", "hi\n", 0), &config);
+        assert!(large.output.is_none());
+        assert!(large.artifact_ref.is_some());
+
+        let _ = std::fs::remove_dir_all(&config.artifact_dir);
+    }
+
+    #[test]
+    fn test_dry_run_report() {
+        let data = serde_json::json!({
+            "commands": ["ls -la", "sudo pacman -Syu", "rm -rf /", "definitely-not-a-binary-xyz --help"]
+        });
+        let report = dry_run(&data).unwrap();
+        assert_eq!(report.steps.len(), 4);
+        assert_eq!(report.steps[0].risk, RiskLevel::Low);
+        assert_eq!(report.steps[1].risk, RiskLevel::High);
+        assert_eq!(report.steps[1].risk_class, RiskClass::Privileged);
+        assert_eq!(report.steps[1].binary, "pacman");
+        assert!(!report.steps[2].valid);
+        assert!(!report.steps[3].binary_available);
+        assert!(!report.ok_to_run);
+    }
+
+    #[test]
+    fn test_risk_levels_and_variables() {
+        assert_eq!(risk::classify("systemctl restart sshd").class.level(), RiskLevel::Medium);
+        assert_eq!(risk::classify("systemctl status sshd").class.level(), RiskLevel::Low);
+        assert_eq!(risk::classify("echo hi > out.txt").class.level(), RiskLevel::Medium);
+
+        let vars = find_variables("cd $HOME && echo ${ARCHY_DRY_RUN_UNSET_VAR}");
+        assert_eq!(vars.len(), 2);
+        assert_eq!(vars[0].name, "HOME");
+        assert!(vars[1].value.is_none());
+    }
+
+    fn ok_result(step: &BatchStep) -> BatchCommandResult {
+        BatchCommandResult {
+            status: "success".to_string(),
+            success: true,
+            ..failed_step(step, String::new())
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_roundtrip_and_prefix() {
+        let dir = std::env::temp_dir()
+            .join(format!("archy-batch-state-test-{}", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+
+        let steps = parse_steps(&serde_json::json!({"commands": ["a", "b", "c"]})).unwrap();
+        let ok = ok_result(&steps[0]);
+        let checkpoint = BatchCheckpoint {
+            batch_id: artifacts::new_id(),
+            request: serde_json::json!({"commands": ["a", "b", "c"]}),
+            results: vec![
+                skipped_step(&steps[2], "step 2 failed (failure_policy: abort)".to_string()),
+                failed_step(&steps[1], "boom".to_string()),
+                ok,
+            ],
+            outputs: HashMap::from([(1, "out a".to_string())]),
+            complete: true,
+        };
+
+        save_checkpoint(&dir, &checkpoint).unwrap();
+        let loaded = load_checkpoint(&dir, &checkpoint.batch_id).unwrap();
+        assert_eq!(loaded.results.len(), 3);
+        assert_eq!(loaded.outputs.get(&1).map(String::as_str), Some("out a"));
+
+        let kept = resumable_prefix(&loaded.results);
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].index, 1);
+
+        // Steps the emergency stop skipped never ran - resume starts at the first of them
+        let stopped = [
+            ok_result(&steps[0]),
+            skipped_step(&steps[1], STOP_REASON.to_string()),
+            skipped_step(&steps[2], STOP_REASON.to_string()),
+        ];
+        assert_eq!(resumable_prefix(&stopped).iter().map(|r| r.index).collect::<Vec<_>>(), vec![steps[0].index]);
+        let by_run_if = [ok_result(&steps[0]), skipped_step(&steps[1], "run_if not met".to_string()), ok_result(&steps[2])];
+        assert_eq!(resumable_prefix(&by_run_if).len(), 3);
+
+        assert!(load_checkpoint(&dir, "../etc/passwd").is_err());
+
+        // A checkpoint someone else could have edited is never replayed
+        let path = checkpoint_path(&dir, &checkpoint.batch_id);
+        assert_eq!(std::fs::metadata(&path).unwrap().mode() & 0o777, 0o600);
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o666)).unwrap();
+        assert!(load_checkpoint(&dir, &checkpoint.batch_id).unwrap_err().contains("writable by other users"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_resume_after_panic_stop_runs_the_skipped_steps() {
+        let _serial = killswitch::TEST_SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        let session = format!("archy-test-resume-{}", std::process::id());
+        let dir = std::env::temp_dir().join(format!("archy-batch-resume-test-{}", std::process::id()));
+        let config = Config {
+            default_session: session.clone(),
+            batch_state_dir: dir.join("state").to_string_lossy().to_string(),
+            artifact_dir: dir.join("artifacts").to_string_lossy().to_string(),
+            ..Config::default()
+        };
+        let data = serde_json::json!({"commands": ["echo first", "echo second"]});
+
+        // Engaged before the batch starts: every step is skipped and the checkpoint is complete
+        killswitch::engage_quietly("test");
+        let stopped = execute_batch(&data, &config);
+        let _ = killswitch::resume();
+        let stopped = match stopped {
+            Ok(result) => result,
+            Err(_) => return, // no tmux server can run here
+        };
+        assert_eq!(stopped.skipped, 2);
+
+        let checkpoint = load_checkpoint(&config.batch_state_dir, &stopped.batch_id).unwrap();
+        assert!(checkpoint.complete);
+        let resumed = resume_batch(checkpoint, None, &config);
+        let _ = tmux::kill_session(&session);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let resumed = resumed.expect("resume after the stop is lifted");
+        assert_eq!(resumed.resumed_from, None);
+        assert_eq!(resumed.skipped, 0);
+        assert_eq!(resumed.commands.iter().map(|r| r.attempts).collect::<Vec<_>>(), vec![1, 1]);
+    }
+
+    #[test]
+    fn test_object_steps_mix_with_strings() {
+        let data = serde_json::json!({
+            "commands": [
+                "uptime",
+                {"command": "curl -sf http://localhost:8080/health", "timeout_ms": 2000,
+                 "retries": 3, "retry_backoff_ms": 500, "ignore_failure": true},
+                {"command": "ls", "depends_on": [1]}
+            ],
+            "explanations": ["load", "health", "list"],
+            "max_wait": 30
+        });
+        let steps = parse_steps(&data).unwrap();
+        assert_eq!(steps[0].timeout_ms, 30_000);
+        assert_eq!(steps[1].timeout_ms, 2000);
+        assert_eq!(steps[1].retries, 3);
+        assert!(steps[1].ignore_failure);
+        assert_eq!(steps[1].explanation, "health");
+        assert_eq!(steps[2].depends_on, vec![1]);
+
+        assert!(parse_steps(&serde_json::json!({"commands": [{"timeout_ms": 5}]})).is_err());
+    }
+
+    #[test]
+    fn test_rollback_undoes_completed_steps_newest_first() {
+        let _serial = killswitch::TEST_SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        let data = serde_json::json!({
+            "commands": ["mkdir a", "touch b", "cp a c", "make d"],
+            "compensations": ["rmdir a", "", "rm c", "make clean"]
+        });
+        let steps = parse_steps(&data).unwrap();
+        // Steps 1-3 completed, the last one failed
+        let completed: Vec<BatchCommandResult> = steps.iter()
+            .map(|step| BatchCommandResult { success: step.index != steps[3].index, ..failed_step(step, String::new()) })
+            .collect();
+
+        let mut ran = Vec::new();
+        let report = rollback(&completed, &steps, steps[3].index, |undo| {
+            ran.push(undo.command.clone());
+            BatchCommandResult { success: true, ..failed_step(undo, String::new()) }
+        });
+        assert_eq!(ran, vec!["rm c", "rmdir a"]);
+        assert_eq!(report.steps.iter().map(|r| r.index).collect::<Vec<_>>(), vec![steps[2].index, steps[0].index]);
+        assert_eq!(report.not_compensated, vec![steps[1].index]);
+        assert!(report.complete);
+    }
+
+    #[test]
+    fn test_retry_backoff_doubles_and_caps() {
+        assert_eq!(retry_delay_ms(500, 1), 500);
+        assert_eq!(retry_delay_ms(500, 2), 1000);
+        assert_eq!(retry_delay_ms(500, 3), 2000);
+        assert_eq!(retry_delay_ms(500, 30), MAX_RETRY_BACKOFF_MS);
+    }
+
+    #[test]
+    fn test_prompt_responses() {
+        let data = serde_json::json!({
+            "commands": [{"command": "sudo pacman -S htop", "responses": {"proceed.*\\[y/n\\]": "y"}}],
+            "responses": {"\\[y/n\\]": "n", "overwrite": "yes"},
+            "prompt_policy": "abort"
+        });
+        let steps = parse_steps(&data).unwrap();
+        assert_eq!(steps[0].answer_for(":: Proceed with installation? [Y/n]"), Some("y"));
+        assert_eq!(steps[0].answer_for("Overwrite existing file? (yes/no)"), Some("yes"));
+        assert_eq!(steps[0].answer_for("[sudo] password for me:"), None);
+        assert_eq!(PromptPolicy::from_request(&data).unwrap(), PromptPolicy::Abort);
+        assert_eq!(PromptPolicy::from_request(&serde_json::json!({})).unwrap(), PromptPolicy::Pause);
+    }
+
+    #[test]
+    fn test_aggregate_orders_by_severity() {
+        let steps = parse_steps(&serde_json::json!({"commands": ["df -h", "cat /etc/shadow", "make"]})).unwrap();
+
+        let mut disk = BatchCommandResult {
+            status: "warning".to_string(),
+            success: true,
+            ..failed_step(&steps[0], String::new())
+        };
+        disk.findings = vec![Finding::new("Disk Space Critical", "/ is 95% full", Importance::Critical)];
+
+        let denied = failed_step(&steps[1], "Permission denied".to_string());
+
+        let mut make = BatchCommandResult {
+            status: "success".to_string(),
+            success: true,
+            ..failed_step(&steps[2], String::new())
+        };
+        make.findings = vec![Finding::new("Info", "built 3 targets", Importance::Info)];
+
+        let aggregate = aggregate(&[disk, denied, make]);
+        assert_eq!(aggregate.needs_attention, vec![1, 2]);
+        assert_eq!(aggregate.total_errors, 1);
+        assert_eq!(aggregate.most_severe.unwrap().category, "Disk Space Critical");
+        assert!(aggregate.findings[1].message.starts_with("[step 2]"));
+        assert!(aggregate.summary.contains("#1, #2"));
+
+        assert!(super::aggregate(&[]).summary.contains("healthy"));
+    }
+
+    #[test]
+    fn test_forward_dependency_rejected() {
+        let data = serde_json::json!({"commands": ["a", "b"], "depends_on": [[2], []]});
+        assert!(parse_steps(&data).is_err());
+    }
+}
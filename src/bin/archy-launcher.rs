@@ -0,0 +1,456 @@
+// archy-launcher - GUI app launcher, run by the executor as a separate process
+// Desktop entries are untrusted input: their Exec lines name arbitrary programs. Parsing and spawning
+// happen here, so a hostile .desktop file only ever reaches this short-lived process - never the
+// daemon that enforces policy.
+//
+// Contract: one JSON request on stdin, one JSON reply on stdout, then exit.
+//   request: {"desktop_entry": "firefox", "desktop_files": ["/usr/share/applications/firefox.desktop"],
+//             "exec_dirs": ["/usr/bin", ...], "terminal": ["foot", "-e", "{exec}"],
+//             "files": ["/home/me/a.pdf", "https://example.org"]}
+//   reply:   {"success": true, "output": "...", "error": null, "pid": 4242, "still_running": true,
+//             "stderr": "..."}
+// `desktop_files` are the candidates the executor already vetted (path policy, owner, permissions, age);
+// nothing else is read. Only programs inside `exec_dirs` are started. Entries are read by desktop.rs
+// (Exec quoting and field codes, TryExec); Path= becomes the working directory and a Terminal=true
+// entry runs inside `terminal`, whose program must pass the same check. Flatpak and Snap entries start
+// through `flatpak run` / `snap run`, and a flatpak app id without a desktop file through `flatpak run`.
+// A DBusActivatable=true entry is first activated over the session bus (org.freedesktop.Application
+// on its desktop file id), falling back to the other ways if nothing answers. `files` (absolute paths
+// and URIs the executor vetted) go where Exec's %f %F %u %U say, to Open over D-Bus, after gtk-launch's
+// entry and after a program started without a desktop file. The GUI environment (DISPLAY,
+// WAYLAND_DISPLAY, ...) comes from this process's own environment.
+//
+// Apps the launcher starts itself are watched for SURVIVAL_WINDOW: one that exits non-zero in that
+// time is a failed launch, reported with what it wrote to stderr so far (kept in an unlinked temp file,
+// so the app can go on writing to it). gtk-launch is only a fallback - the app it starts isn't ours
+// to watch - and a D-Bus activated app's pid is asked of the bus.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+// Shared with the executor, which uses the parts the launcher doesn't
+#[allow(dead_code)]
+#[path = "../desktop.rs"]
+mod desktop;
+
+use desktop::{in_exec_dirs, resolve_program, DesktopEntry};
+
+/// Largest request accepted on stdin
+const MAX_REQUEST_BYTES: u64 = 64 * 1024;
+
+/// Longest a D-Bus activation may take before the app is started another way
+const DBUS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a started app is watched for an early exit
+const SURVIVAL_WINDOW: Duration = Duration::from_millis(500);
+
+/// Most of an app's early stderr passed back
+const MAX_STDERR_BYTES: usize = 4096;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LaunchRequest {
+    desktop_entry: String,
+    #[serde(default)]
+    desktop_files: Vec<String>,
+    #[serde(default)]
+    exec_dirs: Vec<String>, // canonical directories programs may be started from
+    #[serde(default)]
+    terminal: Vec<String>, // program and arguments running "{exec}" in a terminal window
+    #[serde(default)]
+    files: Vec<String>, // absolute paths and URIs to open
+}
+
+/// Placeholder in `terminal` for the program to run
+const EXEC_PLACEHOLDER: &str = "{exec}";
+
+/// What a vetted entry runs, and where
+#[derive(Debug, PartialEq)]
+struct Launch {
+    argv: Vec<String>,           // program resolved to its path, then its arguments
+    dir: Option<PathBuf>,        // Path=
+    dbus: bool,                  // DBusActivatable=true
+}
+
+/// What became of a launch
+#[derive(Debug, Default, PartialEq)]
+struct Started {
+    output: String,
+    pid: Option<u32>,
+    still_running: Option<bool>, // None when the launcher didn't start the app itself
+    stderr: Option<String>,      // what it wrote in SURVIVAL_WINDOW
+}
+
+impl Started {
+    fn untracked(output: String) -> Started {
+        Started { output, ..Started::default() }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct LaunchReply {
+    success: bool,
+    output: Option<String>,
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    still_running: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stderr: Option<String>,
+}
+
+fn main() {
+    let reply = read_request().and_then(|request| launch(&request));
+    let reply = match reply {
+        Ok(started) => LaunchReply {
+            success: true,
+            output: Some(started.output),
+            error: None,
+            pid: started.pid,
+            still_running: started.still_running,
+            stderr: started.stderr,
+        },
+        Err(e) => LaunchReply { success: false, output: None, error: Some(e), pid: None, still_running: None, stderr: None },
+    };
+    let json = serde_json::to_string(&reply).unwrap_or_else(|_| {
+        r#"{"success":false,"output":null,"error":"Launcher serialization error"}"#.to_string()
+    });
+    let _ = std::io::stdout().write_all(json.as_bytes());
+}
+
+fn read_request() -> Result<LaunchRequest, String> {
+    let mut input = String::new();
+    std::io::stdin()
+        .take(MAX_REQUEST_BYTES)
+        .read_to_string(&mut input)
+        .map_err(|e| format!("Failed to read launch request: {}", e))?;
+    let request: LaunchRequest = serde_json::from_str(&input)
+        .map_err(|e| format!("Invalid launch request: {}", e))?;
+
+    // Checked again here - the launcher doesn't rely on its caller having done it
+    let entry = &request.desktop_entry;
+    if entry.is_empty() || entry.len() > 255 || entry.contains('/') || entry.contains("..") || entry.contains('\0') {
+        return Err("Invalid desktop_entry: contains illegal characters".to_string());
+    }
+    // Never an option - a path or URI can't start with '-'
+    if let Some(file) = request.files.iter().find(|f| f.contains('\0') || !(f.starts_with('/') || desktop::is_uri(f))) {
+        return Err(format!("Invalid file to open: {}", file));
+    }
+    Ok(request)
+}
+
+/// Launch the first vetted desktop file whose TryExec and Exec pass (its Exec directly, or via
+/// gtk-launch if that can't be spawned), else a program of that name in PATH, else an installed
+/// flatpak app of that id
+fn launch(request: &LaunchRequest) -> Result<Started, String> {
+    let entry = &request.desktop_entry;
+    let mut rejected = Vec::new();
+
+    for file in &request.desktop_files {
+        let content = match fs::read_to_string(file) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+        let launch = match vet_entry(&content, Path::new(file), &request.exec_dirs, &request.terminal, &request.files) {
+            Ok(launch) => launch,
+            Err(e) => {
+                rejected.push(format!("{}: {}", file, e));
+                continue;
+            }
+        };
+
+        if launch.dbus {
+            match dbus_activate(entry, &request.files) {
+                Ok(pid) => return Ok(Started {
+                    pid,
+                    ..Started::untracked(format!("✓ GUI app '{}' launched via D-Bus activation", entry))
+                }),
+                Err(e) => eprintln!("archy-launcher: D-Bus activation of {} failed: {}", entry, e),
+            }
+        }
+        let mut command = Command::new(&launch.argv[0]);
+        command.args(&launch.argv[1..]);
+        if let Some(dir) = &launch.dir {
+            command.current_dir(dir);
+        }
+        match tracked(&mut command, &format!("✓ GUI app '{}' launched (from desktop file)", entry)) {
+            Ok(Some(started)) => return Ok(started),
+            Ok(None) => {}
+            Err(e) => return Err(format!("GUI app '{}' {}", entry, e)),
+        }
+        if let Ok(mut child) = detached(Command::new("gtk-launch").arg(entry).args(&request.files)) {
+            // Give it a moment - a quick non-zero exit means gtk-launch couldn't start it
+            std::thread::sleep(Duration::from_millis(100));
+            match child.try_wait() {
+                Ok(Some(status)) if !status.success() => {}
+                _ => return Ok(Started::untracked(format!("✓ GUI app '{}' launched via gtk-launch", entry))),
+            }
+        }
+    }
+
+    if let Some(program) = resolve_program(entry).filter(|program| in_exec_dirs(program, &request.exec_dirs)) {
+        match tracked(Command::new(&program).args(&request.files), &format!("✓ GUI app '{}' launched directly", entry)) {
+            Ok(Some(started)) => return Ok(started),
+            Ok(None) => {}
+            Err(e) => return Err(format!("GUI app '{}' {}", entry, e)),
+        }
+    }
+
+    // A flatpak app id with no desktop file exported where the executor looks
+    if let Some(flatpak) = installed_flatpak(entry, &request.exec_dirs) {
+        let mut command = Command::new(&flatpak);
+        command.args(["run", entry.as_str()]).args(&request.files);
+        match tracked(&mut command, &format!("✓ GUI app '{}' launched via flatpak run", entry)) {
+            Ok(Some(started)) => return Ok(started),
+            Ok(None) => {}
+            Err(e) => return Err(format!("GUI app '{}' {}", entry, e)),
+        }
+    }
+
+    if !rejected.is_empty() {
+        return Err(format!("Refused to launch '{}': {}", entry, rejected.join("; ")));
+    }
+    Err(format!("Failed to launch GUI app '{}' - not found or not accessible", entry))
+}
+
+/// What an entry would run to open `files`, if TryExec is installed, Exec takes them, Exec (and the
+/// terminal, for Terminal=true) resolves inside `exec_dirs` and Path= is a directory
+fn vet_entry(content: &str, file: &Path, exec_dirs: &[String], terminal: &[String], files: &[String]) -> Result<Launch, String> {
+    let entry = DesktopEntry::parse(content);
+    if let Some(try_exec) = entry.get("TryExec").filter(|t| !t.is_empty()) {
+        if resolve_program(&try_exec).is_none() {
+            return Err(format!("TryExec {} is not installed", try_exec));
+        }
+    }
+    let exec = entry.exec_argv(files, Some(file))?;
+    // Sandboxed apps start through their runner - their Exec line is the runner's business
+    let mut argv = match entry.packaging().runner() {
+        Some(runner) => runner.into_iter().chain(files.iter().cloned()).collect(),
+        None => exec,
+    };
+    if entry.terminal() {
+        if terminal.is_empty() || !terminal.iter().any(|arg| arg == EXEC_PLACEHOLDER) {
+            return Err("it runs in a terminal and no terminal is configured".to_string());
+        }
+        argv = terminal.iter()
+            .flat_map(|arg| if arg == EXEC_PLACEHOLDER { argv.clone() } else { vec![arg.clone()] })
+            .collect();
+    }
+
+    let program = resolve_program(&argv[0]).ok_or_else(|| format!("{} is not an installed program", argv[0]))?;
+    if !in_exec_dirs(&program, exec_dirs) {
+        return Err(format!("{} is outside the allowed program directories", program.display()));
+    }
+    argv[0] = program.to_string_lossy().to_string();
+
+    let dir = match entry.get("Path").filter(|p| !p.is_empty()) {
+        Some(dir) if Path::new(&dir).is_absolute() && Path::new(&dir).is_dir() => Some(PathBuf::from(dir)),
+        Some(dir) => return Err(format!("Path {} is not a directory", dir)),
+        None => None,
+    };
+    Ok(Launch { argv, dir, dbus: entry.flag("DBusActivatable") })
+}
+
+/// Object path a D-Bus activatable app serves org.freedesktop.Application on (org.gnome.Calculator ->
+/// /org/gnome/Calculator)
+fn dbus_object_path(id: &str) -> String {
+    format!("/{}", id.replace('.', "/").replace('-', "_"))
+}
+
+/// Activate `id` on the session bus, or have it open `files` - the bus starts the app if it isn't
+/// running. The pid of whoever owns the name, if the bus says
+fn dbus_activate(id: &str, files: &[String]) -> Result<Option<u32>, String> {
+    let name = zbus::names::WellKnownName::try_from(id).map_err(|e| format!("{} is not a bus name: {}", id, e))?;
+    let connection = zbus::blocking::connection::Builder::session()
+        .map(|builder| builder.method_timeout(DBUS_TIMEOUT))
+        .and_then(|builder| builder.build())
+        .map_err(|e| format!("no session bus: {}", e))?;
+    // platform-data (startup notification ids and the like) - none to give
+    let platform_data: HashMap<&str, zbus::zvariant::Value> = HashMap::new();
+    let path = dbus_object_path(id);
+    let called = if files.is_empty() {
+        connection.call_method(Some(name.clone()), path.as_str(), Some("org.freedesktop.Application"), "Activate", &platform_data)
+    } else {
+        let uris: Vec<String> = files.iter().map(|file| desktop::file_uri(file)).collect();
+        connection.call_method(Some(name.clone()), path.as_str(), Some("org.freedesktop.Application"), "Open", &(uris, platform_data))
+    };
+    called.map_err(|e| e.to_string())?;
+
+    let pid = connection
+        .call_method(Some("org.freedesktop.DBus"), "/org/freedesktop/DBus", Some("org.freedesktop.DBus"), "GetConnectionUnixProcessID", &name.as_str())
+        .ok()
+        .and_then(|reply| reply.body().deserialize::<u32>().ok());
+    Ok(pid)
+}
+
+/// Spawn `command` detached and watch it for SURVIVAL_WINDOW. None if it couldn't be spawned at all
+/// (so the next way can be tried), an error if it exited non-zero in that time
+fn tracked(command: &mut Command, output: &str) -> Result<Option<Started>, String> {
+    let stderr = stderr_file();
+    let sink = match stderr.as_ref().and_then(|file| file.try_clone().ok()) {
+        Some(file) => Stdio::from(file),
+        None => Stdio::null(),
+    };
+    let Ok(mut child) = command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(sink).spawn() else {
+        return Ok(None);
+    };
+
+    let started = std::time::Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(None) if started.elapsed() < SURVIVAL_WINDOW => std::thread::sleep(Duration::from_millis(25)),
+            Ok(status) => break status,
+            Err(_) => break None,
+        }
+    };
+    let early = stderr.as_ref().map(read_stderr).filter(|text| !text.is_empty());
+    match status {
+        Some(status) if !status.success() => Err(format!(
+            "exited right after starting ({}){}",
+            status,
+            early.as_deref().map(|text| format!(": {}", text)).unwrap_or_default()
+        )),
+        // An exit 0 is normal for apps that hand over to an instance already running
+        status => Ok(Some(Started {
+            output: output.to_string(),
+            pid: Some(child.id()),
+            still_running: Some(status.is_none()),
+            stderr: early,
+        })),
+    }
+}
+
+/// A temp file nobody else can open - unlinked at once, the app keeps writing to it after we're gone
+fn stderr_file() -> Option<fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+    let path = std::env::temp_dir().join(format!("archy-launch-{}-{}", std::process::id(), nanos));
+    let file = fs::OpenOptions::new().read(true).write(true).create_new(true).mode(0o600).open(&path).ok()?;
+    let _ = fs::remove_file(&path);
+    Some(file)
+}
+
+/// The start of what was written to `file` - read by position, the app shares its offset
+fn read_stderr(file: &fs::File) -> String {
+    use std::os::unix::fs::FileExt;
+    let mut buffer = vec![0u8; MAX_STDERR_BYTES];
+    let read = file.read_at(&mut buffer, 0).unwrap_or(0);
+    String::from_utf8_lossy(&buffer[..read]).trim().to_string()
+}
+
+/// The flatpak binary, if `id` is an installed flatpak app and flatpak may be run
+fn installed_flatpak(id: &str, exec_dirs: &[String]) -> Option<PathBuf> {
+    if !desktop::is_flatpak_id(id) {
+        return None;
+    }
+    let flatpak = resolve_program("flatpak").filter(|program| in_exec_dirs(program, exec_dirs))?;
+    let installed = Command::new(&flatpak)
+        .args(["info", "--show-ref", id])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+    installed.then_some(flatpak)
+}
+
+/// Spawn without tying the app to our stdio - it outlives the launcher
+fn detached(command: &mut Command) -> std::io::Result<std::process::Child> {
+    command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).spawn()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vet_entry() {
+        let entry = "[Desktop Entry]\nName=Shell\nExec=sh -c true %U\n[Desktop Action x]\nExec=/tmp/evil\n";
+        let file = Path::new("/usr/share/applications/shell.desktop");
+        let sh = resolve_program("sh").unwrap();
+        let dir = fs::canonicalize(&sh).unwrap().parent().unwrap().to_string_lossy().to_string();
+
+        let launch = vet_entry(entry, file, std::slice::from_ref(&dir), &[], &[]).unwrap();
+        assert_eq!(launch.argv, vec![sh.to_string_lossy().to_string(), "-c".to_string(), "true".to_string()]);
+        assert_eq!(launch.dir, None);
+        assert!(!launch.dbus);
+        assert!(vet_entry(entry, file, &["/nonexistent".to_string()], &[], &[]).unwrap_err().contains("outside"));
+
+        let missing = "[Desktop Entry]\nTryExec=no-such-program-xyz\nExec=sh\n";
+        assert!(vet_entry(missing, file, std::slice::from_ref(&dir), &[], &[]).unwrap_err().contains("TryExec"));
+
+        let quoted = "[Desktop Entry]\nExec=sh -c \"echo \\\\$HOME\"\nPath=/tmp\n";
+        let launch = vet_entry(quoted, file, std::slice::from_ref(&dir), &[], &[]).unwrap();
+        assert_eq!(&launch.argv[1..], ["-c", "echo $HOME"]);
+        assert_eq!(launch.dir, Some(PathBuf::from("/tmp")));
+        let nowhere = "[Desktop Entry]\nExec=sh\nPath=/nonexistent/dir\n";
+        assert!(vet_entry(nowhere, file, std::slice::from_ref(&dir), &[], &[]).unwrap_err().contains("Path"));
+    }
+
+    #[test]
+    fn test_vet_entry_with_files() {
+        let file = Path::new("/usr/share/applications/viewer.desktop");
+        let sh = resolve_program("sh").unwrap();
+        let dir = fs::canonicalize(&sh).unwrap().parent().unwrap().to_string_lossy().to_string();
+        let files = vec!["/tmp/a b.pdf".to_string(), "file:///tmp/c%20d.pdf".to_string()];
+
+        // Each file one argument, file:// URIs as paths for %F
+        let viewer = "[Desktop Entry]\nExec=sh -c true --open %F\n";
+        let launch = vet_entry(viewer, file, std::slice::from_ref(&dir), &[], &files).unwrap();
+        assert_eq!(&launch.argv[3..], ["--open", "/tmp/a b.pdf", "/tmp/c d.pdf"]);
+        let browser = "[Desktop Entry]\nExec=sh %U\n";
+        let uri = vec!["https://example.org".to_string()];
+        assert_eq!(&vet_entry(browser, file, std::slice::from_ref(&dir), &[], &uri).unwrap().argv[1..], ["https://example.org"]);
+        assert!(vet_entry(viewer, file, std::slice::from_ref(&dir), &[], &uri).unwrap_err().contains("local files"));
+        assert!(vet_entry("[Desktop Entry]\nExec=sh\n", file, std::slice::from_ref(&dir), &[], &files).is_err());
+    }
+
+    #[test]
+    fn test_tracked_launch() {
+        let crashed = tracked(Command::new("sh").args(["-c", "echo 'cannot open display' >&2; exit 3"]), "ok").unwrap_err();
+        assert!(crashed.contains("exited") && crashed.contains("cannot open display"));
+
+        let started = tracked(Command::new("sh").args(["-c", "echo warming up >&2; sleep 2"]), "ok").unwrap().unwrap();
+        assert_eq!((started.still_running, started.stderr.as_deref()), (Some(true), Some("warming up")));
+        assert!(started.pid.is_some());
+        // Handed over to a running instance
+        let handed = tracked(&mut Command::new("true"), "ok").unwrap().unwrap();
+        assert_eq!((handed.still_running, handed.stderr), (Some(false), None));
+        assert_eq!(tracked(&mut Command::new("/nonexistent/app"), "ok"), Ok(None));
+    }
+
+    #[test]
+    fn test_dbus_activation() {
+        assert_eq!(dbus_object_path("org.gnome.Calculator"), "/org/gnome/Calculator");
+        assert_eq!(dbus_object_path("org.gnome.font-viewer"), "/org/gnome/font_viewer");
+
+        let entry = "[Desktop Entry]\nName=Calculator\nExec=sh\nDBusActivatable=true\n";
+        let sh = resolve_program("sh").unwrap();
+        let dir = fs::canonicalize(&sh).unwrap().parent().unwrap().to_string_lossy().to_string();
+        let launch = vet_entry(entry, Path::new("/usr/share/applications/org.gnome.Calculator.desktop"), &[dir], &[], &[]).unwrap();
+        assert!(launch.dbus);
+        // Not a bus name - refused before any connection is tried
+        assert!(dbus_activate("not a bus name", &[]).is_err());
+    }
+
+    #[test]
+    fn test_vet_terminal_entry() {
+        let entry = "[Desktop Entry]\nName=Top\nExec=top -d 2\nTerminal=true\n";
+        let file = Path::new("/usr/share/applications/top.desktop");
+        let sh = resolve_program("sh").unwrap();
+        let dir = fs::canonicalize(&sh).unwrap().parent().unwrap().to_string_lossy().to_string();
+
+        assert!(vet_entry(entry, file, std::slice::from_ref(&dir), &[], &[]).unwrap_err().contains("terminal"));
+        // The terminal is what gets started (and checked), the entry's Exec its argument
+        let terminal = vec!["sh".to_string(), "-c".to_string(), "exec \"$@\"".to_string(), "term".to_string(), EXEC_PLACEHOLDER.to_string()];
+        let launch = vet_entry(entry, file, std::slice::from_ref(&dir), &terminal, &[]).unwrap();
+        assert_eq!(launch.argv[0], sh.to_string_lossy());
+        assert_eq!(&launch.argv[4..], ["top", "-d", "2"]);
+    }
+}
@@ -0,0 +1,234 @@
+// bundle.rs - Debug bundles to attach to bug reports
+// `collect_debug_bundle` writes one .tar.gz to `[paths] bundle_dir` holding what a maintainer asks
+// for first:
+//
+//   version.json      executor, protocol, tmux and kernel versions
+//   config.json       effective config with each value's source, plus validation problems
+//   environment.json  display/shell variables, ARCHY_* overrides and the doctor report
+//   requests.json     recent request timings and the last request/reply exchanges
+//   events.json       the daemon event log
+//   stats.json        per-action latency statistics (when enabled)
+//   daemon.log        the tail of the log file, or of the systemd journal when logging to stderr
+//
+// Every file goes through the configured-secret and leak redaction before it's written, since
+// bundles are made to be shared.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::Path;
+use std::time::Duration;
+use crate::artifacts;
+use crate::config::Config;
+use crate::doctor;
+use crate::eventlog::{self, EventQuery};
+use crate::helpers::process;
+use crate::leaks;
+use crate::secrets;
+use crate::stats::{self, StatsQuery};
+use crate::timings::{self, RecentQuery};
+
+/// Log lines included when the client gives no number
+const DEFAULT_LOG_LINES: usize = 500;
+
+/// Upper bound for `log_lines`
+const MAX_LOG_LINES: usize = 10_000;
+
+/// Only this much of the end of the log file is read
+const MAX_LOG_READ: u64 = 4 * 1024 * 1024;
+
+/// Exchanges included when the client gives no number
+const DEFAULT_REQUESTS: usize = 20;
+
+/// For tar, journalctl, tmux and uname
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Environment variables worth seeing (ARCHY_* are added on top)
+const ENV_VARS: &[&str] = &[
+    "SHELL", "TERM", "LANG", "XDG_SESSION_TYPE", "XDG_CURRENT_DESKTOP", "XDG_RUNTIME_DIR", "WAYLAND_DISPLAY",
+    "DISPLAY", "TMUX_TMPDIR",
+];
+
+/// systemd units the daemon ships with, user unit first
+const UNITS: &[(&str, bool)] = &[("archy-executor-user.service", true), ("archy-executor.service", false)];
+
+/// `collect_debug_bundle` options - all optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BundleOptions {
+    pub requests: Option<usize>, // request/reply exchanges to include (default 20)
+    pub log_lines: Option<usize>, // default 500
+}
+
+#[derive(Debug, Serialize)]
+pub struct Bundle {
+    pub path: String,
+    pub bytes: u64,
+    pub files: Vec<&'static str>,
+}
+
+/// Gather everything into `<bundle_dir>/archy-debug-<id>.tar.gz`
+pub fn collect(options: &BundleOptions, config: &Config) -> Result<Bundle, String> {
+    let name = format!("archy-debug-{}", artifacts::new_id());
+    let dir = Path::new(&config.bundle_dir);
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .map_err(|e| format!("Cannot create bundle dir {}: {}", dir.display(), e))?;
+    let staging = dir.join(&name);
+    fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&staging)
+        .map_err(|e| format!("Cannot create {}: {}", staging.display(), e))?;
+
+    let result = write_files(&staging, options, config).and_then(|files| {
+        let tarball = dir.join(format!("{}.tar.gz", name));
+        let argv: Vec<String> = vec![
+            "tar".into(), "-czf".into(), tarball.display().to_string(), "-C".into(), dir.display().to_string(), name.clone(),
+        ];
+        match process::run(&argv, COMMAND_TIMEOUT) {
+            Ok((0, _, _)) => {}
+            Ok((_, _, stderr)) => return Err(format!("tar failed: {}", stderr.trim())),
+            Err(e) => return Err(format!("Cannot run tar: {}", e)),
+        }
+        let bytes = fs::set_permissions(&tarball, fs::Permissions::from_mode(0o600))
+            .and_then(|()| fs::metadata(&tarball))
+            .map_err(|e| format!("Cannot finish {}: {}", tarball.display(), e))?
+            .len();
+        Ok(Bundle { path: tarball.display().to_string(), bytes, files })
+    });
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+fn write_files(staging: &Path, options: &BundleOptions, config: &Config) -> Result<Vec<&'static str>, String> {
+    let requests = options.requests.unwrap_or(DEFAULT_REQUESTS).min(timings::MAX_EXCHANGES);
+    let log_lines = options.log_lines.unwrap_or(DEFAULT_LOG_LINES).min(MAX_LOG_LINES);
+
+    let (errors, warnings) = config.validate();
+    let stats = match stats::report(&StatsQuery::default(), config) {
+        Ok(report) => json!(report),
+        Err(e) => json!({"disabled": e}),
+    };
+    let doctor = doctor::run(config);
+    let files: Vec<(&'static str, String)> = vec![
+        ("version.json", pretty(&json!({
+            "executor": env!("CARGO_PKG_VERSION"),
+            "protocol": archy_protocol::PROTOCOL_VERSION,
+            "pid": std::process::id(),
+            "tmux": command_output(&["tmux", "-V"]),
+            "kernel": command_output(&["uname", "-srmv"]),
+        }))),
+        ("config.json", pretty(&json!({
+            "files": config.config_files,
+            "values": config.effective_values(),
+            "errors": errors,
+            "warnings": warnings,
+        }))),
+        ("environment.json", pretty(&json!({
+            "variables": environment(),
+            "doctor": {"status": doctor.status, "summary": doctor.summary(), "checks": doctor.checks},
+        }))),
+        ("requests.json", pretty(&json!({
+            "timings": timings::recent(&RecentQuery { limit: Some(requests), ..RecentQuery::default() }),
+            "exchanges": timings::exchanges(requests),
+        }))),
+        ("events.json", pretty(&json!(eventlog::query(&EventQuery { limit: Some(eventlog::MAX_EVENTS), ..EventQuery::default() })))),
+        ("stats.json", pretty(&stats)),
+        ("daemon.log", log_tail(config, log_lines)),
+    ];
+
+    let mut written = Vec::new();
+    for (file, content) in files {
+        let path = staging.join(file);
+        fs::write(&path, leaks::scrub(&secrets::redact(&content)))
+            .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+        written.push(file);
+    }
+    Ok(written)
+}
+
+fn pretty(value: &Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_else(|e| format!("serialization error: {}", e))
+}
+
+/// First line of a command's stdout, or why there is none
+fn command_output(argv: &[&str]) -> String {
+    let argv: Vec<String> = argv.iter().map(|arg| arg.to_string()).collect();
+    match process::run(&argv, COMMAND_TIMEOUT) {
+        Ok((0, stdout, _)) => stdout.lines().next().unwrap_or("").trim().to_string(),
+        Ok((code, _, stderr)) => format!("exit {}: {}", code, stderr.trim()),
+        Err(e) => e,
+    }
+}
+
+fn environment() -> Value {
+    let mut variables = serde_json::Map::new();
+    for (name, value) in std::env::vars() {
+        if ENV_VARS.contains(&name.as_str()) || name.starts_with("ARCHY_") {
+            variables.insert(name, Value::String(value));
+        }
+    }
+    Value::Object(variables)
+}
+
+/// The last `lines` lines the daemon logged
+fn log_tail(config: &Config, lines: usize) -> String {
+    if let Some(file) = &config.log_file {
+        return match read_tail(Path::new(file), lines) {
+            Ok(tail) => tail,
+            Err(e) => format!("# Cannot read log file {}: {}\n", file, e),
+        };
+    }
+    // Logging to stderr - under systemd that's the journal
+    for (unit, user) in UNITS {
+        let mut argv = vec!["journalctl".to_string()];
+        if *user {
+            argv.push("--user".to_string());
+        }
+        argv.extend(["-u", unit, "-n", &lines.to_string(), "-o", "short-iso", "--no-pager", "-q"].map(str::to_string));
+        if let Ok((0, stdout, _)) = process::run(&argv, COMMAND_TIMEOUT) {
+            if !stdout.trim().is_empty() {
+                return format!("# journalctl{} -u {}\n{}", if *user { " --user" } else { "" }, unit, stdout);
+            }
+        }
+    }
+    "# No log file configured and no journal entries for the archy units - set [log] file to capture logs\n".to_string()
+}
+
+fn read_tail(path: &Path, lines: usize) -> Result<String, String> {
+    let mut file = fs::File::open(path).map_err(|e| e.to_string())?;
+    let len = file.metadata().map_err(|e| e.to_string())?.len();
+    let start = len.saturating_sub(MAX_LOG_READ);
+    file.seek(SeekFrom::Start(start)).map_err(|e| e.to_string())?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+    let text = String::from_utf8_lossy(&bytes);
+    Ok(tail_lines(&text, lines, start > 0))
+}
+
+/// The last `lines` lines of `text` (a partial first line is dropped when `text` starts mid-file)
+fn tail_lines(text: &str, lines: usize, mid_file: bool) -> String {
+    let mut all: Vec<&str> = text.lines().collect();
+    if mid_file && !all.is_empty() {
+        all.remove(0);
+    }
+    let skip = all.len().saturating_sub(lines);
+    let mut tail = all[skip..].join("\n");
+    tail.push('\n');
+    tail
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tail_lines() {
+        assert_eq!(tail_lines("a\nb\nc\n", 2, false), "b\nc\n");
+        assert_eq!(tail_lines("tial\nb\nc", 5, true), "b\nc\n");
+    }
+}
@@ -0,0 +1,79 @@
+// canonical.rs - Canonical JSON serialization and content hashing
+// Identical results always serialize (and hash) identically, so history and the brain can dedup them
+
+use serde::Serialize;
+use serde_json::{Map, Number, Value};
+use sha2::{Digest, Sha256};
+
+/// Floats are rounded to this many decimal places before hashing/serializing
+const FLOAT_PRECISION: i32 = 6;
+
+/// Normalize a JSON value: sorted object keys, rounded floats, integral floats as integers
+pub fn canonicalize(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            let mut sorted = Map::new();
+            for key in keys {
+                sorted.insert(key.clone(), canonicalize(&map[key]));
+            }
+            Value::Object(sorted)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(canonicalize).collect()),
+        Value::Number(n) => normalize_number(n),
+        other => other.clone(),
+    }
+}
+
+fn normalize_number(n: &Number) -> Value {
+    if n.is_i64() || n.is_u64() {
+        return Value::Number(n.clone());
+    }
+
+    let f = n.as_f64().unwrap_or(0.0);
+    let factor = 10f64.powi(FLOAT_PRECISION);
+    let rounded = (f * factor).round() / factor;
+
+    if rounded.fract() == 0.0 && rounded.abs() < i64::MAX as f64 {
+        Value::from(rounded as i64)
+    } else {
+        Number::from_f64(rounded).map(Value::Number).unwrap_or(Value::Null)
+    }
+}
+
+/// Serialize any value in canonical form (compact, sorted keys, normalized floats)
+pub fn to_canonical_string<T: Serialize>(value: &T) -> Result<String, String> {
+    let json = serde_json::to_value(value)
+        .map_err(|e| format!("Serialization error: {}", e))?;
+    serde_json::to_string(&canonicalize(&json))
+        .map_err(|e| format!("Serialization error: {}", e))
+}
+
+/// Hex SHA-256 of the canonical serialization
+pub fn content_hash<T: Serialize>(value: &T) -> String {
+    let canonical = to_canonical_string(value).unwrap_or_default();
+    let digest = Sha256::digest(canonical.as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_key_order_does_not_matter() {
+        let a = json!({"b": 1, "a": {"y": 2, "x": 1}});
+        let b = json!({"a": {"x": 1, "y": 2}, "b": 1});
+        assert_eq!(content_hash(&a), content_hash(&b));
+        assert_eq!(to_canonical_string(&a).unwrap(), r#"{"a":{"x":1,"y":2},"b":1}"#);
+    }
+
+    #[test]
+    fn test_floats_normalized() {
+        assert_eq!(canonicalize(&json!(1.0)), json!(1));
+        assert_eq!(canonicalize(&json!(0.1234567891)), json!(0.123457));
+        assert_eq!(content_hash(&json!({"v": 2.0})), content_hash(&json!({"v": 2})));
+    }
+}
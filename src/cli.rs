@@ -0,0 +1,300 @@
+// cli.rs - Command-line interface for the executor binary
+// `archy serve` runs the daemon; exec/status/config talk to (or inspect) it without hand-written JSON.
+// `archy classify` runs the command policy offline - rust-brain uses it to judge commands in fragments.
+
+use clap::{Parser, Subcommand};
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+use crate::config::Config;
+use crate::helpers::security::{check_blocked_patterns, validate_command};
+use crate::risk;
+
+#[derive(Debug, Parser)]
+#[command(name = "archy", version, about = "Archy executor daemon and client")]
+pub struct Cli {
+    /// Extra config file layered over /etc/archy and ~/.config/archy (env vars still win)
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Commands {
+    /// Run the executor daemon (default when no subcommand is given)
+    Serve,
+    /// Run one command through the daemon and print the analyzed result
+    Exec {
+        /// Shell command to run in the tmux session
+        command: String,
+        /// tmux session (defaults to the configured session)
+        #[arg(long)]
+        session: Option<String>,
+        /// Maximum seconds to wait for completion
+        #[arg(long, value_name = "SECONDS")]
+        max_wait: Option<u64>,
+        /// Print the full DisplayOutput JSON instead of the rendered display
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check that the daemon is reachable and the session exists
+    Status,
+    /// Judge commands against the security policy without running them (JSON out; one command per
+    /// line on stdin when none are given)
+    Classify {
+        commands: Vec<String>,
+    },
+    /// Inspect configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Validate config files and print the effective configuration with sources
+    #[command(alias = "validate")]
+    Check,
+}
+
+/// Send one request to the daemon and read the full JSON reply
+pub fn send_request(socket_path: &str, action: &str, data: Value, timeout: Duration) -> Result<Value, String> {
+    let mut stream = UnixStream::connect(socket_path)
+        .map_err(|e| format!("Cannot connect to {}: {} (is `archy serve` running?)", socket_path, e))?;
+    stream.set_read_timeout(Some(timeout)).map_err(|e| e.to_string())?;
+
+    let request = json!({"action": action, "data": data});
+    stream.write_all(request.to_string().as_bytes())
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+    let _ = stream.shutdown(std::net::Shutdown::Write);
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)
+        .map_err(|e| format!("Failed to read reply: {}", e))?;
+    serde_json::from_str(&reply).map_err(|e| format!("Invalid reply from daemon: {}", e))
+}
+
+/// `archy exec` - returns the process exit code
+pub fn exec(config: &Config, command: &str, session: Option<&str>, max_wait: Option<u64>, as_json: bool) -> i32 {
+    let max_wait = max_wait.unwrap_or(config.max_wait_seconds);
+    let data = json!({
+        "command": command,
+        "session": session.unwrap_or(&config.default_session),
+        "max_wait": max_wait,
+    });
+
+    // The daemon answers once the command finishes - leave headroom over max_wait
+    let timeout = Duration::from_secs(max_wait + 30);
+    let mut reply = match send_request(&config.socket_path, "execute_and_wait", data, timeout) {
+        Ok(reply) => reply,
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            return 2;
+        }
+    };
+
+    // Destructive commands are held by the daemon - the person at the terminal decides
+    if reply.get("status").and_then(|v| v.as_str()) == Some("confirmation_required") {
+        let token = reply.get("token").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+        if !ask_confirmation(&reply) {
+            eprintln!("❌ Not confirmed - nothing was run");
+            return 1;
+        }
+        reply = match send_request(&config.socket_path, "confirm_execute", json!({"token": token}), timeout) {
+            Ok(reply) => reply,
+            Err(e) => {
+                eprintln!("❌ {}", e);
+                return 2;
+            }
+        };
+    }
+
+    // Requests rejected before running come back as a plain error response
+    if reply.get("display").is_none() {
+        if let Some(error) = reply.get("error").and_then(|v| v.as_str()) {
+            eprintln!("❌ {}", error);
+            return 1;
+        }
+    }
+
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&reply).unwrap_or_default());
+    } else {
+        let display = reply.get("display").and_then(|v| v.as_str()).unwrap_or_default();
+        println!("{}", display);
+    }
+
+    match reply.get("status").and_then(|v| v.as_str()) {
+        Some("error") | Some("timeout") => reply.get("exit_code")
+            .and_then(|v| v.as_i64())
+            .filter(|code| *code != 0)
+            .unwrap_or(1) as i32,
+        _ => 0,
+    }
+}
+
+/// Show why a command was held and ask on the terminal (never confirms without a TTY)
+fn ask_confirmation(reply: &Value) -> bool {
+    let risk = &reply["risk"];
+    eprintln!(
+        "⚠️  {} command: {}",
+        risk["class"].as_str().unwrap_or("risky"),
+        risk["reason"].as_str().unwrap_or_default()
+    );
+    for command in reply["commands"].as_array().into_iter().flatten() {
+        eprintln!("   {}", command.as_str().unwrap_or_default());
+    }
+
+    if !std::io::IsTerminal::is_terminal(&std::io::stdin()) {
+        return false;
+    }
+    eprint!("Run it? [y/N] ");
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).is_ok()
+        && matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// `archy status` - returns the process exit code
+pub fn status(config: &Config) -> i32 {
+    println!("Socket:  {}", config.socket_path);
+    println!("Session: {}", config.default_session);
+
+    match send_request(&config.socket_path, "check_session", json!({}), Duration::from_secs(10)) {
+        Ok(reply) => {
+            println!("Daemon:  ✅ running");
+            let exists = reply.get("exists").and_then(|v| v.as_bool()).unwrap_or(false);
+            println!("tmux:    {}", if exists { "✅ session exists" } else { "⚠️ session not started" });
+            0
+        }
+        Err(e) => {
+            println!("Daemon:  ❌ {}", e);
+            1
+        }
+    }
+}
+
+/// Policy verdict for one command: blocked (validation or `blocked_patterns`) and its risk class
+pub fn policy_verdict(config: &Config, command: &str) -> Value {
+    let blocked = validate_command(command)
+        .and_then(|_| check_blocked_patterns(command, &config.blocked_patterns))
+        .err();
+    json!({
+        "command": command,
+        "allowed": blocked.is_none(),
+        "blocked": blocked,
+        "risk": risk::classify(command),
+    })
+}
+
+/// `archy classify` - prints a JSON array of verdicts
+pub fn classify(config: &Config, commands: Vec<String>) -> i32 {
+    let commands = if commands.is_empty() {
+        let mut input = String::new();
+        if let Err(e) = std::io::stdin().read_to_string(&mut input) {
+            eprintln!("❌ Cannot read stdin: {}", e);
+            return 1;
+        }
+        input.lines().filter(|line| !line.trim().is_empty()).map(str::to_string).collect()
+    } else {
+        commands
+    };
+    let verdicts: Vec<Value> = commands.iter().map(|command| policy_verdict(config, command)).collect();
+    println!("{}", Value::Array(verdicts));
+    0
+}
+
+/// `archy config check` - validation report with every effective value and its source
+pub fn config_check(config: &Config, env_errors: &[String]) -> i32 {
+    let mut report = config.diagnostics();
+    report.errors.splice(0..0, env_errors.iter().cloned());
+    report.valid = report.errors.is_empty();
+
+    if report.config_files.is_empty() {
+        println!("No config files found (using defaults and environment)");
+    }
+    for path in &report.config_files {
+        println!("📄 {}", path);
+    }
+
+    println!();
+    for value in &report.values {
+        println!("{:<22} = {:<32} ({})", value.key, value.value.to_string(), value.source);
+    }
+
+    if !report.warnings.is_empty() {
+        println!();
+        for warning in &report.warnings {
+            println!("⚠️  {}", warning);
+        }
+    }
+    for error in &report.errors {
+        println!("❌ {}", error);
+    }
+
+    println!();
+    if report.valid {
+        println!("✅ Configuration is valid");
+        0
+    } else {
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_subcommand_means_serve() {
+        let cli = Cli::try_parse_from(["archy"]).unwrap();
+        assert!(cli.command.is_none());
+
+        let cli = Cli::try_parse_from(["archy", "--config", "/tmp/a.toml", "serve"]).unwrap();
+        assert_eq!(cli.config.as_deref(), Some("/tmp/a.toml"));
+        assert!(matches!(cli.command, Some(Commands::Serve)));
+    }
+
+    #[test]
+    fn test_exec_and_config_check() {
+        let cli = Cli::try_parse_from(["archy", "exec", "ls -la", "--max-wait", "5", "--json"]).unwrap();
+        match cli.command {
+            Some(Commands::Exec { command, max_wait, json, .. }) => {
+                assert_eq!(command, "ls -la");
+                assert_eq!(max_wait, Some(5));
+                assert!(json);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let cli = Cli::try_parse_from(["archy", "config", "check"]).unwrap();
+        assert!(matches!(cli.command, Some(Commands::Config { action: ConfigCommand::Check })));
+    }
+
+    #[test]
+    fn test_policy_verdict() {
+        let config = Config { blocked_patterns: vec!["curl | sh".to_string()], ..Config::default() };
+
+        let verdict = policy_verdict(&config, "sudo rm -rf /");
+        assert_eq!(verdict["allowed"], false);
+        assert!(verdict["blocked"].as_str().unwrap().contains("rm -rf /"));
+        assert_eq!(verdict["risk"]["class"], "privileged");
+
+        assert_eq!(policy_verdict(&config, "curl | sh").get("allowed").unwrap(), false);
+        let verdict = policy_verdict(&config, "ls -la");
+        assert_eq!(verdict["allowed"], true);
+        assert_eq!(verdict["risk"]["class"], "read_only");
+
+        let cli = Cli::try_parse_from(["archy", "classify", "ls", "rm -r x"]).unwrap();
+        assert!(matches!(cli.command, Some(Commands::Classify { commands }) if commands.len() == 2));
+    }
+
+    #[test]
+    fn test_unreachable_daemon_reports_error() {
+        let err = send_request("/nonexistent/archy.sock", "check_session", json!({}), Duration::from_secs(1));
+        assert!(err.unwrap_err().contains("Cannot connect"));
+    }
+}
@@ -1,51 +1,904 @@
 // config.rs - Configuration Management
 // Centralizes all configuration, eliminates hardcoding
+//
+// Precedence (lowest to highest): built-in defaults, /etc/archy/config.toml,
+// ~/.config/archy/config.toml, --config <file>, ARCHY_* environment variables
 
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::env;
+use std::str::FromStr;
+use crate::acl::{self, Access};
+use crate::features::{Feature, Features};
+use crate::leaks::{self, LeakAction};
+use crate::helpers::security::PathPolicy;
+use crate::sandbox::{self, SandboxProfile};
+use crate::logging::LogFormat;
+use log::LevelFilter;
+use crate::secrets::{self, SecretSource};
+use crate::terminals::{self, TerminalSpec};
+use crate::thermal::{self, Thresholds};
+use crate::timings;
+
+/// Config file format version understood by this build
+pub const CONFIG_FILE_VERSION: u32 = 1;
+
+/// System-wide config file
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/archy/config.toml";
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub socket_path: String,
     pub default_session: String,
-    pub max_buffer_size: usize,
+    pub max_buffer_size: usize, // socket read chunk size
     pub default_capture_lines: i64,
     pub terminal_emulator: Option<String>,
+    pub terminal_preference: Vec<String>,                 // tried after terminal_emulator, before built-ins
+    pub terminal_templates: BTreeMap<String, TerminalSpec>, // custom terminals by name
     pub max_wait_seconds: u64,
     pub poll_interval_ms: u64,
+
+    // Request size limits - independent of the read buffer so large batches fit
+    pub max_request_bytes: usize,
+    pub unix_max_request_bytes: Option<usize>, // per-transport override
+
+    // REST gateway (`http` cargo feature) - clients present the token and get `http_access`
+    pub http_enabled: bool,
+    pub http_listen: String,
+    pub http_token_file: Option<String>,
+    pub http_access: Access,
+    pub http_max_request_bytes: Option<usize>, // per-transport override
+
+    // Output size budgets - anything larger spills to an artifact file
+    pub max_raw_output_bytes: usize,
+    pub max_display_bytes: usize,
+    pub max_structured_bytes: usize,
+    pub artifact_dir: String,
+
+    // Batch checkpoints for resume_batch
+    pub batch_state_dir: String,
+
+    // Saved workflows (persist across reboots, unlike the /tmp dirs)
+    pub workflow_dir: String,
+
+    // Tarballs from collect_debug_bundle
+    pub bundle_dir: String,
+
+    // Theme - false sends the plain rendering in `display` too
+    pub colors: bool,
+
+    // Security policy - extra substrings rejected on top of the built-in blocklist
+    pub blocked_patterns: Vec<String>,
+    pub confirm_destructive: bool,      // hold destructive/privileged commands for confirm_execute
+    pub confirmation_ttl_seconds: u64,  // how long a confirmation token stays valid
+    pub allow_unmanaged_sessions: bool, // send commands to tmux sessions archy didn't create or claim
+
+    // Outbound secret scanning - matches in replies are redacted or the reply is withheld
+    pub leak_scan: LeakAction,
+    pub leak_patterns: BTreeMap<String, String>, // name -> regex, on top of the built-in patterns
+
+    // Path policy for actions that read or write files (desktop entries, ...) - `~` is $HOME
+    pub fs_allowed_roots: Vec<String>,
+    pub fs_denied_paths: Vec<String>, // globs, checked against the resolved path
+    pub fs_max_depth: usize,
+    pub fs_exec_dirs: Vec<String>,     // programs a desktop entry may start must live under one of these
+    pub desktop_trust_seconds: u64,    // desktop files changed more recently need confirm_execute (0 = off)
+
+    // Sandboxed execution (`"sandbox": true` or a profile name on execute requests)
+    pub sandbox_backend: String, // auto, bwrap or firejail
+    pub sandbox_profiles: BTreeMap<String, SandboxProfile>,
+
+    // Rate limits (commands per minute, 0 = unlimited) and anomaly rules that lock execution
+    pub rate_session_per_minute: u32,
+    pub rate_global_per_minute: u32,
+    pub rate_privileged_burst: u32,        // privileged commands per minute before locking
+    pub rate_failed_validation_burst: u32, // rejected commands per minute before locking
+    pub rate_lockout_seconds: u64,
+
+    // Socket access control - peers by uid or user name; the daemon's own user is admin unless listed
+    pub acl_default: Access,
+    pub acl_users: BTreeMap<String, Access>,
+
+    // Audit log - one JSON line per request, optionally hash-chained
+    pub audit_enabled: bool,
+    pub audit_log: String,
+    pub audit_hash_chain: bool,
+
+    // Logging - the level can also be changed at runtime (set_log_level)
+    pub log_level: LevelFilter,
+    pub log_file: Option<String>, // None = stderr
+    pub log_format: LogFormat,
+    pub slow_request_ms: u64,   // requests slower than this are logged with their phases and data (0 = off)
+    pub recent_requests: usize, // timed requests kept for get_recent_requests
+
+    // Usage statistics for `stats` - latency per action, most-run commands, kept across restarts
+    pub stats_enabled: bool,
+    pub stats_path: String,
+    pub stats_goal_ms: u64, // latency the `stats` report measures actions against
+
+    // Capability switches enforced at the dispatcher
+    pub features: Features,
+
+    // Temperatures (°C) from which thermal_info and the monitor stream report findings
+    pub thermal: Thresholds,
+
+    // Session watchdog - recreates managed sessions after a tmux crash or when a pane stops responding
+    pub watchdog_enabled: bool,
+    pub watchdog_interval_seconds: u64,
+    pub watchdog_stall_seconds: u64, // a command's keys unechoed this long marks the pane wedged
+
+    // Session-output memory - results forwarded to a rust-brain socket for recall_similar_outputs
+    pub brain_memory: bool,
+    pub brain_socket: String,
+    pub brain_collection: String,
+
+    // Named secrets for `{{secret:name}}` (only sources - values are never held in config)
+    pub secrets: BTreeMap<String, SecretSource>,
+    pub secret_dir: String,
+
+    // Config files that were found and applied, in order
+    pub config_files: Vec<String>,
+
+    // Where each non-default setting came from ("file:<path>" or "env:<VAR>")
+    pub sources: HashMap<&'static str, String>,
+}
+
+/// On-disk config file - every section and key is optional and layers over what's already set
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileConfig {
+    pub version: Option<u32>,
+    #[serde(default)]
+    pub server: ServerSection,
+    #[serde(default)]
+    pub session: SessionSection,
+    #[serde(default)]
+    pub timing: TimingSection,
+    #[serde(default)]
+    pub output: OutputSection,
+    #[serde(default)]
+    pub paths: PathsSection,
+    #[serde(default)]
+    pub terminals: TerminalsSection,
+    #[serde(default)]
+    pub theme: ThemeSection,
+    #[serde(default)]
+    pub security: SecuritySection,
+    pub secrets: Option<BTreeMap<String, SecretSource>>,
+    #[serde(default)]
+    pub features: FeaturesSection,
+    #[serde(default)]
+    pub log: LogSection,
+    #[serde(default)]
+    pub audit: AuditSection,
+    #[serde(default)]
+    pub acl: AclSection,
+    #[serde(default)]
+    pub rate_limit: RateLimitSection,
+    #[serde(default)]
+    pub leak_scan: LeakScanSection,
+    #[serde(default)]
+    pub filesystem: FilesystemSection,
+    #[serde(default)]
+    pub sandbox: SandboxSection,
+    #[serde(default)]
+    pub brain: BrainSection,
+    #[serde(default)]
+    pub thermal: ThermalSection,
+    #[serde(default)]
+    pub watchdog: WatchdogSection,
+    #[serde(default)]
+    pub stats: StatsSection,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerSection {
+    pub socket_path: Option<String>,
+    pub max_buffer_size: Option<usize>,
+    pub max_request_bytes: Option<usize>,
+    #[serde(default)]
+    pub unix: TransportSection,
+    #[serde(default)]
+    pub http: HttpSection,
+}
+
+/// `[server.http]` - the REST gateway, with its own request limit
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpSection {
+    pub enabled: Option<bool>,
+    pub listen: Option<String>,
+    pub token_file: Option<String>,
+    pub access: Option<Access>,
+    pub max_request_bytes: Option<usize>,
+}
+
+/// Per-transport overrides, e.g. `[server.unix]`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TransportSection {
+    pub max_request_bytes: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SessionSection {
+    pub default: Option<String>,
+    pub capture_lines: Option<i64>,
+    pub terminal: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TimingSection {
+    pub max_wait_seconds: Option<u64>,
+    pub poll_interval_ms: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputSection {
+    pub max_raw_output_bytes: Option<usize>,
+    pub max_display_bytes: Option<usize>,
+    pub max_structured_bytes: Option<usize>,
+    pub artifact_dir: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PathsSection {
+    pub batch_state_dir: Option<String>,
+    pub workflow_dir: Option<String>,
+    pub secret_dir: Option<String>,
+    pub bundle_dir: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TerminalsSection {
+    pub preference: Option<Vec<String>>,
+    pub custom: Option<BTreeMap<String, TerminalSpec>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LeakScanSection {
+    pub action: Option<LeakAction>,
+    pub patterns: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FilesystemSection {
+    pub allowed_roots: Option<Vec<String>>,
+    pub denied: Option<Vec<String>>,
+    pub max_depth: Option<usize>,
+    pub exec_dirs: Option<Vec<String>>,
+    pub desktop_trust_seconds: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SandboxSection {
+    pub backend: Option<String>,
+    pub profiles: Option<BTreeMap<String, SandboxProfile>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitSection {
+    pub session_per_minute: Option<u32>,
+    pub global_per_minute: Option<u32>,
+    pub privileged_burst: Option<u32>,
+    pub failed_validation_burst: Option<u32>,
+    pub lockout_seconds: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AclSection {
+    pub default: Option<Access>,
+    pub users: Option<BTreeMap<String, Access>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuditSection {
+    pub enabled: Option<bool>,
+    pub path: Option<String>,
+    pub hash_chain: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogSection {
+    pub level: Option<LevelFilter>,
+    pub file: Option<String>,
+    pub format: Option<LogFormat>,
+    pub slow_request_ms: Option<u64>,
+    pub recent_requests: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeaturesSection {
+    pub gui: Option<bool>,
+    pub fallback_terminal: Option<bool>,
+    pub file_write: Option<bool>,
+    pub direct_executor: Option<bool>,
+    pub tcp_listener: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BrainSection {
+    pub memory: Option<bool>,
+    pub socket: Option<String>,
+    pub collection: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThermalSection {
+    pub cpu_warning: Option<f64>,
+    pub cpu_critical: Option<f64>,
+    pub gpu_warning: Option<f64>,
+    pub gpu_critical: Option<f64>,
+    pub nvme_warning: Option<f64>,
+    pub nvme_critical: Option<f64>,
+    pub other_warning: Option<f64>,
+    pub other_critical: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WatchdogSection {
+    pub enabled: Option<bool>,
+    pub interval_seconds: Option<u64>,
+    pub stall_seconds: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StatsSection {
+    pub enabled: Option<bool>,
+    pub path: Option<String>,
+    pub goal_ms: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThemeSection {
+    pub colors: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SecuritySection {
+    pub blocked_patterns: Option<Vec<String>>,
+    pub confirm_destructive: Option<bool>,
+    pub confirmation_ttl_seconds: Option<u64>,
+    pub allow_unmanaged_sessions: Option<bool>,
+}
+
+impl FileConfig {
+    /// Parse a config file's contents (rejects unknown keys and unsupported versions)
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let file: FileConfig = toml::from_str(content).map_err(|e| e.to_string())?;
+        match file.version {
+            None | Some(CONFIG_FILE_VERSION) => Ok(file),
+            Some(other) => Err(format!(
+                "unsupported config version {} (this build understands version {})",
+                other, CONFIG_FILE_VERSION
+            )),
+        }
+    }
+}
+
+/// Read and parse an environment variable - unset is None, unparsable is an error (never a silent default)
+fn env_parse<T: FromStr>(key: &str) -> Result<Option<T>, String> {
+    match env::var(key) {
+        Ok(raw) => raw.trim().parse().map(Some).map_err(|_| {
+            format!("{}={:?} is not a valid {}", key, raw, std::any::type_name::<T>())
+        }),
+        Err(_) => Ok(None),
+    }
+}
+
+/// One effective setting and where its value came from
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigValue {
+    pub key: &'static str,
+    pub value: serde_json::Value,
+    pub source: String, // "default", "file:<path>" or "env:<VAR>"
+}
+
+/// Result of validating the effective configuration
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigDiagnostics {
+    pub valid: bool,
+    pub config_files: Vec<String>,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+    pub values: Vec<ConfigValue>,
 }
 
 impl Config {
-    /// Load configuration from environment variables with sensible defaults
-    pub fn from_env() -> Self {
-        Config {
-            socket_path: env::var("ARCHY_SOCKET")
-                .unwrap_or_else(|_| "/tmp/archy.sock".to_string()),
+    /// Load the full layered configuration: defaults, system file, user file, `--config` file, env
+    /// Fails on unreadable/invalid files, unparsable env values and invalid settings
+    pub fn load(explicit_path: Option<&str>) -> Result<Self, String> {
+        let (config, mut errors) = Config::load_layers(explicit_path)?;
+        errors.extend(config.validate().0);
+        if !errors.is_empty() {
+            return Err(format!("Invalid configuration:\n  - {}", errors.join("\n  - ")));
+        }
+        Ok(config)
+    }
+
+    /// Apply every layer without validating - returns the config plus unparsable env variable errors
+    /// (only unreadable or malformed files are fatal here, so diagnostics can still report the rest)
+    pub fn load_layers(explicit_path: Option<&str>) -> Result<(Self, Vec<String>), String> {
+        let mut config = Config::default();
+
+        let mut candidates: Vec<(String, bool)> = vec![(SYSTEM_CONFIG_PATH.to_string(), false)];
+        if let Some(user) = user_config_path() {
+            candidates.push((user, false));
+        }
+        if let Some(path) = explicit_path {
+            candidates.push((path.to_string(), true));
+        }
+
+        for (path, required) in candidates {
+            match std::fs::read_to_string(&path) {
+                Ok(content) => {
+                    let file = FileConfig::parse(&content)
+                        .map_err(|e| format!("Invalid config file {}: {}", path, e))?;
+                    config.apply_file(file, &path);
+                    config.config_files.push(path);
+                }
+                Err(e) if required => return Err(format!("Cannot read config file {}: {}", path, e)),
+                Err(_) => {}
+            }
+        }
+
+        let env_errors = config.apply_env();
+        Ok((config, env_errors))
+    }
+
+    /// Layer a parsed config file over the current values
+    pub fn apply_file(&mut self, file: FileConfig, path: &str) {
+        let source = format!("file:{}", path);
+        macro_rules! layer {
+            ($($field:ident).+, $value:expr, $key:literal) => {
+                if let Some(value) = $value {
+                    self.$($field).+ = value;
+                    self.sources.insert($key, source.clone());
+                }
+            };
+        }
+
+        layer!(socket_path, file.server.socket_path, "socket_path");
+        layer!(max_buffer_size, file.server.max_buffer_size, "max_buffer_size");
+        layer!(max_request_bytes, file.server.max_request_bytes, "max_request_bytes");
+        layer!(unix_max_request_bytes, file.server.unix.max_request_bytes.map(Some), "unix_max_request_bytes");
+        layer!(http_enabled, file.server.http.enabled, "http_enabled");
+        layer!(http_listen, file.server.http.listen, "http_listen");
+        layer!(http_token_file, file.server.http.token_file.map(Some), "http_token_file");
+        layer!(http_access, file.server.http.access, "http_access");
+        layer!(http_max_request_bytes, file.server.http.max_request_bytes.map(Some), "http_max_request_bytes");
+        layer!(default_session, file.session.default, "default_session");
+        layer!(default_capture_lines, file.session.capture_lines, "default_capture_lines");
+        layer!(terminal_emulator, file.session.terminal.map(Some), "terminal_emulator");
+        layer!(terminal_preference, file.terminals.preference, "terminal_preference");
+        if let Some(custom) = file.terminals.custom {
+            // Templates accumulate across files; a later file redefines a name rather than dropping others
+            self.terminal_templates.extend(custom);
+            self.sources.insert("terminal_templates", source.clone());
+        }
+        layer!(max_wait_seconds, file.timing.max_wait_seconds, "max_wait_seconds");
+        layer!(poll_interval_ms, file.timing.poll_interval_ms, "poll_interval_ms");
+        layer!(max_raw_output_bytes, file.output.max_raw_output_bytes, "max_raw_output_bytes");
+        layer!(max_display_bytes, file.output.max_display_bytes, "max_display_bytes");
+        layer!(max_structured_bytes, file.output.max_structured_bytes, "max_structured_bytes");
+        layer!(artifact_dir, file.output.artifact_dir, "artifact_dir");
+        layer!(batch_state_dir, file.paths.batch_state_dir, "batch_state_dir");
+        layer!(bundle_dir, file.paths.bundle_dir, "bundle_dir");
+        layer!(workflow_dir, file.paths.workflow_dir, "workflow_dir");
+        layer!(secret_dir, file.paths.secret_dir, "secret_dir");
+        layer!(leak_scan, file.leak_scan.action, "leak_scan");
+        if let Some(patterns) = file.leak_scan.patterns {
+            self.leak_patterns.extend(patterns);
+            self.sources.insert("leak_patterns", source.clone());
+        }
+        layer!(fs_allowed_roots, file.filesystem.allowed_roots, "fs_allowed_roots");
+        layer!(fs_denied_paths, file.filesystem.denied, "fs_denied_paths");
+        layer!(fs_max_depth, file.filesystem.max_depth, "fs_max_depth");
+        layer!(fs_exec_dirs, file.filesystem.exec_dirs, "fs_exec_dirs");
+        layer!(desktop_trust_seconds, file.filesystem.desktop_trust_seconds, "desktop_trust_seconds");
+        layer!(sandbox_backend, file.sandbox.backend, "sandbox_backend");
+        if let Some(profiles) = file.sandbox.profiles {
+            self.sandbox_profiles.extend(profiles);
+            self.sources.insert("sandbox_profiles", source.clone());
+        }
+        layer!(rate_session_per_minute, file.rate_limit.session_per_minute, "rate_session_per_minute");
+        layer!(rate_global_per_minute, file.rate_limit.global_per_minute, "rate_global_per_minute");
+        layer!(rate_privileged_burst, file.rate_limit.privileged_burst, "rate_privileged_burst");
+        layer!(rate_failed_validation_burst, file.rate_limit.failed_validation_burst, "rate_failed_validation_burst");
+        layer!(rate_lockout_seconds, file.rate_limit.lockout_seconds, "rate_lockout_seconds");
+        layer!(acl_default, file.acl.default, "acl_default");
+        if let Some(users) = file.acl.users {
+            // Like secrets: entries accumulate across files, a later file overrides a user it names again
+            self.acl_users.extend(users);
+            self.sources.insert("acl_users", source.clone());
+        }
+        layer!(audit_enabled, file.audit.enabled, "audit_enabled");
+        layer!(audit_log, file.audit.path, "audit_log");
+        layer!(audit_hash_chain, file.audit.hash_chain, "audit_hash_chain");
+        layer!(log_level, file.log.level, "log_level");
+        layer!(log_file, file.log.file.map(Some), "log_file");
+        layer!(log_format, file.log.format, "log_format");
+        layer!(slow_request_ms, file.log.slow_request_ms, "slow_request_ms");
+        layer!(recent_requests, file.log.recent_requests, "recent_requests");
+        layer!(stats_enabled, file.stats.enabled, "stats_enabled");
+        layer!(stats_path, file.stats.path, "stats_path");
+        layer!(stats_goal_ms, file.stats.goal_ms, "stats_goal_ms");
+        layer!(features.gui, file.features.gui, "features");
+        layer!(features.fallback_terminal, file.features.fallback_terminal, "features");
+        layer!(features.file_write, file.features.file_write, "features");
+        layer!(features.direct_executor, file.features.direct_executor, "features");
+        layer!(features.tcp_listener, file.features.tcp_listener, "features");
+        layer!(thermal.cpu.warning, file.thermal.cpu_warning, "thermal");
+        layer!(thermal.cpu.critical, file.thermal.cpu_critical, "thermal");
+        layer!(thermal.gpu.warning, file.thermal.gpu_warning, "thermal");
+        layer!(thermal.gpu.critical, file.thermal.gpu_critical, "thermal");
+        layer!(thermal.nvme.warning, file.thermal.nvme_warning, "thermal");
+        layer!(thermal.nvme.critical, file.thermal.nvme_critical, "thermal");
+        layer!(thermal.other.warning, file.thermal.other_warning, "thermal");
+        layer!(thermal.other.critical, file.thermal.other_critical, "thermal");
+        layer!(watchdog_enabled, file.watchdog.enabled, "watchdog_enabled");
+        layer!(watchdog_interval_seconds, file.watchdog.interval_seconds, "watchdog_interval_seconds");
+        layer!(watchdog_stall_seconds, file.watchdog.stall_seconds, "watchdog_stall_seconds");
+        layer!(brain_memory, file.brain.memory, "brain_memory");
+        layer!(brain_socket, file.brain.socket, "brain_socket");
+        layer!(brain_collection, file.brain.collection, "brain_collection");
+        if let Some(secrets) = file.secrets {
+            self.secrets.extend(secrets);
+            self.sources.insert("secrets", source.clone());
+        }
+        layer!(colors, file.theme.colors, "colors");
+        layer!(blocked_patterns, file.security.blocked_patterns, "blocked_patterns");
+        layer!(confirm_destructive, file.security.confirm_destructive, "confirm_destructive");
+        layer!(confirmation_ttl_seconds, file.security.confirmation_ttl_seconds, "confirmation_ttl_seconds");
+        layer!(allow_unmanaged_sessions, file.security.allow_unmanaged_sessions, "allow_unmanaged_sessions");
+    }
 
-            default_session: env::var("ARCHY_TMUX_SESSION")
-                .unwrap_or_else(|_| "archy_session".to_string()),
+    /// Environment variables override everything else - returns one error per unparsable variable
+    pub fn apply_env(&mut self) -> Vec<String> {
+        let mut errors = Vec::new();
+        macro_rules! env_layer {
+            ($field:ident, $var:literal, $key:literal) => {
+                match env_parse($var) {
+                    Ok(Some(value)) => {
+                        self.$field = value;
+                        self.sources.insert($key, format!("env:{}", $var));
+                    }
+                    Ok(None) => {}
+                    Err(e) => errors.push(e),
+                }
+            };
+        }
+
+        env_layer!(socket_path, "ARCHY_SOCKET", "socket_path");
+        env_layer!(default_session, "ARCHY_TMUX_SESSION", "default_session");
+        env_layer!(max_buffer_size, "ARCHY_BUFFER_SIZE", "max_buffer_size");
+        env_layer!(max_request_bytes, "ARCHY_MAX_REQUEST_BYTES", "max_request_bytes");
+        env_layer!(default_capture_lines, "ARCHY_CAPTURE_LINES", "default_capture_lines");
+        env_layer!(max_wait_seconds, "ARCHY_MAX_WAIT", "max_wait_seconds");
+        env_layer!(confirm_destructive, "ARCHY_CONFIRM_DESTRUCTIVE", "confirm_destructive");
+        env_layer!(confirmation_ttl_seconds, "ARCHY_CONFIRMATION_TTL", "confirmation_ttl_seconds");
+        env_layer!(allow_unmanaged_sessions, "ARCHY_ALLOW_UNMANAGED_SESSIONS", "allow_unmanaged_sessions");
+        env_layer!(poll_interval_ms, "ARCHY_POLL_INTERVAL", "poll_interval_ms");
+        env_layer!(max_raw_output_bytes, "ARCHY_MAX_RAW_OUTPUT", "max_raw_output_bytes");
+        env_layer!(max_display_bytes, "ARCHY_MAX_DISPLAY", "max_display_bytes");
+        env_layer!(max_structured_bytes, "ARCHY_MAX_STRUCTURED", "max_structured_bytes");
+        env_layer!(artifact_dir, "ARCHY_ARTIFACT_DIR", "artifact_dir");
+        env_layer!(batch_state_dir, "ARCHY_BATCH_STATE_DIR", "batch_state_dir");
+        env_layer!(workflow_dir, "ARCHY_WORKFLOW_DIR", "workflow_dir");
+        env_layer!(secret_dir, "ARCHY_SECRET_DIR", "secret_dir");
+        env_layer!(colors, "ARCHY_COLORS", "colors");
+        env_layer!(sandbox_backend, "ARCHY_SANDBOX_BACKEND", "sandbox_backend");
+        env_layer!(brain_memory, "ARCHY_BRAIN_MEMORY", "brain_memory");
+        env_layer!(brain_socket, "ARCHY_BRAIN_SOCKET", "brain_socket");
+        env_layer!(brain_collection, "ARCHY_BRAIN_COLLECTION", "brain_collection");
+        env_layer!(leak_scan, "ARCHY_LEAK_SCAN", "leak_scan");
+        env_layer!(rate_session_per_minute, "ARCHY_RATE_SESSION", "rate_session_per_minute");
+        env_layer!(rate_global_per_minute, "ARCHY_RATE_GLOBAL", "rate_global_per_minute");
+        env_layer!(audit_enabled, "ARCHY_AUDIT", "audit_enabled");
+        env_layer!(audit_log, "ARCHY_AUDIT_LOG", "audit_log");
+        env_layer!(audit_hash_chain, "ARCHY_AUDIT_HASH_CHAIN", "audit_hash_chain");
+        env_layer!(log_level, "ARCHY_LOG_LEVEL", "log_level");
+        env_layer!(log_format, "ARCHY_LOG_FORMAT", "log_format");
+        env_layer!(slow_request_ms, "ARCHY_SLOW_REQUEST_MS", "slow_request_ms");
 
-            max_buffer_size: env::var("ARCHY_BUFFER_SIZE")
-                .ok()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(8192),
+        match env_parse("ARCHY_UNIX_MAX_REQUEST_BYTES") {
+            Ok(Some(limit)) => {
+                self.unix_max_request_bytes = Some(limit);
+                self.sources.insert("unix_max_request_bytes", "env:ARCHY_UNIX_MAX_REQUEST_BYTES".to_string());
+            }
+            Ok(None) => {}
+            Err(e) => errors.push(e),
+        }
 
-            default_capture_lines: env::var("ARCHY_CAPTURE_LINES")
-                .ok()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(100),
+        // e.g. ARCHY_DISABLE_FEATURES=gui,file_write
+        if let Ok(list) = env::var("ARCHY_DISABLE_FEATURES") {
+            for name in list.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+                match Feature::from_name(name) {
+                    Some(feature) => self.features.set(feature, false),
+                    None => errors.push(format!("ARCHY_DISABLE_FEATURES: unknown feature {:?}", name)),
+                }
+            }
+            self.sources.insert("features", "env:ARCHY_DISABLE_FEATURES".to_string());
+        }
 
-            terminal_emulator: env::var("ARCHY_TERMINAL").ok(),
+        if let Ok(list) = env::var("ARCHY_TERMINAL_PREFERENCE") {
+            self.terminal_preference = list.split(',')
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect();
+            self.sources.insert("terminal_preference", "env:ARCHY_TERMINAL_PREFERENCE".to_string());
+        }
 
-            max_wait_seconds: env::var("ARCHY_MAX_WAIT")
-                .ok()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(600),
+        if let Ok(path) = env::var("ARCHY_LOG_FILE") {
+            self.log_file = Some(path).filter(|p| !p.is_empty());
+            self.sources.insert("log_file", "env:ARCHY_LOG_FILE".to_string());
+        }
 
-            poll_interval_ms: env::var("ARCHY_POLL_INTERVAL")
-                .ok()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(500),
+        if let Ok(terminal) = env::var("ARCHY_TERMINAL") {
+            self.terminal_emulator = Some(terminal);
+            self.sources.insert("terminal_emulator", "env:ARCHY_TERMINAL".to_string());
         }
+
+        errors
+    }
+
+    /// Check the effective values - returns (errors, warnings)
+    pub fn validate(&self) -> (Vec<String>, Vec<String>) {
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+
+        if self.socket_path.trim().is_empty() {
+            errors.push("socket_path must not be empty".to_string());
+        }
+        if self.default_session.is_empty()
+            || self.default_session.contains(|c: char| c == ':' || c == '.' || c.is_whitespace())
+        {
+            errors.push(format!(
+                "default_session {:?} is not a valid tmux session name (no ':', '.' or spaces)",
+                self.default_session
+            ));
+        }
+        if self.max_buffer_size < 1024 {
+            errors.push(format!("max_buffer_size must be at least 1024 bytes (got {})", self.max_buffer_size));
+        }
+        if self.unix_request_limit() < 1024 {
+            errors.push(format!("max_request_bytes must be at least 1024 bytes (got {})", self.unix_request_limit()));
+        }
+        if self.http_enabled {
+            if !cfg!(feature = "http") {
+                errors.push("server.http.enabled needs a build with `--features http`".to_string());
+            }
+            if self.http_token_file.is_none() {
+                errors.push("server.http.token_file is required while the HTTP gateway is enabled".to_string());
+            }
+            match self.http_listen.parse::<std::net::SocketAddr>() {
+                Ok(addr) if !addr.ip().is_loopback() => warnings.push(format!(
+                    "server.http.listen {} is reachable from other machines - anyone with the token gets {} access",
+                    addr, self.http_access.as_str()
+                )),
+                Ok(_) => {}
+                Err(e) => errors.push(format!("server.http.listen {:?} is not an address:port ({})", self.http_listen, e)),
+            }
+            if self.http_request_limit() < 1024 {
+                errors.push(format!("server.http.max_request_bytes must be at least 1024 bytes (got {})", self.http_request_limit()));
+            }
+        }
+        if self.default_capture_lines <= 0 {
+            errors.push(format!("default_capture_lines must be positive (got {})", self.default_capture_lines));
+        }
+        if self.max_wait_seconds == 0 {
+            errors.push("max_wait_seconds must be greater than 0".to_string());
+        }
+        if self.poll_interval_ms == 0 {
+            errors.push("poll_interval_ms must be greater than 0".to_string());
+        }
+        for (key, value) in [
+            ("max_raw_output_bytes", self.max_raw_output_bytes),
+            ("max_display_bytes", self.max_display_bytes),
+            ("max_structured_bytes", self.max_structured_bytes),
+        ] {
+            if value == 0 {
+                errors.push(format!("{} must be greater than 0", key));
+            }
+        }
+        errors.extend(terminals::validate(self));
+        errors.extend(secrets::validate(self));
+        errors.extend(acl::validate(self));
+        errors.extend(sandbox::validate(self));
+        errors.extend(leaks::validate(self));
+        errors.extend(thermal::validate(self));
+        if let Err(e) = self.path_policy() {
+            errors.push(format!("filesystem: {}", e));
+        }
+        if self.fs_max_depth == 0 {
+            errors.push("filesystem.max_depth must be greater than 0".to_string());
+        }
+        if self.blocked_patterns.iter().any(|p| p.trim().is_empty()) {
+            errors.push("blocked_patterns must not contain empty patterns".to_string());
+        }
+        if self.audit_enabled && self.audit_log.trim().is_empty() {
+            errors.push("audit path must not be empty while the audit log is enabled".to_string());
+        }
+        if self.recent_requests == 0 || self.recent_requests > timings::MAX_RECENT {
+            errors.push(format!("log.recent_requests must be between 1 and {}", timings::MAX_RECENT));
+        }
+        if self.stats_enabled && self.stats_path.trim().is_empty() {
+            errors.push("stats.path must not be empty while stats are enabled".to_string());
+        }
+        if self.rate_lockout_seconds == 0 && (self.rate_privileged_burst > 0 || self.rate_failed_validation_burst > 0) {
+            errors.push("rate_limit.lockout_seconds must be greater than 0 while anomaly rules are enabled".to_string());
+        }
+        if self.watchdog_enabled && (self.watchdog_interval_seconds == 0 || self.watchdog_stall_seconds == 0) {
+            errors.push("watchdog.interval_seconds and watchdog.stall_seconds must be greater than 0".to_string());
+        }
+        if self.confirmation_ttl_seconds == 0 {
+            errors.push("confirmation_ttl_seconds must be greater than 0".to_string());
+        }
+        if self.brain_socket.trim().is_empty() {
+            errors.push("brain.socket must not be empty".to_string());
+        }
+        if self.brain_collection.is_empty()
+            || !self.brain_collection.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            errors.push(format!(
+                "brain.collection {:?} is not a valid collection name (letters, digits, '_' and '-')",
+                self.brain_collection
+            ));
+        }
+
+        // Suspicious but workable combinations
+        for feature in Feature::ALL {
+            if feature.is_reserved() && self.features.enabled(feature) {
+                warnings.push(format!("features.{} is not available in this build and has no effect", feature.name()));
+            }
+        }
+        if self.poll_interval_ms > self.max_wait_seconds.saturating_mul(1000) {
+            warnings.push(format!(
+                "poll_interval_ms ({}) is longer than max_wait_seconds ({}s) - commands will time out before the first poll",
+                self.poll_interval_ms, self.max_wait_seconds
+            ));
+        }
+        if self.max_display_bytes > self.max_raw_output_bytes {
+            warnings.push(format!(
+                "max_display_bytes ({}) exceeds max_raw_output_bytes ({}) - display is derived from raw output",
+                self.max_display_bytes, self.max_raw_output_bytes
+            ));
+        }
+        if !self.socket_path.starts_with('/') {
+            warnings.push(format!("socket_path {:?} is relative to the daemon's working directory", self.socket_path));
+        }
+
+        (errors, warnings)
+    }
+
+    /// Every effective setting with its source
+    pub fn effective_values(&self) -> Vec<ConfigValue> {
+        let value = |key: &'static str, value: serde_json::Value| ConfigValue {
+            key,
+            value,
+            source: self.sources.get(key).cloned().unwrap_or_else(|| "default".to_string()),
+        };
+
+        vec![
+            value("socket_path", self.socket_path.clone().into()),
+            value("default_session", self.default_session.clone().into()),
+            value("max_buffer_size", self.max_buffer_size.into()),
+            value("max_request_bytes", self.max_request_bytes.into()),
+            value("unix_max_request_bytes", self.unix_max_request_bytes.into()),
+            value("http_enabled", self.http_enabled.into()),
+            value("http_listen", self.http_listen.clone().into()),
+            value("http_token_file", self.http_token_file.clone().into()),
+            value("http_access", self.http_access.as_str().into()),
+            value("http_max_request_bytes", self.http_max_request_bytes.into()),
+            value("default_capture_lines", self.default_capture_lines.into()),
+            value("terminal_emulator", self.terminal_emulator.clone().into()),
+            value("terminal_preference", self.terminal_preference.clone().into()),
+            value("terminal_templates", serde_json::to_value(&self.terminal_templates).unwrap_or_default()),
+            value("max_wait_seconds", self.max_wait_seconds.into()),
+            value("poll_interval_ms", self.poll_interval_ms.into()),
+            value("max_raw_output_bytes", self.max_raw_output_bytes.into()),
+            value("max_display_bytes", self.max_display_bytes.into()),
+            value("max_structured_bytes", self.max_structured_bytes.into()),
+            value("artifact_dir", self.artifact_dir.clone().into()),
+            value("batch_state_dir", self.batch_state_dir.clone().into()),
+            value("bundle_dir", self.bundle_dir.clone().into()),
+            value("workflow_dir", self.workflow_dir.clone().into()),
+            value("colors", self.colors.into()),
+            value("blocked_patterns", self.blocked_patterns.clone().into()),
+            value("confirm_destructive", self.confirm_destructive.into()),
+            value("confirmation_ttl_seconds", self.confirmation_ttl_seconds.into()),
+            value("allow_unmanaged_sessions", self.allow_unmanaged_sessions.into()),
+            value("secrets", self.secrets.iter()
+                .map(|(name, source)| (name.clone(), serde_json::Value::from(source.kind())))
+                .collect::<serde_json::Map<_, _>>()
+                .into()),
+            value("secret_dir", self.secret_dir.clone().into()),
+            value("features", serde_json::to_value(&self.features).unwrap_or_default()),
+            value("thermal", serde_json::to_value(self.thermal).unwrap_or_default()),
+            value("watchdog_enabled", self.watchdog_enabled.into()),
+            value("watchdog_interval_seconds", self.watchdog_interval_seconds.into()),
+            value("watchdog_stall_seconds", self.watchdog_stall_seconds.into()),
+            value("leak_scan", serde_json::to_value(self.leak_scan).unwrap_or_default()),
+            value("leak_patterns", serde_json::to_value(&self.leak_patterns).unwrap_or_default()),
+            value("fs_allowed_roots", self.fs_allowed_roots.clone().into()),
+            value("fs_denied_paths", self.fs_denied_paths.clone().into()),
+            value("fs_max_depth", self.fs_max_depth.into()),
+            value("fs_exec_dirs", self.fs_exec_dirs.clone().into()),
+            value("desktop_trust_seconds", self.desktop_trust_seconds.into()),
+            value("sandbox_backend", self.sandbox_backend.clone().into()),
+            value("brain_memory", self.brain_memory.into()),
+            value("brain_socket", self.brain_socket.clone().into()),
+            value("brain_collection", self.brain_collection.clone().into()),
+            value("sandbox_profiles", serde_json::to_value(&self.sandbox_profiles).unwrap_or_default()),
+            value("rate_session_per_minute", self.rate_session_per_minute.into()),
+            value("rate_global_per_minute", self.rate_global_per_minute.into()),
+            value("rate_privileged_burst", self.rate_privileged_burst.into()),
+            value("rate_failed_validation_burst", self.rate_failed_validation_burst.into()),
+            value("rate_lockout_seconds", self.rate_lockout_seconds.into()),
+            value("acl_default", self.acl_default.as_str().into()),
+            value("acl_users", self.acl_users.iter()
+                .map(|(user, access)| (user.clone(), serde_json::Value::from(access.as_str())))
+                .collect::<serde_json::Map<_, _>>()
+                .into()),
+            value("audit_enabled", self.audit_enabled.into()),
+            value("audit_log", self.audit_log.clone().into()),
+            value("audit_hash_chain", self.audit_hash_chain.into()),
+            value("log_level", self.log_level.as_str().to_lowercase().into()),
+            value("log_file", self.log_file.clone().into()),
+            value("log_format", serde_json::to_value(self.log_format).unwrap_or_default()),
+            value("slow_request_ms", self.slow_request_ms.into()),
+            value("recent_requests", self.recent_requests.into()),
+            value("stats_enabled", self.stats_enabled.into()),
+            value("stats_path", self.stats_path.clone().into()),
+            value("stats_goal_ms", self.stats_goal_ms.into()),
+        ]
+    }
+
+    /// Full validation report for `validate_config` / `archy config check`
+    pub fn diagnostics(&self) -> ConfigDiagnostics {
+        let (errors, warnings) = self.validate();
+        ConfigDiagnostics {
+            valid: errors.is_empty(),
+            config_files: self.config_files.clone(),
+            errors,
+            warnings,
+            values: self.effective_values(),
+        }
+    }
+
+    /// Largest request accepted over the Unix socket
+    pub fn unix_request_limit(&self) -> usize {
+        self.unix_max_request_bytes.unwrap_or(self.max_request_bytes)
+    }
+
+    /// Largest request body accepted by the HTTP gateway
+    pub fn http_request_limit(&self) -> usize {
+        self.http_max_request_bytes.unwrap_or(self.max_request_bytes)
+    }
+
+    /// Path policy for file-touching actions (`[filesystem]`)
+    pub fn path_policy(&self) -> Result<PathPolicy, String> {
+        PathPolicy::new(&self.fs_allowed_roots, &self.fs_denied_paths, self.fs_max_depth)
     }
 
     /// Get session name from data or use default
@@ -63,16 +916,138 @@ impl Config {
     }
 }
 
+/// ~/.config/archy/config.toml (honors XDG_CONFIG_HOME)
+pub fn user_config_path() -> Option<String> {
+    match env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => Some(format!("{}/archy/config.toml", dir)),
+        _ => env::var("HOME")
+            .ok()
+            .filter(|home| !home.is_empty())
+            .map(|home| format!("{}/.config/archy/config.toml", home)),
+    }
+}
+
+/// ~/.local/state/archy/stats.json (or /tmp when HOME is unset)
+fn default_stats_path() -> String {
+    match env::var("HOME") {
+        Ok(home) if !home.is_empty() => format!("{}/.local/state/archy/stats.json", home),
+        _ => "/tmp/archy-stats.json".to_string(),
+    }
+}
+
+/// $XDG_RUNTIME_DIR/archy-secrets (tmpfs, per-user) or /tmp when unset
+fn default_secret_dir() -> String {
+    match env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) if !dir.is_empty() => format!("{}/archy-secrets", dir),
+        _ => "/tmp/archy-secrets".to_string(),
+    }
+}
+
+/// ~/.local/share/archy/workflows (or /tmp when HOME is unset)
+fn default_workflow_dir() -> String {
+    match env::var("HOME") {
+        Ok(home) if !home.is_empty() => format!("{}/.local/share/archy/workflows", home),
+        _ => "/tmp/archy-workflows".to_string(),
+    }
+}
+
+fn default_audit_log() -> String {
+    match env::var("HOME") {
+        Ok(home) if !home.is_empty() => format!("{}/.local/state/archy/audit.jsonl", home),
+        _ => "/tmp/archy-audit.jsonl".to_string(),
+    }
+}
+
+/// $XDG_STATE_HOME/archy/batches (~/.local/state by default) - checkpoints are replayed as commands,
+/// so the fallback without HOME is per-user too
+fn default_batch_state_dir() -> String {
+    match (env::var("XDG_STATE_HOME"), env::var("HOME")) {
+        (Ok(state), _) if !state.is_empty() => format!("{}/archy/batches", state),
+        (_, Ok(home)) if !home.is_empty() => format!("{}/.local/state/archy/batches", home),
+        _ => format!("/tmp/archy-batches-{}", crate::peer::own_uid()),
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Config {
             socket_path: "/tmp/archy.sock".to_string(),
             default_session: "archy_session".to_string(),
             max_buffer_size: 8192,
+            max_request_bytes: 1024 * 1024,
+            unix_max_request_bytes: None,
+            http_enabled: false,
+            http_listen: "127.0.0.1:8731".to_string(),
+            http_token_file: None,
+            http_access: Access::Read,
+            http_max_request_bytes: None,
             default_capture_lines: 100,
             terminal_emulator: None,
+            terminal_preference: Vec::new(),
+            terminal_templates: BTreeMap::new(),
             max_wait_seconds: 600,
             poll_interval_ms: 500,
+            max_raw_output_bytes: 256 * 1024,
+            max_display_bytes: 64 * 1024,
+            max_structured_bytes: 256 * 1024,
+            artifact_dir: "/tmp/archy-artifacts".to_string(),
+            batch_state_dir: default_batch_state_dir(),
+            bundle_dir: "/tmp/archy-bundles".to_string(),
+            workflow_dir: default_workflow_dir(),
+            colors: true,
+            blocked_patterns: Vec::new(),
+            confirm_destructive: true,
+            confirmation_ttl_seconds: 120,
+            allow_unmanaged_sessions: false,
+            leak_scan: LeakAction::Redact,
+            leak_patterns: BTreeMap::new(),
+            fs_allowed_roots: [
+                "~", "/tmp", "/usr/share", "/usr/local/share", "/opt",
+                // Exported flatpak desktop files are symlinks into /var/lib/flatpak/app
+                "/var/lib/flatpak/exports/share", "/var/lib/flatpak/app", "/var/lib/snapd/desktop",
+            ].map(String::from).to_vec(),
+            fs_denied_paths: [
+                "/etc/shadow", "/etc/gshadow", "/etc/sudoers", "/etc/sudoers.d/*",
+                "~/.ssh/**", "~/.gnupg/**", "~/.aws/**", "~/.kube/config",
+            ].map(String::from).to_vec(),
+            fs_max_depth: 32,
+            fs_exec_dirs: [
+                "/usr/bin", "/bin", "/usr/sbin", "/usr/local/bin", "/usr/lib", "/usr/libexec", "/opt",
+                "/snap/bin", "/var/lib/flatpak/exports/bin", "~/.local/bin",
+            ].map(String::from).to_vec(),
+            desktop_trust_seconds: 600,
+            sandbox_backend: "auto".to_string(),
+            sandbox_profiles: BTreeMap::new(),
+            brain_memory: false,
+            brain_socket: "/tmp/archy-brain.sock".to_string(),
+            brain_collection: "session_outputs".to_string(),
+            rate_session_per_minute: 120,
+            rate_global_per_minute: 300,
+            rate_privileged_burst: 10,
+            rate_failed_validation_burst: 5,
+            rate_lockout_seconds: 120,
+            acl_default: Access::None,
+            acl_users: BTreeMap::new(),
+            audit_enabled: true,
+            audit_log: default_audit_log(),
+            audit_hash_chain: false,
+            log_level: LevelFilter::Info,
+            log_file: None,
+            log_format: LogFormat::Pretty,
+            slow_request_ms: 2000,
+            recent_requests: 100,
+            stats_enabled: true,
+            stats_path: default_stats_path(),
+            stats_goal_ms: 100,
+            features: Features::default(),
+            thermal: Thresholds::default(),
+            watchdog_enabled: true,
+            watchdog_interval_seconds: 15,
+            watchdog_stall_seconds: 30,
+            secrets: BTreeMap::new(),
+            secret_dir: default_secret_dir(),
+            config_files: Vec::new(),
+            sources: HashMap::new(),
         }
     }
 }
@@ -87,6 +1062,11 @@ mod tests {
         assert_eq!(config.socket_path, "/tmp/archy.sock");
         assert_eq!(config.default_session, "archy_session");
         assert_eq!(config.max_buffer_size, 8192);
+        assert_eq!(config.max_display_bytes, 64 * 1024);
+        assert_eq!(config.artifact_dir, "/tmp/archy-artifacts");
+        assert!(config.batch_state_dir.ends_with("archy/batches") || config.batch_state_dir.starts_with("/tmp/archy-batches-"));
+        assert_eq!(config.bundle_dir, "/tmp/archy-bundles");
+        assert!(config.colors);
     }
 
     #[test]
@@ -104,5 +1084,180 @@ mod tests {
         let data = serde_json::json!({});
         assert_eq!(config.get_session(&data), "archy_session");
     }
-}
 
+    #[test]
+    fn test_file_layers_over_defaults() {
+        let file = FileConfig::parse(r#"
+            version = 1
+
+            [session]
+            default = "work"
+
+            [timing]
+            poll_interval_ms = 250
+
+            [theme]
+            colors = false
+
+            [security]
+            blocked_patterns = ["curl | sh"]
+        "#).unwrap();
+
+        let mut config = Config::default();
+        config.apply_file(file, "/tmp/test.toml");
+        assert_eq!(config.default_session, "work");
+        assert_eq!(config.poll_interval_ms, 250);
+        assert_eq!(config.max_wait_seconds, 600); // untouched
+        assert!(!config.colors);
+        assert_eq!(config.blocked_patterns, vec!["curl | sh".to_string()]);
+    }
+
+    #[test]
+    fn test_file_rejects_typos_and_future_versions() {
+        assert!(FileConfig::parse("[timing]\npoll_intervl_ms = 5").is_err());
+        assert!(FileConfig::parse("version = 2").is_err());
+    }
+
+    #[test]
+    fn test_explicit_config_must_exist() {
+        assert!(Config::load(Some("/nonexistent/archy.toml")).is_err());
+    }
+
+    #[test]
+    fn test_validation_errors_and_warnings() {
+        let config = Config {
+            default_session: "bad:name".to_string(),
+            poll_interval_ms: 5_000,
+            max_wait_seconds: 2,
+            ..Config::default()
+        };
+        let (errors, warnings) = config.validate();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("default_session"));
+        assert!(warnings.iter().any(|w| w.contains("poll_interval_ms")));
+        assert!(Config::default().diagnostics().valid);
+    }
+
+    #[test]
+    fn test_sources_tracked() {
+        let mut config = Config::default();
+        config.apply_file(FileConfig::parse("[timing]\nmax_wait_seconds = 30").unwrap(), "/etc/archy/config.toml");
+        let values = config.effective_values();
+        let source = |key: &str| values.iter().find(|v| v.key == key).unwrap().source.clone();
+        assert_eq!(source("max_wait_seconds"), "file:/etc/archy/config.toml");
+        assert_eq!(source("poll_interval_ms"), "default");
+    }
+
+    #[test]
+    fn test_unparsable_env_is_an_error() {
+        env::set_var("ARCHY_TEST_NOT_A_NUMBER", "8k");
+        assert!(env_parse::<usize>("ARCHY_TEST_NOT_A_NUMBER").is_err());
+        assert_eq!(env_parse::<usize>("ARCHY_TEST_UNSET_VARIABLE").unwrap(), None);
+    }
+
+    #[test]
+    fn test_request_limit_per_transport() {
+        let mut config = Config::default();
+        assert_eq!(config.unix_request_limit(), 1024 * 1024);
+
+        let file = FileConfig::parse("[server]\nmax_request_bytes = 65536\n\n[server.unix]\nmax_request_bytes = 4194304").unwrap();
+        config.apply_file(file, "/tmp/test.toml");
+        assert_eq!(config.max_request_bytes, 65536);
+        assert_eq!(config.unix_request_limit(), 4 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_features_layer_from_file() {
+        let mut config = Config::default();
+        config.apply_file(FileConfig::parse("[features]\ngui = false").unwrap(), "/tmp/test.toml");
+        assert!(!config.features.gui);
+        assert!(config.features.file_write);
+    }
+
+    #[test]
+    fn test_log_section() {
+        let mut config = Config::default();
+        config.apply_file(FileConfig::parse("[log]\nlevel = \"debug\"\nformat = \"json\"").unwrap(), "/tmp/test.toml");
+        assert_eq!(config.log_level, LevelFilter::Debug);
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert!(FileConfig::parse("[log]\nlevel = \"loud\"").is_err());
+
+        config.apply_file(FileConfig::parse("[log]\nslow_request_ms = 500\nrecent_requests = 0").unwrap(), "/tmp/test.toml");
+        assert_eq!(config.slow_request_ms, 500);
+        assert!(config.validate().0.iter().any(|e| e.contains("recent_requests")));
+    }
+
+    #[test]
+    fn test_acl_section() {
+        let mut config = Config::default();
+        let file = FileConfig::parse("[acl]\ndefault = \"read\"\n\n[acl.users]\n1234 = \"execute\"").unwrap();
+        config.apply_file(file, "/tmp/test.toml");
+        assert_eq!(config.acl_default, Access::Read);
+        assert_eq!(config.acl_users.get("1234"), Some(&Access::Execute));
+        assert!(FileConfig::parse("[acl]\ndefault = \"root\"").is_err());
+    }
+
+    #[test]
+    fn test_thermal_section() {
+        let mut config = Config::default();
+        config.apply_file(FileConfig::parse("[thermal]\ncpu_warning = 75\ncpu_critical = 90").unwrap(), "/tmp/test.toml");
+        assert_eq!((config.thermal.cpu.warning, config.thermal.cpu.critical), (75.0, 90.0));
+        assert_eq!(config.thermal.nvme, Thresholds::default().nvme);
+        config.apply_file(FileConfig::parse("[thermal]\ngpu_warning = 99").unwrap(), "/tmp/test.toml");
+        assert!(config.validate().0.iter().any(|e| e.contains("thermal.gpu")));
+    }
+
+    #[test]
+    fn test_watchdog_section() {
+        let mut config = Config::default();
+        assert!(config.watchdog_enabled);
+        config.apply_file(FileConfig::parse("[watchdog]\ninterval_seconds = 5\nstall_seconds = 0").unwrap(), "/tmp/test.toml");
+        assert_eq!(config.watchdog_interval_seconds, 5);
+        assert!(config.validate().0.iter().any(|e| e.contains("watchdog")));
+        config.apply_file(FileConfig::parse("[watchdog]\nenabled = false").unwrap(), "/tmp/test.toml");
+        assert!(config.validate().0.is_empty());
+    }
+
+    #[test]
+    fn test_http_section() {
+        let mut config = Config::default();
+        assert!(!config.http_enabled);
+        let file = FileConfig::parse("[server.http]\nenabled = true\nlisten = \"0.0.0.0:9000\"\naccess = \"execute\"\nmax_request_bytes = 4096").unwrap();
+        config.apply_file(file, "/tmp/test.toml");
+        assert_eq!((config.http_access, config.http_request_limit()), (Access::Execute, 4096));
+        let (errors, warnings) = config.validate();
+        assert!(errors.iter().any(|e| e.contains("token_file")));
+        assert!(warnings.iter().any(|w| w.contains("other machines")));
+
+        config.http_token_file = Some("/run/archy/http.token".to_string());
+        config.http_listen = "localhost".to_string();
+        assert!(config.validate().0.iter().any(|e| e.contains("server.http.listen")));
+    }
+
+    #[test]
+    fn test_stats_section() {
+        let mut config = Config::default();
+        assert!(config.stats_enabled);
+        assert_eq!(config.stats_goal_ms, 100);
+        config.apply_file(FileConfig::parse("[stats]\ngoal_ms = 50\npath = \"\"").unwrap(), "/tmp/test.toml");
+        assert_eq!(config.stats_goal_ms, 50);
+        assert!(config.validate().0.iter().any(|e| e.contains("stats.path")));
+        config.apply_file(FileConfig::parse("[stats]\nenabled = false").unwrap(), "/tmp/test.toml");
+        assert!(config.validate().0.is_empty());
+    }
+
+    #[test]
+    fn test_brain_section() {
+        let mut config = Config::default();
+        assert!(!config.brain_memory);
+        let file = FileConfig::parse("[brain]\nmemory = true\nsocket = \"/run/archy/brain.sock\"").unwrap();
+        config.apply_file(file, "/tmp/test.toml");
+        assert!(config.brain_memory);
+        assert_eq!(config.brain_socket, "/run/archy/brain.sock");
+        assert_eq!(config.brain_collection, "session_outputs");
+        assert!(config.validate().0.is_empty());
+
+        config.brain_collection = "../outputs".to_string();
+        assert!(config.validate().0.iter().any(|e| e.contains("brain.collection")));
+    }
+}
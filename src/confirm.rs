@@ -0,0 +1,191 @@
+// confirm.rs - Human confirmation for dangerous commands
+// Destructive or privileged commands aren't run straight away: the client gets a one-time token and the
+// request only executes when `confirm_execute {token}` arrives before the token expires. The stored request
+// is what runs, so a confirmation can't be replayed against different commands.
+//
+// The client that was held is usually the one driving commands, so it must not approve itself:
+// `confirm_execute` needs admin access (give automated clients `execute` in `[acl]`), and a token is
+// refused to the process it was held for - the human confirms through `archy-executor run` or another
+// client of their own.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::canonical;
+use crate::config::Config;
+use crate::peer::PeerCred;
+use crate::risk::{self, RiskAssessment};
+
+/// Requests waiting for confirmation, by token
+static PENDING: Mutex<BTreeMap<String, Pending>> = Mutex::new(BTreeMap::new());
+
+struct Pending {
+    action: String,
+    data: Value,
+    expires: Instant,
+    held_for: Option<i32>, // pid of the client that sent the request
+}
+
+/// Reply sent instead of running the request
+#[derive(Debug, Serialize)]
+pub struct ConfirmationRequired {
+    pub success: bool, // always false - nothing ran
+    pub status: &'static str,
+    pub error: String,
+    pub token: String,
+    pub action: String,
+    pub commands: Vec<String>, // the commands that need confirming
+    pub risk: RiskAssessment,
+    pub expires_in_seconds: u64,
+}
+
+/// Hold back a request whose commands need confirmation; None when it may run now
+pub fn gate(action: &str, data: &Value, commands: &[String], requester: Option<PeerCred>, config: &Config) -> Option<ConfirmationRequired> {
+    if !config.confirm_destructive {
+        return None;
+    }
+
+    let risky: Vec<(String, RiskAssessment)> = commands.iter()
+        .map(|command| (command.clone(), risk::classify(command)))
+        .filter(|(_, assessment)| assessment.requires_confirmation)
+        .collect();
+    let risk = risk::highest(risky.iter().map(|(_, assessment)| assessment))?;
+    let summary = format!("{} command ({})", risk.class.as_str(), risk.reason);
+    let commands = risky.into_iter().map(|(command, _)| command).collect();
+    Some(hold(action, data, commands, risk, &summary, requester, config))
+}
+
+/// Store a request under a fresh token and build the reply asking for confirm_execute
+pub fn hold(
+    action: &str,
+    data: &Value,
+    commands: Vec<String>,
+    risk: RiskAssessment,
+    summary: &str,
+    requester: Option<PeerCred>,
+    config: &Config,
+) -> ConfirmationRequired {
+    let token = new_token();
+    let ttl = Duration::from_secs(config.confirmation_ttl_seconds);
+    let mut pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+    pending.retain(|_, p| p.expires > Instant::now());
+    pending.insert(token.clone(), Pending {
+        action: action.to_string(),
+        data: data.clone(),
+        expires: Instant::now() + ttl,
+        held_for: requester.map(|peer| peer.pid),
+    });
+
+    tracing::warn!("Holding {} for confirmation: {}", action, summary);
+    ConfirmationRequired {
+        success: false,
+        status: "confirmation_required",
+        error: format!(
+            "Confirmation required: {} - send confirm_execute with the token within {}s",
+            summary,
+            config.confirmation_ttl_seconds
+        ),
+        token,
+        action: action.to_string(),
+        commands,
+        risk,
+        expires_in_seconds: config.confirmation_ttl_seconds,
+    }
+}
+
+/// Take the request held under `token` - each token works once, only before it expires, and never
+/// for the client it was held for (the token stays valid for someone else)
+pub fn redeem(token: &str, requester: Option<PeerCred>) -> Result<(String, Value), String> {
+    let mut pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+    let own = pending.get(token)
+        .ok_or_else(|| "Unknown or already used confirmation token".to_string())?
+        .held_for
+        .is_some_and(|pid| requester.is_some_and(|peer| peer.pid == pid));
+    if own {
+        return Err("A client cannot confirm its own request - a human has to confirm it".to_string());
+    }
+    let held = pending.remove(token)
+        .ok_or_else(|| "Unknown or already used confirmation token".to_string())?;
+    if held.expires <= Instant::now() {
+        return Err("Confirmation token expired - send the request again".to_string());
+    }
+    Ok((held.action, held.data))
+}
+
+/// Actions waiting for confirmation with the time their tokens have left
+pub fn pending() -> Vec<(String, Duration)> {
+    let now = Instant::now();
+    PENDING.lock().unwrap_or_else(|e| e.into_inner())
+        .values()
+        .filter(|held| held.expires > now)
+        .map(|held| (held.action.clone(), held.expires - now))
+        .collect()
+}
+
+/// 128 random bits as hex (hashed clock and pid if /dev/urandom is unavailable)
+fn new_token() -> String {
+    let mut bytes = [0u8; 16];
+    if File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut bytes)).is_ok() {
+        return bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    }
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    canonical::content_hash(&(nanos.to_string(), std::process::id()))[..32].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_destructive_command_needs_token() {
+        let config = Config::default();
+        let data = serde_json::json!({"command": "rm -rf build"});
+
+        let held = gate("execute", &data, &["rm -rf build".to_string()], None, &config).unwrap();
+        assert_eq!(held.status, "confirmation_required");
+        assert_eq!(held.token.len(), 32);
+
+        let (action, stored) = redeem(&held.token, None).unwrap();
+        assert_eq!(action, "execute");
+        assert_eq!(stored, data);
+        assert!(redeem(&held.token, None).unwrap_err().contains("already used"));
+    }
+
+    #[test]
+    fn test_safe_or_disabled_runs_immediately() {
+        let mut config = Config::default();
+        assert!(gate("execute", &Value::Null, &["ls -la".to_string()], None, &config).is_none());
+
+        config.confirm_destructive = false;
+        assert!(gate("execute", &Value::Null, &["rm -rf /tmp/x".to_string()], None, &config).is_none());
+    }
+
+    #[test]
+    fn test_expired_token_rejected() {
+        let config = Config { confirmation_ttl_seconds: 0, ..Config::default() };
+        let held = gate("execute", &Value::Null, &["sudo reboot".to_string()], None, &config).unwrap();
+        assert!(redeem(&held.token, None).unwrap_err().contains("expired"));
+    }
+
+    #[test]
+    fn test_client_cannot_confirm_itself() {
+        let agent = PeerCred { pid: 4242, uid: 1000, gid: 1000 };
+        let human = PeerCred { pid: 4343, uid: 1000, gid: 1000 };
+        let held = gate("execute", &Value::Null, &["rm -rf build".to_string()], Some(agent), &Config::default()).unwrap();
+
+        // An execute-level client isn't allowed confirm_execute at all
+        assert!(crate::acl::check("confirm_execute", crate::acl::Access::Execute, "agent").is_err());
+        assert!(crate::acl::check("execute", crate::acl::Access::Execute, "agent").is_ok());
+
+        // Even with admin access, the held client's own process can't redeem - and the token survives that
+        assert!(redeem(&held.token, Some(agent)).unwrap_err().contains("own request"));
+        assert!(redeem(&held.token, Some(human)).is_ok());
+    }
+}
@@ -0,0 +1,135 @@
+// criteria.rs - Declarative success criteria
+// Evaluated after parsing so batch steps and the AI can branch without re-reading raw text
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::output::DisplayOutput;
+
+/// A single declarative check, e.g. {"type": "output_matches", "regex": "active \\(running\\)"}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Criterion {
+    ExitCode { equals: i32 },
+    OutputMatches { regex: String },
+    OutputNotMatches { regex: String },
+    FindingAbsent { category: String },
+    StatusIs { status: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CriterionResult {
+    pub criterion: Criterion,
+    pub passed: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CriteriaReport {
+    pub all_passed: bool,
+    pub results: Vec<CriterionResult>,
+}
+
+/// Parse `success_criteria` from a request payload (None when absent)
+pub fn from_request(data: &Value) -> Result<Option<Vec<Criterion>>, String> {
+    match data.get("success_criteria") {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => serde_json::from_value(value.clone())
+            .map(Some)
+            .map_err(|e| format!("Invalid success_criteria: {}", e)),
+    }
+}
+
+/// Evaluate one criterion against an analyzed output
+pub fn evaluate_one(criterion: &Criterion, output: &DisplayOutput) -> CriterionResult {
+    let (passed, detail) = match criterion {
+        Criterion::ExitCode { equals } => (
+            output.exit_code == *equals,
+            format!("exit code {} (expected {})", output.exit_code, equals),
+        ),
+        Criterion::OutputMatches { regex } => match Regex::new(regex) {
+            Ok(re) => {
+                let found = re.is_match(&output.raw_output);
+                (found, if found { "pattern found".to_string() } else { "pattern not found".to_string() })
+            }
+            Err(e) => (false, format!("invalid regex: {}", e)),
+        },
+        Criterion::OutputNotMatches { regex } => match Regex::new(regex) {
+            Ok(re) => {
+                let found = re.is_match(&output.raw_output);
+                (!found, if found { "pattern found".to_string() } else { "pattern not found".to_string() })
+            }
+            Err(e) => (false, format!("invalid regex: {}", e)),
+        },
+        Criterion::FindingAbsent { category } => {
+            let needle = category.to_lowercase();
+            let matching: Vec<&str> = output.findings.iter()
+                .filter(|f| f.category.to_lowercase().contains(&needle))
+                .map(|f| f.category.as_str())
+                .collect();
+            if matching.is_empty() {
+                (true, format!("no '{}' findings", category))
+            } else {
+                (false, format!("found: {}", matching.join(", ")))
+            }
+        }
+        Criterion::StatusIs { status } => (
+            output.status == *status,
+            format!("status '{}' (expected '{}')", output.status, status),
+        ),
+    };
+
+    CriterionResult {
+        criterion: criterion.clone(),
+        passed,
+        detail,
+    }
+}
+
+/// Evaluate all criteria
+pub fn evaluate(criteria: &[Criterion], output: &DisplayOutput) -> CriteriaReport {
+    let results: Vec<CriterionResult> = criteria.iter()
+        .map(|c| evaluate_one(c, output))
+        .collect();
+
+    CriteriaReport {
+        all_passed: results.iter().all(|r| r.passed),
+        results,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_and_evaluate() {
+        let data = json!({"success_criteria": [
+            {"type": "exit_code", "equals": 0},
+            {"type": "output_matches", "regex": "active \\(running\\)"},
+            {"type": "finding_absent", "category": "error"}
+        ]});
+        let criteria = from_request(&data).unwrap().unwrap();
+        let output = DisplayOutput::from_command_output("svc", "Active: active (running)\n", 0);
+
+        let report = evaluate(&criteria, &output);
+        assert!(report.all_passed);
+        assert_eq!(report.results.len(), 3);
+    }
+
+    #[test]
+    fn test_failing_criterion() {
+        let criteria = vec![Criterion::FindingAbsent { category: "error".to_string() }];
+        let output = DisplayOutput::from_command_output("cat x", "cat: x: No such file or directory\n", 0);
+        let report = evaluate(&criteria, &output);
+        assert!(!report.all_passed);
+        assert!(report.results[0].detail.contains("no_such_file"));
+    }
+
+    #[test]
+    fn test_invalid_criteria_rejected() {
+        assert!(from_request(&json!({"success_criteria": [{"type": "bogus"}]})).is_err());
+        assert!(from_request(&json!({})).unwrap().is_none());
+    }
+}
@@ -0,0 +1,618 @@
+// desktop.rs - .desktop file parsing (Freedesktop Desktop Entry Specification 1.5)
+// Shared by the executor (the application index) and archy-launcher (which runs Exec), so both read
+// an entry the same way. Only the [Desktop Entry] group is read; actions and other groups are skipped.
+//
+//   - values are unescaped (\s \n \t \r \\), lists split on unescaped ';'
+//   - localized keys (Name[de_DE@euro]=) are matched lang_COUNTRY@MODIFIER, lang_COUNTRY, lang@MODIFIER,
+//     lang, then unlocalized - the locale comes from LC_ALL, LC_MESSAGES or LANG
+//   - Exec is split into arguments by its quoting rules (double quotes, with \" \` \$ \\ escaped inside)
+//     and its field codes are expanded: %f %u (a file), %F %U (all files), %i (--icon <Icon>),
+//     %c (the localized Name), %k (the file's path), %% ('%'); the deprecated %d %D %n %N %v %m vanish.
+//     Anything else is an error, as the spec requires
+//   - files to open are local paths or URIs: %u %U take either, %f %F local paths only (file:// URIs
+//     become paths). An entry without a code for them, or with %f %u and several, can't open them
+//   - Flatpak and Snap apps are told apart (X-Flatpak, X-SnapInstanceName or a /snap/bin Exec) - they
+//     start through `flatpak run <app-id>` / `snap run <command>`, not their Exec line
+//
+// Std only - the launcher links nothing it doesn't need.
+
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+const GROUP: &str = "[Desktop Entry]";
+
+#[derive(Debug, Default)]
+pub struct DesktopEntry {
+    values: HashMap<String, String>, // key (with any [locale]) -> raw value
+}
+
+impl DesktopEntry {
+    pub fn parse(content: &str) -> DesktopEntry {
+        let mut values = HashMap::new();
+        let mut in_entry = false;
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                in_entry = line == GROUP;
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=').filter(|_| in_entry) {
+                // A key given twice is invalid - the first one counts
+                values.entry(key.trim().to_string()).or_insert_with(|| value.trim().to_string());
+            }
+        }
+        DesktopEntry { values }
+    }
+
+    /// Unlocalized string value
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.values.get(key).map(|v| unescape(v))
+    }
+
+    /// The value for the current locale
+    pub fn localized(&self, key: &str) -> Option<String> {
+        self.localized_in(key, current_locale().as_deref())
+    }
+
+    pub fn localized_in(&self, key: &str, locale: Option<&str>) -> Option<String> {
+        self.raw_localized(key, locale).map(|v| unescape(v))
+    }
+
+    fn raw_localized(&self, key: &str, locale: Option<&str>) -> Option<&String> {
+        locale_variants(locale.unwrap_or(""))
+            .iter()
+            .find_map(|variant| self.values.get(&format!("{}[{}]", key, variant)))
+            .or_else(|| self.values.get(key))
+    }
+
+    pub fn flag(&self, key: &str) -> bool {
+        self.values.get(key).is_some_and(|v| v == "true")
+    }
+
+    /// A `;`-separated list (a trailing `;` is optional, `\;` is a literal semicolon)
+    pub fn list(&self, key: &str) -> Vec<String> {
+        self.values.get(key).map(|raw| split_list(raw)).unwrap_or_default()
+    }
+
+    /// A list for the current locale (Keywords[de]=...)
+    pub fn localized_list(&self, key: &str) -> Vec<String> {
+        self.raw_localized(key, current_locale().as_deref()).map(|raw| split_list(raw)).unwrap_or_default()
+    }
+
+    /// Hidden (deleted) or NoDisplay (not for menus)
+    pub fn is_hidden(&self) -> bool {
+        self.flag("Hidden") || self.flag("NoDisplay")
+    }
+
+    /// Runs in a terminal (Terminal=true)
+    pub fn terminal(&self) -> bool {
+        self.flag("Terminal")
+    }
+
+    pub fn packaging(&self) -> Packaging {
+        if let Some(id) = self.get("X-Flatpak").filter(|id| is_flatpak_id(id)) {
+            return Packaging::Flatpak(id);
+        }
+        // snapd writes Exec=env BAMF_DESKTOP_FILE_HINT=... /snap/bin/<command> %U
+        let snap_command = self.exec_argv(&[], None).ok().and_then(|argv| {
+            argv.iter().find_map(|arg| arg.strip_prefix("/snap/bin/").map(str::to_string))
+        });
+        match (snap_command, self.get("X-SnapInstanceName").filter(|name| !name.is_empty())) {
+            (Some(command), _) | (None, Some(command)) => Packaging::Snap(command),
+            (None, None) => Packaging::Native,
+        }
+    }
+
+    /// The program Exec starts, without its directory - past `env VAR=value`, and for sandboxed apps
+    /// the command inside the sandbox rather than the runner
+    pub fn program(&self) -> Option<String> {
+        let argv = self.exec_argv(&[], None).ok()?;
+        let base = |arg: &str| arg.rsplit('/').next().unwrap_or(arg).to_string();
+        match self.packaging() {
+            Packaging::Flatpak(_) => argv.iter().find_map(|arg| arg.strip_prefix("--command=")).map(base),
+            Packaging::Snap(command) => Some(command),
+            Packaging::Native => {
+                let mut args = argv.iter().map(String::as_str).peekable();
+                if args.peek().is_some_and(|arg| base(arg) == "env") {
+                    args.next();
+                    while args.peek().is_some_and(|arg| arg.contains('=') && !arg.starts_with('-')) {
+                        args.next();
+                    }
+                }
+                args.next().map(base)
+            }
+        }
+    }
+
+    /// Exec as an argument vector, opening `files` - `path` is the desktop file (for %k)
+    pub fn exec_argv(&self, files: &[String], path: Option<&Path>) -> Result<Vec<String>, String> {
+        let exec = self.get("Exec").filter(|e| !e.is_empty()).ok_or("no Exec line")?;
+        let context = ExecContext {
+            files,
+            icon: self.get("Icon").filter(|i| !i.is_empty()),
+            name: self.localized("Name"),
+            path: path.map(|p| p.to_string_lossy().into_owned()),
+        };
+        parse_exec(&exec, &context)
+    }
+}
+
+/// How an application is installed
+#[derive(Debug, Clone, PartialEq)]
+pub enum Packaging {
+    Native,
+    Flatpak(String), // app id, e.g. org.mozilla.firefox
+    Snap(String),    // command as in /snap/bin, e.g. firefox or code.url-handler
+}
+
+impl Packaging {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Packaging::Native => "desktop",
+            Packaging::Flatpak(_) => "flatpak",
+            Packaging::Snap(_) => "snap",
+        }
+    }
+
+    pub fn app_id(&self) -> Option<&str> {
+        match self {
+            Packaging::Native => None,
+            Packaging::Flatpak(id) | Packaging::Snap(id) => Some(id),
+        }
+    }
+
+    /// The command that starts a sandboxed app (a native one runs its Exec)
+    #[allow(dead_code)] // archy-launcher's - the executor only reports the packaging
+    pub fn runner(&self) -> Option<Vec<String>> {
+        match self {
+            Packaging::Native => None,
+            Packaging::Flatpak(id) => Some(vec!["flatpak".to_string(), "run".to_string(), id.clone()]),
+            Packaging::Snap(command) => Some(vec!["snap".to_string(), "run".to_string(), command.clone()]),
+        }
+    }
+}
+
+/// Flatpak application ids are reverse-DNS names with at least three parts
+pub fn is_flatpak_id(id: &str) -> bool {
+    let parts: Vec<&str> = id.split('.').collect();
+    parts.len() >= 3
+        && id.len() <= 255
+        && parts.iter().all(|part| {
+            part.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        })
+}
+
+/// Whether `target` is a URI (`scheme:...`) rather than a path
+pub fn is_uri(target: &str) -> bool {
+    target.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+            && scheme.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+/// The local path a file to open stands for: a path as it is, a file:// URI decoded, else None
+pub fn local_path(target: &str) -> Option<String> {
+    if !is_uri(target) {
+        return Some(target.to_string());
+    }
+    let rest = target.strip_prefix("file://")?;
+    // file:///path or file://localhost/path
+    let path = rest.strip_prefix("localhost").unwrap_or(rest);
+    if !path.starts_with('/') {
+        return None;
+    }
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// A file:// URI for a local path, or a URI as it is
+pub fn file_uri(target: &str) -> String {
+    if is_uri(target) {
+        return target.to_string();
+    }
+    let mut uri = String::from("file://");
+    for byte in target.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+/// Path of an executable, given a path or a name looked up in PATH
+pub fn resolve_program(program: &str) -> Option<PathBuf> {
+    let candidate = if program.contains('/') {
+        PathBuf::from(program)
+    } else {
+        std::env::var("PATH").ok()?
+            .split(':')
+            .map(|dir| Path::new(dir).join(program))
+            .find(|path| is_executable(path))?
+    };
+    is_executable(&candidate).then_some(candidate)
+}
+
+/// Whether `program` lives under one of `exec_dirs` (canonical). Judged by the canonical path, so a
+/// symlink can't smuggle in a program from elsewhere
+pub fn in_exec_dirs(program: &Path, exec_dirs: &[String]) -> bool {
+    fs::canonicalize(program).is_ok_and(|program| exec_dirs.iter().any(|dir| program.starts_with(dir)))
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+/// What Exec field codes expand to
+#[derive(Debug, Default)]
+pub struct ExecContext<'a> {
+    pub files: &'a [String], // local paths or URIs
+    pub icon: Option<String>,
+    pub name: Option<String>,
+    pub path: Option<String>,
+}
+
+/// Split a raw list value on unescaped `;`, unescaping each item
+fn split_list(raw: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(';') => current.push(';'),
+                Some(next) => {
+                    current.push('\\');
+                    current.push(next);
+                }
+                None => current.push('\\'),
+            },
+            ';' => items.push(unescape(&std::mem::take(&mut current))),
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        items.push(unescape(&current));
+    }
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+/// The general unescaping every string value gets
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => out.push(' '),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('\\') => out.push('\\'),
+            // Not an escape the spec defines - kept as written (Exec quoting has its own)
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// LC_ALL, else LC_MESSAGES, else LANG ("C" and "POSIX" mean none)
+fn current_locale() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .filter(|value| value != "C" && value != "POSIX" && !value.starts_with("C."))
+}
+
+/// Keys to try for `locale`, most specific first (the encoding is ignored)
+fn locale_variants(locale: &str) -> Vec<String> {
+    let (rest, modifier) = match locale.split_once('@') {
+        Some((rest, modifier)) => (rest, Some(modifier)),
+        None => (locale, None),
+    };
+    let rest = rest.split('.').next().unwrap_or("");
+    let (lang, country) = match rest.split_once('_') {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (rest, None),
+    };
+    if lang.is_empty() {
+        return Vec::new();
+    }
+
+    let mut variants = Vec::new();
+    if let (Some(country), Some(modifier)) = (country, modifier) {
+        variants.push(format!("{}_{}@{}", lang, country, modifier));
+    }
+    if let Some(country) = country {
+        variants.push(format!("{}_{}", lang, country));
+    }
+    if let Some(modifier) = modifier {
+        variants.push(format!("{}@{}", lang, modifier));
+    }
+    variants.push(lang.to_string());
+    variants
+}
+
+#[derive(Debug, PartialEq)]
+enum Piece {
+    Text(String),
+    Code(char),
+}
+
+/// Split an (already unescaped) Exec value into arguments and expand its field codes
+pub fn parse_exec(exec: &str, context: &ExecContext) -> Result<Vec<String>, String> {
+    let words = split_exec(exec)?;
+    let codes: Vec<char> = words.iter().flatten()
+        .filter_map(|piece| match piece {
+            Piece::Code(code @ ('f' | 'F' | 'u' | 'U')) => Some(*code),
+            _ => None,
+        })
+        .collect();
+    if !context.files.is_empty() {
+        if codes.is_empty() {
+            return Err("it doesn't open files".to_string());
+        }
+        if context.files.len() > 1 && !codes.iter().any(|code| matches!(code, 'F' | 'U')) {
+            return Err("it opens one file at a time".to_string());
+        }
+    }
+    // %f %F get paths - a URI other than file:// has none
+    let local: Vec<String> = if codes.iter().any(|code| matches!(code, 'f' | 'F')) {
+        context.files.iter()
+            .map(|file| local_path(file).ok_or_else(|| format!("it opens local files only, not {}", file)))
+            .collect::<Result<_, _>>()?
+    } else {
+        Vec::new()
+    };
+
+    let mut argv = Vec::new();
+    for word in words {
+        // Codes that stand for a list of arguments, or none, only make sense as a whole argument
+        if let [Piece::Code(code)] = word.as_slice() {
+            match code {
+                'F' => argv.extend(local.iter().cloned()),
+                'U' => argv.extend(context.files.iter().cloned()),
+                'f' => argv.extend(local.first().cloned()),
+                'u' => argv.extend(context.files.first().cloned()),
+                'i' => {
+                    if let Some(icon) = &context.icon {
+                        argv.extend(["--icon".to_string(), icon.clone()]);
+                    }
+                }
+                'k' => argv.extend(context.path.clone()),
+                'c' => argv.extend(context.name.clone()),
+                'd' | 'D' | 'n' | 'N' | 'v' | 'm' => {}
+                '%' => argv.push("%".to_string()),
+                other => return Err(format!("invalid field code %{} in Exec", other)),
+            }
+            continue;
+        }
+
+        let mut arg = String::new();
+        for piece in word {
+            match piece {
+                Piece::Text(text) => arg.push_str(&text),
+                Piece::Code('%') => arg.push('%'),
+                Piece::Code('f') => arg.push_str(local.first().map(String::as_str).unwrap_or("")),
+                Piece::Code('u') => arg.push_str(context.files.first().map(String::as_str).unwrap_or("")),
+                Piece::Code('c') => arg.push_str(context.name.as_deref().unwrap_or("")),
+                Piece::Code('k') => arg.push_str(context.path.as_deref().unwrap_or("")),
+                Piece::Code('d' | 'D' | 'n' | 'N' | 'v' | 'm') => {}
+                Piece::Code(code @ ('F' | 'U' | 'i')) => {
+                    return Err(format!("field code %{} must be an argument of its own in Exec", code))
+                }
+                Piece::Code(other) => return Err(format!("invalid field code %{} in Exec", other)),
+            }
+        }
+        argv.push(arg);
+    }
+    if argv.is_empty() {
+        return Err("Exec names no program".to_string());
+    }
+    Ok(argv)
+}
+
+/// Arguments as pieces of literal text and field codes (quoted text is always literal)
+fn split_exec(exec: &str) -> Result<Vec<Vec<Piece>>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<Vec<Piece>> = None; // Some once an argument has started, even an empty ""
+    let mut text = String::new();
+    let mut chars = exec.chars();
+
+    let flush = |word: &mut Option<Vec<Piece>>, text: &mut String| {
+        let pieces = word.get_or_insert_with(Vec::new);
+        if !text.is_empty() {
+            pieces.push(Piece::Text(std::mem::take(text)));
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' => {
+                if word.is_some() || !text.is_empty() {
+                    flush(&mut word, &mut text);
+                    words.extend(word.take());
+                }
+            }
+            '"' => {
+                word.get_or_insert_with(Vec::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped @ ('"' | '`' | '$' | '\\')) => text.push(escaped),
+                            Some(other) => {
+                                text.push('\\');
+                                text.push(other);
+                            }
+                            None => return Err("unterminated quote in Exec".to_string()),
+                        },
+                        Some(other) => text.push(other),
+                        None => return Err("unterminated quote in Exec".to_string()),
+                    }
+                }
+            }
+            '%' => {
+                let code = chars.next().ok_or("Exec ends with a lone %")?;
+                flush(&mut word, &mut text);
+                if let Some(pieces) = word.as_mut() {
+                    pieces.push(Piece::Code(code));
+                }
+            }
+            other => text.push(other),
+        }
+    }
+    if word.is_some() || !text.is_empty() {
+        flush(&mut word, &mut text);
+        words.extend(word.take());
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(exec: &str) -> Result<Vec<String>, String> {
+        parse_exec(exec, &ExecContext::default())
+    }
+
+    #[test]
+    fn test_exec_field_codes() {
+        assert_eq!(argv("/usr/bin/firefox %u").unwrap(), vec!["/usr/bin/firefox"]);
+        assert_eq!(argv("code --new-window %F").unwrap(), vec!["code", "--new-window"]);
+        assert!(argv(" %U ").is_err());
+
+        let files = vec!["/tmp/a b.txt".to_string(), "/tmp/c.txt".to_string()];
+        let context = ExecContext {
+            files: &files,
+            icon: Some("gedit".to_string()),
+            name: Some("Text Editor".to_string()),
+            path: Some("/usr/share/applications/gedit.desktop".to_string()),
+        };
+        assert_eq!(
+            parse_exec("gedit %i --title=%c %k %F", &context).unwrap(),
+            vec!["gedit", "--icon", "gedit", "--title=Text Editor", "/usr/share/applications/gedit.desktop", "/tmp/a b.txt", "/tmp/c.txt"],
+        );
+        let one = ExecContext { files: &files[..1], ..ExecContext::default() };
+        assert_eq!(parse_exec("open --file=%f 100%%", &one).unwrap(), vec!["open", "--file=/tmp/a b.txt", "100%"]);
+        // Deprecated codes vanish, unknown ones are refused
+        assert_eq!(argv("xterm %d %m").unwrap(), vec!["xterm"]);
+        assert!(argv("app %z").unwrap_err().contains("%z"));
+        assert!(argv("app --files=%F").unwrap_err().contains("own"));
+        assert!(argv("app 50%").is_err());
+    }
+
+    #[test]
+    fn test_exec_files_and_uris() {
+        let targets = vec!["file:///tmp/a%20b.pdf".to_string(), "https://example.org/?q=a b".to_string()];
+        let context = ExecContext { files: &targets, ..ExecContext::default() };
+        // %U passes URIs as given, %F wants local paths
+        assert_eq!(parse_exec("firefox %U", &context).unwrap(), vec!["firefox", "file:///tmp/a%20b.pdf", "https://example.org/?q=a b"]);
+        assert!(parse_exec("okular %F", &context).unwrap_err().contains("local files only"));
+        let local = ExecContext { files: &targets[..1], ..ExecContext::default() };
+        assert_eq!(parse_exec("okular %f", &local).unwrap(), vec!["okular", "/tmp/a b.pdf"]);
+        // Nowhere to put them, or only room for one
+        assert!(parse_exec("xterm", &local).unwrap_err().contains("doesn't open files"));
+        assert!(parse_exec("mpv %u", &context).unwrap_err().contains("one file"));
+
+        assert!(is_uri("mailto:a@example.org") && !is_uri("/tmp/x:y"));
+        assert_eq!(local_path("file://localhost/etc/hosts").as_deref(), Some("/etc/hosts"));
+        assert_eq!(local_path("https://example.org"), None);
+        assert_eq!(file_uri("/tmp/a b/ü.txt"), "file:///tmp/a%20b/%C3%BC.txt");
+        assert_eq!(local_path(&file_uri("/tmp/a b/ü.txt")).as_deref(), Some("/tmp/a b/ü.txt"));
+    }
+
+    #[test]
+    fn test_exec_quoting() {
+        assert_eq!(
+            argv(r#""/opt/My App/run" --name "a \"quoted\" \$word" "" x"#).unwrap(),
+            vec!["/opt/My App/run", "--name", "a \"quoted\" $word", "", "x"],
+        );
+        // Quoted text is literal, field codes included
+        assert_eq!(argv(r#"sh -c "echo %u""#).unwrap(), vec!["sh", "-c", "echo %u"]);
+        assert_eq!(argv(r#"--a="b c"d"#).unwrap(), vec!["--a=b cd"]);
+        assert!(argv(r#"sh -c "echo"#).unwrap_err().contains("unterminated"));
+    }
+
+    #[test]
+    fn test_entry_values() {
+        let entry = DesktopEntry::parse(
+            "# comment\n[Desktop Entry]\nName=Files\nName[de]=Dateien\nName[de_AT]=Dateien (AT)\nName[sr@latin]=Datoteke\n\
+             Comment=Line\\none\\sand\\\\two\nCategories=GNOME;GTK;Utility\\;Core;\nTerminal=true\nPath=/srv/work\n\
+             Exec=\"/opt/my app/bin\" \"--x=\\\\\\\\\" %U\n\n[Desktop Action new]\nName=New Window\nExec=evil\n",
+        );
+        assert_eq!(entry.get("Name").as_deref(), Some("Files"));
+        assert_eq!(entry.localized_in("Name", Some("de_AT.UTF-8")).as_deref(), Some("Dateien (AT)"));
+        assert_eq!(entry.localized_in("Name", Some("de_CH")).as_deref(), Some("Dateien"));
+        assert_eq!(entry.localized_in("Name", Some("sr_RS@latin")).as_deref(), Some("Datoteke"));
+        assert_eq!(entry.localized_in("Name", Some("fr_FR")).as_deref(), Some("Files"));
+        assert_eq!(entry.get("Comment").as_deref(), Some("Line\none and\\two"));
+        assert_eq!(entry.list("Categories"), vec!["GNOME", "GTK", "Utility;Core"]);
+        assert!(entry.terminal());
+        assert!(!entry.is_hidden());
+        assert_eq!(entry.get("Path").as_deref(), Some("/srv/work"));
+        // General unescaping first (\\\\ -> \\), then Exec quoting (\\ -> \)
+        assert_eq!(entry.exec_argv(&[], None).unwrap(), vec!["/opt/my app/bin", "--x=\\"]);
+        assert_eq!(entry.packaging(), Packaging::Native);
+        assert_eq!(entry.program().as_deref(), Some("bin"));
+    }
+
+    #[test]
+    fn test_packaging() {
+        let flatpak = DesktopEntry::parse(
+            "[Desktop Entry]\nName=Firefox\nExec=/usr/bin/flatpak run --branch=stable --arch=x86_64 --command=firefox \
+             --file-forwarding org.mozilla.firefox @@u %u @@\nX-Flatpak=org.mozilla.firefox\n",
+        );
+        assert_eq!(flatpak.packaging(), Packaging::Flatpak("org.mozilla.firefox".to_string()));
+        assert_eq!(flatpak.packaging().runner().unwrap(), vec!["flatpak", "run", "org.mozilla.firefox"]);
+        assert_eq!(flatpak.program().as_deref(), Some("firefox"));
+
+        let snap = DesktopEntry::parse(
+            "[Desktop Entry]\nName=Visual Studio Code - URL Handler\n\
+             Exec=env BAMF_DESKTOP_FILE_HINT=/var/lib/snapd/desktop/applications/code_code-url-handler.desktop /snap/bin/code.url-handler --open-url %U\n\
+             X-SnapInstanceName=code\n",
+        );
+        assert_eq!(snap.packaging(), Packaging::Snap("code.url-handler".to_string()));
+        assert_eq!(snap.packaging().runner().unwrap(), vec!["snap", "run", "code.url-handler"]);
+        assert_eq!(snap.program().as_deref(), Some("code.url-handler"));
+
+        let native = DesktopEntry::parse("[Desktop Entry]\nExec=env GDK_BACKEND=x11 /usr/bin/gimp %U\n");
+        assert_eq!(native.packaging(), Packaging::Native);
+        assert_eq!(native.program().as_deref(), Some("gimp"));
+
+        assert!(is_flatpak_id("org.gnome.Calculator"));
+        assert!(!is_flatpak_id("firefox"));
+        assert!(!is_flatpak_id("--command=sh.x.y"));
+    }
+}
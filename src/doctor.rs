@@ -0,0 +1,308 @@
+// doctor.rs - Checks of the environment the daemon runs in, for `doctor`
+// Most support questions end in the same handful of causes: no tmux or an ancient one, a shell whose
+// rc file can't take the integration, no terminal emulator for the session, a socket other users can
+// reach, a service that never got DISPLAY/WAYLAND_DISPLAY, a full disk under the logs. Each check
+// says pass, warn or fail, what it saw, and - unless it passed - what to do about it.
+//
+// Nothing is changed; every check only looks.
+
+use serde::Serialize;
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::acl::Access;
+use crate::config::Config;
+use crate::helpers::environment::{DisplayServer, Session};
+use crate::helpers::process;
+use crate::{peer, storage, terminals};
+
+const TMUX_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Oldest tmux archy is used with
+const MIN_TMUX: (u32, u32) = (3, 0);
+
+/// Free space under a log directory below which logging is at risk
+const WARN_FREE_BYTES: u64 = 512 * 1024 * 1024;
+const FAIL_FREE_BYTES: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remedy: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: String) -> Self {
+        Check { name, status: Status::Pass, detail, remedy: None }
+    }
+
+    fn warn(name: &'static str, detail: String, remedy: &str) -> Self {
+        Check { name, status: Status::Warn, detail, remedy: Some(remedy.to_string()) }
+    }
+
+    fn fail(name: &'static str, detail: String, remedy: &str) -> Self {
+        Check { name, status: Status::Fail, detail, remedy: Some(remedy.to_string()) }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub status: Status, // the worst check
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    pub fn summary(&self) -> String {
+        let count = |status: Status| self.checks.iter().filter(|check| check.status == status).count();
+        format!("{} passed, {} warning(s), {} failed", count(Status::Pass), count(Status::Warn), count(Status::Fail))
+    }
+}
+
+pub fn run(config: &Config) -> Report {
+    let session = Session::detect();
+    let mut checks = vec![
+        tmux(),
+        shell_hooks(),
+        terminals(config, &session),
+        socket(config),
+        display(&session),
+    ];
+    checks.extend(log_space(config));
+    let status = checks.iter().map(|check| check.status).max().unwrap_or(Status::Pass);
+    Report { status, checks }
+}
+
+fn tmux() -> Check {
+    let argv = vec!["tmux".to_string(), "-V".to_string()];
+    let version = match process::run(&argv, TMUX_TIMEOUT) {
+        Ok((0, stdout, _)) => stdout.trim().to_string(),
+        Ok((_, _, stderr)) => return Check::fail("tmux", format!("tmux -V failed: {}", stderr.trim()), "Reinstall tmux"),
+        Err(e) => return Check::fail("tmux", e, "Install tmux with your package manager (e.g. `sudo pacman -S tmux`)"),
+    };
+    match parse_tmux_version(&version) {
+        Some(found) if found < MIN_TMUX => Check::warn(
+            "tmux",
+            format!("{} is older than {}.{}", version, MIN_TMUX.0, MIN_TMUX.1),
+            "Upgrade tmux - older releases miss options the daemon sets on its sessions",
+        ),
+        _ => Check::pass("tmux", version),
+    }
+}
+
+/// (major, minor) from "tmux 3.4", "tmux 3.3a" or "tmux next-3.5" - None for "tmux master" and the like
+fn parse_tmux_version(output: &str) -> Option<(u32, u32)> {
+    let version = output.split_whitespace().nth(1)?;
+    let version = version.trim_start_matches(|c: char| !c.is_ascii_digit());
+    let (major, rest) = version.split_once('.')?;
+    let minor: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+/// The rc file a shell integration would go into, by shell name
+fn rc_file(shell: &str, home: &Path) -> Option<PathBuf> {
+    match shell {
+        "bash" => Some(home.join(".bashrc")),
+        "zsh" => Some(std::env::var("ZDOTDIR").map(PathBuf::from).unwrap_or_else(|_| home.to_path_buf()).join(".zshrc")),
+        "fish" => Some(home.join(".config/fish/config.fish")),
+        _ => None,
+    }
+}
+
+fn shell_hooks() -> Check {
+    let shell = std::env::var("SHELL").unwrap_or_default();
+    let name = Path::new(&shell).file_name().and_then(|n| n.to_str()).unwrap_or("").to_string();
+    let home = PathBuf::from(std::env::var("HOME").unwrap_or_default());
+    let rc = match rc_file(&name, &home) {
+        Some(rc) => rc,
+        None => return Check::warn(
+            "shell_hooks",
+            if shell.is_empty() { "$SHELL is unset".to_string() } else { format!("{} has no supported integration", shell) },
+            "Use bash, zsh or fish in the tmux session - other shells rely on output stability alone to tell when a command is done",
+        ),
+    };
+    let target = if rc.exists() { rc.clone() } else { rc.parent().map(Path::to_path_buf).unwrap_or_default() };
+    if writable(&target) {
+        Check::pass("shell_hooks", format!("{} integration can be installed into {}", name, rc.display()))
+    } else {
+        Check::warn(
+            "shell_hooks",
+            format!("{} is not writable", target.display()),
+            "Fix the ownership of the shell's rc file (or its directory) so the integration can be added",
+        )
+    }
+}
+
+fn terminals(config: &Config, session: &Session) -> Check {
+    let installed: Vec<String> = terminals::ranked(config, session).into_iter()
+        .filter(|spec| spec.is_installed())
+        .map(|spec| spec.name)
+        .collect();
+    if let Some(configured) = &config.terminal_emulator {
+        if !installed.contains(configured) {
+            return Check::warn(
+                "terminals",
+                format!("terminal_emulator {:?} is not installed or can't run in this session", configured),
+                "Install it or point terminal_emulator at one that is - the next candidates are used meanwhile",
+            );
+        }
+    }
+    match installed.first() {
+        Some(first) => Check::pass("terminals", format!("{} (also usable: {})", first, if installed.len() > 1 { installed[1..].join(", ") } else { "none".to_string() })),
+        None if session.server == DisplayServer::Unknown => Check::warn(
+            "terminals",
+            "no graphical session to open a terminal in".to_string(),
+            "open_terminal needs a graphical session - see the display check",
+        ),
+        None => Check::fail(
+            "terminals",
+            "no supported terminal emulator is installed".to_string(),
+            "Install one (foot, kitty, alacritty, ...) or add yours under [terminals.templates]",
+        ),
+    }
+}
+
+fn socket(config: &Config) -> Check {
+    let meta = match fs::metadata(&config.socket_path) {
+        Ok(meta) => meta,
+        Err(e) => return Check::fail(
+            "socket",
+            format!("{}: {}", config.socket_path, e),
+            "The daemon binds it again within a second - if this persists, check the directory's permissions",
+        ),
+    };
+    socket_check(&config.socket_path, meta.file_type().is_socket(), meta.uid(), meta.mode(), config.acl_default)
+}
+
+fn socket_check(path: &str, is_socket: bool, owner: u32, mode: u32, acl_default: Access) -> Check {
+    let perms = mode & 0o777;
+    if !is_socket {
+        return Check::fail("socket", format!("{} is not a socket", path), "Remove the file and restart the daemon");
+    }
+    if owner != peer::own_uid() {
+        return Check::fail(
+            "socket",
+            format!("{} is owned by uid {}, not the daemon", path, owner),
+            "Another daemon may be running under a different user - stop it or give this one its own socket_path",
+        );
+    }
+    // Connecting to a Unix socket needs write permission on it
+    if perms & 0o002 != 0 && acl_default > Access::None {
+        return Check::warn(
+            "socket",
+            format!("{} is mode {:o} and acl.default is {}", path, perms, acl_default.as_str()),
+            "Any local user can connect - set acl.default = \"none\" and list trusted users under [acl.users]",
+        );
+    }
+    Check::pass("socket", format!("{} (mode {:o}, acl.default {})", path, perms, acl_default.as_str()))
+}
+
+fn display(session: &Session) -> Check {
+    let compositor = session.compositor.as_deref().map(|c| format!(" ({})", c)).unwrap_or_default();
+    match session.server {
+        DisplayServer::Wayland => Check::pass("display", format!(
+            "Wayland{} on {}{}",
+            compositor,
+            session.wayland_display.as_deref().unwrap_or("?"),
+            match &session.display {
+                Some(display) => format!(", XWayland on {}", display),
+                None => ", no XWayland".to_string(),
+            }
+        )),
+        DisplayServer::X11 => Check::pass("display", format!("X11{} on {}", compositor, session.display.as_deref().unwrap_or("?"))),
+        DisplayServer::Unknown => Check::warn(
+            "display",
+            "neither WAYLAND_DISPLAY nor DISPLAY leads to a running session".to_string(),
+            "Run `systemctl --user import-environment WAYLAND_DISPLAY DISPLAY XDG_CURRENT_DESKTOP` from the desktop session (e.g. in its autostart)",
+        ),
+    }
+}
+
+/// Free space and write access where logs and overflow output go
+fn log_space(config: &Config) -> Vec<Check> {
+    let mut dirs: Vec<(&str, PathBuf)> = Vec::new();
+    if let Some(file) = &config.log_file {
+        dirs.push(("log_file", Path::new(file).parent().map(Path::to_path_buf).unwrap_or_default()));
+    }
+    if config.audit_enabled {
+        dirs.push(("audit_log", Path::new(&config.audit_log).parent().map(Path::to_path_buf).unwrap_or_default()));
+    }
+    dirs.push(("artifact_dir", PathBuf::from(&config.artifact_dir)));
+
+    dirs.into_iter()
+        .map(|(setting, dir)| {
+            // Directories that don't exist yet are created on first write - look at what's there
+            let existing = dir.ancestors().find(|a| a.exists()).unwrap_or(Path::new("/"));
+            if !writable(existing) {
+                return Check::fail(
+                    "log_space",
+                    format!("{}: {} is not writable", setting, existing.display()),
+                    "Fix its ownership or point the setting somewhere the daemon's user can write",
+                );
+            }
+            match storage::free_space(&existing.to_string_lossy()) {
+                Some((available, _)) => space_check(setting, &dir, available),
+                None => Check::warn("log_space", format!("{}: cannot stat {}", setting, existing.display()), "Check the mount holding it"),
+            }
+        })
+        .collect()
+}
+
+fn space_check(setting: &str, dir: &Path, available: u64) -> Check {
+    let detail = format!("{}: {} MiB free under {}", setting, available / (1024 * 1024), dir.display());
+    let remedy = "Free some space there, or move the setting to a larger filesystem";
+    if available < FAIL_FREE_BYTES {
+        Check::fail("log_space", detail, remedy)
+    } else if available < WARN_FREE_BYTES {
+        Check::warn("log_space", detail, remedy)
+    } else {
+        Check::pass("log_space", detail)
+    }
+}
+
+fn writable(path: &Path) -> bool {
+    let Ok(path) = CString::new(path.to_string_lossy().as_bytes()) else { return false };
+    // SAFETY: `path` is NUL-terminated and outlives the call
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tmux_version() {
+        assert_eq!(parse_tmux_version("tmux 3.4\n"), Some((3, 4)));
+        assert_eq!(parse_tmux_version("tmux 3.3a"), Some((3, 3)));
+        assert_eq!(parse_tmux_version("tmux next-3.5"), Some((3, 5)));
+        assert_eq!(parse_tmux_version("tmux master"), None);
+        assert!(parse_tmux_version("tmux 2.9a").unwrap() < MIN_TMUX);
+    }
+
+    #[test]
+    fn test_socket_and_space_checks() {
+        let me = peer::own_uid();
+        assert_eq!(socket_check("/tmp/a.sock", true, me, 0o140755, Access::None).status, Status::Pass);
+        assert_eq!(socket_check("/tmp/a.sock", true, me, 0o140777, Access::None).status, Status::Pass);
+        assert_eq!(socket_check("/tmp/a.sock", true, me, 0o140777, Access::Read).status, Status::Warn);
+        assert_eq!(socket_check("/tmp/a.sock", true, me + 1, 0o140755, Access::None).status, Status::Fail);
+        assert_eq!(socket_check("/tmp/a.sock", false, me, 0o100644, Access::None).status, Status::Fail);
+
+        let dir = Path::new("/var/log/archy");
+        assert_eq!(space_check("log_file", dir, 10 << 30).status, Status::Pass);
+        assert_eq!(space_check("log_file", dir, 100 << 20).status, Status::Warn);
+        assert!(space_check("log_file", dir, 1 << 20).remedy.is_some());
+    }
+}
@@ -0,0 +1,191 @@
+// errors.rs - Error Detection Module
+// Scans raw command output for well-known failure signatures and derives an overall status.
+// Also re-exports the error taxonomy (archy-protocol) so clients can branch on a stable `error.kind`.
+
+use std::collections::HashMap;
+
+/// The error taxonomy is part of the wire protocol, shared with rust-brain
+pub use archy_protocol::ErrorKind;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ErrorSeverity {
+    Critical,
+    High,
+    Medium,
+    Low,
+}
+
+/// A failure signature found in command output
+#[derive(Debug, Clone)]
+pub struct DetectedError {
+    pub pattern: String,     // Stable pattern name, e.g. "permission_denied"
+    pub message: String,     // Human-readable description
+    pub severity: ErrorSeverity,
+    pub occurrences: usize,  // How many lines matched
+    pub lines: Vec<usize>,   // 1-based line numbers of the matches
+    pub confidence: f32,     // Specific signatures score higher than generic "error:" lines
+}
+
+/// Confidence for specific failure signatures vs. generic "error:"/"warning:" prefixes
+const SPECIFIC_PATTERN_CONFIDENCE: f32 = 0.9;
+const GENERIC_PATTERN_CONFIDENCE: f32 = 0.6;
+
+/// (pattern name, lowercase needle, severity, description)
+const ERROR_PATTERNS: &[(&str, &str, ErrorSeverity, &str)] = &[
+    ("kernel_panic", "kernel panic", ErrorSeverity::Critical, "Kernel panic reported"),
+    ("segfault", "segmentation fault", ErrorSeverity::Critical, "Process crashed with a segmentation fault"),
+    ("out_of_memory", "out of memory", ErrorSeverity::Critical, "System ran out of memory"),
+    ("out_of_memory", "cannot allocate memory", ErrorSeverity::Critical, "System ran out of memory"),
+    ("disk_full", "no space left on device", ErrorSeverity::Critical, "Disk is full"),
+    ("command_not_found", "command not found", ErrorSeverity::High, "Command not found"),
+    ("no_such_file", "no such file or directory", ErrorSeverity::High, "File or directory does not exist"),
+    ("permission_denied", "permission denied", ErrorSeverity::High, "Permission denied"),
+    ("operation_not_permitted", "operation not permitted", ErrorSeverity::High, "Operation not permitted"),
+    ("syntax_error", "syntax error", ErrorSeverity::High, "Shell or program syntax error"),
+    ("fatal", "fatal:", ErrorSeverity::High, "Fatal error reported"),
+    ("connection_refused", "connection refused", ErrorSeverity::Medium, "Connection refused"),
+    ("timed_out", "timed out", ErrorSeverity::Medium, "Operation timed out"),
+    ("generic_error", "error:", ErrorSeverity::Medium, "Error reported in output"),
+    ("generic_warning", "warning:", ErrorSeverity::Low, "Warning reported in output"),
+];
+
+/// Detect known error signatures in raw output (one entry per pattern, in order of first appearance)
+pub fn detect_errors(raw: &str) -> Vec<DetectedError> {
+    let mut detected: Vec<DetectedError> = Vec::new();
+    let mut index_by_pattern: HashMap<&str, usize> = HashMap::new();
+
+    for (line_idx, line) in raw.lines().enumerate() {
+        let lower = line.to_lowercase();
+
+        // Only the first (most specific) pattern counts for each line
+        if let Some((name, _, severity, description)) = ERROR_PATTERNS
+            .iter()
+            .find(|(_, needle, _, _)| lower.contains(needle))
+        {
+            match index_by_pattern.get(name) {
+                Some(&idx) => {
+                    detected[idx].occurrences += 1;
+                    detected[idx].lines.push(line_idx + 1);
+                }
+                None => {
+                    index_by_pattern.insert(name, detected.len());
+                    detected.push(DetectedError {
+                        pattern: name.to_string(),
+                        message: format!("{}: {}", description, line.trim()),
+                        severity: *severity,
+                        occurrences: 1,
+                        lines: vec![line_idx + 1],
+                        confidence: if name.starts_with("generic_") {
+                            GENERIC_PATTERN_CONFIDENCE
+                        } else {
+                            SPECIFIC_PATTERN_CONFIDENCE
+                        },
+                    });
+                }
+            }
+        }
+    }
+
+    detected
+}
+
+/// Most severe error among the detected ones
+pub fn max_severity(errors: &[DetectedError]) -> Option<ErrorSeverity> {
+    errors.iter().map(|e| e.severity).min()
+}
+
+/// Derive the overall status: "error" for Critical/High, "warning" for Medium/Low, else "success"
+pub fn determine_status(errors: &[DetectedError]) -> String {
+    match max_severity(errors) {
+        Some(ErrorSeverity::Critical) | Some(ErrorSeverity::High) => "error".to_string(),
+        Some(ErrorSeverity::Medium) | Some(ErrorSeverity::Low) => "warning".to_string(),
+        None => "success".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::DisplayOutput;
+    use crate::parser::parse_intelligently;
+
+    #[test]
+    fn test_clean_output_is_success() {
+        let detected = detect_errors("total 0\nfile.txt\n");
+        assert!(detected.is_empty());
+        assert_eq!(determine_status(&detected), "success");
+    }
+
+    #[test]
+    fn test_permission_denied_is_error() {
+        let raw = "cat: /etc/shadow: Permission denied\n";
+        let detected = detect_errors(raw);
+        assert_eq!(detected.len(), 1);
+        assert_eq!(detected[0].pattern, "permission_denied");
+        assert_eq!(detected[0].severity, ErrorSeverity::High);
+        assert_eq!(determine_status(&detected), "error");
+    }
+
+    #[test]
+    fn test_warning_only_is_warning() {
+        let detected = detect_errors("warning: deprecated flag\nok\n");
+        assert_eq!(determine_status(&detected), "warning");
+    }
+
+    #[test]
+    fn test_repeated_pattern_is_counted_once() {
+        let raw = "ls: a: No such file or directory\nls: b: No such file or directory\n";
+        let detected = detect_errors(raw);
+        assert_eq!(detected.len(), 1);
+        assert_eq!(detected[0].occurrences, 2);
+    }
+
+    #[test]
+    fn test_status_flows_into_display_output() {
+        let parsed = parse_intelligently("bash: foo: command not found\n", "foo");
+        assert_eq!(parsed.status, "error");
+
+        let output = DisplayOutput::from_command_output("foo", "bash: foo: command not found\n", 0);
+        assert!(!output.success);
+        assert_eq!(output.status, "error");
+
+        let output = DisplayOutput::from_command_output("make", "warning: unused variable\n", 0);
+        assert!(output.success);
+        assert_eq!(output.status, "warning");
+    }
+
+    #[test]
+    fn test_critical_parser_finding_downgrades_to_warning() {
+        let raw = "Filesystem Size Used Avail Use% Mounted\n/dev/sda1 100G 95G 5G 95% /\n";
+        let parsed = parse_intelligently(raw, "df -h");
+        assert_eq!(parsed.status, "warning");
+    }
+
+    #[test]
+    fn test_error_findings_reference_lines() {
+        let raw = "ok\nls: a: No such file or directory\nok\nls: b: No such file or directory\n";
+        let parsed = parse_intelligently(raw, "ls a b");
+        let finding = parsed.findings.iter()
+            .find(|f| f.category == "Error: no_such_file")
+            .expect("error finding");
+        assert_eq!(finding.line_refs, vec![2, 4]);
+        assert_eq!(
+            finding.excerpt.as_deref(),
+            Some("2: ls: a: No such file or directory\n4: ls: b: No such file or directory")
+        );
+    }
+
+    #[test]
+    fn test_findings_carry_provenance() {
+        let raw = "Filesystem Size Used Avail Use% Mounted\n/dev/sda1 100G 95G 5G 95% /\nwarning: quota\n";
+        let parsed = parse_intelligently(raw, "df -h");
+
+        let disk = parsed.findings.iter().find(|f| f.category == "Disk Space Critical").unwrap();
+        assert_eq!(disk.source_parser, "disk_usage");
+        assert!(disk.confidence > 0.8);
+
+        let warning = parsed.findings.iter().find(|f| f.category == "Error: generic_warning").unwrap();
+        assert_eq!(warning.source_parser, "error_detector");
+        assert!(warning.confidence < disk.confidence);
+    }
+}
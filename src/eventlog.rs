@@ -0,0 +1,156 @@
+// eventlog.rs - What happened in the daemon, for clients that follow along
+// Replies only tell the client that asked. Things a dashboard wants to see regardless of who caused
+// them - sessions created, killed or reset, batches starting and stopping, requests refused, the
+// emergency stop, runtime config changes - go into an in-memory log as numbered events. Clients
+// poll `get_events {since}` with the `next` cursor of their last call; when they fall more than
+// MAX_EVENTS behind, `missed` says how many went by unseen. Messages are redacted like replies.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::secrets;
+
+/// Events kept in memory
+pub const MAX_EVENTS: usize = 1000;
+
+/// get_events page size when the client gives no limit
+const DEFAULT_LIMIT: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    DaemonStarted,
+    SessionCreated,
+    SessionClaimed,
+    SessionKilled,
+    SessionReset, // recreated by the watchdog
+    BatchStarted,
+    BatchPaused, // a step waits for input
+    BatchFinished,
+    CommandBlocked,
+    AccessDenied,
+    Throttled,
+    ConfirmationRequired,
+    EmergencyStop,
+    ExecutionResumed,
+    ConfigChanged,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    pub seq: u64, // increasing from 1, never reused while the daemon runs
+    pub ts_ms: u64,
+    pub kind: Kind,
+    pub session: Option<String>,
+    pub message: String,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub detail: Value,
+}
+
+struct Log {
+    last_seq: u64,
+    events: VecDeque<Event>, // oldest first, at most MAX_EVENTS
+}
+
+static LOG: Mutex<Log> = Mutex::new(Log { last_seq: 0, events: VecDeque::new() });
+
+/// Append an event
+pub fn emit(kind: Kind, session: Option<&str>, message: impl Into<String>, detail: Value) {
+    let ts_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let mut log = LOG.lock().unwrap_or_else(|e| e.into_inner());
+    log.last_seq += 1;
+    let event = Event {
+        seq: log.last_seq,
+        ts_ms,
+        kind,
+        session: session.map(str::to_string),
+        message: secrets::redact(&message.into()),
+        detail,
+    };
+    log.events.push_back(event);
+    while log.events.len() > MAX_EVENTS {
+        log.events.pop_front();
+    }
+}
+
+/// `get_events` filters - all optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EventQuery {
+    #[serde(default)]
+    pub since: u64, // return events after this seq (the `next` of the previous call)
+    pub limit: Option<usize>, // oldest N matches (default 100)
+    pub kinds: Option<Vec<Kind>>,
+    pub session: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Page {
+    pub events: Vec<Event>,
+    pub next: u64, // pass as `since` to continue
+    pub missed: u64, // events after `since` that were dropped before this call
+}
+
+/// Matching events after `since`, oldest first
+pub fn query(query: &EventQuery) -> Page {
+    let log = LOG.lock().unwrap_or_else(|e| e.into_inner());
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_EVENTS);
+    let oldest = log.events.front().map(|event| event.seq).unwrap_or(log.last_seq + 1);
+    let missed = oldest.saturating_sub(query.since + 1).min(log.last_seq.saturating_sub(query.since));
+
+    let mut events = Vec::new();
+    let mut next = log.last_seq.max(query.since);
+    let matches = log.events.iter()
+        .filter(|event| event.seq > query.since)
+        .filter(|event| query.kinds.as_ref().is_none_or(|kinds| kinds.contains(&event.kind)))
+        .filter(|event| query.session.as_deref().is_none_or(|session| event.session.as_deref() == Some(session)));
+    for event in matches {
+        if events.len() == limit {
+            // More to come - resume right after the last one returned
+            next = events.last().map(|event: &Event| event.seq).unwrap_or(next);
+            break;
+        }
+        events.push(event.clone());
+    }
+    Page { events, next, missed }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_filters_and_missed() {
+        let start = query(&EventQuery::default()).next;
+        for n in 0..3 {
+            emit(Kind::SessionCreated, Some("eventlog-test"), format!("created {}", n), Value::Null);
+        }
+        emit(Kind::BatchStarted, Some("eventlog-test"), "batch", serde_json::json!({"steps": 2}));
+
+        let mine = |page: &Page| page.events.iter().filter(|e| e.session.as_deref() == Some("eventlog-test")).count();
+        let page = query(&EventQuery { since: start, limit: Some(2), session: Some("eventlog-test".to_string()), ..EventQuery::default() });
+        assert_eq!((mine(&page), page.missed), (2, 0));
+        assert_eq!(page.next, page.events[1].seq);
+
+        let rest = query(&EventQuery {
+            since: page.next,
+            session: Some("eventlog-test".to_string()),
+            kinds: Some(vec![Kind::BatchStarted]),
+            ..EventQuery::default()
+        });
+        assert_eq!(rest.events.len(), 1);
+        assert_eq!(rest.events[0].detail["steps"], 2);
+        assert!(rest.next >= rest.events[0].seq);
+
+        // A cursor from before everything kept reports the gap
+        for _ in 0..MAX_EVENTS {
+            emit(Kind::ConfigChanged, None, "filler", Value::Null);
+        }
+        assert!(query(&EventQuery { since: start, limit: Some(1), ..EventQuery::default() }).missed >= 4);
+    }
+}
@@ -0,0 +1,110 @@
+// events.rs - Security events surfaced as findings
+// A blocked command, a tripped rate limit or a redaction is reported as a structured Critical/High
+// finding on the reply, so the AI loop and the UI present "blocked: matches destructive pattern
+// rm -rf /" the same way as anything the parser found - not as an opaque error string.
+
+use serde_json::Value;
+use std::sync::Mutex;
+use crate::eventlog::{self, Kind};
+use crate::leaks;
+use crate::parser::{Finding, Importance};
+use crate::secrets;
+
+/// Longest command quoted in a finding
+const MAX_COMMAND_CHARS: usize = 200;
+
+/// Events for the request being handled (the daemon serves one connection at a time)
+static PENDING: Mutex<Vec<Finding>> = Mutex::new(Vec::new());
+
+pub fn record(finding: Finding) {
+    PENDING.lock().unwrap_or_else(|e| e.into_inner()).push(finding);
+}
+
+/// A command refused by validation or `[security] blocked_patterns`
+pub fn blocked(command: &str, reason: &str) {
+    let mut quoted: String = command.chars().take(MAX_COMMAND_CHARS).collect();
+    if quoted.len() < command.len() {
+        quoted.push('…');
+    }
+    tracing::warn!("Blocked command: {} ({})", reason, quoted);
+    eventlog::emit(Kind::CommandBlocked, None, format!("{} - command: {}", reason, quoted), serde_json::Value::Null);
+    record(
+        Finding::new("Command Blocked", format!("{} - command: {}", reason, quoted), Importance::Critical)
+            .with_provenance("validation", 1.0),
+    );
+}
+
+/// Drop events that never made it into a reply (called after each connection)
+pub fn clear() {
+    PENDING.lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+/// Move pending events into the reply's `findings` (added when the reply has none)
+pub fn attach(reply: &mut Value) {
+    let pending: Vec<Finding> = std::mem::take(&mut *PENDING.lock().unwrap_or_else(|e| e.into_inner()));
+    if pending.is_empty() {
+        return;
+    }
+    let findings = match reply.as_object_mut().map(|map| map.entry("findings").or_insert_with(|| Value::Array(Vec::new()))) {
+        Some(Value::Array(findings)) => findings,
+        _ => return,
+    };
+    for finding in pending {
+        let finding = serde_json::to_value(finding).unwrap_or_default();
+        // Handlers that already reported the event (throttle, batch results) aren't repeated
+        let seen = findings.iter().any(|f| f["category"] == finding["category"] && f["message"] == finding["message"]);
+        if !seen {
+            findings.push(finding);
+        }
+    }
+}
+
+/// Every reply passes through here on its way out: secrets redacted by value, leak scan, then the
+/// request's security events attached
+pub fn outbound(reply: Value) -> Value {
+    let mut reply = reply;
+    let names = secrets::redact_value(&mut reply);
+    if !names.is_empty() {
+        record(
+            Finding::new(
+                "Secret Redacted",
+                format!("Configured secret values redacted from the output ({})", names.join(", ")),
+                Importance::High,
+            )
+            .with_provenance("secrets", 1.0),
+        );
+    }
+    let mut reply = leaks::guard(reply);
+    attach(&mut reply);
+    reply
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_become_findings() {
+        clear();
+        blocked("rm -rf / --no-preserve-root", "Blocked dangerous command pattern: rm -rf /");
+        let mut reply = serde_json::json!({"success": false, "error": "Blocked dangerous command pattern: rm -rf /"});
+        attach(&mut reply);
+
+        let finding = &reply["findings"][0];
+        assert_eq!(finding["category"], "Command Blocked");
+        assert_eq!(finding["importance"], "Critical");
+        assert!(finding["message"].as_str().unwrap().starts_with("Blocked dangerous command pattern: rm -rf / - command:"));
+
+        // Drained - the next reply starts clean
+        let mut next = serde_json::json!({"success": true});
+        attach(&mut next);
+        assert!(next.get("findings").is_none());
+
+        // One test - PENDING is shared, parallel tests would see each other's events
+        let finding = Finding::new("Rate Limited", "slow down", Importance::Critical);
+        let mut reply = serde_json::json!({"findings": [serde_json::to_value(&finding).unwrap()]});
+        record(finding);
+        attach(&mut reply);
+        assert_eq!(reply["findings"].as_array().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,132 @@
+// features.rs - Capability switches for risky subsystems
+// Minimal deployments turn whole capabilities off; the dispatcher refuses their actions before any handler runs
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    Gui,              // launching desktop apps and terminal windows
+    FallbackTerminal, // running commands in a new throwaway terminal
+    FileWrite,        // actions that write files on request (saved workflows, autostart entries, default apps)
+    DirectExecutor,   // running commands outside tmux (reserved - not in this build)
+    TcpListener,      // network transport (reserved - not in this build)
+}
+
+impl Feature {
+    pub const ALL: [Feature; 5] = [
+        Feature::Gui,
+        Feature::FallbackTerminal,
+        Feature::FileWrite,
+        Feature::DirectExecutor,
+        Feature::TcpListener,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Feature::Gui => "gui",
+            Feature::FallbackTerminal => "fallback_terminal",
+            Feature::FileWrite => "file_write",
+            Feature::DirectExecutor => "direct_executor",
+            Feature::TcpListener => "tcp_listener",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Feature::ALL.into_iter().find(|f| f.name() == name)
+    }
+
+    /// Not implemented yet - accepted in config so files stay forward compatible
+    pub fn is_reserved(&self) -> bool {
+        matches!(self, Feature::DirectExecutor | Feature::TcpListener)
+    }
+
+    /// The capability an action needs, if it's gated at all
+    pub fn for_action(action: &str) -> Option<Self> {
+        match action {
+            "launch_gui_app" | "open_with_default" | "open_terminal" | "focus_window" | "close_window" => Some(Feature::Gui),
+            "launch_fallback_terminal" => Some(Feature::FallbackTerminal),
+            "save_workflow" | "add_autostart" | "remove_autostart" | "set_default_app" => Some(Feature::FileWrite),
+            _ => None,
+        }
+    }
+}
+
+/// Effective switches (`[features]` in the config file)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Features {
+    pub gui: bool,
+    pub fallback_terminal: bool,
+    pub file_write: bool,
+    pub direct_executor: bool,
+    pub tcp_listener: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Features {
+            gui: true,
+            fallback_terminal: true,
+            file_write: true,
+            direct_executor: false,
+            tcp_listener: false,
+        }
+    }
+}
+
+impl Features {
+    pub fn enabled(&self, feature: Feature) -> bool {
+        match feature {
+            Feature::Gui => self.gui,
+            Feature::FallbackTerminal => self.fallback_terminal,
+            Feature::FileWrite => self.file_write,
+            Feature::DirectExecutor => self.direct_executor,
+            Feature::TcpListener => self.tcp_listener,
+        }
+    }
+
+    pub fn set(&mut self, feature: Feature, enabled: bool) {
+        match feature {
+            Feature::Gui => self.gui = enabled,
+            Feature::FallbackTerminal => self.fallback_terminal = enabled,
+            Feature::FileWrite => self.file_write = enabled,
+            Feature::DirectExecutor => self.direct_executor = enabled,
+            Feature::TcpListener => self.tcp_listener = enabled,
+        }
+    }
+
+    /// Dispatcher gate - Err when the action's capability is switched off
+    pub fn check_action(&self, action: &str) -> Result<(), String> {
+        match Feature::for_action(action) {
+            Some(feature) if !self.enabled(feature) => Err(format!(
+                "Action '{}' is disabled by configuration (features.{} = false)",
+                action,
+                feature.name()
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled_feature_blocks_action() {
+        let mut features = Features::default();
+        assert!(features.check_action("launch_gui_app").is_ok());
+
+        features.set(Feature::Gui, false);
+        assert!(features.check_action("launch_gui_app").unwrap_err().contains("features.gui"));
+        assert!(features.check_action("open_terminal").is_err());
+        assert!(features.check_action("execute").is_ok());
+    }
+
+    #[test]
+    fn test_names_round_trip() {
+        for feature in Feature::ALL {
+            assert_eq!(Feature::from_name(feature.name()), Some(feature));
+        }
+        assert_eq!(Feature::from_name("bogus"), None);
+    }
+}
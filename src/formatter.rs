@@ -2,7 +2,8 @@
 // Handles ALL formatting, coloring, and pretty display generation
 
 use serde_json::Value;
-use crate::parser::{Finding, Importance, Metadata};
+use crate::parser::{Finding, Importance, RiskLevel, SuggestedAction};
+use crate::risk::{RiskAssessment, RiskClass};
 
 /// ANSI color utilities
 pub fn color_red(s: &str) -> String {
@@ -17,10 +18,6 @@ pub fn color_yellow(s: &str) -> String {
     format!("\x1b[33m{}\x1b[0m", s)
 }
 
-pub fn color_blue(s: &str) -> String {
-    format!("\x1b[34m{}\x1b[0m", s)
-}
-
 pub fn color_magenta(s: &str) -> String {
     format!("\x1b[35m{}\x1b[0m", s)
 }
@@ -73,11 +70,47 @@ pub fn format_finding(finding: &Finding) -> String {
         Importance::Info => "ℹ️ ",
     };
 
-    format!(
+    let mut output = format!(
         "  {} {} - {}\n",
         icon,
         color_bold(&finding.category),
         finding.message
+    );
+
+    // Contextual excerpt: "L12 │ text" for each referenced line
+    if let Some(excerpt) = &finding.excerpt {
+        for line in excerpt.lines() {
+            let (number, text) = line.split_once(": ").unwrap_or(("", line));
+            output.push_str(&format!(
+                "     {} {}\n",
+                color_dim(&format!("L{:<4}│", number)),
+                color_dim(&truncate_string(text, 100))
+            ));
+        }
+        if finding.line_refs.len() > excerpt.lines().count() {
+            output.push_str(&color_dim(&format!(
+                "     … {} more line(s)\n",
+                finding.line_refs.len() - excerpt.lines().count()
+            )));
+        }
+    }
+
+    output
+}
+
+/// Format a suggested follow-up command with its risk level
+pub fn format_suggestion(suggestion: &SuggestedAction) -> String {
+    let risk = match suggestion.risk {
+        RiskLevel::Low => color_green("(low risk)"),
+        RiskLevel::Medium => color_yellow("(medium risk)"),
+        RiskLevel::High => color_red("(high risk)"),
+    };
+
+    format!(
+        "  → {} {}\n    {}\n",
+        color_bold(&suggestion.command),
+        risk,
+        color_dim(&suggestion.rationale)
     )
 }
 
@@ -275,6 +308,7 @@ pub fn format_as_json(value: &Value) -> String {
 pub fn format_pretty(
     data: &Value,
     findings: &[Finding],
+    suggestions: &[SuggestedAction],
     command: &str,
 ) -> String {
     let mut output = String::new();
@@ -299,6 +333,14 @@ pub fn format_pretty(
         output.push_str(&data_section);
     }
 
+    // Suggested next steps
+    if !suggestions.is_empty() {
+        output.push_str(&color_yellow("\n💡 Suggested Next Steps:\n"));
+        for suggestion in suggestions {
+            output.push_str(&format_suggestion(suggestion));
+        }
+    }
+
     // Summary
     let summary = generate_summary(findings);
     output.push_str(&format!(
@@ -309,6 +351,91 @@ pub fn format_pretty(
     output
 }
 
+/// Markdown rendering of an analyzed command (for chat UIs and reports)
+pub fn format_markdown(
+    command: &str,
+    summary: &str,
+    findings: &[Finding],
+    suggestions: &[SuggestedAction],
+    data: &Value,
+) -> String {
+    let mut output = String::new();
+
+    output.push_str(&format!("### `{}`\n\n", command));
+
+    if !findings.is_empty() {
+        output.push_str("**Key Findings**\n\n");
+        for finding in findings {
+            let level = match finding.importance {
+                Importance::Critical => "critical",
+                Importance::High => "high",
+                Importance::Medium => "medium",
+                Importance::Low => "low",
+                Importance::Info => "info",
+            };
+            output.push_str(&format!("- **{}** ({}): {}\n", finding.category, level, finding.message));
+            if let Some(excerpt) = &finding.excerpt {
+                output.push_str(&format!("  ```\n{}\n  ```\n", excerpt));
+            }
+        }
+        output.push('\n');
+    }
+
+    if let Value::Object(obj) = data {
+        if is_table_like(obj) && !obj.is_empty() {
+            output.push_str("| Key | Value |\n|---|---|\n");
+            for (key, value) in obj {
+                let value_str = match value {
+                    Value::String(s) => s.replace('|', "\\|").replace('\n', " "),
+                    _ => value.to_string(),
+                };
+                output.push_str(&format!("| {} | {} |\n", key, truncate_string(&value_str, 120)));
+            }
+            output.push('\n');
+        }
+    }
+
+    if !suggestions.is_empty() {
+        output.push_str("**Suggested Next Steps**\n\n");
+        for suggestion in suggestions {
+            output.push_str(&format!("- `{}` - {}\n", suggestion.command, suggestion.rationale));
+        }
+        output.push('\n');
+    }
+
+    output.push_str(&format!("**Summary:** {}\n", summary));
+    output
+}
+
+/// Format success criteria results as a checklist
+pub fn format_criteria(report: &crate::criteria::CriteriaReport) -> String {
+    let mut output = color_yellow("\n🎯 Success Criteria:\n");
+    for result in &report.results {
+        let mark = if result.passed { color_green("✓") } else { color_red("✗") };
+        output.push_str(&format!("  {} {}\n", mark, result.detail));
+    }
+    output
+}
+
+/// Format findings added after the main render (e.g. project parser rules)
+pub fn format_extra_findings(findings: &[Finding]) -> String {
+    let mut output = color_yellow("\n📌 Project Findings:\n");
+    for finding in findings {
+        output.push_str(&format_finding(finding));
+    }
+    output
+}
+
+/// Risk line for commands that change state (read-only commands get none)
+pub fn format_risk(assessment: &RiskAssessment) -> String {
+    let label = format!("⚠ Risk: {} - {}", assessment.class.as_str(), assessment.reason);
+    match assessment.class {
+        RiskClass::ReadOnly => String::new(),
+        RiskClass::Modifying => format!("\n{}\n", color_yellow(&label)),
+        RiskClass::Destructive | RiskClass::Privileged => format!("\n{}\n", color_red(&label)),
+    }
+}
+
 /// Format error message
 pub fn format_error(command: &str, error: &str) -> String {
     format!(
@@ -318,28 +445,19 @@ pub fn format_error(command: &str, error: &str) -> String {
     )
 }
 
-/// Format metadata display
-pub fn format_metadata(metadata: &Metadata) -> String {
-    let mut output = String::new();
-
-    output.push_str(&color_dim("\n─── Metadata ───\n"));
-    output.push_str(&color_dim(&format!("Lines: {}\n", metadata.line_count)));
-    output.push_str(&color_dim(&format!("Size: {} bytes\n", metadata.byte_count)));
-
-    if let Some(duration) = metadata.duration_ms {
-        output.push_str(&color_dim(&format!("Duration: {}ms\n", duration)));
-    }
-
-    output
-}
-
 /// Format batch execution result with AI-friendly summary
 pub fn format_batch_result(batch: &crate::batch::BatchExecutionResult) -> String {
     let mut output = String::new();
 
     // Header
-    output.push_str("\n");
-    output.push_str(&format!("{}\n", color_cyan("⚡ Executing commands in sequence...")));
+    output.push('\n');
+    match batch.resumed_from {
+        Some(step) => output.push_str(&format!(
+            "{}\n",
+            color_cyan(&format!("⚡ Resuming batch {} from step {}...", batch.batch_id, step))
+        )),
+        None => output.push_str(&format!("{}\n", color_cyan("⚡ Executing commands in sequence..."))),
+    }
     output.push_str(&format!("{}\n\n", color_dim(&"─".repeat(60))));
 
     // Command list
@@ -351,12 +469,61 @@ pub fn format_batch_result(batch: &crate::batch::BatchExecutionResult) -> String
 
         if cmd.success {
             output.push_str(&format!("  {}\n", color_green("✓ Completed")));
+        } else if cmd.failure_ignored {
+            output.push_str(&format!(
+                "  {}\n",
+                color_yellow(&format!("⚠ Failed (ignored): {}", cmd.error.as_deref().unwrap_or("Unknown error")))
+            ));
+        } else if cmd.status == "awaiting_input" {
+            output.push_str(&format!(
+                "  {}\n",
+                color_magenta(&format!("⌨ Waiting for input: {}", cmd.prompt.as_deref().unwrap_or("prompt")))
+            ));
+        } else if cmd.status == "skipped" {
+            output.push_str(&format!(
+                "  {}\n",
+                color_dim(&format!("⊘ {}", cmd.error.as_deref().unwrap_or("Skipped")))
+            ));
+        } else if cmd.status == "timeout" {
+            output.push_str(&format!(
+                "  {}\n",
+                color_yellow(&format!("⏱ Timed out: {}", cmd.error.as_deref().unwrap_or("no prompt detected")))
+            ));
         } else {
             output.push_str(&format!(
                 "  {}\n",
                 color_red(&format!("✗ Failed: {}", cmd.error.as_ref().unwrap_or(&"Unknown error".to_string())))
             ));
         }
+
+        for prompt in &cmd.answered_prompts {
+            output.push_str(&format!("  {}\n", color_dim(&format!("↳ answered: {}", prompt))));
+        }
+
+        if cmd.attempts > 1 {
+            output.push_str(&format!("  {}\n", color_dim(&format!("↻ {} attempts", cmd.attempts))));
+        }
+    }
+
+    if let Some(rollback) = &batch.rollback {
+        output.push_str(&format!(
+            "\n{}\n",
+            color_yellow(&format!("↩ Rolling back after step {} failed", rollback.triggered_by))
+        ));
+        for undo in &rollback.steps {
+            let mark = if undo.success { color_green("✓") } else { color_red("✗") };
+            output.push_str(&format!("  {} [undo {}] {}\n", mark, undo.index, undo.command));
+        }
+        if !rollback.not_compensated.is_empty() {
+            let steps: Vec<String> = rollback.not_compensated.iter().map(|i| i.to_string()).collect();
+            output.push_str(&format!(
+                "  {}\n",
+                color_dim(&format!("No compensation declared for step(s): {}", steps.join(", ")))
+            ));
+        }
+        if !rollback.complete {
+            output.push_str(&format!("  {}\n", color_red("⚠ Rollback incomplete - manual cleanup needed")));
+        }
     }
 
     output.push_str(&format!("{}\n", color_dim(&"─".repeat(60))));
@@ -372,7 +539,19 @@ pub fn format_batch_result(batch: &crate::batch::BatchExecutionResult) -> String
         } else {
             output.push_str("  📝 Explanation: (AI explanation pending)\n");
         }
-        output.push_str("\n");
+        output.push('\n');
+    }
+
+    // Cross-step findings (only the ones worth a look)
+    let notable: Vec<&Finding> = batch.aggregate.findings.iter()
+        .filter(|f| f.importance.rank() <= Importance::Medium.rank())
+        .collect();
+    if !notable.is_empty() {
+        output.push_str(&format!("{}\n", color_yellow("🔎 FINDINGS ACROSS STEPS")));
+        for finding in notable {
+            output.push_str(&format_finding(finding));
+        }
+        output.push('\n');
     }
 
     // Summary
@@ -385,6 +564,10 @@ pub fn format_batch_result(batch: &crate::batch::BatchExecutionResult) -> String
         color_green(&format!("{}/{}", batch.successful, batch.total_commands))
     ));
 
+    if !batch.aggregate.needs_attention.is_empty() {
+        output.push_str(&format!("{}\n", color_yellow(&batch.aggregate.summary)));
+    }
+
     if batch.failed > 0 {
         output.push_str(&format!(
             "✗ {} failed\n",
@@ -392,5 +575,97 @@ pub fn format_batch_result(batch: &crate::batch::BatchExecutionResult) -> String
         ));
     }
 
+    if let Some(step) = batch.awaiting_input {
+        output.push_str(&format!(
+            "{}\n",
+            color_magenta(&format!(
+                "⌨ Step {} is waiting for input - answer with resume_batch {{\"batch_id\": \"{}\", \"input\": \"...\"}}",
+                step, batch.batch_id
+            ))
+        ));
+    }
+
+    if (batch.failed > 0 || batch.timed_out > 0) && !batch.batch_id.is_empty() {
+        output.push_str(&format!(
+            "{}\n",
+            color_dim(&format!("↻ Fix the problem, then resume with resume_batch {{\"batch_id\": \"{}\"}}", batch.batch_id))
+        ));
+    }
+
+    if batch.timed_out > 0 {
+        output.push_str(&format!(
+            "⏱ {} timed out\n",
+            color_yellow(&batch.timed_out.to_string())
+        ));
+    }
+
+    if batch.skipped > 0 {
+        output.push_str(&format!(
+            "⊘ {} skipped\n",
+            color_dim(&batch.skipped.to_string())
+        ));
+    }
+
+    if batch.ignored_failures > 0 {
+        output.push_str(&format!(
+            "⚠ {} failed with ignore_failure\n",
+            color_yellow(&batch.ignored_failures.to_string())
+        ));
+    }
+
+    if batch.wall_time_saved_ms > 0 {
+        output.push_str(&format!(
+            "⚡ {:.1}s saved by parallel groups\n",
+            batch.wall_time_saved_ms as f64 / 1000.0
+        ));
+    }
+
+    output
+}
+
+/// Format a batch dry-run (pre-flight) report
+pub fn format_dry_run(report: &crate::batch::DryRunReport) -> String {
+    let mut output = String::new();
+
+    output.push('\n');
+    output.push_str(&format!("{}\n", color_cyan("🔍 Batch dry run - nothing was executed")));
+    output.push_str(&format!("{}\n\n", color_dim(&"─".repeat(60))));
+
+    for step in &report.steps {
+        let risk = match step.risk {
+            RiskLevel::Low => color_green("(low risk)"),
+            RiskLevel::Medium => color_yellow("(medium risk)"),
+            RiskLevel::High => color_red("(high risk)"),
+        };
+        output.push_str(&format!(
+            "{} {}\n",
+            color_cyan(&format!("[{}/{}] {}", step.index, report.total_commands, step.command)),
+            risk
+        ));
+
+        if let Some(error) = &step.validation_error {
+            output.push_str(&format!("  {}\n", color_red(&format!("✗ {}", error))));
+        }
+        if !step.binary_available {
+            output.push_str(&format!("  {}\n", color_red(&format!("✗ '{}' not found in PATH", step.binary))));
+        }
+        if step.risk != RiskLevel::Low {
+            output.push_str(&format!("  {}\n", color_dim(&step.risk_reason)));
+        }
+        for var in &step.variables {
+            match &var.value {
+                Some(value) => output.push_str(&format!("  ${} = {}\n", var.name, value)),
+                None => output.push_str(&format!("  {}\n", color_yellow(&format!("${} is not set", var.name)))),
+            }
+        }
+    }
+
+    output.push_str(&format!("\n{}\n", color_dim(&"─".repeat(60))));
+    if report.ok_to_run {
+        output.push_str(&format!("{}\n", color_green(&format!("✓ {}", report.summary))));
+    } else {
+        output.push_str(&format!("{}\n", color_red(&format!("✗ {}", report.summary))));
+    }
+
     output
 }
@@ -0,0 +1,371 @@
+// gateway.rs - REST gateway for clients that don't speak the socket protocol
+// With the `http` cargo feature and `[server.http] enabled = true`, the daemon also listens on
+// `[server.http] listen`:
+//
+//   POST /v1/actions/{action}   JSON body = the request's `data`, reply = the socket reply
+//   GET  /v1/actions/{action}   the same without data (health, stats, introspect, ...)
+//                               Streaming actions (`monitor`) answer with server-sent events instead:
+//                               one `sample` event per line, then `done` (or `error`)
+//   GET  /v1/events             server-sent events from the event log (`since`, `kinds`, `session`
+//                               query parameters; Last-Event-ID resumes after a reconnect)
+//
+// Every request needs `Authorization: Bearer <token>` with the contents of `[server.http] token_file`
+// and runs with `[server.http] access`. Actions aren't handled here: each one is queued for the main
+// thread like a socket request, over a socket pair, so audit, throttling, confirmation, timings and
+// redaction all apply unchanged. The HTTP status follows the reply's `error_code`.
+
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::sse::{Event as SseEvent, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use archy_protocol::Request;
+use crate::config::Config;
+use crate::errors::ErrorKind;
+use crate::eventlog::{self, EventQuery, Kind};
+use crate::introspect;
+use crate::supervisor::{self, Incoming};
+
+/// How often an SSE stream looks for new events
+const POLL: Duration = Duration::from_millis(250);
+
+/// Runtime worker threads - requests mostly wait for the main thread anyway
+const WORKERS: usize = 2;
+
+/// Actions that reply with one JSON object per line until they're done
+const STREAMING_ACTIONS: &[&str] = &["monitor"];
+
+struct Gateway {
+    config: Config,
+    token: String,
+}
+
+/// Bind `[server.http] listen` and serve it on a thread of its own
+pub fn spawn(config: &Config) -> Result<(), String> {
+    let token = read_token(config)?;
+    let listener = std::net::TcpListener::bind(&config.http_listen)
+        .and_then(|listener| listener.set_nonblocking(true).map(|()| listener))
+        .map_err(|e| format!("Cannot listen on {}: {}", config.http_listen, e))?;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(WORKERS)
+        .thread_name("archy-http")
+        .enable_all()
+        .build()
+        .map_err(|e| format!("Cannot start the HTTP runtime: {}", e))?;
+
+    let gateway = Arc::new(Gateway { config: config.clone(), token });
+    let app = Router::new()
+        .route("/v1/actions/{action}", get(action_get).post(action_post))
+        .route("/v1/events", get(events))
+        .layer(DefaultBodyLimit::max(config.http_request_limit()))
+        .with_state(gateway);
+    tracing::info!(listen = %config.http_listen, access = config.http_access.as_str(), "HTTP gateway listening");
+
+    std::thread::spawn(move || {
+        runtime.block_on(async move {
+            let served = match tokio::net::TcpListener::from_std(listener) {
+                Ok(listener) => axum::serve(listener, app).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = served {
+                tracing::error!("HTTP gateway stopped: {}", e);
+            }
+        });
+    });
+    Ok(())
+}
+
+/// The bearer token - refused when other users could read it
+fn read_token(config: &Config) -> Result<String, String> {
+    let path = config.http_token_file.as_deref().ok_or("server.http.token_file is not set")?;
+    let meta = fs::metadata(path).map_err(|e| format!("Cannot read token file {}: {}", path, e))?;
+    if meta.permissions().mode() & 0o077 != 0 {
+        return Err(format!("Token file {} is accessible to other users - chmod 600 it", path));
+    }
+    let token = fs::read_to_string(path).map_err(|e| format!("Cannot read token file {}: {}", path, e))?;
+    let token = token.trim().to_string();
+    if token.is_empty() {
+        return Err(format!("Token file {} is empty", path));
+    }
+    Ok(token)
+}
+
+/// Whether the request carries the token (compared in constant time)
+fn authenticated(headers: &HeaderMap, token: &str) -> bool {
+    let presented = headers.get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or("");
+    presented.len() == token.len()
+        && presented.bytes().zip(token.bytes()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// The status for a reply's `error_code`
+fn status_for(reply: &Value) -> StatusCode {
+    if reply["success"] != false {
+        return StatusCode::OK;
+    }
+    match reply["error_code"].as_str().unwrap_or("") {
+        "validation" | "parse" => StatusCode::BAD_REQUEST,
+        "denied" => StatusCode::FORBIDDEN,
+        "unknown_action" | "session_missing" => StatusCode::NOT_FOUND,
+        "timeout" => StatusCode::GATEWAY_TIMEOUT,
+        "tmux_unavailable" => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+fn error_response(status: StatusCode, kind: ErrorKind, message: &str) -> Response {
+    (status, Json(archy_protocol::error_reply(kind, message))).into_response()
+}
+
+fn unauthorized() -> Response {
+    error_response(StatusCode::UNAUTHORIZED, ErrorKind::Denied, "Missing or wrong bearer token")
+}
+
+async fn action_get(State(gateway): State<Arc<Gateway>>, Path(action): Path<String>, headers: HeaderMap) -> Response {
+    if !authenticated(&headers, &gateway.token) {
+        return unauthorized();
+    }
+    dispatch(action, Value::Null, id_header(&headers), gateway).await
+}
+
+async fn action_post(State(gateway): State<Arc<Gateway>>, Path(action): Path<String>, headers: HeaderMap, body: Bytes) -> Response {
+    if !authenticated(&headers, &gateway.token) {
+        return unauthorized();
+    }
+    let data = if body.iter().all(u8::is_ascii_whitespace) {
+        Value::Null
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(data) => data,
+            Err(e) => return error_response(StatusCode::BAD_REQUEST, ErrorKind::Parse, &format!("Invalid JSON: {}", e)),
+        }
+    };
+    dispatch(action, data, id_header(&headers), gateway).await
+}
+
+/// `X-Request-Id`, echoed in the reply's `id` like a socket request's
+fn id_header(headers: &HeaderMap) -> Option<Value> {
+    headers.get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .map(|id| Value::String(id.to_string()))
+}
+
+/// Queue the request for the main thread and wait for its reply
+async fn dispatch(action: String, data: Value, id: Option<Value>, gateway: Arc<Gateway>) -> Response {
+    let (mut ours, theirs) = match UnixStream::pair() {
+        Ok(pair) => pair,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorKind::Io, &format!("Cannot queue the request: {}", e)),
+    };
+    let streaming = STREAMING_ACTIONS.contains(&action.as_str());
+    let incoming = Incoming {
+        stream: theirs,
+        peer: None,
+        accepted: Instant::now(),
+        read: Duration::ZERO,
+        request: Ok(Request { action, data, id }),
+        granted: Some(gateway.config.http_access),
+    };
+    // Like the socket acceptor: `introspect` is answered right away, the rest queues
+    if let Some(incoming) = introspect::answer_now(incoming, &gateway.config) {
+        supervisor::submit(incoming);
+    }
+    if streaming {
+        return stream_reply(ours).await;
+    }
+    // The handler closes its end after the reply
+    let reply = tokio::task::spawn_blocking(move || {
+        let mut reply = Vec::new();
+        ours.read_to_end(&mut reply).map(|_| reply)
+    })
+    .await;
+    match reply {
+        Ok(Ok(reply)) => match serde_json::from_slice::<Value>(&reply) {
+            Ok(reply) => (status_for(&reply), Json(reply)).into_response(),
+            Err(_) if reply.is_empty() => error_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorKind::Failed, "The daemon closed the request without a reply"),
+            Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorKind::Parse, &format!("Unreadable reply: {}", e)),
+        },
+        Ok(Err(e)) => error_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorKind::Io, &format!("Cannot read the reply: {}", e)),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorKind::Failed, &format!("Reply reader failed: {}", e)),
+    }
+}
+
+/// Forward a line-streaming reply as server-sent events. A failure in the first line (bad options, too
+/// many streams) is answered as plain JSON with its status, like any other reply
+async fn stream_reply(reply: UnixStream) -> Response {
+    let mut lines = Box::pin(reply_lines(reply));
+    let first = match lines.next().await {
+        Some(first) => first,
+        None => return error_response(StatusCode::INTERNAL_SERVER_ERROR, ErrorKind::Failed, "The daemon closed the request without a reply"),
+    };
+    if first["success"] == false {
+        return (status_for(&first), Json(first)).into_response();
+    }
+    let events = stream::once(async { first })
+        .chain(lines)
+        .map(|line| Ok::<_, Infallible>(SseEvent::default().event(line_event(&line)).data(line.to_string())));
+    // Dropping the stream when the HTTP client goes away closes our end, which stops the sender
+    Sse::new(events).keep_alive(KeepAlive::default()).into_response()
+}
+
+/// The lines of a streaming reply, parsed - ends at EOF or at a line that isn't JSON
+fn reply_lines(reply: UnixStream) -> impl Stream<Item = Value> {
+    stream::unfold(BufReader::new(reply), |mut reader| async move {
+        let read = tokio::task::spawn_blocking(move || loop {
+            let mut line = String::new();
+            match reader.read_line(&mut line) {
+                Ok(0) | Err(_) => return None,
+                Ok(_) if line.trim().is_empty() => continue,
+                Ok(_) => return serde_json::from_str::<Value>(&line).ok().map(|value| (value, reader)),
+            }
+        })
+        .await;
+        read.ok().flatten()
+    })
+}
+
+/// SSE event name for a line of a streaming reply
+fn line_event(line: &Value) -> &'static str {
+    if line["success"] == false {
+        "error"
+    } else if line.get("done").is_some() {
+        "done"
+    } else {
+        "sample"
+    }
+}
+
+/// `/v1/events` query parameters - all optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EventParams {
+    since: Option<u64>,
+    kinds: Option<String>, // comma-separated, e.g. "session_created,session_killed"
+    session: Option<String>,
+}
+
+fn parse_kinds(kinds: &str) -> Result<Vec<Kind>, String> {
+    kinds.split(',')
+        .map(str::trim)
+        .filter(|kind| !kind.is_empty())
+        .map(|kind| serde_json::from_value(Value::String(kind.to_string())).map_err(|_| format!("Unknown event kind {:?}", kind)))
+        .collect()
+}
+
+async fn events(
+    State(gateway): State<Arc<Gateway>>,
+    Query(params): Query<EventParams>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<SseEvent, Infallible>>>, Response> {
+    if !authenticated(&headers, &gateway.token) {
+        return Err(unauthorized());
+    }
+    // Same gates as `get_events` over the socket
+    supervisor::authorize("get_events", None, Some(gateway.config.http_access), &gateway.config)
+        .and_then(|()| gateway.config.features.check_action("get_events"))
+        .map_err(|e| error_response(StatusCode::FORBIDDEN, ErrorKind::Denied, &e))?;
+    let kinds = match params.kinds.as_deref().map(parse_kinds).transpose() {
+        Ok(kinds) => kinds,
+        Err(e) => return Err(error_response(StatusCode::BAD_REQUEST, ErrorKind::Validation, &e)),
+    };
+    // A reconnecting EventSource resumes after the last event it saw
+    let resume = headers.get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|id| id.parse::<u64>().ok());
+    let since = resume.or(params.since).unwrap_or_else(|| eventlog::query(&EventQuery::default()).next);
+
+    let query = EventQuery { since, limit: None, kinds, session: params.session };
+    let events = stream::unfold((query, VecDeque::new()), |(mut query, mut pending)| async move {
+        loop {
+            if let Some(event) = pending.pop_front() {
+                return Some((Ok(event), (query, pending)));
+            }
+            let page = eventlog::query(&query);
+            if page.missed > 0 {
+                pending.push_back(SseEvent::default().event("missed").data(page.missed.to_string()));
+            }
+            pending.extend(page.events.iter().filter_map(|event| {
+                SseEvent::default().id(event.seq.to_string()).event(kind_name(event.kind)).json_data(event).ok()
+            }));
+            query.since = page.next;
+            if pending.is_empty() {
+                tokio::time::sleep(POLL).await;
+            }
+        }
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// The snake_case name clients filter on
+fn kind_name(kind: Kind) -> String {
+    serde_json::to_value(kind).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_auth_status_and_kinds() {
+        let mut headers = HeaderMap::new();
+        assert!(!authenticated(&headers, "s3cret"));
+        headers.insert(header::AUTHORIZATION, "Bearer s3cre".parse().unwrap());
+        assert!(!authenticated(&headers, "s3cret"));
+        headers.insert(header::AUTHORIZATION, "Bearer s3cret".parse().unwrap());
+        assert!(authenticated(&headers, "s3cret"));
+
+        assert_eq!(status_for(&json!({"success": true})), StatusCode::OK);
+        assert_eq!(status_for(&json!({"success": false, "error_code": "denied"})), StatusCode::FORBIDDEN);
+        assert_eq!(status_for(&json!({"success": false, "error_code": "unknown_action"})), StatusCode::NOT_FOUND);
+        assert_eq!(status_for(&json!({"success": false})), StatusCode::INTERNAL_SERVER_ERROR);
+
+        assert_eq!(parse_kinds("session_created, batch_finished,").unwrap(), vec![Kind::SessionCreated, Kind::BatchFinished]);
+        assert!(parse_kinds("session_exploded").is_err());
+        assert_eq!(kind_name(Kind::EmergencyStop), "emergency_stop");
+    }
+
+    #[test]
+    fn test_streaming_reply() {
+        use std::io::Write;
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+
+        // Samples become events until the stream ends
+        let (mut daemon, ours) = UnixStream::pair().unwrap();
+        std::thread::spawn(move || {
+            daemon.write_all(b"{\"success\":true,\"seq\":1}\n\n{\"success\":true,\"seq\":2}\n").unwrap();
+            daemon.write_all(b"{\"success\":true,\"done\":true,\"samples\":2}\n").unwrap();
+        });
+        let lines: Vec<Value> = runtime.block_on(reply_lines(ours).collect());
+        assert_eq!(lines.iter().map(line_event).collect::<Vec<_>>(), vec!["sample", "sample", "done"]);
+        assert_eq!(lines[1]["seq"], 2);
+
+        let (mut daemon, ours) = UnixStream::pair().unwrap();
+        daemon.write_all(b"{\"success\":true,\"seq\":1}\n").unwrap();
+        drop(daemon);
+        let response = runtime.block_on(stream_reply(ours));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/event-stream");
+
+        // A refused stream is a plain JSON error with its status
+        let (mut daemon, ours) = UnixStream::pair().unwrap();
+        daemon.write_all(archy_protocol::error_reply(ErrorKind::Validation, "interval_ms too small").to_string().as_bytes()).unwrap();
+        drop(daemon);
+        assert_eq!(runtime.block_on(stream_reply(ours)).status(), StatusCode::BAD_REQUEST);
+        assert_eq!(line_event(&archy_protocol::error_reply(ErrorKind::Failed, "x")), "error");
+    }
+}
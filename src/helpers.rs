@@ -11,17 +11,29 @@ use std::io::Write;
 /// Security helpers - Input validation and output sanitization
 pub mod security {
     use super::*;
-
-    /// Safely serialize and send JSON response, prevents unwrap() panics (FIX #1)
-    pub fn safe_json_response(response: &Response, stream: &mut UnixStream) -> std::io::Result<()> {
-        match serde_json::to_string(response) {
+    use std::fs;
+    use std::path::{Component, Path, PathBuf};
+
+    /// Send a reply and close the stream - a reply that can't be serialized becomes a generic error
+    pub fn safe_json_response<T: Serialize>(response: &T, stream: &mut UnixStream) -> std::io::Result<()> {
+        let _format = crate::timings::enter(crate::timings::Phase::Format);
+        match serde_json::to_value(response).and_then(|value| {
+            let value = crate::stamp_reply(crate::events::outbound(value));
+            crate::audit::record_reply(&value);
+            crate::timings::record_reply(&value);
+            serde_json::to_string(&value)
+        }) {
             Ok(json) => {
+                // Secret values a command echoed back never leave the daemon (object keys included)
+                let json = crate::secrets::redact(&json);
+                let _write = crate::timings::enter(crate::timings::Phase::Write);
+                crate::timings::wrote(json.len());
                 stream.write_all(json.as_bytes())?;
                 stream.flush()?;
             }
             Err(e) => {
-                eprintln!("❌ JSON serialization failed: {}", e);
-                let fallback = r#"{"success":false,"error":"Internal serialization error"}"#;
+                tracing::warn!("JSON serialization error: {}", e);
+                let fallback = r#"{"success":false,"output":null,"error":"Internal serialization error","exists":null}"#;
                 let _ = stream.write_all(fallback.as_bytes());
                 let _ = stream.flush();
             }
@@ -48,30 +60,6 @@ pub mod security {
          .replace('$', "\\$")
     }
 
-    /// Validate that a path is safe to execute (prevents command injection) (FIX #6)
-    pub fn is_safe_executable_path(path: &str) -> bool {
-        // Allowed prefixes for executables
-        let allowed_prefixes = ["/usr/", "/bin/", "/usr/local/bin/", "/opt/", "/snap/bin/"];
-        let is_allowed_prefix = allowed_prefixes.iter().any(|prefix| path.starts_with(prefix));
-
-        if !is_allowed_prefix {
-            return false;
-        }
-
-        // Verify file exists and is executable
-        if let Ok(metadata) = std::fs::metadata(path) {
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::PermissionsExt;
-                return metadata.permissions().mode() & 0o111 != 0;
-            }
-            #[cfg(not(unix))]
-            return true;
-        }
-
-        false
-    }
-
     /// Validate command for dangerous patterns
     pub fn validate_command(command: &str) -> Result<(), String> {
         // Check for null bytes (common injection vector)
@@ -104,6 +92,104 @@ pub mod security {
         Ok(())
     }
 
+    /// Reject commands containing any of the configured `[security] blocked_patterns`
+    pub fn check_blocked_patterns(command: &str, patterns: &[String]) -> Result<(), String> {
+        let command_lower = command.to_lowercase();
+        match patterns.iter().find(|p| !p.is_empty() && command_lower.contains(&p.to_lowercase())) {
+            Some(pattern) => Err(format!("Blocked by security policy: {}", pattern)),
+            None => Ok(()),
+        }
+    }
+
+    /// Quote a value as a single POSIX shell word
+    pub fn shell_quote(value: &str) -> String {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
+
+    /// Like `shell_quote`, but words the shell can't misread are left bare (keeps commands readable)
+    pub fn quote_word(value: &str) -> String {
+        let plain = !value.is_empty()
+            && value.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+        if plain { value.to_string() } else { shell_quote(value) }
+    }
+
+    /// Shell line running exactly `argv` - every argument is one word, nothing is expanded or
+    /// interpreted. Newlines are rejected because tmux would type them as Enter
+    pub fn quote_argv(argv: &[String]) -> Result<String, String> {
+        if argv.first().is_none_or(|program| program.trim().is_empty()) {
+            return Err("'argv' needs at least a program name".to_string());
+        }
+        if argv.iter().any(|arg| arg.contains(['\n', '\r', '\0'])) {
+            return Err("'argv' arguments can't contain newlines or NUL bytes".to_string());
+        }
+        Ok(argv.iter().map(|arg| quote_word(arg)).collect::<Vec<_>>().join(" "))
+    }
+
+    fn expand_home(path: &str) -> String {
+        match (path.strip_prefix('~'), std::env::var("HOME")) {
+            (Some(rest), Ok(home)) if rest.is_empty() || rest.starts_with('/') => format!("{}{}", home, rest),
+            _ => path.to_string(),
+        }
+    }
+
+    /// Canonical forms of configured directories (`~` expanded). A directory that doesn't exist can't
+    /// contain anything, so it's simply left out
+    pub fn canonical_dirs(dirs: &[String]) -> Vec<String> {
+        dirs.iter()
+            .filter_map(|dir| fs::canonicalize(expand_home(dir)).ok())
+            .map(|dir| dir.to_string_lossy().to_string())
+            .collect()
+    }
+
+    /// Where file-touching actions may read or write (`[filesystem]`). Every rule is applied to the
+    /// canonical path, so `..` and symlinks can't walk a handler out of its roots
+    #[derive(Debug)]
+    pub struct PathPolicy {
+        allowed_roots: Vec<PathBuf>,
+        denied: Vec<glob::Pattern>,
+        max_depth: usize,
+    }
+
+    impl PathPolicy {
+        pub fn new(allowed_roots: &[String], denied: &[String], max_depth: usize) -> Result<Self, String> {
+            let denied = denied.iter()
+                .map(|p| glob::Pattern::new(&expand_home(p)).map_err(|e| format!("invalid denied path {:?}: {}", p, e)))
+                .collect::<Result<_, _>>()?;
+            let allowed_roots = canonical_dirs(allowed_roots).into_iter().map(PathBuf::from).collect();
+            Ok(PathPolicy { allowed_roots, denied, max_depth })
+        }
+
+        /// Canonical form of `path` if the policy allows it. A file that doesn't exist yet (for
+        /// writes) is resolved through its parent directory
+        pub fn check(&self, path: &Path) -> Result<PathBuf, String> {
+            let canonical = match fs::canonicalize(path) {
+                Ok(canonical) => canonical,
+                Err(_) => match (path.parent(), path.file_name()) {
+                    (Some(parent), Some(name)) => fs::canonicalize(parent)
+                        .map(|parent| parent.join(name))
+                        .map_err(|e| format!("Cannot resolve {}: {}", path.display(), e))?,
+                    _ => return Err(format!("Cannot resolve {}", path.display())),
+                },
+            };
+
+            let depth = canonical.components().filter(|c| matches!(c, Component::Normal(_))).count();
+            if depth > self.max_depth {
+                return Err(format!("Access denied: {} is nested deeper than {} levels", canonical.display(), self.max_depth));
+            }
+            if !self.allowed_roots.iter().any(|root| canonical.starts_with(root)) {
+                return Err(format!("Access denied: {} is outside the allowed roots", canonical.display()));
+            }
+            // Denied globs see both spellings - a symlinked ~/.ssh is still ~/.ssh
+            let options = glob::MatchOptions { require_literal_separator: true, ..glob::MatchOptions::new() };
+            let denied = self.denied.iter()
+                .find(|p| p.matches_path_with(&canonical, options) || p.matches_path_with(path, options));
+            if let Some(pattern) = denied {
+                return Err(format!("Access denied: {} matches denied path {}", canonical.display(), pattern));
+            }
+            Ok(canonical)
+        }
+    }
+
     /// Validate desktop entry name to prevent directory traversal
     pub fn validate_desktop_entry(entry: &str) -> Result<(), String> {
         if entry.contains('/') || entry.contains("..") || entry.contains('\0') {
@@ -141,16 +227,6 @@ pub mod response {
         }
     }
 
-    /// Create a successful response without output
-    pub fn success_empty() -> Response {
-        Response {
-            success: true,
-            output: None,
-            error: None,
-            exists: None,
-        }
-    }
-
     /// Create an error response
     pub fn error(message: String) -> Response {
         Response {
@@ -192,20 +268,6 @@ pub mod params {
             .ok_or_else(|| format!("Missing required parameter: {}", key))
     }
 
-    /// Extract optional string parameter
-    pub fn extract_string_opt(data: &Value, key: &str) -> Option<String> {
-        data.get(key)
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string())
-    }
-
-    /// Extract integer parameter with default
-    pub fn extract_i64(data: &Value, key: &str, default: i64) -> i64 {
-        data.get(key)
-            .and_then(|v| v.as_i64())
-            .unwrap_or(default)
-    }
-
     /// Extract u64 parameter with default
     pub fn extract_u64(data: &Value, key: &str, default: u64) -> u64 {
         data.get(key)
@@ -213,36 +275,52 @@ pub mod params {
             .unwrap_or(default)
     }
 
-    /// Extract boolean parameter with default
-    pub fn extract_bool(data: &Value, key: &str, default: bool) -> bool {
-        data.get(key)
-            .and_then(|v| v.as_bool())
-            .unwrap_or(default)
-    }
 }
 
-/// String manipulation helpers
-pub mod strings {
-    /// Check if string is empty or whitespace
-    pub fn is_blank(s: &str) -> bool {
-        s.trim().is_empty()
-    }
-
-    /// Sanitize command string (remove dangerous characters)
-    pub fn sanitize_command(cmd: &str) -> String {
-        cmd.replace('\0', "")
-           .replace('\r', "")
-           .trim()
-           .to_string()
-    }
-
-    /// Truncate string to max length
-    pub fn truncate(s: &str, max_len: usize) -> String {
-        if s.len() <= max_len {
-            s.to_string()
-        } else {
-            format!("{}...", &s[..max_len.saturating_sub(3)])
+/// Running helper programs to completion
+pub mod process {
+    use std::io::Read;
+    use std::process::{Command, Stdio};
+    use std::time::{Duration, Instant};
+
+    /// Run `argv` with the C locale and wait up to `timeout`: (exit code, stdout, stderr). Output is
+    /// read as it comes, so a long listing can't fill the pipe and stall the program
+    pub fn run(argv: &[String], timeout: Duration) -> Result<(i32, String, String), String> {
+        let program = argv.first().ok_or("Empty command")?;
+        let mut child = Command::new(program)
+            .args(&argv[1..])
+            .env("LC_ALL", "C")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Cannot run {}: {}", program, e))?;
+        fn drain(pipe: Option<impl Read + Send + 'static>) -> std::thread::JoinHandle<String> {
+            std::thread::spawn(move || {
+                let mut bytes = Vec::new();
+                if let Some(mut pipe) = pipe {
+                    let _ = pipe.read_to_end(&mut bytes);
+                }
+                String::from_utf8_lossy(&bytes).to_string()
+            })
         }
+        let stdout = drain(child.stdout.take());
+        let stderr = drain(child.stderr.take());
+
+        let started = Instant::now();
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if started.elapsed() < timeout => std::thread::sleep(Duration::from_millis(50)),
+                Ok(None) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(format!("{} timed out after {}s", argv.join(" "), timeout.as_secs()));
+                }
+                Err(e) => return Err(format!("Cannot wait for {}: {}", program, e)),
+            }
+        };
+        Ok((status.code().unwrap_or(-1), stdout.join().unwrap_or_default(), stderr.join().unwrap_or_default()))
     }
 }
 
@@ -250,48 +328,6 @@ pub mod strings {
 pub mod environment {
     use std::process::Command;
 
-    /// Detect the correct DISPLAY for the current session
-    /// First checks env var, then queries systemd user environment, then searches for active X displays
-    pub fn get_display() -> String {
-        // First, check if DISPLAY is already set in environment
-        if let Ok(display) = std::env::var("DISPLAY") {
-            if !display.is_empty() {
-                return display;
-            }
-        }
-
-        // Try to get DISPLAY from systemd user environment (for services)
-        if let Ok(output) = Command::new("systemctl")
-            .args(&["--user", "show-environment"])
-            .output()
-        {
-            if let Ok(env_output) = String::from_utf8(output.stdout) {
-                for line in env_output.lines() {
-                    if line.starts_with("DISPLAY=") {
-                        if let Some(display) = line.strip_prefix("DISPLAY=") {
-                            if !display.is_empty() {
-                                return display.to_string();
-                            }
-                        }
-                    }
-                }
-            }
-        }
-
-        // Try to detect active X server display
-        // Check common X socket locations
-        for display_num in &["0", "1", "2", "10", "99"] {
-            let socket_path = format!("/tmp/.X11-unix/X{}", display_num);
-            if std::path::Path::new(&socket_path).exists() {
-                return format!(":{}", display_num);
-            }
-        }
-
-
-        // Fallback
-        ":0".to_string()
-    }
-
     /// Get XAUTHORITY file location
     pub fn get_xauthority() -> String {
         std::env::var("XAUTHORITY").unwrap_or_else(|_| {
@@ -299,37 +335,6 @@ pub mod environment {
         })
     }
 
-    /// Get WAYLAND_DISPLAY for Wayland sessions
-    pub fn get_wayland_display() -> String {
-        // Check environment variable first
-        if let Ok(display) = std::env::var("WAYLAND_DISPLAY") {
-            if !display.is_empty() {
-                return display;
-            }
-        }
-
-        // Try to get from systemd user environment
-        if let Ok(output) = Command::new("systemctl")
-            .args(&["--user", "show-environment"])
-            .output()
-        {
-            if let Ok(env_output) = String::from_utf8(output.stdout) {
-                for line in env_output.lines() {
-                    if line.starts_with("WAYLAND_DISPLAY=") {
-                        if let Some(display) = line.strip_prefix("WAYLAND_DISPLAY=") {
-                            if !display.is_empty() {
-                                return display.to_string();
-                            }
-                        }
-                    }
-                }
-            }
-        }
-
-        // Fallback
-        "wayland-0".to_string()
-    }
-
     /// Get proper DBUS address for the current session
     pub fn get_dbus_address() -> String {
         // Try to get from environment
@@ -341,7 +346,7 @@ pub mod environment {
 
         // Try to get from systemd user environment
         if let Ok(output) = Command::new("systemctl")
-            .args(&["--user", "show-environment"])
+            .args(["--user", "show-environment"])
             .output()
         {
             if let Ok(env_output) = String::from_utf8(output.stdout) {
@@ -382,18 +387,152 @@ pub mod environment {
             .unwrap_or_else(|_| "1000".to_string()); // Common default UID
         format!("unix:path=/run/user/{}/bus", uid)
     }
+
+    /// Which display server the user's session runs
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum DisplayServer {
+        Wayland,
+        X11,
+        Unknown,
+    }
+
+    /// The graphical session GUI programs are started into
+    #[derive(Debug, Clone, PartialEq, serde::Serialize)]
+    pub struct Session {
+        pub server: DisplayServer,
+        pub compositor: Option<String>, // hyprland, sway, niri, gnome, kde, ... (lowercase)
+        pub wayland_display: Option<String>, // only if its socket exists
+        pub display: Option<String>, // X11, or XWayland under Wayland
+        pub xwayland: bool, // a Wayland session that can run X11 programs too
+    }
+
+    impl Session {
+        /// Read from our environment and the systemd user environment. Wayland needs a live socket
+        /// in XDG_RUNTIME_DIR, so a stale WAYLAND_DISPLAY left in the service environment doesn't count
+        pub fn detect() -> Session {
+            let runtime_dir = session_var("XDG_RUNTIME_DIR").unwrap_or_else(|| format!("/run/user/{}", crate::peer::own_uid()));
+            let wayland_display = session_var("WAYLAND_DISPLAY").filter(|name| {
+                let socket = std::path::Path::new(name);
+                if socket.is_absolute() { socket.exists() } else { std::path::Path::new(&runtime_dir).join(socket).exists() }
+            });
+            let display = session_var("DISPLAY").or_else(|| {
+                // No DISPLAY exported - an X server with a socket is still one
+                (0..10).map(|n| format!(":{}", n)).find(|d| std::path::Path::new(&format!("/tmp/.X11-unix/X{}", &d[1..])).exists())
+            });
+            Session::from_parts(
+                session_var("XDG_SESSION_TYPE").as_deref(),
+                wayland_display,
+                display,
+                compositor(session_var("XDG_CURRENT_DESKTOP").as_deref()),
+            )
+        }
+
+        pub(crate) fn from_parts(session_type: Option<&str>, wayland_display: Option<String>, display: Option<String>, compositor: Option<String>) -> Session {
+            let server = match session_type {
+                _ if wayland_display.is_some() => DisplayServer::Wayland,
+                Some("wayland") => DisplayServer::Wayland,
+                Some("x11") => DisplayServer::X11,
+                _ if display.is_some() => DisplayServer::X11,
+                _ => DisplayServer::Unknown,
+            };
+            Session {
+                xwayland: server == DisplayServer::Wayland && display.is_some(),
+                server,
+                compositor,
+                wayland_display,
+                display,
+            }
+        }
+
+        pub fn is_wayland(&self) -> bool {
+            self.server == DisplayServer::Wayland
+        }
+
+        /// Whether a program using only X11 can show a window here
+        pub fn runs_x11(&self) -> bool {
+            self.server == DisplayServer::X11 || self.xwayland
+        }
+
+        /// Variables a GUI program started from the daemon needs - only the ones that hold, so an X11
+        /// desktop isn't handed a WAYLAND_DISPLAY that sends toolkits looking for a compositor
+        pub fn gui_env(&self) -> Vec<(&'static str, String)> {
+            let mut env = Vec::new();
+            if let Some(wayland) = &self.wayland_display {
+                env.push(("WAYLAND_DISPLAY", wayland.clone()));
+            }
+            if let Some(display) = &self.display {
+                env.push(("DISPLAY", display.clone()));
+                let xauthority = get_xauthority();
+                if std::path::Path::new(&xauthority).exists() {
+                    env.push(("XAUTHORITY", xauthority));
+                }
+            }
+            match self.server {
+                DisplayServer::Wayland => env.push(("XDG_SESSION_TYPE", "wayland".to_string())),
+                DisplayServer::X11 => env.push(("XDG_SESSION_TYPE", "x11".to_string())),
+                DisplayServer::Unknown => {}
+            }
+            env.push(("DBUS_SESSION_BUS_ADDRESS", get_dbus_address()));
+            for var in ["XDG_RUNTIME_DIR", "XDG_CURRENT_DESKTOP", "HYPRLAND_INSTANCE_SIGNATURE", "SWAYSOCK", "NIRI_SOCKET"] {
+                if let Some(value) = session_var(var) {
+                    env.push((var, value));
+                }
+            }
+            env
+        }
+    }
+
+    /// The compositor / desktop, by the sockets wlroots-style compositors export, else XDG_CURRENT_DESKTOP
+    fn compositor(current_desktop: Option<&str>) -> Option<String> {
+        for (var, name) in [("HYPRLAND_INSTANCE_SIGNATURE", "hyprland"), ("SWAYSOCK", "sway"), ("NIRI_SOCKET", "niri")] {
+            if session_var(var).is_some() {
+                return Some(name.to_string());
+            }
+        }
+        // "ubuntu:GNOME", "KDE", "X-Cinnamon" - the last entry names the desktop
+        current_desktop
+            .and_then(|desktops| desktops.split(':').rfind(|d| !d.is_empty()))
+            .map(|desktop| desktop.trim_start_matches("X-").to_lowercase())
+    }
+
+    /// A session variable (SWAYSOCK, HYPRLAND_INSTANCE_SIGNATURE, ...) from our environment, else from
+    /// the systemd user environment (for services). None if unset or empty in both
+    pub fn session_var(name: &str) -> Option<String> {
+        if let Some(value) = std::env::var(name).ok().filter(|v| !v.is_empty()) {
+            return Some(value);
+        }
+        let output = Command::new("systemctl")
+            .args(["--user", "show-environment"])
+            .output()
+            .ok()?;
+        let prefix = format!("{}=", name);
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .find_map(|line| line.strip_prefix(&prefix).map(str::to_string))
+            .filter(|value| !value.is_empty())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use serde_json::json;
+    use std::fs;
 
     #[test]
-    fn test_environment_detection() {
-        let display = environment::get_display();
-        assert!(!display.is_empty());
-        eprintln!("Detected DISPLAY: {}", display);
+    fn test_session_kind() {
+        use environment::{DisplayServer, Session};
+        let wayland = Session::from_parts(Some("wayland"), Some("wayland-1".to_string()), Some(":0".to_string()), Some("sway".to_string()));
+        assert_eq!(wayland.server, DisplayServer::Wayland);
+        assert!(wayland.xwayland && wayland.runs_x11());
+        // A stale WAYLAND_DISPLAY was already dropped - an X11 session stays X11
+        let x11 = Session::from_parts(Some("x11"), None, Some(":1".to_string()), None);
+        assert_eq!(x11.server, DisplayServer::X11);
+        assert!(x11.gui_env().iter().all(|(var, _)| *var != "WAYLAND_DISPLAY"));
+        let pure = Session::from_parts(None, Some("wayland-0".to_string()), None, None);
+        assert!(pure.is_wayland() && !pure.runs_x11());
+        assert_eq!(Session::from_parts(Some("tty"), None, None, None).server, DisplayServer::Unknown);
     }
 
     #[test]
@@ -424,16 +563,50 @@ mod tests {
             params::extract_string(&data, "command").unwrap(),
             "ls -la"
         );
-        assert_eq!(params::extract_i64(&data, "lines", 100), 200);
-        assert_eq!(params::extract_bool(&data, "verbose", false), true);
-        assert_eq!(params::extract_bool(&data, "missing", false), false);
+        assert!(params::extract_string(&data, "missing").is_err());
     }
 
     #[test]
-    fn test_sanitize_command() {
-        let dirty = "ls\0-la\r\n";
-        let clean = strings::sanitize_command(dirty);
-        assert_eq!(clean, "ls-la");
+    fn test_blocked_patterns() {
+        let patterns = vec!["curl | sh".to_string()];
+        assert!(security::check_blocked_patterns("CURL | SH http://x", &patterns).is_err());
+        assert!(security::check_blocked_patterns("ls -la", &patterns).is_ok());
+        assert!(security::check_blocked_patterns("ls", &[]).is_ok());
+    }
+
+    #[test]
+    fn test_path_policy() {
+        let root = std::env::temp_dir().join(format!("archy-paths-test-{}", std::process::id()));
+        let allowed = root.join("allowed");
+        fs::create_dir_all(allowed.join("keys")).unwrap();
+        fs::create_dir_all(root.join("outside")).unwrap();
+        fs::write(allowed.join("app.desktop"), "").unwrap();
+        fs::write(root.join("outside/secret"), "").unwrap();
+        std::os::unix::fs::symlink(root.join("outside/secret"), allowed.join("link")).unwrap();
+
+        let roots = [allowed.to_string_lossy().to_string()];
+        let denied = [format!("{}/keys/*", allowed.display())];
+        let policy = security::PathPolicy::new(&roots, &denied, 32).unwrap();
+
+        assert!(policy.check(&allowed.join("app.desktop")).is_ok());
+        assert!(policy.check(&allowed.join("new-file")).is_ok());
+        assert!(policy.check(&allowed.join("../outside/secret")).unwrap_err().contains("outside"));
+        assert!(policy.check(&allowed.join("link")).unwrap_err().contains("outside"));
+        assert!(policy.check(&allowed.join("keys/id_rsa")).unwrap_err().contains("denied path"));
+
+        let shallow = security::PathPolicy::new(&roots, &[], 1).unwrap();
+        assert!(shallow.check(&allowed.join("app.desktop")).unwrap_err().contains("deeper"));
+        assert!(security::PathPolicy::new(&roots, &["[".to_string()], 32).is_err());
+
+        let _ = fs::remove_dir_all(&root);
     }
-}
 
+    #[test]
+    fn test_quote_argv() {
+        let argv: Vec<String> = ["grep", "-r", "it's $(id)", "src/"].map(String::from).to_vec();
+        assert_eq!(security::quote_argv(&argv).unwrap(), r"grep -r 'it'\''s $(id)' src/");
+        assert_eq!(security::quote_word(""), "''");
+        assert!(security::quote_argv(&[]).is_err());
+        assert!(security::quote_argv(&["echo".to_string(), "a\nb".to_string()]).is_err());
+    }
+}
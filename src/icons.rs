@@ -0,0 +1,335 @@
+// icons.rs - Icon theme lookup (Freedesktop Icon Theme Specification 0.13)
+// Resolves a desktop entry's Icon= to a file, so frontends get a path instead of redoing the lookup:
+//
+//   - an absolute Icon is taken as it is, if the file exists
+//   - otherwise the name is looked up in the user's theme, then the themes it Inherits (depth first),
+//     then hicolor, then the pixmaps directory. In each theme a directory of the wanted size and scale
+//     wins; failing that the closest size does
+//   - themes are searched for in $HOME/.icons, $XDG_DATA_HOME/icons, each $XDG_DATA_DIRS /icons and the
+//     flatpak exports; a theme's index.theme is read from the first base directory that has one
+//   - the user's theme is the request's `theme`, else the desktop's setting: kdeglobals on KDE, else
+//     gsettings, else GTK's settings.ini, else hicolor
+//
+// Std only, apart from asking gsettings.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use crate::helpers::environment;
+
+/// Extensions an icon file may have, in preference order
+const EXTENSIONS: &[&str] = &["png", "svg", "xpm"];
+
+/// The theme every other theme falls back to
+const FALLBACK_THEME: &str = "hicolor";
+
+/// Inherits chains deeper than this are a loop
+const MAX_INHERIT_DEPTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SizeType {
+    Fixed,
+    Scalable,
+    Threshold,
+}
+
+/// A directory of a theme, as its index.theme describes it
+#[derive(Debug, Clone, PartialEq)]
+struct ThemeDir {
+    path: String, // relative to the theme, e.g. "48x48/apps"
+    size: u32,
+    scale: u32,
+    min_size: u32,
+    max_size: u32,
+    threshold: u32,
+    kind: SizeType,
+}
+
+impl ThemeDir {
+    fn matches_size(&self, size: u32, scale: u32) -> bool {
+        if self.scale != scale {
+            return false;
+        }
+        match self.kind {
+            SizeType::Fixed => self.size == size,
+            SizeType::Scalable => self.min_size <= size && size <= self.max_size,
+            SizeType::Threshold => self.size.saturating_sub(self.threshold) <= size && size <= self.size + self.threshold,
+        }
+    }
+
+    fn size_distance(&self, size: u32, scale: u32) -> u32 {
+        let wanted = size * scale;
+        let (low, high) = match self.kind {
+            SizeType::Fixed => (self.size, self.size),
+            SizeType::Scalable => (self.min_size, self.max_size),
+            SizeType::Threshold => (self.size.saturating_sub(self.threshold), self.size + self.threshold),
+        };
+        let (low, high) = (low * self.scale, high * self.scale);
+        if self.kind == SizeType::Fixed {
+            return low.abs_diff(wanted);
+        }
+        if wanted < low {
+            low - wanted
+        } else {
+            wanted.saturating_sub(high)
+        }
+    }
+}
+
+/// A theme's index.theme: its directories and the themes it inherits
+#[derive(Debug, Default)]
+struct Theme {
+    dirs: Vec<ThemeDir>,
+    inherits: Vec<String>,
+}
+
+impl Theme {
+    fn parse(content: &str) -> Theme {
+        let groups = parse_ini(content);
+        let Some(main) = groups.get("Icon Theme") else { return Theme::default() };
+        let list = |key: &str| -> Vec<String> {
+            main.get(key)
+                .map(|v| v.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect())
+                .unwrap_or_default()
+        };
+        let mut names = list("Directories");
+        names.extend(list("ScaledDirectories"));
+        names.dedup();
+        let dirs = names.into_iter()
+            .filter_map(|name| {
+                let group = groups.get(&name)?;
+                let number = |key: &str| group.get(key).and_then(|v| v.trim().parse::<u32>().ok());
+                let size = number("Size")?;
+                let kind = match group.get("Type").map(String::as_str) {
+                    Some("Fixed") => SizeType::Fixed,
+                    Some("Scalable") => SizeType::Scalable,
+                    _ => SizeType::Threshold,
+                };
+                Some(ThemeDir {
+                    path: name,
+                    size,
+                    scale: number("Scale").unwrap_or(1).max(1),
+                    min_size: number("MinSize").unwrap_or(size),
+                    max_size: number("MaxSize").unwrap_or(size),
+                    threshold: number("Threshold").unwrap_or(2),
+                    kind,
+                })
+            })
+            .collect();
+        Theme { dirs, inherits: list("Inherits") }
+    }
+}
+
+/// group -> key -> value, for the simple ini files themes and toolkits use
+fn parse_ini(content: &str) -> HashMap<String, HashMap<String, String>> {
+    let mut groups: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let mut current = None;
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current = Some(name.to_string());
+            groups.entry(name.to_string()).or_default();
+        } else if let (Some(group), Some((key, value))) = (&current, line.split_once('=')) {
+            groups.entry(group.clone()).or_default().insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+    groups
+}
+
+/// Base directories themes are looked up in, highest precedence first
+fn base_directories() -> Vec<PathBuf> {
+    let home = std::env::var("HOME").unwrap_or_default();
+    let data_home = std::env::var("XDG_DATA_HOME").ok().filter(|d| d.starts_with('/'))
+        .unwrap_or_else(|| format!("{}/.local/share", home));
+    let data_dirs = std::env::var("XDG_DATA_DIRS").ok().filter(|d| !d.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    let mut dirs = vec![PathBuf::from(format!("{}/.icons", home)), Path::new(&data_home).join("icons")];
+    dirs.extend(data_dirs.split(':').filter(|d| d.starts_with('/')).map(|d| Path::new(d).join("icons")));
+    dirs.push(PathBuf::from(format!("{}/.local/share/flatpak/exports/share/icons", home)));
+    dirs.push(PathBuf::from("/var/lib/flatpak/exports/share/icons"));
+    let mut seen = std::collections::HashSet::new();
+    dirs.retain(|dir| seen.insert(dir.clone()));
+    dirs
+}
+
+/// Icon lookups against one set of base directories, with each theme's index read once
+pub struct Lookup {
+    bases: Vec<PathBuf>,
+    pixmaps: Vec<PathBuf>,
+    themes: HashMap<String, Option<Theme>>,
+}
+
+impl Lookup {
+    /// Lookups in the user's and the system's icon directories
+    pub fn system() -> Lookup {
+        Lookup::with_dirs(base_directories(), vec![PathBuf::from("/usr/share/pixmaps")])
+    }
+
+    fn with_dirs(bases: Vec<PathBuf>, pixmaps: Vec<PathBuf>) -> Lookup {
+        Lookup { bases, pixmaps, themes: HashMap::new() }
+    }
+
+    /// The file for `icon` (an Icon= value) at `size` x `scale`, looked up from `theme`
+    pub fn find(&mut self, icon: &str, size: u32, scale: u32, theme: &str) -> Option<PathBuf> {
+        if icon.starts_with('/') {
+            return Path::new(icon).is_file().then(|| PathBuf::from(icon));
+        }
+        if icon.is_empty() || icon.contains('/') {
+            return None;
+        }
+        // Older entries name the file, extension and all
+        let name = EXTENSIONS.iter()
+            .find_map(|ext| icon.strip_suffix(&format!(".{}", ext)))
+            .unwrap_or(icon);
+        let (size, scale) = (size.max(1), scale.max(1));
+        self.find_in(name, size, scale, theme, 0)
+            .or_else(|| self.find_in(name, size, scale, FALLBACK_THEME, 0))
+            .or_else(|| self.fallback(name))
+    }
+
+    /// `name` in `theme` or what it inherits
+    fn find_in(&mut self, name: &str, size: u32, scale: u32, theme: &str, depth: usize) -> Option<PathBuf> {
+        if depth > MAX_INHERIT_DEPTH {
+            return None;
+        }
+        if let Some(found) = self.lookup(name, size, scale, theme) {
+            return Some(found);
+        }
+        let parents = self.theme(theme).map(|t| t.inherits.clone()).unwrap_or_default();
+        parents.iter()
+            .filter(|parent| parent.as_str() != FALLBACK_THEME)
+            .find_map(|parent| self.find_in(name, size, scale, parent, depth + 1))
+    }
+
+    /// `name` in `theme` alone: a directory of the size if any has it, else the closest
+    fn lookup(&mut self, name: &str, size: u32, scale: u32, theme: &str) -> Option<PathBuf> {
+        let dirs = self.theme(theme)?.dirs.clone();
+        let files = |dir: &ThemeDir| -> Vec<PathBuf> {
+            self.bases.iter()
+                .flat_map(|base| EXTENSIONS.iter().map(move |ext| base.join(theme).join(&dir.path).join(format!("{}.{}", name, ext))))
+                .filter(|file| file.is_file())
+                .collect()
+        };
+        if let Some(exact) = dirs.iter().filter(|dir| dir.matches_size(size, scale)).find_map(|dir| files(dir).into_iter().next()) {
+            return Some(exact);
+        }
+        dirs.iter()
+            .filter_map(|dir| files(dir).into_iter().next().map(|file| (dir.size_distance(size, scale), file)))
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, file)| file)
+    }
+
+    /// Unthemed icons: the pixmaps directory and the base directories themselves
+    fn fallback(&self, name: &str) -> Option<PathBuf> {
+        self.bases.iter().chain(&self.pixmaps)
+            .flat_map(|dir| EXTENSIONS.iter().map(move |ext| dir.join(format!("{}.{}", name, ext))))
+            .find(|file| file.is_file())
+    }
+
+    /// A theme's index, from the first base directory that has one
+    fn theme(&mut self, name: &str) -> Option<&Theme> {
+        if !self.themes.contains_key(name) {
+            let theme = (!name.is_empty() && !name.contains('/') && name != "..").then(|| {
+                self.bases.iter()
+                    .find_map(|base| fs::read_to_string(base.join(name).join("index.theme")).ok())
+                    .map(|content| Theme::parse(&content))
+            }).flatten();
+            self.themes.insert(name.to_string(), theme);
+        }
+        self.themes.get(name).and_then(Option::as_ref)
+    }
+}
+
+/// The icon theme the user's desktop is set to
+pub fn current_theme() -> String {
+    let home = std::env::var("HOME").unwrap_or_default();
+    let config_home = std::env::var("XDG_CONFIG_HOME").ok().filter(|d| d.starts_with('/'))
+        .unwrap_or_else(|| format!("{}/.config", home));
+    let from_ini = |file: &str, group: &str, key: &str| {
+        fs::read_to_string(Path::new(&config_home).join(file)).ok()
+            .and_then(|content| parse_ini(&content).get(group)?.get(key).cloned())
+            .filter(|theme| !theme.is_empty())
+    };
+    let kde = environment::session_var("XDG_CURRENT_DESKTOP").is_some_and(|d| d.to_lowercase().contains("kde"));
+    let kde_theme = || from_ini("kdeglobals", "Icons", "Theme");
+    let gtk_theme = || {
+        gsettings_theme()
+            .or_else(|| from_ini("gtk-4.0/settings.ini", "Settings", "gtk-icon-theme-name"))
+            .or_else(|| from_ini("gtk-3.0/settings.ini", "Settings", "gtk-icon-theme-name"))
+    };
+    let theme = if kde { kde_theme().or_else(gtk_theme) } else { gtk_theme().or_else(kde_theme) };
+    theme.unwrap_or_else(|| FALLBACK_THEME.to_string())
+}
+
+/// `gsettings get org.gnome.desktop.interface icon-theme`, unquoted
+fn gsettings_theme() -> Option<String> {
+    let output = Command::new("gsettings")
+        .args(["get", "org.gnome.desktop.interface", "icon-theme"])
+        .env("DBUS_SESSION_BUS_ADDRESS", environment::get_dbus_address())
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    let theme = String::from_utf8_lossy(&output.stdout).trim().trim_matches('\'').to_string();
+    (!theme.is_empty()).then_some(theme)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INDEX: &str = "[Icon Theme]\nName=Test\nInherits=Parent,hicolor\nDirectories=16x16/apps,48x48/apps,scalable/apps\n\n\
+        [16x16/apps]\nSize=16\nType=Fixed\n\n[48x48/apps]\nSize=48\nType=Fixed\n\n\
+        [scalable/apps]\nSize=64\nMinSize=8\nMaxSize=512\nType=Scalable\n";
+
+    #[test]
+    fn test_theme_dirs() {
+        let theme = Theme::parse(INDEX);
+        assert_eq!(theme.inherits, vec!["Parent", "hicolor"]);
+        assert_eq!(theme.dirs.len(), 3);
+        let fixed = &theme.dirs[1];
+        assert!(fixed.matches_size(48, 1) && !fixed.matches_size(48, 2) && !fixed.matches_size(32, 1));
+        assert_eq!(fixed.size_distance(32, 1), 16);
+        let scalable = &theme.dirs[2];
+        assert!(scalable.matches_size(100, 1));
+        assert_eq!(scalable.size_distance(4, 1), 4);
+    }
+
+    #[test]
+    fn test_lookup_order() {
+        let root = std::env::temp_dir().join(format!("archy-icons-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let write = |path: &str, content: &str| {
+            let file = root.join(path);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, content).unwrap();
+        };
+        write("icons/Test/index.theme", INDEX);
+        write("icons/Test/16x16/apps/editor.png", "");
+        write("icons/Test/scalable/apps/editor.svg", "");
+        write("icons/Parent/index.theme", "[Icon Theme]\nDirectories=apps\n[apps]\nSize=48\n");
+        write("icons/Parent/apps/viewer.png", "");
+        write("icons/hicolor/index.theme", "[Icon Theme]\nDirectories=48x48/apps\n[48x48/apps]\nSize=48\nType=Fixed\n");
+        write("icons/hicolor/48x48/apps/player.png", "");
+        write("pixmaps/old.xpm", "");
+
+        let mut lookup = Lookup::with_dirs(vec![root.join("icons")], vec![root.join("pixmaps")]);
+        let found = |lookup: &mut Lookup, icon: &str, size: u32| {
+            lookup.find(icon, size, 1, "Test").map(|path| path.strip_prefix(&root).unwrap().to_string_lossy().to_string())
+        };
+        // The size asked for, else the directory closest to it
+        assert_eq!(found(&mut lookup, "editor", 16).as_deref(), Some("icons/Test/16x16/apps/editor.png"));
+        assert_eq!(found(&mut lookup, "editor", 128).as_deref(), Some("icons/Test/scalable/apps/editor.svg"));
+        // Inherited theme, hicolor, pixmaps
+        assert_eq!(found(&mut lookup, "viewer", 48).as_deref(), Some("icons/Parent/apps/viewer.png"));
+        assert_eq!(found(&mut lookup, "player.png", 48).as_deref(), Some("icons/hicolor/48x48/apps/player.png"));
+        assert_eq!(found(&mut lookup, "old", 48).as_deref(), Some("pixmaps/old.xpm"));
+        assert_eq!(found(&mut lookup, "missing", 48), None);
+        assert_eq!(found(&mut lookup, "../Test/16x16/apps/editor", 16), None);
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,196 @@
+// introspect.rs - What the daemon is doing right now
+// `introspect` is for when the daemon "feels stuck". It lists:
+//
+//   connections  the request being handled (action, phase, how long) and those queued behind it
+//   batches      the batch in progress: which steps run, how many are done, how long it's been
+//   timers       everything counting down - pane waits, confirmation tokens, the rate-limit lockout
+//                and commands the watchdog hasn't seen answered
+//
+// The request being handled is usually the reason for being stuck, so `introspect` doesn't queue
+// behind it: the acceptor thread answers it directly (it's neither audited nor timed).
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::batch;
+use crate::config::Config;
+use crate::confirm;
+use crate::errors::ErrorKind;
+use crate::eventlog::{self, Kind};
+use crate::secrets;
+use crate::supervisor::{self, Incoming};
+use crate::throttle;
+use crate::timings;
+use crate::watchdog;
+
+static NEXT_WAIT: AtomicU64 = AtomicU64::new(0);
+
+/// Pane waits in progress
+static WAITS: Mutex<Vec<Wait>> = Mutex::new(Vec::new());
+
+struct Wait {
+    id: u64,
+    label: String,
+    session: String,
+    started: Instant,
+    timeout: Duration,
+}
+
+/// Something counting down
+#[derive(Debug, Serialize)]
+pub struct Timer {
+    pub kind: &'static str, // "wait", "confirmation", "lockout" or "watchdog"
+    pub label: String,
+    pub session: Option<String>,
+    pub elapsed_ms: Option<u64>,
+    pub remaining_ms: u64,
+}
+
+/// Drops the wait from the list
+pub struct WaitGuard(u64);
+
+impl Drop for WaitGuard {
+    fn drop(&mut self) {
+        WAITS.lock().unwrap_or_else(|e| e.into_inner()).retain(|wait| wait.id != self.0);
+    }
+}
+
+/// List a pane wait of at most `timeout` until the guard drops
+pub fn waiting(session: &str, label: impl Into<String>, timeout: Duration) -> WaitGuard {
+    let id = NEXT_WAIT.fetch_add(1, Ordering::Relaxed);
+    WAITS.lock().unwrap_or_else(|e| e.into_inner()).push(Wait {
+        id,
+        label: label.into(),
+        session: session.to_string(),
+        started: Instant::now(),
+        timeout,
+    });
+    WaitGuard(id)
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis() as u64
+}
+
+fn timers(config: &Config) -> Vec<Timer> {
+    let mut timers: Vec<Timer> = WAITS.lock().unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .map(|wait| Timer {
+            kind: "wait",
+            label: wait.label.clone(),
+            session: Some(wait.session.clone()),
+            elapsed_ms: Some(millis(wait.started.elapsed())),
+            remaining_ms: millis(wait.timeout.saturating_sub(wait.started.elapsed())),
+        })
+        .collect();
+    timers.extend(confirm::pending().into_iter().map(|(action, remaining)| Timer {
+        kind: "confirmation",
+        label: format!("{} waits for confirm_execute", action),
+        session: None,
+        elapsed_ms: None,
+        remaining_ms: millis(remaining),
+    }));
+    if let Some((remaining, reason)) = throttle::lockout() {
+        timers.push(Timer {
+            kind: "lockout",
+            label: format!("Execution locked: {}", reason),
+            session: None,
+            elapsed_ms: None,
+            remaining_ms: millis(remaining),
+        });
+    }
+    let stall = Duration::from_secs(config.watchdog_stall_seconds);
+    timers.extend(watchdog::unanswered().into_iter().map(|(session, elapsed)| Timer {
+        kind: "watchdog",
+        label: "Keys sent, screen not seen changing yet - recreated when the countdown runs out".to_string(),
+        session: Some(session),
+        elapsed_ms: Some(millis(elapsed)),
+        remaining_ms: millis(stall.saturating_sub(elapsed)),
+    }));
+    timers
+}
+
+/// The `introspect` reply
+pub fn report(config: &Config) -> Value {
+    let current = timings::current();
+    let connections: Vec<Value> = supervisor::connections()
+        .into_iter()
+        .map(|connection| {
+            let mut entry = json!(connection);
+            // The handler's own view: confirm_execute swaps the action, and the phase moves on
+            if let (Some(current), "handling") = (&current, connection.state) {
+                entry["action"] = json!(current.action);
+                entry["session"] = json!(current.session);
+                entry["phase"] = json!(current.phase);
+                entry["phase_ms"] = json!(current.phase_ms);
+            }
+            entry
+        })
+        .collect();
+    let batches: Vec<batch::ActiveBatch> = batch::active().into_iter().collect();
+    let timers = timers(config);
+
+    let queued = connections.iter().filter(|c| c["state"] == "queued").count();
+    let handling = match &current {
+        Some(current) if !current.action.is_empty() => format!(
+            "handling {} ({} phase for {:.0} ms, {:.0} ms in total)",
+            current.action,
+            current.phase.as_str(),
+            current.phase_ms,
+            current.age_ms
+        ),
+        _ => "idle".to_string(),
+    };
+    json!({
+        "success": true,
+        "output": format!("{}, {} queued, {} batch(es), {} timer(s)", handling, queued, batches.len(), timers.len()),
+        "connections": connections,
+        "batches": batches,
+        "timers": timers,
+    })
+}
+
+/// Answer `introspect` on the acceptor thread; anything else goes back to be queued
+pub fn answer_now(incoming: Incoming, config: &Config) -> Option<Incoming> {
+    let id = match &incoming.request {
+        Ok(request) if request.action == "introspect" => request.id.clone(),
+        _ => return Some(incoming),
+    };
+    let allowed = incoming.authorize("introspect", config)
+        .and_then(|()| config.features.check_action("introspect"));
+    let reply = match allowed {
+        Ok(()) => report(config),
+        Err(e) => {
+            tracing::warn!("{}", e);
+            eventlog::emit(Kind::AccessDenied, None, e.clone(), json!({"action": "introspect", "uid": incoming.peer.map(|peer| peer.uid)}));
+            archy_protocol::error_reply(ErrorKind::Denied, &e)
+        }
+    };
+    let reply = archy_protocol::stamp(reply, id.as_ref());
+    let mut stream = incoming.stream;
+    let json = secrets::redact(&reply.to_string());
+    if let Err(e) = stream.write_all(json.as_bytes()).and_then(|()| stream.flush()) {
+        tracing::warn!("Cannot send the introspect reply: {}", e);
+    }
+    let _ = stream.shutdown(std::net::Shutdown::Both);
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_waits_listed_until_dropped() {
+        let listed = || timers(&Config::default()).into_iter().filter(|t| t.label == "introspect-test").count();
+        let wait = waiting("work", "introspect-test", Duration::from_secs(60));
+        assert_eq!(listed(), 1);
+        let timer = timers(&Config::default()).into_iter().find(|t| t.label == "introspect-test").unwrap();
+        assert!(timer.remaining_ms > 59_000 && timer.remaining_ms <= 60_000);
+        drop(wait);
+        assert_eq!(listed(), 0);
+    }
+}
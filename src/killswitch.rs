@@ -0,0 +1,119 @@
+// killswitch.rs - Emergency stop
+// `panic_stop` (or SIGUSR1 to the daemon) interrupts every pane archy manages, makes running batches
+// skip their remaining steps and refuses everything but reads until an explicit `resume`. The signal is
+// picked up by a watcher thread, so it works even while a long batch is holding the socket.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use crate::acl::{self, Access};
+use crate::eventlog::{self, Kind};
+use crate::tmux;
+
+static STOPPED: AtomicBool = AtomicBool::new(false);
+
+/// Set by the signal handler - nothing else is safe to do there
+static SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+/// Why the stop was engaged, for replies and status
+static REASON: Mutex<Option<String>> = Mutex::new(None);
+
+/// Held by tests that engage the stop, and by tests that need it not engaged
+#[cfg(test)]
+pub static TEST_SERIAL: Mutex<()> = Mutex::new(());
+
+extern "C" fn on_sigusr1(_signal: libc::c_int) {
+    SIGNALLED.store(true, Ordering::SeqCst);
+}
+
+/// Route SIGUSR1 to the emergency stop (called once at startup)
+pub fn install() {
+    let handler: extern "C" fn(libc::c_int) = on_sigusr1;
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+    unsafe {
+        libc::signal(libc::SIGUSR1, handler as libc::sighandler_t);
+    }
+    std::thread::spawn(|| loop {
+        std::thread::sleep(Duration::from_millis(100));
+        if SIGNALLED.swap(false, Ordering::SeqCst) {
+            engage("SIGUSR1");
+        }
+    });
+}
+
+pub fn is_stopped() -> bool {
+    STOPPED.load(Ordering::SeqCst)
+}
+
+/// Stop everything - returns the sessions that were interrupted
+pub fn engage(reason: &str) -> Vec<String> {
+    STOPPED.store(true, Ordering::SeqCst);
+    *REASON.lock().unwrap_or_else(|e| e.into_inner()) = Some(reason.to_string());
+    tracing::error!("Emergency stop engaged ({}) - execution refused until resume", reason);
+    eventlog::emit(Kind::EmergencyStop, None, format!("Emergency stop engaged ({})", reason), serde_json::json!({"reason": reason}));
+
+    let sessions: Vec<String> = tmux::list_sessions()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|session| tmux::is_managed(session))
+        .collect();
+    for session in &sessions {
+        if let Err(e) = tmux::send_interrupt(session) {
+            tracing::warn!("Emergency stop: interrupting {} failed: {}", session, e.trim());
+        }
+    }
+    sessions
+}
+
+/// Set the stop without interrupting sessions - engage() would reach real tmux sessions on this machine
+#[cfg(test)]
+pub fn engage_quietly(reason: &str) {
+    STOPPED.store(true, Ordering::SeqCst);
+    *REASON.lock().unwrap_or_else(|e| e.into_inner()) = Some(reason.to_string());
+}
+
+/// Allow execution again - Err when no stop was engaged
+pub fn resume() -> Result<String, String> {
+    if !STOPPED.swap(false, Ordering::SeqCst) {
+        return Err("No emergency stop is engaged".to_string());
+    }
+    let reason = REASON.lock().unwrap_or_else(|e| e.into_inner()).take().unwrap_or_default();
+    tracing::warn!("Emergency stop lifted (was: {})", reason);
+    eventlog::emit(Kind::ExecutionResumed, None, format!("Emergency stop lifted (was: {})", reason), serde_json::Value::Null);
+    Ok(reason)
+}
+
+/// Actions still answered while stopped besides reads - what it takes to see the stop and lift it
+const WHILE_STOPPED: &[&str] = &["resume", "panic_stop", "health", "describe"];
+
+/// Dispatcher gate while stopped: anything beyond a read waits for `resume`
+pub fn check(action: &str) -> Result<(), String> {
+    if !is_stopped() || WHILE_STOPPED.contains(&action) || acl::required(action) <= Access::Read {
+        return Ok(());
+    }
+    let reason = REASON.lock().unwrap_or_else(|e| e.into_inner()).clone().unwrap_or_default();
+    Err(format!("Emergency stop engaged ({}) - send resume to allow '{}' again", reason, action))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stop_and_resume() {
+        let _serial = TEST_SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        engage_quietly("test");
+        assert!(check("execute").unwrap_err().contains("resume"));
+        // Everything beyond a read is refused, admin actions and desktop changes included
+        for action in ["run_workflow", "launch_gui_app", "add_autostart", "set_default_app", "confirm_execute", "set_log_level"] {
+            assert!(check(action).is_err(), "{}", action);
+        }
+        for action in ["panic_stop", "resume", "health", "describe", "capture", "check_session"] {
+            assert!(check(action).is_ok(), "{}", action);
+        }
+
+        assert_eq!(resume().unwrap(), "test");
+        assert!(check("execute").is_ok());
+        assert!(resume().is_err());
+    }
+}
@@ -0,0 +1,212 @@
+// logging.rs - Daemon logging with tracing
+// Events are emitted with tracing's macros. Each request runs inside a `request` span carrying its id,
+// action and session, so every line it logs says which request it came from, and log-crate records
+// from dependencies are bridged into the same output. Level, target (stderr or file) and format come
+// from the `[log]` config section; the level can be changed at runtime with the `set_log_level`
+// action. Lines pass through secret redaction on their way out.
+
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{filter, reload, Layer, Registry};
+use crate::secrets;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Pretty, // "<ts>  WARN request{id=7 action=execute}: message" - readable in journalctl
+    Json,   // one JSON object per line, span fields included, for log shippers
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("unknown log format {:?} (pretty or json)", other)),
+        }
+    }
+}
+
+/// Parse a level name ("off", "error", "warn", "info", "debug", "trace")
+pub fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    LevelFilter::from_str(level.trim())
+        .map_err(|_| format!("unknown log level {:?} (off, error, warn, info, debug, trace)", level))
+}
+
+fn as_tracing(level: LevelFilter) -> filter::LevelFilter {
+    match level {
+        LevelFilter::Off => filter::LevelFilter::OFF,
+        LevelFilter::Error => filter::LevelFilter::ERROR,
+        LevelFilter::Warn => filter::LevelFilter::WARN,
+        LevelFilter::Info => filter::LevelFilter::INFO,
+        LevelFilter::Debug => filter::LevelFilter::DEBUG,
+        LevelFilter::Trace => filter::LevelFilter::TRACE,
+    }
+}
+
+/// The installed level filter and its level, for set_level
+struct Level {
+    handle: reload::Handle<filter::LevelFilter, Registry>,
+    current: Mutex<LevelFilter>,
+}
+
+static LEVEL: OnceLock<Level> = OnceLock::new();
+
+/// Where formatted lines go - None is stderr (journald under systemd)
+struct Sink(Option<Mutex<Box<dyn Write + Send>>>);
+
+impl<'a> MakeWriter<'a> for Sink {
+    type Writer = Line<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        Line { sink: self, buffer: Vec::new() }
+    }
+}
+
+/// One event's line, redacted and written out whole when the formatter is done with it
+struct Line<'a> {
+    sink: &'a Sink,
+    buffer: Vec<u8>,
+}
+
+impl Write for Line<'_> {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Line<'_> {
+    fn drop(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let line = secrets::redact(&String::from_utf8_lossy(&self.buffer));
+        match &self.sink.0 {
+            Some(file) => {
+                let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+                let _ = file.write_all(line.as_bytes());
+                let _ = file.flush();
+            }
+            None => {
+                let _ = std::io::stderr().write_all(line.as_bytes());
+            }
+        }
+    }
+}
+
+/// The formatting layer for `format`, writing to `sink`
+fn output<S>(format: LogFormat, sink: Sink) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    let layer = tracing_subscriber::fmt::layer().with_writer(sink).with_ansi(false).with_target(false);
+    match format {
+        LogFormat::Pretty => layer.boxed(),
+        LogFormat::Json => layer.json().flatten_event(true).with_current_span(true).with_span_list(false).boxed(),
+    }
+}
+
+/// Install the process-wide subscriber (once, when the daemon starts)
+pub fn init(level: LevelFilter, format: LogFormat, file: Option<&str>) -> Result<(), String> {
+    let sink = match file {
+        Some(path) => Sink(Some(Mutex::new(Box::new(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("Cannot open log file {}: {}", path, e))?,
+        )))),
+        None => Sink(None),
+    };
+
+    let (filter, handle) = reload::Layer::new(as_tracing(level));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(output(format, sink))
+        .try_init()
+        .map_err(|e| format!("Logger already initialized: {}", e))?;
+    log::set_max_level(level);
+    let _ = LEVEL.set(Level { handle, current: Mutex::new(level) });
+    Ok(())
+}
+
+/// The level in effect
+pub fn level() -> LevelFilter {
+    LEVEL.get().map(|level| *level.current.lock().unwrap_or_else(|e| e.into_inner())).unwrap_or(LevelFilter::Off)
+}
+
+/// Change the level at runtime - returns the previous level
+pub fn set_level(level: &str) -> Result<LevelFilter, String> {
+    let level = parse_level(level)?;
+    let installed = LEVEL.get().ok_or("Logging is not initialized")?;
+    installed.handle.reload(as_tracing(level)).map_err(|e| format!("Cannot change the log level: {}", e))?;
+    log::set_max_level(level);
+    let mut current = installed.current.lock().unwrap_or_else(|e| e.into_inner());
+    Ok(std::mem::replace(&mut *current, level))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// A writer the test can read back
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_parse_level_and_format() {
+        assert_eq!(parse_level("DEBUG").unwrap(), LevelFilter::Debug);
+        assert!(parse_level("loud").is_err());
+        assert_eq!("json".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert!("xml".parse::<LogFormat>().is_err());
+    }
+
+    #[test]
+    fn test_json_with_request_span() {
+        let captured = Captured::default();
+        let subscriber = tracing_subscriber::registry()
+            .with(filter::LevelFilter::INFO)
+            .with(output(LogFormat::Json, Sink(Some(Mutex::new(Box::new(captured.clone()))))));
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("request", id = "7", action = "execute_batch", session = "work");
+            let _entered = span.enter();
+            tracing::warn!("batch {} done", 7);
+            tracing::debug!("filtered out");
+        });
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let parsed: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(parsed["level"], "WARN");
+        assert_eq!(parsed["message"], "batch 7 done");
+        assert_eq!(parsed["span"]["action"], "execute_batch");
+        assert_eq!(parsed["span"]["session"], "work");
+    }
+}
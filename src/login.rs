@@ -0,0 +1,210 @@
+// login.rs - Who is logged in and how, for session_info
+// Launch decisions need more than DISPLAY: whether the session is Wayland or X11, which compositor
+// runs it, whether anyone is at the keyboard. Three sources:
+//
+//   - the graphical session: helpers::environment::Session, the same detection GUI launches use, and
+//     the variables it would hand a launched program (gui_env)
+//   - logins: logind over the system bus (org.freedesktop.login1) - type, class, seat, tty, remote
+//     host and idle hint of every session. Without logind (containers, non-systemd inits) `who` gives
+//     the users and terminals only, and `source` says so
+//   - idle: logind's IdleHint for the daemon user's own graphical session, which the compositor or
+//     screen locker keeps up to date. Unknown (null) when there's no such session
+//
+// Nothing here changes state.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+use crate::helpers::{environment, process};
+
+const LOGIND: &str = "org.freedesktop.login1";
+const LOGIND_PATH: &str = "/org/freedesktop/login1";
+const DBUS_TIMEOUT: Duration = Duration::from_secs(5);
+const WHO_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize)]
+pub struct SessionInfo {
+    pub graphical: environment::Session,
+    pub runs_x11: bool,
+    pub logins: Vec<Login>,
+    pub idle: Option<bool>,
+    pub idle_since_us: Option<u64>, // realtime µs since the epoch
+    pub env: BTreeMap<&'static str, String>,
+    pub source: &'static str, // "logind" or "who"
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Login {
+    pub id: String,
+    pub user: String,
+    pub uid: Option<u32>,
+    pub kind: Option<String>,  // logind Type: "wayland", "x11", "tty", "mir" or "unspecified"
+    pub class: Option<String>, // "user", "greeter", "lock-screen", "background"
+    pub state: Option<String>, // "active", "online" or "closing"
+    pub seat: Option<String>,
+    pub tty: Option<String>,
+    pub display: Option<String>,
+    pub desktop: Option<String>,
+    pub remote_host: Option<String>,
+    pub idle: Option<bool>,
+    pub idle_since_us: Option<u64>,
+    pub since_us: Option<u64>,
+}
+
+pub fn collect() -> SessionInfo {
+    let graphical = environment::Session::detect();
+    let (logins, source) = match logind_sessions() {
+        Ok(logins) => (logins, "logind"),
+        Err(e) => {
+            tracing::debug!("logind unavailable ({}), asking who", e);
+            let argv = vec!["who".to_string()];
+            let logins = process::run(&argv, WHO_TIMEOUT).map(|(_, stdout, _)| parse_who(&stdout)).unwrap_or_default();
+            (logins, "who")
+        }
+    };
+    let own = own_graphical(&logins, crate::peer::own_uid());
+    let (idle, idle_since_us) = own.map(|login| (login.idle, login.idle_since_us)).unwrap_or((None, None));
+    SessionInfo {
+        runs_x11: graphical.runs_x11(),
+        env: graphical.gui_env().into_iter().collect(),
+        graphical,
+        logins,
+        idle,
+        idle_since_us,
+        source,
+    }
+}
+
+impl SessionInfo {
+    pub fn summary(&self) -> String {
+        let server = match self.graphical.server {
+            environment::DisplayServer::Wayland => "Wayland",
+            environment::DisplayServer::X11 => "X11",
+            environment::DisplayServer::Unknown => "no graphical session",
+        };
+        let mut summary = match &self.graphical.compositor {
+            Some(compositor) => format!("{} ({})", server, compositor),
+            None => server.to_string(),
+        };
+        let mut users: Vec<&str> = self.logins.iter().map(|login| login.user.as_str()).collect();
+        users.sort();
+        users.dedup();
+        summary.push_str(&format!(", {} login(s)", self.logins.len()));
+        if !users.is_empty() {
+            summary.push_str(&format!(" by {}", users.join(", ")));
+        }
+        if self.idle == Some(true) {
+            summary.push_str(", idle");
+        }
+        summary
+    }
+}
+
+type SessionRow = (String, u32, String, String, OwnedObjectPath);
+
+/// Every logind session with its properties
+fn logind_sessions() -> Result<Vec<Login>, String> {
+    let connection = zbus::blocking::connection::Builder::system()
+        .map(|builder| builder.method_timeout(DBUS_TIMEOUT))
+        .and_then(|builder| builder.build())
+        .map_err(|e| format!("No system bus: {}", e))?;
+    let rows: Vec<SessionRow> = connection
+        .call_method(Some(LOGIND), LOGIND_PATH, Some("org.freedesktop.login1.Manager"), "ListSessions", &())
+        .and_then(|reply| reply.body().deserialize())
+        .map_err(|e| e.to_string())?;
+    let mut logins: Vec<Login> = rows.into_iter()
+        .map(|(id, uid, user, seat, path)| {
+            let props: HashMap<String, OwnedValue> = connection
+                .call_method(Some(LOGIND), path.as_str(), Some("org.freedesktop.DBus.Properties"), "GetAll", &("org.freedesktop.login1.Session",))
+                .ok()
+                .and_then(|reply| reply.body().deserialize().ok())
+                .unwrap_or_default();
+            let text = |key: &str| props.get(key).and_then(|v| <&str>::try_from(v).ok()).filter(|s| !s.is_empty()).map(String::from);
+            let time = |key: &str| props.get(key).and_then(|v| u64::try_from(v).ok()).filter(|&t| t > 0);
+            let remote = props.get("Remote").and_then(|v| bool::try_from(v).ok()).unwrap_or(false);
+            Login {
+                id,
+                user,
+                uid: Some(uid),
+                kind: text("Type"),
+                class: text("Class"),
+                state: text("State"),
+                seat: Some(seat).filter(|s| !s.is_empty()),
+                tty: text("TTY"),
+                display: text("Display"),
+                desktop: text("Desktop"),
+                remote_host: if remote { text("RemoteHost") } else { None },
+                idle: props.get("IdleHint").and_then(|v| bool::try_from(v).ok()),
+                idle_since_us: time("IdleSinceHint"),
+                since_us: time("Timestamp"),
+            }
+        })
+        .collect();
+    logins.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(logins)
+}
+
+/// `who` lines: USER TTY DATE TIME [(HOST)]
+fn parse_who(text: &str) -> Vec<Login> {
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let user = fields.next()?.to_string();
+            let tty = fields.next()?.to_string();
+            // A trailing "(host)" is the remote host - or "(:0)" for a local X display
+            let host = line.rfind('(').and_then(|start| Some(line[start + 1..].strip_suffix(')')?.to_string()));
+            let (display, remote_host) = match host {
+                Some(host) if host.starts_with(':') => (Some(host), None),
+                host => (None, host),
+            };
+            Some(Login { id: tty.clone(), user, tty: Some(tty), display, remote_host, ..Login::default() })
+        })
+        .collect()
+}
+
+/// The user's own local graphical session - the active one if there are several
+fn own_graphical(logins: &[Login], uid: u32) -> Option<&Login> {
+    let graphical = |login: &&Login| {
+        login.uid == Some(uid) && login.remote_host.is_none() && matches!(login.kind.as_deref(), Some("wayland" | "x11" | "mir"))
+    };
+    logins.iter()
+        .filter(graphical)
+        .find(|login| login.state.as_deref() == Some("active"))
+        .or_else(|| logins.iter().find(graphical))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_who() {
+        let logins = parse_who("alice    tty2         2026-10-16 08:01 (tty2)\nalice    pts/0        2026-10-16 09:12 (:0)\n\
+            bob      pts/1        2026-10-16 09:30 (10.0.0.7)\n");
+        assert_eq!(logins.len(), 3);
+        assert_eq!((logins[1].display.as_deref(), logins[1].remote_host.as_deref()), (Some(":0"), None));
+        assert_eq!((logins[2].user.as_str(), logins[2].remote_host.as_deref()), ("bob", Some("10.0.0.7")));
+    }
+
+    #[test]
+    fn test_own_graphical() {
+        let login = |id: &str, uid: u32, kind: &str, state: &str, idle: bool| Login {
+            id: id.to_string(),
+            uid: Some(uid),
+            kind: Some(kind.to_string()),
+            state: Some(state.to_string()),
+            idle: Some(idle),
+            ..Login::default()
+        };
+        let logins = vec![
+            login("1", 1000, "tty", "active", false),
+            login("2", 1000, "wayland", "online", false),
+            login("3", 1000, "wayland", "active", true),
+            login("4", 1001, "x11", "active", false),
+        ];
+        assert_eq!(own_graphical(&logins, 1000).map(|l| l.id.as_str()), Some("3"));
+        assert_eq!(own_graphical(&logins[..2], 1000).map(|l| l.id.as_str()), Some("2"));
+        assert!(own_graphical(&logins, 0).is_none());
+    }
+}
@@ -1,10 +1,10 @@
-use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::net::UnixStream;
 use std::io::{Read, Write};
 use std::process::Command;
-use serde::Deserialize;
-use serde_json;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
 
 // New modular architecture
 mod formatter;
@@ -15,135 +15,416 @@ mod helpers;
 mod tmux;
 mod batch;
 mod errors;  // NEW: Error detection module
-
-#[cfg(test)]
-mod test_error_detection;
-
-use output::DisplayOutput;
+mod artifacts;
+mod criteria;
+mod canonical;
+mod workflows;
+mod cli;
+mod terminals;
+mod project;
+mod secrets;
+mod features;
+mod logging;
+mod risk;
+mod confirm;
+mod peer;
+mod audit;
+mod acl;
+mod throttle;
+mod sandbox;
+mod ownership;
+mod policy;
+mod leaks;
+mod killswitch;
+mod events;
+mod memory;
+mod apps;
+mod desktop;
+mod windows;
+mod autostart;
+mod icons;
+mod mimeapps;
+mod sysinfo;
+mod power;
+mod monitor;
+mod network;
+mod pkg;
+mod units;
+mod storage;
+mod login;
+mod thermal;
+mod supervisor;
+mod timings;
+mod doctor;
+mod watchdog;
+mod stats;
+mod eventlog;
+mod bundle;
+mod introspect;
+#[cfg(feature = "http")]
+mod gateway;
+
+use output::{DisplayOutput, OutputBudget};
+use errors::ErrorKind;
 use config::Config;
 use helpers::{response, params, Response};
-use helpers::security::{safe_json_response, escape_pgrep_pattern, validate_command, validate_desktop_entry};
+use helpers::security::{safe_json_response, escape_pgrep_pattern, validate_command, validate_desktop_entry, check_blocked_patterns};
 use serde_json::Value;
+use clap::Parser;
+use cli::{Commands, ConfigCommand};
 
-#[derive(Deserialize)]
-struct Request {
-    action: String,
-    data: Value,
-}
+use archy_protocol::Request;
+
+/// Id of the request being handled, echoed on its reply (the daemon serves one connection at a time)
+static REQUEST_ID: Mutex<Option<Value>> = Mutex::new(None);
+
+/// Peer of the request being handled (confirmation tokens are bound to it)
+static REQUESTER: Mutex<Option<peer::PeerCred>> = Mutex::new(None);
+
+/// When `serve` started, for `health`
+static STARTED: OnceLock<Instant> = OnceLock::new();
+
+/// Every action the dispatcher answers, for `describe`
+const ACTIONS: &[&str] = &[
+    "execute", "execute_analyzed", "execute_and_wait", "execute_smart", "batch_execute", "execute_batch",
+    "execute_batch_analyzed", "resume_batch", "confirm_execute", "capture", "capture_analyzed",
+    "check_session", "open_terminal", "close_terminal", "close_session", "claim_session", "panic_stop",
+    "resume", "is_foot_running", "check_command", "get_system_info", "power_info", "monitor", "network_info", "pkg_search", "pkg_info", "pkg_list_upgrades", "unit_status", "list_failed_units", "unit_journal_tail", "storage_health", "session_info", "thermal_info", "find_desktop_entry", "list_applications", "get_app_info", "is_app_running",
+    "extract_directory", "wait_for_prompt", "launch_gui_app", "open_with_default", "get_default_app", "set_default_app", "list_windows", "list_monitors", "focus_window",
+    "close_window", "add_autostart", "list_autostart", "remove_autostart", "detect_terminal", "launch_fallback_terminal",
+    "save_workflow", "list_workflows", "run_workflow", "get_artifact", "validate_config", "set_log_level",
+    "query_audit", "get_recent_requests", "collect_debug_bundle", "introspect", "doctor", "recall_similar_outputs", "health", "stats", "get_events", "describe",
+];
 
 fn main() -> std::io::Result<()> {
-    // Load configuration from environment
-    let config = Config::from_env();
-
-    // Remove old socket if exists
-    let _ = fs::remove_file(&config.socket_path);
-
-    let listener = UnixListener::bind(&config.socket_path)?;
-    println!("🦀 Archy Executor (Rust) listening on {}", config.socket_path);
-    println!("✅ Configuration loaded:");
-    println!("   • Socket: {}", config.socket_path);
-    println!("   • Default session: {}", config.default_session);
-    println!("   • Buffer size: {}", config.max_buffer_size);
-    println!("✅ Ready to handle system operations...\n");
-
-    for stream in listener.incoming() {
-        match stream {
-            Ok(stream) => {
-
-                if let Err(e) = handle_client(stream, &config) {
-                    eprintln!("❌ Client handler error: {}", e);
-                }
+    let cli = cli::Cli::parse();
+
+    let config_path = cli.config.as_deref();
+    let code = match cli.command {
+        None | Some(Commands::Serve) => return serve(&load_config_or_exit(config_path)),
+        Some(Commands::Exec { command, session, max_wait, json }) => {
+            cli::exec(&load_config_or_exit(config_path), &command, session.as_deref(), max_wait, json)
+        }
+        Some(Commands::Status) => cli::status(&load_config_or_exit(config_path)),
+        Some(Commands::Classify { commands }) => cli::classify(&load_config_or_exit(config_path), commands),
+        // `config check` reports problems itself instead of refusing to start
+        Some(Commands::Config { action: ConfigCommand::Check }) => match Config::load_layers(config_path) {
+            Ok((config, env_errors)) => cli::config_check(&config, &env_errors),
+            Err(e) => {
+                eprintln!("❌ {}", e);
+                1
             }
-            Err(e) => eprintln!("❌ Connection failed: {}", e),
+        },
+    };
+    std::process::exit(code);
+}
+
+/// Load layered configuration: defaults, config files, --config <file>, environment
+fn load_config_or_exit(path: Option<&str>) -> Config {
+    match Config::load(path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            std::process::exit(2);
         }
     }
-
-    Ok(())
 }
 
-fn handle_client(mut stream: UnixStream, config: &Config) -> std::io::Result<()> {
-    // Set read timeout to prevent hanging connections
-    use std::time::Duration;
-    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
-    stream.set_write_timeout(Some(Duration::from_secs(30)))?;
-
-    // Read the full request (handle partial reads)
-    let mut buffer = Vec::new();
-    let mut temp_buf = vec![0; 8192];
-    let mut total_read = 0;
+/// Run the daemon until the listener fails
+fn serve(config: &Config) -> std::io::Result<()> {
+    if let Err(e) = logging::init(config.log_level, config.log_format, config.log_file.as_deref()) {
+        eprintln!("❌ {}", e);
+        std::process::exit(2);
+    }
+    if let Err(e) = leaks::configure(config) {
+        tracing::error!("{}", e);
+        std::process::exit(2);
+    }
+    killswitch::install();
+    supervisor::install_panic_hook();
+    watchdog::spawn(config);
+    stats::load(config);
+    STARTED.get_or_init(Instant::now);
+    eventlog::emit(
+        eventlog::Kind::DaemonStarted,
+        None,
+        format!("Archy executor {} started", env!("CARGO_PKG_VERSION")),
+        serde_json::json!({"pid": std::process::id(), "config_files": config.config_files}),
+    );
+
+    let listener = supervisor::Listener::bind(&config.socket_path)?;
+    tracing::info!(
+        socket = %config.socket_path,
+        default_session = %config.default_session,
+        buffer_size = config.max_buffer_size,
+        max_request_bytes = config.unix_request_limit(),
+        log_level = %config.log_level,
+        config_files = ?config.config_files,
+        "Archy executor listening"
+    );
+    supervisor::spawn_acceptor(listener, config.clone(), introspect::answer_now);
+    #[cfg(feature = "http")]
+    if config.http_enabled {
+        if let Err(e) = gateway::spawn(config) {
+            tracing::error!("{}", e);
+            std::process::exit(2);
+        }
+    }
 
     loop {
-        match stream.read(&mut temp_buf) {
-            Ok(0) => break,  // EOF
-            Ok(n) => {
-                total_read += n;
-                buffer.extend_from_slice(&temp_buf[..n]);
-
-                // Try to parse - if successful, we have a complete message
-                if let Ok(_) = serde_json::from_slice::<Request>(&buffer) {
-                    break;
+        let incoming = supervisor::next();
+        let _quiet = watchdog::hold();
+        timings::start(incoming.accepted, incoming.read);
+        let reply_to = incoming.stream.try_clone().ok();
+        // A panicking handler costs its own request, not the daemon
+        match supervisor::isolate(|| handle_client(incoming, config)) {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::error!("Client handler error: {}", e),
+            Err(message) => {
+                if let Some(mut stream) = reply_to {
+                    let _ = send_error(&mut stream, ErrorKind::Failed, &format!("Internal error: {} - the daemon is still running", message));
                 }
+            }
+        }
+        audit::finish(config);
+        if let Some(mut timing) = timings::finish(config) {
+            // One bucket for names we don't serve, so junk requests can't grow the stats file
+            if !ACTIONS.contains(&timing.action.as_str()) {
+                timing.action.clear();
+            }
+            stats::record(&timing, config);
+        }
+        events::clear();
+        secrets::release();
+        *REQUEST_ID.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        *REQUESTER.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+fn handle_client(incoming: supervisor::Incoming, config: &Config) -> std::io::Result<()> {
+    let mut stream = incoming.stream;
+    let mut request = match incoming.request {
+        Ok(request) => request,
+        Err(e) => return send_error(&mut stream, e.kind, &e.message),
+    };
+    *REQUEST_ID.lock().unwrap_or_else(|e| e.into_inner()) = request.id.clone();
+
+    // Everything logged while handling the request carries its id, action and session
+    let span = tracing::info_span!(
+        "request",
+        id = tracing::field::Empty,
+        action = %request.action,
+        session = tracing::field::Empty,
+        confirmed = tracing::field::Empty,
+    );
+    if let Some(id) = &request.id {
+        span.record("id", tracing::field::display(id));
+    }
+    if let Some(session) = request_session(&request, config) {
+        span.record("session", session.as_str());
+    }
+    let _entered = span.enter();
+    time_request(&request, config);
+    timings::switch(timings::Phase::Validate);
+    watchdog::announce(config.get_session(&request.data));
+
+    // `argv` requests become one quoted command line up front, so the audit and every check see it
+    let argv = batch::resolve_argv(&mut request.data);
+    let (requester, granted) = (incoming.peer, incoming.granted);
+    *REQUESTER.lock().unwrap_or_else(|e| e.into_inner()) = requester;
+    begin_audit(&request, requester, false, config);
+
+    // Peers only get the actions their ACL level allows
+    if let Err(e) = supervisor::authorize(&request.action, requester, granted, config) {
+        tracing::warn!("{}", e);
+        eventlog::emit(
+            eventlog::Kind::AccessDenied,
+            request_session(&request, config).as_deref(),
+            e.clone(),
+            serde_json::json!({"action": request.action, "uid": requester.map(|peer| peer.uid)}),
+        );
+        send_error(&mut stream, ErrorKind::Denied, &e)?;
+        return Ok(());
+    }
+
+    if let Err(e) = argv {
+        send_error(&mut stream, ErrorKind::Validation, &e)?;
+        return Ok(());
+    }
 
-                // Prevent infinite reads
-                if total_read > config.max_buffer_size {
-                    send_error(&mut stream, "Request too large")?;
+    // A confirmed request runs exactly as it was held - the confirming client can't swap in other data
+    let confirmed = request.action == "confirm_execute";
+    if confirmed {
+        match params::extract_string(&request.data, "token").and_then(|token| confirm::redeem(&token, requester)) {
+            Ok((action, data)) => {
+                tracing::info!("Confirmed {}", action);
+                span.record("confirmed", action.as_str());
+                request = Request { action, data, id: request.id.take() };
+                begin_audit(&request, requester, true, config);
+                time_request(&request, config);
+                // The held request needs its own level too (it may have been held for someone else)
+                if let Err(e) = supervisor::authorize(&request.action, requester, granted, config) {
+                    send_error(&mut stream, ErrorKind::Denied, &e)?;
                     return Ok(());
                 }
             }
-            // FIX #2: Handle TimedOut instead of WouldBlock (socket is blocking with timeout)
-            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
-                // Socket timeout - we have partial data, try to parse it
-                if buffer.is_empty() {
-                    send_error(&mut stream, "Connection timeout")?;
-                }
-                break;
+            Err(e) => {
+                send_error(&mut stream, ErrorKind::Validation, &e)?;
+                return Ok(());
             }
+        }
+    }
+
+    // After panic_stop only reads are answered until an explicit resume
+    if let Err(e) = killswitch::check(&request.action) {
+        send_error(&mut stream, ErrorKind::Denied, &e)?;
+        return Ok(());
+    }
+
+    // Capabilities switched off in config never reach their handlers
+    if let Err(e) = config.features.check_action(&request.action) {
+        send_error(&mut stream, ErrorKind::Denied, &e)?;
+        return Ok(());
+    }
+
+    // Only sessions archy created or was handed via claim_session receive commands
+    if let Err(e) = ownership::check(&request.action, &request.data, config) {
+        send_error(&mut stream, ErrorKind::Denied, &e)?;
+        return Ok(());
+    }
+
+    // Destructive commands wait for confirm_execute (before secret expansion, so the held request keeps templates)
+    if !confirmed {
+        let held = require_confirmation(&request.action, &request.data, config)
+            .or_else(|| require_desktop_confirmation(&request.action, &request.data, config));
+        if let Some(held) = held {
+            eventlog::emit(
+                eventlog::Kind::ConfirmationRequired,
+                request_session(&request, config).as_deref(),
+                format!("{} is held until confirm_execute", request.action),
+                serde_json::json!({"action": request.action}),
+            );
+            return safe_json_response(&held, &mut stream);
+        }
+    }
+
+    // Rate limits and anomaly lockouts apply to whatever is about to run
+    if let Err(throttled) = check_throttle(&request.action, &request.data, config) {
+        eventlog::emit(
+            eventlog::Kind::Throttled,
+            Some(config.get_session(&request.data)),
+            throttled.error.clone(),
+            serde_json::json!({"status": throttled.status, "retry_after_seconds": throttled.retry_after_seconds}),
+        );
+        return safe_json_response(&throttled, &mut stream);
+    }
+
+    // Validation and blocked_patterns for every command-running action, not just plain execute
+    if let Err(e) = policy::check(&request.action, &request.data, config) {
+        send_error(&mut stream, ErrorKind::Validation, &e)?;
+        return Ok(());
+    }
+
+    // `{{secret:name}}` becomes a read of the secret file - saved workflows keep the template for run time
+    if request.action != "save_workflow" {
+        match secrets::expand_value(&request.data, config) {
+            Ok(data) => request.data = data,
             Err(e) => {
-                eprintln!("❌ Read error: {}", e);
+                send_error(&mut stream, ErrorKind::Validation, &e)?;
                 return Ok(());
             }
         }
     }
 
-    // FIX #7: Validate buffer is not empty and send error response
-    if buffer.is_empty() {
-        send_error(&mut stream, "Empty request received")?;
+    // Project-scoped settings (.archy.toml above the session's cwd)
+    if let Err(e) = apply_project(&request.action, &request.data, config) {
+        send_error(&mut stream, ErrorKind::Validation, &e)?;
         return Ok(());
     }
 
-    let request: Request = match serde_json::from_slice(&buffer) {
-        Ok(req) => req,
-        Err(e) => {
-            send_error(&mut stream, &format!("Invalid JSON: {}", e))?;
+    // Opt-in sandbox - wrapped last, so every check above saw the command as written
+    if COMMAND_ACTIONS.contains(&request.action.as_str()) {
+        if let Err(e) = sandbox::apply(&mut request.data, config) {
+            send_error(&mut stream, ErrorKind::Validation, &e)?;
             return Ok(());
         }
-    };
+    }
 
+    timings::switch(timings::Phase::Execute);
     let response = match request.action.as_str() {
         "execute" => execute_command(&request.data, config),
-        "execute_analyzed" => return handle_execute_analyzed(&mut stream, &request.data),
-        "execute_and_wait" => return handle_execute_and_wait(&mut stream, &request.data),
+        "execute_analyzed" => return handle_execute_analyzed(&mut stream, &request.data, config),
+        "execute_and_wait" => return handle_execute_and_wait(&mut stream, &request.data, config),
         "capture" => capture_tmux_output(&request.data, config),
-        "capture_analyzed" => return handle_capture_analyzed(&mut stream, &request.data),
+        "capture_analyzed" => return handle_capture_analyzed(&mut stream, &request.data, config),
         "check_session" => check_tmux_session(config),
         "open_terminal" => open_terminal(config),
-        "close_terminal" => close_terminal(),
-        "close_session" => close_session(&request.data),
-        "is_foot_running" => is_foot_running(),
+        "close_terminal" => close_terminal(config),
+        "close_session" => close_session(&request.data, config),
+        "claim_session" => claim_session(&request.data),
+        "panic_stop" => panic_stop(),
+        "resume" => resume_execution(),
+        "is_foot_running" => is_foot_running(config),
         "check_command" => check_command_available(&request.data),
-        "get_system_info" => get_system_info(),
-        "find_desktop_entry" => find_desktop_entry(&request.data),
+        "get_system_info" => return handle_get_system_info(&mut stream),
+        "power_info" => return handle_power_info(&mut stream),
+        "monitor" => return handle_monitor(&mut stream, &request.data, config),
+        "network_info" => return handle_network_info(&mut stream, &request.data),
+        "pkg_search" | "pkg_info" | "pkg_list_upgrades" => return handle_pkg_query(&mut stream, &request.action, &request.data),
+        "unit_status" => return handle_unit_status(&mut stream, &request.data),
+        "list_failed_units" => return handle_list_failed_units(&mut stream, &request.data),
+        "unit_journal_tail" => return handle_unit_journal_tail(&mut stream, &request.data),
+        "storage_health" => return handle_storage_health(&mut stream, &request.data),
+        "session_info" => return handle_session_info(&mut stream),
+        "thermal_info" => return handle_thermal_info(&mut stream, config),
+        "find_desktop_entry" => return handle_find_desktop_entry(&mut stream, &request.data, config),
+        "list_applications" => return handle_list_applications(&mut stream, &request.data, config),
+        "get_app_info" => return handle_get_app_info(&mut stream, &request.data, config),
+        "is_app_running" => return handle_is_app_running(&mut stream, &request.data, config),
         "extract_directory" => extract_current_directory(&request.data),
-        "wait_for_prompt" => wait_for_command_completion(&request.data),
-        "launch_gui_app" => launch_gui_app(&request.data),
-        "detect_terminal" => detect_terminal(),
-        "launch_fallback_terminal" => launch_fallback_terminal(&request.data),
+        "wait_for_prompt" => wait_for_command_completion(&request.data, config),
+        "launch_gui_app" => return safe_json_response(&launch_gui_app(&request.data, config, confirmed), &mut stream),
+        "open_with_default" => return safe_json_response(&open_with_default(&request.data, config), &mut stream),
+        "get_default_app" => return handle_get_default_app(&mut stream, &request.data, config),
+        "set_default_app" => return handle_set_default_app(&mut stream, &request.data, config),
+        "list_windows" => return handle_list_windows(&mut stream, &request.data),
+        "list_monitors" => return handle_list_monitors(&mut stream),
+        "focus_window" => focus_window(&request.data),
+        "close_window" => close_window(&request.data),
+        "add_autostart" => add_autostart(&request.data, config),
+        "list_autostart" => return handle_list_autostart(&mut stream),
+        "remove_autostart" => remove_autostart(&request.data, config),
+        "detect_terminal" => detect_terminal(config),
+        "launch_fallback_terminal" => launch_fallback_terminal(&request.data, config),
         "execute_smart" => execute_command_smart(&request.data, config),
         "batch_execute" => return handle_batch_execute(&mut stream, &request.data, config),
-        _ => response::error("Unknown action".to_string()),
+        "execute_batch" => return handle_execute_batch(&mut stream, &request.data, config),
+        "execute_batch_analyzed" => return handle_execute_batch_analyzed(&mut stream, &request.data, config),
+        "resume_batch" => return handle_resume_batch(&mut stream, &request.data, confirmed, config),
+        "save_workflow" => save_workflow(&request.data, config),
+        "list_workflows" => return handle_list_workflows(&mut stream, config),
+        "run_workflow" => return handle_run_workflow(&mut stream, &request.data, config),
+        "get_artifact" => get_artifact(&request.data, config),
+        "validate_config" => return safe_json_response(&config.diagnostics(), &mut stream),
+        "set_log_level" => set_log_level(&request.data),
+        "query_audit" => return handle_query_audit(&mut stream, &request.data, config),
+        "get_recent_requests" => return handle_get_recent_requests(&mut stream, &request.data, config),
+        "collect_debug_bundle" => return handle_collect_debug_bundle(&mut stream, &request.data, config),
+        "doctor" => return handle_doctor(&mut stream, config),
+        "recall_similar_outputs" => return handle_recall_similar_outputs(&mut stream, &request.data, config),
+        "health" => return safe_json_response(&health(config), &mut stream),
+        "stats" => return handle_stats(&mut stream, &request.data, config),
+        "get_events" => return handle_get_events(&mut stream, &request.data),
+        "describe" => return safe_json_response(&describe(config), &mut stream),
+        _ => return send_error(&mut stream, ErrorKind::UnknownAction, "Unknown action"),
     };
 
-    // FIX #1: Use safe_json_response instead of unwrap()
+    // These return once the command is sent - its secret files stay until it has actually run
+    if matches!(request.action.as_str(), "execute" | "execute_smart") {
+        let command = request.data.get("command").and_then(|v| v.as_str()).unwrap_or_default();
+        secrets::release_after(config.get_session(&request.data), command);
+    }
+
     safe_json_response(&response, &mut stream)?;
     Ok(())
 }
@@ -161,7 +442,8 @@ fn execute_command(data: &Value, config: &Config) -> Response {
     }
 
     // FIX: Use centralized command validation
-    if let Err(e) = validate_command(&command) {
+    if let Err(e) = validate_command(&command).and_then(|_| check_blocked_patterns(&command, &config.blocked_patterns)) {
+        events::blocked(&command, &e);
         return response::error(e);
     }
 
@@ -170,7 +452,7 @@ fn execute_command(data: &Value, config: &Config) -> Response {
     // Ensure session exists before sending command
     if !tmux::has_session(session) {
         if let Err(e) = tmux::new_session(session) {
-            eprintln!("⚠️ Failed to create session {}: {}", session, e);
+            tracing::warn!("Failed to create session {}: {}", session, e);
             return response::error(format!("Failed to create tmux session: {}", e));
         }
         // Brief wait for session initialization
@@ -190,7 +472,10 @@ fn capture_tmux_output(data: &Value, config: &Config) -> Response {
 
     // Use tmux module for capture
     match tmux::capture_pane(session, lines) {
-        Ok(output) => response::success(output),
+        Ok(output) => {
+            timings::captured(output.len());
+            response::success(output)
+        }
         Err(e) => response::error(e),
     }
 }
@@ -210,17 +495,13 @@ fn open_terminal(config: &Config) -> Response {
 
     // Check if session exists, create if not
     let has_session = Command::new("tmux")
-        .args(&["has-session", "-t", session])
+        .args(["has-session", "-t", session])
         .status();
 
     if let Ok(status) = has_session {
         if !status.success() {
             // Create new session
-            let create = Command::new("tmux")
-                .args(&["new-session", "-d", "-s", session])
-                .status();
-
-            if let Err(e) = create {
+            if let Err(e) = tmux::new_session(session) {
                 return Response {
                     success: false,
                     output: None,
@@ -233,42 +514,39 @@ fn open_terminal(config: &Config) -> Response {
 
     // FIX #3: Escape session name in pgrep pattern to prevent regex injection
     let escaped_session = escape_pgrep_pattern(session);
-    let check_foot = Command::new("pgrep")
-        .args(&["-f", &format!("foot.*tmux.*attach.*{}", escaped_session)])
+    let check_attached = Command::new("pgrep")
+        .args(["-f", &terminals::attached_pattern(config, Some(&escaped_session))])
         .output();
 
-    if let Ok(result) = check_foot {
-        if result.status.success() {
-            // foot is already running, don't open another one
-            return Response {
-                success: true,
-                output: Some("✓ Terminal already open (reattached)".to_string()),
-                error: None,
-                exists: None,
-            };
-        }
+    // tmux's own client list also sees terminals whose process line doesn't show the attach
+    if tmux::client_count(session) > 0 || check_attached.is_ok_and(|result| result.status.success()) {
+        // a terminal is already attached, don't open another one
+        return Response {
+            success: true,
+            output: Some("✓ Terminal already open (reattached)".to_string()),
+            error: None,
+            exists: None,
+        };
     }
 
-    // Open foot terminal attached to session (non-blocking, detached)
-    // FIX: Set environment variables for GUI/terminal to work correctly when running as systemd service
-    use helpers::environment;
-    let display = environment::get_display();
-    let xauthority = environment::get_xauthority();
-    let dbus_addr = environment::get_dbus_address();
-    let wayland_display = environment::get_wayland_display();
+    // Configured terminal, then preference list, then built-ins - first one installed wins
+    let terminal = match terminals::detect(config) {
+        Some(terminal) => terminal,
+        None => return response::error("No terminal emulator found".to_string()),
+    };
 
+    // Open terminal attached to session (non-blocking, detached)
+    // FIX: Set environment variables for GUI/terminal to work correctly when running as systemd service
     let result = Command::new("setsid")
-        .env("DISPLAY", &display)
-        .env("XAUTHORITY", &xauthority)
-        .env("DBUS_SESSION_BUS_ADDRESS", &dbus_addr)
-        .env("WAYLAND_DISPLAY", &wayland_display)
-        .args(&["foot", "-e", "tmux", "attach", "-t", session])
+        .envs(helpers::environment::Session::detect().gui_env())
+        .arg(&terminal.binary)
+        .args(terminal.attach_line(session))
         .spawn();
 
     match result {
         Ok(_) => Response {
             success: true,
-            output: Some("✓ Terminal opened".to_string()),
+            output: Some(format!("✓ Terminal opened ({})", terminal.name)),
             error: None,
             exists: None,
         },
@@ -281,11 +559,11 @@ fn open_terminal(config: &Config) -> Response {
     }
 }
 
-fn close_terminal() -> Response {
-    // Find foot processes by looking for foot running with tmux attach
+fn close_terminal(config: &Config) -> Response {
+    // Find terminal processes running tmux attach
     // The process line looks like: setsid foot -e tmux attach -t archy_session
     let output = Command::new("pgrep")
-        .args(&["-f", "foot.*tmux.*attach"])
+        .args(["-f", &terminals::attached_pattern(config, None)])
         .output();
 
     match output {
@@ -295,10 +573,14 @@ fn close_terminal() -> Response {
                 let mut closed_any = false;
 
                 for pid in pids.lines() {
-                    if let Ok(_) = Command::new("kill").arg(pid).status() {
+                    if Command::new("kill").arg(pid).status().is_ok() {
                         closed_any = true;
                     }
                 }
+                // Whatever window is still attached closes with its tmux client
+                if tmux::client_count(&config.default_session) > 0 {
+                    closed_any |= tmux::detach_clients(&config.default_session).is_ok();
+                }
 
                 if closed_any {
                     Response {
@@ -311,15 +593,17 @@ fn close_terminal() -> Response {
                     Response {
                         success: false,
                         output: None,
-                        error: Some("No foot terminal found".to_string()),
+                        error: Some("No attached terminal found".to_string()),
                         exists: None,
                     }
                 }
+            } else if tmux::client_count(&config.default_session) > 0 && tmux::detach_clients(&config.default_session).is_ok() {
+                response::success("✓ Terminal window closed".to_string())
             } else {
                 Response {
                     success: false,
                     output: None,
-                    error: Some("No foot terminal found".to_string()),
+                    error: Some("No attached terminal found".to_string()),
                     exists: None,
                 }
             }
@@ -333,27 +617,28 @@ fn close_terminal() -> Response {
     }
 }
 
-fn close_session(data: &serde_json::Value) -> Response {
-    let session = data.get("session")
-        .and_then(|v| v.as_str())
-        .unwrap_or("archy_session");
+fn close_session(data: &serde_json::Value, config: &Config) -> Response {
+    let session = config.get_session(data);
 
     // FIX #3: Escape session name in pgrep pattern
     let escaped_session = escape_pgrep_pattern(session);
 
-    // First close any foot terminals
+    // First close any attached terminals
     let _ = Command::new("pkill")
-        .args(&["-f", &format!("foot.*{}", escaped_session)])
+        .args(["-f", &terminals::attached_pattern(config, Some(&escaped_session))])
         .status();
 
     // Then kill the tmux session
+    project::forget_session(session);
+    watchdog::forget(session);
     let result = Command::new("tmux")
-        .args(&["kill-session", "-t", session])
+        .args(["kill-session", "-t", session])
         .status();
 
     match result {
         Ok(status) => {
             if status.success() {
+                eventlog::emit(eventlog::Kind::SessionKilled, Some(session), format!("Session {} closed", session), Value::Null);
                 Response {
                     success: true,
                     output: Some("✓ Session closed".to_string()),
@@ -378,16 +663,16 @@ fn close_session(data: &serde_json::Value) -> Response {
     }
 }
 
-fn is_foot_running() -> Response {
-    // Check if foot terminal is running by looking for foot with tmux attach
+/// Kept under its historical name - checks for any known terminal attached to tmux
+fn is_foot_running(config: &Config) -> Response {
     // The process line looks like: setsid foot -e tmux attach -t archy_session
     let output = Command::new("pgrep")
-        .args(&["-f", "foot.*tmux.*attach"])
+        .args(["-f", &terminals::attached_pattern(config, None)])
         .output();
 
     match output {
         Ok(result) => {
-            if result.status.success() && !result.stdout.is_empty() {
+            if (result.status.success() && !result.stdout.is_empty()) || tmux::client_count(&config.default_session) > 0 {
                 Response {
                     success: true,
                     output: None,
@@ -446,231 +731,411 @@ fn check_command_available(data: &serde_json::Value) -> Response {
     }
 }
 
-fn get_system_info() -> Response {
-    let output = Command::new("uname")
-        .arg("-a")
-        .output();
+/// Structured system facts, with the old one-line string kept as `output`
+fn handle_get_system_info(stream: &mut UnixStream) -> std::io::Result<()> {
+    let info = sysinfo::collect();
+    safe_json_response(&serde_json::json!({
+        "success": true,
+        "output": format!("System: {}", info.summary()),
+        "system": info,
+    }), stream)
+}
 
-    match output {
-        Ok(result) => {
-            if result.status.success() {
-                // FIX #4: Handle invalid UTF-8 properly instead of silently corrupting
-                let info = match String::from_utf8(result.stdout) {
-                    Ok(s) => s.trim().to_string(),
-                    Err(e) => {
-                        eprintln!("⚠️ Invalid UTF-8 in system info: {}", e);
-                        return Response {
-                            success: false,
-                            output: None,
-                            error: Some("Invalid UTF-8 in system output".to_string()),
-                            exists: None,
-                        };
-                    }
-                };
-                Response {
-                    success: true,
-                    output: Some(format!("System: {}", info)),
-                    error: None,
-                    exists: None,
-                }
-            } else {
-                Response {
-                    success: false,
-                    output: Some("System info unavailable".to_string()),
-                    error: None,
-                    exists: None,
-                }
-            }
+/// Battery and AC state, with findings for low batteries
+fn handle_power_info(stream: &mut UnixStream) -> std::io::Result<()> {
+    let power = power::collect();
+    safe_json_response(&serde_json::json!({
+        "success": true,
+        "output": power.summary(),
+        "on_ac": power.on_ac,
+        "on_battery": power.on_battery,
+        "batteries": power.batteries,
+        "source": power.source,
+        "findings": power.findings,
+    }), stream)
+}
+
+/// Interfaces, default routes and DNS servers, plus a connectivity probe with {probe: true}
+fn handle_network_info(stream: &mut UnixStream, data: &Value) -> std::io::Result<()> {
+    let probe = data.get("probe").and_then(|v| v.as_bool()).unwrap_or(false);
+    let network = network::collect(probe);
+    safe_json_response(&serde_json::json!({
+        "success": true,
+        "output": network.summary(),
+        "interfaces": network.interfaces,
+        "default_routes": network.default_routes,
+        "dns": network.dns,
+        "connectivity": network.connectivity,
+    }), stream)
+}
+
+/// A package query's parsed result: `packages`/`upgrades` cut to `limit`, or the `package`
+fn handle_pkg_query(stream: &mut UnixStream, action: &str, data: &Value) -> std::io::Result<()> {
+    let query = match pkg::PackageQuery::from_request(action, data) {
+        Ok(query) => query,
+        Err(e) => return send_error(stream, ErrorKind::Validation, &e),
+    };
+    let outcome = match query.run() {
+        Ok(outcome) => outcome,
+        Err(e) => return send_error(stream, ErrorKind::Failed, &e),
+    };
+    let limit = query.limit;
+    let mut structured = outcome.parsed.structured;
+    let mut total = None;
+    for key in ["packages", "upgrades"] {
+        if let Some(list) = structured.get_mut(key).and_then(|v| v.as_array_mut()) {
+            total = Some(list.len());
+            list.truncate(limit);
         }
-        Err(e) => Response {
-            success: false,
-            output: Some("System info unavailable".to_string()),
-            error: Some(e.to_string()),
-            exists: None,
-        },
     }
+    let mut reply = serde_json::json!({
+        "success": true,
+        "output": outcome.parsed.summary,
+        "manager": outcome.manager.name(),
+        "command": outcome.command,
+        "findings": outcome.parsed.findings,
+        "suggestions": outcome.parsed.suggestions,
+    });
+    for key in ["packages", "upgrades", "package"] {
+        if let Some(value) = structured.get_mut(key) {
+            reply[key] = value.take();
+        }
+    }
+    if let Some(total) = total {
+        reply["total"] = serde_json::json!(total);
+        reply["truncated"] = serde_json::json!(total > limit);
+    }
+    safe_json_response(&reply, stream)
+}
+
+/// One systemd unit's state, with findings when it has failed
+fn handle_unit_status(stream: &mut UnixStream, data: &Value) -> std::io::Result<()> {
+    let query = match units::UnitQuery::from_request(data) {
+        Ok(query) => query,
+        Err(e) => return send_error(stream, ErrorKind::Validation, &e),
+    };
+    let (status, source) = match units::status(&query) {
+        Ok(found) => found,
+        Err(e) => return send_error(stream, ErrorKind::Failed, &e),
+    };
+    let (findings, suggestions) = units::status_findings(&status);
+    safe_json_response(&serde_json::json!({
+        "success": true,
+        "output": format!("{}: {} ({})", status.id, status.active_state, status.sub_state),
+        "source": source,
+        "unit": status,
+        "findings": findings,
+        "suggestions": suggestions,
+    }), stream)
 }
 
-fn find_desktop_entry(data: &serde_json::Value) -> Response {
+/// Units in the failed state
+fn handle_list_failed_units(stream: &mut UnixStream, data: &Value) -> std::io::Result<()> {
+    let scope = match units::Scope::from_request(data) {
+        Ok(scope) => scope,
+        Err(e) => return send_error(stream, ErrorKind::Validation, &e),
+    };
+    let (failed, source) = match units::list_failed(scope) {
+        Ok(found) => found,
+        Err(e) => return send_error(stream, ErrorKind::Failed, &e),
+    };
+    let (findings, suggestions) = units::failed_findings(&failed);
+    safe_json_response(&serde_json::json!({
+        "success": true,
+        "output": format!("{} failed unit(s)", failed.len()),
+        "source": source,
+        "units": failed,
+        "findings": findings,
+        "suggestions": suggestions,
+    }), stream)
+}
+
+/// A unit's last journal entries, oldest first
+fn handle_unit_journal_tail(stream: &mut UnixStream, data: &Value) -> std::io::Result<()> {
+    let query = match units::UnitQuery::from_request(data) {
+        Ok(query) => query,
+        Err(e) => return send_error(stream, ErrorKind::Validation, &e),
+    };
+    let (entries, findings) = match units::journal_tail(&query) {
+        Ok(tail) => tail,
+        Err(e) => return send_error(stream, ErrorKind::Failed, &e),
+    };
+    safe_json_response(&serde_json::json!({
+        "success": true,
+        "output": format!("{} journal entries", entries.len()),
+        "entries": entries,
+        "findings": findings,
+    }), stream)
+}
+
+/// Filesystems, disks and SMART in one report, with findings for full, remounted and failing storage
+fn handle_storage_health(stream: &mut UnixStream, data: &Value) -> std::io::Result<()> {
+    let report = storage::collect(data);
+    safe_json_response(&serde_json::json!({
+        "success": true,
+        "output": report.summary(),
+        "filesystems": report.filesystems,
+        "disks": report.disks,
+        "smart_available": report.smart_available,
+        "findings": report.findings,
+        "suggestions": report.suggestions,
+    }), stream)
+}
+
+/// The graphical session, who is logged in, whether the user is idle, and the launch environment
+fn handle_session_info(stream: &mut UnixStream) -> std::io::Result<()> {
+    let info = login::collect();
+    safe_json_response(&serde_json::json!({
+        "success": true,
+        "output": info.summary(),
+        "graphical": info.graphical,
+        "runs_x11": info.runs_x11,
+        "logins": info.logins,
+        "idle": info.idle,
+        "idle_since_us": info.idle_since_us,
+        "env": info.env,
+        "source": info.source,
+    }), stream)
+}
+
+/// hwmon temperatures and fan speeds, with findings past the configured thresholds
+fn handle_thermal_info(stream: &mut UnixStream, config: &Config) -> std::io::Result<()> {
+    let info = thermal::collect(&config.thermal);
+    safe_json_response(&serde_json::json!({
+        "success": true,
+        "output": info.summary(),
+        "sensors": info.sensors,
+        "fans": info.fans,
+        "thresholds": config.thermal,
+        "findings": info.findings,
+        "suggestions": info.suggestions,
+    }), stream)
+}
+
+/// Stream resource samples on this connection, from a thread of its own - the daemon moves on at once
+fn handle_monitor(stream: &mut UnixStream, data: &Value, config: &Config) -> std::io::Result<()> {
+    let options = match monitor::Options::from_request(data, config.thermal) {
+        Ok(options) => options,
+        Err(e) => return send_error(stream, ErrorKind::Validation, &e),
+    };
+    let slot = match monitor::reserve() {
+        Ok(slot) => slot,
+        Err(e) => return send_error(stream, ErrorKind::Failed, &e),
+    };
+    let id = REQUEST_ID.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    monitor::spawn(stream.try_clone()?, options, id, slot);
+    Ok(())
+}
+
+/// Default and largest number of ranked `matches` find_desktop_entry returns
+const DEFAULT_APP_MATCHES: usize = 5;
+const MAX_APP_MATCHES: usize = 50;
+
+/// The desktop entry an app name refers to - `output` is its id, `source` says whether it is a
+/// desktop file, a flatpak or a snap (with the `app_id` flatpak/snap run take). `matches` ranks the
+/// entries the name could mean, with scores, so an ambiguous name ("code") can be resolved by asking
+fn handle_find_desktop_entry(stream: &mut UnixStream, data: &Value, config: &Config) -> std::io::Result<()> {
     let app_name = match data.get("app_name").and_then(|v| v.as_str()) {
         Some(name) => name,
-        None => return Response {
-            success: false,
-            output: None,
-            error: Some("Missing app_name parameter".to_string()),
-            exists: None,
-        },
+        None => return safe_json_response(&response::error("Missing app_name parameter".to_string()), stream),
     };
 
     // Validate app_name to prevent directory traversal
     if app_name.contains('/') || app_name.contains("..") || app_name.contains('\0') {
-        return Response {
-            success: false,
-            output: None,
-            error: Some("Invalid app_name: contains illegal characters".to_string()),
-            exists: None,
-        };
+        return safe_json_response(&response::error("Invalid app_name: contains illegal characters".to_string()), stream);
     }
 
     // Limit length
     if app_name.len() > 255 {
-        return Response {
-            success: false,
-            output: None,
-            error: Some("Invalid app_name: too long".to_string()),
-            exists: None,
-        };
+        return safe_json_response(&response::error("Invalid app_name: too long".to_string()), stream);
     }
+    let limit = data.get("limit")
+        .and_then(|v| v.as_u64())
+        .map_or(DEFAULT_APP_MATCHES, |n| (n as usize).clamp(1, MAX_APP_MATCHES));
 
-    let app_name_lower = app_name.to_lowercase();
+    let found = apps::with_index(config, |index| {
+        let matches = serde_json::to_value(index.search(app_name, limit)).unwrap_or_default();
+        (index.find(app_name).cloned(), matches)
+    });
+    match found {
+        Ok((Some(app), matches)) => safe_json_response(&serde_json::json!({
+            "success": true,
+            "output": app.id,
+            "error": null,
+            "exists": true,
+            "source": app.source,
+            "app_id": app.app_id,
+            "terminal": app.terminal,
+            "matches": matches,
+        }), stream),
+        Ok((None, matches)) => safe_json_response(&serde_json::json!({
+            "success": true,
+            "output": null,
+            "error": format!("Desktop entry '{}' not found", app_name),
+            "exists": false,
+            "matches": matches,
+        }), stream),
+        Err(e) => safe_json_response(&response::error(e), stream),
+    }
+}
 
-    let desktop_dirs = vec![
-        format!("{}/.local/share/applications", std::env::var("HOME").unwrap_or_default()),
-        "/usr/local/share/applications".to_string(),
-        "/usr/share/applications".to_string(),
-        "/usr/share/applications/kde4".to_string(),
-        "/usr/share/applications/kde5".to_string(),
-        format!("{}/.config/applications", std::env::var("HOME").unwrap_or_default()),
-        "/opt/applications".to_string(),
-    ];
+/// Installed applications from the desktop-entry index, filtered by `query` and `category`
+fn handle_list_applications(stream: &mut UnixStream, data: &Value, config: &Config) -> std::io::Result<()> {
+    match apps::list(data, config) {
+        Ok(applications) => safe_json_response(&serde_json::json!({
+            "success": true,
+            "count": applications.len(),
+            "applications": applications,
+        }), stream),
+        Err(e) => send_error(stream, ErrorKind::Validation, &e),
+    }
+}
 
-    // First pass: try exact filename match
-    for dir in &desktop_dirs {
-        let path = PathBuf::from(&dir);
-        if !path.exists() || !path.is_dir() {
-            continue;
-        }
+/// Default and largest icon size (pixels) and scale get_app_info resolves icons for
+const DEFAULT_ICON_SIZE: u32 = 48;
+const MAX_ICON_SIZE: u32 = 1024;
+const MAX_ICON_SCALE: u32 = 4;
 
-        let desktop_file = format!("{}/{}.desktop", dir, app_name);
-        if fs::metadata(&desktop_file).is_ok() {
-            return Response {
-                success: true,
-                output: Some(app_name.to_string()),
-                error: None,
-                exists: Some(true),
-            };
-        }
+/// An app's metadata for an app card: {app_name, size?, scale?, theme?} - the index entry (name,
+/// comment, categories, keywords, ...) and `icon_path`, its Icon resolved in the icon theme
+fn handle_get_app_info(stream: &mut UnixStream, data: &Value, config: &Config) -> std::io::Result<()> {
+    let app_name = match data.get("app_name").and_then(|v| v.as_str()) {
+        Some(name) => name,
+        None => return send_error(stream, ErrorKind::Validation, "Missing app_name parameter"),
+    };
+    if let Err(e) = validate_desktop_entry(app_name) {
+        return send_error(stream, ErrorKind::Validation, &e);
     }
-
-    // Second pass: search by Name field or Exec field
-    for dir in &desktop_dirs {
-        let path = PathBuf::from(&dir);
-        if !path.exists() || !path.is_dir() {
-            continue;
+    let size = data.get("size").and_then(|v| v.as_u64())
+        .map_or(DEFAULT_ICON_SIZE, |n| (n as u32).clamp(1, MAX_ICON_SIZE));
+    let scale = data.get("scale").and_then(|v| v.as_u64())
+        .map_or(1, |n| (n as u32).clamp(1, MAX_ICON_SCALE));
+    let theme = match data.get("theme").and_then(|v| v.as_str()) {
+        Some(theme) if theme.is_empty() || theme.contains('/') || theme == ".." => {
+            return send_error(stream, ErrorKind::Validation, "Invalid theme name");
         }
+        Some(theme) => theme.to_string(),
+        None => icons::current_theme(),
+    };
+    let app = match apps::with_index(config, |index| index.find(app_name).cloned()) {
+        Ok(Some(app)) => app,
+        Ok(None) => return send_error(stream, ErrorKind::Validation, &format!("Application '{}' not found", app_name)),
+        Err(e) => return send_error(stream, ErrorKind::Validation, &e),
+    };
+    // Only paths the filesystem policy lets a request see
+    let icon_path = app.icon.as_deref()
+        .and_then(|icon| icons::Lookup::system().find(icon, size, scale, &theme))
+        .and_then(|path| config.path_policy().ok()?.check(&path).ok());
+    safe_json_response(&serde_json::json!({
+        "success": true,
+        "output": app.id,
+        "exists": true,
+        "app": app,
+        "icon_path": icon_path,
+        "icon_theme": theme,
+    }), stream)
+}
 
-        if let Ok(entries) = fs::read_dir(&path) {
-            for entry in entries.flatten() {
-                let filepath = entry.path();
-                if let Some(ext) = filepath.extension() {
-                    if ext == "desktop" {
-                        if let Ok(content) = fs::read_to_string(&filepath) {
-                            let mut found = false;
-
-                            for line in content.lines() {
-                                // Check Name field
-                                if line.starts_with("Name=") && line.len() > 5 {
-                                    let name_value = &line[5..];
-                                    if name_value.eq_ignore_ascii_case(&app_name_lower) {
-                                        found = true;
-                                        break;
-                                    }
-                                }
-
-                                // Check GenericName field
-                                if line.starts_with("GenericName=") && line.len() > 12 {
-                                    let generic_value = &line[12..];
-                                    if generic_value.eq_ignore_ascii_case(&app_name_lower) {
-                                        found = true;
-                                        break;
-                                    }
-                                }
-
-                                // Check Exec field for exact command match
-                                if line.starts_with("Exec=") && line.len() > 5 {
-                                    let exec_value = &line[5..];
-                                    let parts: Vec<&str> = exec_value.split_whitespace().collect();
-                                    if !parts.is_empty() {
-                                        let command = parts[0];
-                                        let binary_name = if command.contains('/') {
-                                            command.rsplit('/').next().unwrap_or(command)
-                                        } else {
-                                            command
-                                        };
-
-                                        if binary_name.eq_ignore_ascii_case(&app_name_lower) {
-                                            found = true;
-                                            break;
-                                        }
-                                    }
-                                }
-                            }
+/// Whether the app `app_name` names (as find_desktop_entry resolves it) runs - `pids` of its
+/// processes and its `windows`
+fn handle_is_app_running(stream: &mut UnixStream, data: &Value, config: &Config) -> std::io::Result<()> {
+    let app_name = match data.get("app_name").and_then(|v| v.as_str()) {
+        Some(name) => name,
+        None => return send_error(stream, ErrorKind::Validation, "Missing app_name parameter"),
+    };
+    if let Err(e) = validate_desktop_entry(app_name) {
+        return send_error(stream, ErrorKind::Validation, &e);
+    }
+    let app = match apps::with_index(config, |index| index.find(app_name).cloned()) {
+        Ok(Some(app)) => app,
+        Ok(None) => return send_error(stream, ErrorKind::Validation, &format!("Application '{}' not found", app_name)),
+        Err(e) => return send_error(stream, ErrorKind::Validation, &e),
+    };
+    let running = apps::running(&app);
+    safe_json_response(&serde_json::json!({
+        "success": true,
+        "output": app.id,
+        "exists": running.is_running(),
+        "running": running.is_running(),
+        "pids": running.pids,
+        "windows": running.windows,
+    }), stream)
+}
 
-                            if found {
-                                if let Some(stem) = filepath.file_stem() {
-                                    let entry_name = stem.to_string_lossy().to_string();
-                                    return Response {
-                                        success: true,
-                                        output: Some(entry_name),
-                                        error: None,
-                                        exists: Some(true),
-                                    };
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
+/// Open windows, optionally only those matching {app?, title?, id?}
+fn handle_list_windows(stream: &mut UnixStream, data: &Value) -> std::io::Result<()> {
+    let matcher = windows::WindowMatch::from_request(data).unwrap_or_default();
+    match windows::find(&matcher) {
+        Ok((backend, windows)) => safe_json_response(&serde_json::json!({
+            "success": true,
+            "backend": backend,
+            "count": windows.len(),
+            "windows": windows,
+        }), stream),
+        Err(e) => send_error(stream, ErrorKind::Failed, &e),
     }
+}
 
-    // Third pass: fuzzy match (partial match) - BUT ONLY for longer app names
-    // Don't fuzzy match single-letter or 2-letter commands (ls, cd, ps, rm, etc.)
-    if app_name_lower.len() >= 4 {
-        for dir in &desktop_dirs {
-            let path = PathBuf::from(&dir);
-            if !path.exists() || !path.is_dir() {
-                continue;
-            }
+/// Connected monitors, for launch_gui_app's `monitor`
+fn handle_list_monitors(stream: &mut UnixStream) -> std::io::Result<()> {
+    match windows::Backend::detect().and_then(|backend| Ok((backend, backend.monitors()?))) {
+        Ok((backend, monitors)) => safe_json_response(&serde_json::json!({
+            "success": true,
+            "backend": backend,
+            "count": monitors.len(),
+            "monitors": monitors,
+        }), stream),
+        Err(e) => send_error(stream, ErrorKind::Failed, &e),
+    }
+}
 
-            if let Ok(entries) = fs::read_dir(&path) {
-                for entry in entries.flatten() {
-                    let filepath = entry.path();
-                    if let Some(ext) = filepath.extension() {
-                        if ext == "desktop" {
-                            if let Ok(content) = fs::read_to_string(&filepath) {
-                                for line in content.lines() {
-                                    if line.starts_with("Name=") && line.len() > 5 {
-                                        let name_value = &line[5..].to_lowercase();
-                                        // Only fuzzy match if it's a substantial match (>80% similar length)
-                                        let min_match_len = (app_name_lower.len() as f32 * 0.8) as usize;
-
-                                        if name_value.contains(app_name_lower.as_str()) && name_value.len() >= min_match_len {
-                                            if let Some(stem) = filepath.file_stem() {
-                                                return Response {
-                                                    success: true,
-                                                    output: Some(stem.to_string_lossy().to_string()),
-                                                    error: None,
-                                                    exists: Some(true),
-                                                };
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
+/// Bring the first window matching {app?, title?, id?} to the front
+fn focus_window(data: &Value) -> Response {
+    let matcher = match windows::WindowMatch::from_request(data) {
+        Ok(matcher) => matcher,
+        Err(e) => return response::error(e),
+    };
+    let (backend, found) = match windows::find(&matcher) {
+        Ok(found) => found,
+        Err(e) => return response::error(e),
+    };
+    let Some(window) = found.first() else {
+        return response::error("No matching window".to_string());
+    };
+    match backend.focus(window) {
+        Ok(()) if found.len() > 1 => response::success(format!(
+            "✓ Focused '{}' ({}) - {} windows matched", window.title, window.app, found.len()
+        )),
+        Ok(()) => response::success(format!("✓ Focused '{}' ({})", window.title, window.app)),
+        Err(e) => response::error(e),
     }
+}
 
-    Response {
-        success: true,
-        output: None,
-        error: Some(format!("Desktop entry '{}' not found", app_name)),
-        exists: Some(false),
+/// Close the window matching {app?, title?, id?} - several only with `all: true`
+fn close_window(data: &Value) -> Response {
+    let matcher = match windows::WindowMatch::from_request(data) {
+        Ok(matcher) => matcher,
+        Err(e) => return response::error(e),
+    };
+    let all = data.get("all").and_then(|v| v.as_bool()).unwrap_or(false);
+    let (backend, found) = match windows::find(&matcher) {
+        Ok(found) => found,
+        Err(e) => return response::error(e),
+    };
+    if found.is_empty() {
+        return response::error("No matching window".to_string());
+    }
+    if found.len() > 1 && !all {
+        let titles: Vec<String> = found.iter().map(|w| format!("{} '{}' ({})", w.id, w.title, w.app)).collect();
+        return response::error(format!(
+            "{} windows match - pass id, narrow by title, or set all: {}", found.len(), titles.join(", ")
+        ));
     }
+    let failed: Vec<String> = found.iter().filter_map(|window| backend.close(window).err()).collect();
+    if !failed.is_empty() {
+        return response::error(failed.join("; "));
+    }
+    response::success(format!("✓ Asked {} window(s) to close", found.len()))
 }
 
 fn extract_current_directory(data: &serde_json::Value) -> Response {
@@ -760,21 +1225,19 @@ fn extract_current_directory(data: &serde_json::Value) -> Response {
     }
 }
 
-fn wait_for_command_completion(data: &serde_json::Value) -> Response {
-    let session = data.get("session")
-        .and_then(|v| v.as_str())
-        .unwrap_or("archy_session");
+fn wait_for_command_completion(data: &serde_json::Value, config: &Config) -> Response {
+    let session = config.get_session(data);
 
     let max_wait_seconds = data.get("max_wait")
         .and_then(|v| v.as_u64())
-        .unwrap_or(600) as u64; // Default 10 minutes
+        .unwrap_or(600); // Default 10 minutes
 
     // Cap max_wait to prevent abuse (max 1 hour)
     let max_wait_seconds = max_wait_seconds.min(3600);
 
     let check_interval_ms = data.get("interval_ms")
         .and_then(|v| v.as_u64())
-        .unwrap_or(500) as u64; // Default 500ms
+        .unwrap_or(500); // Default 500ms
 
     // Cap check interval to prevent rapid polling (min 100ms)
     let check_interval_ms = check_interval_ms.max(100);
@@ -783,356 +1246,895 @@ fn wait_for_command_completion(data: &serde_json::Value) -> Response {
         .and_then(|v| v.as_str())
         .unwrap_or("");
 
-    use std::time::{Duration, Instant};
-    use std::thread;
-
-    let start_time = Instant::now();
-    let max_duration = Duration::from_secs(max_wait_seconds);
-    let check_interval = Duration::from_millis(check_interval_ms);
-
-    let mut last_output = String::new();
-    let mut stable_count = 0;
-    let required_stable_checks = 3; // Output must be stable for 3 checks
-
-    while start_time.elapsed() < max_duration {
-        thread::sleep(check_interval);
-
-        // Capture current output
-        let output_result = Command::new("tmux")
-            .args(&["capture-pane", "-pt", session, "-S", "-100"])
-            .output();
-
-        if let Ok(out) = output_result {
-            if out.status.success() {
-                // FIX #4: Handle invalid UTF-8 properly
-                let current_output = match String::from_utf8(out.stdout) {
-                    Ok(s) => s,
-                    Err(e) => {
-                        eprintln!("⚠️ Invalid UTF-8 in tmux output: {}", e);
-                        continue;
-                    }
-                };
-
-                // Check if output has stabilized (FIX #6: Don't clone full string every loop)
-                if current_output == last_output {
-                    stable_count += 1;
-                } else {
-                    stable_count = 0;
-                    last_output = current_output.clone();
-                }
+    let outcome = tmux::wait_for_completion(session, command, max_wait_seconds * 1000, check_interval_ms);
 
-                // Look for prompt in last line
-                let lines: Vec<&str> = current_output.trim().split('\n').collect();
-                if let Some(last_line) = lines.last() {
-                    // FIX #5: Support more shell prompts
-                    let has_prompt = last_line.contains('$') ||
-                                   last_line.contains('#') ||
-                                   last_line.contains('❯') ||
-                                   last_line.contains('>') ||
-                                   last_line.contains('❮') ||
-                                   last_line.contains('⚡');
-
-                    // Make sure the command itself is not in the last line (it just echoed)
-                    let command_not_echoed = !last_line.contains(command) || command.is_empty();
-
-                    // Check if it's waiting for password
-                    let waiting_for_password = last_line.to_lowercase().contains("password for") ||
-                                              last_line.to_lowercase().contains("[sudo]");
-
-                    if !waiting_for_password && has_prompt && command_not_echoed && stable_count >= required_stable_checks {
-                        return Response {
-                            success: true,
-                            output: Some(current_output),
-                            error: None,
-                            exists: Some(true),
-                        };
-                    }
-                }
-            }
+    if outcome.completed {
+        Response {
+            success: true,
+            output: Some(outcome.output),
+            error: None,
+            exists: Some(true),
+        }
+    } else {
+        // Timeout reached
+        Response {
+            success: false,
+            output: Some(outcome.output),
+            error: Some("Command timeout - may still be running".to_string()),
+            exists: Some(false),
         }
     }
+}
 
-    // Timeout reached
-    Response {
-        success: false,
-        output: Some(last_output),
-        error: Some("Command timeout - may still be running".to_string()),
-        exists: Some(false),
+/// Actions that run commands in the session - project settings and confirmation apply to these
+const COMMAND_ACTIONS: &[&str] = &[
+    "execute", "execute_analyzed", "execute_and_wait", "execute_smart",
+    "batch_execute", "execute_batch", "execute_batch_analyzed",
+];
+
+/// Enforce the session's project `allowed_commands` and activate its cwd/env before running anything
+fn apply_project(action: &str, data: &Value, config: &Config) -> Result<(), String> {
+    if !COMMAND_ACTIONS.contains(&action) {
+        return Ok(());
     }
-}
 
-fn send_error(stream: &mut UnixStream, msg: &str) -> std::io::Result<()> {
-    let response = Response {
-        success: false,
-        output: None,
-        error: Some(msg.to_string()),
-        exists: None,
+    let session = config.get_session(data);
+    let project = match project::ProjectConfig::for_session(session)? {
+        Some(project) => project,
+        None => return Ok(()),
     };
-    safe_json_response(&response, stream)?;
-    Ok(())
-}
 
-/// Helper to safely send JSON response and gracefully handle serialization errors
-fn send_json_response<T: serde::Serialize>(stream: &mut UnixStream, data: &T) -> std::io::Result<()> {
-    match serde_json::to_string(data) {
-        Ok(json) => {
-            stream.write_all(json.as_bytes())?;
-            stream.flush()?;
-        }
-        Err(e) => {
-            eprintln!("⚠️ JSON serialization error: {}", e);
-            let fallback = r#"{"success":false,"output":null,"error":"Internal serialization error","exists":null}"#;
-            let _ = stream.write_all(fallback.as_bytes());
-            let _ = stream.flush();
+    for command in &request_commands(data) {
+        project.check_command(command)?;
+    }
+
+    if is_dry_run(data) {
+        return Ok(());
+    }
+    project.activate(session, config)
+}
+
+/// Every command a request would run (single `command`, or batch `commands` with their rollback
+/// compensations)
+fn request_commands(data: &Value) -> Vec<String> {
+    if data.get("commands").is_some() {
+        // Malformed batches are reported by the batch handler itself
+        batch::parse_steps(data)
+            .map(|steps| steps.into_iter().flat_map(|s| std::iter::once(s.command).chain(s.compensation)).collect())
+            .unwrap_or_default()
+    } else {
+        data.get("command").and_then(|v| v.as_str()).map(|c| vec![c.to_string()]).unwrap_or_default()
+    }
+}
+
+/// Open the audit entry for a request (before secret expansion, so templates are logged rather than values)
+fn begin_audit(request: &Request, requester: Option<peer::PeerCred>, confirmed: bool, config: &Config) {
+    audit::begin(&request.action, request_commands(&request.data), request_session(request, config), requester, confirmed);
+}
+
+/// Name the request on its timing entry
+fn time_request(request: &Request, config: &Config) {
+    timings::identify(request.id.as_ref(), &request.action, request_session(request, config), &request_commands(&request.data), &request.data);
+}
+
+/// The tmux session a request names, or the default one when it runs commands without naming one
+fn request_session(request: &Request, config: &Config) -> Option<String> {
+    request.data.get("session")
+        .and_then(|v| v.as_str())
+        .map(str::to_string)
+        .or_else(|| (!request_commands(&request.data).is_empty()).then(|| config.default_session.clone()))
+}
+
+/// Emergency brake: interrupt every managed pane and refuse execution until `resume`
+fn panic_stop() -> Response {
+    let sessions = killswitch::engage("panic_stop");
+    let interrupted = if sessions.is_empty() {
+        "no managed sessions were running".to_string()
+    } else {
+        format!("interrupted {}", sessions.join(", "))
+    };
+    response::success(format!("🛑 Emergency stop engaged - {}. Send resume to continue", interrupted))
+}
+
+fn resume_execution() -> Response {
+    match killswitch::resume() {
+        Ok(reason) => response::success(format!("✓ Execution resumed (stop was: {})", reason)),
+        Err(e) => response::error(e),
+    }
+}
+
+/// Mark an existing session as managed, so archy may send commands to it
+fn claim_session(data: &Value) -> Response {
+    let session = match params::extract_string(data, "session") {
+        Ok(session) => session,
+        Err(e) => return response::error(e),
+    };
+    if !tmux::has_session(&session) {
+        return response::error(format!("Session '{}' not found", session));
+    }
+    match tmux::mark_managed(&session) {
+        Ok(()) => {
+            tracing::info!("Session '{}' claimed", session);
+            eventlog::emit(eventlog::Kind::SessionClaimed, Some(&session), format!("Session {} claimed", session), Value::Null);
+            response::success(format!("✓ Session '{}' is now managed by archy", session))
         }
+        Err(e) => response::error(format!("Failed to claim session '{}': {}", session, e.trim())),
     }
-    let _ = stream.shutdown(std::net::Shutdown::Both);
-    Ok(())
 }
 
-fn launch_gui_app(data: &serde_json::Value) -> Response {
-    let desktop_entry = match data.get("desktop_entry").and_then(|v| v.as_str()) {
-        Some(entry) => entry,
-        None => return Response {
-            success: false,
-            output: None,
-            error: Some("Missing desktop_entry parameter".to_string()),
-            exists: None,
-        },
+fn requester() -> Option<peer::PeerCred> {
+    *REQUESTER.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Hold destructive requests until a human confirms them (dry runs never execute, so they pass)
+fn require_confirmation(action: &str, data: &Value, config: &Config) -> Option<confirm::ConfirmationRequired> {
+    if !COMMAND_ACTIONS.contains(&action) || is_dry_run(data) {
+        return None;
+    }
+    confirm::gate(action, data, &request_commands(data), requester(), config)
+}
+
+/// Count a command-running request against the rate limits (dry runs execute nothing)
+fn check_throttle(action: &str, data: &Value, config: &Config) -> Result<(), throttle::Throttled> {
+    if !COMMAND_ACTIONS.contains(&action) || is_dry_run(data) {
+        return Ok(());
+    }
+    throttle::check(config.get_session(data), &request_commands(data), config)
+}
+
+/// Echo the request id and give failures an `error_code` (see archy-protocol)
+fn stamp_reply(reply: Value) -> Value {
+    archy_protocol::stamp(reply, REQUEST_ID.lock().unwrap_or_else(|e| e.into_inner()).as_ref())
+}
+
+/// Liveness probe: who is answering, for how long, and whether panic_stop is in force
+fn health(config: &Config) -> Value {
+    let mut reply = archy_protocol::health("archy-executor", env!("CARGO_PKG_VERSION"), *STARTED.get_or_init(Instant::now));
+    reply["socket"] = serde_json::json!(config.socket_path);
+    reply["stopped"] = serde_json::json!(killswitch::is_stopped());
+    reply["panics"] = serde_json::json!(supervisor::panics());
+    reply["socket_rebinds"] = serde_json::json!(supervisor::rebinds());
+    reply["session_resets"] = serde_json::json!(watchdog::resets());
+    reply
+}
+
+/// Introspection: every action with the access level it needs and whether config switched it off
+fn describe(config: &Config) -> Value {
+    let actions: Vec<Value> = ACTIONS.iter()
+        .map(|action| serde_json::json!({
+            "name": action,
+            "access": acl::required(action).as_str(),
+            "enabled": config.features.check_action(action).is_ok(),
+        }))
+        .collect();
+    archy_protocol::describe("archy-executor", env!("CARGO_PKG_VERSION"), Value::Array(actions))
+}
+
+fn send_error(stream: &mut UnixStream, kind: ErrorKind, msg: &str) -> std::io::Result<()> {
+    safe_json_response(&archy_protocol::error_reply(kind, msg), stream)
+}
+
+/// Send a DisplayOutput after evaluating success criteria and enforcing size budgets, rendering an older schema if the client asked for one via `schema_version`
+fn send_display_output(stream: &mut UnixStream, mut output: DisplayOutput, data: &Value, config: &Config) -> std::io::Result<()> {
+    let _format = timings::enter(timings::Phase::Format);
+    timings::captured(output.raw_output.len());
+
+    // Project parser rules add findings before criteria are evaluated against them
+    if let Ok(Some(project)) = project::ProjectConfig::for_session(config.get_session(data)) {
+        let findings = project.findings(&output.raw_output);
+        output.add_findings(findings);
+    }
+
+    // Declarative success criteria are evaluated against the full, untruncated output
+    match criteria::from_request(data) {
+        Ok(Some(checks)) => {
+            let report = criteria::evaluate(&checks, &output);
+            output.set_criteria(report);
+        }
+        Ok(None) => {}
+        Err(e) => return safe_json_response(&DisplayOutput::from_error(&output.command, ErrorKind::Validation, &e), stream),
+    }
+
+    // Session-output memory gets the complete findings, before budgets trim anything
+    memory::remember(&output, config.get_session(data), config);
+
+    output.enforce_budget(&OutputBudget::from_config(config), &config.artifact_dir);
+
+    // Theme: colors disabled means `display` carries the plain rendering too
+    if !config.colors {
+        output.display = output.display_plain.clone();
+    }
+
+    // Optional multi-format bundle, e.g. "formats": ["ansi", "plain", "markdown"]
+    if let Some(formats) = data.get("formats").and_then(|v| v.as_array()) {
+        let formats: Vec<String> = formats.iter()
+            .filter_map(|f| f.as_str())
+            .map(|f| f.to_string())
+            .collect();
+        if let Err(e) = output.add_renders(&formats) {
+            return safe_json_response(&DisplayOutput::from_error(&output.command, ErrorKind::Validation, &e), stream);
+        }
+    }
+
+    let canonical = data.get("canonical").and_then(|v| v.as_bool()).unwrap_or(false);
+    let rendered = match data.get("schema_version").and_then(|v| v.as_u64()) {
+        Some(version) => output.render_for_schema(version as u32),
+        None if canonical => serde_json::to_value(&output).map_err(|e| format!("Serialization error: {}", e)),
+        None => return safe_json_response(&output, stream),
     };
 
-    // Use centralized validation helper
-    if let Err(e) = validate_desktop_entry(desktop_entry) {
-        return Response {
-            success: false,
-            output: None,
-            error: Some(e),
-            exists: None,
-        };
+    let rendered = match rendered {
+        Ok(value) => value,
+        Err(e) => return safe_json_response(&DisplayOutput::from_error(&output.command, ErrorKind::Validation, &e), stream),
+    };
+
+    // Canonical mode: sorted keys and normalized floats, byte-identical for identical results
+    if canonical {
+        return safe_json_response(&canonical::canonicalize(&rendered), stream);
     }
 
-    // Get current environment variables (DISPLAY, DBUS, etc.) using helpers
-    use helpers::environment;
-    let display = environment::get_display();
-    let xauthority = environment::get_xauthority();
-    let dbus_addr = environment::get_dbus_address();
-    let wayland_display = environment::get_wayland_display();
-
-    // Try gtk-launch first (most reliable)
-    let gtk_result = Command::new("gtk-launch")
-        .env("DISPLAY", &display)
-        .env("XAUTHORITY", &xauthority)
-        .env("DBUS_SESSION_BUS_ADDRESS", &dbus_addr)
-        .env("WAYLAND_DISPLAY", &wayland_display)
-        .arg(desktop_entry)
-        .spawn();
+    safe_json_response(&rendered, stream)
+}
 
-    if let Ok(mut child) = gtk_result {
-        // Give it a moment to start
-        std::thread::sleep(std::time::Duration::from_millis(100));
+/// Fetch (a window of) an overflow artifact referenced by a truncated DisplayOutput
+fn get_artifact(data: &Value, config: &Config) -> Response {
+    let artifact_ref = match params::extract_string(data, "artifact_ref") {
+        Ok(id) => id,
+        Err(e) => return response::error(e),
+    };
+    let offset = params::extract_u64(data, "offset", 0) as usize;
+    let length = data.get("length").and_then(|v| v.as_u64()).map(|n| n as usize);
 
-        match child.try_wait() {
-            Ok(Some(status)) => {
-                if !status.success() {
-                    // gtk-launch failed, continue to fallback
-                } else {
-                    return Response {
-                        success: true,
-                        output: Some(format!("✓ GUI app '{}' launched via gtk-launch", desktop_entry)),
-                        error: None,
-                        exists: None,
-                    };
-                }
-            }
-            Ok(None) => {
-                // Still running - success! Don't kill it, just detach
-                return Response {
-                    success: true,
-                    output: Some(format!("✓ GUI app '{}' launched via gtk-launch", desktop_entry)),
-                    error: None,
-                    exists: None,
-                };
-            }
-            Err(_e) => {
-                // Error checking status, continue to fallback
-            }
+    response::from_result(artifacts::load(&config.artifact_dir, &artifact_ref, offset, length))
+}
+
+/// Search the audit log (`verify` also checks the hash chain)
+fn handle_query_audit(stream: &mut UnixStream, data: &Value, config: &Config) -> std::io::Result<()> {
+    let query: audit::AuditQuery = match serde_json::from_value(data.clone()) {
+        Ok(query) => query,
+        Err(e) => return safe_json_response(&response::error(format!("Invalid audit query: {}", e)), stream),
+    };
+    match audit::query(&config.audit_log, &query) {
+        Ok(result) => safe_json_response(&result, stream),
+        Err(e) => safe_json_response(&response::error(e), stream),
+    }
+}
+
+/// The last requests with their per-phase timings, newest first
+fn handle_get_recent_requests(stream: &mut UnixStream, data: &Value, config: &Config) -> std::io::Result<()> {
+    let query: timings::RecentQuery = match serde_json::from_value(data.clone()) {
+        Ok(query) => query,
+        Err(e) => return send_error(stream, ErrorKind::Validation, &format!("Invalid recent requests query: {}", e)),
+    };
+    let requests = timings::recent(&query);
+    let slow = requests.iter().filter(|request| request.slow).count();
+    safe_json_response(&serde_json::json!({
+        "success": true,
+        "output": format!("{} request(s), {} slow", requests.len(), slow),
+        "requests": requests,
+        "capacity": config.recent_requests,
+        "slow_request_ms": config.slow_request_ms,
+    }), stream)
+}
+
+/// Latency percentiles per action, failure rates and most-run commands since counting started
+fn handle_stats(stream: &mut UnixStream, data: &Value, config: &Config) -> std::io::Result<()> {
+    let query: stats::StatsQuery = match serde_json::from_value(data.clone()) {
+        Ok(query) => query,
+        Err(e) => return send_error(stream, ErrorKind::Validation, &format!("Invalid stats query: {}", e)),
+    };
+    match stats::report(&query, config) {
+        Ok(report) => safe_json_response(&serde_json::json!({
+            "success": true,
+            "output": report.summary(),
+            "goal_ms": report.goal_ms,
+            "since_ms": report.since_ms,
+            "missing_goal": report.missing_goal,
+            "actions": report.actions,
+            "top_commands": report.top_commands,
+            "reset": query.reset,
+        }), stream),
+        Err(e) => send_error(stream, ErrorKind::Denied, &e),
+    }
+}
+
+/// Daemon events after the client's cursor (`since`), oldest first
+fn handle_get_events(stream: &mut UnixStream, data: &Value) -> std::io::Result<()> {
+    let query: eventlog::EventQuery = match serde_json::from_value(data.clone()) {
+        Ok(query) => query,
+        Err(e) => return send_error(stream, ErrorKind::Validation, &format!("Invalid events query: {}", e)),
+    };
+    let page = eventlog::query(&query);
+    let mut output = format!("{} event(s)", page.events.len());
+    if page.missed > 0 {
+        output.push_str(&format!(", {} missed", page.missed));
+    }
+    safe_json_response(&serde_json::json!({
+        "success": true,
+        "output": output,
+        "events": page.events,
+        "next": page.next,
+        "missed": page.missed,
+    }), stream)
+}
+
+/// Logs, recent requests, config, versions and environment in one redacted tarball for a bug report
+fn handle_collect_debug_bundle(stream: &mut UnixStream, data: &Value, config: &Config) -> std::io::Result<()> {
+    let options: bundle::BundleOptions = match serde_json::from_value(data.clone()) {
+        Ok(options) => options,
+        Err(e) => return send_error(stream, ErrorKind::Validation, &format!("Invalid bundle options: {}", e)),
+    };
+    match bundle::collect(&options, config) {
+        Ok(bundle) => {
+            tracing::info!("Debug bundle written to {}", bundle.path);
+            safe_json_response(&serde_json::json!({
+                "success": true,
+                "output": format!("Debug bundle written to {} ({} bytes)", bundle.path, bundle.bytes),
+                "path": bundle.path,
+                "bytes": bundle.bytes,
+                "files": bundle.files,
+            }), stream)
+        }
+        Err(e) => send_error(stream, ErrorKind::Io, &e),
+    }
+}
+
+/// Environment checks (tmux, shell, terminals, socket, display, log space) with remediation hints
+fn handle_doctor(stream: &mut UnixStream, config: &Config) -> std::io::Result<()> {
+    let report = doctor::run(config);
+    safe_json_response(&serde_json::json!({
+        "success": true,
+        "output": report.summary(),
+        "status": report.status,
+        "checks": report.checks,
+    }), stream)
+}
+
+/// Past results similar to the given text, from the brain's session-output memory
+fn handle_recall_similar_outputs(stream: &mut UnixStream, data: &Value, config: &Config) -> std::io::Result<()> {
+    match memory::recall(data, config) {
+        Ok(reply) => safe_json_response(&reply, stream),
+        Err((kind, e)) => send_error(stream, kind, &e),
+    }
+}
+
+/// Change the daemon's log level without a restart (e.g. `debug` while chasing a problem)
+fn set_log_level(data: &Value) -> Response {
+    let level = match params::extract_string(data, "level") {
+        Ok(level) => level,
+        Err(e) => return response::error(e),
+    };
+
+    match logging::set_level(&level) {
+        Ok(previous) => {
+            let current = logging::level();
+            tracing::info!("Log level changed from {} to {}", previous, current);
+            eventlog::emit(
+                eventlog::Kind::ConfigChanged,
+                None,
+                format!("Log level changed from {} to {}", previous, current),
+                serde_json::json!({"key": "log_level", "from": previous.as_str().to_lowercase(), "to": current.as_str().to_lowercase()}),
+            );
+            response::success(format!(
+                "Log level set to {} (was {})",
+                current.as_str().to_lowercase(),
+                previous.as_str().to_lowercase()
+            ))
         }
+        Err(e) => response::error(e),
     }
+}
+
+/// A desktop file that passed the path policy and the owner/permission checks
+struct DesktopCandidate {
+    path: PathBuf, // canonical
+    age: std::time::Duration, // since last modification
+}
 
-    // Fallback: Try to find and execute the desktop entry directly
+/// Desktop files for `entry` that may be launched: allowed by the path policy, owned by root or the
+/// daemon's user, and not writable by group or others. Rejected files are logged and left out
+fn desktop_candidates(entry: &str, config: &Config) -> Result<Vec<DesktopCandidate>, String> {
+    use std::os::unix::fs::MetadataExt;
 
-    let desktop_dirs = vec![
-        format!("{}/.local/share/applications", std::env::var("HOME").unwrap_or_default()),
-        "/usr/local/share/applications".to_string(),
-        "/usr/share/applications".to_string(),
+    let policy = config.path_policy()?;
+    let mut desktop_dirs = vec![
+        PathBuf::from(format!("{}/.local/share/applications", std::env::var("HOME").unwrap_or_default())),
+        PathBuf::from("/usr/local/share/applications"),
+        PathBuf::from("/usr/share/applications"),
     ];
+    desktop_dirs.extend(apps::package_directories());
 
-    for dir in desktop_dirs {
-        let desktop_file = format!("{}/{}.desktop", dir, desktop_entry);
-
-        if let Ok(content) = fs::read_to_string(&desktop_file) {
-
-            // Parse the desktop file more carefully
-            for line in content.lines() {
-                if line.starts_with("Exec=") && line.len() > 5 {
-                    let exec_line = &line[5..];
-
-                    // Handle desktop entry codes like %U, %F, %i, %c, %k, etc.
-                    let exec_line = exec_line
-                        .replace("%U", "")
-                        .replace("%F", "")
-                        .replace("%u", "")
-                        .replace("%f", "")
-                        .replace("%i", "")
-                        .replace("%c", "")
-                        .replace("%k", "")
-                        .replace("%v", "");
-
-                    let exec_line = exec_line.trim();
-                    if exec_line.is_empty() {
-                        continue;
-                    }
+    let mut candidates = Vec::new();
+    for file in desktop_dirs.iter().map(|dir| dir.join(format!("{}.desktop", entry))) {
+        if !file.exists() {
+            continue;
+        }
+        let vetted = policy.check(&file).and_then(|path| {
+            let meta = fs::metadata(&path).map_err(|e| format!("Cannot stat {}: {}", path.display(), e))?;
+            if meta.uid() != 0 && meta.uid() != peer::own_uid() {
+                return Err(format!("{} is owned by uid {}", path.display(), meta.uid()));
+            }
+            if meta.mode() & 0o022 != 0 {
+                return Err(format!("{} is writable by group or others", path.display()));
+            }
+            let age = meta.modified().ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .unwrap_or_default();
+            Ok(DesktopCandidate { path, age })
+        });
+        match vetted {
+            Ok(candidate) => candidates.push(candidate),
+            Err(e) => tracing::warn!("Skipping desktop file: {}", e),
+        }
+    }
+    Ok(candidates)
+}
 
-                    let parts: Vec<&str> = exec_line.split_whitespace().collect();
-                    if parts.is_empty() {
-                        continue;
-                    }
+/// Launching from a desktop file modified within the trust window needs confirm_execute
+fn require_desktop_confirmation(action: &str, data: &Value, config: &Config) -> Option<confirm::ConfirmationRequired> {
+    if action != "launch_gui_app" || config.desktop_trust_seconds == 0 {
+        return None;
+    }
+    let entry = data.get("desktop_entry").and_then(|v| v.as_str())?;
+    validate_desktop_entry(entry).ok()?;
+    let recent = desktop_candidates(entry, config).ok()?
+        .into_iter()
+        .find(|c| c.age.as_secs() < config.desktop_trust_seconds)?;
+
+    let reason = format!("desktop file {} was modified {}s ago", recent.path.display(), recent.age.as_secs());
+    let risk = risk::RiskAssessment {
+        class: risk::RiskClass::Modifying,
+        reason: reason.clone(),
+        requires_confirmation: true,
+    };
+    let commands = vec![recent.path.to_string_lossy().to_string()];
+    Some(confirm::hold(action, data, commands, risk, &reason, requester(), config))
+}
 
-                    let exec_path = parts[0];
-                    eprintln!("    Exec path: {}", exec_path);
-
-                    // Try to execute it - be more permissive for direct execution
-                    // First check if it exists and is executable
-                    if let Ok(metadata) = std::fs::metadata(exec_path) {
-                        #[cfg(unix)]
-                        {
-                            use std::os::unix::fs::PermissionsExt;
-                            if metadata.permissions().mode() & 0o111 == 0 {
-                                continue;
-                            }
-                        }
+/// Most files and URIs one launch may open
+const MAX_LAUNCH_TARGETS: usize = 32;
+
+/// URI schemes an app may be handed - file:// URIs are checked against the path policy like paths
+const LAUNCH_URI_SCHEMES: &[&str] = &["http", "https", "ftp", "mailto", "file"];
+
+/// `files` (paths) and `uris` from a launch payload, vetted: paths and file:// URIs must exist and be
+/// allowed by the path policy (they are passed on canonical), other URIs must use a LAUNCH_URI_SCHEMES
+/// scheme and contain no whitespace or control characters
+fn launch_targets(data: &Value, config: &Config) -> Result<Vec<String>, String> {
+    let list = |key: &str| -> Result<Vec<String>, String> {
+        match data.get(key) {
+            None | Some(Value::Null) => Ok(Vec::new()),
+            Some(Value::Array(items)) => items.iter()
+                .map(|item| item.as_str().map(str::to_string).ok_or_else(|| format!("{} must be an array of strings", key)))
+                .collect(),
+            Some(_) => Err(format!("{} must be an array of strings", key)),
+        }
+    };
+    let (files, uris) = (list("files")?, list("uris")?);
+    if files.len() + uris.len() > MAX_LAUNCH_TARGETS {
+        return Err(format!("Too many files to open (at most {})", MAX_LAUNCH_TARGETS));
+    }
 
-                        // Get environment variables for GUI support using helpers
-                        let display = environment::get_display();
-                        let xauthority = environment::get_xauthority();
-                        let dbus_addr = environment::get_dbus_address();
-                        let wayland_display = environment::get_wayland_display();
-
-                        let result = Command::new(exec_path)
-                            .env("DISPLAY", &display)
-                            .env("XAUTHORITY", &xauthority)
-                            .env("DBUS_SESSION_BUS_ADDRESS", &dbus_addr)
-                            .env("WAYLAND_DISPLAY", &wayland_display)
-                            .args(&parts[1..])
-                            .spawn();
-
-                        match result {
-                            Ok(_child) => {
-                                // Detach from parent - don't wait for it, don't kill it!
-                                return Response {
-                                    success: true,
-                                    output: Some(format!("✓ GUI app '{}' launched (from desktop file)", desktop_entry)),
-                                    error: None,
-                                    exists: None,
-                                };
-                            }
-                            Err(_e) => {
-                                // Failed to spawn, continue
-                            }
-                        }
-                    }
-                }
+    let policy = config.path_policy()?;
+    let local = |path: &str| -> Result<String, String> {
+        let canonical = policy.check(std::path::Path::new(path))?;
+        if !canonical.exists() {
+            return Err(format!("{} does not exist", path));
+        }
+        Ok(canonical.to_string_lossy().into_owned())
+    };
+    let mut targets = Vec::new();
+    for file in &files {
+        if file.is_empty() || file.contains('\0') || !file.starts_with('/') {
+            return Err(format!("Invalid file {:?}: expected an absolute path", file));
+        }
+        targets.push(local(file)?);
+    }
+    for uri in &uris {
+        let scheme = uri.split_once(':').map(|(scheme, _)| scheme.to_lowercase()).filter(|_| desktop::is_uri(uri));
+        match scheme.as_deref() {
+            Some(scheme) if !LAUNCH_URI_SCHEMES.contains(&scheme) => return Err(format!("URI scheme {} is not allowed", scheme)),
+            None => return Err(format!("Invalid URI {:?}", uri)),
+            _ if uri.len() > 4096 || uri.chars().any(|c| c.is_whitespace() || c.is_control()) => {
+                return Err(format!("Invalid URI {:?}", uri));
+            }
+            Some("file") => {
+                let path = desktop::local_path(uri).ok_or_else(|| format!("Invalid file URI {:?}", uri))?;
+                targets.push(desktop::file_uri(&local(&path)?));
             }
+            Some(_) => targets.push(uri.clone()),
         }
     }
+    Ok(targets)
+}
 
-    // Last resort: Try to run it directly as a command if it's in PATH
-    let which_result = Command::new("which")
-        .arg(desktop_entry)
-        .output();
+/// `trust_recent` is set once a human confirmed the launch - otherwise desktop files modified within
+/// the trust window are refused. `files` and `uris` are opened with the app (see launch_targets).
+/// With `mode: "launch_or_focus"` an app already running (and not asked to open anything) has its
+/// window focused instead of a second instance started
+/// `launch_gui_app`, then the placement it asks for ({workspace?, monitor?}) - a launch that
+/// succeeded stays successful if its window can't be moved
+fn launch_gui_app(data: &serde_json::Value, config: &Config, trust_recent: bool) -> LaunchResponse {
+    let placement = match windows::Placement::from_request(data) {
+        Ok(placement) => placement,
+        Err(e) => return response::error(e).into(),
+    };
+    let mut launched = launch_app(data, config, trust_recent);
+    if let Some(placement) = placement.filter(|_| launched.response.success) {
+        place_launched(&mut launched, &placement);
+    }
+    launched
+}
 
-    if let Ok(result) = which_result {
-        if result.status.success() {
-            let cmd_path = String::from_utf8_lossy(&result.stdout).trim().to_string();
-            if !cmd_path.is_empty() {
-                eprintln!("    Found in PATH: {}", cmd_path);
-
-                // Get environment variables for GUI support using helpers
-                let display = environment::get_display();
-                let xauthority = environment::get_xauthority();
-                let dbus_addr = environment::get_dbus_address();
-                let wayland_display = environment::get_wayland_display();
-
-                let spawn_result = Command::new(&cmd_path)
-                    .env("DISPLAY", &display)
-                    .env("XAUTHORITY", &xauthority)
-                    .env("DBUS_SESSION_BUS_ADDRESS", &dbus_addr)
-                    .env("WAYLAND_DISPLAY", &wayland_display)
-                    .spawn();
-
-                if let Ok(_child) = spawn_result {
-                    return Response {
-                        success: true,
-                        output: Some(format!("✓ GUI app '{}' launched directly", desktop_entry)),
-                        error: None,
-                        exists: None,
-                    };
-                }
+/// Move the launched (or focused) app's window, waiting a little longer for it if need be
+fn place_launched(launched: &mut LaunchResponse, placement: &windows::Placement) {
+    if launched.window.is_none() {
+        // LAUNCH_WINDOW_WAIT of it has passed already
+        launched.window = launched.pid
+            .filter(|&pid| windows::is_alive(pid))
+            .and_then(|pid| windows::wait_for_pid(pid, PLACEMENT_WINDOW_WAIT.saturating_sub(LAUNCH_WINDOW_WAIT)));
+    }
+    let placed = match &launched.window {
+        Some(window) => windows::Backend::detect().and_then(|backend| backend.place(window, placement)),
+        None => Err("The app's window wasn't found - it can't be placed".to_string()),
+    };
+    match placed {
+        Ok(()) => {
+            if let (Some(window), Some(workspace)) = (launched.window.as_mut(), &placement.workspace) {
+                window.workspace = Some(workspace.clone());
             }
+            launched.placed = Some(true);
+        }
+        Err(e) => {
+            tracing::warn!("Could not place launched window: {}", e);
+            launched.placed = Some(false);
+            launched.placement_error = Some(e);
         }
     }
+}
 
-    Response {
-        success: false,
-        output: None,
-        error: Some(format!("Failed to launch GUI app '{}' - not found or not accessible", desktop_entry)),
-        exists: None,
+fn launch_app(data: &serde_json::Value, config: &Config, trust_recent: bool) -> LaunchResponse {
+    let desktop_entry = match data.get("desktop_entry").and_then(|v| v.as_str()) {
+        Some(entry) => entry,
+        None => return response::error("Missing desktop_entry parameter".to_string()).into(),
+    };
+
+    // Use centralized validation helper
+    if let Err(e) = validate_desktop_entry(desktop_entry) {
+        return response::error(e).into();
+    }
+
+    let targets = match launch_targets(data, config) {
+        Ok(targets) => targets,
+        Err(e) => return response::error(e).into(),
+    };
+    match data.get("mode").and_then(|v| v.as_str()).unwrap_or("launch") {
+        "launch" => {}
+        "launch_or_focus" if targets.is_empty() => {
+            if let Some(focused) = focus_running(desktop_entry, config) {
+                return focused;
+            }
+        }
+        "launch_or_focus" => {}
+        other => return response::error(format!("Invalid mode '{}': expected launch or launch_or_focus", other)).into(),
     }
+
+    // Only vetted desktop files are handed to the launcher - it reads nothing else
+    let candidates = match desktop_candidates(desktop_entry, config) {
+        Ok(candidates) => candidates,
+        Err(e) => return response::error(e).into(),
+    };
+    let (trusted, recent): (Vec<_>, Vec<_>) = candidates.into_iter()
+        .partition(|c| trust_recent || c.age.as_secs() >= config.desktop_trust_seconds);
+    if trusted.is_empty() {
+        if let Some(recent) = recent.first() {
+            return response::error(format!(
+                "Desktop file {} was modified {}s ago - launch it with launch_gui_app to confirm",
+                recent.path.display(),
+                recent.age.as_secs()
+            )).into();
+        }
+    }
+    let desktop_files: Vec<String> = trusted.iter().map(|c| c.path.to_string_lossy().to_string()).collect();
+    // For Terminal=true entries - the launcher checks the terminal against exec_dirs like any program
+    let terminal: Vec<String> = terminals::detect(config)
+        .map(|spec| std::iter::once(spec.binary).chain(spec.args).collect())
+        .unwrap_or_default();
+
+    run_launcher(desktop_entry, &desktop_files, &helpers::security::canonical_dirs(&config.fs_exec_dirs), &terminal, &targets)
 }
 
+/// Focus the window of `desktop_entry` if it already runs and one can be found
+fn focus_running(desktop_entry: &str, config: &Config) -> Option<LaunchResponse> {
+    let app = apps::with_index(config, |index| index.find(desktop_entry).cloned()).ok()??;
+    let running = apps::running(&app);
+    // Prefer the window last focused (Hyprland lists it first, others mark it)
+    let window = running.windows.iter().find(|w| w.focused == Some(true)).or(running.windows.first())?;
+    let focused = running.backend?.focus(window);
+    if let Err(e) = focused {
+        tracing::warn!("Could not focus {} - launching it instead: {}", app.id, e);
+        return None;
+    }
+    Some(LaunchResponse {
+        response: response::success(format!("✓ GUI app '{}' is already running - focused its window", app.id)),
+        pid: window.pid.or(running.pids.first().copied()),
+        still_running: Some(true),
+        stderr: None,
+        window: Some(window.clone()),
+        placed: None,
+        placement_error: None,
+    })
+}
 
+/// Start an installed application at login (an entry in ~/.config/autostart)
+fn add_autostart(data: &Value, config: &Config) -> Response {
+    match autostart::add(data, config) {
+        Ok(path) => response::success(format!("✓ Will start at login ({})", path.display())),
+        Err(e) => response::error(e),
+    }
+}
 
-fn detect_terminal() -> Response {
-    let terminals = vec![
-        ("foot", vec!["-e", "bash", "-c"]),
-        ("kitty", vec!["-e", "bash", "-c"]),
-        ("konsole", vec!["-e", "bash", "-c"]),
-        ("gnome-terminal", vec!["--", "bash", "-c"]),
-        ("xfce4-terminal", vec!["-e", "bash", "-c"]),
-        ("alacritty", vec!["-e", "bash", "-c"]),
-        ("terminator", vec!["-e", "bash", "-c"]),
-    ];
+/// Autostart entries, user and system
+fn handle_list_autostart(stream: &mut UnixStream) -> std::io::Result<()> {
+    let entries = autostart::list();
+    safe_json_response(&serde_json::json!({
+        "success": true,
+        "count": entries.len(),
+        "entries": entries,
+    }), stream)
+}
 
-    for (term, args) in terminals {
-        let check = Command::new("which")
-            .arg(term)
-            .output();
+fn remove_autostart(data: &Value, config: &Config) -> Response {
+    match autostart::remove(data, config) {
+        Ok(autostart::Removed::Deleted) => response::success("✓ Removed from autostart".to_string()),
+        Ok(autostart::Removed::Disabled) => response::success("✓ System autostart entry disabled for this user".to_string()),
+        Err(e) => response::error(e),
+    }
+}
 
-        if let Ok(result) = check {
-            if result.status.success() {
-                let response_data = serde_json::json!({
-                    "terminal": term,
-                    "args": args
-                });
-                return Response {
-                    success: true,
-                    output: Some(response_data.to_string()),
-                    error: None,
-                    exists: Some(true),
-                };
+/// `open_with_default`: {files?, uris?} - open them with the application xdg-mime associates with
+/// the first one's type, through launch_gui_app
+fn open_with_default(data: &Value, config: &Config) -> LaunchResponse {
+    let targets = match launch_targets(data, config) {
+        Ok(targets) => targets,
+        Err(e) => return response::error(e).into(),
+    };
+    let Some(first) = targets.first() else {
+        return response::error("Missing files or uris parameter".to_string()).into();
+    };
+    let desktop_entry = match default_app(first, config) {
+        Ok((_, Some(entry), _)) => entry,
+        Ok((mime, None, _)) => return response::error(format!("No default application for {}", mime)).into(),
+        Err(e) => return response::error(e).into(),
+    };
+    if let Err(e) = validate_desktop_entry(&desktop_entry) {
+        return response::error(e).into();
+    }
+
+    let mut launch = data.clone();
+    launch["desktop_entry"] = Value::String(desktop_entry);
+    launch_gui_app(&launch, config, false)
+}
+
+/// The MIME type `target` (a MIME type, URI, path, file name or extension) stands for, its default
+/// application and every installed one associated with it. An existing file is only looked into if
+/// the path policy allows it
+fn default_app(target: &str, config: &Config) -> Result<(String, Option<String>, Vec<String>), String> {
+    if let Some(path) = desktop::local_path(target).filter(|path| path.starts_with('/') && std::path::Path::new(path).exists()) {
+        config.path_policy()?.check(std::path::Path::new(&path))?;
+    }
+    let mime = mimeapps::mime_type(target)?;
+    let (default, all) = apps::with_index(config, |index| {
+        mimeapps::lookup(&mime, |id| index.get(id).is_some(), &index.handlers(&mime))
+    })?;
+    Ok((mime, default, all))
+}
+
+/// `get_default_app`: {mime_or_url} - what opens it by default, and what else could
+fn handle_get_default_app(stream: &mut UnixStream, data: &Value, config: &Config) -> std::io::Result<()> {
+    let target = match data.get("mime_or_url").and_then(|v| v.as_str()) {
+        Some(target) => target,
+        None => return send_error(stream, ErrorKind::Validation, "Missing mime_or_url parameter"),
+    };
+    match default_app(target, config) {
+        Ok((mime, default, all)) => safe_json_response(&serde_json::json!({
+            "success": true,
+            "output": default,
+            "exists": default.is_some(),
+            "mime_type": mime,
+            "default": default,
+            "applications": all,
+        }), stream),
+        Err(e) => send_error(stream, ErrorKind::Validation, &e),
+    }
+}
+
+/// `set_default_app`: {mime_or_url, desktop_entry} - make the app the user's default for it. A
+/// wildcard (`video/*`) sets every type of that kind the app's MimeType lists
+fn handle_set_default_app(stream: &mut UnixStream, data: &Value, config: &Config) -> std::io::Result<()> {
+    let (Some(target), Some(entry)) = (
+        data.get("mime_or_url").and_then(|v| v.as_str()),
+        data.get("desktop_entry").and_then(|v| v.as_str()),
+    ) else {
+        return send_error(stream, ErrorKind::Validation, "Missing mime_or_url or desktop_entry parameter");
+    };
+    if let Err(e) = validate_desktop_entry(entry) {
+        return send_error(stream, ErrorKind::Validation, &e);
+    }
+    let app = match apps::with_index(config, |index| index.get(entry).or_else(|| index.find(entry)).cloned()) {
+        Ok(Some(app)) => app,
+        Ok(None) => return send_error(stream, ErrorKind::Validation, &format!("Application '{}' not found", entry)),
+        Err(e) => return send_error(stream, ErrorKind::Validation, &e),
+    };
+    let mime = match mimeapps::mime_type(target) {
+        Ok(mime) => mime,
+        Err(e) => return send_error(stream, ErrorKind::Validation, &e),
+    };
+    let mimes: Vec<String> = match mime.strip_suffix("/*") {
+        Some(kind) => app.mime_types.iter()
+            .filter(|m| m.to_lowercase().starts_with(&format!("{}/", kind)))
+            .cloned()
+            .collect(),
+        None => vec![mime.clone()],
+    };
+    if mimes.is_empty() {
+        return send_error(stream, ErrorKind::Validation, &format!("'{}' opens no {} types", app.id, mime));
+    }
+    match mimeapps::set_default(&mimes, &app.id) {
+        Ok(file) => safe_json_response(&serde_json::json!({
+            "success": true,
+            "output": format!("✓ {} is now the default for {} type(s)", app.id, mimes.len()),
+            "desktop_entry": app.id,
+            "mime_types": mimes,
+            "file": file,
+        }), stream),
+        Err(e) => send_error(stream, ErrorKind::Io, &e),
+    }
+}
+
+/// Longest the launcher may take (it only spawns the app and watches its first moments)
+const LAUNCHER_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How long a launched app's window is waited for
+const LAUNCH_WINDOW_WAIT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// ... and how long in all when it's to be placed on a workspace or monitor
+const PLACEMENT_WINDOW_WAIT: std::time::Duration = std::time::Duration::from_secs(8);
+
+/// launch_gui_app's reply - the usual fields, and what became of the app: its pid and whether it
+/// still runs (where the launcher or the bus could tell), its early stderr, the window it opened and
+/// whether that was moved where the request asked
+#[derive(serde::Serialize)]
+struct LaunchResponse {
+    #[serde(flatten)]
+    response: Response,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    still_running: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stderr: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    window: Option<windows::Window>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    placed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    placement_error: Option<String>,
+}
+
+impl From<Response> for LaunchResponse {
+    fn from(response: Response) -> LaunchResponse {
+        LaunchResponse { response, pid: None, still_running: None, stderr: None, window: None, placed: None, placement_error: None }
+    }
+}
+
+/// Launch via the archy-launcher helper next to this binary. Desktop files are untrusted, so their
+/// parsing and Exec spawning happen in that process, which gets only the GUI environment
+fn run_launcher(desktop_entry: &str, desktop_files: &[String], exec_dirs: &[String], terminal: &[String], files: &[String]) -> LaunchResponse {
+    use helpers::environment;
+    use std::process::Stdio;
+
+    let launcher = match std::env::current_exe() {
+        Ok(exe) => exe.with_file_name("archy-launcher"),
+        Err(e) => return response::error(format!("Cannot locate GUI launcher: {}", e)).into(),
+    };
+
+    let mut command = Command::new(&launcher);
+    command.env_clear().envs(environment::Session::detect().gui_env());
+    for var in ["PATH", "HOME", "USER", "LANG", "LC_ALL", "LC_MESSAGES", "XDG_DATA_DIRS"] {
+        if let Ok(value) = std::env::var(var) {
+            command.env(var, value);
+        }
+    }
+    let mut child = match command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::null()).spawn() {
+        Ok(child) => child,
+        Err(e) => return response::error(format!("Cannot start GUI launcher {}: {}", launcher.display(), e)).into(),
+    };
+
+    let request = serde_json::json!({
+        "desktop_entry": desktop_entry,
+        "desktop_files": desktop_files,
+        "exec_dirs": exec_dirs,
+        "terminal": terminal,
+        "files": files,
+    });
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(request.to_string().as_bytes());
+    }
+
+    let started = std::time::Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if started.elapsed() < LAUNCHER_TIMEOUT => std::thread::sleep(std::time::Duration::from_millis(20)),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return response::error(format!("GUI launcher timed out launching '{}'", desktop_entry)).into();
             }
         }
     }
 
-    Response {
-        success: false,
-        output: None,
-        error: Some("No terminal emulator found".to_string()),
-        exists: Some(false),
+    let mut reply = String::new();
+    if let Some(mut stdout) = child.stdout.take() {
+        let _ = stdout.read_to_string(&mut reply);
+    }
+    let reply = match serde_json::from_str::<Value>(&reply) {
+        Ok(reply) => reply,
+        Err(_) => return response::error(format!("GUI launcher returned an invalid reply for '{}'", desktop_entry)).into(),
+    };
+    let pid = reply["pid"].as_u64().and_then(|pid| u32::try_from(pid).ok());
+    let still_running = reply["still_running"].as_bool();
+    // The launcher saw it survive its first moments - look for the window it opens
+    let window = pid.filter(|_| still_running != Some(false)).and_then(|pid| windows::wait_for_pid(pid, LAUNCH_WINDOW_WAIT));
+    LaunchResponse {
+        response: Response {
+            success: reply["success"].as_bool().unwrap_or(false),
+            output: reply["output"].as_str().map(str::to_string),
+            error: reply["error"].as_str().map(str::to_string),
+            exists: None,
+        },
+        pid,
+        still_running: still_running.or_else(|| pid.map(windows::is_alive)),
+        stderr: reply["stderr"].as_str().map(str::to_string),
+        window,
+        placed: None,
+        placement_error: None,
     }
 }
 
-fn launch_fallback_terminal(data: &serde_json::Value) -> Response {
+
+fn detect_terminal(config: &Config) -> Response {
+    let session = helpers::environment::Session::detect();
+    match terminals::ranked(config, &session).into_iter().find(|spec| spec.is_installed()) {
+        Some(terminal) => {
+            // `args` are the arguments preceding the command string, e.g. ["-e", "bash", "-c"];
+            // `argv` is the whole command line, the script going where "{command}" is
+            let response_data = serde_json::json!({
+                "terminal": terminal.name,
+                "binary": terminal.binary,
+                "args": terminal.command_line(&["bash", "-c"]),
+                "argv": std::iter::once(terminal.binary.clone()).chain(terminal.shell_line("{command}")).collect::<Vec<_>>(),
+                "display_server": session.server,
+                "compositor": session.compositor,
+            });
+            Response {
+                success: true,
+                output: Some(response_data.to_string()),
+                error: None,
+                exists: Some(true),
+            }
+        }
+        None => Response {
+            success: false,
+            output: None,
+            error: Some("No terminal emulator found".to_string()),
+            exists: Some(false),
+        },
+    }
+}
+
+fn launch_fallback_terminal(data: &serde_json::Value, config: &Config) -> Response {
     let command = match data.get("command").and_then(|v| v.as_str()) {
         Some(cmd) => cmd,
         None => return Response {
@@ -1171,41 +2173,30 @@ fn launch_fallback_terminal(data: &serde_json::Value) -> Response {
         };
     }
 
-    let terminal = data.get("terminal")
-        .and_then(|v| v.as_str())
-        .unwrap_or("foot");
-
-    // Validate terminal name (only allow known terminals)
-    let allowed_terminals = ["foot", "kitty", "konsole", "gnome-terminal",
-                            "xfce4-terminal", "alacritty", "terminator"];
-    if !allowed_terminals.contains(&terminal) {
-        return Response {
-            success: false,
-            output: None,
-            error: Some("Invalid terminal specified".to_string()),
-            exists: None,
-        };
-    }
+    // Only known terminals (built-in or configured templates) - never an arbitrary binary from the request
+    let terminal = match data.get("terminal").and_then(|v| v.as_str()) {
+        Some(name) => match terminals::lookup(config, name) {
+            Some(terminal) => terminal,
+            None => return Response {
+                success: false,
+                output: None,
+                error: Some("Invalid terminal specified".to_string()),
+                exists: None,
+            },
+        },
+        None => match terminals::detect(config) {
+            Some(terminal) => terminal,
+            None => return response::error("No terminal emulator found".to_string()),
+        },
+    };
 
     let terminal_cmd = format!("{}; echo ''; echo 'Press Enter to close...'; read", command);
 
     // Set environment variables for terminal to work correctly
-    use helpers::environment;
-    let display = environment::get_display();
-    let xauthority = environment::get_xauthority();
-    let dbus_addr = environment::get_dbus_address();
-    let wayland_display = environment::get_wayland_display();
-
     let result = Command::new("setsid")
-        .env("DISPLAY", &display)
-        .env("XAUTHORITY", &xauthority)
-        .env("DBUS_SESSION_BUS_ADDRESS", &dbus_addr)
-        .env("WAYLAND_DISPLAY", &wayland_display)
-        .arg(terminal)
-        .arg("-e")
-        .arg("bash")
-        .arg("-c")
-        .arg(&terminal_cmd)
+        .envs(helpers::environment::Session::detect().gui_env())
+        .arg(&terminal.binary)
+        .args(terminal.shell_line(&terminal_cmd))
         .stdout(std::process::Stdio::null())
         .stderr(std::process::Stdio::null())
         .spawn();
@@ -1213,7 +2204,7 @@ fn launch_fallback_terminal(data: &serde_json::Value) -> Response {
     match result {
         Ok(_) => Response {
             success: true,
-            output: Some(format!("✓ Command launched in new {} terminal", terminal)),
+            output: Some(format!("✓ Command launched in new {} terminal", terminal.name)),
             error: None,
             exists: None,
         },
@@ -1267,9 +2258,7 @@ fn execute_command_smart(data: &serde_json::Value, config: &Config) -> Response
         };
     }
 
-    let session = data.get("session")
-        .and_then(|v| v.as_str())
-        .unwrap_or("archy_session");
+    let session = config.get_session(data);
 
     // Extract app name
     let parts: Vec<&str> = command.split_whitespace().collect();
@@ -1282,17 +2271,14 @@ fn execute_command_smart(data: &serde_json::Value, config: &Config) -> Response
         };
     }
 
-    let app_name = parts[0].split('/').last().unwrap_or(parts[0]);
-
-    // Check if it's a GUI app
-    let desktop_entry_data = serde_json::json!({"app_name": app_name});
-    let desktop_result = find_desktop_entry(&desktop_entry_data);
+    let app_name = parts[0].split('/').next_back().unwrap_or(parts[0]);
 
-    if desktop_result.success && desktop_result.exists == Some(true) {
-        // It's a GUI app - launch detached
-        if let Some(desktop_entry) = desktop_result.output {
-            return launch_gui_app(&serde_json::json!({"desktop_entry": desktop_entry}));
-        }
+    // A GUI app launches detached - terminal programs (Terminal=true, e.g. htop) run in tmux like any command
+    let gui_entry = apps::with_index(config, |index| {
+        index.find(app_name).filter(|app| !app.terminal).map(|app| app.id.clone())
+    });
+    if let Ok(Some(desktop_entry)) = gui_entry {
+        return launch_gui_app(&serde_json::json!({"desktop_entry": desktop_entry}), config, false).response;
     }
 
     // Check if tmux is available
@@ -1304,15 +2290,13 @@ fn execute_command_smart(data: &serde_json::Value, config: &Config) -> Response
         if result.status.success() {
             // Check if session exists, create if needed
             let session_check = Command::new("tmux")
-                .args(&["has-session", "-t", session])
+                .args(["has-session", "-t", session])
                 .status();
 
             if let Ok(status) = session_check {
                 if !status.success() {
                     // Create session
-                    let _ = Command::new("tmux")
-                        .args(&["new-session", "-d", "-s", session])
-                        .status();
+                    let _ = tmux::new_session(session);
                 }
             }
 
@@ -1320,8 +2304,8 @@ fn execute_command_smart(data: &serde_json::Value, config: &Config) -> Response
             match tmux::send_keys(session, command) {
                 Ok(_) => {
                     // Ensure terminal window is open
-                    let foot_check = is_foot_running();
-                    if foot_check.exists != Some(true) {
+                    let foot_check = is_foot_running(config);
+                    if foot_check.exists != Some(true) && config.features.gui {
                         let _ = open_terminal(config);
                         return Response {
                             success: true,
@@ -1346,15 +2330,15 @@ fn execute_command_smart(data: &serde_json::Value, config: &Config) -> Response
     }
 
     // Fallback to new terminal window
-    let terminal_result = detect_terminal();
-    if terminal_result.success {
+    let terminal_result = detect_terminal(config);
+    if terminal_result.success && config.features.fallback_terminal {
         if let Some(terminal_info) = terminal_result.output {
             if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(&terminal_info) {
                 if let Some(terminal) = parsed.get("terminal").and_then(|v| v.as_str()) {
                     return launch_fallback_terminal(&serde_json::json!({
                         "command": command,
                         "terminal": terminal
-                    }));
+                    }), config);
                 }
             }
         }
@@ -1370,27 +2354,26 @@ fn execute_command_smart(data: &serde_json::Value, config: &Config) -> Response
 
 
 /// Handle execute_analyzed action - executes command, waits, and returns analyzed output
-fn handle_execute_analyzed(stream: &mut UnixStream, data: &serde_json::Value) -> std::io::Result<()> {
+fn handle_execute_analyzed(stream: &mut UnixStream, data: &serde_json::Value, config: &Config) -> std::io::Result<()> {
     let command = match data.get("command").and_then(|v| v.as_str()) {
         Some(cmd) => cmd,
         None => {
-            let output = DisplayOutput::from_error("", "Missing command parameter");
-            return send_json_response(stream, &output);
+            let output = DisplayOutput::from_error("", ErrorKind::Validation, "Missing command parameter");
+            return send_display_output(stream, output, data, config);
         }
     };
 
-    let session = data.get("session")
-        .and_then(|v| v.as_str())
-        .unwrap_or("archy_session");
+    let session = config.get_session(data);
 
     // Execute command in tmux
+    watchdog::sent(session);
     let exec_result = Command::new("tmux")
-        .args(&["send-keys", "-t", session, command, "C-m"])
+        .args(["send-keys", "-t", session, command, "C-m"])
         .output();
 
     if let Err(e) = exec_result {
-        let output = DisplayOutput::from_error(command, &e.to_string());
-        return send_json_response(stream, &output);
+        let output = DisplayOutput::from_error_detail(command, ErrorKind::TmuxUnavailable, "Failed to run tmux", &e.to_string());
+        return send_display_output(stream, output, data, config);
     }
 
     // Wait for command completion
@@ -1401,31 +2384,29 @@ fn handle_execute_analyzed(stream: &mut UnixStream, data: &serde_json::Value) ->
         "interval_ms": data.get("interval_ms").and_then(|v| v.as_u64()).unwrap_or(500)
     });
 
-    let wait_result = wait_for_command_completion(&wait_data);
+    let wait_result = wait_for_command_completion(&wait_data, config);
 
     let display_output = if wait_result.success {
         if let Some(raw_output) = wait_result.output {
             DisplayOutput::from_command_output(command, &raw_output, 0)
         } else {
-            DisplayOutput::from_error(command, "No output captured")
+            DisplayOutput::from_error(command, ErrorKind::Io, "No output captured")
         }
     } else {
         let partial = wait_result.output.unwrap_or_default();
         DisplayOutput::from_timeout(command, &partial)
     };
 
-    send_json_response(stream, &display_output)
+    send_display_output(stream, display_output, data, config)
 }
 
 /// Handle capture_analyzed action - captures current output and returns analyzed version
-fn handle_capture_analyzed(stream: &mut UnixStream, data: &serde_json::Value) -> std::io::Result<()> {
+fn handle_capture_analyzed(stream: &mut UnixStream, data: &serde_json::Value, config: &Config) -> std::io::Result<()> {
     let lines = data.get("lines")
         .and_then(|v| v.as_i64())
         .unwrap_or(100);
 
-    let session = data.get("session")
-        .and_then(|v| v.as_str())
-        .unwrap_or("archy_session");
+    let session = config.get_session(data);
 
     let command = data.get("command")
         .and_then(|v| v.as_str())
@@ -1433,7 +2414,7 @@ fn handle_capture_analyzed(stream: &mut UnixStream, data: &serde_json::Value) ->
 
     // Capture output from tmux
     let output = Command::new("tmux")
-        .args(&["capture-pane", "-pt", session, "-S", &format!("-{}", lines)])
+        .args(["capture-pane", "-pt", session, "-S", &format!("-{}", lines)])
         .output();
 
     let display_output = match output {
@@ -1449,53 +2430,53 @@ fn handle_capture_analyzed(stream: &mut UnixStream, data: &serde_json::Value) ->
             
             DisplayOutput::from_command_output(&detected_command, &raw_output, 0)
         }
-        Ok(_) => {
-            DisplayOutput::from_error(command, "Failed to capture output")
+        Ok(out) => {
+            let stderr = String::from_utf8_lossy(&out.stderr).trim().to_string();
+            DisplayOutput::from_error_detail(command, ErrorKind::SessionMissing, "Failed to capture output", &stderr)
         }
         Err(e) => {
-            DisplayOutput::from_error(command, &e.to_string())
+            DisplayOutput::from_error_detail(command, ErrorKind::TmuxUnavailable, "Failed to run tmux", &e.to_string())
         }
     };
 
-    send_json_response(stream, &display_output)
+    send_display_output(stream, display_output, data, config)
 }
 
 /// Handle execute_and_wait - executes command, waits for completion, then analyzes
 /// This is the SMART way - no hardcoded timeouts!
-fn handle_execute_and_wait(stream: &mut UnixStream, data: &serde_json::Value) -> std::io::Result<()> {
+fn handle_execute_and_wait(stream: &mut UnixStream, data: &serde_json::Value, config: &Config) -> std::io::Result<()> {
     let command = match data.get("command").and_then(|v| v.as_str()) {
         Some(cmd) => cmd,
         None => {
-            let output = DisplayOutput::from_error("", "Missing command parameter");
-            return send_json_response(stream, &output);
+            let output = DisplayOutput::from_error("", ErrorKind::Validation, "Missing command parameter");
+            return send_display_output(stream, output, data, config);
         }
     };
 
-    let session = data.get("session")
-        .and_then(|v| v.as_str())
-        .unwrap_or("archy_session");
+    let session = config.get_session(data);
 
     // CRITICAL: Ensure tmux session exists before sending commands
     // This prevents "no server running" errors that cause broken pipes
     if !tmux::has_session(session) {
-        eprintln!("⚠️ Session {} doesn't exist, creating...", session);
+        tracing::warn!("Session {} doesn't exist, creating...", session);
         if let Err(e) = tmux::new_session(session) {
-            eprintln!("❌ Failed to create session: {}", e);
-            let output = DisplayOutput::from_error(command, &format!("Failed to create tmux session: {}", e));
-            return send_json_response(stream, &output);
+            tracing::error!("Failed to create session: {}", e);
+            let output = DisplayOutput::from_error_detail(command, ErrorKind::SessionMissing, "Failed to create tmux session", &e);
+            return send_display_output(stream, output, data, config);
         }
         // Brief wait for session to initialize
         std::thread::sleep(std::time::Duration::from_millis(100));
     }
 
     // Execute command in tmux
+    watchdog::sent(session);
     let exec_result = Command::new("tmux")
-        .args(&["send-keys", "-t", session, command, "C-m"])
+        .args(["send-keys", "-t", session, command, "C-m"])
         .output();
 
     if let Err(e) = exec_result {
-        let output = DisplayOutput::from_error(command, &e.to_string());
-        return send_json_response(stream, &output);
+        let output = DisplayOutput::from_error_detail(command, ErrorKind::TmuxUnavailable, "Failed to run tmux", &e.to_string());
+        return send_display_output(stream, output, data, config);
     }
 
     // Wait for command completion using smart prompt detection
@@ -1506,20 +2487,20 @@ fn handle_execute_and_wait(stream: &mut UnixStream, data: &serde_json::Value) ->
         "interval_ms": data.get("interval_ms").and_then(|v| v.as_u64()).unwrap_or(500)  // Check every 500ms
     });
 
-    let wait_result = wait_for_command_completion(&wait_data);
+    let wait_result = wait_for_command_completion(&wait_data, config);
 
     let display_output = if wait_result.success {
         if let Some(raw_output) = wait_result.output {
             DisplayOutput::from_command_output(command, &raw_output, 0)
         } else {
-            DisplayOutput::from_error(command, "No output captured")
+            DisplayOutput::from_error(command, ErrorKind::Io, "No output captured")
         }
     } else {
         let partial = wait_result.output.unwrap_or_default();
         DisplayOutput::from_timeout(command, &partial)
     };
 
-    send_json_response(stream, &display_output)
+    send_display_output(stream, display_output, data, config)
 }
 
 /// Handle batch execution of multiple commands
@@ -1528,13 +2509,219 @@ fn handle_batch_execute(
     data: &Value,
     config: &Config,
 ) -> std::io::Result<()> {
+    if is_dry_run(data) {
+        return match batch::dry_run(data) {
+            Ok(report) => safe_json_response(&report, stream),
+            Err(e) => safe_json_response(&response::error(e), stream),
+        };
+    }
+
     match batch::execute_batch(data, config) {
         Ok(result) => {
-            send_json_response(stream, &result)
+            safe_json_response(&result, stream)
         }
         Err(e) => {
             let error_response = response::error(e);
-            send_json_response(stream, &error_response)
+            safe_json_response(&error_response, stream)
+        }
+    }
+}
+
+/// Batch requests with `"dry_run": true` are validated and reported, never executed
+fn is_dry_run(data: &Value) -> bool {
+    data.get("dry_run").and_then(|v| v.as_bool()).unwrap_or(false)
+}
+
+/// Handle batch execution, returning the structured result alongside the formatted display
+fn handle_execute_batch(
+    stream: &mut UnixStream,
+    data: &Value,
+    config: &Config,
+) -> std::io::Result<()> {
+    if is_dry_run(data) {
+        return match batch::dry_run(data) {
+            Ok(report) => {
+                let display = formatter::format_dry_run(&report);
+                safe_json_response(&serde_json::json!({
+                    "success": report.ok_to_run,
+                    "dry_run": true,
+                    "display_plain": formatter::strip_colors(&display),
+                    "display": display,
+                    "result": report,
+                }), stream)
+            }
+            Err(e) => safe_json_response(&response::error(e), stream),
+        };
+    }
+
+    send_batch_result(stream, batch::execute_batch(data, config))
+}
+
+/// Resume a checkpointed batch from its first failed step (or answer the prompt it paused at).
+/// The stored commands pass the same gates as a new batch - run_workflow does the same for saved ones
+fn handle_resume_batch(
+    stream: &mut UnixStream,
+    data: &Value,
+    confirmed: bool,
+    config: &Config,
+) -> std::io::Result<()> {
+    let batch_id = match params::extract_string(data, "batch_id") {
+        Ok(id) => id,
+        Err(e) => return safe_json_response(&response::error(e), stream),
+    };
+    let checkpoint = match batch::load_checkpoint(&config.batch_state_dir, &batch_id) {
+        Ok(checkpoint) => checkpoint,
+        Err(e) => return safe_json_response(&response::error(e), stream),
+    };
+
+    let stored = &checkpoint.request;
+    if let Err(e) = ownership::check("execute_batch", stored, config) {
+        return safe_json_response(&response::error(e), stream);
+    }
+    // Held as resume_batch, so confirm_execute resumes rather than starting the batch over
+    if !confirmed {
+        if let Some(held) = confirm::gate("resume_batch", data, &request_commands(stored), requester(), config) {
+            return safe_json_response(&held, stream);
+        }
+    }
+    if let Err(throttled) = check_throttle("execute_batch", stored, config) {
+        return safe_json_response(&throttled, stream);
+    }
+    // Checkpoints hold the commands as they ran, already sandbox-wrapped - only check the sandbox is still there
+    if let Err(e) = apply_project("execute_batch", stored, config).and_then(|()| sandbox::check(stored, config)) {
+        return safe_json_response(&response::error(e), stream);
+    }
+
+    // The stored commands read secret files that were deleted when the first run finished
+    if let Err(e) = secrets::restore(stored, config) {
+        return safe_json_response(&response::error(e), stream);
+    }
+
+    let input = data.get("input").and_then(|v| v.as_str());
+    send_batch_result(stream, batch::resume_batch(checkpoint, input, config))
+}
+
+/// Save a parameterized batch definition under a name
+fn save_workflow(data: &Value, config: &Config) -> Response {
+    let workflow = match workflows::from_request(data) {
+        Ok(workflow) => workflow,
+        Err(e) => return response::error(e),
+    };
+
+    match workflows::save(&config.workflow_dir, &workflow) {
+        Ok(()) => response::success(format!("✓ Saved workflow '{}'", workflow.name)),
+        Err(e) => response::error(e),
+    }
+}
+
+/// List saved workflows with their parameters
+fn handle_list_workflows(stream: &mut UnixStream, config: &Config) -> std::io::Result<()> {
+    let workflows: Vec<Value> = workflows::list(&config.workflow_dir)
+        .into_iter()
+        .map(|w| serde_json::json!({
+            "name": w.name,
+            "description": w.description,
+            "parameters": w.parameters,
+            "steps": w.batch.get("commands").and_then(|c| c.as_array()).map(|c| c.len()).unwrap_or(0),
+        }))
+        .collect();
+
+    safe_json_response(&serde_json::json!({
+        "success": true,
+        "workflows": workflows,
+    }), stream)
+}
+
+/// Run a saved workflow by name - runtime options (session, dry_run, ...) override the saved batch
+fn handle_run_workflow(stream: &mut UnixStream, data: &Value, config: &Config) -> std::io::Result<()> {
+    let name = match params::extract_string(data, "name") {
+        Ok(name) => name,
+        Err(e) => return safe_json_response(&response::error(e), stream),
+    };
+
+    let workflow = match workflows::load(&config.workflow_dir, &name) {
+        Ok(workflow) => workflow,
+        Err(e) => return safe_json_response(&response::error(e), stream),
+    };
+
+    let empty = serde_json::Map::new();
+    let params = data.get("params").and_then(|v| v.as_object()).unwrap_or(&empty);
+    let mut payload = match workflows::instantiate(&workflow, params) {
+        Ok(payload) => payload,
+        Err(e) => return safe_json_response(&response::error(e), stream),
+    };
+
+    for key in ["session", "dry_run", "include_outputs", "interval_ms", "max_wait", "sandbox"] {
+        if let Some(value) = data.get(key) {
+            payload[key] = value.clone();
+        }
+    }
+    if let Err(e) = batch::resolve_argv(&mut payload) {
+        return safe_json_response(&response::error(e), stream);
+    }
+    if let Err(e) = ownership::check("execute_batch", &payload, config) {
+        return safe_json_response(&response::error(e), stream);
+    }
+
+    if let Some(held) = require_confirmation("execute_batch", &payload, config) {
+        return safe_json_response(&held, stream);
+    }
+    if let Err(throttled) = check_throttle("execute_batch", &payload, config) {
+        return safe_json_response(&throttled, stream);
+    }
+
+    let payload = match secrets::expand_value(&payload, config) {
+        Ok(payload) => payload,
+        Err(e) => return safe_json_response(&response::error(e), stream),
+    };
+    if let Err(e) = apply_project("execute_batch", &payload, config) {
+        return safe_json_response(&response::error(e), stream);
+    }
+    let mut payload = payload;
+    if let Err(e) = sandbox::apply(&mut payload, config) {
+        return safe_json_response(&response::error(e), stream);
+    }
+
+    handle_execute_batch(stream, &payload, config)
+}
+
+/// Send a batch result with its formatted display (shared by execute_batch and resume_batch)
+fn send_batch_result(
+    stream: &mut UnixStream,
+    result: Result<batch::BatchExecutionResult, String>,
+) -> std::io::Result<()> {
+    match result {
+        Ok(result) => {
+            let display = formatter::format_batch_result(&result);
+            safe_json_response(&serde_json::json!({
+                "success": result.failed == 0 && result.timed_out == 0 && result.awaiting_input.is_none(),
+                "awaiting_input": result.awaiting_input.is_some(),
+                "display_plain": formatter::strip_colors(&display),
+                "display": display,
+                "result": result,
+            }), stream)
         }
+        Err(e) => safe_json_response(&response::error(e), stream),
     }
 }
+
+/// Handle batch execution with the full DisplayOutput pipeline (criteria, budgets, formats)
+fn handle_execute_batch_analyzed(
+    stream: &mut UnixStream,
+    data: &Value,
+    config: &Config,
+) -> std::io::Result<()> {
+    let output = if is_dry_run(data) {
+        match batch::dry_run(data) {
+            Ok(report) => DisplayOutput::from_dry_run(&report),
+            Err(e) => DisplayOutput::from_error("batch", ErrorKind::Validation, &e),
+        }
+    } else {
+        match batch::execute_batch(data, config) {
+            Ok(result) => DisplayOutput::from_batch(&result),
+            Err(e) => DisplayOutput::from_error("batch", ErrorKind::Validation, &e),
+        }
+    };
+
+    send_display_output(stream, output, data, config)
+}
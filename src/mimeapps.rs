@@ -0,0 +1,359 @@
+// mimeapps.rs - Default applications and MIME associations (Association between MIME types and
+// applications 1.0.1, shared-mime-info globs)
+// Answers "what opens .svg files?" and sets "mpv opens videos" without shelling out per question:
+//
+//   - what a request names becomes a MIME type: a MIME type as it is (`video/*` means every video type
+//     the app handles when setting), a URI scheme x-scheme-handler/<scheme>, an existing file by
+//     `xdg-mime query filetype` (which looks at the content), anything else - a path, a file name, an
+//     extension - by the globs2 files of the mime databases
+//   - the default is the first installed entry of a [Default Applications] line, read from the
+//     mimeapps.list files in spec order ($XDG_CONFIG_HOME, $XDG_CONFIG_DIRS, then the deprecated
+//     applications directories, each desktop-specific file before the plain one). Failing that, the
+//     first app associated with the type: [Added Associations], then every installed app whose MimeType
+//     lists it, less [Removed Associations]
+//   - setting a default edits $XDG_CONFIG_HOME/mimeapps.list only: the type's [Default Applications]
+//     line, and the app goes first in its [Added Associations] line. Other lines are kept as they are
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use crate::desktop;
+
+const DEFAULTS: &str = "Default Applications";
+const ADDED: &str = "Added Associations";
+const REMOVED: &str = "Removed Associations";
+
+/// Top-level media types a `type/subtype` argument may have
+const MEDIA_TYPES: &[&str] = &[
+    "application", "audio", "font", "image", "inode", "message", "model", "multipart", "text", "video",
+    "x-content", "x-scheme-handler",
+];
+
+/// A mimeapps.list, as lines - so a rewrite keeps what it doesn't touch
+#[derive(Debug, Default, Clone, PartialEq)]
+struct MimeAppsList {
+    lines: Vec<String>,
+}
+
+impl MimeAppsList {
+    fn parse(content: &str) -> MimeAppsList {
+        MimeAppsList { lines: content.lines().map(String::from).collect() }
+    }
+
+    /// The desktop ids `mime` has in `group`, in order
+    fn get(&self, group: &str, mime: &str) -> Vec<String> {
+        let mut current = "";
+        for line in &self.lines {
+            let line = line.trim();
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                current = name;
+            } else if current == group {
+                if let Some((key, value)) = line.split_once('=') {
+                    if key.trim() == mime {
+                        return split_ids(value);
+                    }
+                }
+            }
+        }
+        Vec::new()
+    }
+
+    /// Replace (or add) `mime`'s line in `group`, adding the group if it's missing. No ids removes it
+    fn set(&mut self, group: &str, mime: &str, ids: &[String]) {
+        if ids.is_empty() {
+            let mut current = String::new();
+            self.lines.retain(|line| {
+                if let Some(name) = line.trim().strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                    current = name.to_string();
+                }
+                current != group || line.split_once('=').is_none_or(|(key, _)| key.trim() != mime)
+            });
+            return;
+        }
+        let line = format!("{}={};", mime, ids.iter().map(|id| format!("{}.desktop", id)).collect::<Vec<_>>().join(";"));
+        let header = format!("[{}]", group);
+        let Some(start) = self.lines.iter().position(|l| l.trim() == header) else {
+            if self.lines.last().is_some_and(|l| !l.trim().is_empty()) {
+                self.lines.push(String::new());
+            }
+            self.lines.push(header);
+            self.lines.push(line);
+            return;
+        };
+        let end = self.lines[start + 1..].iter()
+            .position(|l| l.trim().starts_with('['))
+            .map_or(self.lines.len(), |offset| start + 1 + offset);
+        let existing = (start + 1..end).find(|&i| self.lines[i].split_once('=').is_some_and(|(key, _)| key.trim() == mime));
+        match existing {
+            Some(i) => self.lines[i] = line,
+            None => {
+                // After the group's last entry, before any blank lines ending it
+                let mut at = end;
+                while at > start + 1 && self.lines[at - 1].trim().is_empty() {
+                    at -= 1;
+                }
+                self.lines.insert(at, line);
+            }
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut content = self.lines.join("\n");
+        content.push('\n');
+        content
+    }
+}
+
+/// `a.desktop;b.desktop;` -> [a, b]
+fn split_ids(value: &str) -> Vec<String> {
+    value.split(';')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| id.strip_suffix(".desktop").unwrap_or(id).to_string())
+        .collect()
+}
+
+fn config_home() -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_default();
+    std::env::var("XDG_CONFIG_HOME").ok().filter(|d| d.starts_with('/'))
+        .map_or_else(|| Path::new(&home).join(".config"), PathBuf::from)
+}
+
+/// $XDG_DATA_HOME, then $XDG_DATA_DIRS
+fn data_dirs() -> Vec<PathBuf> {
+    let home = std::env::var("HOME").unwrap_or_default();
+    let data_home = std::env::var("XDG_DATA_HOME").ok().filter(|d| d.starts_with('/'))
+        .unwrap_or_else(|| format!("{}/.local/share", home));
+    let data_dirs = std::env::var("XDG_DATA_DIRS").ok().filter(|d| !d.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    std::iter::once(data_home.as_str())
+        .chain(data_dirs.split(':'))
+        .filter(|d| d.starts_with('/'))
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// The mimeapps.list files, highest precedence first
+fn list_files() -> Vec<PathBuf> {
+    let desktops: Vec<String> = std::env::var("XDG_CURRENT_DESKTOP").unwrap_or_default()
+        .split(':')
+        .filter(|d| !d.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let config_dirs = std::env::var("XDG_CONFIG_DIRS").ok().filter(|d| !d.is_empty())
+        .unwrap_or_else(|| "/etc/xdg".to_string());
+    let mut dirs = vec![config_home()];
+    dirs.extend(config_dirs.split(':').filter(|d| d.starts_with('/')).map(PathBuf::from));
+    dirs.extend(data_dirs().into_iter().map(|dir| dir.join("applications")));
+    dirs.iter()
+        .flat_map(|dir| {
+            desktops.iter()
+                .map(move |desktop| dir.join(format!("{}-mimeapps.list", desktop)))
+                .chain(std::iter::once(dir.join("mimeapps.list")))
+        })
+        .collect()
+}
+
+fn read_lists(files: &[PathBuf]) -> Vec<MimeAppsList> {
+    files.iter()
+        .filter_map(|file| fs::read_to_string(file).ok())
+        .map(|content| MimeAppsList::parse(&content))
+        .collect()
+}
+
+/// Whether `value` is a MIME type (`image/svg+xml`, `video/*`) rather than a path or file name
+pub fn is_mime_type(value: &str) -> bool {
+    let Some((media, subtype)) = value.split_once('/') else { return false };
+    MEDIA_TYPES.contains(&media.to_lowercase().as_str())
+        && !subtype.is_empty()
+        && subtype.chars().all(|c| c.is_ascii_alphanumeric() || "+-._*".contains(c))
+}
+
+/// The MIME type `target` stands for: a MIME type, a URI, a path, a file name or an extension
+pub fn mime_type(target: &str) -> Result<String, String> {
+    let target = target.trim();
+    if target.is_empty() {
+        return Err("Empty MIME type or file".to_string());
+    }
+    if is_mime_type(target) {
+        return Ok(target.to_lowercase());
+    }
+    let path = match desktop::local_path(target) {
+        Some(path) => path,
+        None if desktop::is_uri(target) => {
+            let scheme = target.split_once(':').map_or("", |(scheme, _)| scheme);
+            return Ok(format!("x-scheme-handler/{}", scheme.to_lowercase()));
+        }
+        None => target.to_string(),
+    };
+    if Path::new(&path).is_file() {
+        if let Ok(mime) = xdg_mime(&["query", "filetype", &path]) {
+            return Ok(mime);
+        }
+    }
+    // A bare extension is a file name with nothing before it
+    let name = path.rsplit('/').next().unwrap_or(&path);
+    let name = if name.contains('.') { name.to_string() } else { format!(".{}", name) };
+    let globs: Vec<String> = data_dirs().iter()
+        .filter_map(|dir| fs::read_to_string(dir.join("mime/globs2")).ok())
+        .collect();
+    mime_from_globs(&name, &globs).ok_or_else(|| format!("Unknown file type: {}", target))
+}
+
+/// The type globs2 files give a file name: the heaviest matching pattern, the longest on a tie.
+/// Lines are `weight:type:glob[:flags]`, matched case-insensitively unless flagged `cs`
+fn mime_from_globs(name: &str, globs: &[String]) -> Option<String> {
+    let lower = name.to_lowercase();
+    let mut best: Option<(u32, usize, &str)> = None;
+    for line in globs.iter().flat_map(|content| content.lines()) {
+        if line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split(':');
+        let (Some(weight), Some(mime), Some(glob)) = (fields.next(), fields.next(), fields.next()) else { continue };
+        let case_sensitive = fields.next().is_some_and(|flags| flags.split(',').any(|f| f == "cs"));
+        let Ok(weight) = weight.parse::<u32>() else { continue };
+        let (subject, pattern) = if case_sensitive { (name, glob.to_string()) } else { (lower.as_str(), glob.to_lowercase()) };
+        let matched = match pattern.strip_prefix('*') {
+            Some(suffix) if !suffix.contains(['*', '?', '[']) => subject.ends_with(suffix),
+            _ => glob::Pattern::new(&pattern).is_ok_and(|p| p.matches(subject)),
+        };
+        if matched && best.is_none_or(|(w, len, _)| (weight, glob.len()) > (w, len)) {
+            best = Some((weight, glob.len(), mime));
+        }
+    }
+    best.map(|(_, _, mime)| mime.to_string())
+}
+
+/// What opens `mime`: the default, if any, and every installed app associated with it, the default
+/// first. `installed` tells installed desktop ids; `handlers` are the ones whose MimeType lists it
+pub fn lookup(mime: &str, installed: impl Fn(&str) -> bool, handlers: &[String]) -> (Option<String>, Vec<String>) {
+    lookup_in(&read_lists(&list_files()), mime, installed, handlers)
+}
+
+fn lookup_in(lists: &[MimeAppsList], mime: &str, installed: impl Fn(&str) -> bool, handlers: &[String]) -> (Option<String>, Vec<String>) {
+    let mut removed = HashSet::new();
+    let mut associated: Vec<String> = Vec::new();
+    let mut default = None;
+    for list in lists {
+        if default.is_none() {
+            default = list.get(DEFAULTS, mime).into_iter().find(|id| !removed.contains(id) && installed(id));
+        }
+        // A file's removals hide what less important files add, not what it adds itself
+        associated.extend(list.get(ADDED, mime).into_iter().filter(|id| !removed.contains(id)));
+        removed.extend(list.get(REMOVED, mime));
+    }
+    associated.extend(handlers.iter().filter(|id| !removed.contains(*id)).cloned());
+    let mut seen = HashSet::new();
+    associated.retain(|id| installed(id) && seen.insert(id.clone()));
+    let default = default.or_else(|| associated.first().cloned());
+    if let Some(default) = &default {
+        associated.retain(|id| id != default);
+        associated.insert(0, default.clone());
+    }
+    (default, associated)
+}
+
+/// Make `id` the default for each of `mimes` in the user's mimeapps.list - the file written
+pub fn set_default(mimes: &[String], id: &str) -> Result<PathBuf, String> {
+    let file = config_home().join("mimeapps.list");
+    let mut list = match fs::read_to_string(&file) {
+        Ok(content) => MimeAppsList::parse(&content),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => MimeAppsList::default(),
+        Err(e) => return Err(format!("Cannot read {}: {}", file.display(), e)),
+    };
+    for mime in mimes {
+        apply_default(&mut list, mime, id);
+    }
+    let dir = file.parent().ok_or("mimeapps.list has no directory")?;
+    fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    let temp = file.with_extension("list.tmp");
+    fs::write(&temp, list.render())
+        .and_then(|()| fs::rename(&temp, &file))
+        .map_err(|e| {
+            let _ = fs::remove_file(&temp);
+            format!("Cannot write {}: {}", file.display(), e)
+        })?;
+    Ok(file)
+}
+
+fn apply_default(list: &mut MimeAppsList, mime: &str, id: &str) {
+    list.set(DEFAULTS, mime, &[id.to_string()]);
+    let mut added = list.get(ADDED, mime);
+    added.retain(|other| other != id);
+    added.insert(0, id.to_string());
+    list.set(ADDED, mime, &added);
+    // Nothing the user removed earlier may hide the new default
+    let removed = list.get(REMOVED, mime);
+    if removed.iter().any(|other| other == id) {
+        let kept: Vec<String> = removed.into_iter().filter(|other| other != id).collect();
+        list.set(REMOVED, mime, &kept);
+    }
+}
+
+/// First line of `xdg-mime <args>`
+fn xdg_mime(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("xdg-mime")
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|e| format!("Cannot run xdg-mime: {}", e))?;
+    let line = String::from_utf8_lossy(&output.stdout).lines().next().unwrap_or("").trim().to_string();
+    if !output.status.success() || line.is_empty() {
+        return Err(format!("xdg-mime {} found nothing", args.join(" ")));
+    }
+    Ok(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER: &str = "[Default Applications]\nvideo/mp4=gone.desktop;mpv.desktop;\n\n[Removed Associations]\nimage/png=gimp.desktop;\n";
+    const SYSTEM: &str = "[Default Applications]\nimage/png=gimp.desktop\nvideo/mp4=vlc.desktop\n\n[Added Associations]\nimage/png=gimp.desktop;eog.desktop;\n";
+
+    #[test]
+    fn test_mime_type_arguments() {
+        assert!(is_mime_type("image/svg+xml") && is_mime_type("video/*"));
+        assert!(!is_mime_type("/home/me/a.svg") && !is_mime_type("docs/a.pdf"));
+        assert_eq!(mime_type("mailto:me@example.org").unwrap(), "x-scheme-handler/mailto");
+        assert_eq!(mime_type("HTTPS://example.org").unwrap(), "x-scheme-handler/https");
+
+        let globs = vec!["# comment\n50:image/svg+xml:*.svg\n50:text/x-c++src:*.C:cs\n55:application/x-compressed-tar:*.tar.gz\n\
+            50:application/gzip:*.gz\n50:text/x-makefile:Makefile\n".to_string()];
+        assert_eq!(mime_from_globs("Drawing.SVG", &globs).as_deref(), Some("image/svg+xml"));
+        assert_eq!(mime_from_globs("a.tar.gz", &globs).as_deref(), Some("application/x-compressed-tar"));
+        assert_eq!(mime_from_globs("Makefile", &globs).as_deref(), Some("text/x-makefile"));
+        assert_eq!(mime_from_globs("main.c", &globs), None);
+    }
+
+    #[test]
+    fn test_lookup_precedence() {
+        let lists = [MimeAppsList::parse(USER), MimeAppsList::parse(SYSTEM)];
+        let installed = |id: &str| id != "gone";
+        // The user's first installed default wins over the system's
+        let (default, all) = lookup_in(&lists, "video/mp4", installed, &["vlc".to_string()]);
+        assert_eq!(default.as_deref(), Some("mpv"));
+        assert_eq!(all, vec!["mpv", "vlc"]);
+        // The system default is kept, but the user's removal hides the system's association
+        let (default, all) = lookup_in(&lists, "image/png", installed, &["gimp".to_string(), "krita".to_string()]);
+        assert_eq!(default.as_deref(), Some("eog"));
+        assert_eq!(all, vec!["eog", "krita"]);
+        assert_eq!(lookup_in(&lists, "text/plain", installed, &[]), (None, Vec::new()));
+    }
+
+    #[test]
+    fn test_set_default_keeps_the_rest() {
+        let mut list = MimeAppsList::parse(USER);
+        apply_default(&mut list, "image/png", "gimp");
+        apply_default(&mut list, "video/mp4", "vlc");
+        assert_eq!(list.get(DEFAULTS, "image/png"), vec!["gimp"]);
+        assert_eq!(list.get(DEFAULTS, "video/mp4"), vec!["vlc"]);
+        assert_eq!(list.get(ADDED, "video/mp4"), vec!["vlc"]);
+        assert!(list.get(REMOVED, "image/png").is_empty());
+        assert_eq!(list.render(), "[Default Applications]\nvideo/mp4=vlc.desktop;\nimage/png=gimp.desktop;\n\n\
+            [Removed Associations]\n\n[Added Associations]\nimage/png=gimp.desktop;\nvideo/mp4=vlc.desktop;\n");
+    }
+}
@@ -0,0 +1,305 @@
+// monitor.rs - Live resource samples for the `monitor` action
+// Instead of running `top` in tmux and parsing the screen, the daemon reads the kernel's counters
+// itself and streams what changed between two reads:
+//
+//   - CPU: /proc/stat jiffies, overall and per core, as a busy percentage
+//   - load: /proc/loadavg
+//   - memory: /proc/meminfo (total, available, used %)
+//   - disk IO: /proc/diskstats sectors of whole disks (loop and ram devices left out), in bytes/s
+//   - network: /proc/net/dev bytes of every interface but lo, in bytes/s
+//   - thermal: the hottest CPU/GPU/NVMe sensor and fan speeds from hwmon, with findings past the
+//     [thermal] thresholds (left out where there are no sensors)
+//
+// `monitor` is the one streaming action: the connection is handed to its own thread (so the daemon
+// keeps serving), which writes one JSON object per line - {"success", "seq", "sample"} every interval,
+// then {"success", "done", "samples"} - and stops early when the client hangs up. At most
+// MAX_STREAMS run at once.
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::sysinfo;
+use crate::thermal::{self, ThermalSample, Thresholds};
+
+const DEFAULT_INTERVAL_MS: u64 = 1000;
+const MIN_INTERVAL_MS: u64 = 250;
+const MAX_INTERVAL_MS: u64 = 60_000;
+
+const DEFAULT_SAMPLES: u64 = 60;
+const MAX_SAMPLES: u64 = 3600;
+
+/// Streams running at once - each holds a thread and a connection
+const MAX_STREAMS: usize = 4;
+
+/// /proc/diskstats counts 512-byte sectors whatever the device's block size
+const SECTOR_BYTES: u64 = 512;
+
+static STREAMS: AtomicUsize = AtomicUsize::new(0);
+
+/// {interval_ms?, samples?} from the request, checked, and the configured thermal thresholds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Options {
+    pub interval: Duration,
+    pub samples: u64,
+    pub thresholds: Thresholds,
+}
+
+impl Options {
+    pub fn from_request(data: &Value, thresholds: Thresholds) -> Result<Options, String> {
+        let number = |key: &str, default: u64, min: u64, max: u64| -> Result<u64, String> {
+            match data.get(key) {
+                None | Some(Value::Null) => Ok(default),
+                Some(value) => value.as_u64()
+                    .filter(|n| (min..=max).contains(n))
+                    .ok_or_else(|| format!("{} must be a whole number from {} to {}", key, min, max)),
+            }
+        };
+        Ok(Options {
+            interval: Duration::from_millis(number("interval_ms", DEFAULT_INTERVAL_MS, MIN_INTERVAL_MS, MAX_INTERVAL_MS)?),
+            samples: number("samples", DEFAULT_SAMPLES, 1, MAX_SAMPLES)?,
+            thresholds,
+        })
+    }
+}
+
+/// Jiffies of one cpu line: (busy, total)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct CpuTimes {
+    busy: u64,
+    total: u64,
+}
+
+/// Raw counters at one instant
+#[derive(Debug, Default)]
+struct Counters {
+    cpu: CpuTimes,
+    cores: Vec<CpuTimes>,
+    disk_read_bytes: u64,
+    disk_write_bytes: u64,
+    net_rx_bytes: u64,
+    net_tx_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Sample {
+    pub timestamp_ms: u64,
+    pub cpu_percent: f64,
+    pub per_core_percent: Vec<f64>,
+    pub load_average: Option<[f64; 3]>,
+    pub memory: MemorySample,
+    pub disk: DiskIo,
+    pub network: NetworkIo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thermal: Option<ThermalSample>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MemorySample {
+    pub total: Option<u64>,
+    pub available: Option<u64>,
+    pub used_percent: Option<f64>,
+}
+
+/// Bytes per second in each direction
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct DiskIo {
+    pub read_bytes_per_sec: u64,
+    pub write_bytes_per_sec: u64,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct NetworkIo {
+    pub rx_bytes_per_sec: u64,
+    pub tx_bytes_per_sec: u64,
+}
+
+fn parse_cpu_line(line: &str) -> Option<CpuTimes> {
+    let values: Vec<u64> = line.split_whitespace().skip(1).filter_map(|v| v.parse().ok()).collect();
+    if values.len() < 4 {
+        return None;
+    }
+    // user nice system idle iowait irq softirq steal - guest time is already counted in user
+    let idle = values[3] + values.get(4).copied().unwrap_or(0);
+    let total: u64 = values.iter().take(8).sum();
+    Some(CpuTimes { busy: total - idle, total })
+}
+
+/// (all cpus, each core) from /proc/stat
+fn parse_stat(content: &str) -> (CpuTimes, Vec<CpuTimes>) {
+    let mut all = CpuTimes::default();
+    let mut cores = Vec::new();
+    for line in content.lines() {
+        if line.starts_with("cpu ") {
+            all = parse_cpu_line(line).unwrap_or_default();
+        } else if line.starts_with("cpu") {
+            cores.extend(parse_cpu_line(line));
+        }
+    }
+    (all, cores)
+}
+
+/// (read, written) bytes of the devices `whole_disk` accepts, from /proc/diskstats
+fn parse_diskstats(content: &str, whole_disk: impl Fn(&str) -> bool) -> (u64, u64) {
+    content.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .filter(|fields| fields.len() >= 10 && whole_disk(fields[2]))
+        .fold((0, 0), |(read, written), fields| {
+            let sectors = |i: usize| fields[i].parse::<u64>().unwrap_or(0) * SECTOR_BYTES;
+            (read + sectors(5), written + sectors(9))
+        })
+}
+
+/// (received, transmitted) bytes of every interface but loopback, from /proc/net/dev
+fn parse_net_dev(content: &str) -> (u64, u64) {
+    content.lines()
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(name, _)| name.trim() != "lo")
+        .map(|(_, counters)| counters.split_whitespace().filter_map(|v| v.parse::<u64>().ok()).collect::<Vec<_>>())
+        .filter(|counters| counters.len() >= 9)
+        .fold((0, 0), |(rx, tx), counters| (rx + counters[0], tx + counters[8]))
+}
+
+/// Disks as /sys/block lists them, without loop, ram and zram devices
+fn is_whole_disk(name: &str) -> bool {
+    !["loop", "ram", "zram"].iter().any(|prefix| name.starts_with(prefix)) && Path::new("/sys/block").join(name).exists()
+}
+
+fn read_counters() -> Counters {
+    let read = |path: &str| fs::read_to_string(path).unwrap_or_default();
+    let (cpu, cores) = parse_stat(&read("/proc/stat"));
+    let (disk_read_bytes, disk_write_bytes) = parse_diskstats(&read("/proc/diskstats"), is_whole_disk);
+    let (net_rx_bytes, net_tx_bytes) = parse_net_dev(&read("/proc/net/dev"));
+    Counters { cpu, cores, disk_read_bytes, disk_write_bytes, net_rx_bytes, net_tx_bytes }
+}
+
+fn busy_percent(before: CpuTimes, after: CpuTimes) -> f64 {
+    let total = after.total.saturating_sub(before.total);
+    if total == 0 {
+        return 0.0;
+    }
+    let percent = after.busy.saturating_sub(before.busy) as f64 * 100.0 / total as f64;
+    (percent * 10.0).round() / 10.0
+}
+
+/// What changed from `before` to `after`, `elapsed` apart
+fn sample(before: &Counters, after: &Counters, elapsed: Duration, thresholds: &Thresholds) -> Sample {
+    let seconds = elapsed.as_secs_f64().max(0.001);
+    let rate = |before: u64, after: u64| (after.saturating_sub(before) as f64 / seconds).round() as u64;
+    let memory = fs::read_to_string("/proc/meminfo").map(|content| sysinfo::parse_meminfo(&content)).unwrap_or_default();
+    Sample {
+        timestamp_ms: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0),
+        cpu_percent: busy_percent(before.cpu, after.cpu),
+        per_core_percent: before.cores.iter().zip(&after.cores).map(|(b, a)| busy_percent(*b, *a)).collect(),
+        load_average: fs::read_to_string("/proc/loadavg").ok().and_then(|content| parse_loadavg(&content)),
+        memory: MemorySample {
+            used_percent: memory.total.zip(memory.available).filter(|(total, _)| *total > 0)
+                .map(|(total, available)| ((total.saturating_sub(available)) as f64 * 1000.0 / total as f64).round() / 10.0),
+            total: memory.total,
+            available: memory.available,
+        },
+        disk: DiskIo {
+            read_bytes_per_sec: rate(before.disk_read_bytes, after.disk_read_bytes),
+            write_bytes_per_sec: rate(before.disk_write_bytes, after.disk_write_bytes),
+        },
+        network: NetworkIo {
+            rx_bytes_per_sec: rate(before.net_rx_bytes, after.net_rx_bytes),
+            tx_bytes_per_sec: rate(before.net_tx_bytes, after.net_tx_bytes),
+        },
+        thermal: thermal::sample(thresholds),
+    }
+}
+
+fn parse_loadavg(content: &str) -> Option<[f64; 3]> {
+    let values: Vec<f64> = content.split_whitespace().take(3).filter_map(|v| v.parse().ok()).collect();
+    Some([*values.first()?, *values.get(1)?, *values.get(2)?])
+}
+
+/// A running stream's slot, given back when the stream ends
+pub struct Slot(());
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        STREAMS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A slot for one more stream, unless MAX_STREAMS already run
+pub fn reserve() -> Result<Slot, String> {
+    let taken = STREAMS.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |running| (running < MAX_STREAMS).then_some(running + 1));
+    taken.map(|_| Slot(())).map_err(|running| format!("{} monitor streams are already running - close one first", running))
+}
+
+/// Stream `options.samples` samples to `stream` on a thread of their own, each line stamped with `id`
+pub fn spawn(mut stream: UnixStream, options: Options, id: Option<Value>, slot: Slot) {
+    // The stream outlives the request handler - its lines keep the request's span
+    let span = tracing::Span::current();
+    std::thread::spawn(move || {
+        let _entered = span.enter();
+        let _slot = slot;
+        let mut write = |reply: Value| -> std::io::Result<()> {
+            let mut line = archy_protocol::stamp(reply, id.as_ref()).to_string();
+            line.push('\n');
+            stream.write_all(line.as_bytes())?;
+            stream.flush()
+        };
+        let mut before = read_counters();
+        let mut taken = Instant::now();
+        let mut sent = 0;
+        while sent < options.samples {
+            std::thread::sleep(options.interval);
+            let after = read_counters();
+            let now = Instant::now();
+            let sample = sample(&before, &after, now - taken, &options.thresholds);
+            sent += 1;
+            if let Err(e) = write(json!({"success": true, "seq": sent, "sample": sample})) {
+                tracing::debug!("Monitor stream ended by the client after {} samples: {}", sent - 1, e);
+                return;
+            }
+            (before, taken) = (after, now);
+        }
+        let _ = write(json!({"success": true, "done": true, "samples": sent}));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters() {
+        let (all, cores) = parse_stat("cpu  100 0 50 800 50 0 0 0 0 0\ncpu0 60 0 20 400 20 0 0 0 0 0\ncpu1 40 0 30 400 30 0 0 0 0 0\nintr 1 2\n");
+        assert_eq!(all, CpuTimes { busy: 150, total: 1000 });
+        assert_eq!(cores.len(), 2);
+        assert_eq!(busy_percent(all, CpuTimes { busy: 250, total: 1400 }), 25.0);
+        assert_eq!(busy_percent(all, all), 0.0);
+
+        let diskstats = "   8       0 sda 100 0 2000 0 50 0 4000 0 0 0 0\n   8       1 sda1 90 0 1800 0 40 0 3000 0 0 0 0\n   7       0 loop0 1 0 8 0 0 0 0 0 0 0 0\n";
+        assert_eq!(parse_diskstats(diskstats, |name| name == "sda"), (2000 * 512, 4000 * 512));
+
+        let net = "Inter-|   Receive |  Transmit\n face |bytes packets|bytes\n    lo: 500 5 0 0 0 0 0 0 500 5 0 0 0 0 0 0\n  eth0: 1000 10 0 0 0 0 0 0 300 3 0 0 0 0 0 0\n";
+        assert_eq!(parse_net_dev(net), (1000, 300));
+        assert_eq!(parse_loadavg("0.55 0.51 0.44 2/74 6028\n"), Some([0.55, 0.51, 0.44]));
+
+        let before = Counters { net_rx_bytes: 1000, disk_write_bytes: 0, ..Default::default() };
+        let after = Counters { net_rx_bytes: 3000, disk_write_bytes: 1024, ..Default::default() };
+        let sample = sample(&before, &after, Duration::from_millis(500), &Thresholds::default());
+        assert_eq!(sample.network, NetworkIo { rx_bytes_per_sec: 4000, tx_bytes_per_sec: 0 });
+        assert_eq!(sample.disk.write_bytes_per_sec, 2048);
+    }
+
+    #[test]
+    fn test_options() {
+        let thresholds = Thresholds::default();
+        let defaults = Options::from_request(&json!({}), thresholds).unwrap();
+        assert_eq!(defaults, Options { interval: Duration::from_millis(DEFAULT_INTERVAL_MS), samples: DEFAULT_SAMPLES, thresholds });
+        assert_eq!(Options::from_request(&json!({"interval_ms": 500, "samples": 3}), thresholds).unwrap().samples, 3);
+        assert!(Options::from_request(&json!({"interval_ms": 10}), thresholds).is_err());
+        assert!(Options::from_request(&json!({"samples": 0}), thresholds).is_err());
+        assert!(Options::from_request(&json!({"samples": "5"}), thresholds).is_err());
+    }
+}
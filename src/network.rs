@@ -0,0 +1,399 @@
+// network.rs - Network state for network_info
+// One structured answer instead of `ip addr`, `ip route`, `cat /etc/resolv.conf` and a ping strung
+// together in tmux:
+//
+//   - interfaces: /sys/class/net (state, MAC, MTU, speed, kind) with their addresses from getifaddrs
+//   - default routes: /proc/net/route and /proc/net/ipv6_route, lowest metric first
+//   - DNS: /etc/resolv.conf; when that only names systemd-resolved's stub (127.0.0.53) the real
+//     servers come from `resolvectl dns`
+//   - connectivity (only with `probe: true`): resolve PROBE_HOST and open a TCP connection to it, each
+//     bounded by PROBE_TIMEOUT. The host is fixed - the probe is not a way to reach arbitrary hosts
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+const SYS_NET_DIR: &str = "/sys/class/net";
+
+/// What the connectivity probe resolves and connects to
+const PROBE_HOST: &str = "archlinux.org";
+const PROBE_PORT: u16 = 443;
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// systemd-resolved's stub listeners
+const RESOLVED_STUBS: &[&str] = &["127.0.0.53", "127.0.0.54"];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Address {
+    pub address: String,
+    pub prefix: Option<u32>,
+    pub family: &'static str, // "ipv4" or "ipv6"
+}
+
+#[derive(Debug, Serialize)]
+pub struct Interface {
+    pub name: String,
+    pub kind: &'static str, // "loopback", "ethernet", "wireless", "bridge" or "virtual"
+    pub state: String,      // operstate: "up", "down", "unknown", ...
+    pub mac: Option<String>,
+    pub mtu: Option<u32>,
+    pub speed_mbps: Option<u32>,
+    pub addresses: Vec<Address>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Route {
+    pub interface: String,
+    pub gateway: Option<String>, // None for on-link routes
+    pub metric: u32,
+    pub family: &'static str,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Dns {
+    pub servers: Vec<String>,
+    pub search: Vec<String>,
+    pub source: &'static str, // "resolv.conf" or "systemd-resolved"
+}
+
+#[derive(Debug, Serialize)]
+pub struct Probe {
+    pub host: &'static str,
+    pub dns_ok: bool,
+    pub dns_ms: Option<u64>,
+    pub tcp_ok: bool,
+    pub tcp_ms: Option<u64>,
+    pub address: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NetworkInfo {
+    pub interfaces: Vec<Interface>,
+    pub default_routes: Vec<Route>,
+    pub dns: Dns,
+    pub connectivity: Option<Probe>,
+}
+
+/// The machine's network state, probing connectivity if asked to
+pub fn collect(probe: bool) -> NetworkInfo {
+    let mut addresses = interface_addresses();
+    let interfaces = read_interfaces(Path::new(SYS_NET_DIR), &mut addresses);
+    let read = |path: &str| fs::read_to_string(path).unwrap_or_default();
+    let mut default_routes = parse_route(&read("/proc/net/route"));
+    default_routes.extend(parse_ipv6_route(&read("/proc/net/ipv6_route")));
+    NetworkInfo {
+        interfaces,
+        default_routes,
+        dns: dns(&read("/etc/resolv.conf")),
+        connectivity: probe.then(probe_connectivity),
+    }
+}
+
+fn read_interfaces(dir: &Path, addresses: &mut HashMap<String, Vec<Address>>) -> Vec<Interface> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+    let mut interfaces: Vec<Interface> = entries.filter_map(|e| e.ok())
+        .map(|entry| {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            let read = |attr: &str| fs::read_to_string(path.join(attr)).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+            let kind = if read("type").as_deref() == Some("772") {
+                "loopback"
+            } else if path.join("wireless").exists() || path.join("phy80211").exists() {
+                "wireless"
+            } else if path.join("bridge").exists() {
+                "bridge"
+            } else if path.join("device").exists() {
+                "ethernet"
+            } else {
+                "virtual"
+            };
+            Interface {
+                kind,
+                state: read("operstate").unwrap_or_else(|| "unknown".to_string()),
+                mac: read("address").filter(|mac| mac != "00:00:00:00:00:00"),
+                mtu: read("mtu").and_then(|v| v.parse().ok()),
+                // Reading speed of a down link fails with EINVAL; some drivers report -1
+                speed_mbps: read("speed").and_then(|v| v.parse::<i64>().ok()).filter(|&s| s > 0).map(|s| s as u32),
+                addresses: addresses.remove(&name).unwrap_or_default(),
+                name,
+            }
+        })
+        .collect();
+    interfaces.sort_by(|a, b| (a.kind == "loopback").cmp(&(b.kind == "loopback")).then_with(|| a.name.cmp(&b.name)));
+    interfaces
+}
+
+/// The list getifaddrs(3) allocated, freed however the walk ends
+struct IfAddrs(*mut libc::ifaddrs);
+
+impl Drop for IfAddrs {
+    fn drop(&mut self) {
+        // SAFETY: the pointer came from a successful getifaddrs and is freed exactly once, here
+        unsafe { libc::freeifaddrs(self.0) };
+    }
+}
+
+/// Each interface's IPv4 and IPv6 addresses, from getifaddrs(3)
+fn interface_addresses() -> HashMap<String, Vec<Address>> {
+    let mut addresses: HashMap<String, Vec<Address>> = HashMap::new();
+    let mut head: *mut libc::ifaddrs = std::ptr::null_mut();
+    // SAFETY: `head` is valid for writes; on failure getifaddrs leaves nothing to free
+    if unsafe { libc::getifaddrs(&mut head) } != 0 {
+        return addresses;
+    }
+    let list = IfAddrs(head);
+    let mut cursor = list.0;
+    while !cursor.is_null() {
+        // SAFETY: every node of the list stays valid until `list` is dropped after the walk
+        let ifa = unsafe { &*cursor };
+        cursor = ifa.ifa_next;
+        // SAFETY: ifa_addr and ifa_netmask are null or point at a sockaddr of their sa_family
+        let Some(ip) = (unsafe { socket_ip(ifa.ifa_addr) }) else { continue };
+        let prefix = unsafe { socket_ip(ifa.ifa_netmask) }.map(|mask| match mask {
+            IpAddr::V4(mask) => u32::from(mask).count_ones(),
+            IpAddr::V6(mask) => u128::from(mask).count_ones(),
+        });
+        // SAFETY: ifa_name is a NUL-terminated string owned by the list - copied out before it is freed
+        let name = unsafe { CStr::from_ptr(ifa.ifa_name) }.to_string_lossy().to_string();
+        addresses.entry(name).or_default().push(Address {
+            address: ip.to_string(),
+            prefix,
+            family: if ip.is_ipv4() { "ipv4" } else { "ipv6" },
+        });
+    }
+    addresses
+}
+
+/// The IP of an AF_INET / AF_INET6 sockaddr
+///
+/// `addr` must be null or point at a sockaddr that is as large as its `sa_family` says
+unsafe fn socket_ip(addr: *const libc::sockaddr) -> Option<IpAddr> {
+    if addr.is_null() {
+        return None;
+    }
+    match i32::from((*addr).sa_family) {
+        libc::AF_INET => {
+            // SAFETY: an AF_INET address is a sockaddr_in (the caller guarantees the size)
+            let addr = &*(addr as *const libc::sockaddr_in);
+            Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr))))
+        }
+        libc::AF_INET6 => {
+            // SAFETY: an AF_INET6 address is a sockaddr_in6 (the caller guarantees the size)
+            let addr = &*(addr as *const libc::sockaddr_in6);
+            Some(IpAddr::V6(Ipv6Addr::from(addr.sin6_addr.s6_addr)))
+        }
+        _ => None,
+    }
+}
+
+/// IPv4 default routes from /proc/net/route (addresses are hex in the host's byte order)
+fn parse_route(content: &str) -> Vec<Route> {
+    let mut routes: Vec<Route> = content.lines().skip(1)
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .filter(|fields| fields.len() >= 8 && fields[1] == "00000000" && fields[7] == "00000000")
+        .filter(|fields| u32::from_str_radix(fields[3], 16).is_ok_and(|flags| flags & 0x1 != 0)) // RTF_UP
+        .map(|fields| {
+            let gateway = u32::from_str_radix(fields[2], 16).ok()
+                .filter(|&gateway| gateway != 0)
+                .map(|gateway| Ipv4Addr::from(gateway.to_ne_bytes()).to_string());
+            Route {
+                interface: fields[0].to_string(),
+                gateway,
+                metric: fields[6].parse().unwrap_or(0),
+                family: "ipv4",
+            }
+        })
+        .collect();
+    routes.sort_by_key(|route| route.metric);
+    routes
+}
+
+/// IPv6 default routes from /proc/net/ipv6_route
+fn parse_ipv6_route(content: &str) -> Vec<Route> {
+    let mut routes: Vec<Route> = content.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .filter(|fields| fields.len() >= 10 && fields[1] == "00" && fields[0].bytes().all(|b| b == b'0') && fields[9] != "lo")
+        .filter(|fields| u32::from_str_radix(fields[8], 16).is_ok_and(|flags| flags & 0x1 != 0))
+        .map(|fields| Route {
+            interface: fields[9].to_string(),
+            gateway: u128::from_str_radix(fields[4], 16).ok()
+                .filter(|&hop| hop != 0)
+                .map(|hop| Ipv6Addr::from(hop).to_string()),
+            metric: u32::from_str_radix(fields[5], 16).unwrap_or(0),
+            family: "ipv6",
+        })
+        .collect();
+    routes.sort_by_key(|route| route.metric);
+    routes
+}
+
+/// resolv.conf's servers, or systemd-resolved's when resolv.conf points at its stub
+fn dns(resolv_conf: &str) -> Dns {
+    let mut dns = parse_resolv_conf(resolv_conf);
+    if !dns.servers.is_empty() && dns.servers.iter().all(|server| RESOLVED_STUBS.contains(&server.as_str())) {
+        if let Some(servers) = resolvectl_servers().filter(|servers| !servers.is_empty()) {
+            dns.servers = servers;
+            dns.source = "systemd-resolved";
+        }
+    }
+    dns
+}
+
+fn parse_resolv_conf(content: &str) -> Dns {
+    let mut dns = Dns { source: "resolv.conf", ..Dns::default() };
+    for line in content.lines().map(str::trim).filter(|line| !line.starts_with('#') && !line.starts_with(';')) {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("nameserver") => dns.servers.extend(words.next().map(String::from)),
+            Some("search") | Some("domain") => dns.search = words.map(String::from).collect(),
+            _ => {}
+        }
+    }
+    dns
+}
+
+/// `resolvectl dns`: "Global: 1.1.1.1" / "Link 2 (wlan0): 192.168.1.1 fe80::1%2"
+fn resolvectl_servers() -> Option<Vec<String>> {
+    let output = Command::new("resolvectl")
+        .arg("dns")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    Some(parse_resolvectl(&String::from_utf8_lossy(&output.stdout)))
+}
+
+fn parse_resolvectl(text: &str) -> Vec<String> {
+    let mut servers: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let Some((_, list)) = line.split_once(": ") else { continue };
+        for server in list.split_whitespace() {
+            // "1.1.1.1#cloudflare-dns.com" names the DoT host
+            let server = server.split('#').next().unwrap_or(server).to_string();
+            if !servers.contains(&server) {
+                servers.push(server);
+            }
+        }
+    }
+    servers
+}
+
+/// Resolve PROBE_HOST and connect to it, each step bounded by PROBE_TIMEOUT
+fn probe_connectivity() -> Probe {
+    let mut probe = Probe { host: PROBE_HOST, dns_ok: false, dns_ms: None, tcp_ok: false, tcp_ms: None, address: None, error: None };
+    // The resolver has no timeout of its own - ask from a thread and stop waiting after PROBE_TIMEOUT
+    let started = Instant::now();
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send((PROBE_HOST, PROBE_PORT).to_socket_addrs().map(|addrs| addrs.collect::<Vec<SocketAddr>>()));
+    });
+    let addrs = match receiver.recv_timeout(PROBE_TIMEOUT) {
+        Ok(Ok(addrs)) if !addrs.is_empty() => addrs,
+        Ok(Ok(_)) => {
+            probe.error = Some(format!("{} resolved to no addresses", PROBE_HOST));
+            return probe;
+        }
+        Ok(Err(e)) => {
+            probe.error = Some(format!("Cannot resolve {}: {}", PROBE_HOST, e));
+            return probe;
+        }
+        Err(_) => {
+            probe.error = Some(format!("Resolving {} timed out after {}s", PROBE_HOST, PROBE_TIMEOUT.as_secs()));
+            return probe;
+        }
+    };
+    probe.dns_ok = true;
+    probe.dns_ms = Some(started.elapsed().as_millis() as u64);
+
+    let connect_started = Instant::now();
+    let mut last_error = None;
+    for addr in addrs.iter().take(2) {
+        match TcpStream::connect_timeout(addr, PROBE_TIMEOUT) {
+            Ok(_) => {
+                probe.tcp_ok = true;
+                probe.tcp_ms = Some(connect_started.elapsed().as_millis() as u64);
+                probe.address = Some(addr.ip().to_string());
+                return probe;
+            }
+            Err(e) => last_error = Some(format!("Cannot connect to {}: {}", addr, e)),
+        }
+    }
+    probe.error = last_error;
+    probe
+}
+
+impl NetworkInfo {
+    /// One line, e.g. "wlan0 192.168.1.20/24 via 192.168.1.1; DNS 192.168.1.1; online (34 ms)"
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        match self.default_routes.first() {
+            Some(route) => {
+                let address = self.interfaces.iter()
+                    .find(|i| i.name == route.interface)
+                    .and_then(|i| i.addresses.iter().find(|a| a.family == route.family))
+                    .map(|a| format!(" {}{}", a.address, a.prefix.map(|p| format!("/{}", p)).unwrap_or_default()))
+                    .unwrap_or_default();
+                let via = route.gateway.as_ref().map(|g| format!(" via {}", g)).unwrap_or_default();
+                parts.push(format!("{}{}{}", route.interface, address, via));
+            }
+            None => parts.push("no default route".to_string()),
+        }
+        if !self.dns.servers.is_empty() {
+            parts.push(format!("DNS {}", self.dns.servers.join(", ")));
+        }
+        if let Some(probe) = &self.connectivity {
+            parts.push(match (probe.tcp_ok, probe.dns_ok) {
+                (true, _) => format!("online ({} ms)", probe.tcp_ms.unwrap_or(0)),
+                (false, true) => "DNS works but no connection".to_string(),
+                (false, false) => "offline".to_string(),
+            });
+        }
+        parts.join("; ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes() {
+        let route = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+            wlan0\t00000000\t0101A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0\n\
+            eth0\t00000000\t0102A8C0\t0003\t0\t0\t100\t00000000\t0\t0\t0\n\
+            eth0\t0002A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\t0\t0\t0\n";
+        let routes = parse_route(route);
+        assert_eq!(routes.len(), 2);
+        assert_eq!((routes[0].interface.as_str(), routes[0].metric), ("eth0", 100));
+        if cfg!(target_endian = "little") {
+            assert_eq!(routes[0].gateway.as_deref(), Some("192.168.2.1"));
+        }
+
+        let ipv6 = "fe800000000000000000000000000000 40 00000000000000000000000000000000 00 00000000000000000000000000000000 00000100 00000001 00000000 00000001     eth0\n\
+            00000000000000000000000000000000 00 00000000000000000000000000000000 00 fe800000000000000000000000000001 00000400 00000001 00000000 00000003     eth0\n\
+            00000000000000000000000000000000 00 00000000000000000000000000000000 00 00000000000000000000000000000000 ffffffff 00000001 00000000 00200200       lo\n";
+        assert_eq!(parse_ipv6_route(ipv6), vec![Route {
+            interface: "eth0".to_string(),
+            gateway: Some("fe80::1".to_string()),
+            metric: 1024,
+            family: "ipv6",
+        }]);
+    }
+
+    #[test]
+    fn test_dns() {
+        let dns = parse_resolv_conf("# Generated\nnameserver 1.1.1.1\nnameserver 2606:4700::1111\nsearch lan example.org\noptions edns0\n");
+        assert_eq!(dns.servers, vec!["1.1.1.1", "2606:4700::1111"]);
+        assert_eq!(dns.search, vec!["lan", "example.org"]);
+        let servers = parse_resolvectl("Global: 9.9.9.9#dns.quad9.net\nLink 2 (wlan0): 192.168.1.1 fe80::1%2\nLink 3 (eth0):\nLink 4 (tun0): 192.168.1.1\n");
+        assert_eq!(servers, vec!["9.9.9.9", "192.168.1.1", "fe80::1%2"]);
+    }
+}
@@ -3,147 +3,803 @@
 
 use serde::Serialize;
 use serde_json::Value;
-use crate::parser::{Finding, Metadata, parse_intelligently};
-use crate::formatter::{format_pretty, format_error, strip_colors};
+use crate::parser::{Finding, Metadata, SuggestedAction, parse_intelligently};
+use crate::formatter::{format_pretty, format_error, format_markdown, format_criteria, format_extra_findings, format_risk, format_batch_result, format_dry_run, strip_colors};
+use std::collections::BTreeMap;
+use crate::errors::ErrorKind;
+use crate::config::Config;
+use crate::artifacts;
+use crate::canonical;
+use crate::criteria::CriteriaReport;
+use crate::batch::{BatchExecutionResult, DryRunReport};
+use crate::risk::{self, RiskAssessment};
+use crate::timings::{self, Phase};
+
+/// Current DisplayOutput schema version.
+/// Bump this whenever a field is added, and register the new field list in `fields_for_version`.
+pub const SCHEMA_VERSION: u32 = 8;
+
+/// Oldest schema version we can still render for older clients
+pub const MIN_SCHEMA_VERSION: u32 = 1;
+
+/// Field contract per schema version - every DisplayOutput serializes exactly these keys
+const FIELDS_V1: &[&str] = &[
+    "success", "command", "status", "exit_code",
+    "structured", "findings", "summary",
+    "display", "display_plain",
+    "metadata", "parsed", "raw_output",
+];
+
+const FIELDS_V2: &[&str] = &[
+    "schema_version",
+    "success", "command", "status", "exit_code",
+    "structured", "findings", "summary",
+    "display", "display_plain",
+    "metadata", "parsed", "raw_output",
+];
+
+const FIELDS_V3: &[&str] = &[
+    "schema_version",
+    "success", "command", "status", "exit_code",
+    "structured", "findings", "suggestions", "summary",
+    "display", "display_plain",
+    "metadata", "parsed", "raw_output",
+];
+
+const FIELDS_V4: &[&str] = &[
+    "schema_version",
+    "success", "command", "status", "exit_code",
+    "structured", "findings", "suggestions", "summary",
+    "display", "display_plain",
+    "metadata", "parsed", "raw_output", "artifact_ref",
+];
+
+const FIELDS_V5: &[&str] = &[
+    "schema_version",
+    "success", "command", "status", "exit_code",
+    "structured", "findings", "suggestions", "summary",
+    "display", "display_plain", "renders",
+    "metadata", "parsed", "raw_output", "artifact_ref",
+];
+
+const FIELDS_V6: &[&str] = &[
+    "schema_version",
+    "success", "command", "status", "exit_code",
+    "structured", "findings", "suggestions", "summary", "criteria_results",
+    "display", "display_plain", "renders",
+    "metadata", "parsed", "raw_output", "artifact_ref",
+];
+
+const FIELDS_V7: &[&str] = &[
+    "schema_version",
+    "success", "command", "status", "exit_code",
+    "structured", "findings", "suggestions", "summary", "criteria_results",
+    "display", "display_plain", "renders",
+    "metadata", "parsed", "raw_output", "artifact_ref", "content_hash",
+];
+
+const FIELDS_V8: &[&str] = &[
+    "schema_version",
+    "success", "command", "status", "exit_code", "risk",
+    "structured", "findings", "suggestions", "summary", "criteria_results",
+    "display", "display_plain", "renders",
+    "metadata", "parsed", "raw_output", "artifact_ref", "content_hash",
+];
+
+/// Return the stable field list for a schema version (None if unsupported)
+pub fn fields_for_version(version: u32) -> Option<&'static [&'static str]> {
+    match version {
+        1 => Some(FIELDS_V1),
+        2 => Some(FIELDS_V2),
+        3 => Some(FIELDS_V3),
+        4 => Some(FIELDS_V4),
+        5 => Some(FIELDS_V5),
+        6 => Some(FIELDS_V6),
+        7 => Some(FIELDS_V7),
+        8 => Some(FIELDS_V8),
+        _ => None,
+    }
+}
+
+/// Status of an output - serialized as a plain string for Python
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStatus {
+    Success,
+    Warning,
+    Error,
+    Timeout,
+}
+
+impl OutputStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutputStatus::Success => "success",
+            OutputStatus::Warning => "warning",
+            OutputStatus::Error => "error",
+            OutputStatus::Timeout => "timeout",
+        }
+    }
+
+    /// Map a parser status string onto an OutputStatus (unknown values count as success)
+    pub fn from_parser(status: &str) -> Self {
+        match status {
+            "error" => OutputStatus::Error,
+            "warning" => OutputStatus::Warning,
+            "timeout" => OutputStatus::Timeout,
+            _ => OutputStatus::Success,
+        }
+    }
+}
 
 /// Complete output structure returned to Python
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DisplayOutput {
+    pub schema_version: u32,         // Field contract version (see fields_for_version)
     pub success: bool,               // Quick boolean check for Python
     pub command: String,
-    pub status: String,              // "success", "error", "timeout"
+    pub status: String,              // "success", "warning", "error", "timeout"
     pub exit_code: i32,
+    pub risk: Option<RiskAssessment>, // Risk class of the command (None for non-command outputs)
 
     // For Python logic
     pub structured: Value,           // JSON data
     pub findings: Vec<Finding>,      // Key insights
+    pub suggestions: Vec<SuggestedAction>, // Follow-up commands proposed by parsers
     pub summary: String,             // Text summary
+    pub criteria_results: Option<CriteriaReport>, // Declarative success criteria outcome
 
     // For display
     pub display: String,             // Formatted text with colors
     pub display_plain: String,       // No colors (for logging)
+    pub renders: BTreeMap<String, String>, // Extra renderings requested via `formats`
 
     pub metadata: Metadata,
-    
+
     // NEW: Include full parsed output for Python access
     pub parsed: Option<Value>,       // Full ParsedOutput with status/raw_output
     pub raw_output: String,          // Original command output
+    pub artifact_ref: Option<String>, // Set when fields were truncated - fetch via get_artifact
+    pub content_hash: String,        // SHA-256 of the canonical result - identical results hash identically
+}
+
+/// Maximum sizes (bytes) of the heavy DisplayOutput fields
+#[derive(Debug, Clone)]
+pub struct OutputBudget {
+    pub max_raw_output: usize,
+    pub max_display: usize,
+    pub max_structured: usize,
+}
+
+impl OutputBudget {
+    pub fn from_config(config: &Config) -> Self {
+        OutputBudget {
+            max_raw_output: config.max_raw_output_bytes,
+            max_display: config.max_display_bytes,
+            max_structured: config.max_structured_bytes,
+        }
+    }
+}
+
+/// Typed builder - every DisplayOutput variant goes through here so all fields are always populated
+pub struct DisplayOutputBuilder {
+    command: String,
+    status: OutputStatus,
+    exit_code: i32,
+    risk: Option<RiskAssessment>,
+    structured: Value,
+    findings: Vec<Finding>,
+    suggestions: Vec<SuggestedAction>,
+    summary: String,
+    display: String,
+    metadata: Option<Metadata>,
+    parsed: Option<Value>,
+    raw_output: String,
+    format_detected: String,
+}
+
+impl DisplayOutputBuilder {
+    pub fn new(command: &str, status: OutputStatus) -> Self {
+        DisplayOutputBuilder {
+            command: command.to_string(),
+            status,
+            exit_code: if status == OutputStatus::Success { 0 } else { -1 },
+            risk: None,
+            structured: Value::Object(serde_json::Map::new()),
+            findings: Vec::new(),
+            suggestions: Vec::new(),
+            summary: String::new(),
+            display: String::new(),
+            metadata: None,
+            parsed: None,
+            raw_output: String::new(),
+            format_detected: status.as_str().to_string(),
+        }
+    }
+
+    pub fn exit_code(mut self, exit_code: i32) -> Self {
+        self.exit_code = exit_code;
+        self
+    }
+
+    pub fn risk(mut self, risk: Option<RiskAssessment>) -> Self {
+        self.risk = risk;
+        self
+    }
+
+    pub fn structured(mut self, structured: Value) -> Self {
+        self.structured = structured;
+        self
+    }
+
+    pub fn findings(mut self, findings: Vec<Finding>) -> Self {
+        self.findings = findings;
+        self
+    }
+
+    pub fn suggestions(mut self, suggestions: Vec<SuggestedAction>) -> Self {
+        self.suggestions = suggestions;
+        self
+    }
+
+    pub fn summary(mut self, summary: String) -> Self {
+        self.summary = summary;
+        self
+    }
+
+    pub fn display(mut self, display: String) -> Self {
+        self.display = display;
+        self
+    }
+
+    pub fn metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    pub fn parsed(mut self, parsed: Value) -> Self {
+        self.parsed = Some(parsed);
+        self
+    }
+
+    pub fn raw_output(mut self, raw_output: &str) -> Self {
+        self.raw_output = raw_output.to_string();
+        self
+    }
+
+    /// Label used for metadata.format_detected when no explicit metadata is given
+    pub fn format_detected(mut self, format: &str) -> Self {
+        self.format_detected = format.to_string();
+        self
+    }
+
+    pub fn build(mut self) -> DisplayOutput {
+        if let Some(risk) = &self.risk {
+            self.display.push_str(&format_risk(risk));
+        }
+        let display_plain = strip_colors(&self.display);
+
+        // Derive metadata from raw output unless the caller supplied parser metadata
+        let metadata = self.metadata.unwrap_or_else(|| Metadata {
+            line_count: self.raw_output.lines().count(),
+            byte_count: self.raw_output.len(),
+            duration_ms: None,
+            format_detected: self.format_detected.clone(),
+        });
+
+        let success = matches!(self.status, OutputStatus::Success | OutputStatus::Warning)
+            && self.exit_code == 0;
+
+        let mut output = DisplayOutput {
+            schema_version: SCHEMA_VERSION,
+            success,
+            command: self.command,
+            status: self.status.as_str().to_string(),
+            exit_code: self.exit_code,
+            risk: self.risk,
+            structured: self.structured,
+            findings: self.findings,
+            suggestions: self.suggestions,
+            summary: self.summary,
+            criteria_results: None,
+            display: self.display,
+            display_plain,
+            renders: BTreeMap::new(),
+            metadata,
+            parsed: self.parsed,
+            raw_output: self.raw_output,
+            artifact_ref: None,
+            content_hash: String::new(),
+        };
+        output.content_hash = output.result_hash();
+        output
+    }
 }
 
 impl DisplayOutput {
+    /// Start building a DisplayOutput
+    pub fn builder(command: &str, status: OutputStatus) -> DisplayOutputBuilder {
+        DisplayOutputBuilder::new(command, status)
+    }
+
     /// Create a successful output from command execution
     pub fn from_command_output(command: &str, raw_output: &str, exit_code: i32) -> Self {
-        let parsed = parse_intelligently(raw_output, command);
+        let parsed = {
+            let _parse = timings::enter(Phase::Parse);
+            parse_intelligently(raw_output, command)
+        };
 
+        let _format = timings::enter(Phase::Format);
         let display = format_pretty(
             &parsed.structured,
             &parsed.findings,
+            &parsed.suggestions,
             command,
         );
 
-        let display_plain = strip_colors(&display);
+        DisplayOutput::builder(command, OutputStatus::from_parser(&parsed.status))
+            .exit_code(exit_code)
+            .risk(command_risk(command))
+            .structured(parsed.structured.clone())
+            .findings(parsed.findings.clone())
+            .suggestions(parsed.suggestions.clone())
+            .summary(parsed.summary.clone())
+            .display(display)
+            .metadata(parsed.metadata.clone())
+            .parsed(serde_json::to_value(&parsed).unwrap_or_default())
+            .raw_output(raw_output)
+            .build()
+    }
 
-        let is_success = exit_code == 0;
+    /// Create an error output
+    pub fn from_error(command: &str, kind: ErrorKind, error: &str) -> Self {
+        DisplayOutput::build_error(command, kind, error, None)
+    }
 
-        DisplayOutput {
-            success: is_success && parsed.status != "error",
-            command: command.to_string(),
-            status: parsed.status.clone(),
-            exit_code,
-            structured: parsed.structured.clone(),
-            findings: parsed.findings.clone(),
-            summary: parsed.summary.clone(),
-            display,
-            display_plain,
-            metadata: parsed.metadata.clone(),
-            parsed: Some(serde_json::to_value(&parsed).unwrap_or_default()),
-            raw_output: raw_output.to_string(),
-        }
+    /// Create an error output with extra detail (e.g. the underlying OS error)
+    pub fn from_error_detail(command: &str, kind: ErrorKind, error: &str, detail: &str) -> Self {
+        DisplayOutput::build_error(command, kind, error, Some(detail))
     }
 
-    /// Create an error output
-    pub fn from_error(command: &str, error: &str) -> Self {
+    fn build_error(command: &str, kind: ErrorKind, error: &str, detail: Option<&str>) -> Self {
         use serde_json::json;
 
-        let display = format_error(command, error);
-        let display_plain = strip_colors(&display);
-
-        DisplayOutput {
-            success: false,
-            command: command.to_string(),
-            status: "error".to_string(),
-            exit_code: -1,
-            structured: json!({"error": error}),
-            findings: vec![],
-            summary: format!("Error: {}", error),
-            display,
-            display_plain,
-            metadata: Metadata {
+        DisplayOutput::builder(command, OutputStatus::Error)
+            .risk(command_risk(command))
+            .structured(json!({"error": kind.to_json(error, detail)}))
+            .summary(format!("Error: {}", error))
+            .display(format_error(command, error))
+            .metadata(Metadata {
                 line_count: 0,
                 byte_count: 0,
                 duration_ms: None,
                 format_detected: "error".to_string(),
-            },
-            parsed: None,
-            raw_output: error.to_string(),
-        }
+            })
+            .raw_output(error)
+            .build()
     }
 
     /// Create a timeout output
     pub fn from_timeout(command: &str, partial_output: &str) -> Self {
         use serde_json::json;
 
-        let display = format_error(command, "Command timeout - may still be running");
-        let display_plain = strip_colors(&display);
+        DisplayOutput::builder(command, OutputStatus::Timeout)
+            .risk(command_risk(command))
+            .structured(json!({
+                "timeout": true,
+                "partial_output": partial_output,
+                "error": ErrorKind::Timeout.to_json("Command timeout - may still be running", None),
+            }))
+            .summary("Command timeout".to_string())
+            .display(format_error(command, "Command timeout - may still be running"))
+            .raw_output(partial_output)
+            .build()
+    }
 
-        DisplayOutput {
-            success: false,
-            command: command.to_string(),
-            status: "timeout".to_string(),
-            exit_code: -1,
-            structured: json!({"timeout": true, "partial_output": partial_output}),
-            findings: vec![],
-            summary: "Command timeout".to_string(),
-            display,
-            display_plain,
-            metadata: Metadata {
-                line_count: partial_output.lines().count(),
-                byte_count: partial_output.len(),
-                duration_ms: None,
-                format_detected: "timeout".to_string(),
-            },
-            parsed: None,
-            raw_output: partial_output.to_string(),
-        }
+    /// Create an output for a whole batch run - structured holds the BatchExecutionResult
+    pub fn from_batch(result: &BatchExecutionResult) -> Self {
+        // Skips only happen after a failure or an unmet run_if, so they don't count on their own
+        let failures = result.failed + result.timed_out;
+        let status = if result.awaiting_input.is_some() && failures == 0 {
+            OutputStatus::Warning
+        } else if failures == 0 {
+            OutputStatus::Success
+        } else if result.successful == 0 {
+            OutputStatus::Error
+        } else {
+            OutputStatus::Warning
+        };
+
+        // Step previews stand in for raw output so output_matches criteria still work
+        let raw_output = result.commands.iter()
+            .map(|step| format!(
+                "[{}] {}\n{}",
+                step.index,
+                step.command,
+                step.output_preview.as_deref().or(step.error.as_deref()).unwrap_or("")
+            ))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        DisplayOutput::builder("batch", status)
+            .exit_code(if failures == 0 && result.awaiting_input.is_none() { 0 } else { 1 })
+            .risk(risk::highest(result.commands.iter().filter_map(|step| step.risk.as_ref())))
+            .structured(serde_json::to_value(result).unwrap_or(Value::Null))
+            .findings(result.aggregate.findings.clone())
+            .summary(format!("{}. {}", result.summary, result.aggregate.summary))
+            .display(format_batch_result(result))
+            .format_detected("batch")
+            .raw_output(&raw_output)
+            .build()
+    }
+
+    /// Create an output for a batch dry run - structured holds the DryRunReport
+    pub fn from_dry_run(report: &DryRunReport) -> Self {
+        let status = if !report.ok_to_run {
+            OutputStatus::Error
+        } else if report.high_risk > 0 {
+            OutputStatus::Warning
+        } else {
+            OutputStatus::Success
+        };
+
+        let assessments: Vec<RiskAssessment> = report.steps.iter().map(|step| risk::classify(&step.command)).collect();
+
+        DisplayOutput::builder("batch (dry run)", status)
+            .risk(risk::highest(&assessments))
+            .structured(serde_json::to_value(report).unwrap_or(Value::Null))
+            .summary(report.summary.clone())
+            .display(format_dry_run(report))
+            .format_detected("batch_dry_run")
+            .build()
     }
 
     /// Create a simple success response (for non-command actions)
+    #[cfg(test)]
     pub fn simple_success(message: &str) -> Self {
         use serde_json::json;
         use crate::formatter::color_green;
 
-        let display = format!("{}\n", color_green(&format!("✓ {}", message)));
-        let display_plain = strip_colors(&display);
-
-        DisplayOutput {
-            success: true,
-            command: "".to_string(),
-            status: "success".to_string(),
-            exit_code: 0,
-            structured: json!({"message": message}),
-            findings: vec![],
-            summary: message.to_string(),
-            display,
-            display_plain,
-            metadata: Metadata {
-                line_count: 1,
-                byte_count: message.len(),
-                duration_ms: None,
-                format_detected: "simple".to_string(),
+        DisplayOutput::builder("", OutputStatus::Success)
+            .structured(json!({"message": message}))
+            .summary(message.to_string())
+            .display(format!("{}\n", color_green(&format!("✓ {}", message))))
+            .format_detected("simple")
+            .raw_output(message)
+            .build()
+    }
+
+    /// Enforce size budgets: spill the complete output to an artifact and truncate oversized fields
+    pub fn enforce_budget(&mut self, budget: &OutputBudget, artifact_dir: &str) {
+        let structured_size = serde_json::to_string(&self.structured)
+            .map(|s| s.len())
+            .unwrap_or(0);
+
+        let raw_over = self.raw_output.len() > budget.max_raw_output;
+        let display_over = self.display.len() > budget.max_display
+            || self.display_plain.len() > budget.max_display;
+        let structured_over = structured_size > budget.max_structured;
+
+        if !raw_over && !display_over && !structured_over {
+            return;
+        }
+
+        match serde_json::to_string(self) {
+            Ok(full) => match artifacts::store(artifact_dir, &full) {
+                Ok(id) => self.artifact_ref = Some(id),
+                Err(e) => tracing::warn!("Failed to spill oversized output: {}", e),
             },
-            parsed: None,
-            raw_output: message.to_string(),
+            Err(e) => tracing::warn!("Failed to serialize oversized output: {}", e),
         }
+
+        if raw_over {
+            self.raw_output = truncate_with_notice(&self.raw_output, budget.max_raw_output);
+        }
+        if display_over {
+            self.display = truncate_with_notice(&self.display, budget.max_display);
+            self.display_plain = truncate_with_notice(&self.display_plain, budget.max_display);
+        }
+        if structured_over {
+            self.structured = serde_json::json!({
+                "truncated": true,
+                "original_bytes": structured_size,
+                "artifact_ref": self.artifact_ref,
+            });
+        }
+
+        // The parsed copy duplicates raw output and structured data - the artifact has it all
+        self.parsed = None;
     }
+
+    /// Hash of the result-defining fields - display strings and metadata vary without the result changing
+    fn result_hash(&self) -> String {
+        canonical::content_hash(&serde_json::json!({
+            "command": self.command,
+            "status": self.status,
+            "exit_code": self.exit_code,
+            "structured": self.structured,
+            "findings": self.findings,
+            "suggestions": self.suggestions,
+            "summary": self.summary,
+            "raw_output": self.raw_output,
+        }))
+    }
+
+    /// Append findings produced after parsing (e.g. project rules) and list them in the display
+    pub fn add_findings(&mut self, findings: Vec<Finding>) {
+        if findings.is_empty() {
+            return;
+        }
+        let section = format_extra_findings(&findings);
+        self.display.push_str(&section);
+        self.display_plain.push_str(&strip_colors(&section));
+        self.findings.extend(findings);
+        self.content_hash = self.result_hash();
+    }
+
+    /// Attach success criteria results and append them to the rendered display
+    pub fn set_criteria(&mut self, report: CriteriaReport) {
+        let section = format_criteria(&report);
+        self.display.push_str(&section);
+        self.display_plain.push_str(&strip_colors(&section));
+        self.criteria_results = Some(report);
+    }
+
+    /// Populate `renders` with the requested formats ("ansi", "plain", "markdown", "json")
+    pub fn add_renders(&mut self, formats: &[String]) -> Result<(), String> {
+        for format in formats {
+            let rendered = match format.as_str() {
+                "ansi" => self.display.clone(),
+                "plain" => self.display_plain.clone(),
+                "markdown" => format_markdown(
+                    &self.command,
+                    &self.summary,
+                    &self.findings,
+                    &self.suggestions,
+                    &self.structured,
+                ),
+                "json" => serde_json::to_string_pretty(&serde_json::json!({
+                    "command": self.command,
+                    "status": self.status,
+                    "summary": self.summary,
+                    "findings": self.findings,
+                    "suggestions": self.suggestions,
+                    "structured": self.structured,
+                })).map_err(|e| format!("Serialization error: {}", e))?,
+                other => return Err(format!(
+                    "Unknown render format '{}' (supported: ansi, plain, markdown, json)", other
+                )),
+            };
+            self.renders.insert(format.clone(), rendered);
+        }
+        Ok(())
+    }
+
+    /// Render this output for a specific schema version (compatibility mode for older clients)
+    pub fn render_for_schema(&self, version: u32) -> Result<Value, String> {
+        let fields = fields_for_version(version).ok_or_else(|| {
+            format!(
+                "Unsupported schema_version {} (supported: {}-{})",
+                version, MIN_SCHEMA_VERSION, SCHEMA_VERSION
+            )
+        })?;
+
+        let full = serde_json::to_value(self)
+            .map_err(|e| format!("Serialization error: {}", e))?;
+
+        let mut rendered = serde_json::Map::new();
+        if let Value::Object(map) = full {
+            for field in fields {
+                rendered.insert(field.to_string(), map.get(*field).cloned().unwrap_or(Value::Null));
+            }
+        }
+        // The reply has the requested shape, so it carries the requested version
+        if let Some(stamp) = rendered.get_mut("schema_version") {
+            *stamp = Value::from(version);
+        }
+
+        Ok(Value::Object(rendered))
+    }
+}
+
+/// Classification for outputs that may not carry a real command (errors before anything ran)
+fn command_risk(command: &str) -> Option<RiskAssessment> {
+    if command.trim().is_empty() {
+        None
+    } else {
+        Some(risk::classify(command))
+    }
+}
+
+/// Truncate text to a byte budget and append a notice pointing at the artifact
+fn truncate_with_notice(text: &str, max_bytes: usize) -> String {
+    format!(
+        "{}\n… [truncated: {} bytes total, fetch artifact_ref for the full output]",
+        artifacts::truncate_to_bytes(text, max_bytes),
+        text.len()
+    )
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(value: &Value) -> Vec<String> {
+        let mut keys: Vec<String> = value.as_object().unwrap().keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn test_all_variants_share_field_contract() {
+        let variants = vec![
+            DisplayOutput::from_command_output("echo hi", "hi\n", 0),
+            DisplayOutput::from_error("ls", ErrorKind::Io, "boom"),
+            DisplayOutput::from_timeout("sleep 100", "partial"),
+            DisplayOutput::simple_success("done"),
+        ];
+
+        let mut expected: Vec<String> = FIELDS_V8.iter().map(|s| s.to_string()).collect();
+        expected.sort();
+
+        for output in &variants {
+            let value = serde_json::to_value(output).unwrap();
+            assert_eq!(keys(&value), expected);
+            assert_eq!(value["schema_version"], SCHEMA_VERSION);
+        }
+    }
+
+    #[test]
+    fn test_content_hash_is_deterministic() {
+        let a = DisplayOutput::from_command_output("echo hi", "hi\n", 0);
+        let b = DisplayOutput::from_command_output("echo hi", "hi\n", 0);
+        let c = DisplayOutput::from_command_output("echo hi", "hello\n", 0);
+        assert_eq!(a.content_hash.len(), 64);
+        assert_eq!(a.content_hash, b.content_hash);
+        assert_ne!(a.content_hash, c.content_hash);
+    }
+
+    #[test]
+    fn test_batch_output_status() {
+        use crate::batch::BatchCommandResult;
+
+        let step = |index: usize, success: bool| BatchCommandResult {
+            index,
+            command: format!("step{}", index),
+            explanation: String::new(),
+            success,
+            status: if success { "success" } else { "error" }.to_string(),
+            output_preview: Some("ok".to_string()),
+            error: None,
+            duration_ms: 0,
+            output: None,
+            artifact_ref: None,
+            attempts: 1,
+            failure_ignored: false,
+            prompt: None,
+            answered_prompts: Vec::new(),
+            findings: Vec::new(),
+            risk: Some(risk::classify(if success { "ls" } else { "rm -rf out" })),
+        };
+
+        let mut result = BatchExecutionResult::new();
+        result.total_commands = 2;
+        result.successful = 1;
+        result.failed = 1;
+        result.commands = vec![step(1, true), step(2, false)];
+
+        let output = DisplayOutput::from_batch(&result);
+        assert_eq!(output.status, "warning");
+        assert!(!output.success);
+        assert_eq!(output.structured["failed"], 1);
+        assert!(output.display.contains("step2"));
+        assert!(output.raw_output.contains("[2] step2"));
+        assert_eq!(output.risk.unwrap().class, risk::RiskClass::Destructive);
+    }
+
+    #[test]
+    fn test_risk_is_classified_and_shown() {
+        let output = DisplayOutput::from_command_output("sudo systemctl restart sshd", "", 0);
+        let assessment = output.risk.clone().unwrap();
+        assert!(assessment.requires_confirmation);
+        assert!(output.display_plain.contains("Risk: privileged"));
+
+        let output = DisplayOutput::from_command_output("ls", "a\n", 0);
+        assert_eq!(output.risk.unwrap().class, risk::RiskClass::ReadOnly);
+        assert!(!output.display_plain.contains("Risk:"));
+        assert!(DisplayOutput::simple_success("done").risk.is_none());
+    }
+
+    #[test]
+    fn test_render_v1_drops_schema_version() {
+        let output = DisplayOutput::simple_success("done");
+        let v1 = output.render_for_schema(1).unwrap();
+        assert!(v1.get("schema_version").is_none());
+        assert_eq!(v1["summary"], "done");
+    }
+
+    #[test]
+    fn test_render_v2_drops_suggestions() {
+        let output = DisplayOutput::from_command_output("foo", "bash: foo: command not found\n", 0);
+        assert!(!output.suggestions.is_empty());
+        let v2 = output.render_for_schema(2).unwrap();
+        assert!(v2.get("suggestions").is_none());
+        assert_eq!(v2["schema_version"], 2);
+        assert_eq!(output.render_for_schema(SCHEMA_VERSION).unwrap()["schema_version"], SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_disk_full_suggests_du() {
+        let raw = "Filesystem Size Used Avail Use% Mounted\n/dev/sda1 100G 95G 5G 95% /\n";
+        let output = DisplayOutput::from_command_output("df -h", raw, 0);
+        assert!(output.suggestions.iter().any(|s| s.command == "du -sh /var/* | sort -h"));
+    }
+
+    #[test]
+    fn test_error_kind_is_structured() {
+        let output = DisplayOutput::from_error_detail("ls", ErrorKind::TmuxUnavailable, "tmux failed", "No such file");
+        assert_eq!(output.structured["error"]["kind"], "tmux_unavailable");
+        assert_eq!(output.structured["error"]["detail"], "No such file");
+
+        let output = DisplayOutput::from_error("ls", ErrorKind::Validation, "bad");
+        assert!(output.structured["error"].get("detail").is_none());
+
+        let output = DisplayOutput::from_timeout("sleep 9", "");
+        assert_eq!(output.structured["error"]["kind"], "timeout");
+    }
+
+    #[test]
+    fn test_budget_spills_to_artifact() {
+        let dir = std::env::temp_dir()
+            .join(format!("archy-budget-test-{}", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let raw = "x".repeat(500);
+        let mut output = DisplayOutput::from_command_output("cat big", &raw, 0);
+        let budget = OutputBudget { max_raw_output: 100, max_display: 10_000, max_structured: 10_000 };
+
+        output.enforce_budget(&budget, &dir);
+
+        let id = output.artifact_ref.clone().expect("artifact_ref set");
+        assert!(output.raw_output.len() < raw.len());
+        assert!(output.parsed.is_none());
+        let full = artifacts::load(&dir, &id, 0, None).unwrap();
+        assert!(full.contains(&raw));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_budget_noop_when_small() {
+        let mut output = DisplayOutput::simple_success("done");
+        output.enforce_budget(&OutputBudget { max_raw_output: 100, max_display: 100, max_structured: 100 }, "/nonexistent");
+        assert!(output.artifact_ref.is_none());
+    }
+
+    #[test]
+    fn test_add_renders_bundle() {
+        let mut output = DisplayOutput::from_command_output("foo", "bash: foo: command not found\n", 0);
+        let formats: Vec<String> = ["plain", "markdown", "json"].iter().map(|s| s.to_string()).collect();
+        output.add_renders(&formats).unwrap();
+
+        assert_eq!(output.renders.len(), 3);
+        assert_eq!(output.renders["plain"], output.display_plain);
+        assert!(output.renders["markdown"].starts_with("### `foo`"));
+        let json: Value = serde_json::from_str(&output.renders["json"]).unwrap();
+        assert_eq!(json["status"], "error");
+
+        assert!(output.add_renders(&["html".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_render_unknown_version_fails() {
+        let output = DisplayOutput::simple_success("done");
+        assert!(output.render_for_schema(99).is_err());
+    }
+
+    #[test]
+    fn test_builder_status_drives_success() {
+        let output = DisplayOutput::builder("x", OutputStatus::Timeout).build();
+        assert!(!output.success);
+        assert_eq!(output.status, "timeout");
+        assert_eq!(output.exit_code, -1);
+    }
+}
@@ -0,0 +1,103 @@
+// ownership.rs - Which tmux sessions archy may type into or kill
+// Sessions archy created, or was handed with claim_session, carry the @archy_managed option. Any other
+// existing session may be a human's own shell, so actions that send it keys or kill it are refused.
+// Sessions that don't exist yet are fine: archy creates them and marks them managed.
+
+use serde_json::Value;
+use crate::config::Config;
+use crate::tmux;
+
+/// Actions that send keys to (or kill) the session they name
+pub fn drives_session(action: &str) -> bool {
+    crate::COMMAND_ACTIONS.contains(&action) || matches!(action, "resume_batch" | "run_workflow" | "close_session")
+}
+
+/// Refuse `action` when its session exists but isn't managed
+pub fn check(action: &str, data: &Value, config: &Config) -> Result<(), String> {
+    check_with(action, data, config, |session| tmux::has_session(session) && !tmux::is_managed(session))
+}
+
+/// `check` with the tmux lookup passed in - `unmanaged` says whether a session exists unmanaged
+fn check_with(action: &str, data: &Value, config: &Config, unmanaged: impl FnOnce(&str) -> bool) -> Result<(), String> {
+    if !drives_session(action) || config.allow_unmanaged_sessions || crate::is_dry_run(data) {
+        return Ok(());
+    }
+    let session = config.get_session(data);
+    if unmanaged(session) {
+        return Err(format!(
+            "Session '{}' is not managed by archy - send claim_session to allow it, or use another session",
+            session
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const DRIVING: &[&str] = &["resume_batch", "run_workflow", "close_session"];
+
+    #[test]
+    fn test_every_session_driving_action_is_gated() {
+        let config = Config::default();
+        let data = json!({"session": "human"});
+        for action in crate::COMMAND_ACTIONS.iter().chain(DRIVING) {
+            let refused = check_with(action, &data, &config, |session| session == "human");
+            assert!(refused.is_err_and(|e| e.contains("'human'") && e.contains("claim_session")), "{}", action);
+            assert!(check_with(action, &data, &config, |_| false).is_ok(), "{}", action);
+        }
+    }
+
+    #[test]
+    fn test_gate_covers_exactly_the_session_driving_actions() {
+        // A new action that types into a session has to be added here and to drives_session
+        let driving: Vec<&str> = crate::ACTIONS.iter().copied().filter(|action| drives_session(action)).collect();
+        let mut expected: Vec<&str> = crate::COMMAND_ACTIONS.iter().chain(DRIVING).copied().collect();
+        expected.sort();
+        let mut sorted = driving.clone();
+        sorted.sort();
+        assert_eq!(sorted, expected);
+
+        // Reading a session, or claiming one, never needs it managed
+        let config = Config::default();
+        for action in ["capture", "capture_analyzed", "check_session", "wait_for_prompt", "extract_directory", "claim_session"] {
+            assert!(check_with(action, &json!({}), &config, |_| panic!("{} probed tmux", action)).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_gate_and_handler_agree_on_the_default_session() {
+        let session = format!("archy-test-default-{}", std::process::id());
+        let config = Config { default_session: session.clone(), ..Config::default() };
+        // A session the request doesn't name, started outside archy
+        if std::process::Command::new("tmux").args(["new-session", "-d", "-s", &session]).status().map_or(true, |s| !s.success()) {
+            return; // no tmux server can run here
+        }
+        let refused = check("close_session", &json!({}), &config).is_err();
+        let marked = tmux::mark_managed(&session).is_ok();
+        let allowed = check("close_session", &json!({}), &config).is_ok();
+        // The handler closes the session the gate looked at, not a hardcoded one
+        let closed = crate::close_session(&json!({}), &config).success;
+        let gone = !tmux::has_session(&session);
+        let _ = std::process::Command::new("tmux").args(["kill-session", "-t", &format!("={}", session)]).status();
+        assert!(refused && marked && allowed && closed && gone);
+    }
+
+    #[test]
+    fn test_overrides_and_default_session() {
+        let allowed = Config { allow_unmanaged_sessions: true, ..Config::default() };
+        assert!(check_with("execute", &json!({}), &allowed, |_| true).is_ok());
+        assert!(check_with("execute", &json!({"dry_run": true}), &Config::default(), |_| true).is_ok());
+
+        // Without a session in the request the default one is checked
+        let config = Config::default();
+        let mut probed = String::new();
+        let _ = check_with("execute", &json!({}), &config, |session| {
+            probed = session.to_string();
+            false
+        });
+        assert_eq!(probed, config.default_session);
+    }
+}
@@ -5,6 +5,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use regex::Regex;
 use crate::errors;  // NEW: Import error detection module
+use crate::canonical;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Importance {
@@ -15,11 +16,101 @@ pub enum Importance {
     Info,
 }
 
+impl Importance {
+    /// Sort key - lower is more severe
+    pub fn rank(&self) -> u8 {
+        match self {
+            Importance::Critical => 0,
+            Importance::High => 1,
+            Importance::Medium => 2,
+            Importance::Low => 3,
+            Importance::Info => 4,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Finding {
     pub category: String,
     pub message: String,
     pub importance: Importance,
+    #[serde(default)]
+    pub line_refs: Vec<usize>,       // 1-based line numbers in raw output
+    #[serde(default)]
+    pub excerpt: Option<String>,     // The referenced lines, for context
+    #[serde(default = "default_confidence")]
+    pub confidence: f32,             // 0.0-1.0, how sure the extractor is
+    #[serde(default)]
+    pub source_parser: String,       // Which parser produced this finding
+}
+
+fn default_confidence() -> f32 {
+    1.0
+}
+
+/// Max lines quoted in a finding excerpt
+const MAX_EXCERPT_LINES: usize = 3;
+
+impl Finding {
+    pub fn new(category: impl Into<String>, message: impl Into<String>, importance: Importance) -> Self {
+        Finding {
+            category: category.into(),
+            message: message.into(),
+            importance,
+            line_refs: Vec::new(),
+            excerpt: None,
+            confidence: default_confidence(),
+            source_parser: String::new(),
+        }
+    }
+
+    /// Record which parser produced this finding and how confident it is
+    pub fn with_provenance(mut self, source_parser: &str, confidence: f32) -> Self {
+        self.source_parser = source_parser.to_string();
+        self.confidence = confidence.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Point this finding at 1-based lines of the raw output and quote the first few as an excerpt
+    pub fn with_lines(mut self, line_refs: Vec<usize>, raw: &str) -> Self {
+        let lines: Vec<&str> = raw.lines().collect();
+        let quoted: Vec<String> = line_refs
+            .iter()
+            .take(MAX_EXCERPT_LINES)
+            .filter_map(|&n| lines.get(n.wrapping_sub(1)).map(|l| format!("{}: {}", n, l.trim_end())))
+            .collect();
+
+        self.excerpt = if quoted.is_empty() { None } else { Some(quoted.join("\n")) };
+        self.line_refs = line_refs;
+        self
+    }
+}
+
+/// How risky it is to run a suggested follow-up command
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RiskLevel {
+    Low,       // Read-only inspection
+    Medium,    // Changes state but is easy to undo
+    High,      // Privileged or hard to undo
+}
+
+/// A follow-up command the AI loop can offer without inferring it from text
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuggestedAction {
+    pub command: String,
+    pub rationale: String,
+    pub risk: RiskLevel,
+}
+
+impl SuggestedAction {
+    pub fn new(command: &str, rationale: &str, risk: RiskLevel) -> Self {
+        SuggestedAction {
+            command: command.to_string(),
+            rationale: rationale.to_string(),
+            risk,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,10 +126,12 @@ pub struct ParsedOutput {
     pub raw: String,
     pub structured: Value,
     pub findings: Vec<Finding>,
+    pub suggestions: Vec<SuggestedAction>,
     pub summary: String,
     pub metadata: Metadata,
     pub status: String,  // NEW: "success", "warning", or "error"
     pub raw_output: String,  // NEW: Keep original output for Python
+    pub content_hash: String,  // SHA-256 of the canonical result (see canonical.rs)
 }
 
 impl ParsedOutput {
@@ -48,10 +141,12 @@ impl ParsedOutput {
             raw: raw.to_string(),
             structured: json!({}),
             findings: Vec::new(),
+            suggestions: Vec::new(),
             summary: String::new(),
             metadata,
             status: String::new(),
             raw_output: raw.to_string(),
+            content_hash: String::new(),
         }
     }
 
@@ -67,6 +162,12 @@ impl ParsedOutput {
         self
     }
 
+    /// Builder method to set suggested follow-up actions
+    fn with_suggestions(mut self, suggestions: Vec<SuggestedAction>) -> Self {
+        self.suggestions = suggestions;
+        self
+    }
+
     /// Builder method to set summary
     fn with_summary(mut self, summary: String) -> Self {
         self.summary = summary;
@@ -82,54 +183,68 @@ impl ParsedOutput {
     }
 }
 
-/// Detect the format of command output
-pub fn detect_format(output: &str, command: &str) -> String {
+/// Format detection confidence: matched by command name, by output content, or fell through
+const SCORE_COMMAND_MATCH: f32 = 0.9;
+const SCORE_CONTENT_MATCH: f32 = 0.7;
+const SCORE_STRUCTURAL_MATCH: f32 = 0.6;
+const SCORE_FALLBACK: f32 = 0.4;
+
+/// Detect the format of command output together with a detection confidence score
+pub fn score_format(output: &str, command: &str) -> (String, f32) {
     let lower_cmd = command.to_lowercase();
     let lower_output = output.to_lowercase();
 
     // Check by command name first
-    if lower_cmd.contains("nmap") {
-        return "nmap".to_string();
+    let by_command = if let Some((format, _)) = package_command(command) {
+        Some(format)
+    } else if lower_cmd.contains("nmap") {
+        Some("nmap")
     } else if lower_cmd.contains("netstat") || lower_cmd.contains("ss") {
-        return "network_table".to_string();
+        Some("network_table")
     } else if lower_cmd.contains("ps") || lower_cmd.contains("top") {
-        return "process_table".to_string();
+        Some("process_table")
     } else if lower_cmd.contains("ls") && (lower_cmd.contains("-l") || lower_cmd.contains("--long")) {
-        return "ls_long".to_string();
+        Some("ls_long")
     } else if lower_cmd.contains("ip") && (lower_cmd.contains("addr") || lower_cmd.contains("ip a") || lower_cmd == "ip a") {
-        return "ip_addr".to_string();
+        Some("ip_addr")
     } else if lower_cmd.contains("systemctl") {
-        return "systemctl".to_string();
+        Some("systemctl")
     } else if lower_cmd.contains("df") {
-        return "disk_usage".to_string();
+        Some("disk_usage")
     } else if lower_cmd.contains("lsblk") {
-        return "block_devices".to_string();
+        Some("block_devices")
     } else if lower_cmd.contains("journalctl") {
-        return "journalctl".to_string();
+        Some("journalctl")
+    } else {
+        None
+    };
+
+    if let Some(format) = by_command {
+        return (format.to_string(), SCORE_COMMAND_MATCH);
     }
 
     // Check by content patterns
     if lower_output.contains("starting nmap") || lower_output.contains("host is up") {
-        return "nmap".to_string();
+        return ("nmap".to_string(), SCORE_CONTENT_MATCH);
     } else if lower_output.contains("tcp") && lower_output.contains("established") {
-        return "network_table".to_string();
+        return ("network_table".to_string(), SCORE_CONTENT_MATCH);
     } else if output.lines().filter(|l| l.contains("|") || l.contains("│")).count() > 3 {
-        return "table".to_string();
+        return ("table".to_string(), SCORE_STRUCTURAL_MATCH);
     } else if (output.trim().starts_with('{') && output.trim().ends_with('}'))
            || (output.trim().starts_with('[') && output.trim().ends_with(']')) {
         // Only detect as JSON if it's actually a complete JSON structure
         // AND has more than just a simple value
         if output.trim().len() > 10 && (output.contains(':') || output.contains(',')) {
-            return "json".to_string();
+            return ("json".to_string(), SCORE_STRUCTURAL_MATCH);
         }
     }
 
-    "plain_text".to_string()
+    ("plain_text".to_string(), SCORE_FALLBACK)
 }
 
 /// Parse intelligently based on format
 pub fn parse_intelligently(raw: &str, command: &str) -> ParsedOutput {
-    let format = detect_format(raw, command);
+    let (format, format_confidence) = score_format(raw, command);
     let line_count = raw.lines().count();
     let byte_count = raw.len();
 
@@ -142,7 +257,7 @@ pub fn parse_intelligently(raw: &str, command: &str) -> ParsedOutput {
 
     // NEW: Detect errors in output
     let detected_errors = errors::detect_errors(raw);
-    let error_status = errors::determine_status(&detected_errors);
+    let mut status = errors::determine_status(&detected_errors);
 
     // Parse based on format
     let mut parsed = match format.as_str() {
@@ -154,33 +269,107 @@ pub fn parse_intelligently(raw: &str, command: &str) -> ParsedOutput {
         "systemctl" => parse_systemctl(raw, metadata),
         "disk_usage" => parse_disk_usage(raw, metadata),
         "journalctl" => parse_journalctl(raw, metadata),
+        "pkg_search" | "pkg_info" | "pkg_upgrades" => parse_packages(raw, command, metadata),
         "json" => parse_json(raw, metadata),
         _ => parse_generic(raw, metadata),
     };
 
+    // Format parser findings inherit the format detection confidence
+    for finding in parsed.findings.iter_mut().filter(|f| f.source_parser.is_empty()) {
+        finding.source_parser = format.clone();
+        finding.confidence = format_confidence;
+    }
+
     // NEW: Add command to structured output for collaborative monitoring
     if let Some(obj) = parsed.structured.as_object_mut() {
         obj.insert("command".to_string(), json!(command));
     }
 
+    // Critical parser findings (e.g. disk 95% full) don't fail the command but deserve a warning
+    let has_critical_finding = parsed.findings.iter().any(|f| matches!(f.importance, Importance::Critical));
+    if status == "success" && has_critical_finding {
+        status = "warning".to_string();
+    }
+
+    // Suggest follow-ups for detected errors (skip duplicates a format parser already proposed)
+    for detected_error in &detected_errors {
+        for suggestion in suggest_for_error(&detected_error.pattern, command) {
+            if !parsed.suggestions.iter().any(|s| s.command == suggestion.command) {
+                parsed.suggestions.push(suggestion);
+            }
+        }
+    }
+
     // NEW: Add error findings to the parsed output
     for detected_error in detected_errors {
-        parsed.findings.push(Finding {
-            category: format!("Error: {}", detected_error.pattern),
-            message: detected_error.message,
-            importance: match detected_error.severity {
-                errors::ErrorSeverity::Critical => Importance::Critical,
-                errors::ErrorSeverity::High => Importance::High,
-                errors::ErrorSeverity::Medium => Importance::Medium,
-                errors::ErrorSeverity::Low => Importance::Low,
-            },
-        });
+        let message = if detected_error.occurrences > 1 {
+            format!("{} ({} occurrences)", detected_error.message, detected_error.occurrences)
+        } else {
+            detected_error.message
+        };
+
+        let importance = match detected_error.severity {
+            errors::ErrorSeverity::Critical => Importance::Critical,
+            errors::ErrorSeverity::High => Importance::High,
+            errors::ErrorSeverity::Medium => Importance::Medium,
+            errors::ErrorSeverity::Low => Importance::Low,
+        };
+
+        parsed.findings.push(
+            Finding::new(format!("Error: {}", detected_error.pattern), message, importance)
+                .with_lines(detected_error.lines, raw)
+                .with_provenance("error_detector", detected_error.confidence),
+        );
     }
 
-    // NEW: Set status based on error detection
-    parsed.status = error_status;
+    // Status reflects detected errors, not just the exit code
+    parsed.status = status;
+
+    // Hash only the result-defining fields so timing metadata doesn't break dedup
+    parsed.content_hash = canonical::content_hash(&json!({
+        "structured": parsed.structured,
+        "findings": parsed.findings,
+        "suggestions": parsed.suggestions,
+        "summary": parsed.summary,
+        "status": parsed.status,
+        "raw_output": parsed.raw_output,
+    }));
+
+    parsed
+}
 
-parsed
+/// Follow-up commands for a detected error pattern
+fn suggest_for_error(pattern: &str, command: &str) -> Vec<SuggestedAction> {
+    let binary = command.split_whitespace().next().unwrap_or("");
+
+    match pattern {
+        "command_not_found" if !binary.is_empty() => vec![
+            SuggestedAction::new(
+                &format!("pacman -F {}", binary),
+                "Find which package provides the missing command",
+                RiskLevel::Low,
+            ),
+        ],
+        "permission_denied" | "operation_not_permitted" if !command.trim_start().starts_with("sudo ") => vec![
+            SuggestedAction::new(
+                &format!("sudo {}", command.trim()),
+                "Retry with elevated privileges",
+                RiskLevel::High,
+            ),
+        ],
+        "disk_full" => vec![
+            SuggestedAction::new("df -h", "Check which filesystem is full", RiskLevel::Low),
+            SuggestedAction::new("du -sh /var/* | sort -h", "Find the largest directories under /var", RiskLevel::Low),
+        ],
+        "out_of_memory" => vec![
+            SuggestedAction::new("free -h", "Check memory and swap usage", RiskLevel::Low),
+            SuggestedAction::new("ps aux --sort=-%mem | head -n 10", "Find the processes using the most memory", RiskLevel::Low),
+        ],
+        "connection_refused" => vec![
+            SuggestedAction::new("ss -tulpn", "Check which services are listening", RiskLevel::Low),
+        ],
+        _ => Vec::new(),
+    }
 }
 
 /// Extract the last command from terminal output by finding prompt patterns
@@ -248,27 +437,27 @@ fn parse_nmap(raw: &str, metadata: Metadata) -> ParsedOutput {
     }
 
     if hosts_up > 0 {
-        findings.push(Finding {
-            category: "Host Count".to_string(),
-            message: format!("Found {} active host(s) on network", hosts_up),
-            importance: if hosts_up > 10 { Importance::High } else { Importance::Medium },
-        });
+        findings.push(Finding::new(
+            "Host Count",
+            format!("Found {} active host(s) on network", hosts_up),
+            if hosts_up > 10 { Importance::High } else { Importance::Medium },
+        ));
     }
 
     if !open_ports.is_empty() {
-        findings.push(Finding {
-            category: "Open Ports".to_string(),
-            message: format!("Detected {} open port(s): {}", open_ports.len(), open_ports.join(", ")),
-            importance: Importance::High,
-        });
+        findings.push(Finding::new(
+            "Open Ports",
+            format!("Detected {} open port(s): {}", open_ports.len(), open_ports.join(", ")),
+            Importance::High,
+        ));
     }
 
     if !services.is_empty() {
-        findings.push(Finding {
-            category: "Services".to_string(),
-            message: format!("Services detected: {}", services.join(", ")),
-            importance: Importance::Info,
-        });
+        findings.push(Finding::new(
+            "Services",
+            format!("Services detected: {}", services.join(", ")),
+            Importance::Info,
+        ));
     }
 
     let structured = json!({
@@ -284,9 +473,19 @@ fn parse_nmap(raw: &str, metadata: Metadata) -> ParsedOutput {
         "Network scan complete - no hosts detected".to_string()
     };
 
+    let mut suggestions = Vec::new();
+    if !open_ports.is_empty() {
+        suggestions.push(SuggestedAction::new(
+            "ss -tulpn",
+            "Compare open ports with locally listening services",
+            RiskLevel::Low,
+        ));
+    }
+
     ParsedOutput::new(raw, metadata)
         .with_structured(structured)
         .with_findings(findings)
+        .with_suggestions(suggestions)
         .with_summary(summary)
         .complete()
 }
@@ -306,7 +505,7 @@ fn parse_network_table(raw: &str, metadata: Metadata) -> ParsedOutput {
             let parts: Vec<&str> = line.split_whitespace().collect();
             if parts.len() >= 5 {
                 connections.push(json!({
-                    "protocol": parts.get(0).unwrap_or(&""),
+                    "protocol": parts.first().unwrap_or(&""),
                     "local": parts.get(3).unwrap_or(&""),
                     "remote": parts.get(4).unwrap_or(&""),
                     "state": "ESTABLISHED"
@@ -318,19 +517,19 @@ fn parse_network_table(raw: &str, metadata: Metadata) -> ParsedOutput {
     }
 
     if established > 0 {
-        findings.push(Finding {
-            category: "Active Connections".to_string(),
-            message: format!("{} established connection(s)", established),
-            importance: if established > 50 { Importance::High } else { Importance::Info },
-        });
+        findings.push(Finding::new(
+            "Active Connections",
+            format!("{} established connection(s)", established),
+            if established > 50 { Importance::High } else { Importance::Info },
+        ));
     }
 
     if listening > 0 {
-        findings.push(Finding {
-            category: "Listening Ports".to_string(),
-            message: format!("{} listening port(s)", listening),
-            importance: Importance::Info,
-        });
+        findings.push(Finding::new(
+            "Listening Ports",
+            format!("{} listening port(s)", listening),
+            Importance::Info,
+        ));
     }
 
     let structured = json!({
@@ -354,11 +553,11 @@ fn parse_process_table(raw: &str, metadata: Metadata) -> ParsedOutput {
     let process_count = raw.lines().filter(|l| !l.trim().is_empty() && !l.to_lowercase().contains("pid")).count();
 
     if process_count > 0 {
-        findings.push(Finding {
-            category: "Process Count".to_string(),
-            message: format!("{} process(es) listed", process_count),
-            importance: Importance::Info,
-        });
+        findings.push(Finding::new(
+            "Process Count",
+            format!("{} process(es) listed", process_count),
+            Importance::Info,
+        ));
     }
 
     let structured = json!({
@@ -398,11 +597,11 @@ fn parse_ls_long(raw: &str, metadata: Metadata) -> ParsedOutput {
     }
 
     if files > 0 || directories > 0 {
-        findings.push(Finding {
-            category: "Directory Contents".to_string(),
-            message: format!("{} file(s), {} director(ies)", files, directories),
-            importance: Importance::Info,
-        });
+        findings.push(Finding::new(
+            "Directory Contents",
+            format!("{} file(s), {} director(ies)", files, directories),
+            Importance::Info,
+        ));
     }
 
     let structured = json!({
@@ -444,19 +643,19 @@ fn parse_ip_addr(raw: &str, metadata: Metadata) -> ParsedOutput {
     }
 
     if !interfaces.is_empty() {
-        findings.push(Finding {
-            category: "Network Interfaces".to_string(),
-            message: format!("{} interface(s) detected: {}", interfaces.len(), interfaces.join(", ")),
-            importance: Importance::Info,
-        });
+        findings.push(Finding::new(
+            "Network Interfaces",
+            format!("{} interface(s) detected: {}", interfaces.len(), interfaces.join(", ")),
+            Importance::Info,
+        ));
     }
 
     if !ipv4_addresses.is_empty() {
-        findings.push(Finding {
-            category: "IP Addresses".to_string(),
-            message: format!("{} IPv4 address(es): {}", ipv4_addresses.len(), ipv4_addresses.join(", ")),
-            importance: Importance::Info,
-        });
+        findings.push(Finding::new(
+            "IP Addresses",
+            format!("{} IPv4 address(es): {}", ipv4_addresses.len(), ipv4_addresses.join(", ")),
+            Importance::Info,
+        ));
     }
 
     let structured = json!({
@@ -478,8 +677,9 @@ fn parse_systemctl(raw: &str, metadata: Metadata) -> ParsedOutput {
     let mut findings = Vec::new();
     let mut active_services = Vec::new();
     let mut failed_services = Vec::new();
+    let mut failed_lines = Vec::new();
 
-    for line in raw.lines() {
+    for (idx, line) in raw.lines().enumerate() {
         let lower = line.to_lowercase();
         let parts: Vec<&str> = line.split_whitespace().collect();
 
@@ -492,6 +692,7 @@ fn parse_systemctl(raw: &str, metadata: Metadata) -> ParsedOutput {
                     active_services.push(service_name.clone());
                 } else if lower.contains("failed") {
                     failed_services.push(service_name.clone());
+                    failed_lines.push(idx + 1);
                 }
             }
         }
@@ -499,19 +700,19 @@ fn parse_systemctl(raw: &str, metadata: Metadata) -> ParsedOutput {
 
     if !failed_services.is_empty() {
         let service_list = failed_services.join(", ");
-        findings.push(Finding {
-            category: "Failed Services".to_string(),
-            message: format!("{} service(s) in failed state: {}", failed_services.len(), service_list),
-            importance: Importance::High,
-        });
+        findings.push(Finding::new(
+            "Failed Services",
+            format!("{} service(s) in failed state: {}", failed_services.len(), service_list),
+            Importance::High,
+        ).with_lines(failed_lines, raw));
     }
 
     if !active_services.is_empty() {
-        findings.push(Finding {
-            category: "Active Services".to_string(),
-            message: format!("{} service(s) active and running", active_services.len()),
-            importance: Importance::Info,
-        });
+        findings.push(Finding::new(
+            "Active Services",
+            format!("{} service(s) active and running", active_services.len()),
+            Importance::Info,
+        ));
     }
 
     let structured = json!({
@@ -527,9 +728,18 @@ fn parse_systemctl(raw: &str, metadata: Metadata) -> ParsedOutput {
         format!("{} active, {} failed", active_services.len(), failed_services.len())
     };
 
+    let suggestions = failed_services.iter().take(3).map(|service| {
+        SuggestedAction::new(
+            &format!("journalctl -u {} -n 50 --no-pager", service),
+            &format!("Inspect recent logs for failed service {}", service),
+            RiskLevel::Low,
+        )
+    }).collect();
+
     ParsedOutput::new(raw, metadata)
         .with_structured(structured)
         .with_findings(findings)
+        .with_suggestions(suggestions)
         .with_summary(summary)
         .complete()
 }
@@ -538,8 +748,9 @@ fn parse_systemctl(raw: &str, metadata: Metadata) -> ParsedOutput {
 fn parse_disk_usage(raw: &str, metadata: Metadata) -> ParsedOutput {
     let mut findings = Vec::new();
     let mut filesystems = Vec::new();
+    let mut full_mounts: Vec<String> = Vec::new();
 
-    for line in raw.lines() {
+    for (idx, line) in raw.lines().enumerate() {
         if line.contains('%') {
             let parts: Vec<&str> = line.split_whitespace().collect();
             if parts.len() >= 5 {
@@ -555,17 +766,20 @@ fn parse_disk_usage(raw: &str, metadata: Metadata) -> ParsedOutput {
                         }));
 
                         if usage > 90 {
-                            findings.push(Finding {
-                                category: "Disk Space Critical".to_string(),
-                                message: format!("{} is {}% full", parts[0], usage),
-                                importance: Importance::Critical,
-                            });
+                            if let Some(mount) = parts.get(5) {
+                                full_mounts.push(mount.to_string());
+                            }
+                            findings.push(Finding::new(
+                                "Disk Space Critical",
+                                format!("{} is {}% full", parts[0], usage),
+                                Importance::Critical,
+                            ).with_lines(vec![idx + 1], raw));
                         } else if usage > 80 {
-                            findings.push(Finding {
-                                category: "Disk Space Warning".to_string(),
-                                message: format!("{} is {}% full", parts[0], usage),
-                                importance: Importance::High,
-                            });
+                            findings.push(Finding::new(
+                                "Disk Space Warning",
+                                format!("{} is {}% full", parts[0], usage),
+                                Importance::High,
+                            ).with_lines(vec![idx + 1], raw));
                         }
                     }
                 }
@@ -573,6 +787,23 @@ fn parse_disk_usage(raw: &str, metadata: Metadata) -> ParsedOutput {
         }
     }
 
+    let mut suggestions = Vec::new();
+    for mount in &full_mounts {
+        let target = if mount == "/" { "/var" } else { mount.as_str() };
+        suggestions.push(SuggestedAction::new(
+            &format!("du -sh {}/* | sort -h", target.trim_end_matches('/')),
+            &format!("Find what is filling up {}", mount),
+            RiskLevel::Low,
+        ));
+    }
+    if !full_mounts.is_empty() {
+        suggestions.push(SuggestedAction::new(
+            "journalctl --disk-usage",
+            "Check how much space the systemd journal uses",
+            RiskLevel::Low,
+        ));
+    }
+
     let structured = json!({
         "filesystems": filesystems
     });
@@ -582,6 +813,7 @@ fn parse_disk_usage(raw: &str, metadata: Metadata) -> ParsedOutput {
     ParsedOutput::new(raw, metadata)
         .with_structured(structured)
         .with_findings(findings)
+        .with_suggestions(suggestions)
         .with_summary(summary)
         .complete()
 }
@@ -632,13 +864,16 @@ fn parse_journalctl(raw: &str, metadata: Metadata) -> ParsedOutput {
     let mut errors = Vec::new();
     let mut warnings = Vec::new();
     let mut failed_services = std::collections::HashSet::new();
+    let mut error_lines = Vec::new();
+    let mut warning_lines = Vec::new();
 
-    for line in raw.lines() {
+    for (idx, line) in raw.lines().enumerate() {
         let lower = line.to_lowercase();
 
         // Detect error levels
         if lower.contains("error") || lower.contains("failed") || lower.contains("fail") {
             errors.push(line.to_string());
+            error_lines.push(idx + 1);
 
             // Extract service names
             if lower.contains(".service") {
@@ -651,33 +886,34 @@ fn parse_journalctl(raw: &str, metadata: Metadata) -> ParsedOutput {
             }
         } else if lower.contains("warning") || lower.contains("warn") {
             warnings.push(line.to_string());
+            warning_lines.push(idx + 1);
         }
     }
 
     // Generate findings
     if !errors.is_empty() {
-        findings.push(Finding {
-            category: "Errors".to_string(),
-            message: format!("{} error(s) found in logs", errors.len()),
-            importance: Importance::High,
-        });
+        findings.push(Finding::new(
+            "Errors",
+            format!("{} error(s) found in logs", errors.len()),
+            Importance::High,
+        ).with_lines(error_lines, raw));
     }
 
     if !warnings.is_empty() {
-        findings.push(Finding {
-            category: "Warnings".to_string(),
-            message: format!("{} warning(s) found in logs", warnings.len()),
-            importance: Importance::Medium,
-        });
+        findings.push(Finding::new(
+            "Warnings",
+            format!("{} warning(s) found in logs", warnings.len()),
+            Importance::Medium,
+        ).with_lines(warning_lines, raw));
     }
 
     if !failed_services.is_empty() {
         let service_list: Vec<String> = failed_services.iter().cloned().collect();
-        findings.push(Finding {
-            category: "Failed Services".to_string(),
-            message: format!("Services with issues: {}", service_list.join(", ")),
-            importance: Importance::High,
-        });
+        findings.push(Finding::new(
+            "Failed Services",
+            format!("Services with issues: {}", service_list.join(", ")),
+            Importance::High,
+        ));
     }
 
     let structured = json!({
@@ -702,6 +938,345 @@ fn parse_journalctl(raw: &str, metadata: Metadata) -> ParsedOutput {
 }
 
 /// Generic parser for unknown formats
+/// (format, manager) of a package manager query: searches, package details and pending upgrades
+pub fn package_command(command: &str) -> Option<(&'static str, &'static str)> {
+    let words: Vec<&str> = command.split_whitespace()
+        .skip_while(|w| matches!(*w, "sudo" | "env" | "LC_ALL=C"))
+        .collect();
+    let program = words.first()?.rsplit('/').next()?;
+    let has = |word: &str| words.iter().skip(1).any(|w| *w == word);
+    match program {
+        "checkupdates" => Some(("pkg_upgrades", "pacman")),
+        "pacman" | "yay" | "paru" => {
+            let manager = "pacman";
+            if has("-Ss") {
+                Some(("pkg_search", manager))
+            } else if has("-Si") || has("-Sii") || has("-Qi") || has("-Qii") {
+                Some(("pkg_info", manager))
+            } else if has("-Qu") {
+                Some(("pkg_upgrades", manager))
+            } else {
+                None
+            }
+        }
+        "apt" | "apt-cache" => match words.get(1).copied() {
+            Some("search") => Some(("pkg_search", "apt")),
+            Some("show") => Some(("pkg_info", "apt")),
+            Some("list") if has("--upgradable") => Some(("pkg_upgrades", "apt")),
+            _ => None,
+        },
+        "dnf" | "dnf5" | "yum" => {
+            let subcommand = words.iter().skip(1).find(|w| !w.starts_with('-')).copied();
+            match subcommand {
+                Some("search") => Some(("pkg_search", "dnf")),
+                Some("info") => Some(("pkg_info", "dnf")),
+                Some("check-update") | Some("check-upgrade") => Some(("pkg_upgrades", "dnf")),
+                _ => None,
+            }
+        }
+        "zypper" => {
+            let subcommand = words.iter().skip(1).find(|w| !w.starts_with('-')).copied();
+            match subcommand {
+                Some("search") | Some("se") => Some(("pkg_search", "zypper")),
+                Some("info") | Some("if") => Some(("pkg_info", "zypper")),
+                Some("list-updates") | Some("lu") => Some(("pkg_upgrades", "zypper")),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Architectures dnf appends to package names ("bash.x86_64")
+const RPM_ARCHES: &[&str] = &["x86_64", "noarch", "i686", "aarch64", "armv7hl", "ppc64le", "s390x", "src"];
+
+fn strip_rpm_arch(name: &str) -> &str {
+    match name.rsplit_once('.') {
+        Some((base, arch)) if RPM_ARCHES.contains(&arch) => base,
+        _ => name,
+    }
+}
+
+/// The cells of a zypper table row ("i | bash | The GNU Bourne-Again Shell | package")
+fn table_cells(line: &str) -> Option<Vec<&str>> {
+    if !line.contains('|') || line.trim_start().starts_with("--") {
+        return None;
+    }
+    Some(line.split('|').map(str::trim).collect())
+}
+
+fn parse_packages(raw: &str, command: &str, metadata: Metadata) -> ParsedOutput {
+    let Some((format, manager)) = package_command(command) else { return parse_generic(raw, metadata) };
+    match format {
+        "pkg_search" => parse_pkg_search(raw, manager, metadata),
+        "pkg_info" => parse_pkg_info(raw, manager, metadata),
+        _ => parse_pkg_upgrades(raw, manager, metadata),
+    }
+}
+
+fn parse_pkg_search(raw: &str, manager: &str, metadata: Metadata) -> ParsedOutput {
+    let mut packages: Vec<Value> = Vec::new();
+    match manager {
+        // "extra/ripgrep 14.1.0-1 [installed]" then the indented description
+        "pacman" => {
+            for line in raw.lines() {
+                if line.starts_with(char::is_whitespace) {
+                    if let Some(last) = packages.last_mut() {
+                        if last["description"].is_null() {
+                            last["description"] = json!(line.trim());
+                        }
+                    }
+                    continue;
+                }
+                let parts: Vec<&str> = line.split_whitespace().collect();
+                let Some((repository, name)) = parts.first().and_then(|p| p.split_once('/')) else { continue };
+                packages.push(json!({
+                    "name": name,
+                    "version": parts.get(1),
+                    "repository": repository,
+                    "installed": line.contains("[installed"),
+                    "description": null,
+                }));
+            }
+        }
+        // "ripgrep - Recursively searches directories for a regex pattern"
+        "apt" => {
+            for line in raw.lines() {
+                if let Some((name, description)) = line.split_once(" - ") {
+                    packages.push(json!({"name": name.trim(), "description": description.trim()}));
+                } else if let Some((name, rest)) = line.split_once('/') {
+                    // `apt search`: "ripgrep/stable 13.0.0-4 amd64 [installed]" then the description
+                    if !line.starts_with(char::is_whitespace) && !name.contains(' ') {
+                        packages.push(json!({
+                            "name": name,
+                            "version": rest.split_whitespace().nth(1),
+                            "installed": rest.contains("[installed"),
+                            "description": null,
+                        }));
+                    }
+                } else if line.starts_with(char::is_whitespace) && !line.trim().is_empty() {
+                    if let Some(last) = packages.last_mut() {
+                        if last["description"].is_null() {
+                            last["description"] = json!(line.trim());
+                        }
+                    }
+                }
+            }
+        }
+        // dnf4 "ripgrep.x86_64 : Line-oriented search tool", dnf5 " ripgrep.x86_64\tLine-oriented search tool"
+        "dnf" => {
+            for line in raw.lines() {
+                let trimmed = line.trim();
+                if trimmed.is_empty() || trimmed.starts_with('=') || trimmed.starts_with("Last metadata")
+                    || trimmed.starts_with("Matched fields") || trimmed.starts_with("Updating and loading") {
+                    continue;
+                }
+                let (name, description) = match trimmed.split_once(" : ") {
+                    Some((name, description)) => (name.trim(), description.trim()),
+                    None => match trimmed.split_once(char::is_whitespace) {
+                        Some((name, description)) => (name, description.trim()),
+                        None => continue,
+                    },
+                };
+                if name.contains('.') {
+                    packages.push(json!({"name": strip_rpm_arch(name), "description": description}));
+                }
+            }
+        }
+        // "S | Name | Summary | Type" table
+        _ => {
+            for cells in raw.lines().filter_map(table_cells) {
+                if cells.len() < 3 || cells[1] == "Name" || cells[1].is_empty() {
+                    continue;
+                }
+                packages.push(json!({
+                    "name": cells[1],
+                    "installed": cells[0].starts_with('i'),
+                    "description": cells[2],
+                }));
+            }
+        }
+    }
+
+    let mut findings = Vec::new();
+    if packages.is_empty() {
+        findings.push(Finding::new("Packages", "No packages matched the search", Importance::Low));
+    }
+    let installed = packages.iter().filter(|p| p["installed"] == json!(true)).count();
+    let summary = match installed {
+        0 => format!("{} package(s) found", packages.len()),
+        _ => format!("{} package(s) found, {} installed", packages.len(), installed),
+    };
+
+    ParsedOutput::new(raw, metadata)
+        .with_structured(json!({"manager": manager, "packages": packages}))
+        .with_findings(findings)
+        .with_summary(summary)
+        .complete()
+}
+
+fn parse_pkg_info(raw: &str, manager: &str, metadata: Metadata) -> ParsedOutput {
+    // "Key : value" (pacman, dnf, zypper) or "Key: value" (apt); indented lines continue the value
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for line in raw.lines() {
+        if line.trim().is_empty() {
+            // apt and dnf print one stanza per version - the first is the candidate
+            if !fields.is_empty() {
+                break;
+            }
+            continue;
+        }
+        if line.starts_with(char::is_whitespace) {
+            if let Some((_, value)) = fields.last_mut() {
+                let more = line.trim().trim_start_matches(": ").trim();
+                if more != "." {
+                    value.push(if value.is_empty() { ' ' } else { '\n' });
+                    value.push_str(more);
+                }
+                *value = value.trim().to_string();
+            }
+        } else if let Some((key, value)) = line.split_once(':') {
+            fields.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    let field = |keys: &[&str]| -> Option<String> {
+        keys.iter()
+            .find_map(|key| fields.iter().find(|(k, _)| k == key))
+            .map(|(_, value)| value.clone())
+            .filter(|value| !value.is_empty() && value != "None")
+    };
+    // "glibc  zlib" (pacman) or "libc6 (>= 2.34), libgcc-s1" (apt) - names only
+    let list = |keys: &[&str]| -> Vec<String> {
+        let Some(value) = field(keys) else { return Vec::new() };
+        let items: Vec<&str> = if value.contains(',') { value.split(',').collect() } else { value.split_whitespace().collect() };
+        items.iter().filter_map(|item| item.split_whitespace().next()).map(String::from).collect()
+    };
+    let version = match (field(&["Version"]), field(&["Release"])) {
+        (Some(version), Some(release)) if manager == "dnf" => Some(format!("{}-{}", version, release)),
+        (version, _) => version,
+    };
+    let name = field(&["Name", "Package"]);
+    let package = json!({
+        "name": name,
+        "version": version,
+        "description": field(&["Description", "Description-en", "Summary"]).map(|d| d.lines().next().unwrap_or_default().to_string()),
+        "repository": field(&["Repository", "From repo", "Repo"]),
+        "url": field(&["URL", "Homepage", "Url"]),
+        "licenses": list(&["Licenses", "License"]),
+        "depends": list(&["Depends On", "Depends", "Requires"]),
+        "installed_size": field(&["Installed Size", "Installed-Size", "Installed size"]),
+        "installed": field(&["Install Date", "Installed"]).is_some_and(|v| v != "No"),
+    });
+
+    let mut findings = Vec::new();
+    let summary = match &name {
+        Some(name) => format!("{} {}", name, package["version"].as_str().unwrap_or("")).trim().to_string(),
+        None => {
+            findings.push(Finding::new("Packages", "No package details in the output", Importance::Medium));
+            "Package not found".to_string()
+        }
+    };
+
+    ParsedOutput::new(raw, metadata)
+        .with_structured(json!({"manager": manager, "package": package, "fields": fields.into_iter().map(|(k, v)| (k, json!(v))).collect::<serde_json::Map<String, Value>>()}))
+        .with_findings(findings)
+        .with_summary(summary)
+        .complete()
+}
+
+fn parse_pkg_upgrades(raw: &str, manager: &str, metadata: Metadata) -> ParsedOutput {
+    let mut upgrades: Vec<Value> = Vec::new();
+    let mut kernel_lines = Vec::new();
+    for (idx, line) in raw.lines().enumerate() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let upgrade = match manager {
+            // "linux 6.10.1.arch1-1 -> 6.10.2.arch1-1"
+            "pacman" => match parts.as_slice() {
+                [name, current, "->", available, ..] => Some(json!({
+                    "name": name, "current": current, "available": available,
+                    "ignored": line.contains("[ignored]"), "security": false,
+                })),
+                _ => None,
+            },
+            // "openssl/bookworm-security 3.0.14-1~deb12u1 amd64 [upgradable from: 3.0.13-1~deb12u1]"
+            "apt" => match (parts.first().and_then(|p| p.split_once('/')), parts.get(1)) {
+                (Some((name, suites)), Some(available)) if line.contains("[upgradable from:") => Some(json!({
+                    "name": name,
+                    "current": line.rsplit("from: ").next().map(|c| c.trim_end_matches(']').trim()),
+                    "available": available,
+                    "repository": suites,
+                    "security": suites.split(',').any(|s| s.ends_with("-security")),
+                })),
+                _ => None,
+            },
+            // "kernel.x86_64    6.9.7-200.fc40    updates"
+            "dnf" => {
+                if line.starts_with("Obsoleting") || line.starts_with("Security:") {
+                    break;
+                }
+                match parts.as_slice() {
+                    [name, available, repository] if name.contains('.') && !line.starts_with(char::is_whitespace) => Some(json!({
+                        "name": strip_rpm_arch(name), "available": available, "repository": repository,
+                        "security": repository.contains("security"),
+                    })),
+                    _ => None,
+                }
+            }
+            // "v | Main Repository | bash | 5.2.26-1.2 | 5.2.32-1.1 | x86_64"
+            _ => table_cells(line).filter(|cells| cells.len() >= 5 && cells[2] != "Name" && !cells[2].is_empty()).map(|cells| json!({
+                "name": cells[2], "current": cells[3], "available": cells[4], "repository": cells[1],
+                "security": false,
+            })),
+        };
+        if let Some(upgrade) = upgrade {
+            let name = upgrade["name"].as_str().unwrap_or_default();
+            if name == "linux" || name.starts_with("linux-image") || name.starts_with("linux-lts") || name.starts_with("linux-zen")
+                || name == "kernel" || name == "kernel-core" || name == "kernel-default" {
+                kernel_lines.push(idx + 1);
+            }
+            upgrades.push(upgrade);
+        }
+    }
+
+    let mut findings = Vec::new();
+    let security = upgrades.iter().filter(|u| u["security"] == json!(true)).count();
+    if security > 0 {
+        findings.push(Finding::new("Security Updates", format!("{} security update(s) pending", security), Importance::High));
+    }
+    if !kernel_lines.is_empty() {
+        findings.push(
+            Finding::new("Kernel Update", "A kernel upgrade is pending - reboot after upgrading", Importance::Medium)
+                .with_lines(kernel_lines, raw),
+        );
+    }
+    if !upgrades.is_empty() {
+        findings.push(Finding::new("Updates", format!("{} package upgrade(s) available", upgrades.len()), Importance::Info));
+    }
+
+    let mut suggestions = Vec::new();
+    if !upgrades.is_empty() {
+        let upgrade = match manager {
+            "pacman" => "sudo pacman -Syu",
+            "apt" => "sudo apt upgrade",
+            "dnf" => "sudo dnf upgrade",
+            _ => "sudo zypper update",
+        };
+        suggestions.push(SuggestedAction::new(upgrade, "Install the pending upgrades", RiskLevel::High));
+    }
+
+    let summary = match upgrades.len() {
+        0 => "System is up to date".to_string(),
+        count => format!("{} upgrade(s) available", count),
+    };
+
+    ParsedOutput::new(raw, metadata)
+        .with_structured(json!({"manager": manager, "upgrades": upgrades}))
+        .with_findings(findings)
+        .with_suggestions(suggestions)
+        .with_summary(summary)
+        .complete()
+}
+
 fn parse_generic(raw: &str, metadata: Metadata) -> ParsedOutput {
     let findings = Vec::new();
     let trimmed = raw.trim();
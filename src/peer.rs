@@ -0,0 +1,109 @@
+// peer.rs - Identity of the process on the other end of the Unix socket
+// The kernel reports the connecting process's pid/uid/gid (SO_PEERCRED) - it can't be spoofed by the client
+
+use serde::{Deserialize, Serialize};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerCred {
+    pub pid: i32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// Credentials of the connected client
+pub fn peer_cred(stream: &UnixStream) -> Result<PeerCred, String> {
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+
+    // SAFETY: `cred` and `len` are valid for writes and `len` holds the buffer's size
+    let rc = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if rc != 0 {
+        return Err(format!("SO_PEERCRED failed: {}", std::io::Error::last_os_error()));
+    }
+    Ok(PeerCred { pid: cred.pid, uid: cred.uid, gid: cred.gid })
+}
+
+/// uid the daemon runs as
+pub fn own_uid() -> u32 {
+    // SAFETY: getuid has no preconditions and can't fail
+    unsafe { libc::getuid() }
+}
+
+/// First buffer for a passwd entry's strings - doubled while the lookup reports ERANGE, up to the cap
+const PASSWD_BUF: usize = 4096;
+const PASSWD_BUF_MAX: usize = 1 << 20;
+
+/// Run a getpwuid_r/getpwnam_r `lookup` and hand the entry to `read` - its strings (pw_name, ...) point
+/// into a buffer that is freed when this returns, so `read` must copy out what it keeps
+fn passwd_entry<T>(
+    lookup: impl Fn(*mut libc::passwd, *mut libc::c_char, usize, *mut *mut libc::passwd) -> libc::c_int,
+    read: impl FnOnce(&libc::passwd) -> T,
+) -> Option<T> {
+    let mut size = PASSWD_BUF;
+    loop {
+        // SAFETY: passwd is plain C data (integers and pointers) - all zeroes is a valid value, and the
+        // lookup overwrites it before it is read
+        let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut buf = vec![0 as libc::c_char; size];
+        let mut result: *mut libc::passwd = std::ptr::null_mut();
+        match lookup(&mut pwd, buf.as_mut_ptr(), buf.len(), &mut result) {
+            libc::ERANGE if size < PASSWD_BUF_MAX => size *= 2,
+            // `result` is null when there is no such entry
+            0 if !result.is_null() => return Some(read(&pwd)),
+            _ => return None,
+        }
+    }
+}
+
+/// Login name for a uid (None if it has no passwd entry)
+pub fn user_name(uid: u32) -> Option<String> {
+    passwd_entry(
+        // SAFETY: all pointers are valid for the call and `len` is the buffer's real size
+        |pwd, buf, len, result| unsafe { libc::getpwuid_r(uid, pwd, buf, len, result) },
+        // SAFETY: on success pw_name points at a NUL-terminated string inside the buffer, which is
+        // still alive here - it is copied before passwd_entry returns
+        |pwd| unsafe { std::ffi::CStr::from_ptr(pwd.pw_name) }.to_string_lossy().into_owned(),
+    )
+}
+
+/// uid for a login name (None if there's no such user)
+pub fn uid_for_name(name: &str) -> Option<u32> {
+    let c_name = std::ffi::CString::new(name).ok()?;
+    passwd_entry(
+        // SAFETY: `c_name` is NUL-terminated and outlives the call; the other pointers are valid and
+        // `len` is the buffer's real size
+        |pwd, buf, len, result| unsafe { libc::getpwnam_r(c_name.as_ptr(), pwd, buf, len, result) },
+        |pwd| pwd.pw_uid,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_socketpair_reports_own_process() {
+        let (a, _b) = UnixStream::pair().unwrap();
+        let cred = peer_cred(&a).unwrap();
+        assert_eq!(cred.pid, std::process::id() as i32);
+        assert_eq!(cred.uid, own_uid());
+    }
+
+    #[test]
+    fn test_name_lookup_round_trip() {
+        if let Some(name) = user_name(own_uid()) {
+            assert_eq!(uid_for_name(&name), Some(own_uid()));
+        }
+        assert_eq!(uid_for_name("no-such-user-archy"), None);
+    }
+}
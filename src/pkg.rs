@@ -0,0 +1,298 @@
+// pkg.rs - Package manager queries: pkg_search, pkg_info, pkg_list_upgrades
+// One set of actions whatever the distro packages with. The manager is found from os-release (ID, then
+// ID_LIKE) among those installed, else the first installed of pacman, apt, dnf, zypper. Each action
+// builds that manager's command, runs it directly (read-only queries, no tmux, no root) under
+// PKG_TIMEOUT with LC_ALL=C, and hands the output to the parser, whose pkg_* formats turn it into
+// packages, findings and suggestions - the same result the AI loop gets from running it in tmux.
+//
+// Only package names and search words reach the command line, limited to letters, digits and @._+- and never
+// starting with '-', so nothing in a request can become an option.
+
+use serde_json::Value;
+use std::time::Duration;
+use crate::desktop;
+use crate::helpers::process;
+use crate::parser::{self, ParsedOutput};
+use crate::sysinfo;
+
+/// Searches may refresh metadata (dnf) or sync a temporary database (checkupdates)
+const PKG_TIMEOUT: Duration = Duration::from_secs(60);
+
+const MAX_NAME_LEN: usize = 128;
+const MAX_SEARCH_TERMS: usize = 8;
+
+/// Default and largest number of packages a search or upgrade list returns
+const DEFAULT_LIMIT: usize = 50;
+const MAX_LIMIT: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Manager {
+    Pacman,
+    Apt,
+    Dnf,
+    Zypper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Query {
+    Search,
+    Info,
+    Upgrades,
+}
+
+/// A query's command and its parsed output
+pub struct Outcome {
+    pub manager: Manager,
+    pub command: String,
+    pub parsed: ParsedOutput,
+}
+
+impl Manager {
+    const ALL: [Manager; 4] = [Manager::Pacman, Manager::Apt, Manager::Dnf, Manager::Zypper];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Manager::Pacman => "pacman",
+            Manager::Apt => "apt",
+            Manager::Dnf => "dnf",
+            Manager::Zypper => "zypper",
+        }
+    }
+
+    /// The program that has to be installed for this manager to be usable
+    fn program(&self) -> &'static str {
+        match self {
+            Manager::Pacman => "pacman",
+            Manager::Apt => "apt-cache",
+            Manager::Dnf => "dnf",
+            Manager::Zypper => "zypper",
+        }
+    }
+
+    /// os-release IDs of the distros (and families) this manager belongs to
+    fn distros(&self) -> &'static [&'static str] {
+        match self {
+            Manager::Pacman => &["arch", "manjaro", "endeavouros", "cachyos", "garuda", "artix"],
+            Manager::Apt => &["debian", "ubuntu", "linuxmint", "pop", "raspbian", "kali"],
+            Manager::Dnf => &["fedora", "rhel", "centos", "rocky", "almalinux", "nobara"],
+            Manager::Zypper => &["opensuse", "suse", "sles", "opensuse-tumbleweed", "opensuse-leap"],
+        }
+    }
+
+    /// The distro's manager, if its program is installed
+    pub fn detect() -> Result<Manager, String> {
+        let installed: Vec<Manager> = Manager::ALL.into_iter()
+            .filter(|m| desktop::resolve_program(m.program()).is_some())
+            .collect();
+        let distro = sysinfo::os_release();
+        let ids: Vec<String> = distro.id.iter().chain(distro.id_like.iter())
+            .flat_map(|ids| ids.split_whitespace().map(str::to_lowercase).collect::<Vec<_>>())
+            .collect();
+        Manager::pick(&installed, &ids).ok_or_else(|| "No supported package manager found (pacman, apt, dnf, zypper)".to_string())
+    }
+
+    fn pick(installed: &[Manager], distro_ids: &[String]) -> Option<Manager> {
+        distro_ids.iter()
+            .find_map(|id| installed.iter().find(|m| m.distros().contains(&id.as_str()) || id.starts_with("opensuse") && **m == Manager::Zypper))
+            .or(installed.first())
+            .copied()
+    }
+
+    /// The commands for a query, tried in order until one succeeds
+    fn commands(&self, query: Query, args: &[String]) -> Vec<Vec<String>> {
+        let with = |base: &[&str]| base.iter().map(|s| s.to_string()).chain(args.iter().cloned()).collect::<Vec<_>>();
+        match (self, query) {
+            (Manager::Pacman, Query::Search) => vec![with(&["pacman", "-Ss"])],
+            // Repositories first, else what's installed (AUR and local packages)
+            (Manager::Pacman, Query::Info) => vec![with(&["pacman", "-Si"]), with(&["pacman", "-Qi"])],
+            // checkupdates syncs a temporary database; -Qu is only as fresh as the last -Sy
+            (Manager::Pacman, Query::Upgrades) if desktop::resolve_program("checkupdates").is_some() => vec![with(&["checkupdates"])],
+            (Manager::Pacman, Query::Upgrades) => vec![with(&["pacman", "-Qu"])],
+            (Manager::Apt, Query::Search) => vec![with(&["apt-cache", "search"])],
+            (Manager::Apt, Query::Info) => vec![with(&["apt-cache", "show", "--no-all-versions"])],
+            (Manager::Apt, Query::Upgrades) => vec![with(&["apt", "list", "--upgradable"])],
+            (Manager::Dnf, Query::Search) => vec![with(&["dnf", "-q", "search"])],
+            (Manager::Dnf, Query::Info) => vec![with(&["dnf", "-q", "info"])],
+            (Manager::Dnf, Query::Upgrades) => vec![with(&["dnf", "-q", "check-update"])],
+            (Manager::Zypper, Query::Search) => vec![with(&["zypper", "--non-interactive", "--quiet", "search"])],
+            (Manager::Zypper, Query::Info) => vec![with(&["zypper", "--non-interactive", "--quiet", "info"])],
+            (Manager::Zypper, Query::Upgrades) => vec![with(&["zypper", "--non-interactive", "--quiet", "list-updates"])],
+        }
+    }
+
+    /// Exit codes that mean "answered", not "failed": no match, or updates pending
+    fn answered(&self, query: Query, program: &str, code: i32) -> bool {
+        code == 0 || match (self, query) {
+            (Manager::Pacman, Query::Search) | (Manager::Pacman, Query::Upgrades) if program == "pacman" => code == 1,
+            (Manager::Pacman, Query::Upgrades) => code == 2, // checkupdates: nothing to update
+            (Manager::Dnf, Query::Upgrades) => code == 100,
+            (Manager::Dnf, Query::Search) => code == 1,
+            (Manager::Zypper, Query::Search) | (Manager::Zypper, Query::Info) => code == 104,
+            (Manager::Zypper, Query::Upgrades) => code == 100,
+            _ => false,
+        }
+    }
+}
+
+/// A package name or search word: nothing an option or a shell could make anything of
+fn validate_name(name: &str, what: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(format!("Invalid {}: must be 1-{} characters", what, MAX_NAME_LEN));
+    }
+    if name.starts_with('-') {
+        return Err(format!("Invalid {}: cannot start with '-'", what));
+    }
+    if let Some(c) = name.chars().find(|c| !(c.is_ascii_alphanumeric() || "@._+-".contains(*c))) {
+        return Err(format!("Invalid {}: character '{}' not allowed", what, c));
+    }
+    Ok(())
+}
+
+/// A checked pkg_* request
+#[derive(Debug, PartialEq)]
+pub struct PackageQuery {
+    query: Query,
+    args: Vec<String>,
+    pub limit: usize,
+}
+
+impl PackageQuery {
+    /// `pkg_search` {query, limit?} (words that must all match), `pkg_info` {package},
+    /// `pkg_list_upgrades` {limit?}
+    pub fn from_request(action: &str, data: &Value) -> Result<PackageQuery, String> {
+        let limit = match data.get("limit") {
+            None | Some(Value::Null) => DEFAULT_LIMIT,
+            Some(value) => value.as_u64()
+                .map(|n| n as usize)
+                .filter(|n| (1..=MAX_LIMIT).contains(n))
+                .ok_or_else(|| format!("limit must be a whole number from 1 to {}", MAX_LIMIT))?,
+        };
+        let (query, args) = match action {
+            "pkg_search" => {
+                let query = data.get("query").and_then(|v| v.as_str()).ok_or("Missing query parameter")?;
+                let terms: Vec<String> = query.split_whitespace().map(String::from).collect();
+                if terms.is_empty() || terms.len() > MAX_SEARCH_TERMS {
+                    return Err(format!("query must have 1-{} words", MAX_SEARCH_TERMS));
+                }
+                for term in &terms {
+                    validate_name(term, "search word")?;
+                }
+                (Query::Search, terms)
+            }
+            "pkg_info" => {
+                let package = data.get("package").and_then(|v| v.as_str()).ok_or("Missing package parameter")?;
+                validate_name(package, "package name")?;
+                (Query::Info, vec![package.to_string()])
+            }
+            "pkg_list_upgrades" => (Query::Upgrades, Vec::new()),
+            _ => return Err(format!("Not a package query: {}", action)),
+        };
+        Ok(PackageQuery { query, args, limit })
+    }
+
+    /// Run it with the distro's package manager
+    pub fn run(&self) -> Result<Outcome, String> {
+        run(Manager::detect()?, self.query, &self.args)
+    }
+}
+
+fn run(manager: Manager, query: Query, args: &[String]) -> Result<Outcome, String> {
+    let mut failure = String::new();
+    for argv in manager.commands(query, args) {
+        let command = argv.join(" ");
+        let (code, stdout, stderr) = process::run(&argv, PKG_TIMEOUT)?;
+        if manager.answered(query, &argv[0], code) {
+            let parsed = parser::parse_intelligently(&stdout, &command);
+            return Ok(Outcome { manager, command, parsed });
+        }
+        let reason = stderr.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or("no error output").to_string();
+        failure = format!("{} failed (exit {}): {}", command, code, reason);
+    }
+    Err(failure)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_manager() {
+        let ids = |ids: &[&str]| ids.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let all = [Manager::Pacman, Manager::Apt, Manager::Dnf];
+        assert_eq!(Manager::pick(&all, &ids(&["ubuntu", "debian"])), Some(Manager::Apt));
+        assert_eq!(Manager::pick(&all, &ids(&["nobara", "fedora"])), Some(Manager::Dnf));
+        // An unknown derivative falls back to its ID_LIKE, then to whatever is installed
+        assert_eq!(Manager::pick(&all, &ids(&["mydistro", "arch"])), Some(Manager::Pacman));
+        assert_eq!(Manager::pick(&[Manager::Zypper], &ids(&["opensuse-slowroll"])), Some(Manager::Zypper));
+        assert_eq!(Manager::pick(&[Manager::Dnf], &ids(&["debian"])), Some(Manager::Dnf));
+        assert_eq!(Manager::pick(&[], &ids(&["arch"])), None);
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("lib32-gcc-libs", "package name").is_ok());
+        assert!(validate_name("gtk+3.0@x", "package name").is_ok());
+        assert!(validate_name("--config=/tmp/x", "package name").is_err());
+        assert!(validate_name("vim;rm", "package name").is_err());
+        assert!(validate_name("", "package name").is_err());
+        let request = |action: &str, data: Value| PackageQuery::from_request(action, &data);
+        assert!(request("pkg_search", serde_json::json!({"query": "a b c d e f g h i"})).is_err());
+        assert!(request("pkg_search", serde_json::json!({"query": "vim -Syu"})).is_err());
+        assert!(request("pkg_list_upgrades", serde_json::json!({"limit": 0})).is_err());
+        assert_eq!(request("pkg_info", serde_json::json!({"package": "vim"})).unwrap(), PackageQuery {
+            query: Query::Info,
+            args: vec!["vim".to_string()],
+            limit: DEFAULT_LIMIT,
+        });
+    }
+
+    #[test]
+    fn test_parse_outputs() {
+        let search = parser::parse_intelligently(
+            "extra/ripgrep 14.1.0-1 [installed]\n    A search tool\ncore/grep 3.11-1\n    GNU grep\n",
+            "pacman -Ss grep",
+        );
+        let packages = search.structured["packages"].as_array().unwrap();
+        assert_eq!(packages.len(), 2);
+        assert_eq!((packages[0]["name"].as_str(), packages[0]["installed"].as_bool()), (Some("ripgrep"), Some(true)));
+        assert_eq!(packages[1]["description"], "GNU grep");
+
+        let upgrades = parser::parse_intelligently(
+            "Listing... Done\nopenssl/bookworm-security 3.0.14-1~deb12u1 amd64 [upgradable from: 3.0.13-1~deb12u1]\n\
+             linux-image-amd64/bookworm 6.1.99-1 amd64 [upgradable from: 6.1.94-1]\n",
+            "apt list --upgradable",
+        );
+        let list = upgrades.structured["upgrades"].as_array().unwrap();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0]["current"], "3.0.13-1~deb12u1");
+        assert!(upgrades.findings.iter().any(|f| f.category == "Security Updates"));
+        assert!(upgrades.findings.iter().any(|f| f.category == "Kernel Update"));
+        assert_eq!(upgrades.suggestions[0].command, "sudo apt upgrade");
+
+        let dnf = parser::parse_intelligently("\nkernel.x86_64    6.9.7-200.fc40    updates\nbash.x86_64  5.2.26-3.fc40  updates\n", "dnf -q check-update");
+        assert_eq!(dnf.structured["upgrades"][1]["name"], "bash");
+
+        let info = parser::parse_intelligently(
+            "Repository      : extra\nName            : ripgrep\nVersion         : 14.1.0-1\nDescription     : A search tool\n\
+             Licenses        : MIT  Unlicense\nDepends On      : gcc-libs  pcre2\nOptional Deps   : None\n",
+            "pacman -Si ripgrep",
+        );
+        let package = &info.structured["package"];
+        assert_eq!((package["name"].as_str(), package["repository"].as_str()), (Some("ripgrep"), Some("extra")));
+        assert_eq!(package["depends"], serde_json::json!(["gcc-libs", "pcre2"]));
+        assert_eq!(info.summary, "ripgrep 14.1.0-1");
+
+        let apt = parser::parse_intelligently(
+            "Package: curl\nVersion: 7.88.1-10\nDepends: libc6 (>= 2.34), libcurl4 (= 7.88.1-10)\nDescription-en: command line tool\n more text\n .\n",
+            "apt-cache show --no-all-versions curl",
+        );
+        assert_eq!(apt.structured["package"]["depends"], serde_json::json!(["libc6", "libcurl4"]));
+        assert_eq!(apt.structured["package"]["description"], "command line tool");
+
+        let zypper = parser::parse_intelligently(
+            "S | Name | Summary | Type\n--+------+---------+--------\ni | bash | The GNU Bourne-Again Shell | package\n",
+            "zypper --non-interactive --quiet search bash",
+        );
+        assert_eq!(zypper.structured["packages"][0]["installed"], true);
+    }
+}
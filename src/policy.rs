@@ -0,0 +1,72 @@
+// policy.rs - Command validation and `[security] blocked_patterns` for every command-running action
+// Checked once in handle_client, so execute_analyzed, execute_and_wait and execute_smart refuse the
+// same commands as execute. Batch steps are checked again as they run (after secret expansion).
+
+use serde_json::Value;
+use crate::config::Config;
+use crate::events;
+use crate::helpers::security::{check_blocked_patterns, validate_command};
+
+/// Refuse `action` when any command it would run fails validation or matches a blocked pattern
+pub fn check(action: &str, data: &Value, config: &Config) -> Result<(), String> {
+    match refused(action, data, config) {
+        Some((command, e)) => {
+            events::blocked(&command, &e);
+            Err(e)
+        }
+        None => Ok(()),
+    }
+}
+
+/// The first refused command and why - dry runs report validation themselves
+fn refused(action: &str, data: &Value, config: &Config) -> Option<(String, String)> {
+    if !crate::COMMAND_ACTIONS.contains(&action) || crate::is_dry_run(data) {
+        return None;
+    }
+    crate::request_commands(data).into_iter().find_map(|command| {
+        validate_command(&command)
+            .and_then(|_| check_blocked_patterns(&command, &config.blocked_patterns))
+            .err()
+            .map(|e| (command, e))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(action: &str, command: &str) -> Value {
+        if action.contains("batch") {
+            json!({"commands": ["echo ok", command]})
+        } else {
+            json!({"command": command})
+        }
+    }
+
+    #[test]
+    fn test_every_command_action_is_checked() {
+        let config = Config { blocked_patterns: vec!["shutdown".to_string()], ..Config::default() };
+        for action in crate::COMMAND_ACTIONS {
+            let (command, e) = refused(action, &request(action, "sudo shutdown -h now"), &config).expect(action);
+            assert_eq!(command, "sudo shutdown -h now", "{}", action);
+            assert!(e.contains("shutdown"), "{}: {}", action, e);
+
+            // Built-in validation applies without any configured pattern
+            assert!(refused(action, &request(action, "rm -rf /"), &Config::default()).is_some(), "{}", action);
+            assert!(refused(action, &request(action, "ls -la"), &config).is_none(), "{}", action);
+        }
+    }
+
+    #[test]
+    fn test_other_actions_and_dry_runs_pass() {
+        let config = Config { blocked_patterns: vec!["shutdown".to_string()], ..Config::default() };
+        assert!(refused("capture", &json!({"command": "shutdown"}), &config).is_none());
+        assert!(refused("save_workflow", &json!({"commands": ["shutdown"]}), &config).is_none());
+        assert!(refused("execute_batch", &json!({"commands": ["shutdown"], "dry_run": true}), &config).is_none());
+
+        // Rollback compensations would run too
+        let batch = json!({"commands": [{"command": "echo ok", "compensation": "shutdown"}]});
+        assert_eq!(refused("execute_batch", &batch, &config).map(|(command, _)| command).as_deref(), Some("shutdown"));
+    }
+}
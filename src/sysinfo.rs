@@ -0,0 +1,343 @@
+// sysinfo.rs - Structured system information for get_system_info
+// Everything is read from /proc, /sys and os-release - nothing is spawned, so the action stays cheap
+// enough to call on every prompt:
+//
+//   - kernel: /proc/sys/kernel (ostype, osrelease, version, hostname)
+//   - distro: /etc/os-release, else /usr/lib/os-release
+//   - cpu: /proc/cpuinfo for the model and cores, cpufreq in /sys for the clock (cpuinfo's "cpu MHz"
+//     when the kernel has no cpufreq driver, as in most VMs)
+//   - memory and swap: /proc/meminfo
+//   - uptime: /proc/uptime
+//   - virtualization: container markers first (/.dockerenv, /run/.containerenv, PID 1's cgroups), then
+//     WSL's kernel release, then the DMI vendor and the cpu's hypervisor flag
+//
+// Missing files just leave their fields empty - a container without /sys still gets an answer.
+
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Default, Serialize)]
+pub struct SystemInfo {
+    pub hostname: Option<String>,
+    pub arch: &'static str,
+    pub kernel: Kernel,
+    pub distro: Distro,
+    pub cpu: Cpu,
+    pub memory: Memory,
+    pub uptime_seconds: Option<u64>,
+    pub virtualization: Virtualization,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Kernel {
+    pub name: Option<String>,    // "Linux"
+    pub release: Option<String>, // "6.8.0-45-generic"
+    pub version: Option<String>, // "#45-Ubuntu SMP PREEMPT_DYNAMIC ..."
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Distro {
+    pub id: Option<String>,
+    pub id_like: Option<String>, // the distros this one derives from, e.g. "debian" on Ubuntu
+    pub name: Option<String>,
+    pub pretty_name: Option<String>,
+    pub version_id: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Cpu {
+    pub model: Option<String>,
+    pub logical_cores: usize,
+    pub physical_cores: usize,
+    pub sockets: usize,
+    pub current_mhz: Option<u64>,
+    pub max_mhz: Option<u64>,
+}
+
+/// Sizes in bytes
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Memory {
+    pub total: Option<u64>,
+    pub available: Option<u64>,
+    pub swap_total: Option<u64>,
+    pub swap_free: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Virtualization {
+    pub kind: &'static str, // "none", "vm", "container" or "wsl"
+    pub technology: Option<String>,
+}
+
+impl Default for Virtualization {
+    fn default() -> Self {
+        Virtualization { kind: "none", technology: None }
+    }
+}
+
+/// DMI vendor / product prefixes, lowercased, and the hypervisor they mean
+const HYPERVISORS: &[(&str, &str)] = &[
+    ("qemu", "qemu"),
+    ("kvm", "kvm"),
+    ("vmware", "vmware"),
+    ("innotek", "virtualbox"),
+    ("virtualbox", "virtualbox"),
+    ("xen", "xen"),
+    ("microsoft corporation", "hyper-v"),
+    ("amazon ec2", "kvm"),
+    ("google", "kvm"),
+    ("parallels", "parallels"),
+    ("bochs", "bochs"),
+];
+
+/// The running system
+pub fn collect() -> SystemInfo {
+    collect_in(Path::new("/"))
+}
+
+/// The system whose /proc, /sys and /etc are under `root`
+fn collect_in(root: &Path) -> SystemInfo {
+    let read = |path: &str| fs::read_to_string(root.join(path)).ok();
+    let line = |path: &str| read(path).map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+
+    let kernel = Kernel {
+        name: line("proc/sys/kernel/ostype"),
+        release: line("proc/sys/kernel/osrelease"),
+        version: line("proc/sys/kernel/version"),
+    };
+    let cpuinfo = read("proc/cpuinfo").unwrap_or_default();
+    let mut cpu = parse_cpuinfo(&cpuinfo);
+    let khz = |path: &str| line(path).and_then(|v| v.parse::<u64>().ok()).map(|khz| khz / 1000);
+    if let Some(mhz) = khz("sys/devices/system/cpu/cpu0/cpufreq/scaling_cur_freq") {
+        cpu.current_mhz = Some(mhz);
+    }
+    cpu.max_mhz = khz("sys/devices/system/cpu/cpu0/cpufreq/cpuinfo_max_freq");
+
+    let virtualization = detect_virtualization(root, &cpuinfo, kernel.release.as_deref());
+    SystemInfo {
+        hostname: line("proc/sys/kernel/hostname"),
+        arch: std::env::consts::ARCH,
+        distro: os_release_in(root),
+        memory: read("proc/meminfo").map(|content| parse_meminfo(&content)).unwrap_or_default(),
+        uptime_seconds: read("proc/uptime")
+            .and_then(|content| content.split_whitespace().next()?.parse::<f64>().ok())
+            .map(|seconds| seconds as u64),
+        kernel,
+        cpu,
+        virtualization,
+    }
+}
+
+/// The running distro, from os-release
+pub fn os_release() -> Distro {
+    os_release_in(Path::new("/"))
+}
+
+fn os_release_in(root: &Path) -> Distro {
+    fs::read_to_string(root.join("etc/os-release")).or_else(|_| fs::read_to_string(root.join("usr/lib/os-release")))
+        .map(|content| parse_os_release(&content))
+        .unwrap_or_default()
+}
+
+/// key: value pairs of one /proc/cpuinfo block
+fn cpuinfo_fields(block: &str) -> impl Iterator<Item = (&str, &str)> {
+    block.lines().filter_map(|line| line.split_once(':')).map(|(key, value)| (key.trim(), value.trim()))
+}
+
+fn parse_cpuinfo(content: &str) -> Cpu {
+    let mut cpu = Cpu::default();
+    let mut cores = HashSet::new();
+    let mut sockets = HashSet::new();
+    for block in content.split("\n\n").filter(|block| !block.trim().is_empty()) {
+        let mut physical = None;
+        let mut core = None;
+        let mut is_processor = false;
+        for (key, value) in cpuinfo_fields(block) {
+            match key {
+                "processor" => is_processor = value.parse::<u32>().is_ok(),
+                // x86 / ARM / POWER / MIPS spellings of the model
+                "model name" | "Hardware" | "cpu model" | "cpu" if cpu.model.is_none() && !value.is_empty() => {
+                    cpu.model = Some(value.to_string());
+                }
+                "cpu MHz" if cpu.current_mhz.is_none() => {
+                    cpu.current_mhz = value.parse::<f64>().ok().map(|mhz| mhz as u64);
+                }
+                "physical id" => physical = Some(value.to_string()),
+                "core id" => core = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        if is_processor {
+            cpu.logical_cores += 1;
+            if let Some(core) = core {
+                cores.insert((physical.clone(), core));
+            }
+            if let Some(physical) = physical {
+                sockets.insert(physical);
+            }
+        }
+    }
+    // Without topology fields (ARM, most VMs' guests) every logical cpu counts as a core
+    cpu.physical_cores = if cores.is_empty() { cpu.logical_cores } else { cores.len() };
+    cpu.sockets = sockets.len().max(usize::from(cpu.logical_cores > 0));
+    cpu
+}
+
+pub fn parse_meminfo(content: &str) -> Memory {
+    let field = |name: &str| {
+        content.lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim() == name)
+            .and_then(|(_, value)| value.split_whitespace().next()?.parse::<u64>().ok())
+            .map(|kb| kb * 1024)
+    };
+    Memory {
+        total: field("MemTotal"),
+        available: field("MemAvailable").or_else(|| field("MemFree")),
+        swap_total: field("SwapTotal"),
+        swap_free: field("SwapFree"),
+    }
+}
+
+fn parse_os_release(content: &str) -> Distro {
+    let field = |name: &str| {
+        content.lines()
+            .filter_map(|line| line.trim().split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.trim().trim_matches(|c| c == '"' || c == '\'').to_string())
+            .filter(|value| !value.is_empty())
+    };
+    Distro {
+        id: field("ID"),
+        id_like: field("ID_LIKE"),
+        name: field("NAME"),
+        pretty_name: field("PRETTY_NAME"),
+        version_id: field("VERSION_ID"),
+    }
+}
+
+fn detect_virtualization(root: &Path, cpuinfo: &str, kernel_release: Option<&str>) -> Virtualization {
+    let found = |kind: &'static str, technology: &str| Virtualization { kind, technology: Some(technology.to_string()) };
+    if root.join(".dockerenv").exists() {
+        return found("container", "docker");
+    }
+    if root.join("run/.containerenv").exists() {
+        return found("container", "podman");
+    }
+    let cgroups = fs::read_to_string(root.join("proc/1/cgroup")).unwrap_or_default();
+    for (marker, technology) in [("docker", "docker"), ("libpod", "podman"), ("lxc", "lxc"), ("kubepods", "kubernetes")] {
+        if cgroups.contains(marker) {
+            return found("container", technology);
+        }
+    }
+    if kernel_release.is_some_and(|release| release.to_lowercase().contains("microsoft")) {
+        return found("wsl", "wsl");
+    }
+    let dmi = ["sys/class/dmi/id/sys_vendor", "sys/class/dmi/id/product_name"].iter()
+        .filter_map(|path| fs::read_to_string(root.join(path)).ok())
+        .map(|value| value.trim().to_lowercase())
+        .collect::<Vec<_>>();
+    if let Some((_, technology)) = HYPERVISORS.iter().find(|(prefix, _)| dmi.iter().any(|value| value.starts_with(prefix))) {
+        return found("vm", technology);
+    }
+    let hypervisor_flag = cpuinfo_fields(cpuinfo)
+        .any(|(key, value)| key == "flags" && value.split_whitespace().any(|flag| flag == "hypervisor"));
+    if hypervisor_flag {
+        return Virtualization { kind: "vm", technology: None };
+    }
+    Virtualization::default()
+}
+
+impl SystemInfo {
+    /// One line for prompts and the old string reply, e.g.
+    /// "Arch Linux, kernel 6.10.2-arch1-1 (x86_64), AMD Ryzen 7 5800X (8 cores / 16 threads), 31.3 GiB RAM"
+    pub fn summary(&self) -> String {
+        let gib = |bytes: u64| format!("{:.1} GiB", bytes as f64 / (1u64 << 30) as f64);
+        let mut parts = Vec::new();
+        if let Some(distro) = self.distro.pretty_name.as_ref().or(self.distro.name.as_ref()) {
+            parts.push(distro.clone());
+        }
+        parts.push(format!(
+            "{} {} ({})",
+            self.kernel.name.as_deref().unwrap_or("kernel"),
+            self.kernel.release.as_deref().unwrap_or("unknown"),
+            self.arch
+        ));
+        if let Some(model) = &self.cpu.model {
+            parts.push(format!("{} ({} cores / {} threads)", model, self.cpu.physical_cores, self.cpu.logical_cores));
+        }
+        if let Some(total) = self.memory.total {
+            parts.push(format!("{} RAM", gib(total)));
+        }
+        if self.virtualization.kind != "none" {
+            parts.push(match &self.virtualization.technology {
+                Some(technology) => format!("{} ({})", self.virtualization.kind, technology),
+                None => self.virtualization.kind.to_string(),
+            });
+        }
+        parts.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc() {
+        let cpuinfo = "processor\t: 0\nmodel name\t: AMD Ryzen 7 5800X\ncpu MHz\t\t: 3800.012\nphysical id\t: 0\ncore id\t\t: 0\n\
+            flags\t\t: fpu hypervisor\n\nprocessor\t: 1\nmodel name\t: AMD Ryzen 7 5800X\nphysical id\t: 0\ncore id\t\t: 0\n\n\
+            processor\t: 2\nphysical id\t: 0\ncore id\t\t: 1\n\n";
+        assert_eq!(parse_cpuinfo(cpuinfo), Cpu {
+            model: Some("AMD Ryzen 7 5800X".to_string()),
+            logical_cores: 3,
+            physical_cores: 2,
+            sockets: 1,
+            current_mhz: Some(3800),
+            max_mhz: None,
+        });
+        // ARM: no topology, the model under "Hardware"
+        let arm = parse_cpuinfo("processor\t: 0\nBogoMIPS\t: 108.00\n\nprocessor\t: 1\n\nHardware\t: BCM2835\n");
+        assert_eq!((arm.logical_cores, arm.physical_cores, arm.model.as_deref()), (2, 2, Some("BCM2835")));
+
+        let memory = parse_meminfo("MemTotal:       16384 kB\nMemFree:  1024 kB\nMemAvailable:   8192 kB\nSwapTotal: 0 kB\n");
+        assert_eq!(memory, Memory { total: Some(16 << 20), available: Some(8 << 20), swap_total: Some(0), swap_free: None });
+
+        let distro = parse_os_release("NAME=\"Arch Linux\"\nPRETTY_NAME=\"Arch Linux\"\nID=arch\nBUILD_ID=rolling\n");
+        assert_eq!((distro.id.as_deref(), distro.name.as_deref(), distro.version_id), (Some("arch"), Some("Arch Linux"), None));
+    }
+
+    #[test]
+    fn test_collect_in() {
+        let root = std::env::temp_dir().join(format!("archy-sysinfo-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let write = |path: &str, content: &str| {
+            let file = root.join(path);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, content).unwrap();
+        };
+        write("proc/sys/kernel/ostype", "Linux\n");
+        write("proc/sys/kernel/osrelease", "6.10.2-arch1-1\n");
+        write("proc/uptime", "12345.67 98765.43\n");
+        write("proc/cpuinfo", "processor\t: 0\nmodel name\t: Test CPU\n\n");
+        write("sys/devices/system/cpu/cpu0/cpufreq/scaling_cur_freq", "2400000\n");
+        write("sys/devices/system/cpu/cpu0/cpufreq/cpuinfo_max_freq", "4200000\n");
+        write("sys/class/dmi/id/sys_vendor", "QEMU\n");
+        write("usr/lib/os-release", "PRETTY_NAME=\"Debian GNU/Linux 12\"\nID=debian\nVERSION_ID=\"12\"\n");
+
+        let info = collect_in(&root);
+        assert_eq!(info.kernel.release.as_deref(), Some("6.10.2-arch1-1"));
+        assert_eq!(info.uptime_seconds, Some(12345));
+        assert_eq!((info.cpu.current_mhz, info.cpu.max_mhz), (Some(2400), Some(4200)));
+        assert_eq!(info.distro.version_id.as_deref(), Some("12"));
+        assert_eq!(info.virtualization, Virtualization { kind: "vm", technology: Some("qemu".to_string()) });
+        assert_eq!(info.memory, Memory::default());
+        assert!(info.summary().starts_with("Debian GNU/Linux 12, Linux 6.10.2-arch1-1"));
+
+        write(".dockerenv", "");
+        assert_eq!(collect_in(&root).virtualization.kind, "container");
+        let _ = fs::remove_dir_all(&root);
+    }
+}
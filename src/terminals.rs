@@ -0,0 +1,287 @@
+// terminals.rs - Terminal emulator selection
+// Resolves the configured terminal, the preference list and the built-in fallbacks into launchable command lines.
+// Every path that opens a terminal goes through a TerminalSpec: `attach_line` for a window on a tmux session,
+// `shell_line` for a one-off script. Built-ins are started so the process lives as long as its window
+// (gnome-terminal --wait, xfce4-terminal --disable-server), which is what finding and closing them relies on;
+// `[terminals.templates]` entries replace a built-in of the same name everywhere.
+
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+use crate::config::Config;
+use crate::helpers::environment::{DisplayServer, Session};
+
+/// Placeholder in an argument template that expands to the program to run (e.g. `tmux attach -t s`)
+pub const EXEC_PLACEHOLDER: &str = "{exec}";
+
+/// How to launch a terminal running a program
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TerminalSpec {
+    #[serde(default)]
+    pub name: String,
+    pub binary: String,
+    pub args: Vec<String>, // must contain "{exec}"
+}
+
+/// Display servers a terminal can open a window on
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Runs {
+    Any,
+    Wayland,
+    X11,
+}
+
+/// Built-in terminals, in default preference order
+const BUILTIN: &[(&str, &[&str], Runs)] = &[
+    ("foot", &["-e", EXEC_PLACEHOLDER], Runs::Wayland),
+    ("kitty", &["-e", EXEC_PLACEHOLDER], Runs::Any),
+    ("wezterm", &["start", "--", EXEC_PLACEHOLDER], Runs::Any),
+    ("ghostty", &["-e", EXEC_PLACEHOLDER], Runs::Any),
+    ("alacritty", &["-e", EXEC_PLACEHOLDER], Runs::Any),
+    ("konsole", &["-e", EXEC_PLACEHOLDER], Runs::Any),
+    ("gnome-terminal", &["--wait", "--", EXEC_PLACEHOLDER], Runs::Any),
+    ("xfce4-terminal", &["--disable-server", "-x", EXEC_PLACEHOLDER], Runs::Any),
+    ("terminator", &["-x", EXEC_PLACEHOLDER], Runs::X11),
+    ("xterm", &["-e", EXEC_PLACEHOLDER], Runs::X11),
+];
+
+/// The terminal each desktop ships - preferred over the generic order when it's installed
+const NATIVE: &[(&str, &str)] = &[
+    ("gnome", "gnome-terminal"),
+    ("kde", "konsole"),
+    ("xfce", "xfce4-terminal"),
+];
+
+impl TerminalSpec {
+    fn builtin(name: &str) -> Option<Self> {
+        BUILTIN.iter().find(|(n, _, _)| *n == name).map(|(n, args, _)| TerminalSpec {
+            name: n.to_string(),
+            binary: n.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+        })
+    }
+
+    /// Full argv (after the binary) running `program` in the terminal
+    pub fn command_line(&self, program: &[&str]) -> Vec<String> {
+        let mut argv = Vec::new();
+        for arg in &self.args {
+            if arg == EXEC_PLACEHOLDER {
+                argv.extend(program.iter().map(|p| p.to_string()));
+            } else {
+                argv.push(arg.clone());
+            }
+        }
+        argv
+    }
+
+    /// Whether this terminal can open a window in the session. Configured templates are trusted
+    pub fn runs_on(&self, session: &Session) -> bool {
+        let runs = BUILTIN.iter().find(|(n, _, _)| *n == self.binary).map_or(Runs::Any, |(_, _, runs)| *runs);
+        match runs {
+            Runs::Any => true,
+            Runs::Wayland => session.is_wayland(),
+            Runs::X11 => session.runs_x11(),
+        }
+    }
+
+    /// Argv (after the binary) of a window attached to tmux session `session`
+    pub fn attach_line(&self, session: &str) -> Vec<String> {
+        self.command_line(&["tmux", "attach-session", "-t", session])
+    }
+
+    /// Argv (after the binary) running `script` with bash - one argument, whatever the terminal's syntax
+    pub fn shell_line(&self, script: &str) -> Vec<String> {
+        self.command_line(&["bash", "-c", script])
+    }
+
+    pub fn is_installed(&self) -> bool {
+        Command::new("which")
+            .arg(&self.binary)
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(false)
+    }
+}
+
+/// Look up a terminal by name: custom templates first, then built-ins
+pub fn lookup(config: &Config, name: &str) -> Option<TerminalSpec> {
+    if let Some(custom) = config.terminal_templates.get(name) {
+        return Some(TerminalSpec { name: name.to_string(), ..custom.clone() });
+    }
+    TerminalSpec::builtin(name)
+}
+
+/// Every terminal name this config knows how to launch
+pub fn known_names(config: &Config) -> Vec<String> {
+    let mut names: Vec<String> = BUILTIN.iter().map(|(n, _, _)| n.to_string()).collect();
+    for name in config.terminal_templates.keys() {
+        if !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+    names
+}
+
+/// Candidates in order: the configured terminal, the preference list, then the built-in order
+pub fn candidates(config: &Config) -> Vec<TerminalSpec> {
+    let mut specs: Vec<TerminalSpec> = Vec::new();
+    let mut push = |spec: TerminalSpec| {
+        if !specs.iter().any(|s| s.name == spec.name) {
+            specs.push(spec);
+        }
+    };
+
+    if let Some(name) = &config.terminal_emulator {
+        // An unknown terminal_emulator is taken as a binary following the common `-e` convention
+        push(lookup(config, name).unwrap_or_else(|| TerminalSpec {
+            name: name.clone(),
+            binary: name.clone(),
+            args: vec!["-e".to_string(), EXEC_PLACEHOLDER.to_string()],
+        }));
+    }
+    for name in &config.terminal_preference {
+        if let Some(spec) = lookup(config, name) {
+            push(spec);
+        }
+    }
+    for (name, _, _) in BUILTIN {
+        if let Some(spec) = lookup(config, name) {
+            push(spec);
+        }
+    }
+    specs
+}
+
+/// Candidates that can run in `session`. What the config names keeps its order; among the built-ins
+/// the desktop's own terminal goes first. An unknown session filters nothing
+pub fn ranked(config: &Config, session: &Session) -> Vec<TerminalSpec> {
+    let native = session.compositor.as_deref()
+        .and_then(|desktop| NATIVE.iter().find(|(d, _)| *d == desktop))
+        .map(|(_, terminal)| *terminal);
+    let mut specs = candidates(config);
+    let named = |spec: &TerminalSpec| config.terminal_emulator.iter().chain(&config.terminal_preference).any(|n| *n == spec.name);
+    let configured = specs.iter().take_while(|spec| named(spec)).count();
+    if let Some(position) = specs.iter().skip(configured).position(|spec| Some(spec.name.as_str()) == native) {
+        let spec = specs.remove(configured + position);
+        specs.insert(configured.min(specs.len()), spec);
+    }
+    if session.server != DisplayServer::Unknown {
+        specs.retain(|spec| spec.runs_on(session));
+    }
+    specs
+}
+
+/// First installed candidate for the current session
+pub fn detect(config: &Config) -> Option<TerminalSpec> {
+    ranked(config, &Session::detect()).into_iter().find(|spec| spec.is_installed())
+}
+
+/// pgrep -f pattern matching any known terminal attached to tmux (optionally a specific, pre-escaped session)
+pub fn attached_pattern(config: &Config, escaped_session: Option<&str>) -> String {
+    let binaries: Vec<String> = candidates(config)
+        .into_iter()
+        .map(|spec| regex::escape(&spec.binary))
+        .collect();
+    format!(
+        "({}).*tmux.*attach{}",
+        binaries.join("|"),
+        escaped_session.map(|s| format!(".*{}", s)).unwrap_or_default()
+    )
+}
+
+/// Config errors for the terminal settings
+pub fn validate(config: &Config) -> Vec<String> {
+    let mut errors = Vec::new();
+    for name in &config.terminal_preference {
+        if lookup(config, name).is_none() {
+            errors.push(format!(
+                "terminal preference {:?} is unknown (known: {})",
+                name,
+                known_names(config).join(", ")
+            ));
+        }
+    }
+    for (name, spec) in &config.terminal_templates {
+        if spec.binary.trim().is_empty() {
+            errors.push(format!("terminal template {:?} has an empty binary", name));
+        }
+        if !spec.args.iter().any(|a| a == EXEC_PLACEHOLDER) {
+            errors.push(format!("terminal template {:?} args must contain \"{}\"", name, EXEC_PLACEHOLDER));
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_line_expands_exec() {
+        let wezterm = TerminalSpec::builtin("wezterm").unwrap();
+        assert_eq!(
+            wezterm.command_line(&["tmux", "attach", "-t", "s"]),
+            vec!["start", "--", "tmux", "attach", "-t", "s"]
+        );
+    }
+
+    #[test]
+    fn test_candidate_order() {
+        let mut config = Config {
+            terminal_emulator: Some("ghostty".to_string()),
+            terminal_preference: vec!["kitty".to_string(), "mine".to_string()],
+            ..Config::default()
+        };
+        config.terminal_templates.insert("mine".to_string(), TerminalSpec {
+            name: String::new(),
+            binary: "/opt/mine/bin/mine".to_string(),
+            args: vec!["--run".to_string(), EXEC_PLACEHOLDER.to_string()],
+        });
+
+        let names: Vec<String> = candidates(&config).into_iter().map(|s| s.name).collect();
+        assert_eq!(&names[..4], &["ghostty", "kitty", "mine", "foot"]);
+        assert_eq!(names.iter().filter(|n| *n == "kitty").count(), 1);
+        assert!(validate(&config).is_empty());
+
+        config.terminal_preference.push("nope".to_string());
+        assert_eq!(validate(&config).len(), 1);
+    }
+
+    #[test]
+    fn test_builtin_override() {
+        let mut config = Config::default();
+        config.terminal_templates.insert("foot".to_string(), TerminalSpec {
+            name: String::new(),
+            binary: "footclient".to_string(),
+            args: vec!["--hold".to_string(), EXEC_PLACEHOLDER.to_string()],
+        });
+        // The template replaces the built-in in the fallback order too, not only when named
+        let foot = candidates(&config).into_iter().find(|s| s.name == "foot").unwrap();
+        assert_eq!(foot.binary, "footclient");
+        assert_eq!(foot.attach_line("s"), vec!["--hold", "tmux", "attach-session", "-t", "s"]);
+
+        let gnome = TerminalSpec::builtin("gnome-terminal").unwrap();
+        assert_eq!(gnome.shell_line("ls; read"), vec!["--wait", "--", "bash", "-c", "ls; read"]);
+    }
+
+    #[test]
+    fn test_ranked_for_session() {
+        let config = Config::default();
+        let names = |session: &Session| ranked(&config, session).into_iter().map(|s| s.name).collect::<Vec<_>>();
+
+        let x11 = Session::from_parts(Some("x11"), None, Some(":0".to_string()), Some("kde".to_string()));
+        let on_x11 = names(&x11);
+        assert_eq!(on_x11[0], "konsole");
+        assert!(!on_x11.contains(&"foot".to_string()) && on_x11.contains(&"xterm".to_string()));
+
+        let wayland = Session::from_parts(Some("wayland"), Some("wayland-1".to_string()), None, Some("sway".to_string()));
+        let on_wayland = names(&wayland);
+        assert_eq!(on_wayland[0], "foot");
+        assert!(!on_wayland.contains(&"xterm".to_string()));
+
+        // The configured terminal stays first even where the desktop has its own
+        let config = Config { terminal_emulator: Some("kitty".to_string()), ..Config::default() };
+        let gnome = Session::from_parts(Some("wayland"), Some("wayland-0".to_string()), Some(":0".to_string()), Some("gnome".to_string()));
+        let on_gnome: Vec<String> = ranked(&config, &gnome).into_iter().map(|s| s.name).collect();
+        assert_eq!(&on_gnome[..3], &["kitty", "gnome-terminal", "foot"]);
+    }
+}
@@ -0,0 +1,272 @@
+// thermal.rs - Temperatures and fans for thermal_info and the monitor stream
+// Read straight from the kernel's hwmon class in /sys, no lm-sensors needed:
+//
+//   - every /sys/class/hwmon/hwmonN has a `name` (the driver: coretemp, k10temp, amdgpu, nvme, ...),
+//     tempN_input in millidegrees with optional tempN_label / _max / _crit, and fanN_input in RPM with
+//     an optional fanN_alarm
+//   - the driver decides the kind: cpu, gpu, nvme, or other (ACPI zones, chipset, wifi, drivetemp)
+//
+// Findings come from the configured [thermal] thresholds per kind, one per kind for its hottest
+// sensor, so sixteen hot cores are one finding. The chip's own max/crit are reported but not judged -
+// some firmware reports nonsense there. NVIDIA's proprietary driver has no hwmon node and isn't seen.
+
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use crate::config::Config;
+use crate::parser::{Finding, Importance, RiskLevel, SuggestedAction};
+
+const HWMON_DIR: &str = "/sys/class/hwmon";
+
+/// hwmon driver names and the kind of sensor they are
+const DRIVER_KINDS: &[(&str, &str)] = &[
+    ("coretemp", "cpu"), ("k10temp", "cpu"), ("k8temp", "cpu"), ("zenpower", "cpu"), ("cpu_thermal", "cpu"),
+    ("soc_thermal", "cpu"), ("amdgpu", "gpu"), ("radeon", "gpu"), ("nouveau", "gpu"), ("i915", "gpu"),
+    ("xe", "gpu"), ("nvme", "nvme"),
+];
+
+/// Warning and critical temperatures in °C
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Limits {
+    pub warning: f64,
+    pub critical: f64,
+}
+
+/// Limits per kind of sensor - `[thermal]` in the config
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Thresholds {
+    pub cpu: Limits,
+    pub gpu: Limits,
+    pub nvme: Limits,
+    pub other: Limits,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds {
+            cpu: Limits { warning: 85.0, critical: 95.0 },
+            gpu: Limits { warning: 85.0, critical: 95.0 },
+            // NVMe drives throttle around 70-80°C
+            nvme: Limits { warning: 65.0, critical: 75.0 },
+            other: Limits { warning: 80.0, critical: 95.0 },
+        }
+    }
+}
+
+impl Thresholds {
+    fn for_kind(&self, kind: &str) -> Limits {
+        match kind {
+            "cpu" => self.cpu,
+            "gpu" => self.gpu,
+            "nvme" => self.nvme,
+            _ => self.other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Sensor {
+    pub chip: String,
+    pub kind: &'static str, // "cpu", "gpu", "nvme" or "other"
+    pub label: String,
+    pub celsius: f64,
+    pub high_c: Option<f64>,     // the chip's own tempN_max
+    pub critical_c: Option<f64>, // the chip's own tempN_crit
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Fan {
+    pub chip: String,
+    pub label: String,
+    pub rpm: u32,
+    pub alarm: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ThermalInfo {
+    pub sensors: Vec<Sensor>,
+    pub fans: Vec<Fan>,
+    pub findings: Vec<Finding>,
+    pub suggestions: Vec<SuggestedAction>,
+}
+
+/// The hottest reading of each kind and the fan speeds - what the monitor stream carries
+#[derive(Debug, Default, Serialize)]
+pub struct ThermalSample {
+    pub cpu_c: Option<f64>,
+    pub gpu_c: Option<f64>,
+    pub nvme_c: Option<f64>,
+    pub fans_rpm: Vec<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub findings: Vec<Finding>,
+}
+
+pub fn collect(thresholds: &Thresholds) -> ThermalInfo {
+    let (sensors, fans) = read_hwmon(Path::new(HWMON_DIR));
+    let (findings, suggestions) = findings(&sensors, &fans, thresholds);
+    ThermalInfo { sensors, fans, findings, suggestions }
+}
+
+/// One monitor sample's worth; None on machines without hwmon sensors (most VMs)
+pub fn sample(thresholds: &Thresholds) -> Option<ThermalSample> {
+    let (sensors, fans) = read_hwmon(Path::new(HWMON_DIR));
+    if sensors.is_empty() && fans.is_empty() {
+        return None;
+    }
+    let hottest = |kind: &str| sensors.iter().filter(|s| s.kind == kind).map(|s| s.celsius).reduce(f64::max);
+    Some(ThermalSample {
+        cpu_c: hottest("cpu"),
+        gpu_c: hottest("gpu"),
+        nvme_c: hottest("nvme"),
+        fans_rpm: fans.iter().map(|fan| fan.rpm).collect(),
+        findings: findings(&sensors, &fans, thresholds).0,
+    })
+}
+
+impl ThermalInfo {
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        for (kind, name) in [("cpu", "CPU"), ("gpu", "GPU"), ("nvme", "NVMe")] {
+            if let Some(hottest) = self.sensors.iter().filter(|s| s.kind == kind).map(|s| s.celsius).reduce(f64::max) {
+                parts.push(format!("{} {:.0}°C", name, hottest));
+            }
+        }
+        if !self.fans.is_empty() {
+            parts.push(format!("{} fan(s)", self.fans.len()));
+        }
+        match parts.is_empty() {
+            true => "No temperature sensors found".to_string(),
+            false => parts.join(", "),
+        }
+    }
+}
+
+/// Sensors and fans of every chip under an hwmon class directory
+fn read_hwmon(dir: &Path) -> (Vec<Sensor>, Vec<Fan>) {
+    let Ok(entries) = fs::read_dir(dir) else { return (Vec::new(), Vec::new()) };
+    let mut chips: Vec<_> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    chips.sort();
+    let mut sensors = Vec::new();
+    let mut fans = Vec::new();
+    for chip in chips {
+        let read = |attr: &str| fs::read_to_string(chip.join(attr)).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        let Some(name) = read("name") else { continue };
+        let kind = DRIVER_KINDS.iter().find(|(driver, _)| *driver == name).map(|(_, kind)| *kind).unwrap_or("other");
+        let mut attrs: Vec<String> = fs::read_dir(&chip)
+            .map(|entries| entries.filter_map(|e| e.ok()).map(|e| e.file_name().to_string_lossy().to_string()).collect())
+            .unwrap_or_default();
+        attrs.sort_by_key(|attr| (attr.len(), attr.clone())); // temp2 before temp10
+        for attr in &attrs {
+            let millidegrees = |suffix: &str| read(&attr.replace("_input", suffix)).and_then(|v| v.parse::<f64>().ok()).map(|v| v / 1000.0);
+            if attr.starts_with("temp") && attr.ends_with("_input") {
+                // A sensor that can't be read right now (a GPU asleep) fails with EIO - skip it
+                let Some(celsius) = millidegrees("_input") else { continue };
+                sensors.push(Sensor {
+                    chip: name.clone(),
+                    kind,
+                    label: read(&attr.replace("_input", "_label")).unwrap_or_else(|| attr.trim_end_matches("_input").to_string()),
+                    celsius,
+                    high_c: millidegrees("_max").filter(|&t| t > 0.0),
+                    critical_c: millidegrees("_crit").filter(|&t| t > 0.0),
+                });
+            } else if attr.starts_with("fan") && attr.ends_with("_input") {
+                let Some(rpm) = read(attr).and_then(|v| v.parse::<u32>().ok()) else { continue };
+                fans.push(Fan {
+                    chip: name.clone(),
+                    label: read(&attr.replace("_input", "_label")).unwrap_or_else(|| attr.trim_end_matches("_input").to_string()),
+                    rpm,
+                    alarm: read(&attr.replace("_input", "_alarm")).is_some_and(|v| v == "1"),
+                });
+            }
+        }
+    }
+    (sensors, fans)
+}
+
+fn findings(sensors: &[Sensor], fans: &[Fan], thresholds: &Thresholds) -> (Vec<Finding>, Vec<SuggestedAction>) {
+    let mut findings = Vec::new();
+    let mut suggestions = Vec::new();
+    for kind in ["cpu", "gpu", "nvme", "other"] {
+        let Some(hottest) = sensors.iter().filter(|s| s.kind == kind).max_by(|a, b| a.celsius.total_cmp(&b.celsius)) else { continue };
+        let limits = thresholds.for_kind(kind);
+        let what = format!("{} {} ({}) is at {:.0}°C", hottest.chip, hottest.label, kind, hottest.celsius);
+        if hottest.celsius >= limits.critical {
+            findings.push(Finding::new("Overheating", format!("{}, critical is {:.0}°C", what, limits.critical), Importance::Critical));
+            if kind == "cpu" || kind == "gpu" {
+                suggestions.push(SuggestedAction::new(
+                    "ps -eo pid,comm,%cpu --sort=-%cpu | head -n 10",
+                    "See which processes are keeping the CPU busy",
+                    RiskLevel::Low,
+                ));
+            }
+        } else if hottest.celsius >= limits.warning {
+            findings.push(Finding::new("High Temperature", format!("{}, warning is {:.0}°C", what, limits.warning), Importance::High));
+        }
+    }
+    for fan in fans.iter().filter(|fan| fan.alarm) {
+        findings.push(Finding::new("Fan Alarm", format!("{} {} raised an alarm at {} RPM", fan.chip, fan.label, fan.rpm), Importance::High));
+    }
+    suggestions.dedup_by(|a, b| a.command == b.command);
+    (findings, suggestions)
+}
+
+/// Config errors in `[thermal]`
+pub fn validate(config: &Config) -> Vec<String> {
+    let thresholds = &config.thermal;
+    [("cpu", thresholds.cpu), ("gpu", thresholds.gpu), ("nvme", thresholds.nvme), ("other", thresholds.other)]
+        .iter()
+        .filter(|(_, limits)| !(limits.warning > 0.0 && limits.warning < limits.critical))
+        .map(|(kind, limits)| format!(
+            "thermal.{}: warning ({}) must be above 0 and below critical ({})",
+            kind, limits.warning, limits.critical
+        ))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_hwmon() {
+        let root = std::env::temp_dir().join(format!("archy-thermal-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let write = |path: &str, content: &str| {
+            let file = root.join(path);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, content).unwrap();
+        };
+        write("hwmon0/name", "k10temp\n");
+        write("hwmon0/temp1_input", "97250\n");
+        write("hwmon0/temp1_label", "Tctl\n");
+        write("hwmon0/temp1_crit", "100000\n");
+        write("hwmon1/name", "nvme\n");
+        write("hwmon1/temp1_input", "41850\n");
+        write("hwmon2/name", "nct6775\n");
+        write("hwmon2/fan2_input", "0\n");
+        write("hwmon2/fan2_alarm", "1\n");
+        write("hwmon2/temp10_input", "30000\n");
+        write("hwmon2/temp2_input", "32000\n");
+
+        let (sensors, fans) = read_hwmon(&root);
+        assert_eq!(sensors.iter().map(|s| (s.kind, s.label.as_str())).collect::<Vec<_>>(),
+            vec![("cpu", "Tctl"), ("nvme", "temp1"), ("other", "temp2"), ("other", "temp10")]);
+        assert_eq!((sensors[0].celsius, sensors[0].critical_c), (97.25, Some(100.0)));
+        assert_eq!(fans, vec![Fan { chip: "nct6775".to_string(), label: "fan2".to_string(), rpm: 0, alarm: true }]);
+
+        let (findings, suggestions) = findings(&sensors, &fans, &Thresholds::default());
+        assert_eq!(findings.iter().map(|f| f.category.as_str()).collect::<Vec<_>>(), vec!["Overheating", "Fan Alarm"]);
+        assert_eq!(suggestions.len(), 1);
+        let relaxed = Thresholds { cpu: Limits { warning: 90.0, critical: 105.0 }, ..Thresholds::default() };
+        assert_eq!(super::findings(&sensors, &[], &relaxed).0[0].category, "High Temperature");
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config = Config::default();
+        assert!(validate(&config).is_empty());
+        config.thermal.nvme = Limits { warning: 80.0, critical: 70.0 };
+        assert_eq!(validate(&config).len(), 1);
+    }
+}
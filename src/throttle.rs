@@ -0,0 +1,222 @@
+// throttle.rs - Rate limits and anomaly rules for command execution
+// Commands are counted per session and globally over a one-minute window. Going over a limit throttles
+// the request; a burst of privileged commands or repeated validation failures locks execution for a while.
+// Both come back with a Critical finding so the AI loop (and the user) sees why.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::config::Config;
+use crate::helpers::security::{check_blocked_patterns, validate_command};
+use crate::parser::{Finding, Importance};
+use crate::risk::{self, RiskClass};
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+static STATE: Mutex<ThrottleState> = Mutex::new(ThrottleState::new());
+
+/// Reply sent instead of running the request
+#[derive(Debug, Serialize)]
+pub struct Throttled {
+    pub success: bool, // always false - nothing ran
+    pub status: &'static str, // "throttled" or "locked"
+    pub error: String,
+    pub retry_after_seconds: u64,
+    pub findings: Vec<Finding>,
+}
+
+struct CommandEvent {
+    at: Instant,
+    session: String,
+    privileged: bool,
+}
+
+pub struct ThrottleState {
+    commands: VecDeque<CommandEvent>,
+    failed_validations: VecDeque<Instant>,
+    locked_until: Option<(Instant, String)>,
+}
+
+impl ThrottleState {
+    pub const fn new() -> Self {
+        ThrottleState {
+            commands: VecDeque::new(),
+            failed_validations: VecDeque::new(),
+            locked_until: None,
+        }
+    }
+
+    /// Admit (and count) a request's commands, or explain why they can't run now
+    pub fn check(&mut self, now: Instant, session: &str, commands: &[String], config: &Config) -> Result<(), Throttled> {
+        while self.commands.front().is_some_and(|e| now.duration_since(e.at) >= WINDOW) {
+            self.commands.pop_front();
+        }
+        while self.failed_validations.front().is_some_and(|at| now.duration_since(*at) >= WINDOW) {
+            self.failed_validations.pop_front();
+        }
+
+        if let Some((until, reason)) = &self.locked_until {
+            if *until > now {
+                return Err(locked(reason, until.duration_since(now)));
+            }
+            self.locked_until = None;
+        }
+
+        // Validation failures are rejected by policy::check - here they only count towards the anomaly rule
+        let failures = commands.iter()
+            .filter(|c| validate_command(c).and_then(|_| check_blocked_patterns(c, &config.blocked_patterns)).is_err())
+            .count();
+        self.failed_validations.extend(std::iter::repeat_n(now, failures));
+        if failures > 0 && over(self.failed_validations.len(), config.rate_failed_validation_burst) {
+            return Err(self.lock(now, config, format!(
+                "{} commands failed validation within a minute",
+                self.failed_validations.len()
+            )));
+        }
+
+        let in_session = self.commands.iter().filter(|e| e.session == session).count();
+        if over(in_session + commands.len(), config.rate_session_per_minute) {
+            return Err(throttled(format!(
+                "Rate limit: session '{}' is limited to {} commands per minute",
+                session, config.rate_session_per_minute
+            ), self.retry_after(now, |e| e.session == session)));
+        }
+        if over(self.commands.len() + commands.len(), config.rate_global_per_minute) {
+            return Err(throttled(format!(
+                "Rate limit: at most {} commands per minute across all sessions",
+                config.rate_global_per_minute
+            ), self.retry_after(now, |_| true)));
+        }
+
+        let privileged: Vec<bool> = commands.iter()
+            .map(|c| risk::classify(c).class == RiskClass::Privileged)
+            .collect();
+        let recent_privileged = self.commands.iter().filter(|e| e.privileged).count()
+            + privileged.iter().filter(|p| **p).count();
+        if privileged.contains(&true) && over(recent_privileged, config.rate_privileged_burst) {
+            return Err(self.lock(now, config, format!(
+                "burst of {} privileged commands within a minute",
+                recent_privileged
+            )));
+        }
+
+        self.commands.extend(privileged.into_iter().map(|privileged| CommandEvent {
+            at: now,
+            session: session.to_string(),
+            privileged,
+        }));
+        Ok(())
+    }
+
+    fn lock(&mut self, now: Instant, config: &Config, reason: String) -> Throttled {
+        let duration = Duration::from_secs(config.rate_lockout_seconds);
+        tracing::error!("Execution locked for {}s: {}", duration.as_secs(), reason);
+        self.locked_until = Some((now + duration, reason.clone()));
+        locked(&reason, duration)
+    }
+
+    /// Seconds until the oldest matching command leaves the window
+    fn retry_after(&self, now: Instant, matches: impl Fn(&CommandEvent) -> bool) -> Duration {
+        self.commands.iter()
+            .find(|e| matches(e))
+            .map(|e| WINDOW.saturating_sub(now.duration_since(e.at)))
+            .unwrap_or(WINDOW)
+    }
+}
+
+/// 0 disables a limit
+fn over(count: usize, limit: u32) -> bool {
+    limit > 0 && count > limit as usize
+}
+
+fn throttled(message: String, retry_after: Duration) -> Throttled {
+    tracing::warn!("{}", message);
+    Throttled {
+        success: false,
+        status: "throttled",
+        error: message.clone(),
+        retry_after_seconds: retry_after.as_secs().max(1),
+        findings: vec![
+            Finding::new("Rate Limited", message, Importance::Critical).with_provenance("throttle", 1.0),
+        ],
+    }
+}
+
+fn locked(reason: &str, remaining: Duration) -> Throttled {
+    let message = format!("Execution locked for {}s: {}", remaining.as_secs().max(1), reason);
+    Throttled {
+        success: false,
+        status: "locked",
+        error: message.clone(),
+        retry_after_seconds: remaining.as_secs().max(1),
+        findings: vec![
+            Finding::new("Anomaly Detected", message, Importance::Critical).with_provenance("throttle", 1.0),
+        ],
+    }
+}
+
+/// Daemon-wide gate for command-running requests
+pub fn check(session: &str, commands: &[String], config: &Config) -> Result<(), Throttled> {
+    let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    state.check(Instant::now(), session, commands, config)
+}
+
+/// Time left on the execution lockout and why it was imposed
+pub fn lockout() -> Option<(Duration, String)> {
+    let state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let (until, reason) = state.locked_until.as_ref()?;
+    let remaining = until.checked_duration_since(Instant::now())?;
+    Some((remaining, reason.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commands(command: &str, n: usize) -> Vec<String> {
+        vec![command.to_string(); n]
+    }
+
+    #[test]
+    fn test_session_limit_and_window() {
+        let config = Config { rate_session_per_minute: 3, ..Config::default() };
+        let mut state = ThrottleState::new();
+        let start = Instant::now();
+
+        assert!(state.check(start, "a", &commands("ls", 3), &config).is_ok());
+        let err = state.check(start, "a", &commands("ls", 1), &config).unwrap_err();
+        assert_eq!(err.status, "throttled");
+        assert!(matches!(err.findings[0].importance, Importance::Critical));
+        assert!(state.check(start, "b", &commands("ls", 1), &config).is_ok());
+
+        assert!(state.check(start + WINDOW, "a", &commands("ls", 3), &config).is_ok());
+    }
+
+    #[test]
+    fn test_privileged_burst_locks() {
+        let config = Config { rate_privileged_burst: 2, rate_lockout_seconds: 30, ..Config::default() };
+        let mut state = ThrottleState::new();
+        let start = Instant::now();
+
+        assert!(state.check(start, "a", &commands("sudo ls", 2), &config).is_ok());
+        assert_eq!(state.check(start, "a", &commands("sudo id", 1), &config).unwrap_err().status, "locked");
+        // Even harmless commands wait out the lockout
+        assert!(state.check(start + Duration::from_secs(10), "a", &commands("ls", 1), &config).is_err());
+        assert!(state.check(start + Duration::from_secs(31), "a", &commands("ls", 1), &config).is_ok());
+    }
+
+    #[test]
+    fn test_repeated_validation_failures_lock() {
+        let config = Config {
+            rate_failed_validation_burst: 2,
+            blocked_patterns: vec!["curl | sh".to_string()],
+            ..Config::default()
+        };
+        let mut state = ThrottleState::new();
+        let now = Instant::now();
+
+        assert!(state.check(now, "a", &commands("curl | sh", 2), &config).is_ok());
+        assert_eq!(state.check(now, "a", &commands("curl | sh", 1), &config).unwrap_err().status, "locked");
+    }
+}
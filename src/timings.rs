@@ -0,0 +1,432 @@
+// timings.rs - Where each request spent its time
+// Every connection is timed from accept to the last byte of its reply, split into phases:
+//
+//   read      reading and decoding the request
+//   queue     waiting for the request before it to finish
+//   validate  ACL, kill switch, features, session ownership, confirmation, throttle, secrets, sandbox
+//   execute   the handler itself, minus the phases below
+//   wait      polling a tmux pane for the command to finish
+//   parse     turning raw output into structure and findings
+//   format    rendering, budgets and serializing the reply
+//   write     putting the reply on the socket
+//
+// Time goes to the phase in effect. `switch` moves on for good; `enter` borrows a phase until its
+// guard drops, so a helper that waits or parses doesn't need to know what its caller was doing.
+// The last `[log] recent_requests` are kept for get_recent_requests, and a request slower than
+// `[log] slow_request_ms` is logged with its phases and data. The last MAX_EXCHANGES requests also
+// keep their data and reply (redacted, clipped) for debug bundles.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::config::Config;
+use crate::secrets;
+
+/// Upper bound for `[log] recent_requests`
+pub const MAX_RECENT: usize = 10_000;
+
+/// get_recent_requests page size when the client gives no limit
+const DEFAULT_LIMIT: usize = 20;
+
+/// Longest request data quoted in a slow-request line, and longest data or reply kept in an exchange
+const MAX_LOGGED_DATA: usize = 4096;
+
+/// Request/reply pairs kept for debug bundles
+pub const MAX_EXCHANGES: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    Read,
+    Queue,
+    Validate,
+    Execute,
+    Wait,
+    Parse,
+    Format,
+    Write,
+}
+
+impl Phase {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Phase::Read => "read",
+            Phase::Queue => "queue",
+            Phase::Validate => "validate",
+            Phase::Execute => "execute",
+            Phase::Wait => "wait",
+            Phase::Parse => "parse",
+            Phase::Format => "format",
+            Phase::Write => "write",
+        }
+    }
+}
+
+/// The request being handled (the daemon serves one connection at a time)
+static CURRENT: Mutex<Option<Timer>> = Mutex::new(None);
+
+/// Finished requests, oldest first
+static RECENT: Mutex<VecDeque<RequestTiming>> = Mutex::new(VecDeque::new());
+
+/// What the last requests asked and got back, oldest first
+static EXCHANGES: Mutex<VecDeque<Exchange>> = Mutex::new(VecDeque::new());
+
+struct Timer {
+    ts_ms: u64,
+    started: Instant,
+    phase: Phase,
+    since: Instant,
+    spent: [Duration; 8], // indexed by Phase
+    id: Option<String>,
+    action: String,
+    session: Option<String>,
+    commands: Vec<String>,
+    data: Value, // for the slow-request line and the exchange
+    reply: Option<String>, // redacted and clipped
+    success: bool,
+    status: String,
+    error: Option<String>,
+    captured_bytes: u64,
+    reply_bytes: u64,
+}
+
+impl Timer {
+    /// Charge the time since the last change to the current phase and move to `phase`
+    fn switch(&mut self, phase: Phase) {
+        let now = Instant::now();
+        self.spent[self.phase as usize] += now - self.since;
+        self.phase = phase;
+        self.since = now;
+    }
+}
+
+/// Milliseconds per phase
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PhaseTimes {
+    pub read: f64,
+    pub queue: f64,
+    pub validate: f64,
+    pub execute: f64,
+    pub wait: f64,
+    pub parse: f64,
+    pub format: f64,
+    pub write: f64,
+}
+
+impl PhaseTimes {
+    fn from_spent(spent: &[Duration; 8]) -> Self {
+        let ms = |phase: Phase| millis(spent[phase as usize]);
+        PhaseTimes {
+            read: ms(Phase::Read),
+            queue: ms(Phase::Queue),
+            validate: ms(Phase::Validate),
+            execute: ms(Phase::Execute),
+            wait: ms(Phase::Wait),
+            parse: ms(Phase::Parse),
+            format: ms(Phase::Format),
+            write: ms(Phase::Write),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestTiming {
+    pub ts_ms: u64,
+    pub id: Option<String>,
+    pub action: String, // empty when the request couldn't be read
+    pub session: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub commands: Vec<String>,
+    pub success: bool,
+    pub status: String,
+    pub error: Option<String>,
+    pub total_ms: f64,
+    pub phases: PhaseTimes,
+    pub captured_bytes: u64, // terminal output read for the request
+    pub reply_bytes: u64,
+    pub slow: bool,
+}
+
+/// A request's data and the reply it got, both redacted and clipped to MAX_LOGGED_DATA
+#[derive(Debug, Clone, Serialize)]
+pub struct Exchange {
+    pub ts_ms: u64,
+    pub id: Option<String>,
+    pub action: String,
+    pub request: String,
+    pub reply: Option<String>, // None when no reply was sent
+}
+
+/// Microsecond-precise milliseconds
+fn millis(duration: Duration) -> f64 {
+    duration.as_micros() as f64 / 1000.0
+}
+
+/// Start timing a connection accepted at `accepted` whose request took `read` to arrive - the rest of
+/// the time since then was spent queued
+pub fn start(accepted: Instant, read: Duration) {
+    let now = Instant::now();
+    let ts_ms = SystemTime::now()
+        .checked_sub(now - accepted)
+        .and_then(|at| at.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let mut spent = [Duration::ZERO; 8];
+    spent[Phase::Read as usize] = read;
+    spent[Phase::Queue as usize] = (now - accepted).saturating_sub(read);
+    *CURRENT.lock().unwrap_or_else(|e| e.into_inner()) = Some(Timer {
+        ts_ms,
+        started: accepted,
+        phase: Phase::Validate,
+        since: now,
+        spent,
+        id: None,
+        action: String::new(),
+        session: None,
+        commands: Vec::new(),
+        data: Value::Null,
+        reply: None,
+        success: false,
+        status: String::new(),
+        error: None,
+        captured_bytes: 0,
+        reply_bytes: 0,
+    });
+}
+
+/// Name the request once it's read (again after confirm_execute swaps in the held one)
+pub fn identify(id: Option<&Value>, action: &str, session: Option<String>, commands: &[String], data: &Value) {
+    if let Some(timer) = CURRENT.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        timer.id = id.map(|id| id.as_str().map(str::to_string).unwrap_or_else(|| id.to_string()));
+        timer.action = action.to_string();
+        timer.session = session;
+        timer.commands = commands.iter().map(|command| secrets::redact(command)).collect();
+        timer.data = data.clone();
+    }
+}
+
+/// Move on to `phase` for good
+pub fn switch(phase: Phase) {
+    if let Some(timer) = CURRENT.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        timer.switch(phase);
+    }
+}
+
+/// Back to the phase that was in effect when it was created
+pub struct PhaseGuard(Option<Phase>);
+
+impl Drop for PhaseGuard {
+    fn drop(&mut self) {
+        if let Some(previous) = self.0 {
+            switch(previous);
+        }
+    }
+}
+
+/// Spend time in `phase` until the guard drops
+pub fn enter(phase: Phase) -> PhaseGuard {
+    let mut current = CURRENT.lock().unwrap_or_else(|e| e.into_inner());
+    PhaseGuard(current.as_mut().map(|timer| {
+        let previous = timer.phase;
+        timer.switch(phase);
+        previous
+    }))
+}
+
+/// Terminal output read on the request's behalf
+pub fn captured(bytes: usize) {
+    if let Some(timer) = CURRENT.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        timer.captured_bytes += bytes as u64;
+    }
+}
+
+/// Reply bytes put on the socket
+pub fn wrote(bytes: usize) {
+    if let Some(timer) = CURRENT.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        timer.reply_bytes += bytes as u64;
+    }
+}
+
+/// The request being handled right now, as `introspect` shows it
+#[derive(Debug, Clone, Serialize)]
+pub struct InFlight {
+    pub id: Option<String>,
+    pub action: String,
+    pub session: Option<String>,
+    pub phase: Phase,
+    pub age_ms: f64,
+    pub phase_ms: f64, // in the current phase, this time round
+}
+
+pub fn current() -> Option<InFlight> {
+    let current = CURRENT.lock().unwrap_or_else(|e| e.into_inner());
+    current.as_ref().map(|timer| InFlight {
+        id: timer.id.clone(),
+        action: timer.action.clone(),
+        session: timer.session.clone(),
+        phase: timer.phase,
+        age_ms: millis(timer.started.elapsed()),
+        phase_ms: millis(timer.since.elapsed()),
+    })
+}
+
+/// Note the outcome from the reply about to be sent (the first reply of a request wins)
+pub fn record_reply(reply: &Value) {
+    let mut current = CURRENT.lock().unwrap_or_else(|e| e.into_inner());
+    let timer = match current.as_mut() {
+        Some(timer) if timer.status.is_empty() => timer,
+        _ => return,
+    };
+    let succeeded = reply.get("success").and_then(|v| v.as_bool()).unwrap_or(false);
+    timer.success = succeeded;
+    timer.status = reply.get("status")
+        .and_then(|v| v.as_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| if succeeded { "success" } else { "error" }.to_string());
+    timer.error = reply.get("error").and_then(|v| v.as_str()).map(str::to_string);
+    timer.reply = Some(clip(secrets::redact(&reply.to_string())));
+}
+
+/// At most MAX_LOGGED_DATA bytes of `text`, cut at a char boundary
+fn clip(mut text: String) -> String {
+    if text.len() > MAX_LOGGED_DATA {
+        let end = (0..=MAX_LOGGED_DATA).rev().find(|&i| text.is_char_boundary(i)).unwrap_or(0);
+        text.truncate(end);
+        text.push('…');
+    }
+    text
+}
+
+/// Stop the clock on the current connection: keep it in the ring, log it if it was slow
+pub fn finish(config: &Config) -> Option<RequestTiming> {
+    let mut timer = CURRENT.lock().unwrap_or_else(|e| e.into_inner()).take()?;
+    timer.switch(timer.phase);
+    let total = timer.started.elapsed();
+    let slow = config.slow_request_ms > 0 && total >= Duration::from_millis(config.slow_request_ms);
+    let entry = RequestTiming {
+        ts_ms: timer.ts_ms,
+        id: timer.id,
+        action: timer.action,
+        session: timer.session,
+        commands: timer.commands,
+        success: timer.success,
+        status: if timer.status.is_empty() { "no_reply".to_string() } else { timer.status },
+        error: timer.error,
+        total_ms: millis(total),
+        phases: PhaseTimes::from_spent(&timer.spent),
+        captured_bytes: timer.captured_bytes,
+        reply_bytes: timer.reply_bytes,
+        slow,
+    };
+
+    let data = clip(secrets::redact(&timer.data.to_string()));
+    if slow {
+        let p = &entry.phases;
+        tracing::warn!(
+            id = entry.id.as_deref().unwrap_or("-"),
+            action = %entry.action,
+            session = entry.session.as_deref().unwrap_or("-"),
+            status = %entry.status,
+            error = entry.error.as_deref().unwrap_or("-"),
+            total_ms = entry.total_ms,
+            read_ms = p.read,
+            queue_ms = p.queue,
+            validate_ms = p.validate,
+            execute_ms = p.execute,
+            wait_ms = p.wait,
+            parse_ms = p.parse,
+            format_ms = p.format,
+            write_ms = p.write,
+            data = %data,
+            "Slow request: {} took {:.0} ms (threshold {} ms)", entry.action, entry.total_ms, config.slow_request_ms
+        );
+    }
+
+    push(entry.clone(), config.recent_requests);
+    let mut exchanges = EXCHANGES.lock().unwrap_or_else(|e| e.into_inner());
+    exchanges.push_back(Exchange {
+        ts_ms: entry.ts_ms,
+        id: entry.id.clone(),
+        action: entry.action.clone(),
+        request: data,
+        reply: timer.reply,
+    });
+    while exchanges.len() > MAX_EXCHANGES {
+        exchanges.pop_front();
+    }
+    Some(entry)
+}
+
+/// The last `limit` exchanges, newest first
+pub fn exchanges(limit: usize) -> Vec<Exchange> {
+    EXCHANGES.lock().unwrap_or_else(|e| e.into_inner()).iter().rev().take(limit).cloned().collect()
+}
+
+fn push(entry: RequestTiming, capacity: usize) {
+    let mut recent = RECENT.lock().unwrap_or_else(|e| e.into_inner());
+    recent.push_back(entry);
+    while recent.len() > capacity.max(1) {
+        recent.pop_front();
+    }
+}
+
+/// `get_recent_requests` filters - all optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RecentQuery {
+    pub limit: Option<usize>, // newest N matches (default 20)
+    pub action: Option<String>,
+    #[serde(default)]
+    pub slow_only: bool,
+}
+
+/// Matching requests, newest first
+pub fn recent(query: &RecentQuery) -> Vec<RequestTiming> {
+    let recent = RECENT.lock().unwrap_or_else(|e| e.into_inner());
+    recent.iter()
+        .rev()
+        .filter(|entry| query.action.as_deref().is_none_or(|action| entry.action == action))
+        .filter(|entry| !query.slow_only || entry.slow)
+        .take(query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_RECENT))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phases_and_ring() {
+        let config = Config { slow_request_ms: 0, recent_requests: 2, ..Config::default() };
+
+        for action in ["health", "execute_and_wait", "describe"] {
+            start(Instant::now() - Duration::from_millis(3), Duration::from_millis(1));
+            identify(Some(&Value::from(7)), action, Some("work".to_string()), &[], &Value::Null);
+            switch(Phase::Execute);
+            {
+                let _wait = enter(Phase::Wait);
+                std::thread::sleep(Duration::from_millis(5));
+                let _parse = enter(Phase::Parse);
+            }
+            record_reply(&serde_json::json!({"success": false, "error": "boom"}));
+            record_reply(&serde_json::json!({"success": true}));
+            finish(&config);
+        }
+
+        let all = recent(&RecentQuery::default());
+        assert_eq!(all.iter().map(|e| e.action.as_str()).collect::<Vec<_>>(), ["describe", "execute_and_wait"]);
+        let entry = &all[1];
+        assert_eq!((entry.id.as_deref(), entry.status.as_str(), entry.error.as_deref()), (Some("7"), "error", Some("boom")));
+        assert!(entry.phases.wait >= 5.0);
+        assert_eq!(entry.phases.read, 1.0);
+        assert!(entry.phases.queue >= 2.0);
+        assert!(entry.total_ms >= entry.phases.wait + entry.phases.parse);
+        assert!(!entry.slow);
+
+        let filtered = recent(&RecentQuery { action: Some("describe".to_string()), slow_only: true, ..RecentQuery::default() });
+        assert!(filtered.is_empty());
+    }
+}
@@ -2,7 +2,14 @@
 // Centralizes all tmux interactions, eliminates repetition
 
 use std::process::Command;
-use crate::config::Config;
+use crate::eventlog::{self, Kind};
+use crate::introspect;
+use crate::killswitch;
+use crate::watchdog;
+use crate::timings::{self, Phase};
+
+/// Session option set on sessions archy created or was allowed to use (survives daemon restarts)
+const MANAGED_OPTION: &str = "@archy_managed";
 
 /// Execute a tmux command and return output
 fn run_tmux(args: &[&str]) -> Result<String, String> {
@@ -34,25 +41,72 @@ pub fn has_session(session: &str) -> bool {
 
 /// Send keys to a tmux session (execute command)
 pub fn send_keys(session: &str, command: &str) -> Result<(), String> {
+    watchdog::sent(session);
     run_tmux(&["send-keys", "-t", session, command, "C-m"])
         .map(|_| ())
 }
 
+/// Send literal text followed by Enter (answers to interactive prompts)
+pub fn send_input(session: &str, text: &str) -> Result<(), String> {
+    run_tmux(&["send-keys", "-t", session, "-l", text])?;
+    run_tmux(&["send-keys", "-t", session, "Enter"]).map(|_| ())
+}
+
+/// Interrupt whatever is running in the pane
+pub fn send_interrupt(session: &str) -> Result<(), String> {
+    run_tmux(&["send-keys", "-t", session, "C-c"]).map(|_| ())
+}
+
 /// Capture output from tmux pane
 pub fn capture_pane(session: &str, lines: i64) -> Result<String, String> {
     run_tmux(&["capture-pane", "-pt", session, "-S", &format!("-{}", lines)])
 }
 
-/// Create a new tmux session
+/// Create a new tmux session (marked as managed by archy)
 pub fn new_session(session: &str) -> Result<(), String> {
-    run_tmux(&["new-session", "-d", "-s", session])
-        .map(|_| ())
+    new_session_in(session, None)
+}
+
+/// Create a new tmux session starting in a given directory (falls back to tmux's default)
+pub fn new_session_in(session: &str, dir: Option<&str>) -> Result<(), String> {
+    let mut args = vec!["new-session", "-d", "-s", session];
+    if let Some(dir) = dir {
+        args.extend(["-c", dir]);
+    }
+    run_tmux(&args)?;
+    mark_managed(session)?;
+    eventlog::emit(Kind::SessionCreated, Some(session), format!("Session {} created", session), serde_json::json!({"cwd": dir}));
+    Ok(())
+}
+
+/// Let archy send commands to a session (`=name:` so the name isn't matched as a prefix)
+pub fn mark_managed(session: &str) -> Result<(), String> {
+    run_tmux(&["set-option", "-t", &format!("={}:", session), MANAGED_OPTION, "1"]).map(|_| ())
+}
+
+/// Whether archy created or claimed this exact session
+pub fn is_managed(session: &str) -> bool {
+    run_tmux(&["show-options", "-v", "-t", &format!("={}:", session), MANAGED_OPTION])
+        .is_ok_and(|value| value.trim() == "1")
+}
+
+/// How many clients (terminal windows) are attached to this exact session
+pub fn client_count(session: &str) -> usize {
+    run_tmux(&["list-clients", "-t", &format!("={}:", session), "-F", "#{client_tty}"])
+        .map(|clients| clients.lines().filter(|line| !line.trim().is_empty()).count())
+        .unwrap_or(0)
+}
+
+/// Detach every client of the session - a terminal started just to attach closes with it
+pub fn detach_clients(session: &str) -> Result<(), String> {
+    run_tmux(&["detach-client", "-s", &format!("={}:", session)]).map(|_| ())
 }
 
 /// Kill a tmux session
 pub fn kill_session(session: &str) -> Result<(), String> {
-    run_tmux(&["kill-session", "-t", session])
-        .map(|_| ())
+    run_tmux(&["kill-session", "-t", session])?;
+    eventlog::emit(Kind::SessionKilled, Some(session), format!("Session {} killed", session), serde_json::Value::Null);
+    Ok(())
 }
 
 /// List all tmux sessions
@@ -71,90 +125,147 @@ pub fn get_pane_cwd(session: &str) -> Result<String, String> {
         .map(|s| s.trim().to_string())
 }
 
-/// Wait for command completion by monitoring prompt
-pub fn wait_for_prompt(
+/// Result of waiting for a command to finish
+pub struct WaitOutcome {
+    pub completed: bool,        // false = timed out (or stopped at a prompt), output is partial
+    pub output: String,
+    pub prompt: Option<String>, // Interactive prompt line the command is blocked on
+}
+
+/// Interactive prompts that will never complete on their own (Y/n confirmations, passwords)
+pub fn is_input_prompt(line: &str) -> bool {
+    const MARKERS: &[&str] = &[
+        "[y/n]", "(y/n)", "[yes/no]", "(yes/no", "[y/n/", "password", "passphrase", "[sudo]",
+        "press enter", "press return",
+    ];
+    let lower = line.trim().to_lowercase();
+    !lower.is_empty() && MARKERS.iter().any(|m| lower.contains(m))
+}
+
+/// Wait for a command to finish: prompt back on the last line, command not just echoed,
+/// not waiting for a password, and output stable for a few polls
+pub fn wait_for_completion(
     session: &str,
+    command: &str,
     max_wait_ms: u64,
-    poll_interval_ms: u64,
-) -> Result<String, String> {
-    use std::thread;
-    use std::time::Duration;
+    check_interval_ms: u64,
+) -> WaitOutcome {
+    wait_for(session, command, max_wait_ms, check_interval_ms, false)
+}
 
-    let max_iterations = max_wait_ms / poll_interval_ms;
-    let mut previous_output = String::new();
-    let mut stable_count = 0;
-    const STABILITY_THRESHOLD: u32 = 6; // 3 seconds at 500ms intervals
+/// Like wait_for_completion, but returns early when the command sits at an interactive prompt
+pub fn wait_for_completion_or_prompt(
+    session: &str,
+    command: &str,
+    max_wait_ms: u64,
+    check_interval_ms: u64,
+) -> WaitOutcome {
+    wait_for(session, command, max_wait_ms, check_interval_ms, true)
+}
 
-    for _ in 0..max_iterations {
-        thread::sleep(Duration::from_millis(poll_interval_ms));
+/// wait_for_completion off the request's thread - not timed or listed as one of the request's waits
+pub fn wait_for_completion_untracked(
+    session: &str,
+    command: &str,
+    max_wait_ms: u64,
+    check_interval_ms: u64,
+) -> WaitOutcome {
+    poll(session, command, max_wait_ms, check_interval_ms, false)
+}
 
-        let current_output = capture_pane(session, 50)?;
+fn wait_for(
+    session: &str,
+    command: &str,
+    max_wait_ms: u64,
+    check_interval_ms: u64,
+    stop_at_prompt: bool,
+) -> WaitOutcome {
+    let _wait = timings::enter(Phase::Wait);
+    let _listed = introspect::waiting(session, format!("Waiting for `{}` to finish", command), std::time::Duration::from_millis(max_wait_ms));
+    poll(session, command, max_wait_ms, check_interval_ms, stop_at_prompt)
+}
 
-        if current_output == previous_output {
-            stable_count += 1;
-            if stable_count >= STABILITY_THRESHOLD {
-                // Output stable for 3 seconds - command complete
-                return Ok(current_output);
-            }
-        } else {
-            stable_count = 0;
-            previous_output = current_output;
-        }
-    }
+fn poll(
+    session: &str,
+    command: &str,
+    max_wait_ms: u64,
+    check_interval_ms: u64,
+    stop_at_prompt: bool,
+) -> WaitOutcome {
+    use std::thread;
+    use std::time::{Duration, Instant};
 
-    // Timeout - return what we have
-    Ok(previous_output)
-}
+    let start_time = Instant::now();
+    let max_duration = Duration::from_millis(max_wait_ms);
+    let check_interval = Duration::from_millis(check_interval_ms);
 
-/// High-level session management
-pub struct Session<'a> {
-    pub name: &'a str,
-    pub config: &'a Config,
-}
+    let mut last_output = String::new();
+    let mut stable_count = 0;
+    let required_stable_checks = 3; // Output must be stable for 3 checks
 
-impl<'a> Session<'a> {
-    pub fn new(name: &'a str, config: &'a Config) -> Self {
-        Session { name, config }
-    }
+    // An emergency stop ends the wait early (the pane has just been sent C-c)
+    while start_time.elapsed() < max_duration && !killswitch::is_stopped() {
+        thread::sleep(check_interval);
 
-    /// Check if this session exists
-    pub fn exists(&self) -> bool {
-        has_session(self.name)
-    }
+        // Capture current output
+        let output_result = Command::new("tmux")
+            .args(["capture-pane", "-pt", session, "-S", "-100"])
+            .output();
 
-    /// Ensure session exists (create if needed)
-    pub fn ensure_exists(&self) -> Result<(), String> {
-        if !self.exists() {
-            new_session(self.name)?;
-        }
-        Ok(())
-    }
+        if let Ok(out) = output_result {
+            if out.status.success() {
+                // FIX #4: Handle invalid UTF-8 properly
+                let current_output = match String::from_utf8(out.stdout) {
+                    Ok(s) => s,
+                    Err(e) => {
+                        tracing::warn!("Invalid UTF-8 in tmux output: {}", e);
+                        continue;
+                    }
+                };
 
-    /// Execute command in this session
-    pub fn execute(&self, command: &str) -> Result<(), String> {
-        self.ensure_exists()?;
-        send_keys(self.name, command)
-    }
+                // Check if output has stabilized (FIX #6: Don't clone full string every loop)
+                if current_output == last_output {
+                    stable_count += 1;
+                } else {
+                    stable_count = 0;
+                    last_output = current_output.clone();
+                }
 
-    /// Capture output from this session
-    pub fn capture(&self, lines: i64) -> Result<String, String> {
-        capture_pane(self.name, lines)
-    }
+                // Look for prompt in last line
+                let lines: Vec<&str> = current_output.trim().split('\n').collect();
+                if let Some(last_line) = lines.last() {
+                    // FIX #5: Support more shell prompts
+                    let has_prompt = last_line.contains('$') ||
+                                   last_line.contains('#') ||
+                                   last_line.contains('❯') ||
+                                   last_line.contains('>') ||
+                                   last_line.contains('❮') ||
+                                   last_line.contains('⚡');
 
-    /// Execute command and wait for completion
-    pub fn execute_and_wait(&self, command: &str) -> Result<String, String> {
-        self.execute(command)?;
-        wait_for_prompt(
-            self.name,
-            self.config.max_wait_seconds * 1000,
-            self.config.poll_interval_ms,
-        )
-    }
+                    // Make sure the command itself is not in the last line (it just echoed)
+                    let command_not_echoed = !last_line.contains(command) || command.is_empty();
+
+                    // Check if it's waiting for password
+                    let waiting_for_password = last_line.to_lowercase().contains("password for") ||
+                                              last_line.to_lowercase().contains("[sudo]");
+
+                    if !waiting_for_password && has_prompt && command_not_echoed && stable_count >= required_stable_checks {
+                        return WaitOutcome { completed: true, output: current_output, prompt: None };
+                    }
 
-    /// Kill this session
-    pub fn kill(&self) -> Result<(), String> {
-        kill_session(self.name)
+                    if stop_at_prompt && stable_count >= required_stable_checks && is_input_prompt(last_line) {
+                        return WaitOutcome {
+                            completed: false,
+                            prompt: Some(last_line.trim().to_string()),
+                            output: current_output,
+                        };
+                    }
+                }
+            }
+        }
     }
+
+    WaitOutcome { completed: false, output: last_output, prompt: None }
 }
 
 #[cfg(test)]
@@ -165,14 +276,31 @@ mod tests {
     fn test_has_session() {
         // This will fail if no tmux sessions exist, which is fine for unit tests
         let result = has_session("nonexistent_session_xyz123");
-        assert_eq!(result, false);
+        assert!(!result);
+        assert!(!is_managed("nonexistent_session_xyz123"));
+    }
+
+    #[test]
+    fn test_is_managed_only_once_marked() {
+        let session = format!("archy-test-managed-{}", std::process::id());
+        // A session someone else started, as a human's shell would be
+        if run_tmux(&["new-session", "-d", "-s", &session]).is_err() {
+            return; // no tmux server can run here
+        }
+        let unmanaged = has_session(&session) && !is_managed(&session);
+        let marked = mark_managed(&session).is_ok() && is_managed(&session);
+        // The name is matched exactly, not as a prefix of the managed session
+        let prefix = is_managed(&session[..session.len() - 1]);
+        let _ = run_tmux(&["kill-session", "-t", &format!("={}", session)]);
+        assert!(unmanaged && marked && !prefix);
     }
 
     #[test]
-    fn test_session_struct() {
-        let config = Config::default();
-        let session = Session::new("test_session", &config);
-        assert_eq!(session.name, "test_session");
+    fn test_is_input_prompt() {
+        assert!(is_input_prompt(":: Proceed with installation? [Y/n] "));
+        assert!(is_input_prompt("[sudo] password for archy:"));
+        assert!(is_input_prompt("Are you sure you want to continue connecting (yes/no/[fingerprint])?"));
+        assert!(!is_input_prompt("user@host ~ $"));
     }
 }
 
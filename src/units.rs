@@ -0,0 +1,405 @@
+// units.rs - systemd units: unit_status, list_failed_units, unit_journal_tail
+// Unit state comes from systemd's D-Bus API (org.freedesktop.systemd1 on the system bus, or the user
+// manager on the session bus with `scope: "user"`) as typed properties, not scraped from `systemctl
+// status`. Where there is no bus to ask - containers, chroots, a daemon started outside the session -
+// the same answers come from `systemctl show` and `systemctl list-units --plain`, and `source` says
+// which was used. Systems without systemd get an error saying so.
+//
+// The journal has no D-Bus API: unit_journal_tail reads `journalctl -o json` and runs the messages
+// through the parser's journalctl format for its findings.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+use crate::helpers::process;
+use crate::parser::{self, Finding, Importance, RiskLevel, SuggestedAction};
+
+const SYSTEMD: &str = "org.freedesktop.systemd1";
+const SYSTEMD_PATH: &str = "/org/freedesktop/systemd1";
+const MANAGER: &str = "org.freedesktop.systemd1.Manager";
+const DBUS_TIMEOUT: Duration = Duration::from_secs(5);
+const CLI_TIMEOUT: Duration = Duration::from_secs(15);
+
+const MAX_UNIT_NAME: usize = 256;
+
+/// Default and largest journal tail
+const DEFAULT_JOURNAL_LINES: usize = 50;
+const MAX_JOURNAL_LINES: usize = 1000;
+
+/// Journal suggestions offered for at most this many failed units
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Properties `systemctl show` is asked for in the fallback - the ones UnitStatus carries
+const SHOW_PROPERTIES: &str = "Id,Description,LoadState,ActiveState,SubState,UnitFileState,FragmentPath,\
+    ActiveEnterTimestamp,MainPID,ExecMainStatus,Result,NRestarts,MemoryCurrent";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    System,
+    User,
+}
+
+impl Scope {
+    pub fn from_request(data: &Value) -> Result<Scope, String> {
+        match data.get("scope").and_then(|v| v.as_str()) {
+            None | Some("system") => Ok(Scope::System),
+            Some("user") => Ok(Scope::User),
+            Some(other) => Err(format!("Invalid scope '{}': system or user", other)),
+        }
+    }
+
+    fn flag(&self) -> &'static str {
+        match self {
+            Scope::System => "--system",
+            Scope::User => "--user",
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct UnitStatus {
+    pub id: String,
+    pub description: Option<String>,
+    pub load_state: String,
+    pub active_state: String,
+    pub sub_state: String,
+    pub unit_file_state: Option<String>,
+    pub fragment_path: Option<String>,
+    pub active_since_us: Option<u64>, // realtime µs since the epoch
+    pub main_pid: Option<u32>,
+    pub exit_status: Option<i32>,
+    pub result: Option<String>, // services: "success", "exit-code", "signal", ...
+    pub restarts: Option<u32>,
+    pub memory_bytes: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FailedUnit {
+    pub name: String,
+    pub description: String,
+    pub load_state: String,
+    pub active_state: String,
+    pub sub_state: String,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct JournalEntry {
+    pub timestamp_us: Option<u64>,
+    pub priority: Option<u8>, // 0 emerg .. 7 debug
+    pub pid: Option<u32>,
+    pub identifier: Option<String>,
+    pub message: String,
+}
+
+/// A validated request for one unit
+pub struct UnitQuery {
+    pub unit: String,
+    pub scope: Scope,
+    pub lines: usize, // unit_journal_tail only
+}
+
+impl UnitQuery {
+    /// {unit, scope?, lines?}
+    pub fn from_request(data: &Value) -> Result<UnitQuery, String> {
+        let lines = match data.get("lines") {
+            None | Some(Value::Null) => DEFAULT_JOURNAL_LINES,
+            Some(value) => value.as_u64()
+                .map(|n| n as usize)
+                .filter(|n| (1..=MAX_JOURNAL_LINES).contains(n))
+                .ok_or_else(|| format!("lines must be a whole number from 1 to {}", MAX_JOURNAL_LINES))?,
+        };
+        Ok(UnitQuery { unit: unit_name(data)?, scope: Scope::from_request(data)?, lines })
+    }
+}
+
+/// A unit name as systemd takes it: no suffix means .service
+fn unit_name(data: &Value) -> Result<String, String> {
+    let unit = data.get("unit").and_then(|v| v.as_str()).ok_or("Missing unit parameter")?;
+    if unit.is_empty() || unit.len() > MAX_UNIT_NAME || unit.starts_with('-') {
+        return Err(format!("Invalid unit name '{}'", unit));
+    }
+    if let Some(c) = unit.chars().find(|c| !(c.is_ascii_alphanumeric() || ":_.@-\\".contains(*c))) {
+        return Err(format!("Invalid unit name: character '{}' not allowed", c));
+    }
+    const SUFFIXES: &[&str] = &[
+        ".service", ".socket", ".target", ".timer", ".mount", ".automount", ".path", ".slice", ".scope", ".device", ".swap",
+    ];
+    Ok(match SUFFIXES.iter().any(|suffix| unit.ends_with(suffix)) {
+        true => unit.to_string(),
+        false => format!("{}.service", unit),
+    })
+}
+
+fn connect(scope: Scope) -> Result<zbus::blocking::Connection, String> {
+    let builder = match scope {
+        Scope::System => zbus::blocking::connection::Builder::system(),
+        Scope::User => zbus::blocking::connection::Builder::session(),
+    };
+    builder.map(|builder| builder.method_timeout(DBUS_TIMEOUT))
+        .and_then(|builder| builder.build())
+        .map_err(|e| format!("No {} bus: {}", if scope == Scope::System { "system" } else { "session" }, e))
+}
+
+/// `unit_status`: (status, source)
+pub fn status(query: &UnitQuery) -> Result<(UnitStatus, &'static str), String> {
+    let (unit, scope) = (&query.unit, query.scope);
+    match status_dbus(unit, scope) {
+        Ok(status) => Ok((status, "dbus")),
+        Err(e) => {
+            tracing::debug!("systemd D-Bus unavailable ({}), asking systemctl", e);
+            status_cli(unit, scope).map(|status| (status, "systemctl"))
+        }
+    }
+}
+
+fn status_dbus(unit: &str, scope: Scope) -> Result<UnitStatus, String> {
+    let connection = connect(scope)?;
+    // LoadUnit also answers for units that aren't loaded (GetUnit would fail for them)
+    let path: OwnedObjectPath = connection.call_method(Some(SYSTEMD), SYSTEMD_PATH, Some(MANAGER), "LoadUnit", &(unit,))
+        .and_then(|reply| reply.body().deserialize())
+        .map_err(|e| e.to_string())?;
+    let properties = |interface: &str| -> HashMap<String, OwnedValue> {
+        connection.call_method(Some(SYSTEMD), path.as_str(), Some("org.freedesktop.DBus.Properties"), "GetAll", &(interface,))
+            .ok()
+            .and_then(|reply| reply.body().deserialize().ok())
+            .unwrap_or_default()
+    };
+    let mut props = properties("org.freedesktop.systemd1.Unit");
+    if props.is_empty() {
+        return Err(format!("systemd returned no properties for {}", unit));
+    }
+    if unit.ends_with(".service") {
+        props.extend(properties("org.freedesktop.systemd1.Service"));
+    }
+    let text = |key: &str| props.get(key).and_then(|v| <&str>::try_from(v).ok()).filter(|s| !s.is_empty()).map(String::from);
+    let number = |key: &str| props.get(key).and_then(|v| u64::try_from(v).ok());
+    Ok(UnitStatus {
+        id: text("Id").unwrap_or_else(|| unit.to_string()),
+        description: text("Description"),
+        load_state: text("LoadState").unwrap_or_default(),
+        active_state: text("ActiveState").unwrap_or_default(),
+        sub_state: text("SubState").unwrap_or_default(),
+        unit_file_state: text("UnitFileState"),
+        fragment_path: text("FragmentPath"),
+        active_since_us: number("ActiveEnterTimestamp").filter(|&t| t > 0),
+        main_pid: props.get("MainPID").and_then(|v| u32::try_from(v).ok()).filter(|&pid| pid > 0),
+        exit_status: props.get("ExecMainStatus").and_then(|v| i32::try_from(v).ok()),
+        result: text("Result"),
+        restarts: props.get("NRestarts").and_then(|v| u32::try_from(v).ok()),
+        memory_bytes: number("MemoryCurrent").filter(|&m| m != u64::MAX), // u64::MAX: not accounted
+    })
+}
+
+fn status_cli(unit: &str, scope: Scope) -> Result<UnitStatus, String> {
+    let argv: Vec<String> = ["systemctl", scope.flag(), "show", "--no-pager", "-p", SHOW_PROPERTIES, "--", unit]
+        .iter().map(|s| s.to_string()).collect();
+    let (code, stdout, stderr) = process::run(&argv, CLI_TIMEOUT).map_err(no_systemd)?;
+    if code != 0 {
+        return Err(format!("systemctl show {} failed: {}", unit, stderr.trim()));
+    }
+    Ok(parse_show(&stdout, unit))
+}
+
+/// A missing systemctl means there's no systemd to ask
+fn no_systemd(e: String) -> String {
+    format!("systemd is not available here ({})", e)
+}
+
+/// `systemctl show` KEY=VALUE lines
+fn parse_show(text: &str, unit: &str) -> UnitStatus {
+    let props: HashMap<&str, &str> = text.lines().filter_map(|line| line.split_once('=')).collect();
+    let text = |key: &str| props.get(key).map(|v| v.trim()).filter(|v| !v.is_empty() && *v != "[not set]").map(String::from);
+    let number = |key: &str| props.get(key).and_then(|v| v.trim().parse::<u64>().ok());
+    UnitStatus {
+        id: text("Id").unwrap_or_else(|| unit.to_string()),
+        description: text("Description"),
+        load_state: text("LoadState").unwrap_or_default(),
+        active_state: text("ActiveState").unwrap_or_default(),
+        sub_state: text("SubState").unwrap_or_default(),
+        unit_file_state: text("UnitFileState"),
+        fragment_path: text("FragmentPath"),
+        // `show` prints timestamps as dates - only D-Bus gives the number
+        active_since_us: None,
+        main_pid: number("MainPID").filter(|&pid| pid > 0).map(|pid| pid as u32),
+        exit_status: props.get("ExecMainStatus").and_then(|v| v.trim().parse().ok()),
+        result: text("Result"),
+        restarts: number("NRestarts").map(|n| n as u32),
+        memory_bytes: number("MemoryCurrent"),
+    }
+}
+
+/// `list_failed_units`: (units, source)
+pub fn list_failed(scope: Scope) -> Result<(Vec<FailedUnit>, &'static str), String> {
+    match list_failed_dbus(scope) {
+        Ok(units) => Ok((units, "dbus")),
+        Err(e) => {
+            tracing::debug!("systemd D-Bus unavailable ({}), asking systemctl", e);
+            let argv: Vec<String> = ["systemctl", scope.flag(), "list-units", "--state=failed", "--plain", "--no-legend", "--no-pager"]
+                .iter().map(|s| s.to_string()).collect();
+            let (code, stdout, stderr) = process::run(&argv, CLI_TIMEOUT).map_err(no_systemd)?;
+            if code != 0 {
+                return Err(format!("systemctl list-units failed: {}", stderr.trim()));
+            }
+            Ok((parse_list_units(&stdout), "systemctl"))
+        }
+    }
+}
+
+type UnitRow = (String, String, String, String, String, String, OwnedObjectPath, u32, String, OwnedObjectPath);
+
+fn list_failed_dbus(scope: Scope) -> Result<Vec<FailedUnit>, String> {
+    let connection = connect(scope)?;
+    let rows: Vec<UnitRow> = connection.call_method(Some(SYSTEMD), SYSTEMD_PATH, Some(MANAGER), "ListUnitsFiltered", &(vec!["failed"],))
+        .and_then(|reply| reply.body().deserialize())
+        .map_err(|e| e.to_string())?;
+    let mut units: Vec<FailedUnit> = rows.into_iter()
+        .map(|(name, description, load_state, active_state, sub_state, ..)| FailedUnit { name, description, load_state, active_state, sub_state })
+        .collect();
+    units.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(units)
+}
+
+/// `systemctl list-units --plain --no-legend` rows: UNIT LOAD ACTIVE SUB DESCRIPTION
+fn parse_list_units(text: &str) -> Vec<FailedUnit> {
+    text.lines()
+        .filter_map(|line| {
+            // A leading "●" marks failed units on some versions even with --plain
+            let mut fields = line.trim_start_matches(|c: char| c == '●' || c == '*' || c.is_whitespace()).split_whitespace();
+            let name = fields.next()?.to_string();
+            let (load_state, active_state, sub_state) = (fields.next()?.to_string(), fields.next()?.to_string(), fields.next()?.to_string());
+            Some(FailedUnit { name, description: fields.collect::<Vec<_>>().join(" "), load_state, active_state, sub_state })
+        })
+        .collect()
+}
+
+/// What's wrong with a unit, if anything
+pub fn status_findings(status: &UnitStatus) -> (Vec<Finding>, Vec<SuggestedAction>) {
+    let mut findings = Vec::new();
+    let mut suggestions = Vec::new();
+    if status.load_state == "not-found" {
+        findings.push(Finding::new("Unit Not Found", format!("{} is not installed", status.id), Importance::Medium));
+    } else if status.active_state == "failed" {
+        let why = match (status.result.as_deref(), status.exit_status) {
+            (Some(result), Some(code)) if code != 0 => format!(" ({}, status {})", result, code),
+            (Some(result), _) => format!(" ({})", result),
+            _ => String::new(),
+        };
+        findings.push(Finding::new("Failed Services", format!("{} has failed{}", status.id, why), Importance::High));
+        suggestions.push(journal_suggestion(&status.id));
+    }
+    if status.restarts.is_some_and(|n| n >= 3) {
+        findings.push(Finding::new(
+            "Restart Loop",
+            format!("{} has been restarted {} times", status.id, status.restarts.unwrap_or(0)),
+            Importance::Medium,
+        ));
+    }
+    (findings, suggestions)
+}
+
+pub fn failed_findings(units: &[FailedUnit]) -> (Vec<Finding>, Vec<SuggestedAction>) {
+    if units.is_empty() {
+        return (Vec::new(), Vec::new());
+    }
+    let names: Vec<&str> = units.iter().map(|u| u.name.as_str()).collect();
+    let finding = Finding::new("Failed Services", format!("{} unit(s) in failed state: {}", units.len(), names.join(", ")), Importance::High);
+    let suggestions = names.iter().take(MAX_SUGGESTIONS).map(|name| journal_suggestion(name)).collect();
+    (vec![finding], suggestions)
+}
+
+fn journal_suggestion(unit: &str) -> SuggestedAction {
+    SuggestedAction::new(
+        &format!("journalctl -u {} -n 50 --no-pager", unit),
+        &format!("Inspect recent logs for failed unit {}", unit),
+        RiskLevel::Low,
+    )
+}
+
+/// `unit_journal_tail`: the unit's last journal entries, oldest first, and the parser's findings on them
+pub fn journal_tail(query: &UnitQuery) -> Result<(Vec<JournalEntry>, Vec<Finding>), String> {
+    let (unit, lines) = (&query.unit, query.lines);
+    let unit_flag = if query.scope == Scope::User { "--user-unit" } else { "--unit" };
+    let argv: Vec<String> = ["journalctl", unit_flag, unit, "-n", &lines.to_string(), "-o", "json", "--no-pager", "-q"]
+        .iter().map(|s| s.to_string()).collect();
+    let (code, stdout, stderr) = process::run(&argv, CLI_TIMEOUT).map_err(no_systemd)?;
+    if code != 0 && stdout.trim().is_empty() {
+        return Err(format!("journalctl failed: {}", stderr.trim()));
+    }
+    let entries = parse_journal_json(&stdout);
+    let text: Vec<&str> = entries.iter().map(|e| e.message.as_str()).collect();
+    let parsed = parser::parse_intelligently(&text.join("\n"), &format!("journalctl -u {}", unit));
+    Ok((entries, parsed.findings))
+}
+
+/// `journalctl -o json` lines. MESSAGE is an array of bytes when it isn't valid UTF-8
+fn parse_journal_json(text: &str) -> Vec<JournalEntry> {
+    text.lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .map(|entry| {
+            let field = |key: &str| entry.get(key).and_then(|v| v.as_str()).map(String::from);
+            let message = match entry.get("MESSAGE") {
+                Some(Value::String(message)) => message.clone(),
+                Some(Value::Array(bytes)) => {
+                    let bytes: Vec<u8> = bytes.iter().filter_map(|b| b.as_u64()).map(|b| b as u8).collect();
+                    String::from_utf8_lossy(&bytes).to_string()
+                }
+                _ => String::new(),
+            };
+            JournalEntry {
+                timestamp_us: field("__REALTIME_TIMESTAMP").and_then(|t| t.parse().ok()),
+                priority: field("PRIORITY").and_then(|p| p.parse().ok()),
+                pid: field("_PID").and_then(|p| p.parse().ok()),
+                identifier: field("SYSLOG_IDENTIFIER"),
+                message,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_unit_name() {
+        assert_eq!(unit_name(&json!({"unit": "sshd"})).unwrap(), "sshd.service");
+        assert_eq!(unit_name(&json!({"unit": "getty@tty1.service"})).unwrap(), "getty@tty1.service");
+        assert_eq!(unit_name(&json!({"unit": "fstrim.timer"})).unwrap(), "fstrim.timer");
+        assert!(unit_name(&json!({"unit": "--all"})).is_err());
+        assert!(unit_name(&json!({"unit": "a b"})).is_err());
+        assert!(Scope::from_request(&json!({"scope": "root"})).is_err());
+        assert_eq!(UnitQuery::from_request(&json!({"unit": "sshd", "scope": "user"})).unwrap().lines, DEFAULT_JOURNAL_LINES);
+        assert!(UnitQuery::from_request(&json!({"unit": "sshd", "lines": 0})).is_err());
+    }
+
+    #[test]
+    fn test_cli_fallback() {
+        let show = "Id=nginx.service\nDescription=A high performance web server\nLoadState=loaded\nActiveState=failed\n\
+            SubState=failed\nResult=exit-code\nExecMainStatus=1\nMainPID=0\nNRestarts=5\nMemoryCurrent=[not set]\n";
+        let status = parse_show(show, "nginx.service");
+        assert_eq!((status.active_state.as_str(), status.exit_status, status.main_pid), ("failed", Some(1), None));
+        assert_eq!(status.memory_bytes, None);
+        let (findings, suggestions) = status_findings(&status);
+        assert_eq!(findings.len(), 2);
+        assert!(findings[0].message.contains("exit-code, status 1"));
+        assert_eq!(suggestions[0].command, "journalctl -u nginx.service -n 50 --no-pager");
+
+        let units = parse_list_units("● nginx.service loaded failed failed A high performance web server\nbackup.timer loaded failed failed Nightly backup\n");
+        assert_eq!(units.len(), 2);
+        assert_eq!((units[0].name.as_str(), units[0].description.as_str()), ("nginx.service", "A high performance web server"));
+        assert_eq!(failed_findings(&units).1.len(), 2);
+    }
+
+    #[test]
+    fn test_journal_json() {
+        let text = "{\"__REALTIME_TIMESTAMP\":\"1700000000000000\",\"PRIORITY\":\"3\",\"_PID\":\"42\",\"SYSLOG_IDENTIFIER\":\"nginx\",\"MESSAGE\":\"bind() failed\"}\n\
+            {\"MESSAGE\":[104,105],\"PRIORITY\":\"6\"}\nnot json\n";
+        let entries = parse_journal_json(text);
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].priority, entries[0].pid, entries[0].timestamp_us), (Some(3), Some(42), Some(1_700_000_000_000_000)));
+        assert_eq!(entries[1].message, "hi");
+    }
+}
@@ -0,0 +1,258 @@
+// watchdog.rs - Bringing back managed tmux sessions that died or stopped responding
+// A background thread looks at the sessions archy manages every `[watchdog] interval_seconds`:
+//
+//   - the tmux server is gone (crashed, killed, OOM): every session it was watching is recreated
+//   - a pane is wedged: keys for a command went in more than `stall_seconds` ago and the screen never
+//     changed - not even the echo of the command line. The session is killed and recreated
+//
+// A recreated session starts in the directory the old one was last in, and the project's `cd` and
+// exports (.archy.toml) are replayed. The next reply for that session carries a `Session Reset`
+// finding, since shell variables, history and whatever was running are gone. Ticks only run between
+// requests, so a handler never sees a session vanish under it. Sessions closed through archy are
+// forgotten, not recreated.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::config::Config;
+use crate::eventlog::{self, Kind};
+use crate::events;
+use crate::parser::{Finding, Importance};
+use crate::project::{self, ProjectConfig};
+use crate::tmux;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static RESETS: AtomicU64 = AtomicU64::new(0);
+
+/// Held while a request is handled and while the watchdog looks - the two never overlap
+static QUIET: Mutex<()> = Mutex::new(());
+
+/// Managed sessions seen on the last tick
+static SESSIONS: Mutex<BTreeMap<String, Watched>> = Mutex::new(BTreeMap::new());
+
+/// Resets no reply has reported yet
+static UNANNOUNCED: Mutex<Vec<Reset>> = Mutex::new(Vec::new());
+
+#[derive(Default)]
+struct Watched {
+    cwd: Option<String>,
+    sent: Option<Sent>, // keys not yet seen on screen
+}
+
+struct Sent {
+    at: Instant,
+    screen: String, // the visible pane just before
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Reset {
+    pub session: String,
+    pub reason: &'static str,
+    pub ts_ms: u64,
+    pub cwd: Option<String>,
+    pub project: Option<String>, // root of the project whose setup was replayed
+}
+
+/// Sessions recreated since startup
+pub fn resets() -> u64 {
+    RESETS.load(Ordering::SeqCst)
+}
+
+/// Keep the watchdog out until the guard drops (the serve loop holds it around each connection)
+pub fn hold() -> MutexGuard<'static, ()> {
+    QUIET.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Start the watchdog thread if `[watchdog]` enables it
+pub fn spawn(config: &Config) {
+    if !config.watchdog_enabled {
+        return;
+    }
+    ENABLED.store(true, Ordering::SeqCst);
+    let config = config.clone();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(Duration::from_secs(config.watchdog_interval_seconds));
+        let _quiet = hold();
+        tick(&config);
+    });
+}
+
+/// A command's keys are about to go to `session` - remember the screen they should change
+pub fn sent(session: &str) {
+    if !ENABLED.load(Ordering::SeqCst) {
+        return;
+    }
+    let Ok(screen) = tmux::capture_pane(session, 0) else { return };
+    let mut sessions = SESSIONS.lock().unwrap_or_else(|e| e.into_inner());
+    let watched = sessions.entry(session.to_string()).or_default();
+    if watched.sent.is_none() {
+        watched.sent = Some(Sent { at: Instant::now(), screen });
+    }
+}
+
+/// Sessions whose last command hasn't shown on screen yet, and for how long
+pub fn unanswered() -> Vec<(String, Duration)> {
+    SESSIONS.lock().unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .filter_map(|(session, watched)| watched.sent.as_ref().map(|sent| (session.clone(), sent.at.elapsed())))
+        .collect()
+}
+
+/// Stop watching a session archy closed on purpose
+pub fn forget(session: &str) {
+    SESSIONS.lock().unwrap_or_else(|e| e.into_inner()).remove(session);
+}
+
+/// Report resets of `session` on the reply being built
+pub fn announce(session: &str) {
+    for reset in take_unannounced(session) {
+        events::record(finding(&reset));
+    }
+}
+
+fn take_unannounced(session: &str) -> Vec<Reset> {
+    let mut pending = UNANNOUNCED.lock().unwrap_or_else(|e| e.into_inner());
+    let (mine, rest) = std::mem::take(&mut *pending).into_iter().partition(|reset| reset.session == session);
+    *pending = rest;
+    mine
+}
+
+fn finding(reset: &Reset) -> Finding {
+    let replayed = match &reset.project {
+        Some(root) => format!(", project setup from {} replayed", root),
+        None => String::new(),
+    };
+    Finding::new(
+        "Session Reset",
+        format!(
+            "tmux session {} was recreated ({}){} - shell variables, history and running commands from before are gone",
+            reset.session, reset.reason, replayed
+        ),
+        Importance::High,
+    )
+    .with_provenance("watchdog", 1.0)
+}
+
+fn tick(config: &Config) {
+    let live = match tmux::list_sessions() {
+        Ok(live) => live,
+        Err(e) => {
+            // No server - every session went down with it
+            let lost: Vec<(String, Option<String>)> = std::mem::take(&mut *SESSIONS.lock().unwrap_or_else(|e| e.into_inner()))
+                .into_iter()
+                .map(|(session, watched)| (session, watched.cwd))
+                .collect();
+            if !lost.is_empty() {
+                tracing::warn!("tmux server is gone ({}) - recreating {} session(s)", e.trim(), lost.len());
+            }
+            for (session, cwd) in lost {
+                recreate(&session, cwd, "the tmux server died", config);
+            }
+            return;
+        }
+    };
+
+    let stall = Duration::from_secs(config.watchdog_stall_seconds);
+    let managed: Vec<String> = live.into_iter().filter(|session| tmux::is_managed(session)).collect();
+    let mut wedged = Vec::new();
+    {
+        let mut sessions = SESSIONS.lock().unwrap_or_else(|e| e.into_inner());
+        // Closed or never ours - nothing to bring back
+        sessions.retain(|session, _| managed.contains(session));
+        for session in &managed {
+            let watched = sessions.entry(session.clone()).or_default();
+            if let Ok(cwd) = tmux::get_pane_cwd(session) {
+                watched.cwd = Some(cwd.trim().to_string()).filter(|cwd| !cwd.is_empty());
+            }
+            let Some(sent) = &watched.sent else { continue };
+            match tmux::capture_pane(session, 0) {
+                Ok(screen) if screen != sent.screen => watched.sent = None,
+                _ if sent.at.elapsed() >= stall => wedged.push((session.clone(), watched.cwd.clone())),
+                _ => {}
+            }
+        }
+        for (session, _) in &wedged {
+            sessions.remove(session);
+        }
+    }
+
+    for (session, cwd) in wedged {
+        tracing::warn!("tmux session {} ignored a command for {}s - recreating it", session, stall.as_secs());
+        let _ = tmux::kill_session(&session);
+        recreate(&session, cwd, "it stopped responding", config);
+    }
+}
+
+/// New session in the old one's directory, with the project's setup replayed
+fn recreate(session: &str, cwd: Option<String>, reason: &'static str, config: &Config) {
+    project::forget_session(session);
+    let dir = cwd.as_deref().filter(|dir| Path::new(dir).is_dir());
+    if let Err(e) = tmux::new_session_in(session, dir) {
+        tracing::error!("Cannot recreate tmux session {}: {}", session, e);
+        return;
+    }
+
+    let project = match ProjectConfig::for_session(session) {
+        Ok(Some(project)) => match project.activate(session, config) {
+            Ok(()) => Some(project.root.display().to_string()),
+            Err(e) => {
+                tracing::warn!("Project setup for recreated session {} failed: {}", session, e);
+                None
+            }
+        },
+        Ok(None) => None,
+        Err(e) => {
+            tracing::warn!("{}", e);
+            None
+        }
+    };
+
+    RESETS.fetch_add(1, Ordering::SeqCst);
+    tracing::warn!(session, reason, cwd = dir.unwrap_or("-"), "Recreated tmux session {}", session);
+    eventlog::emit(
+        Kind::SessionReset,
+        Some(session),
+        format!("Session {} recreated ({})", session, reason),
+        serde_json::json!({"reason": reason, "cwd": dir, "project": project}),
+    );
+    SESSIONS.lock().unwrap_or_else(|e| e.into_inner()).insert(session.to_string(), Watched { cwd: dir.map(str::to_string), sent: None });
+    let ts_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    UNANNOUNCED.lock().unwrap_or_else(|e| e.into_inner()).push(Reset {
+        session: session.to_string(),
+        reason,
+        ts_ms,
+        cwd: dir.map(str::to_string),
+        project,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_announce_only_for_its_session() {
+        let reset = |session: &str| Reset {
+            session: session.to_string(),
+            reason: "the tmux server died",
+            ts_ms: 0,
+            cwd: None,
+            project: Some("/home/u/app".to_string()),
+        };
+        UNANNOUNCED.lock().unwrap().extend([reset("watchdog-test-a"), reset("watchdog-test-b")]);
+        assert_eq!(take_unannounced("watchdog-test-a").len(), 1);
+        let pending: Vec<String> = UNANNOUNCED.lock().unwrap().iter().map(|r| r.session.clone()).collect();
+        assert!(pending.contains(&"watchdog-test-b".to_string()));
+        assert!(!pending.contains(&"watchdog-test-a".to_string()));
+
+        let finding = finding(&reset("work"));
+        assert_eq!(finding.category, "Session Reset");
+        assert!(finding.message.contains("/home/u/app"));
+    }
+}
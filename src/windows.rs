@@ -0,0 +1,607 @@
+// windows.rs - Window control (list, focus, close, place) through the desktop's own tools
+// Hyprland and Sway are asked over their IPC tools (hyprctl, swaymsg), found by the
+// HYPRLAND_INSTANCE_SIGNATURE / SWAYSOCK their sessions export; anything else is taken for X11 and
+// driven by wmctrl, or xdotool if wmctrl isn't installed. GNOME and KDE on Wayland offer no such tool
+// and are reported as unsupported.
+//
+// Windows are matched by `app` (class / app_id, case-insensitive, a substring will do), `title`
+// (substring, case-insensitive) and `id` (exact), all given ones having to match. Listing order is
+// the tool's, except that on Hyprland the most recently focused window comes first. A launched app's
+// window is found by pid - its own or a descendant's, walking /proc.
+//
+// Placement moves a window to a workspace (named as list_windows reports it: Hyprland and Sway
+// workspace names, X11 desktop numbers from 0) and/or a monitor (its output name, or its position in
+// the list, 1 for the first). Monitors come from hyprctl, swaymsg or xrandr. On Hyprland and Sway a
+// monitor alone means the workspace it shows; with both, the workspace is moved to that monitor. On
+// X11 a monitor moves the window to its top left corner.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use crate::helpers::environment;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Hyprland,
+    Sway,
+    Wmctrl,
+    Xdotool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Window {
+    pub id: String, // what focus_window / close_window take: hyprland address, sway con_id, X11 window id
+    pub app: String, // class (X11, Hyprland) or app_id (Sway)
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub focused: Option<bool>, // None where the tool doesn't say
+}
+
+/// A connected monitor, in the compositor's / xrandr's order
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Monitor {
+    pub name: String, // output name: DP-1, eDP-1, HDMI-A-1
+    pub x: i64,
+    pub y: i64,
+    pub width: u32,
+    pub height: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>, // the workspace it shows (Hyprland, Sway)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub focused: Option<bool>,
+}
+
+/// Where a request wants a window: {workspace?, monitor?}
+#[derive(Debug, Default, PartialEq)]
+pub struct Placement {
+    pub workspace: Option<String>,
+    pub monitor: Option<String>, // a name, or a position counting from 1
+}
+
+impl Placement {
+    /// None when the request asks for no placement. Numbers are taken as their decimal text
+    pub fn from_request(data: &Value) -> Result<Option<Placement>, String> {
+        let field = |key: &str| -> Result<Option<String>, String> {
+            match data.get(key) {
+                None | Some(Value::Null) => Ok(None),
+                Some(Value::Number(n)) if n.is_u64() => Ok(Some(n.to_string())),
+                Some(Value::String(s)) if !s.trim().is_empty() && !s.contains(['\n', '\0', ',']) => Ok(Some(s.trim().to_string())),
+                Some(_) => Err(format!("Invalid {}: expected a name or a number", key)),
+            }
+        };
+        let placement = Placement { workspace: field("workspace")?, monitor: field("monitor")? };
+        Ok((placement.workspace.is_some() || placement.monitor.is_some()).then_some(placement))
+    }
+}
+
+/// The monitor `wanted` names among `monitors`: by name (case-insensitive), else by position from 1
+fn pick_monitor<'a>(monitors: &'a [Monitor], wanted: &str) -> Result<&'a Monitor, String> {
+    monitors.iter()
+        .find(|m| m.name.eq_ignore_ascii_case(wanted))
+        .or_else(|| wanted.parse::<usize>().ok().filter(|&n| n >= 1).and_then(|n| monitors.get(n - 1)))
+        .ok_or_else(|| format!(
+            "No monitor '{}' - connected: {}",
+            wanted,
+            monitors.iter().map(|m| m.name.as_str()).collect::<Vec<_>>().join(", ")
+        ))
+}
+
+/// What a request names a window by
+#[derive(Debug, Default)]
+pub struct WindowMatch {
+    pub app: Option<String>,
+    pub title: Option<String>,
+    pub id: Option<String>,
+}
+
+impl WindowMatch {
+    /// From {app?, title?, id?} - at least one is required
+    pub fn from_request(data: &Value) -> Result<WindowMatch, String> {
+        let field = |key: &str| {
+            data.get(key).and_then(|v| v.as_str()).map(str::trim).filter(|s| !s.is_empty()).map(str::to_string)
+        };
+        let matcher = WindowMatch { app: field("app"), title: field("title"), id: field("id") };
+        if matcher.app.is_none() && matcher.title.is_none() && matcher.id.is_none() {
+            return Err("Missing app, title or id parameter".to_string());
+        }
+        Ok(matcher)
+    }
+
+    pub fn matches(&self, window: &Window) -> bool {
+        let contains = |field: &str, wanted: &str| field.to_lowercase().contains(&wanted.to_lowercase());
+        self.id.as_ref().is_none_or(|id| &window.id == id)
+            && self.app.as_ref().is_none_or(|app| contains(&window.app, app))
+            && self.title.as_ref().is_none_or(|title| contains(&window.title, title))
+    }
+}
+
+impl Backend {
+    /// The session's compositor if it has an IPC tool, else the first installed X11 tool
+    pub fn detect() -> Result<Backend, String> {
+        if environment::session_var("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+            return Ok(Backend::Hyprland);
+        }
+        if environment::session_var("SWAYSOCK").is_some() {
+            return Ok(Backend::Sway);
+        }
+        let session = environment::Session::detect();
+        if session.is_wayland() && !session.runs_x11() {
+            return Err("Window control needs Hyprland, Sway or X11 - this Wayland session has no supported tool".to_string());
+        }
+        [Backend::Wmctrl, Backend::Xdotool]
+            .into_iter()
+            .find(|backend| is_installed(backend.program()))
+            .ok_or_else(|| "Window control on X11 needs wmctrl or xdotool".to_string())
+    }
+
+    pub fn program(&self) -> &'static str {
+        match self {
+            Backend::Hyprland => "hyprctl",
+            Backend::Sway => "swaymsg",
+            Backend::Wmctrl => "wmctrl",
+            Backend::Xdotool => "xdotool",
+        }
+    }
+
+    pub fn list(&self) -> Result<Vec<Window>, String> {
+        match self {
+            Backend::Hyprland => parse_hyprland(&run(*self, &["-j", "clients"])?),
+            Backend::Sway => parse_sway(&run(*self, &["-t", "get_tree", "-r"])?),
+            Backend::Wmctrl => {
+                let active = run(Backend::Xdotool, &["getactivewindow"]).ok().and_then(|id| id.trim().parse::<u64>().ok());
+                Ok(parse_wmctrl(&run(*self, &["-l", "-p", "-x"])?, active))
+            }
+            Backend::Xdotool => {
+                let active = run(*self, &["getactivewindow"]).ok().map(|id| id.trim().to_string());
+                let ids = run(*self, &["search", "--onlyvisible", "--name", ""]).unwrap_or_default();
+                Ok(ids.lines().map(str::trim).filter(|id| !id.is_empty()).map(|id| {
+                    let query = |command: &str| run(*self, &[command, id]).map(|out| out.trim().to_string()).ok();
+                    Window {
+                        id: id.to_string(),
+                        app: query("getwindowclassname").unwrap_or_default(),
+                        title: query("getwindowname").unwrap_or_default(),
+                        pid: query("getwindowpid").and_then(|pid| pid.parse().ok()),
+                        workspace: None,
+                        focused: Some(active.as_deref() == Some(id)),
+                    }
+                }).collect())
+            }
+        }
+    }
+
+    pub fn focus(&self, window: &Window) -> Result<(), String> {
+        let id = window.id.as_str();
+        match self {
+            Backend::Hyprland => run(*self, &["dispatch", "focuswindow", &format!("address:{}", id)]),
+            Backend::Sway => run(*self, &[&format!("[con_id={}]", id), "focus"]),
+            Backend::Wmctrl => run(*self, &["-i", "-a", id]),
+            Backend::Xdotool => run(*self, &["windowactivate", id]),
+        }
+        .map(|_| ())
+    }
+
+    pub fn monitors(&self) -> Result<Vec<Monitor>, String> {
+        match self {
+            Backend::Hyprland => parse_hyprland_monitors(&run(*self, &["-j", "monitors"])?),
+            Backend::Sway => parse_sway_outputs(&run(*self, &["-t", "get_outputs", "-r"])?),
+            Backend::Wmctrl | Backend::Xdotool => Ok(parse_xrandr(&run_program("xrandr", &["--listmonitors"])?)),
+        }
+    }
+
+    /// Move the window where `placement` says
+    pub fn place(&self, window: &Window, placement: &Placement) -> Result<(), String> {
+        let id = window.id.as_str();
+        let monitors = match &placement.monitor {
+            Some(_) => self.monitors()?,
+            None => Vec::new(),
+        };
+        let monitor = placement.monitor.as_deref().map(|wanted| pick_monitor(&monitors, wanted)).transpose()?;
+        match self {
+            Backend::Hyprland => {
+                let workspace = placement.workspace.clone()
+                    .or_else(|| monitor.and_then(|m| m.workspace.clone()))
+                    .ok_or("The monitor's workspace is unknown")?;
+                run(*self, &["dispatch", "movetoworkspacesilent", &format!("{},address:{}", workspace, id)])?;
+                if let (Some(monitor), Some(_)) = (monitor, &placement.workspace) {
+                    run(*self, &["dispatch", "moveworkspacetomonitor", &workspace, &monitor.name])?;
+                }
+            }
+            Backend::Sway => {
+                let target = format!("[con_id={}]", id);
+                match (&placement.workspace, monitor) {
+                    (Some(workspace), monitor) => {
+                        run(*self, &[&target, "move", "container", "to", "workspace", workspace])?;
+                        if let Some(monitor) = monitor {
+                            // Acts on the focused workspace - the one the window went to
+                            run(*self, &[&target, "focus"])?;
+                            run(*self, &["move", "workspace", "to", "output", &monitor.name])?;
+                        }
+                    }
+                    (None, Some(monitor)) => {
+                        run(*self, &[&target, "move", "container", "to", "output", &monitor.name])?;
+                    }
+                    (None, None) => {}
+                }
+            }
+            Backend::Wmctrl | Backend::Xdotool => {
+                if let Some(workspace) = &placement.workspace {
+                    let desktop = workspace.parse::<u32>().map_err(|_| format!("X11 workspaces are numbers from 0, not '{}'", workspace))?;
+                    match self {
+                        Backend::Wmctrl => run(*self, &["-i", "-r", id, "-t", &desktop.to_string()])?,
+                        _ => run(*self, &["set_desktop_for_window", id, &desktop.to_string()])?,
+                    };
+                }
+                if let Some(monitor) = monitor {
+                    let (x, y) = (monitor.x.to_string(), monitor.y.to_string());
+                    match self {
+                        Backend::Wmctrl => {
+                            // A maximized window ignores moves
+                            run(*self, &["-i", "-r", id, "-b", "remove,maximized_vert,maximized_horz"])?;
+                            run(*self, &["-i", "-r", id, "-e", &format!("0,{},{},-1,-1", x, y)])?
+                        }
+                        _ => run(*self, &["windowmove", id, &x, &y])?,
+                    };
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Ask the window to close - the app may still prompt about unsaved work
+    pub fn close(&self, window: &Window) -> Result<(), String> {
+        let id = window.id.as_str();
+        match self {
+            Backend::Hyprland => run(*self, &["dispatch", "closewindow", &format!("address:{}", id)]),
+            Backend::Sway => run(*self, &[&format!("[con_id={}]", id), "kill"]),
+            Backend::Wmctrl => run(*self, &["-i", "-c", id]),
+            Backend::Xdotool => run(*self, &["windowclose", id]),
+        }
+        .map(|_| ())
+    }
+}
+
+/// Windows matching `matcher`, with the backend that listed them
+pub fn find(matcher: &WindowMatch) -> Result<(Backend, Vec<Window>), String> {
+    let backend = Backend::detect()?;
+    let windows = backend.list()?.into_iter().filter(|w| matcher.matches(w)).collect();
+    Ok((backend, windows))
+}
+
+/// The window `pid` (or a process it started - flatpak, a wrapper script) opens within `timeout`.
+/// None at once where windows can't be listed
+pub fn wait_for_pid(pid: u32, timeout: Duration) -> Option<Window> {
+    let backend = Backend::detect().ok()?;
+    let deadline = Instant::now() + timeout;
+    loop {
+        let found = backend.list().ok()?.into_iter()
+            .find(|window| window.pid.is_some_and(|own| descends_from(own, pid)));
+        if found.is_some() || Instant::now() >= deadline || !is_alive(pid) {
+            return found;
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+}
+
+/// Whether `pid` is a running (not zombie) process
+pub fn is_alive(pid: u32) -> bool {
+    proc_stat(pid).is_some_and(|(state, _)| state != 'Z')
+}
+
+/// Whether `pid` is `ancestor` or one of its descendants
+pub fn descends_from(pid: u32, ancestor: u32) -> bool {
+    let mut current = pid;
+    // Bounded - a process tree is never this deep, a parent loop in /proc would be
+    for _ in 0..64 {
+        if current == ancestor {
+            return true;
+        }
+        match proc_stat(current) {
+            Some((_, parent)) if parent > 1 => current = parent,
+            _ => return false,
+        }
+    }
+    false
+}
+
+/// State and parent pid from /proc/<pid>/stat (the command name before them may contain anything)
+fn proc_stat(pid: u32) -> Option<(char, u32)> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    let mut fields = stat.rsplit_once(')')?.1.split_whitespace();
+    let state = fields.next()?.chars().next()?;
+    let parent = fields.next()?.parse().ok()?;
+    Some((state, parent))
+}
+
+/// Run a backend tool in the GUI session's environment, its stdout if it succeeded
+fn run(backend: Backend, args: &[&str]) -> Result<String, String> {
+    let stdout = run_program(backend.program(), args)?;
+    // hyprctl dispatch reports failures on stdout with a zero exit
+    if backend == Backend::Hyprland && args.first() == Some(&"dispatch") && stdout.trim() != "ok" {
+        return Err(format!("{} {}: {}", backend.program(), args.join(" "), stdout.trim()));
+    }
+    Ok(stdout)
+}
+
+fn run_program(program: &str, args: &[&str]) -> Result<String, String> {
+    let mut command = Command::new(program);
+    command.args(args).envs(environment::Session::detect().gui_env());
+    let output = command.stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Cannot run {}: {}", program, e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = [stderr.trim(), stdout.trim()].into_iter().find(|s| !s.is_empty()).unwrap_or("failed");
+        return Err(format!("{} {}: {}", program, args.join(" "), reason));
+    }
+    Ok(stdout)
+}
+
+fn is_installed(program: &str) -> bool {
+    Command::new("which")
+        .arg(program)
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+/// `hyprctl -j clients`, most recently focused first
+fn parse_hyprland(json: &str) -> Result<Vec<Window>, String> {
+    let clients: Vec<Value> = serde_json::from_str(json).map_err(|e| format!("Unexpected hyprctl output: {}", e))?;
+    let mut windows: Vec<(i64, Window)> = clients.iter()
+        .filter(|client| client["mapped"].as_bool().unwrap_or(true))
+        .map(|client| {
+            let history = client["focusHistoryID"].as_i64().unwrap_or(i64::MAX);
+            (history, Window {
+                id: client["address"].as_str().unwrap_or_default().to_string(),
+                app: client["class"].as_str().unwrap_or_default().to_string(),
+                title: client["title"].as_str().unwrap_or_default().to_string(),
+                pid: client["pid"].as_u64().and_then(|pid| u32::try_from(pid).ok()),
+                workspace: client["workspace"]["name"].as_str().map(str::to_string),
+                focused: Some(history == 0),
+            })
+        })
+        .collect();
+    windows.sort_by_key(|(history, _)| *history);
+    Ok(windows.into_iter().map(|(_, window)| window).collect())
+}
+
+/// `swaymsg -t get_tree -r`: every view in the tree, with the workspace holding it
+fn parse_sway(json: &str) -> Result<Vec<Window>, String> {
+    fn walk(node: &Value, workspace: Option<&str>, windows: &mut Vec<Window>) {
+        let workspace = if node["type"] == "workspace" { node["name"].as_str() } else { workspace };
+        let is_view = node["pid"].is_u64() && matches!(node["type"].as_str(), Some("con" | "floating_con"));
+        if is_view {
+            let app = node["app_id"].as_str()
+                .or_else(|| node["window_properties"]["class"].as_str())
+                .unwrap_or_default();
+            windows.push(Window {
+                id: node["id"].as_u64().map(|id| id.to_string()).unwrap_or_default(),
+                app: app.to_string(),
+                title: node["name"].as_str().unwrap_or_default().to_string(),
+                pid: node["pid"].as_u64().and_then(|pid| u32::try_from(pid).ok()),
+                workspace: workspace.map(str::to_string),
+                focused: node["focused"].as_bool(),
+            });
+        }
+        for child in ["nodes", "floating_nodes"].iter().filter_map(|key| node[*key].as_array()).flatten() {
+            walk(child, workspace, windows);
+        }
+    }
+    let tree: Value = serde_json::from_str(json).map_err(|e| format!("Unexpected swaymsg output: {}", e))?;
+    let mut windows = Vec::new();
+    walk(&tree, None, &mut windows);
+    Ok(windows)
+}
+
+/// `hyprctl -j monitors`
+fn parse_hyprland_monitors(json: &str) -> Result<Vec<Monitor>, String> {
+    let monitors: Vec<Value> = serde_json::from_str(json).map_err(|e| format!("Unexpected hyprctl output: {}", e))?;
+    Ok(monitors.iter().map(|monitor| Monitor {
+        name: monitor["name"].as_str().unwrap_or_default().to_string(),
+        x: monitor["x"].as_i64().unwrap_or(0),
+        y: monitor["y"].as_i64().unwrap_or(0),
+        width: monitor["width"].as_u64().unwrap_or(0) as u32,
+        height: monitor["height"].as_u64().unwrap_or(0) as u32,
+        workspace: monitor["activeWorkspace"]["name"].as_str().map(str::to_string),
+        focused: monitor["focused"].as_bool(),
+    }).collect())
+}
+
+/// `swaymsg -t get_outputs -r` - the active outputs
+fn parse_sway_outputs(json: &str) -> Result<Vec<Monitor>, String> {
+    let outputs: Vec<Value> = serde_json::from_str(json).map_err(|e| format!("Unexpected swaymsg output: {}", e))?;
+    Ok(outputs.iter().filter(|output| output["active"].as_bool().unwrap_or(true)).map(|output| Monitor {
+        name: output["name"].as_str().unwrap_or_default().to_string(),
+        x: output["rect"]["x"].as_i64().unwrap_or(0),
+        y: output["rect"]["y"].as_i64().unwrap_or(0),
+        width: output["rect"]["width"].as_u64().unwrap_or(0) as u32,
+        height: output["rect"]["height"].as_u64().unwrap_or(0) as u32,
+        workspace: output["current_workspace"].as_str().map(str::to_string),
+        focused: output["focused"].as_bool(),
+    }).collect())
+}
+
+/// `xrandr --listmonitors` lines: ` 0: +*DP-1 2560/597x1440/336+0+0  DP-1`
+fn parse_xrandr(listing: &str) -> Vec<Monitor> {
+    listing.lines()
+        .skip_while(|line| line.starts_with("Monitors:"))
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace().skip(1);
+            let flagged = columns.next()?;
+            let geometry = columns.next()?;
+            let name = columns.next().unwrap_or(flagged.trim_start_matches(['+', '*'])).to_string();
+            // <width>/<mm>x<height>/<mm>+<x>+<y>
+            let (size, offset) = geometry.split_once('+')?;
+            let (x, y) = offset.split_once('+')?;
+            let (width, height) = size.split_once('x')?;
+            let pixels = |dimension: &str| dimension.split('/').next()?.parse::<u32>().ok();
+            Some(Monitor {
+                name,
+                x: x.parse().ok()?,
+                y: y.parse().ok()?,
+                width: pixels(width)?,
+                height: pixels(height)?,
+                workspace: None,
+                focused: None,
+            })
+        })
+        .collect()
+}
+
+/// `wmctrl -l -p -x` lines: id, desktop, pid, instance.class, host, title. `active` is the focused
+/// window's id as xdotool prints it (decimal), if known
+fn parse_wmctrl(listing: &str, active: Option<u64>) -> Vec<Window> {
+    listing.lines()
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace();
+            let id = columns.next()?;
+            let desktop = columns.next()?;
+            let pid = columns.next()?;
+            let class = columns.next()?;
+            let _host = columns.next();
+            let title = columns.collect::<Vec<_>>().join(" ");
+            let numeric = u64::from_str_radix(id.trim_start_matches("0x"), 16).ok();
+            Some(Window {
+                id: id.to_string(),
+                // WM_CLASS is instance.Class - the class names the app
+                app: class.rsplit('.').next().unwrap_or(class).to_string(),
+                title,
+                pid: pid.parse().ok().filter(|&pid| pid != 0),
+                // -1 is "sticky" - on every desktop
+                workspace: Some(desktop.to_string()).filter(|d| d != "-1"),
+                focused: active.map(|active| numeric == Some(active)),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hyprland() {
+        let json = r#"[
+            {"address": "0x55a1", "mapped": true, "workspace": {"id": 2, "name": "2"}, "class": "firefox",
+             "title": "Mozilla Firefox", "pid": 4242, "focusHistoryID": 1},
+            {"address": "0x55b2", "mapped": true, "workspace": {"id": 1, "name": "1"}, "class": "foot",
+             "title": "~/src", "pid": 4343, "focusHistoryID": 0},
+            {"address": "0x55c3", "mapped": false, "class": "hidden", "title": "", "pid": 1, "focusHistoryID": 2}
+        ]"#;
+        let windows = parse_hyprland(json).unwrap();
+        assert_eq!(windows.len(), 2);
+        assert_eq!((windows[0].id.as_str(), windows[0].focused), ("0x55b2", Some(true)));
+        assert_eq!(windows[1].workspace.as_deref(), Some("2"));
+        assert_eq!(windows[1].pid, Some(4242));
+        assert!(parse_hyprland("not json").is_err());
+    }
+
+    #[test]
+    fn test_parse_sway() {
+        let json = r#"{"id": 1, "type": "root", "nodes": [
+            {"id": 3, "type": "output", "name": "eDP-1", "nodes": [
+                {"id": 4, "type": "workspace", "name": "web", "nodes": [
+                    {"id": 7, "type": "con", "name": "Firefox", "pid": 100, "app_id": "firefox", "focused": true, "nodes": []},
+                    {"id": 8, "type": "con", "name": null, "layout": "splitv", "nodes": [
+                        {"id": 9, "type": "con", "name": "xterm", "pid": 101, "app_id": null,
+                         "window_properties": {"class": "XTerm"}, "focused": false, "nodes": []}
+                    ]}
+                ], "floating_nodes": [
+                    {"id": 10, "type": "floating_con", "name": "Calculator", "pid": 102, "app_id": "org.gnome.Calculator", "focused": false}
+                ]}
+            ]}
+        ]}"#;
+        let windows = parse_sway(json).unwrap();
+        let apps: Vec<&str> = windows.iter().map(|w| w.app.as_str()).collect();
+        assert_eq!(apps, ["firefox", "XTerm", "org.gnome.Calculator"]);
+        assert!(windows.iter().all(|w| w.workspace.as_deref() == Some("web")));
+        assert_eq!((windows[0].id.as_str(), windows[0].focused), ("7", Some(true)));
+    }
+
+    #[test]
+    fn test_parse_wmctrl() {
+        let listing = "0x03a00003  0 2173   Navigator.firefox     host Mozilla Firefox - Start  Page\n\
+                       0x01e00004 -1 0      desktop_window.Nautilus  host Desktop\n";
+        let windows = parse_wmctrl(listing, Some(0x03a00003));
+        assert_eq!(windows.len(), 2);
+        assert_eq!(windows[0].app, "firefox");
+        assert_eq!(windows[0].title, "Mozilla Firefox - Start Page");
+        assert_eq!((windows[0].pid, windows[0].focused), (Some(2173), Some(true)));
+        assert_eq!((windows[1].pid, windows[1].workspace.as_deref()), (None, None));
+        assert_eq!(parse_wmctrl(listing, None)[0].focused, None);
+    }
+
+    #[test]
+    fn test_monitors() {
+        let hyprland = r#"[{"id": 0, "name": "eDP-1", "x": 0, "y": 0, "width": 1920, "height": 1080,
+            "activeWorkspace": {"id": 1, "name": "1"}, "focused": true},
+            {"id": 1, "name": "DP-1", "x": 1920, "y": 0, "width": 2560, "height": 1440,
+            "activeWorkspace": {"id": 5, "name": "5"}, "focused": false}]"#;
+        let monitors = parse_hyprland_monitors(hyprland).unwrap();
+        assert_eq!(pick_monitor(&monitors, "2").unwrap().workspace.as_deref(), Some("5"));
+        assert_eq!(pick_monitor(&monitors, "dp-1").unwrap().x, 1920);
+        assert!(pick_monitor(&monitors, "0").unwrap_err().contains("eDP-1, DP-1"));
+
+        let sway = r#"[{"name": "HDMI-A-1", "active": false, "rect": {"x": 0, "y": 0, "width": 0, "height": 0}},
+            {"name": "eDP-1", "active": true, "focused": true, "current_workspace": "web",
+             "rect": {"x": 0, "y": 0, "width": 1920, "height": 1200}}]"#;
+        let outputs = parse_sway_outputs(sway).unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].workspace.as_deref(), Some("web"));
+
+        let xrandr = "Monitors: 2\n 0: +*eDP-1 1920/344x1080/193+0+0  eDP-1\n 1: +HDMI-1 2560/597x1440/336+1920+0  HDMI-1\n";
+        let monitors = parse_xrandr(xrandr);
+        assert_eq!(monitors.len(), 2);
+        assert_eq!((monitors[1].name.as_str(), monitors[1].x, monitors[1].width), ("HDMI-1", 1920, 2560));
+    }
+
+    #[test]
+    fn test_placement_request() {
+        let placement = |data: Value| Placement::from_request(&data);
+        assert_eq!(placement(serde_json::json!({})), Ok(None));
+        assert_eq!(
+            placement(serde_json::json!({"workspace": 3, "monitor": "DP-1"})),
+            Ok(Some(Placement { workspace: Some("3".to_string()), monitor: Some("DP-1".to_string()) }))
+        );
+        assert!(placement(serde_json::json!({"workspace": "2,address:0x1"})).is_err());
+        assert!(placement(serde_json::json!({"monitor": -1})).is_err());
+    }
+
+    #[test]
+    fn test_process_tree() {
+        let own = std::process::id();
+        assert!(is_alive(own));
+        assert!(descends_from(own, own));
+        let mut child = Command::new("sleep").arg("5").spawn().unwrap();
+        assert!(descends_from(child.id(), own));
+        assert!(!descends_from(own, child.id()));
+        let _ = child.kill();
+        let _ = child.wait();
+        assert!(!is_alive(child.id()));
+    }
+
+    #[test]
+    fn test_window_match() {
+        let window = Window {
+            id: "0x55a1".to_string(),
+            app: "firefox".to_string(),
+            title: "Rust Docs - Mozilla Firefox".to_string(),
+            pid: None,
+            workspace: None,
+            focused: None,
+        };
+        let matcher = |data: Value| WindowMatch::from_request(&data).unwrap();
+        assert!(matcher(serde_json::json!({"app": "FireFox"})).matches(&window));
+        assert!(matcher(serde_json::json!({"app": "fire", "title": "rust docs"})).matches(&window));
+        assert!(!matcher(serde_json::json!({"app": "firefox", "title": "mail"})).matches(&window));
+        assert!(!matcher(serde_json::json!({"id": "0x55"})).matches(&window));
+        assert!(WindowMatch::from_request(&serde_json::json!({"app": " "})).is_err());
+    }
+}
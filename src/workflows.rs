@@ -0,0 +1,226 @@
+// workflows.rs - Named, saved batch workflows
+// Parameterized batch definitions stored on disk and invoked by name ("collect diagnostics bundle")
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use crate::batch;
+use crate::helpers::security::quote_word;
+
+/// A declared workflow parameter, referenced as `{{name}}` anywhere in the batch definition.
+/// In shell commands the value is substituted as one quoted word; `argv` elements take it verbatim
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowParam {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub default: Option<String>, // None = required
+}
+
+/// A saved batch definition - `batch` is a regular execute_batch payload
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Workflow {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub parameters: Vec<WorkflowParam>,
+    pub batch: Value,
+}
+
+/// Workflow names become file names - keep them boring
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn workflow_path(dir: &str, name: &str) -> PathBuf {
+    PathBuf::from(dir).join(format!("{}.json", name))
+}
+
+fn placeholder_regex() -> Regex {
+    Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").expect("valid placeholder regex")
+}
+
+/// Parse and validate a `save_workflow` payload
+pub fn from_request(data: &Value) -> Result<Workflow, String> {
+    let workflow: Workflow = serde_json::from_value(data.clone())
+        .map_err(|e| format!("Invalid workflow: {}", e))?;
+
+    if !is_valid_name(&workflow.name) {
+        return Err("Invalid workflow name (use letters, digits, '-' and '_')".to_string());
+    }
+
+    if !workflow.batch.is_object() {
+        return Err("Workflow 'batch' must be an object with a 'commands' array".to_string());
+    }
+    let mut steps = workflow.batch.clone();
+    batch::resolve_argv(&mut steps)?;
+    batch::parse_steps(&steps)?;
+
+    // Every placeholder must be declared, so run_workflow can report missing params up front
+    let declared: Vec<&str> = workflow.parameters.iter().map(|p| p.name.as_str()).collect();
+    let batch_json = workflow.batch.to_string();
+    for cap in placeholder_regex().captures_iter(&batch_json) {
+        if !declared.contains(&&cap[1]) {
+            return Err(format!("Placeholder '{{{{{}}}}}' is not a declared parameter", &cap[1]));
+        }
+    }
+
+    Ok(workflow)
+}
+
+pub fn save(dir: &str, workflow: &Workflow) -> Result<(), String> {
+    fs::create_dir_all(dir)
+        .map_err(|e| format!("Failed to create workflow dir {}: {}", dir, e))?;
+    let json = serde_json::to_string_pretty(workflow)
+        .map_err(|e| format!("Serialization error: {}", e))?;
+    fs::write(workflow_path(dir, &workflow.name), json)
+        .map_err(|e| format!("Failed to save workflow {}: {}", workflow.name, e))
+}
+
+pub fn load(dir: &str, name: &str) -> Result<Workflow, String> {
+    if !is_valid_name(name) {
+        return Err("Invalid workflow name".to_string());
+    }
+    let content = fs::read_to_string(workflow_path(dir, name))
+        .map_err(|_| format!("Workflow '{}' not found", name))?;
+    serde_json::from_str(&content)
+        .map_err(|e| format!("Corrupt workflow '{}': {}", name, e))
+}
+
+/// All saved workflows, sorted by name (unreadable files are skipped)
+pub fn list(dir: &str) -> Vec<Workflow> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut workflows: Vec<Workflow> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|e| fs::read_to_string(e.path()).ok())
+        .filter_map(|content| serde_json::from_str(&content).ok())
+        .collect();
+
+    workflows.sort_by(|a, b| a.name.cmp(&b.name));
+    workflows
+}
+
+/// Substitute parameters into the batch definition, producing an execute_batch payload
+pub fn instantiate(workflow: &Workflow, params: &Map<String, Value>) -> Result<Value, String> {
+    let mut values: HashMap<String, String> = HashMap::new();
+    for param in &workflow.parameters {
+        let value = match params.get(&param.name) {
+            Some(Value::String(s)) => s.clone(),
+            Some(Value::Null) | None => match &param.default {
+                Some(default) => default.clone(),
+                None => return Err(format!("Missing required parameter '{}'", param.name)),
+            },
+            Some(other) => other.to_string(),
+        };
+        values.insert(param.name.clone(), value);
+    }
+
+    Ok(substitute(&workflow.batch, &values, &placeholder_regex(), false))
+}
+
+/// Fields holding shell command lines - parameters substituted there are quoted
+const SHELL_FIELDS: &[&str] = &["command", "commands", "compensation", "compensations"];
+
+fn substitute(value: &Value, values: &HashMap<String, String>, re: &Regex, shell: bool) -> Value {
+    match value {
+        Value::String(s) => Value::String(
+            re.replace_all(s, |caps: &regex::Captures| match values.get(&caps[1]) {
+                Some(value) if shell => quote_word(value),
+                Some(value) => value.clone(),
+                None => caps[0].to_string(),
+            })
+            .to_string(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(|v| substitute(v, values, re, shell)).collect()),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), substitute(v, values, re, SHELL_FIELDS.contains(&k.as_str()))))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sample() -> Value {
+        json!({
+            "name": "new-project",
+            "description": "Scaffold a project",
+            "parameters": [
+                {"name": "dir", "description": "Project directory"},
+                {"name": "branch", "default": "main"}
+            ],
+            "batch": {
+                "commands": ["mkdir -p {{dir}}", "cd {{dir}} && git init -b {{ branch }}"],
+                "failure_policy": "abort"
+            }
+        })
+    }
+
+    #[test]
+    fn test_instantiate_substitutes_params() {
+        let workflow = from_request(&sample()).unwrap();
+        let params = json!({"dir": "/tmp/demo"});
+        let payload = instantiate(&workflow, params.as_object().unwrap()).unwrap();
+        assert_eq!(payload["commands"][1], "cd /tmp/demo && git init -b main");
+        assert_eq!(payload["failure_policy"], "abort");
+
+        assert!(instantiate(&workflow, &Map::new()).unwrap_err().contains("dir"));
+    }
+
+    #[test]
+    fn test_params_cannot_inject_shell() {
+        let mut data = sample();
+        data["batch"]["commands"][1] = json!({"argv": ["ls", "-la", "{{dir}}"], "explanation": "list {{dir}}"});
+        let workflow = from_request(&data).unwrap();
+        let params = json!({"dir": "x; rm -rf ~"});
+        let mut payload = instantiate(&workflow, params.as_object().unwrap()).unwrap();
+
+        assert_eq!(payload["commands"][0], "mkdir -p 'x; rm -rf ~'");
+        assert_eq!(payload["commands"][1]["explanation"], "list x; rm -rf ~");
+        batch::resolve_argv(&mut payload).unwrap();
+        assert_eq!(payload["commands"][1]["command"], "ls -la 'x; rm -rf ~'");
+    }
+
+    #[test]
+    fn test_undeclared_placeholder_rejected() {
+        let mut data = sample();
+        data["batch"]["commands"][0] = json!("rm {{oops}}");
+        assert!(from_request(&data).is_err());
+
+        data["name"] = json!("../escape");
+        assert!(from_request(&data).is_err());
+    }
+
+    #[test]
+    fn test_save_load_list() {
+        let dir = std::env::temp_dir()
+            .join(format!("archy-workflows-test-{}", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+
+        let workflow = from_request(&sample()).unwrap();
+        save(&dir, &workflow).unwrap();
+        assert_eq!(load(&dir, "new-project").unwrap().parameters.len(), 2);
+        assert_eq!(list(&dir).len(), 1);
+        assert!(load(&dir, "missing").is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}